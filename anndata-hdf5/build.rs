@@ -0,0 +1,16 @@
+use std::env;
+
+fn main() {
+    // The HDF5 library bundled by `hdf5-sys` is built without the ROS3 driver,
+    // so the driver is only linked when using the system library. With both
+    // `static` and `ros3` enabled, the bundled library is used and
+    // `H5::open_remote` returns an error.
+    let ros3 = env::var_os("CARGO_FEATURE_ROS3").is_some();
+    let bundled = env::var_os("CARGO_FEATURE_STATIC").is_some();
+    if ros3 && !bundled {
+        println!("cargo:rustc-cfg=ros3_driver");
+    } else if ros3 {
+        println!("cargo:warning=the bundled HDF5 library has no ROS3 driver, remote files cannot be opened");
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -1,21 +1,37 @@
 use anndata::{
+    MemAnnData,
     backend::{
-        Backend, BackendData, DatasetOp, DynArrayView, FileOp, GroupOp, LocationOp, ScalarType,
-        WriteConfig,
+        Backend, BackendData, Compression, DatasetOp, DynArrayView, FileOp, GroupOp, LocationOp,
+        ScalarType, WriteConfig,
     },
-    data::{ArrayOp, BoundedSelectInfo, DynArray, DynScalar, SelectInfoElem, Shape},
+    data::{ArrayOp, BoundedSelectInfo, BoundedSelectInfoElem, DynArray, DynScalar, HasShape, SelectInfoElem, Shape},
 };
 
-use anyhow::{bail, Result, Ok};
+use anyhow::{bail, ensure, Context, Result, Ok};
+use half::f16;
+use num_complex::{Complex32, Complex64};
 use hdf5::{
-    dataset::Dataset,
+    dataset::{Dataset, DatasetBuilderEmpty},
+    plist::{dataset_create::FillValue, DatasetCreate},
     types::IntSize::*,
-    types::{FloatSize, TypeDescriptor, VarLenUnicode},
-    File, Group, H5Type, Location, Selection,
+    types::{CompoundField, CompoundType, FixedAscii, FixedUnicode, FloatSize, TypeDescriptor, VarLenAscii, VarLenUnicode},
+    Datatype, File, Group, H5Type, Location, Selection,
 };
-use ndarray::{Array, ArrayView, RemoveAxis, SliceInfo, ArrayBase};
+use hdf5_sys::{h5d::{H5Dread, H5Dwrite}, h5p::H5P_DEFAULT, h5s::H5S_ALL};
+use log::warn;
+use ndarray::{Array, Array1, ArrayView, RemoveAxis, SliceInfo, ArrayBase, Ix0, IxDyn};
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Once};
+
+/// Set by the build script if the `ros3` feature is enabled and the linked
+/// HDF5 library is not the bundled one, see `build.rs`.
+#[cfg(ros3_driver)]
+mod ros3;
+
+/// Blosc compression level used for LZ4.
+#[cfg(feature = "blosc")]
+const LZ4_LEVEL: u8 = 5;
 
 ///////////////////////////////////////////////////////////////////////////////
 /// Type definitions
@@ -23,7 +39,7 @@ use std::path::{Path, PathBuf};
 
 pub struct H5;
 
-pub struct H5File(File);
+pub struct H5File(File, Arc<FileState>);
 
 impl Deref for H5File {
     type Target = File;
@@ -33,7 +49,7 @@ impl Deref for H5File {
     }
 }
 
-pub struct H5Group(Group);
+pub struct H5Group(Group, Arc<FileState>);
 
 impl Deref for H5Group {
     type Target = Group;
@@ -43,7 +59,7 @@ impl Deref for H5Group {
     }
 }
 
-pub struct H5Dataset(Dataset);
+pub struct H5Dataset(Dataset, Arc<FileState>);
 
 impl Deref for H5Dataset {
     type Target = Dataset;
@@ -53,6 +69,33 @@ impl Deref for H5Dataset {
     }
 }
 
+/// The state of a file recorded when it is opened, shared by the groups and
+/// datasets opened from it.
+struct FileState {
+    filename: PathBuf,
+    writable: bool,
+    /// The memory map of a file opened by `H5::open_mmap`.
+    mmap: Option<memmap2::Mmap>,
+}
+
+impl FileState {
+    /// Writing to a read-only file, e.g., one opened by `H5::open_remote`,
+    /// fails before HDF5 is called, instead of with an HDF5 error stack.
+    fn ensure_writable(&self) -> Result<()> {
+        ensure!(
+            self.writable,
+            "cannot write to '{}', the file is opened in read-only mode",
+            self.filename.display(),
+        );
+        Ok(())
+    }
+}
+
+fn new_file(file: File, writable: bool, mmap: Option<memmap2::Mmap>) -> H5File {
+    let filename = hdf5::Location::filename(&file).into();
+    H5File(file, Arc::new(FileState { filename, writable, mmap }))
+}
+
 ///////////////////////////////////////////////////////////////////////////////
 /// Backend implementation
 ///////////////////////////////////////////////////////////////////////////////
@@ -68,20 +111,51 @@ impl Backend for H5 {
     type Dataset = H5Dataset;
 
     fn create<P: AsRef<Path>>(path: P) -> Result<Self::File> {
-        Ok(H5File(File::create(path)?))
+        Ok(new_file(File::create(path)?, true, None))
     }
 
     /// Opens a file as read-only, file must exist.
     fn open<P: AsRef<Path>>(path: P) -> Result<Self::File> {
-        Ok(File::open(path).map(H5File)?)
+        Ok(new_file(File::open(path)?, false, None))
     }
 
     /// Opens a file as read/write, file must exist.
     fn open_rw<P: AsRef<Path>>(path: P) -> Result<Self::File> {
-        Ok(File::open_rw(path).map(H5File)?)
+        Ok(new_file(File::open_rw(path)?, true, None))
     }
 }
 
+impl H5 {
+    /// Opens a file as read-only, file must exist. Selecting rows of dense
+    /// datasets by a list of indices reads the rows through a memory map of the
+    /// file, instead of reading the whole dataset. This requires the dataset to
+    /// be stored contiguously and uncompressed, i.e., written without chunking
+    /// and compression. Other datasets are read as usual.
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> Result<H5File> {
+        let file = File::open(&path)?;
+        // SAFETY: the file is opened read-only and must not be modified by
+        // other processes while it is mapped.
+        let mmap = unsafe { memmap2::Mmap::map(&std::fs::File::open(&path)?)? };
+        Ok(new_file(file, false, Some(mmap)))
+    }
+
+    /// The bundled HDF5 library has no ROS3 driver, so remote files cannot be
+    /// opened when it is linked, i.e., when the `static` feature is enabled.
+    #[cfg(all(feature = "ros3", not(ros3_driver)))]
+    pub fn open_remote(url: &str) -> Result<H5File> {
+        bail!(
+            "cannot open '{}': the bundled HDF5 library has no ROS3 driver, build without \
+             the `static` feature and link a system HDF5 built with ROS3",
+            url,
+        )
+    }
+}
+
+/// Read a `.h5ad` file into memory. See `MemAnnData`.
+pub fn read_h5ad_in_memory<P: AsRef<Path>>(path: P) -> Result<MemAnnData> {
+    MemAnnData::read::<H5, _>(path)
+}
+
 impl FileOp for H5File {
     type Backend = H5;
 
@@ -89,9 +163,15 @@ impl FileOp for H5File {
         hdf5::Location::filename(&self).into()
     }
 
+    /// Close the file. The memory map of a file opened by `H5::open_mmap` is
+    /// released once the groups and datasets opened from it are dropped too.
     fn close(self) -> Result<()> {
         Ok(self.0.close()?)
     }
+
+    fn flush(&self) -> Result<()> {
+        Ok(self.0.flush()?)
+    }
 }
 
 // Generic GroupOp implementations
@@ -100,20 +180,23 @@ fn list(group: &Group) -> Result<Vec<String>> {
     Ok(group.member_names()?)
 }
 
-fn create_group(group: &Group, name: &str) -> Result<H5Group> {
-    Ok(H5Group(group.create_group(name)?))
+fn create_group(group: &Group, state: &Arc<FileState>, name: &str) -> Result<H5Group> {
+    state.ensure_writable()?;
+    Ok(H5Group(group.create_group(name)?, state.clone()))
 }
 
-fn open_group(group: &Group, name: &str) -> Result<H5Group> {
-    Ok(H5Group(group.group(name)?))
+fn open_group(group: &Group, state: &Arc<FileState>, name: &str) -> Result<H5Group> {
+    Ok(H5Group(group.group(name)?, state.clone()))
 }
 
 fn new_dataset<T: BackendData>(
     group: &Group,
+    state: &Arc<FileState>,
     name: &str,
     shape: &Shape,
     config: WriteConfig,
 ) -> Result<H5Dataset> {
+    state.ensure_writable()?;
     let mut builder = match T::DTYPE {
         ScalarType::U8 => group.new_dataset::<u8>(),
         ScalarType::U16 => group.new_dataset::<u16>(),
@@ -124,18 +207,38 @@ fn new_dataset<T: BackendData>(
         ScalarType::I16 => group.new_dataset::<i16>(),
         ScalarType::I32 => group.new_dataset::<i32>(),
         ScalarType::I64 => group.new_dataset::<i64>(),
+        ScalarType::F16 => group.new_dataset::<f16>(),  
         ScalarType::F32 => group.new_dataset::<f32>(),  
         ScalarType::F64 => group.new_dataset::<f64>(),
+        ScalarType::ComplexF32 => group.new_dataset::<Complex32>(),
+        ScalarType::ComplexF64 => group.new_dataset::<Complex64>(),
         ScalarType::Bool => group.new_dataset::<bool>(),
         ScalarType::String => group.new_dataset::<VarLenUnicode>(),
     };
 
-    builder = if let Some(compression) = config.compression {
-        builder.deflate(compression)
-        //builder.blosc_blosclz(compression, hdf5::filters::BloscShuffle::Byte)
-    } else {
-        builder
-    };
+    builder = compress(builder, config.compression);
+
+    if let Some(fill) = config.fill_value {
+        T::from_dyn(fill.clone()).with_context(|| format!("the fill value must have type {}", T::DTYPE))?;
+        builder = match fill {
+            DynScalar::I8(x) => builder.fill_value(x),
+            DynScalar::I16(x) => builder.fill_value(x),
+            DynScalar::I32(x) => builder.fill_value(x),
+            DynScalar::I64(x) => builder.fill_value(x),
+            DynScalar::U8(x) => builder.fill_value(x),
+            DynScalar::U16(x) => builder.fill_value(x),
+            DynScalar::U32(x) => builder.fill_value(x),
+            DynScalar::U64(x) => builder.fill_value(x),
+            DynScalar::Usize(x) => builder.fill_value(x),
+            DynScalar::F16(x) => builder.fill_value(x),
+            DynScalar::F32(x) => builder.fill_value(x),
+            DynScalar::F64(x) => builder.fill_value(x),
+            DynScalar::ComplexF32(x) => builder.fill_value(x),
+            DynScalar::ComplexF64(x) => builder.fill_value(x),
+            DynScalar::Bool(x) => builder.fill_value(x),
+            DynScalar::String(x) => builder.fill_value(x.parse::<VarLenUnicode>()?),
+        };
+    }
 
     builder = if let Some(s) = config.block_size {
         if s.as_ref().iter().all(|&x| x > 0) {
@@ -149,14 +252,95 @@ fn new_dataset<T: BackendData>(
 
     let s: hdf5::Extents = hdf5::SimpleExtents::resizable(shape.as_ref()).into();
     let dataset = builder.shape(s).create(name)?;
-    Ok(H5Dataset(dataset))
+    Ok(H5Dataset(dataset, state.clone()))
 }
 
-fn open_dataset(group: &Group, name: &str) -> Result<H5Dataset> {
-    Ok(H5Dataset(group.dataset(name)?))
+/// Set the compression filter of a new dataset.
+fn compress(builder: DatasetBuilderEmpty, compression: Option<Compression>) -> DatasetBuilderEmpty {
+    match compression {
+        None => builder,
+        Some(Compression::Gzip(level)) => builder.deflate(level),
+        #[cfg(feature = "blosc")]
+        Some(Compression::Zstd(level)) if hdf5::filters::blosc_available() => {
+            builder.blosc_zstd(level, hdf5::filters::BloscShuffle::Byte)
+        }
+        #[cfg(feature = "blosc")]
+        Some(Compression::Lz4) if hdf5::filters::blosc_available() => {
+            builder.blosc_lz4(LZ4_LEVEL, hdf5::filters::BloscShuffle::Byte)
+        }
+        Some(compression) => {
+            static WARN: Once = Once::new();
+            WARN.call_once(|| warn!(
+                "{:?} compression is not available, falling back to gzip. \
+                Enable the 'blosc' feature to use zstd or lz4.", compression
+            ));
+            match compression {
+                Compression::Zstd(level) => builder.deflate(level.min(9)),
+                _ => builder.deflate(1),
+            }
+        }
+    }
+}
+
+/// Read a string array. Variable-length and fixed-length strings are both supported;
+/// the latter are used by other HDF5-based formats such as Loom and 10x Genomics.
+fn read_str_array<S, D>(dataset: &H5Dataset, selection: &[S]) -> Result<Array<String, D>>
+where
+    S: AsRef<SelectInfoElem>,
+    D: RemoveAxis,
+{
+    fn read<T, S, D>(dataset: &H5Dataset, selection: &[S]) -> Result<Array<String, D>>
+    where
+        T: H5Type + Deref<Target = str>,
+        S: AsRef<SelectInfoElem>,
+        D: RemoveAxis,
+    {
+        if dataset.deref().ndim() == 0 {
+            let s = dataset.deref().read_scalar::<T>()?.deref().to_string();
+            return Ok(Array::from_elem(IxDyn(&[]), s).into_dimensionality::<D>()?);
+        }
+        if selection.iter().any(|x| !x.as_ref().is_slice()) {
+            // fancy indexing is too slow, just read all
+            let arr = dataset.deref().read::<T, D>()?.map(|s| s.deref().to_string());
+            Ok(ArrayOp::select(&arr, selection))
+        } else {
+            let (select, shape) = into_selection(selection, dataset.shape());
+            let arr = if matches!(select, Selection::Points(_)) {
+                dataset
+                    .deref()
+                    .read_slice_1d::<T, _>(select)?
+                    .into_shape(shape.as_ref())?
+                    .into_dimensionality::<D>()?
+            } else {
+                dataset.deref().read_slice::<T, _, D>(select)?
+            };
+            Ok(arr.map(|s| s.deref().to_string()))
+        }
+    }
+
+    macro_rules! read_fixed {
+        ($ty:ident, $n:expr, $($size:literal),+) => {
+            match $n {
+                $(n if n <= $size => read::<$ty<$size>, _, D>(dataset, selection),)+
+                n => bail!("fixed-length strings longer than {} bytes are not supported", n),
+            }
+        };
+    }
+
+    match hdf5::Container::dtype(dataset)?.to_descriptor()? {
+        TypeDescriptor::VarLenAscii => read::<VarLenAscii, _, D>(dataset, selection),
+        TypeDescriptor::FixedAscii(n) => read_fixed!(FixedAscii, n, 16, 64, 256, 1024, 4096),
+        TypeDescriptor::FixedUnicode(n) => read_fixed!(FixedUnicode, n, 16, 64, 256, 1024, 4096),
+        _ => read::<VarLenUnicode, _, D>(dataset, selection),
+    }
 }
 
-fn delete(group: &Group, name: &str) -> Result<()> {
+fn open_dataset(group: &Group, state: &Arc<FileState>, name: &str) -> Result<H5Dataset> {
+    Ok(H5Dataset(group.dataset(name)?, state.clone()))
+}
+
+fn delete(group: &Group, state: &Arc<FileState>, name: &str) -> Result<()> {
+    state.ensure_writable()?;
     Ok(group.unlink(name)?)
 }
 
@@ -164,7 +348,36 @@ fn exists(group: &Group, name: &str) -> Result<bool> {
     Ok(group.link_exists(name))
 }
 
-fn create_scalar_data<D: BackendData>(group: &Group, name: &str, data: &D) -> Result<H5Dataset> {
+fn rename(group: &Group, state: &Arc<FileState>, name: &str, new_name: &str) -> Result<()> {
+    state.ensure_writable()?;
+    if group.link_exists(new_name) {
+        bail!("'{}' already exists", new_name);
+    }
+    Ok(group.relink(name, new_name)?)
+}
+
+fn new_external_link(
+    group: &Group,
+    state: &Arc<FileState>,
+    name: &str,
+    target_file: &Path,
+    target_path: &str,
+) -> Result<()> {
+    state.ensure_writable()?;
+    if group.link_exists(name) {
+        bail!("'{}' already exists", name);
+    }
+    let target_file = target_file.to_str().context("the path of the target file is not valid unicode")?;
+    Ok(group.link_external(target_file, target_path, name)?)
+}
+
+fn create_scalar_data<D: BackendData>(
+    group: &Group,
+    state: &Arc<FileState>,
+    name: &str,
+    data: &D,
+) -> Result<H5Dataset> {
+    state.ensure_writable()?;
     match data.into_dyn() {
         DynScalar::U8(x) => {
             let dataset = group.new_dataset::<u8>().create(name)?;
@@ -211,6 +424,11 @@ fn create_scalar_data<D: BackendData>(group: &Group, name: &str, data: &D) -> Re
             dataset.write_scalar(&x)?;
             Ok(dataset)
         }
+        DynScalar::F16(x) => {
+            let dataset = group.new_dataset::<f16>().create(name)?;
+            dataset.write_scalar(&x)?;
+            Ok(dataset)
+        }
         DynScalar::F32(x) => {
             let dataset = group.new_dataset::<f32>().create(name)?;
             dataset.write_scalar(&x)?;
@@ -221,6 +439,16 @@ fn create_scalar_data<D: BackendData>(group: &Group, name: &str, data: &D) -> Re
             dataset.write_scalar(&x)?;
             Ok(dataset)
         }
+        DynScalar::ComplexF32(x) => {
+            let dataset = group.new_dataset::<Complex32>().create(name)?;
+            dataset.write_scalar(&x)?;
+            Ok(dataset)
+        }
+        DynScalar::ComplexF64(x) => {
+            let dataset = group.new_dataset::<Complex64>().create(name)?;
+            dataset.write_scalar(&x)?;
+            Ok(dataset)
+        }
         DynScalar::Bool(x) => {
             let dataset = group.new_dataset::<bool>().create(name)?;
             dataset.write_scalar(&x)?;
@@ -232,7 +460,266 @@ fn create_scalar_data<D: BackendData>(group: &Group, name: &str, data: &D) -> Re
             Ok(dataset)
         }
     }
-    .map(H5Dataset)
+    .map(|dataset| H5Dataset(dataset, state.clone()))
+}
+
+/// The HDF5 type of the values of a field of a record array.
+fn field_descriptor(values: &DynArray) -> Result<TypeDescriptor> {
+    let ty = match values {
+        DynArray::I8(_) => i8::type_descriptor(),
+        DynArray::I16(_) => i16::type_descriptor(),
+        DynArray::I32(_) => i32::type_descriptor(),
+        DynArray::I64(_) => i64::type_descriptor(),
+        DynArray::U8(_) => u8::type_descriptor(),
+        DynArray::U16(_) => u16::type_descriptor(),
+        DynArray::U32(_) => u32::type_descriptor(),
+        DynArray::U64(_) => u64::type_descriptor(),
+        DynArray::Usize(_) => usize::type_descriptor(),
+        DynArray::F16(_) => f16::type_descriptor(),
+        DynArray::F32(_) => f32::type_descriptor(),
+        DynArray::F64(_) => f64::type_descriptor(),
+        DynArray::ComplexF32(_) => Complex32::type_descriptor(),
+        DynArray::ComplexF64(_) => Complex64::type_descriptor(),
+        DynArray::Bool(_) => bool::type_descriptor(),
+        DynArray::String(_) => VarLenUnicode::type_descriptor(),
+        DynArray::Categorical(_) => bail!("categorical fields are not supported in compound datasets"),
+    };
+    Ok(ty)
+}
+
+/// A compound type with the single field `name`. HDF5 matches the fields of
+/// compound types by name, so this type reads or writes only that field of a
+/// compound dataset.
+fn field_type(name: &str, ty: TypeDescriptor) -> Result<Datatype> {
+    let size = ty.size();
+    let ty = CompoundType { fields: vec![CompoundField::new(name, ty, 0, 0)], size };
+    Ok(Datatype::from_descriptor(&TypeDescriptor::Compound(ty))?)
+}
+
+/// Whether a dataset has a compound type other than complex numbers.
+fn is_record(dataset: &H5Dataset) -> Result<bool> {
+    let record = match hdf5::Container::dtype(dataset)?.to_descriptor()? {
+        TypeDescriptor::Compound(ty) => !is_complex(&ty, FloatSize::U4) && !is_complex(&ty, FloatSize::U8),
+        _ => false,
+    };
+    Ok(record)
+}
+
+fn create_record_data(
+    group: &Group,
+    state: &Arc<FileState>,
+    name: &str,
+    fields: &[(String, DynArray)],
+    config: WriteConfig,
+) -> Result<H5Dataset> {
+    state.ensure_writable()?;
+    let mut size = 0;
+    let members = fields
+        .iter()
+        .enumerate()
+        .map(|(i, (field, values))| {
+            let member = CompoundField::new(field, field_descriptor(values)?, size, i);
+            size += member.ty.size();
+            Ok(member)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let ty = TypeDescriptor::Compound(CompoundType { fields: members, size });
+    let len = fields.first().map_or(0, |(_, values)| values.shape()[0]);
+
+    let mut builder = group.new_dataset_builder().empty_as(&ty);
+    // Compressed datasets must be chunked, and empty datasets cannot be.
+    if len > 0 {
+        builder = compress(builder, config.compression);
+    }
+    let dataset = builder.shape(len).create(name)?;
+    for (field, values) in fields {
+        write_field(&dataset, field, values)?;
+    }
+    Ok(H5Dataset(dataset, state.clone()))
+}
+
+/// Write the values of one field of a compound dataset.
+fn write_field(dataset: &Dataset, name: &str, values: &DynArray) -> Result<()> {
+    fn write<T: H5Type>(dataset: &Dataset, name: &str, values: Vec<T>) -> Result<()> {
+        let mem_type = field_type(name, T::type_descriptor())?;
+        let status = unsafe {
+            H5Dwrite(dataset.id(), mem_type.id(), H5S_ALL, H5S_ALL, H5P_DEFAULT, values.as_ptr().cast())
+        };
+        ensure!(status >= 0, "cannot write field '{}' of '{}'", name, dataset.name());
+        Ok(())
+    }
+
+    match values {
+        DynArray::I8(x) => write(dataset, name, x.iter().copied().collect()),
+        DynArray::I16(x) => write(dataset, name, x.iter().copied().collect()),
+        DynArray::I32(x) => write(dataset, name, x.iter().copied().collect()),
+        DynArray::I64(x) => write(dataset, name, x.iter().copied().collect()),
+        DynArray::U8(x) => write(dataset, name, x.iter().copied().collect()),
+        DynArray::U16(x) => write(dataset, name, x.iter().copied().collect()),
+        DynArray::U32(x) => write(dataset, name, x.iter().copied().collect()),
+        DynArray::U64(x) => write(dataset, name, x.iter().copied().collect()),
+        DynArray::Usize(x) => write(dataset, name, x.iter().copied().collect()),
+        DynArray::F16(x) => write(dataset, name, x.iter().copied().collect()),
+        DynArray::F32(x) => write(dataset, name, x.iter().copied().collect()),
+        DynArray::F64(x) => write(dataset, name, x.iter().copied().collect()),
+        DynArray::ComplexF32(x) => write(dataset, name, x.iter().copied().collect()),
+        DynArray::ComplexF64(x) => write(dataset, name, x.iter().copied().collect()),
+        DynArray::Bool(x) => write(dataset, name, x.iter().copied().collect()),
+        DynArray::String(x) => {
+            let values = x.iter().map(|s| s.parse::<VarLenUnicode>()).collect::<Result<Vec<_>, _>>()?;
+            write(dataset, name, values)
+        }
+        DynArray::Categorical(_) => bail!("categorical fields are not supported in compound datasets"),
+    }
+}
+
+/// Read the fields of a one-dimensional compound dataset.
+fn read_record(dataset: &H5Dataset) -> Result<Vec<(String, DynArray)>> {
+    let ty = match hdf5::Container::dtype(dataset)?.to_descriptor()? {
+        TypeDescriptor::Compound(ty) => ty,
+        ty => bail!("expecting a compound dataset, found type {:?}", ty),
+    };
+    ensure!(dataset.deref().ndim() == 1, "compound datasets must be one-dimensional");
+    ty.fields
+        .into_iter()
+        .map(|field| {
+            let values = read_field(dataset, &field.name, &field.ty)?;
+            Ok((field.name, values))
+        })
+        .collect()
+}
+
+/// Read the values of one field of a compound dataset, which has type `ty` in the file.
+fn read_field(dataset: &Dataset, name: &str, ty: &TypeDescriptor) -> Result<DynArray> {
+    /// Read `width` values of type `T` per record.
+    fn read<T: H5Type>(dataset: &Dataset, name: &str, ty: TypeDescriptor, width: usize) -> Result<Vec<T>> {
+        let len = dataset.size() * width;
+        let mem_type = field_type(name, ty)?;
+        let mut values = Vec::<T>::with_capacity(len);
+        let status = unsafe {
+            H5Dread(dataset.id(), mem_type.id(), H5S_ALL, H5S_ALL, H5P_DEFAULT, values.as_mut_ptr().cast())
+        };
+        ensure!(status >= 0, "cannot read field '{}' of '{}'", name, dataset.name());
+        // SAFETY: the read succeeded, so HDF5 has initialized all `len` values.
+        unsafe { values.set_len(len) };
+        Ok(values)
+    }
+
+    macro_rules! read_as {
+        ($ty:ty) => {
+            DynArray::from(Array1::from_vec(read::<$ty>(dataset, name, <$ty>::type_descriptor(), 1)?))
+        };
+    }
+
+    let values = match ty {
+        TypeDescriptor::Unsigned(U1) => read_as!(u8),
+        TypeDescriptor::Unsigned(U2) => read_as!(u16),
+        TypeDescriptor::Unsigned(U4) => read_as!(u32),
+        TypeDescriptor::Unsigned(U8) => read_as!(u64),
+        TypeDescriptor::Integer(U1) => read_as!(i8),
+        TypeDescriptor::Integer(U2) => read_as!(i16),
+        TypeDescriptor::Integer(U4) => read_as!(i32),
+        TypeDescriptor::Integer(U8) => read_as!(i64),
+        TypeDescriptor::Float(FloatSize::U2) => read_as!(f16),
+        TypeDescriptor::Float(FloatSize::U4) => read_as!(f32),
+        TypeDescriptor::Float(FloatSize::U8) => read_as!(f64),
+        TypeDescriptor::Compound(x) if is_complex(x, FloatSize::U4) => read_as!(Complex32),
+        TypeDescriptor::Compound(x) if is_complex(x, FloatSize::U8) => read_as!(Complex64),
+        TypeDescriptor::Boolean => read_as!(bool),
+        TypeDescriptor::VarLenAscii => {
+            let values = read::<VarLenAscii>(dataset, name, ty.clone(), 1)?;
+            DynArray::from(values.iter().map(|s| s.to_string()).collect::<Array1<_>>())
+        }
+        TypeDescriptor::VarLenUnicode => {
+            let values = read::<VarLenUnicode>(dataset, name, ty.clone(), 1)?;
+            DynArray::from(values.iter().map(|s| s.to_string()).collect::<Array1<_>>())
+        }
+        // Fixed-length strings, e.g., the bytes fields written by h5py, are
+        // padded with nulls.
+        TypeDescriptor::FixedAscii(n) | TypeDescriptor::FixedUnicode(n) => {
+            let bytes = read::<u8>(dataset, name, ty.clone(), *n)?;
+            let values = bytes
+                .chunks(*n)
+                .map(|s| String::from_utf8_lossy(s.split(|&b| b == 0).next().unwrap_or(s)).into_owned())
+                .collect::<Array1<_>>();
+            DynArray::from(values)
+        }
+        ty => bail!("field '{}' has unsupported type: {:?}", name, ty),
+    };
+    Ok(values)
+}
+
+/// Read the selected elements of a dataset through the memory map of the file.
+/// Returns `None` if the file was not opened by `H5::open_mmap`, if the dataset
+/// lives in another file, e.g., behind an external link, or if it is not stored
+/// contiguously in the native layout of `T`.
+fn read_mmap<T, S, D>(dataset: &H5Dataset, selection: &[S]) -> Result<Option<Array<T, D>>>
+where
+    T: H5Type + BackendData,
+    S: AsRef<SelectInfoElem>,
+    D: RemoveAxis,
+{
+    let mmap = match &dataset.1.mmap {
+        Some(mmap) => mmap,
+        None => return Ok(None),
+    };
+    if matches!(T::DTYPE, ScalarType::Bool | ScalarType::String)
+        || PathBuf::from(hdf5::Location::filename(dataset)) != dataset.1.filename
+        || dataset.is_chunked()
+        || hdf5::Container::dtype(dataset)? != hdf5::Datatype::from_type::<T>()?
+    {
+        return Ok(None);
+    }
+    // Only contiguous datasets that have been allocated have an offset.
+    let offset = match dataset.offset() {
+        Some(offset) => offset as usize,
+        None => return Ok(None),
+    };
+    let shape = hdf5::Container::shape(dataset);
+    if shape.is_empty() {
+        return Ok(None);
+    }
+
+    let row_len: usize = shape[1..].iter().product();
+    let row_bytes = row_len * std::mem::size_of::<T>();
+    let rows = BoundedSelectInfoElem::new(selection[0].as_ref(), shape[0]).to_vec();
+    let mut data: Vec<T> = Vec::with_capacity(rows.len() * row_len);
+    for (k, i) in rows.iter().enumerate() {
+        let start = offset + i * row_bytes;
+        let bytes = mmap
+            .get(start..start + row_bytes)
+            .ok_or_else(|| anyhow::anyhow!("dataset is out of the bounds of the file"))?;
+        // The mapped bytes may not be aligned for `T`, so they are copied byte by byte.
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                data.as_mut_ptr().add(k * row_len) as *mut u8,
+                row_bytes,
+            );
+        }
+    }
+    unsafe { data.set_len(rows.len() * row_len) };
+
+    let mut new_shape = shape;
+    new_shape[0] = rows.len();
+    let arr = Array::from_shape_vec(IxDyn(&new_shape), data)?;
+    let rest: Vec<SelectInfoElem> = std::iter::once(SelectInfoElem::full())
+        .chain(selection[1..].iter().map(|x| x.as_ref().clone()))
+        .collect();
+    Ok(Some(ArrayOp::select(&arr, rest.as_slice()).into_dimensionality::<D>()?))
+}
+
+/// Whether a compound type stores complex numbers in the layout used by h5py,
+/// i.e., two float fields named `r` and `i`.
+fn is_complex(ty: &CompoundType, size: FloatSize) -> bool {
+    match ty.fields.as_slice() {
+        [re, im] => {
+            re.name == "r" && im.name == "i"
+                && re.ty == TypeDescriptor::Float(size)
+                && im.ty == TypeDescriptor::Float(size)
+        }
+        _ => false,
+    }
 }
 
 impl DatasetOp for H5Dataset {
@@ -248,11 +735,16 @@ impl DatasetOp for H5Dataset {
             TypeDescriptor::Integer(U2) => ScalarType::I16,
             TypeDescriptor::Integer(U4) => ScalarType::I32,
             TypeDescriptor::Integer(U8) => ScalarType::I64,
+            TypeDescriptor::Float(FloatSize::U2) => ScalarType::F16,
             TypeDescriptor::Float(FloatSize::U4) => ScalarType::F32,
             TypeDescriptor::Float(FloatSize::U8) => ScalarType::F64,
+            TypeDescriptor::Compound(ty) if is_complex(&ty, FloatSize::U4) => ScalarType::ComplexF32,
+            TypeDescriptor::Compound(ty) if is_complex(&ty, FloatSize::U8) => ScalarType::ComplexF64,
             TypeDescriptor::Boolean => ScalarType::Bool,
             TypeDescriptor::VarLenAscii => ScalarType::String,
             TypeDescriptor::VarLenUnicode => ScalarType::String,
+            TypeDescriptor::FixedAscii(_) => ScalarType::String,
+            TypeDescriptor::FixedUnicode(_) => ScalarType::String,
             ty => bail!("Unsupported type: {:?}", ty),
         };
         Ok(ty)
@@ -263,9 +755,18 @@ impl DatasetOp for H5Dataset {
     }
 
     fn reshape(&self, shape: &Shape) -> Result<()> {
+        self.1.ensure_writable()?;
         Ok(Dataset::resize(self, shape.as_ref())?)
     }
 
+    fn is_record(&self) -> Result<bool> {
+        is_record(self)
+    }
+
+    fn read_record(&self) -> Result<Vec<(String, DynArray)>> {
+        read_record(self)
+    }
+
     fn read_scalar<T: BackendData>(&self) -> Result<T> {
         let val = match T::DTYPE {
             ScalarType::Bool => self.deref().read_scalar::<bool>()?.into_dyn(),
@@ -278,16 +779,49 @@ impl DatasetOp for H5Dataset {
             ScalarType::I16 => self.deref().read_scalar::<i16>()?.into_dyn(),
             ScalarType::I32 => self.deref().read_scalar::<i32>()?.into_dyn(),
             ScalarType::I64 => self.deref().read_scalar::<i64>()?.into_dyn(),
+            ScalarType::F16 => self.deref().read_scalar::<f16>()?.into_dyn(),
             ScalarType::F32 => self.deref().read_scalar::<f32>()?.into_dyn(),
             ScalarType::F64 => self.deref().read_scalar::<f64>()?.into_dyn(),
+            ScalarType::ComplexF32 => self.deref().read_scalar::<Complex32>()?.into_dyn(),
+            ScalarType::ComplexF64 => self.deref().read_scalar::<Complex64>()?.into_dyn(),
             ScalarType::String => {
-                let s = self.deref().read_scalar::<VarLenUnicode>()?;
-                s.to_string().into_dyn()
+                let arr = read_str_array::<_, Ix0>(self, &[] as &[SelectInfoElem])?;
+                arr.into_scalar().into_dyn()
             }
         };
         BackendData::from_dyn(val)
     }
 
+    fn fill_value(&self) -> Result<Option<DynScalar>> {
+        fn get<T: H5Type>(dcpl: &DatasetCreate) -> Result<T> {
+            dcpl.fill_value_as::<T>()?.context("cannot read the fill value")
+        }
+
+        let dcpl = self.deref().dcpl()?;
+        if !matches!(dcpl.fill_value_defined(), FillValue::UserDefined) {
+            return Ok(None);
+        }
+        let val = match DatasetOp::dtype(self)? {
+            ScalarType::Bool => get::<bool>(&dcpl)?.into_dyn(),
+            ScalarType::U8 => get::<u8>(&dcpl)?.into_dyn(),
+            ScalarType::U16 => get::<u16>(&dcpl)?.into_dyn(),
+            ScalarType::U32 => get::<u32>(&dcpl)?.into_dyn(),
+            ScalarType::U64 => get::<u64>(&dcpl)?.into_dyn(),
+            ScalarType::Usize => get::<usize>(&dcpl)?.into_dyn(),
+            ScalarType::I8 => get::<i8>(&dcpl)?.into_dyn(),
+            ScalarType::I16 => get::<i16>(&dcpl)?.into_dyn(),
+            ScalarType::I32 => get::<i32>(&dcpl)?.into_dyn(),
+            ScalarType::I64 => get::<i64>(&dcpl)?.into_dyn(),
+            ScalarType::F16 => get::<f16>(&dcpl)?.into_dyn(),
+            ScalarType::F32 => get::<f32>(&dcpl)?.into_dyn(),
+            ScalarType::F64 => get::<f64>(&dcpl)?.into_dyn(),
+            ScalarType::ComplexF32 => get::<Complex32>(&dcpl)?.into_dyn(),
+            ScalarType::ComplexF64 => get::<Complex64>(&dcpl)?.into_dyn(),
+            ScalarType::String => DynScalar::String(get::<VarLenUnicode>(&dcpl)?.to_string()),
+        };
+        Ok(Some(val))
+    }
+
     fn read_array_slice<T, S, D>(&self, selection: &[S]) -> Result<Array<T, D>>
     where
         T: BackendData,
@@ -300,7 +834,10 @@ impl DatasetOp for H5Dataset {
             S: AsRef<SelectInfoElem>,
             D: RemoveAxis,
         {
-            if selection.iter().any(|x| x.as_ref().is_index()) {
+            if selection.iter().any(|x| !x.as_ref().is_slice()) {
+                if let Some(arr) = read_mmap(dataset, selection)? {
+                    return Ok(arr);
+                }
                 // fancy indexing is too slow, just read all
                 let arr = dataset.deref().read::<T, D>()?;
                 Ok(ArrayOp::select(&arr, selection))
@@ -327,35 +864,13 @@ impl DatasetOp for H5Dataset {
             ScalarType::U32 => read_arr::<u32, _, D>(self, selection)?.into(),
             ScalarType::U64 => read_arr::<u64, _, D>(self, selection)?.into(),
             ScalarType::Usize => read_arr::<usize, _, D>(self, selection)?.into(),
+            ScalarType::F16 => read_arr::<f16, _, D>(self, selection)?.into(),
             ScalarType::F32 => read_arr::<f32, _, D>(self, selection)?.into(),
             ScalarType::F64 => read_arr::<f64, _, D>(self, selection)?.into(),
+            ScalarType::ComplexF32 => read_arr::<Complex32, _, D>(self, selection)?.into(),
+            ScalarType::ComplexF64 => read_arr::<Complex64, _, D>(self, selection)?.into(),
             ScalarType::Bool => read_arr::<bool, _, D>(self, selection)?.into(),
-            ScalarType::String => {
-                if selection.as_ref().iter().any(|x| x.as_ref().is_index()) {
-                    // fancy indexing is too slow, just read all
-                    let arr = self.deref().read::<VarLenUnicode, D>()?;
-                    let arr_ = arr.map(|s| s.to_string());
-                    let r: Result<_> = Ok(ArrayOp::select(&arr_, selection));
-                    r
-                } else {
-                    let (select, shape) = into_selection(selection, self.shape());
-                    let arr: Result<_> = if matches!(select, Selection::Points(_)) {
-                        let slice_1d = self.deref().read_slice_1d::<VarLenUnicode, _>(select)?;
-                        Ok(slice_1d
-                            .into_shape(shape.as_ref())?
-                            .into_dimensionality::<D>()?)
-                    } else {
-                        Ok(self.deref().read_slice::<VarLenUnicode, _, D>(select)?)
-                    };
-                    Ok(arr?.map(|s| s.to_string()))
-                }?
-                .into()
-                /*
-                let arr = read_arr::<VarLenUnicode, _, _, D>(dataset, selection)?;
-                let arr = arr.map(|s| s.to_string());
-                arr.into()
-                */
-            }
+            ScalarType::String => read_str_array::<_, D>(self, selection)?.into(),
         };
         Ok(BackendData::from_dyn_arr(array)?.into_dimensionality::<D>()?)
     }
@@ -382,6 +897,7 @@ impl DatasetOp for H5Dataset {
             Ok(())
         }
 
+        self.1.ensure_writable()?;
         match BackendData::into_dyn_arr(data.into()) {
             DynArrayView::U8(x) => write_array_impl(self, x, selection),
             DynArrayView::U16(x) => write_array_impl(self, x, selection),
@@ -392,8 +908,11 @@ impl DatasetOp for H5Dataset {
             DynArrayView::I16(x) => write_array_impl(self, x, selection),
             DynArrayView::I32(x) => write_array_impl(self, x, selection),
             DynArrayView::I64(x) => write_array_impl(self, x, selection),
+            DynArrayView::F16(x) => write_array_impl(self, x, selection),
             DynArrayView::F32(x) => write_array_impl(self, x, selection),
             DynArrayView::F64(x) => write_array_impl(self, x, selection),
+            DynArrayView::ComplexF32(x) => write_array_impl(self, x, selection),
+            DynArrayView::ComplexF64(x) => write_array_impl(self, x, selection),
             DynArrayView::Bool(x) => write_array_impl(self, x, selection),
             DynArrayView::String(x) => {
                 let data: Array<VarLenUnicode, D> = x.map(|x| x.parse().unwrap());
@@ -405,20 +924,21 @@ impl DatasetOp for H5Dataset {
 
 // Generic `LocationOp` functions
 
-fn file(loc: &Location) -> Result<H5File> {
-    Ok(H5File(hdf5::Location::file(loc)?))
+fn file(loc: &Location, state: &Arc<FileState>) -> Result<H5File> {
+    Ok(H5File(hdf5::Location::file(loc)?, state.clone()))
 }
 
 fn path(loc: &Location) -> PathBuf {
     hdf5::Location::name(loc).into()
 }
 
-fn write_array_attr<'a, A, D, Dim>(loc: &Location, name: &str, value: A) -> Result<()>
+fn write_array_attr<'a, A, D, Dim>(loc: &Location, state: &Arc<FileState>, name: &str, value: A) -> Result<()>
 where
     A: Into<ArrayView<'a, D, Dim>>,
     D: BackendData,
     Dim: RemoveAxis,
 {
+    state.ensure_writable()?;
     del_attr(loc, name);
     match BackendData::into_dyn_arr(value.into()) {
         DynArrayView::U8(x) => loc.new_attr_builder().with_data(x).create(name)?,
@@ -430,8 +950,11 @@ where
         DynArrayView::I16(x) => loc.new_attr_builder().with_data(x).create(name)?,
         DynArrayView::I32(x) => loc.new_attr_builder().with_data(x).create(name)?,
         DynArrayView::I64(x) => loc.new_attr_builder().with_data(x).create(name)?,
+        DynArrayView::F16(x) => loc.new_attr_builder().with_data(x).create(name)?,
         DynArrayView::F32(x) => loc.new_attr_builder().with_data(x).create(name)?,
         DynArrayView::F64(x) => loc.new_attr_builder().with_data(x).create(name)?,
+        DynArrayView::ComplexF32(x) => loc.new_attr_builder().with_data(x).create(name)?,
+        DynArrayView::ComplexF64(x) => loc.new_attr_builder().with_data(x).create(name)?,
         DynArrayView::Bool(x) => loc.new_attr_builder().with_data(x).create(name)?,
         DynArrayView::String(x) => {
             let data: Array<VarLenUnicode, Dim> = x.map(|x| x.parse().unwrap());
@@ -441,7 +964,8 @@ where
     Ok(())
 }
 
-fn write_str_attr(loc: &Location, name: &str, value: &str) -> Result<()> {
+fn write_str_attr(loc: &Location, state: &Arc<FileState>, name: &str, value: &str) -> Result<()> {
+    state.ensure_writable()?;
     let value_: VarLenUnicode = value.parse().unwrap();
     let attr = loc
         .attr(name)
@@ -450,7 +974,8 @@ fn write_str_attr(loc: &Location, name: &str, value: &str) -> Result<()> {
     Ok(())
 }
 
-fn write_scalar_attr<D: BackendData>(loc: &Location, name: &str, value: D) -> Result<()> {
+fn write_scalar_attr<D: BackendData>(loc: &Location, state: &Arc<FileState>, name: &str, value: D) -> Result<()> {
+    state.ensure_writable()?;
     del_attr(loc, name);
     match value.into_dyn() {
         DynScalar::U8(x) => loc.new_attr::<u8>().create(name)?.write_scalar(&x)?,
@@ -462,8 +987,11 @@ fn write_scalar_attr<D: BackendData>(loc: &Location, name: &str, value: D) -> Re
         DynScalar::I16(x) => loc.new_attr::<i16>().create(name)?.write_scalar(&x)?,
         DynScalar::I32(x) => loc.new_attr::<i32>().create(name)?.write_scalar(&x)?,
         DynScalar::I64(x) => loc.new_attr::<i64>().create(name)?.write_scalar(&x)?,
+        DynScalar::F16(x) => loc.new_attr::<f16>().create(name)?.write_scalar(&x)?,
         DynScalar::F32(x) => loc.new_attr::<f32>().create(name)?.write_scalar(&x)?,
         DynScalar::F64(x) => loc.new_attr::<f64>().create(name)?.write_scalar(&x)?,
+        DynScalar::ComplexF32(x) => loc.new_attr::<Complex32>().create(name)?.write_scalar(&x)?,
+        DynScalar::ComplexF64(x) => loc.new_attr::<Complex64>().create(name)?.write_scalar(&x)?,
         DynScalar::Bool(x) => loc.new_attr::<bool>().create(name)?.write_scalar(&x)?,
         DynScalar::String(x) => {
             let value_: VarLenUnicode = x.parse().unwrap();
@@ -485,8 +1013,11 @@ fn read_scalar_attr<T: BackendData>(loc: &Location, name: &str) -> Result<T> {
         ScalarType::U32 => attr.read_scalar::<u32>()?.into_dyn(),
         ScalarType::U64 => attr.read_scalar::<u64>()?.into_dyn(),
         ScalarType::Usize => attr.read_scalar::<usize>()?.into_dyn(),
+        ScalarType::F16 => attr.read_scalar::<f16>()?.into_dyn(),
         ScalarType::F32 => attr.read_scalar::<f32>()?.into_dyn(),
         ScalarType::F64 => attr.read_scalar::<f64>()?.into_dyn(),
+        ScalarType::ComplexF32 => attr.read_scalar::<Complex32>()?.into_dyn(),
+        ScalarType::ComplexF64 => attr.read_scalar::<Complex64>()?.into_dyn(),
         ScalarType::Bool => attr.read_scalar::<bool>()?.into_dyn(),
         ScalarType::String => attr.read_scalar::<VarLenUnicode>()?.to_string().into_dyn(),
     };
@@ -509,8 +1040,11 @@ fn read_array_attr<T: BackendData, D: RemoveAxis>(loc: &Location, name: &str) ->
             ScalarType::U32 => attr.read::<u32, D>()?.into(),
             ScalarType::U64 => attr.read::<u64, D>()?.into(),
             ScalarType::Usize => attr.read::<usize, D>()?.into(),
+            ScalarType::F16 => attr.read::<f16, D>()?.into(),
             ScalarType::F32 => attr.read::<f32, D>()?.into(),
             ScalarType::F64 => attr.read::<f64, D>()?.into(),
+            ScalarType::ComplexF32 => attr.read::<Complex32, D>()?.into(),
+            ScalarType::ComplexF64 => attr.read::<Complex64, D>()?.into(),
             ScalarType::Bool => attr.read::<bool, D>()?.into(),
             ScalarType::String => {
                 let s = attr.read::<VarLenUnicode, D>()?;
@@ -533,11 +1067,11 @@ impl GroupOp for H5File {
     }
 
     fn create_group(&self, name: &str) -> Result<<Self::Backend as Backend>::Group> {
-        create_group(self, name)
+        create_group(self, &self.1, name)
     }
 
     fn open_group(&self, name: &str) -> Result<<Self::Backend as Backend>::Group> {
-        open_group(self, name)
+        open_group(self, &self.1, name)
     }
 
     fn new_dataset<T: BackendData>(
@@ -546,27 +1080,44 @@ impl GroupOp for H5File {
         shape: &Shape,
         config: WriteConfig,
     ) -> Result<<Self::Backend as Backend>::Dataset> {
-        new_dataset::<T>(self, name, shape, config)
+        new_dataset::<T>(self, &self.1, name, shape, config)
     }
 
     fn open_dataset(&self, name: &str) -> Result<<Self::Backend as Backend>::Dataset> {
-        open_dataset(self, name)
+        open_dataset(self, &self.1, name)
     }
 
     fn delete(&self, name: &str) -> Result<()> {
-        delete(self, name)
+        delete(self, &self.1, name)
     }
 
     fn exists(&self, name: &str) -> Result<bool> {
         exists(self, name)
     }
 
+    fn rename(&self, name: &str, new_name: &str) -> Result<()> {
+        rename(self, &self.1, name, new_name)
+    }
+
+    fn new_external_link(&self, name: &str, target_file: &Path, target_path: &str) -> Result<()> {
+        new_external_link(self, &self.1, name, target_file, target_path)
+    }
+
     fn create_scalar_data<D: BackendData>(
         &self,
         name: &str,
         data: &D,
     ) -> Result<<Self::Backend as Backend>::Dataset> {
-        create_scalar_data(self, name, data)
+        create_scalar_data(self, &self.1, name, data)
+    }
+
+    fn create_record_data(
+        &self,
+        name: &str,
+        fields: &[(String, DynArray)],
+        config: WriteConfig,
+    ) -> Result<<Self::Backend as Backend>::Dataset> {
+        create_record_data(self, &self.1, name, fields, config)
     }
 }
 
@@ -578,11 +1129,11 @@ impl GroupOp for H5Group {
     }
 
     fn create_group(&self, name: &str) -> Result<<Self::Backend as Backend>::Group> {
-        create_group(self, name)
+        create_group(self, &self.1, name)
     }
 
     fn open_group(&self, name: &str) -> Result<<Self::Backend as Backend>::Group> {
-        open_group(self, name)
+        open_group(self, &self.1, name)
     }
 
     fn new_dataset<T: BackendData>(
@@ -591,27 +1142,44 @@ impl GroupOp for H5Group {
         shape: &Shape,
         config: WriteConfig,
     ) -> Result<<Self::Backend as Backend>::Dataset> {
-        new_dataset::<T>(self, name, shape, config)
+        new_dataset::<T>(self, &self.1, name, shape, config)
     }
 
     fn open_dataset(&self, name: &str) -> Result<<Self::Backend as Backend>::Dataset> {
-        open_dataset(self, name)
+        open_dataset(self, &self.1, name)
     }
 
     fn delete(&self, name: &str) -> Result<()> {
-        delete(self, name)
+        delete(self, &self.1, name)
     }
 
     fn exists(&self, name: &str) -> Result<bool> {
         exists(self, name)
     }
 
+    fn rename(&self, name: &str, new_name: &str) -> Result<()> {
+        rename(self, &self.1, name, new_name)
+    }
+
+    fn new_external_link(&self, name: &str, target_file: &Path, target_path: &str) -> Result<()> {
+        new_external_link(self, &self.1, name, target_file, target_path)
+    }
+
     fn create_scalar_data<D: BackendData>(
         &self,
         name: &str,
         data: &D,
     ) -> Result<<Self::Backend as Backend>::Dataset> {
-        create_scalar_data(self, name, data)
+        create_scalar_data(self, &self.1, name, data)
+    }
+
+    fn create_record_data(
+        &self,
+        name: &str,
+        fields: &[(String, DynArray)],
+        config: WriteConfig,
+    ) -> Result<<Self::Backend as Backend>::Dataset> {
+        create_record_data(self, &self.1, name, fields, config)
     }
 }
 
@@ -619,7 +1187,7 @@ impl LocationOp for H5Group {
     type Backend = H5;
 
     fn file(&self) -> Result<<Self::Backend as Backend>::File> {
-        file(self)
+        file(self, &self.1)
     }
 
     fn path(&self) -> PathBuf {
@@ -632,15 +1200,15 @@ impl LocationOp for H5Group {
         D: BackendData,
         Dim: RemoveAxis,
     {
-        write_array_attr(self, name, value)
+        write_array_attr(self, &self.1, name, value)
     }
 
     fn write_scalar_attr<D: BackendData>(&self, name: &str, value: D) -> Result<()> {
-        write_scalar_attr(self, name, value)
+        write_scalar_attr(self, &self.1, name, value)
     }
 
     fn write_str_attr(&self, name: &str, value: &str) -> Result<()> {
-        write_str_attr(self, name, value)
+        write_str_attr(self, &self.1, name, value)
     }
 
     fn read_scalar_attr<T: BackendData>(&self, name: &str) -> Result<T> {
@@ -656,7 +1224,7 @@ impl LocationOp for H5Dataset {
     type Backend = H5;
 
     fn file(&self) -> Result<<Self::Backend as Backend>::File> {
-        file(self)
+        file(self, &self.1)
     }
 
     fn path(&self) -> PathBuf {
@@ -669,15 +1237,15 @@ impl LocationOp for H5Dataset {
         D: BackendData,
         Dim: RemoveAxis,
     {
-        write_array_attr(self, name, value)
+        write_array_attr(self, &self.1, name, value)
     }
 
     fn write_scalar_attr<D: BackendData>(&self, name: &str, value: D) -> Result<()> {
-        write_scalar_attr(self, name, value)
+        write_scalar_attr(self, &self.1, name, value)
     }
 
     fn write_str_attr(&self, name: &str, value: &str) -> Result<()> {
-        write_str_attr(self, name, value)
+        write_str_attr(self, &self.1, name, value)
     }
 
     fn read_scalar_attr<T: BackendData>(&self, name: &str) -> Result<T> {
@@ -725,10 +1293,10 @@ fn del_attr(loc: &Location, name: &str) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use anndata::s;
+    use anndata::{backend::default_block_size, s};
     use ndarray_rand::rand_distr::Uniform;
     use ndarray_rand::RandomExt;
-    use ndarray::{Array1, Axis, concatenate, Ix1};
+    use ndarray::{Array1, Axis, concatenate, Ix1, Ix2};
     use std::path::PathBuf;
     use tempfile::tempdir;
 
@@ -770,6 +1338,82 @@ mod tests {
         })
     }
 
+    #[test]
+    fn test_compression() -> Result<()> {
+        with_tmp_path(|path| {
+            let file = H5::create(&path)?;
+            let arr = Array::random((200, 50), Uniform::new(0, 100));
+            let compressions = [
+                None,
+                Some(Compression::Gzip(4)),
+                Some(Compression::Zstd(3)),
+                Some(Compression::Lz4),
+            ];
+            for (i, compression) in compressions.into_iter().enumerate() {
+                let config = WriteConfig {
+                    compression,
+                    ..Default::default()
+                };
+                let dataset = file.create_array_data(&format!("test_{}", i), &arr, config)?;
+                assert_eq!(arr, dataset.read_array::<i32, Ix2>()?);
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_chunk_shape() -> Result<()> {
+        with_tmp_path(|path| {
+            let file = H5::create(&path)?;
+            let arr = Array::random((1000, 300), Uniform::new(0.0, 1.0));
+
+            let dataset = file.create_array_data("default", &arr, Default::default())?;
+            let expected = default_block_size(&[1000, 300], std::mem::size_of::<f64>());
+            assert_eq!(dataset.0.chunk(), Some(expected.as_ref().to_vec()));
+
+            let config = WriteConfig {
+                block_size: Some((1000, 1).into()),
+                ..Default::default()
+            };
+            let dataset = file.create_array_data("column", &arr, config)?;
+            assert_eq!(dataset.0.chunk(), Some(vec![1000, 1]));
+            assert_eq!(
+                arr.slice(ndarray::s![10..20, ..]),
+                dataset.read_array_slice::<f64, _, Ix2>(s![10..20, ..].as_ref())?,
+            );
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_fill_value() -> Result<()> {
+        with_tmp_path(|path| {
+            let file = H5::create(&path)?;
+            let mut arr = Array::from_elem((100, 100), 7i32);
+            arr[[3, 5]] = 1;
+            let config = WriteConfig {
+                compression: None,
+                block_size: Some((10, 10).into()),
+                fill_value: Some(DynScalar::I32(7)),
+            };
+            let dataset = file.create_array_data("background", &arr, config)?;
+            assert_eq!(dataset.fill_value()?, Some(DynScalar::I32(7)));
+            assert_eq!(arr, dataset.read_array::<i32, Ix2>()?);
+            // Only the block with the non-background value is stored.
+            assert_eq!(dataset.0.storage_size(), 10 * 10 * 4);
+
+            let dataset = file.create_array_data("default", &arr, Default::default())?;
+            assert_eq!(dataset.fill_value()?, None);
+
+            let config = WriteConfig {
+                fill_value: Some(DynScalar::F64(7.0)),
+                ..Default::default()
+            };
+            assert!(file.create_array_data("mismatch", &arr, config).is_err());
+            Ok(())
+        })
+    }
+
     #[test]
     fn test_write_slice() -> Result<()> {
         with_tmp_path(|path| -> Result<()> {
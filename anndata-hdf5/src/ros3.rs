@@ -0,0 +1,136 @@
+//! Read-only access to remote files through the HDF5 read-only S3 (ROS3)
+//! virtual file driver. The driver fetches data with HTTP range requests, so
+//! only the parts of the file that are read are transferred.
+//!
+//! The HDF5 library must be built with ROS3 support, which is not the case for
+//! the library bundled by `hdf5-sys`. Remote files can therefore only be opened
+//! when the default `static` feature is disabled and a system HDF5 built with
+//! ROS3 is linked, e.g., `--no-default-features --features ros3`.
+
+use crate::{new_file, H5File, H5};
+
+use anyhow::{bail, Context, Result};
+use hdf5::File;
+use hdf5_sys::h5::{hbool_t, herr_t, H5open};
+use hdf5_sys::h5f::{H5Fopen, H5F_ACC_RDONLY};
+use hdf5_sys::h5i::hid_t;
+use hdf5_sys::h5p::{H5Pclose, H5Pcreate, H5P_CLS_FILE_ACCESS};
+use std::ffi::CString;
+use std::os::raw::c_char;
+
+const ROS3_FAPL_VERSION: i32 = 1;
+const MAX_REGION_LEN: usize = 32;
+const MAX_SECRET_ID_LEN: usize = 128;
+const MAX_SECRET_KEY_LEN: usize = 128;
+
+/// Mirrors `H5FD_ros3_fapl_t` in `H5FDros3.h`.
+#[repr(C)]
+struct Ros3Config {
+    version: i32,
+    authenticate: hbool_t,
+    aws_region: [c_char; MAX_REGION_LEN + 1],
+    secret_id: [c_char; MAX_SECRET_ID_LEN + 1],
+    secret_key: [c_char; MAX_SECRET_KEY_LEN + 1],
+}
+
+extern "C" {
+    fn H5Pset_fapl_ros3(fapl_id: hid_t, fa: *const Ros3Config) -> herr_t;
+}
+
+impl Ros3Config {
+    /// Read the credentials from the `AWS_REGION`, `AWS_ACCESS_KEY_ID` and
+    /// `AWS_SECRET_ACCESS_KEY` environment variables. Requests are anonymous
+    /// unless all three are set.
+    fn from_env() -> Result<Self> {
+        let mut config = Self {
+            version: ROS3_FAPL_VERSION,
+            authenticate: 0,
+            aws_region: [0; MAX_REGION_LEN + 1],
+            secret_id: [0; MAX_SECRET_ID_LEN + 1],
+            secret_key: [0; MAX_SECRET_KEY_LEN + 1],
+        };
+        let vars = ["AWS_REGION", "AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY"].map(std::env::var);
+        if let [Ok(region), Ok(id), Ok(key)] = vars {
+            copy_str(&mut config.aws_region, &region, "AWS_REGION")?;
+            copy_str(&mut config.secret_id, &id, "AWS_ACCESS_KEY_ID")?;
+            copy_str(&mut config.secret_key, &key, "AWS_SECRET_ACCESS_KEY")?;
+            config.authenticate = 1;
+        }
+        Ok(config)
+    }
+}
+
+fn copy_str(dst: &mut [c_char], src: &str, name: &str) -> Result<()> {
+    if src.len() >= dst.len() {
+        bail!("{} must be shorter than {} characters", name, dst.len());
+    }
+    dst.iter_mut().zip(src.bytes()).for_each(|(d, s)| *d = s as c_char);
+    Ok(())
+}
+
+/// Convert `s3://bucket/key` to the virtual-hosted-style HTTPS URL expected by
+/// the driver. Other URLs are returned as is.
+fn to_https_url(url: &str, region: Option<&str>) -> Result<String> {
+    match url.strip_prefix("s3://") {
+        Some(path) => {
+            let (bucket, key) = path.split_once('/').context("S3 URL must be of the form s3://bucket/key")?;
+            let host = match region {
+                Some(region) => format!("{}.s3.{}.amazonaws.com", bucket, region),
+                None => format!("{}.s3.amazonaws.com", bucket),
+            };
+            Ok(format!("https://{}/{}", host, key))
+        }
+        None => Ok(url.to_string()),
+    }
+}
+
+impl H5 {
+    /// Opens a remote file as read-only, given an `s3://`, `http://` or
+    /// `https://` URL. Data are fetched on demand, e.g., selecting rows of a
+    /// chunked dataset only downloads the chunks containing these rows.
+    /// Credentials are read from the `AWS_REGION`, `AWS_ACCESS_KEY_ID` and
+    /// `AWS_SECRET_ACCESS_KEY` environment variables if they are all set.
+    pub fn open_remote(url: &str) -> Result<H5File> {
+        let config = Ros3Config::from_env()?;
+        let region = std::env::var("AWS_REGION").ok();
+        let url = CString::new(to_https_url(url, region.as_deref())?)?;
+        let id = unsafe {
+            H5open();
+            let fapl = H5Pcreate(*H5P_CLS_FILE_ACCESS);
+            if fapl < 0 {
+                bail!("failed to create the file access property list");
+            }
+            let id = if H5Pset_fapl_ros3(fapl, &config) < 0 {
+                -1
+            } else {
+                H5Fopen(url.as_ptr(), H5F_ACC_RDONLY, fapl)
+            };
+            H5Pclose(fapl);
+            id
+        };
+        if id < 0 {
+            bail!("failed to open remote file '{}'", url.to_string_lossy());
+        }
+        let file: File = unsafe { hdf5::from_id(id)? };
+        Ok(new_file(file, false, None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_s3_url() {
+        assert_eq!(
+            to_https_url("s3://bucket/dir/data.h5ad", None).unwrap(),
+            "https://bucket.s3.amazonaws.com/dir/data.h5ad",
+        );
+        assert_eq!(
+            to_https_url("s3://bucket/data.h5ad", Some("us-west-2")).unwrap(),
+            "https://bucket.s3.us-west-2.amazonaws.com/data.h5ad",
+        );
+        assert_eq!(to_https_url("https://example.com/a.h5ad", None).unwrap(), "https://example.com/a.h5ad");
+        assert!(to_https_url("s3://bucket", None).is_err());
+    }
+}
@@ -8,7 +8,8 @@ use anndata::{
 use anndata::data::slice::BoundedSlice;
 
 use std::str::FromStr;
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Result};
+use half::f16;
 use n5::{
     filesystem::N5Filesystem, ndarray::N5NdarrayWriter, DataType, DatasetAttributes, N5Lister,
     N5Reader, N5Writer, ReadableDataBlock, ReflectedType, SliceDataBlock,
@@ -158,6 +159,7 @@ impl GroupOp for Group {
         shape: &Shape,
         config: WriteConfig,
     ) -> Result<<Self::Backend as Backend>::Dataset> {
+        ensure!(config.fill_value.is_none(), "fill values are not supported by the N5 backend");
         let dimensions = shape.as_ref().iter().map(|&d| d as u64).collect();
         let block_size = config
             .block_size
@@ -166,7 +168,7 @@ impl GroupOp for Group {
             .iter()
             .map(|x| *x as u32)
             .collect();
-        let data_type = get_data_type::<T>();
+        let data_type = get_data_type::<T>()?;
 
         let path = self.child_path(name);
         let attr = DatasetAttributes::new(dimensions, block_size, data_type, Default::default());
@@ -210,6 +212,16 @@ impl GroupOp for Group {
         Ok(self.root.exists(&self.child_path(name).to_string_lossy())?)
     }
 
+    /// Rename a group or dataset by moving its directory.
+    fn rename(&self, name: &str, new_name: &str) -> Result<()> {
+        if self.exists(new_name)? {
+            bail!("'{}' already exists", new_name);
+        }
+        let dir = |path: PathBuf| self.filename.join(path.strip_prefix("/").unwrap_or(&path));
+        std::fs::rename(dir(self.child_path(name)), dir(self.child_path(new_name)))?;
+        Ok(())
+    }
+
     fn create_scalar_data<D: BackendData>(
         &self,
         name: &str,
@@ -218,7 +230,7 @@ impl GroupOp for Group {
         macro_rules! write_scalar {
             ($data:expr) => {{
                 let path = self.child_path(name);
-                let data_type = get_data_type::<D>();
+                let data_type = get_data_type::<D>()?;
                 let data_size = $data.len();
                 let attr = DatasetAttributes::new(
                     smallvec![data_size as u64],
@@ -256,8 +268,14 @@ impl GroupOp for Group {
             DynScalar::I16(x) => write_scalar!([x]),
             DynScalar::I32(x) => write_scalar!([x]),
             DynScalar::I64(x) => write_scalar!([x]),
+            DynScalar::F16(x) => {
+                let dataset = write_scalar!([x.to_f32()])?;
+                dataset.write_str_attr("custom-data-type", "f16")?;
+                Ok(dataset)
+            }
             DynScalar::F32(x) => write_scalar!([x]),
             DynScalar::F64(x) => write_scalar!([x]),
+            DynScalar::ComplexF32(_) | DynScalar::ComplexF64(_) => bail!("complex numbers are not supported by the N5 backend"),
             DynScalar::Bool(x) => {
                 let dataset = write_scalar!([x as u8])?;
                 dataset.write_str_attr("custom-data-type", "bool")?;
@@ -304,7 +322,14 @@ impl DatasetOp for Dataset {
             DataType::INT16 => Ok(ScalarType::I16),
             DataType::INT32 => Ok(ScalarType::I32),
             DataType::INT64 => Ok(ScalarType::I64),
-            DataType::FLOAT32 => Ok(ScalarType::F32),
+            DataType::FLOAT32 => match self
+                .read_str_attr("custom-data-type")
+                .as_ref()
+                .map(|x| x.as_str())
+            {
+                Ok("f16") => Ok(ScalarType::F16),
+                _ => Ok(ScalarType::F32),
+            },
             DataType::FLOAT64 => Ok(ScalarType::F64),
         }
     }
@@ -342,8 +367,10 @@ impl DatasetOp for Dataset {
             ScalarType::I16 => read::<i16>(self)?[0].into_dyn(),
             ScalarType::I32 => read::<i32>(self)?[0].into_dyn(),
             ScalarType::I64 => read::<i64>(self)?[0].into_dyn(),
+            ScalarType::F16 => f16::from_f32(read::<f32>(self)?[0]).into_dyn(),
             ScalarType::F32 => read::<f32>(self)?[0].into_dyn(),
             ScalarType::F64 => read::<f64>(self)?[0].into_dyn(),
+            ScalarType::ComplexF32 | ScalarType::ComplexF64 => bail!("complex numbers are not supported by the N5 backend"),
             ScalarType::Bool => (read::<u8>(self)?[0] != 0).into_dyn(),
             ScalarType::String => {
                 let data = read::<u8>(self)?;
@@ -393,8 +420,10 @@ impl DatasetOp for Dataset {
             ScalarType::U32 => impl_read!(u32).into(),
             ScalarType::U64 => impl_read!(u64).into(),
             ScalarType::Usize => impl_read!(u64).map(|x| *x as usize).into(),
+            ScalarType::F16 => impl_read!(f32).mapv(f16::from_f32).into(),
             ScalarType::F32 => impl_read!(f32).into(),
             ScalarType::F64 => impl_read!(f64).into(),
+            ScalarType::ComplexF32 | ScalarType::ComplexF64 => bail!("complex numbers are not supported by the N5 backend"),
             _ => todo!(),
         };
         Ok(BackendData::from_dyn_arr(array)?.into_dimensionality::<D>()?)
@@ -443,9 +472,11 @@ impl DatasetOp for Dataset {
             DynArrayView::I16(x) => impl_write!(x.into_dyn(), 0),
             DynArrayView::I32(x) => impl_write!(x.into_dyn(), 0),
             DynArrayView::I64(x) => impl_write!(x.into_dyn(), 0),
+            DynArrayView::F16(x) => impl_write!(x.mapv(f16::to_f32).into_dyn().view(), 0.0),
             DynArrayView::F32(x) => impl_write!(x.into_dyn(), 0.0),
             DynArrayView::F64(x) => impl_write!(x.into_dyn(), 0.0),
             DynArrayView::String(x) => Ok(()),
+            DynArrayView::ComplexF32(_) | DynArrayView::ComplexF64(_) => bail!("complex numbers are not supported by the N5 backend"),
             _ => panic!("unsupported type: {:?}", T::DTYPE),
         }
     }
@@ -483,8 +514,10 @@ impl LocationOp for Location {
             DynArrayView::I16(x) => self.root.set_attribute(&path, name.to_string(), x.into_dyn()),
             DynArrayView::I32(x) => self.root.set_attribute(&path, name.to_string(), x.into_dyn()),
             DynArrayView::I64(x) => self.root.set_attribute(&path, name.to_string(), x.into_dyn()),
+            DynArrayView::F16(x) => self.root.set_attribute(&path, name.to_string(), x.mapv(f16::to_f32).into_dyn()),
             DynArrayView::F32(x) => self.root.set_attribute(&path, name.to_string(), x.into_dyn()),
             DynArrayView::F64(x) => self.root.set_attribute(&path, name.to_string(), x.into_dyn()),
+            DynArrayView::ComplexF32(_) | DynArrayView::ComplexF64(_) => bail!("complex numbers are not supported by the N5 backend"),
             DynArrayView::Bool(x) => self.root.set_attribute(&path, name.to_string(), x.into_dyn()),
             DynArrayView::String(x) => self.root.set_attribute(&path, name.to_string(), x.into_dyn()),
         }?;
@@ -509,8 +542,10 @@ impl LocationOp for Location {
             DynScalar::I16(x) => Value::Number(Number::from(x)),
             DynScalar::I32(x) => Value::Number(Number::from(x)),
             DynScalar::I64(x) => Value::Number(Number::from(x)),
+            DynScalar::F16(x) => Value::Number(Number::from_f64(x.to_f64()).unwrap()),
             DynScalar::F32(x) => Value::Number(Number::from_f64(x as f64).unwrap()),
             DynScalar::F64(x) => Value::Number(Number::from_f64(x).unwrap()),
+            DynScalar::ComplexF32(_) | DynScalar::ComplexF64(_) => bail!("complex numbers are not supported by the N5 backend"),
             DynScalar::Bool(x) => Value::Bool(x),
             DynScalar::String(x) => Value::String(x),
         };
@@ -545,8 +580,10 @@ impl LocationOp for Location {
             ScalarType::I16 => serde_json::from_value::<Array<i16, IxDyn>>(val)?.into(),
             ScalarType::I32 => serde_json::from_value::<Array<i32, IxDyn>>(val)?.into(),
             ScalarType::I64 => serde_json::from_value::<Array<i64, IxDyn>>(val)?.into(),
+            ScalarType::F16 => serde_json::from_value::<Array<f32, IxDyn>>(val)?.mapv(f16::from_f32).into(),
             ScalarType::F32 => serde_json::from_value::<Array<f32, IxDyn>>(val)?.into(),
             ScalarType::F64 => serde_json::from_value::<Array<f64, IxDyn>>(val)?.into(),
+            ScalarType::ComplexF32 | ScalarType::ComplexF64 => bail!("complex numbers are not supported by the N5 backend"),
             ScalarType::Bool => serde_json::from_value::<Array<bool, IxDyn>>(val)?.into(),
             ScalarType::String => serde_json::from_value::<Array<String, IxDyn>>(val)?.into(),
         };
@@ -598,6 +635,11 @@ impl GroupOp for Root {
         self.deref().exists(name)
     }
 
+    /// Rename a group or dataset.
+    fn rename(&self, name: &str, new_name: &str) -> Result<()> {
+        self.deref().rename(name, new_name)
+    }
+
     fn create_scalar_data<D: BackendData>(
         &self,
         name: &str,
@@ -690,8 +732,8 @@ impl LocationOp for Dataset {
 }
 
 
-fn get_data_type<T: BackendData>() -> DataType {
-    match T::DTYPE {
+fn get_data_type<T: BackendData>() -> Result<DataType> {
+    let data_type = match T::DTYPE {
         ScalarType::I8 => DataType::INT8,
         ScalarType::I16 => DataType::INT16,
         ScalarType::I32 => DataType::INT32,
@@ -701,11 +743,15 @@ fn get_data_type<T: BackendData>() -> DataType {
         ScalarType::U32 => DataType::UINT32,
         ScalarType::U64 => DataType::UINT64,
         ScalarType::Usize => DataType::UINT64,
+        // N5 has no half-precision type.
+        ScalarType::F16 => DataType::FLOAT32,
         ScalarType::F32 => DataType::FLOAT32,
         ScalarType::F64 => DataType::FLOAT64,
+        ScalarType::ComplexF32 | ScalarType::ComplexF64 => bail!("complex numbers are not supported by the N5 backend"),
         ScalarType::Bool => DataType::UINT8,
         ScalarType::String => DataType::UINT8,
-    }
+    };
+    Ok(data_type)
 }
 
 /// test module
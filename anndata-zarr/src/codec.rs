@@ -0,0 +1,385 @@
+//! Encoding of the chunks of Zarr arrays: the byte layout of the elements and
+//! the compressors of the numcodecs library that are used by zarr-python.
+
+use anndata::{backend::BackendData, data::DynArray};
+
+use anyhow::{bail, ensure, Context, Result};
+use flate2::{read::{GzDecoder, ZlibDecoder}, write::{GzEncoder, ZlibEncoder}};
+use half::f16;
+use ndarray::ArrayD;
+use num_complex::Complex;
+use serde_json::Value;
+use std::io::{Read, Write};
+
+/// Types that can be stored in the chunks of a Zarr array.
+pub(crate) trait Element: BackendData + Default + PartialEq {
+    /// The size of an element in bytes, which is the type size used by the
+    /// shuffle filter of Blosc.
+    const SIZE: usize;
+
+    /// Decode `n` elements from the uncompressed bytes of a chunk.
+    fn decode(bytes: &[u8], n: usize, big_endian: bool) -> Result<Vec<Self>>;
+
+    /// Encode elements in little-endian byte order.
+    fn encode<'a, I>(values: I) -> Vec<u8>
+    where
+        I: ExactSizeIterator<Item = &'a Self>,
+        Self: 'a;
+
+    fn into_array(arr: ArrayD<Self>) -> DynArray;
+}
+
+fn check_len(bytes: &[u8], expected: usize) -> Result<()> {
+    ensure!(
+        bytes.len() >= expected,
+        "the chunk is too small: expected {} bytes, found {}", expected, bytes.len(),
+    );
+    Ok(())
+}
+
+macro_rules! impl_numeric {
+    ($($ty:ty),*) => {$(
+        impl Element for $ty {
+            const SIZE: usize = std::mem::size_of::<$ty>();
+
+            fn decode(bytes: &[u8], n: usize, big_endian: bool) -> Result<Vec<Self>> {
+                check_len(bytes, n * Self::SIZE)?;
+                let values = bytes[..n * Self::SIZE].chunks_exact(Self::SIZE).map(|x| {
+                    let x = x.try_into().unwrap();
+                    if big_endian { <$ty>::from_be_bytes(x) } else { <$ty>::from_le_bytes(x) }
+                }).collect();
+                Ok(values)
+            }
+
+            fn encode<'a, I>(values: I) -> Vec<u8>
+            where
+                I: ExactSizeIterator<Item = &'a Self>,
+            {
+                values.flat_map(|x| x.to_le_bytes()).collect()
+            }
+
+            fn into_array(arr: ArrayD<Self>) -> DynArray {
+                arr.into()
+            }
+        }
+    )*};
+}
+
+impl_numeric!(i8, i16, i32, i64, u8, u16, u32, u64, f32, f64);
+
+impl Element for f16 {
+    const SIZE: usize = 2;
+
+    fn decode(bytes: &[u8], n: usize, big_endian: bool) -> Result<Vec<Self>> {
+        Ok(u16::decode(bytes, n, big_endian)?.into_iter().map(f16::from_bits).collect())
+    }
+
+    fn encode<'a, I>(values: I) -> Vec<u8>
+    where
+        I: ExactSizeIterator<Item = &'a Self>,
+    {
+        values.flat_map(|x| x.to_bits().to_le_bytes()).collect()
+    }
+
+    fn into_array(arr: ArrayD<Self>) -> DynArray {
+        arr.into()
+    }
+}
+
+/// Complex numbers are stored as pairs of floats, the real part first.
+macro_rules! impl_complex {
+    ($($ty:ty),*) => {$(
+        impl Element for Complex<$ty> {
+            const SIZE: usize = 2 * std::mem::size_of::<$ty>();
+
+            fn decode(bytes: &[u8], n: usize, big_endian: bool) -> Result<Vec<Self>> {
+                let parts = <$ty>::decode(bytes, 2 * n, big_endian)?;
+                Ok(parts.chunks_exact(2).map(|x| Complex::new(x[0], x[1])).collect())
+            }
+
+            fn encode<'a, I>(values: I) -> Vec<u8>
+            where
+                I: ExactSizeIterator<Item = &'a Self>,
+            {
+                values.flat_map(|x| x.re.to_le_bytes().into_iter().chain(x.im.to_le_bytes())).collect()
+            }
+
+            fn into_array(arr: ArrayD<Self>) -> DynArray {
+                arr.into()
+            }
+        }
+    )*};
+}
+
+impl_complex!(f32, f64);
+
+impl Element for bool {
+    const SIZE: usize = 1;
+
+    fn decode(bytes: &[u8], n: usize, _big_endian: bool) -> Result<Vec<Self>> {
+        check_len(bytes, n)?;
+        Ok(bytes[..n].iter().map(|x| *x != 0).collect())
+    }
+
+    fn encode<'a, I>(values: I) -> Vec<u8>
+    where
+        I: ExactSizeIterator<Item = &'a Self>,
+    {
+        values.map(|x| *x as u8).collect()
+    }
+
+    fn into_array(arr: ArrayD<Self>) -> DynArray {
+        arr.into()
+    }
+}
+
+/// Strings are stored in object arrays with the `vlen-utf8` filter: the number
+/// of strings, followed by the length and the UTF-8 bytes of each string, with
+/// all integers being little-endian `u32`.
+impl Element for String {
+    const SIZE: usize = 1;
+
+    fn decode(bytes: &[u8], n: usize, _big_endian: bool) -> Result<Vec<Self>> {
+        fn read_u32(bytes: &[u8], pos: usize) -> Result<usize> {
+            let x = bytes.get(pos..pos + 4).context("the vlen-utf8 chunk is truncated")?;
+            Ok(u32::from_le_bytes(x.try_into().unwrap()) as usize)
+        }
+
+        let count = read_u32(bytes, 0)?;
+        ensure!(count == n, "expected {} strings in the chunk, found {}", n, count);
+        let mut pos = 4;
+        (0..n).map(|_| {
+            let len = read_u32(bytes, pos)?;
+            pos += 4;
+            let s = bytes.get(pos..pos + len).context("the vlen-utf8 chunk is truncated")?;
+            pos += len;
+            Ok(std::str::from_utf8(s)?.to_string())
+        }).collect()
+    }
+
+    fn encode<'a, I>(values: I) -> Vec<u8>
+    where
+        I: ExactSizeIterator<Item = &'a Self>,
+    {
+        let mut bytes = (values.len() as u32).to_le_bytes().to_vec();
+        values.for_each(|x| {
+            bytes.extend((x.len() as u32).to_le_bytes());
+            bytes.extend(x.as_bytes());
+        });
+        bytes
+    }
+
+    fn into_array(arr: ArrayD<Self>) -> DynArray {
+        arr.into()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Compressors
+////////////////////////////////////////////////////////////////////////////////
+
+/// Compress a chunk with the compressor given in the array metadata, where
+/// `null` means no compression. `typesize` is the size of the elements.
+pub(crate) fn compress(config: &Value, data: Vec<u8>, typesize: usize) -> Result<Vec<u8>> {
+    let id = match config {
+        Value::Null => return Ok(data),
+        _ => config["id"].as_str().context("the compressor has no id")?,
+    };
+    let level = |default| config["level"].as_u64().unwrap_or(default) as u32;
+    let compressed = match id {
+        "zlib" => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::new(level(1)));
+            encoder.write_all(&data)?;
+            encoder.finish()?
+        }
+        "gzip" => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::new(level(5)));
+            encoder.write_all(&data)?;
+            encoder.finish()?
+        }
+        "zstd" => zstd::bulk::compress(&data, level(1) as i32)?,
+        // The uncompressed size is stored in front of the LZ4 block.
+        "lz4" => {
+            let acceleration = config["acceleration"].as_i64().unwrap_or(1) as i32;
+            lz4::block::compress(&data, Some(lz4::block::CompressionMode::FAST(acceleration)), true)?
+        }
+        "blosc" => blosc_compress(
+            &data,
+            config["cname"].as_str().unwrap_or("lz4"),
+            config["clevel"].as_i64().unwrap_or(5) as i32,
+            config["shuffle"].as_i64().unwrap_or(1),
+            typesize,
+        )?,
+        id => bail!("the '{}' compressor is not supported by the Zarr backend", id),
+    };
+    Ok(compressed)
+}
+
+/// Decompress a chunk with the compressor given in the array metadata.
+pub(crate) fn decompress(config: &Value, data: Vec<u8>) -> Result<Vec<u8>> {
+    let id = match config {
+        Value::Null => return Ok(data),
+        _ => config["id"].as_str().context("the compressor has no id")?,
+    };
+    let mut out = Vec::new();
+    match id {
+        "zlib" => { ZlibDecoder::new(data.as_slice()).read_to_end(&mut out)?; }
+        "gzip" => { GzDecoder::new(data.as_slice()).read_to_end(&mut out)?; }
+        "zstd" => out = zstd::stream::decode_all(data.as_slice())?,
+        "lz4" => out = lz4::block::decompress(&data, None)?,
+        "blosc" => out = blosc_decompress(&data)?,
+        id => bail!("the '{}' compressor is not supported by the Zarr backend", id),
+    }
+    Ok(out)
+}
+
+/// Flags in the header of a Blosc frame.
+const BLOSC_DOSHUFFLE: u8 = 0x1;
+const BLOSC_MEMCPYED: u8 = 0x2;
+const BLOSC_DOBITSHUFFLE: u8 = 0x4;
+const BLOSC_DONT_SPLIT: u8 = 0x10;
+
+const BLOSC_HEADER_SIZE: usize = 16;
+
+/// Compress `data` into a Blosc frame made of a single block. Frames are
+/// stored uncompressed if compression does not reduce their size, or if the
+/// codec is not available here, e.g., BloscLZ.
+fn blosc_compress(data: &[u8], cname: &str, clevel: i32, shuffle: i64, typesize: usize) -> Result<Vec<u8>> {
+    let typesize = typesize.clamp(1, u8::MAX as usize);
+    // Bit shuffling is not implemented, so only byte shuffling is applied.
+    let byte_shuffle = typesize > 1 && (shuffle == 1 || shuffle == -1);
+    let shuffled = if byte_shuffle { shuffle_bytes(data, typesize) } else { data.to_vec() };
+    let compressed = match cname {
+        _ if clevel == 0 || data.is_empty() => None,
+        "lz4" => Some((1, lz4::block::compress(&shuffled, Some(lz4::block::CompressionMode::FAST(1)), false)?)),
+        "lz4hc" => Some((1, lz4::block::compress(&shuffled, Some(lz4::block::CompressionMode::HIGHCOMPRESSION(clevel)), false)?)),
+        "zlib" => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::new(clevel.clamp(0, 9) as u32));
+            encoder.write_all(&shuffled)?;
+            Some((3, encoder.finish()?))
+        }
+        "zstd" => Some((4, zstd::bulk::compress(&shuffled, clevel)?)),
+        _ => None,
+    }.filter(|(_, x)| x.len() + 8 < data.len());
+
+    let nbytes = u32::try_from(data.len()).context("the chunk is too large for Blosc")?;
+    let mut frame = vec![2, 1, 0, typesize as u8];
+    frame.extend(nbytes.to_le_bytes());
+    frame.extend(nbytes.to_le_bytes());
+    match compressed {
+        Some((format, block)) => {
+            frame[2] = BLOSC_DONT_SPLIT | (format << 5) | if byte_shuffle { BLOSC_DOSHUFFLE } else { 0 };
+            let ctbytes = BLOSC_HEADER_SIZE + 8 + block.len();
+            frame.extend((ctbytes as u32).to_le_bytes());
+            // The offset of the only block, followed by its compressed size.
+            frame.extend(((BLOSC_HEADER_SIZE + 4) as u32).to_le_bytes());
+            frame.extend((block.len() as u32).to_le_bytes());
+            frame.extend(block);
+        }
+        None => {
+            frame[2] = BLOSC_MEMCPYED;
+            frame.extend(((BLOSC_HEADER_SIZE + data.len()) as u32).to_le_bytes());
+            frame.extend(data);
+        }
+    }
+    Ok(frame)
+}
+
+/// Decompress a frame of the Blosc 1.x format, which is the format used by
+/// numcodecs. Only byte shuffling is supported, and the blocks must have
+/// been compressed with LZ4, Zlib or Zstd.
+fn blosc_decompress(src: &[u8]) -> Result<Vec<u8>> {
+    let read_u32 = |pos: usize| -> Result<usize> {
+        let x = src.get(pos..pos + 4).context("the Blosc frame is truncated")?;
+        Ok(u32::from_le_bytes(x.try_into().unwrap()) as usize)
+    };
+
+    ensure!(src.len() >= BLOSC_HEADER_SIZE, "the Blosc frame is truncated");
+    let flags = src[2];
+    let typesize = (src[3] as usize).max(1);
+    let nbytes = read_u32(4)?;
+    let blocksize = read_u32(8)?;
+    if flags & BLOSC_MEMCPYED != 0 {
+        let data = src.get(BLOSC_HEADER_SIZE..BLOSC_HEADER_SIZE + nbytes).context("the Blosc frame is truncated")?;
+        return Ok(data.to_vec());
+    }
+    ensure!(flags & BLOSC_DOBITSHUFFLE == 0, "bit-shuffled Blosc frames are not supported");
+    if nbytes == 0 {
+        return Ok(Vec::new());
+    }
+    ensure!(blocksize > 0, "invalid Blosc block size: 0");
+
+    let format = flags >> 5;
+    let mut out = vec![0; nbytes];
+    for (i, dest) in out.chunks_mut(blocksize).enumerate() {
+        let bsize = dest.len();
+        let leftover = bsize < blocksize;
+        // Blocks are split into one stream per byte of the elements, unless the
+        // frame says otherwise. Old frames rely on this rule alone.
+        let nsplits = if flags & BLOSC_DONT_SPLIT == 0 && typesize <= 16 && bsize / typesize >= 128 && !leftover {
+            typesize
+        } else {
+            1
+        };
+        let neblock = bsize / nsplits;
+        let mut block = Vec::with_capacity(bsize);
+        let mut pos = read_u32(BLOSC_HEADER_SIZE + 4 * i)?;
+        for _ in 0..nsplits {
+            let csize = read_u32(pos)?;
+            pos += 4;
+            let data = src.get(pos..pos + csize).context("the Blosc frame is truncated")?;
+            pos += csize;
+            if csize == neblock {
+                block.extend_from_slice(data);
+            } else {
+                block.extend(blosc_decode_block(format, data, neblock)?);
+            }
+        }
+        ensure!(block.len() == bsize, "block {} of the Blosc frame has {} bytes, expected {}", i, block.len(), bsize);
+        if flags & BLOSC_DOSHUFFLE != 0 && typesize > 1 {
+            unshuffle_bytes(&block, typesize, dest);
+        } else {
+            dest.copy_from_slice(&block);
+        }
+    }
+    Ok(out)
+}
+
+fn blosc_decode_block(format: u8, data: &[u8], size: usize) -> Result<Vec<u8>> {
+    let block = match format {
+        1 => lz4::block::decompress(data, Some(size as i32))?,
+        3 => {
+            let mut out = Vec::with_capacity(size);
+            ZlibDecoder::new(data).read_to_end(&mut out)?;
+            out
+        }
+        4 => zstd::bulk::decompress(data, size)?,
+        0 => bail!("Blosc frames compressed with BloscLZ are not supported"),
+        2 => bail!("Blosc frames compressed with Snappy are not supported"),
+        x => bail!("unknown Blosc compressor: {}", x),
+    };
+    Ok(block)
+}
+
+/// Group the i-th bytes of all elements together. Trailing bytes that do not
+/// form a whole element are left in place.
+fn shuffle_bytes(src: &[u8], typesize: usize) -> Vec<u8> {
+    let n = src.len() / typesize;
+    let mut dest = src.to_vec();
+    for i in 0..n {
+        for j in 0..typesize {
+            dest[j * n + i] = src[i * typesize + j];
+        }
+    }
+    dest
+}
+
+fn unshuffle_bytes(src: &[u8], typesize: usize, dest: &mut [u8]) {
+    let n = src.len() / typesize;
+    for i in 0..n {
+        for j in 0..typesize {
+            dest[i * typesize + j] = src[j * n + i];
+        }
+    }
+    dest[n * typesize..].copy_from_slice(&src[n * typesize..]);
+}
@@ -0,0 +1,1519 @@
+mod codec;
+
+use codec::Element;
+
+use anndata::{
+    backend::{
+        default_block_size, Backend, BackendData, Compression, DatasetOp, DynArrayView, FileOp,
+        GroupOp, LocationOp, ScalarType, WriteConfig,
+    },
+    data::{BoundedSelectInfoElem, DynArray, DynScalar, SelectInfoElem, Shape},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use half::f16;
+use ndarray::{arr0, Array, ArrayD, ArrayView, ArrayViewD, Dimension, IxDyn, RemoveAxis, ShapeBuilder, Slice};
+use num_complex::{Complex32, Complex64};
+use serde_json::{json, Map, Number, Value};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    ops::{Deref, Range},
+    path::{Path, PathBuf},
+};
+
+/// The Zarr backend.
+/// Files are Zarr stores in the version 2 format, i.e., directories in which
+/// groups and arrays are subdirectories described by `.zgroup` and `.zarray`
+/// files, and attributes are kept in `.zattrs` files. Stores written here can
+/// be read by zarr-python and the `read_zarr` function of the anndata package,
+/// and vice versa.
+pub struct Zarr;
+
+/// The level of the LZ4 compressor of Blosc, which is also the default level
+/// of zarr-python.
+const LZ4_LEVEL: u8 = 5;
+
+pub struct Root(Group);
+
+impl Deref for Root {
+    type Target = Group;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+pub struct Group(Location);
+
+impl Deref for Group {
+    type Target = Location;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+pub struct Dataset(Location);
+
+impl Deref for Dataset {
+    type Target = Location;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+pub struct Location {
+    /// The path of the location relative to the root of the store.
+    path: PathBuf,
+    /// The directory of the store.
+    root: PathBuf,
+    writable: bool,
+}
+
+impl Location {
+    fn child(&self, name: &str) -> Location {
+        Location {
+            path: self.path.join(name),
+            root: self.root.clone(),
+            writable: self.writable,
+        }
+    }
+
+    /// The directory of the location.
+    fn dir(&self) -> PathBuf {
+        self.root.join(self.path.strip_prefix("/").unwrap_or(&self.path))
+    }
+
+    fn is_group(&self) -> bool {
+        self.dir().join(".zgroup").is_file()
+    }
+
+    fn is_array(&self) -> bool {
+        self.dir().join(".zarray").is_file()
+    }
+
+    fn ensure_writable(&self) -> Result<()> {
+        ensure!(
+            self.writable,
+            "cannot write to '{}', the Zarr store is opened in read-only mode",
+            self.path.display(),
+        );
+        Ok(())
+    }
+
+    fn attrs(&self) -> Result<Map<String, Value>> {
+        match read_json(&self.dir().join(".zattrs"))? {
+            None => Ok(Map::new()),
+            Some(Value::Object(attrs)) => Ok(attrs),
+            Some(_) => bail!("the attributes of '{}' are not a JSON object", self.path.display()),
+        }
+    }
+
+    fn get_attr(&self, name: &str) -> Result<Value> {
+        self.attrs()?
+            .remove(name)
+            .with_context(|| format!("attribute '{}' does not exist in '{}'", name, self.path.display()))
+    }
+
+    fn set_attr(&self, name: &str, value: Value) -> Result<()> {
+        self.ensure_writable()?;
+        let mut attrs = self.attrs()?;
+        attrs.insert(name.to_string(), value);
+        write_json(&self.dir().join(".zattrs"), &Value::Object(attrs))
+    }
+}
+
+impl Backend for Zarr {
+    const NAME: &'static str = "zarr";
+
+    type File = Root;
+
+    type Group = Group;
+
+    /// datasets contain arrays.
+    type Dataset = Dataset;
+
+    /// Create a new store, replacing the existing one at `path`.
+    fn create<P: AsRef<Path>>(path: P) -> Result<Self::File> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            ensure!(
+                is_store(path) || fs::read_dir(path)?.next().is_none(),
+                "cannot create a Zarr store at '{}': the directory exists and is not a Zarr store",
+                path.display(),
+            );
+            fs::remove_dir_all(path)?;
+        } else if path.exists() {
+            fs::remove_file(path)?;
+        }
+        fs::create_dir_all(path)?;
+        write_json(&path.join(".zgroup"), &json!({ "zarr_format": 2 }))?;
+        Ok(new_root(path, true))
+    }
+
+    /// Opens a file as read-only, file must exist.
+    fn open<P: AsRef<Path>>(path: P) -> Result<Self::File> {
+        open_root(path.as_ref(), false)
+    }
+
+    /// Opens a file as read/write, file must exist.
+    fn open_rw<P: AsRef<Path>>(path: P) -> Result<Self::File> {
+        open_root(path.as_ref(), true)
+    }
+}
+
+fn is_store(path: &Path) -> bool {
+    path.join(".zgroup").is_file() || path.join(".zarray").is_file()
+}
+
+fn new_root(path: &Path, writable: bool) -> Root {
+    Root(Group(Location {
+        path: PathBuf::from("/"),
+        root: path.to_path_buf(),
+        writable,
+    }))
+}
+
+fn open_root(path: &Path, writable: bool) -> Result<Root> {
+    ensure!(
+        path.join(".zgroup").is_file(),
+        "'{}' is not a Zarr store: the .zgroup file is missing",
+        path.display(),
+    );
+    Ok(new_root(path, writable))
+}
+
+impl FileOp for Root {
+    type Backend = Zarr;
+
+    /// Returns the file path.
+    fn filename(&self) -> PathBuf {
+        self.root.clone()
+    }
+
+    /// Close the file. Data is written to disk immediately, so there is
+    /// nothing to do.
+    fn close(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl GroupOp for Group {
+    type Backend = Zarr;
+
+    /// List all groups and datasets in this group.
+    fn list(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(self.dir())? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() && is_store(&entry.path()) {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Create a new group.
+    fn create_group(&self, name: &str) -> Result<<Self::Backend as Backend>::Group> {
+        self.ensure_writable()?;
+        ensure!(!self.exists(name)?, "'{}' already exists", name);
+        let loc = self.child(name);
+        fs::create_dir_all(loc.dir())?;
+        write_json(&loc.dir().join(".zgroup"), &json!({ "zarr_format": 2 }))?;
+        Ok(Group(loc))
+    }
+
+    /// Open an existing group.
+    fn open_group(&self, name: &str) -> Result<<Self::Backend as Backend>::Group> {
+        let loc = self.child(name);
+        ensure!(loc.is_group(), "Group {} does not exist", name);
+        Ok(Group(loc))
+    }
+
+    /// Create an empty dataset holding an array value.
+    fn new_dataset<T: BackendData>(
+        &self,
+        name: &str,
+        shape: &Shape,
+        config: WriteConfig,
+    ) -> Result<<Self::Backend as Backend>::Dataset> {
+        self.ensure_writable()?;
+        ensure!(!self.exists(name)?, "'{}' already exists", name);
+        let chunks: Vec<usize> = config
+            .block_size
+            .unwrap_or_else(|| default_block_size(shape.as_ref(), std::mem::size_of::<T>()))
+            .as_ref()
+            .iter()
+            .map(|x| (*x).max(1))
+            .collect();
+        ensure!(
+            chunks.len() == shape.ndim(),
+            "the chunk shape {:?} does not match the shape {:?} of the dataset",
+            chunks,
+            shape.as_ref(),
+        );
+        let compressor = match config.compression {
+            None => Value::Null,
+            Some(Compression::Gzip(level)) => json!({ "id": "zlib", "level": level }),
+            Some(Compression::Zstd(level)) => blosc_config("zstd", level),
+            Some(Compression::Lz4) => blosc_config("lz4", LZ4_LEVEL),
+        };
+        let fill_value = match config.fill_value {
+            None => Value::Null,
+            Some(fill) => {
+                let fill = T::from_dyn(fill).with_context(|| format!("the fill value must have type {}", T::DTYPE))?;
+                scalar_to_json(fill.into_dyn())
+            }
+        };
+        let filters = match T::DTYPE {
+            ScalarType::String => json!([{ "id": "vlen-utf8" }]),
+            _ => Value::Null,
+        };
+        let meta = json!({
+            "chunks": chunks,
+            "compressor": compressor,
+            "dtype": dtype_to_str(T::DTYPE),
+            "fill_value": fill_value,
+            "filters": filters,
+            "order": "C",
+            "shape": shape.as_ref(),
+            "zarr_format": 2,
+        });
+
+        let loc = self.child(name);
+        fs::create_dir_all(loc.dir())?;
+        write_json(&loc.dir().join(".zarray"), &meta)?;
+        Ok(Dataset(loc))
+    }
+
+    fn open_dataset(&self, name: &str) -> Result<<Self::Backend as Backend>::Dataset> {
+        let loc = self.child(name);
+        ensure!(loc.is_array(), "Dataset {} does not exist", name);
+        Ok(Dataset(loc))
+    }
+
+    /// Delete a group or dataset.
+    fn delete(&self, name: &str) -> Result<()> {
+        self.ensure_writable()?;
+        ensure!(self.exists(name)?, "'{}' does not exist", name);
+        fs::remove_dir_all(self.child(name).dir())?;
+        Ok(())
+    }
+
+    /// Check if a group or dataset exists.
+    fn exists(&self, name: &str) -> Result<bool> {
+        let loc = self.child(name);
+        Ok(loc.is_group() || loc.is_array())
+    }
+
+    /// Rename a group or dataset by moving its directory.
+    fn rename(&self, name: &str, new_name: &str) -> Result<()> {
+        self.ensure_writable()?;
+        ensure!(self.exists(name)?, "'{}' does not exist", name);
+        if self.exists(new_name)? {
+            bail!("'{}' already exists", new_name);
+        }
+        fs::rename(self.child(name).dir(), self.child(new_name).dir())?;
+        Ok(())
+    }
+
+    /// Scalars are stored as zero-dimensional arrays.
+    fn create_scalar_data<D: BackendData>(
+        &self,
+        name: &str,
+        data: &D,
+    ) -> Result<<Self::Backend as Backend>::Dataset> {
+        let config = WriteConfig {
+            compression: None,
+            ..Default::default()
+        };
+        let dataset = self.new_dataset::<D>(name, &Vec::new().into(), config)?;
+        dataset.write_array(&arr0(data.clone()).into_dyn())?;
+        Ok(dataset)
+    }
+}
+
+fn blosc_config(cname: &str, clevel: u8) -> Value {
+    json!({ "id": "blosc", "cname": cname, "clevel": clevel, "shuffle": 1, "blocksize": 0 })
+}
+
+/// The metadata of an array, stored in its `.zarray` file.
+struct ArrayMeta {
+    shape: Vec<usize>,
+    chunks: Vec<usize>,
+    dtype: ScalarType,
+    big_endian: bool,
+    fortran_order: bool,
+    compressor: Value,
+    fill_value: Value,
+    separator: String,
+    /// The content of the `.zarray` file, which is written back as is when
+    /// the array is reshaped.
+    raw: Map<String, Value>,
+}
+
+impl ArrayMeta {
+    fn from_json(value: Value) -> Result<Self> {
+        let raw = match value {
+            Value::Object(raw) => raw,
+            _ => bail!("the array metadata is not a JSON object"),
+        };
+        ensure!(
+            raw.get("zarr_format").and_then(Value::as_u64) == Some(2),
+            "only arrays in the Zarr version 2 format are supported",
+        );
+        let shape = usize_list(&raw["shape"]).context("invalid array shape")?;
+        let chunks = usize_list(&raw["chunks"]).context("invalid chunk shape")?;
+        ensure!(
+            chunks.len() == shape.len() && chunks.iter().all(|x| *x > 0),
+            "invalid chunk shape {:?} for an array of shape {:?}",
+            chunks,
+            shape,
+        );
+        let dtype = raw["dtype"]
+            .as_str()
+            .context("structured data types are not supported by the Zarr backend")?;
+        let (dtype, big_endian) = dtype_from_str(dtype, &raw["filters"])?;
+        let fortran_order = match raw.get("order").and_then(Value::as_str) {
+            None | Some("C") => false,
+            Some("F") => true,
+            Some(x) => bail!("unknown memory layout: '{}'", x),
+        };
+        let separator = raw
+            .get("dimension_separator")
+            .and_then(Value::as_str)
+            .unwrap_or(".")
+            .to_string();
+        Ok(Self {
+            shape,
+            chunks,
+            dtype,
+            big_endian,
+            fortran_order,
+            compressor: raw["compressor"].clone(),
+            fill_value: raw["fill_value"].clone(),
+            separator,
+            raw,
+        })
+    }
+
+    fn fill_value(&self) -> Result<Option<DynScalar>> {
+        match &self.fill_value {
+            Value::Null => Ok(None),
+            // Object arrays may have a numeric fill value, which is not used
+            // for strings.
+            x if self.dtype == ScalarType::String && !x.is_string() => Ok(None),
+            x => json_to_scalar(x, self.dtype).map(Some),
+        }
+    }
+
+    /// The value of the elements that have never been written.
+    fn fill<T: Element>(&self) -> Result<T> {
+        self.fill_value()?.map_or(Ok(T::default()), T::from_dyn)
+    }
+
+    fn chunk_key(&self, index: &[usize]) -> String {
+        if index.is_empty() {
+            "0".to_string()
+        } else {
+            index.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(&self.separator)
+        }
+    }
+}
+
+impl Dataset {
+    fn meta(&self) -> Result<ArrayMeta> {
+        let file = self.dir().join(".zarray");
+        let meta = read_json(&file)?.with_context(|| format!("'{}' does not exist", file.display()))?;
+        ArrayMeta::from_json(meta).with_context(|| format!("cannot read the metadata of '{}'", self.path.display()))
+    }
+
+    fn chunk_file(&self, meta: &ArrayMeta, index: &[usize]) -> PathBuf {
+        self.dir().join(meta.chunk_key(index))
+    }
+
+    /// Read a chunk. Chunks that have not been written are filled with the
+    /// fill value of the array.
+    fn read_chunk<T: Element>(&self, meta: &ArrayMeta, index: &[usize]) -> Result<ArrayD<T>> {
+        let shape = IxDyn(&meta.chunks);
+        match fs::read(self.chunk_file(meta, index)) {
+            Ok(bytes) => {
+                let bytes = codec::decompress(&meta.compressor, bytes)?;
+                let values = T::decode(&bytes, shape.size(), meta.big_endian)
+                    .with_context(|| format!("cannot decode chunk '{}' of '{}'", meta.chunk_key(index), self.path.display()))?;
+                if meta.fortran_order {
+                    Ok(ArrayD::from_shape_vec(shape.f(), values)?)
+                } else {
+                    Ok(ArrayD::from_shape_vec(shape, values)?)
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(ArrayD::from_elem(shape, meta.fill()?)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write a chunk. Chunks that only contain the fill value are removed
+    /// instead, as zarr-python does.
+    fn write_chunk<T: Element>(&self, meta: &ArrayMeta, index: &[usize], chunk: &ArrayD<T>) -> Result<()> {
+        let file = self.chunk_file(meta, index);
+        if meta.fill_value()?.is_some() {
+            let fill = meta.fill::<T>()?;
+            if chunk.iter().all(|x| *x == fill) {
+                return match fs::remove_file(file) {
+                    Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+                    _ => Ok(()),
+                };
+            }
+        }
+        let bytes = if meta.fortran_order {
+            T::encode(chunk.t().iter())
+        } else {
+            T::encode(chunk.iter())
+        };
+        let bytes = codec::compress(&meta.compressor, bytes, T::SIZE)?;
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(file, bytes)?;
+        Ok(())
+    }
+
+    /// The indices of the chunks that are stored.
+    fn stored_chunks(&self, meta: &ArrayMeta) -> Result<Vec<Vec<usize>>> {
+        fn walk(dir: &Path, depth: usize, prefix: &[usize], sep: &str, out: &mut Vec<Vec<usize>>) -> Result<()> {
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.starts_with('.') {
+                    continue;
+                }
+                let parts: Option<Vec<usize>> = if sep == "/" {
+                    name.parse().ok().map(|x| vec![x])
+                } else {
+                    name.split(sep).map(|x| x.parse().ok()).collect()
+                };
+                let Some(parts) = parts else { continue };
+                let index = [prefix, &parts].concat();
+                if sep == "/" && index.len() < depth && entry.file_type()?.is_dir() {
+                    walk(&entry.path(), depth, &index, sep, out)?;
+                } else if index.len() == depth {
+                    out.push(index);
+                }
+            }
+            Ok(())
+        }
+
+        let mut chunks = Vec::new();
+        if meta.shape.is_empty() {
+            if self.chunk_file(meta, &[]).is_file() {
+                chunks.push(Vec::new());
+            }
+        } else {
+            walk(&self.dir(), meta.shape.len(), &[], &meta.separator, &mut chunks)?;
+        }
+        Ok(chunks)
+    }
+}
+
+/// The selected positions along an axis, grouped by chunk. Each group holds the
+/// index of the chunk and the pairs of positions in the selection and in the chunk.
+type AxisGroups = Vec<(usize, Vec<(usize, usize)>)>;
+
+fn group_by_chunk(indices: &[usize], chunk: usize) -> AxisGroups {
+    let mut groups: BTreeMap<usize, Vec<(usize, usize)>> = BTreeMap::new();
+    for (i, x) in indices.iter().enumerate() {
+        groups.entry(x / chunk).or_default().push((i, x % chunk));
+    }
+    groups.into_iter().collect()
+}
+
+/// Resolve a selection into the indices selected along each axis.
+fn selected_indices<S: AsRef<SelectInfoElem>>(selection: &[S], shape: &[usize]) -> Result<Vec<Vec<usize>>> {
+    ensure!(
+        selection.len() == shape.len(),
+        "the selection has {} dimensions, but the dataset has {}",
+        selection.len(),
+        shape.len(),
+    );
+    selection
+        .iter()
+        .zip(shape)
+        .map(|(sel, n)| {
+            // Slices are clipped to the shape, but indices must be within it.
+            let indices = BoundedSelectInfoElem::new(sel, *n).to_vec();
+            match indices.iter().find(|i| **i >= *n) {
+                Some(i) => bail!("index out of bounds: {} >= {}", i, n),
+                None => Ok(indices),
+            }
+        })
+        .collect()
+}
+
+/// The ranges covered by `pairs` in the selection and in the chunk, if both
+/// are contiguous.
+fn as_ranges(pairs: &[(usize, usize)]) -> Option<(Range<usize>, Range<usize>)> {
+    let (first, last) = (pairs.first()?, pairs.last()?);
+    let contiguous = pairs.windows(2).all(|x| x[1].0 == x[0].0 + 1 && x[1].1 == x[0].1 + 1);
+    contiguous.then(|| (first.0..last.0 + 1, first.1..last.1 + 1))
+}
+
+/// Copy the elements selected by `groups` between an array holding the
+/// selection and a chunk, in the direction given by `into_chunk`.
+fn copy_selected<T: Clone>(
+    selected: &mut ArrayD<T>,
+    chunk: &mut ArrayD<T>,
+    groups: &[&[(usize, usize)]],
+    into_chunk: bool,
+) {
+    if let Some(ranges) = groups.iter().map(|x| as_ranges(x)).collect::<Option<Vec<_>>>() {
+        let mut a = selected.slice_each_axis_mut(|ax| Slice::from(ranges[ax.axis.index()].0.clone()));
+        let mut b = chunk.slice_each_axis_mut(|ax| Slice::from(ranges[ax.axis.index()].1.clone()));
+        if into_chunk {
+            b.assign(&a);
+        } else {
+            a.assign(&b);
+        }
+        return;
+    }
+
+    let lens: Vec<usize> = groups.iter().map(|x| x.len()).collect();
+    let mut i = vec![0; groups.len()];
+    let mut j = vec![0; groups.len()];
+    for idx in ndarray::indices(IxDyn(&lens)) {
+        for (k, g) in groups.iter().enumerate() {
+            (i[k], j[k]) = g[idx[k]];
+        }
+        if into_chunk {
+            chunk[j.as_slice()] = selected[i.as_slice()].clone();
+        } else {
+            selected[i.as_slice()] = chunk[j.as_slice()].clone();
+        }
+    }
+}
+
+/// Iterate over the chunks that intersect the selection, together with the
+/// selected positions in each of them.
+fn for_each_chunk<F>(groups: &[AxisGroups], mut f: F) -> Result<()>
+where
+    F: FnMut(&[usize], &[&[(usize, usize)]]) -> Result<()>,
+{
+    let lens: Vec<usize> = groups.iter().map(|x| x.len()).collect();
+    for idx in ndarray::indices(IxDyn(&lens)) {
+        let index: Vec<usize> = (0..groups.len()).map(|k| groups[k][idx[k]].0).collect();
+        let pairs: Vec<&[(usize, usize)]> = (0..groups.len()).map(|k| groups[k][idx[k]].1.as_slice()).collect();
+        f(&index, &pairs)?;
+    }
+    Ok(())
+}
+
+fn read_select<T: Element>(dataset: &Dataset, meta: &ArrayMeta, indices: &[Vec<usize>]) -> Result<DynArray> {
+    let shape: Vec<usize> = indices.iter().map(|x| x.len()).collect();
+    let groups: Vec<AxisGroups> = indices.iter().zip(&meta.chunks).map(|(x, c)| group_by_chunk(x, *c)).collect();
+    let mut result = ArrayD::<T>::default(shape);
+    for_each_chunk(&groups, |index, pairs| {
+        let mut chunk = dataset.read_chunk::<T>(meta, index)?;
+        copy_selected(&mut result, &mut chunk, pairs, false);
+        Ok(())
+    })?;
+    Ok(T::into_array(result))
+}
+
+fn write_select<T: Element>(dataset: &Dataset, meta: &ArrayMeta, data: DynArray, indices: &[Vec<usize>]) -> Result<()> {
+    let mut data = T::from_dyn_arr(data)?;
+    let shape: Vec<usize> = indices.iter().map(|x| x.len()).collect();
+    ensure!(
+        data.shape() == shape.as_slice(),
+        "cannot write data of shape {:?} to a selection of shape {:?}",
+        data.shape(),
+        shape,
+    );
+    let groups: Vec<AxisGroups> = indices.iter().zip(&meta.chunks).map(|(x, c)| group_by_chunk(x, *c)).collect();
+    for_each_chunk(&groups, |index, pairs| {
+        // Chunks that are entirely overwritten need not be read.
+        let covered = pairs.iter().enumerate().all(|(k, x)| {
+            let len = meta.chunks[k].min(meta.shape[k] - index[k] * meta.chunks[k]);
+            as_ranges(x).map_or(false, |(_, r)| r == (0..len))
+        });
+        let mut chunk = if covered {
+            ArrayD::from_elem(IxDyn(&meta.chunks), meta.fill()?)
+        } else {
+            dataset.read_chunk::<T>(meta, index)?
+        };
+        copy_selected(&mut data, &mut chunk, pairs, true);
+        dataset.write_chunk(meta, index, &chunk)
+    })
+}
+
+/// Reset the elements of a chunk that lie outside of `shape` to the fill value.
+fn clear_outside<T: Element>(dataset: &Dataset, meta: &ArrayMeta, index: &[usize], shape: &[usize]) -> Result<()> {
+    let mut chunk = dataset.read_chunk::<T>(meta, index)?;
+    let fill = meta.fill::<T>()?;
+    chunk.indexed_iter_mut().for_each(|(pos, x)| {
+        let outside = (0..shape.len()).any(|k| index[k] * meta.chunks[k] + pos[k] >= shape[k]);
+        if outside {
+            *x = fill.clone();
+        }
+    });
+    dataset.write_chunk(meta, index, &chunk)
+}
+
+/// Call `$func::<T>($args)` with `T` being the type in which the elements of
+/// an array of type `$dtype` are stored.
+macro_rules! dispatch {
+    ($dtype:expr, $func:ident ( $($arg:expr),* )) => {
+        match $dtype {
+            ScalarType::I8 => $func::<i8>($($arg),*),
+            ScalarType::I16 => $func::<i16>($($arg),*),
+            ScalarType::I32 => $func::<i32>($($arg),*),
+            ScalarType::I64 => $func::<i64>($($arg),*),
+            ScalarType::U8 => $func::<u8>($($arg),*),
+            ScalarType::U16 => $func::<u16>($($arg),*),
+            ScalarType::U32 => $func::<u32>($($arg),*),
+            ScalarType::U64 | ScalarType::Usize => $func::<u64>($($arg),*),
+            ScalarType::F16 => $func::<f16>($($arg),*),
+            ScalarType::F32 => $func::<f32>($($arg),*),
+            ScalarType::F64 => $func::<f64>($($arg),*),
+            ScalarType::ComplexF32 => $func::<Complex32>($($arg),*),
+            ScalarType::ComplexF64 => $func::<Complex64>($($arg),*),
+            ScalarType::Bool => $func::<bool>($($arg),*),
+            ScalarType::String => $func::<String>($($arg),*),
+        }
+    };
+}
+
+fn view_to_array<D: Dimension>(x: DynArrayView<'_, D>) -> DynArray {
+    match x {
+        DynArrayView::I8(x) => DynArray::I8(x.to_owned().into_dyn()),
+        DynArrayView::I16(x) => DynArray::I16(x.to_owned().into_dyn()),
+        DynArrayView::I32(x) => DynArray::I32(x.to_owned().into_dyn()),
+        DynArrayView::I64(x) => DynArray::I64(x.to_owned().into_dyn()),
+        DynArrayView::U8(x) => DynArray::U8(x.to_owned().into_dyn()),
+        DynArrayView::U16(x) => DynArray::U16(x.to_owned().into_dyn()),
+        DynArrayView::U32(x) => DynArray::U32(x.to_owned().into_dyn()),
+        DynArrayView::U64(x) => DynArray::U64(x.to_owned().into_dyn()),
+        DynArrayView::Usize(x) => DynArray::Usize(x.to_owned().into_dyn()),
+        DynArrayView::F16(x) => DynArray::F16(x.to_owned().into_dyn()),
+        DynArrayView::F32(x) => DynArray::F32(x.to_owned().into_dyn()),
+        DynArrayView::F64(x) => DynArray::F64(x.to_owned().into_dyn()),
+        DynArrayView::ComplexF32(x) => DynArray::ComplexF32(x.to_owned().into_dyn()),
+        DynArrayView::ComplexF64(x) => DynArray::ComplexF64(x.to_owned().into_dyn()),
+        DynArrayView::Bool(x) => DynArray::Bool(x.to_owned().into_dyn()),
+        DynArrayView::String(x) => DynArray::String(x.to_owned().into_dyn()),
+    }
+}
+
+/// Convert an array to `dtype`, which is needed when the type requested by the
+/// caller differs from the type of the stored elements, e.g., `usize` values
+/// are stored as `u64`.
+fn cast(arr: DynArray, dtype: ScalarType) -> Result<DynArray> {
+    match anndata::ArrayData::from(arr).cast(dtype)? {
+        anndata::ArrayData::Array(x) => Ok(x),
+        _ => unreachable!(),
+    }
+}
+
+impl DatasetOp for Dataset {
+    type Backend = Zarr;
+
+    fn dtype(&self) -> Result<ScalarType> {
+        Ok(self.meta()?.dtype)
+    }
+
+    fn shape(&self) -> Shape {
+        self.meta().map_or(Vec::new(), |x| x.shape).into()
+    }
+
+    /// Resize the array. Chunks that fall outside of the new shape are removed,
+    /// and the parts of the chunks on the new boundary that fall outside are reset
+    /// to the fill value, so that they do not reappear if the array grows again.
+    fn reshape(&self, shape: &Shape) -> Result<()> {
+        self.ensure_writable()?;
+        let mut meta = self.meta()?;
+        let shape = shape.as_ref();
+        ensure!(
+            shape.len() == meta.shape.len(),
+            "Cannot reshape a {}-dimensional dataset to {} dimensions",
+            meta.shape.len(),
+            shape.len(),
+        );
+        for index in self.stored_chunks(&meta)? {
+            let start = |k: usize| index[k] * meta.chunks[k];
+            if (0..shape.len()).any(|k| start(k) >= shape[k]) {
+                fs::remove_file(self.chunk_file(&meta, &index))?;
+            } else if (0..shape.len()).any(|k| shape[k] < meta.shape[k] && start(k) + meta.chunks[k] > shape[k]) {
+                dispatch!(meta.dtype, clear_outside(self, &meta, &index, shape))?;
+            }
+        }
+        meta.shape = shape.to_vec();
+        meta.raw.insert("shape".to_string(), json!(shape));
+        write_json(&self.dir().join(".zarray"), &Value::Object(meta.raw))
+    }
+
+    fn fill_value(&self) -> Result<Option<DynScalar>> {
+        self.meta()?.fill_value()
+    }
+
+    fn read_scalar<T: BackendData>(&self) -> Result<T> {
+        let arr = self.read_array::<T, IxDyn>()?;
+        ensure!(arr.len() == 1, "expecting a scalar, found an array of shape {:?}", arr.shape());
+        Ok(arr.into_iter().next().unwrap())
+    }
+
+    fn read_array_slice<T: BackendData, S, D>(&self, selection: &[S]) -> Result<Array<T, D>>
+    where
+        S: AsRef<SelectInfoElem>,
+        D: RemoveAxis,
+    {
+        let meta = self.meta()?;
+        let indices = selected_indices(selection, &meta.shape)?;
+        let arr = dispatch!(meta.dtype, read_select(self, &meta, &indices))?;
+        Ok(T::from_dyn_arr(cast(arr, T::DTYPE)?)?.into_dimensionality::<D>()?)
+    }
+
+    fn write_array_slice<'a, A, S, T, D>(&self, data: A, selection: &[S]) -> Result<()>
+    where
+        A: Into<ArrayView<'a, T, D>>,
+        T: BackendData,
+        S: AsRef<SelectInfoElem>,
+        D: RemoveAxis,
+    {
+        self.ensure_writable()?;
+        let meta = self.meta()?;
+        let indices = selected_indices(selection, &meta.shape)?;
+        let data = view_to_array(T::into_dyn_arr(data.into()));
+        let dtype = match meta.dtype {
+            ScalarType::Usize => ScalarType::U64,
+            x => x,
+        };
+        dispatch!(meta.dtype, write_select(self, &meta, cast(data, dtype)?, &indices))
+    }
+}
+
+impl LocationOp for Location {
+    type Backend = Zarr;
+
+    fn file(&self) -> Result<<Self::Backend as Backend>::File> {
+        Ok(new_root(&self.root, self.writable))
+    }
+
+    fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn write_array_attr<'a, A, D, Dim>(&self, name: &str, value: A) -> Result<()>
+    where
+        A: Into<ArrayView<'a, D, Dim>>,
+        D: BackendData,
+        Dim: RemoveAxis,
+    {
+        ensure!(
+            !matches!(D::DTYPE, ScalarType::ComplexF32 | ScalarType::ComplexF64),
+            "complex attributes are not supported by the Zarr backend",
+        );
+        self.set_attr(name, array_to_json(value.into().into_dyn()))
+    }
+
+    fn write_str_attr(&self, name: &str, value: &str) -> Result<()> {
+        self.set_attr(name, Value::String(value.to_string()))
+    }
+
+    fn write_scalar_attr<D: BackendData>(&self, name: &str, value: D) -> Result<()> {
+        ensure!(
+            !matches!(D::DTYPE, ScalarType::ComplexF32 | ScalarType::ComplexF64),
+            "complex attributes are not supported by the Zarr backend",
+        );
+        self.set_attr(name, scalar_to_json(value.into_dyn()))
+    }
+
+    fn read_scalar_attr<T: BackendData>(&self, name: &str) -> Result<T> {
+        let value = self.get_attr(name)?;
+        T::from_dyn(json_to_scalar(&value, T::DTYPE)?)
+    }
+
+    fn read_str_attr(&self, name: &str) -> Result<String> {
+        match self.get_attr(name)? {
+            Value::String(s) => Ok(s),
+            _ => bail!("Attribute is not a string"),
+        }
+    }
+
+    fn read_array_attr<T: BackendData, D: RemoveAxis>(&self, name: &str) -> Result<Array<T, D>> {
+        let value = self.get_attr(name)?;
+        let mut shape = Vec::new();
+        let mut x = &value;
+        while let Value::Array(values) = x {
+            shape.push(values.len());
+            match values.first() {
+                Some(v) => x = v,
+                None => break,
+            }
+        }
+        let mut leaves = Vec::new();
+        flatten_json(&value, &shape, &mut leaves)?;
+        let values = leaves
+            .into_iter()
+            .map(|x| T::from_dyn(json_to_scalar(x, T::DTYPE)?))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Array::from_shape_vec(IxDyn(&shape), values)?.into_dimensionality::<D>()?)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// JSON conversion
+////////////////////////////////////////////////////////////////////////////////
+
+fn read_json(file: &Path) -> Result<Option<Value>> {
+    match fs::read(file) {
+        Ok(bytes) => Ok(Some(
+            serde_json::from_slice(&bytes).with_context(|| format!("cannot parse '{}'", file.display()))?,
+        )),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_json(file: &Path, value: &Value) -> Result<()> {
+    fs::write(file, serde_json::to_vec_pretty(value)?)?;
+    Ok(())
+}
+
+fn usize_list(value: &Value) -> Result<Vec<usize>> {
+    value
+        .as_array()
+        .context("expecting a list")?
+        .iter()
+        .map(|x| x.as_u64().map(|x| x as usize).context("expecting a non-negative integer"))
+        .collect()
+}
+
+/// The data types of NumPy, e.g., `<f8`, in which the first character is the
+/// byte order. Strings are stored in object arrays with the `vlen-utf8` filter.
+fn dtype_from_str(dtype: &str, filters: &Value) -> Result<(ScalarType, bool)> {
+    let filter_ids: Vec<&str> = match filters {
+        Value::Null => Vec::new(),
+        Value::Array(x) => x.iter().map(|f| f["id"].as_str().unwrap_or("")).collect(),
+        _ => bail!("invalid filters: {}", filters),
+    };
+    let (order, code) = dtype.split_at(dtype.len().min(1));
+    let ty = match code {
+        "i1" => ScalarType::I8,
+        "i2" => ScalarType::I16,
+        "i4" => ScalarType::I32,
+        "i8" => ScalarType::I64,
+        "u1" => ScalarType::U8,
+        "u2" => ScalarType::U16,
+        "u4" => ScalarType::U32,
+        "u8" => ScalarType::U64,
+        "f2" => ScalarType::F16,
+        "f4" => ScalarType::F32,
+        "f8" => ScalarType::F64,
+        "c8" => ScalarType::ComplexF32,
+        "c16" => ScalarType::ComplexF64,
+        "b1" => ScalarType::Bool,
+        "O" if filter_ids == ["vlen-utf8"] => ScalarType::String,
+        "O" => bail!("object arrays are only supported with the vlen-utf8 filter"),
+        _ => bail!("the data type '{}' is not supported by the Zarr backend", dtype),
+    };
+    ensure!(
+        ty == ScalarType::String || filter_ids.is_empty(),
+        "the filters {:?} are not supported by the Zarr backend",
+        filter_ids,
+    );
+    Ok((ty, order == ">"))
+}
+
+fn dtype_to_str(dtype: ScalarType) -> &'static str {
+    match dtype {
+        ScalarType::I8 => "|i1",
+        ScalarType::I16 => "<i2",
+        ScalarType::I32 => "<i4",
+        ScalarType::I64 => "<i8",
+        ScalarType::U8 => "|u1",
+        ScalarType::U16 => "<u2",
+        ScalarType::U32 => "<u4",
+        ScalarType::U64 | ScalarType::Usize => "<u8",
+        ScalarType::F16 => "<f2",
+        ScalarType::F32 => "<f4",
+        ScalarType::F64 => "<f8",
+        ScalarType::ComplexF32 => "<c8",
+        ScalarType::ComplexF64 => "<c16",
+        ScalarType::Bool => "|b1",
+        ScalarType::String => "|O",
+    }
+}
+
+/// Non-finite floats are written as strings, as in the fill values of Zarr.
+fn float_to_json(x: f64) -> Value {
+    match Number::from_f64(x) {
+        Some(x) => Value::Number(x),
+        None if x.is_nan() => Value::String("NaN".to_string()),
+        None if x > 0.0 => Value::String("Infinity".to_string()),
+        None => Value::String("-Infinity".to_string()),
+    }
+}
+
+fn scalar_to_json(x: DynScalar) -> Value {
+    match x {
+        DynScalar::I8(x) => x.into(),
+        DynScalar::I16(x) => x.into(),
+        DynScalar::I32(x) => x.into(),
+        DynScalar::I64(x) => x.into(),
+        DynScalar::U8(x) => x.into(),
+        DynScalar::U16(x) => x.into(),
+        DynScalar::U32(x) => x.into(),
+        DynScalar::U64(x) => x.into(),
+        DynScalar::Usize(x) => x.into(),
+        DynScalar::F16(x) => float_to_json(x.to_f64()),
+        DynScalar::F32(x) => float_to_json(x as f64),
+        DynScalar::F64(x) => float_to_json(x),
+        DynScalar::ComplexF32(x) => json!([float_to_json(x.re as f64), float_to_json(x.im as f64)]),
+        DynScalar::ComplexF64(x) => json!([float_to_json(x.re), float_to_json(x.im)]),
+        DynScalar::Bool(x) => x.into(),
+        DynScalar::String(x) => x.into(),
+    }
+}
+
+fn json_to_scalar(value: &Value, dtype: ScalarType) -> Result<DynScalar> {
+    fn to_f64(value: &Value) -> Result<f64> {
+        match value {
+            Value::Number(x) => Ok(x.as_f64().unwrap()),
+            Value::String(x) if x == "NaN" => Ok(f64::NAN),
+            Value::String(x) if x == "Infinity" => Ok(f64::INFINITY),
+            Value::String(x) if x == "-Infinity" => Ok(f64::NEG_INFINITY),
+            _ => bail!("cannot convert {} to a number", value),
+        }
+    }
+
+    let x = match value {
+        Value::Bool(x) if dtype == ScalarType::Bool => DynScalar::Bool(*x),
+        Value::String(x) if dtype == ScalarType::String => DynScalar::String(x.clone()),
+        Value::Number(x) => match (x.as_u64(), x.as_i64()) {
+            (Some(x), _) => DynScalar::U64(x),
+            (_, Some(x)) => DynScalar::I64(x),
+            _ => DynScalar::F64(x.as_f64().unwrap()),
+        },
+        Value::Array(x) if x.len() == 2 && matches!(dtype, ScalarType::ComplexF32 | ScalarType::ComplexF64) => {
+            DynScalar::ComplexF64(Complex64::new(to_f64(&x[0])?, to_f64(&x[1])?))
+        }
+        Value::String(_) => DynScalar::F64(to_f64(value)?),
+        _ => bail!("cannot convert {} to {}", value, dtype),
+    };
+    x.cast(dtype)
+}
+
+fn array_to_json<T: BackendData>(arr: ArrayViewD<'_, T>) -> Value {
+    if arr.ndim() == 0 {
+        scalar_to_json(arr.first().unwrap().into_dyn())
+    } else {
+        Value::Array(arr.outer_iter().map(array_to_json).collect())
+    }
+}
+
+/// Collect the elements of nested lists, checking that the lists have the given shape.
+fn flatten_json<'a>(value: &'a Value, shape: &[usize], leaves: &mut Vec<&'a Value>) -> Result<()> {
+    match shape.split_first() {
+        None => leaves.push(value),
+        Some((n, rest)) => match value {
+            Value::Array(values) if values.len() == *n => {
+                values.iter().try_for_each(|x| flatten_json(x, rest, leaves))?
+            }
+            _ => bail!("the attribute is not an array of regular shape"),
+        },
+    }
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Implementation through Deref
+////////////////////////////////////////////////////////////////////////////////
+impl GroupOp for Root {
+    type Backend = Zarr;
+
+    fn list(&self) -> Result<Vec<String>> {
+        self.deref().list()
+    }
+
+    /// Create a new group.
+    fn create_group(&self, name: &str) -> Result<<Self::Backend as Backend>::Group> {
+        self.deref().create_group(name)
+    }
+
+    /// Open an existing group.
+    fn open_group(&self, name: &str) -> Result<<Self::Backend as Backend>::Group> {
+        self.deref().open_group(name)
+    }
+
+    /// Create an empty dataset holding an array value.
+    fn new_dataset<T: BackendData>(
+        &self,
+        name: &str,
+        shape: &Shape,
+        config: WriteConfig,
+    ) -> Result<<Self::Backend as Backend>::Dataset> {
+        self.deref().new_dataset::<T>(name, shape, config)
+    }
+
+    fn open_dataset(&self, name: &str) -> Result<<Self::Backend as Backend>::Dataset> {
+        self.deref().open_dataset(name)
+    }
+
+    /// Delete a group or dataset.
+    fn delete(&self, name: &str) -> Result<()> {
+        self.deref().delete(name)
+    }
+
+    /// Check if a group or dataset exists.
+    fn exists(&self, name: &str) -> Result<bool> {
+        self.deref().exists(name)
+    }
+
+    /// Rename a group or dataset.
+    fn rename(&self, name: &str, new_name: &str) -> Result<()> {
+        self.deref().rename(name, new_name)
+    }
+
+    fn create_scalar_data<D: BackendData>(
+        &self,
+        name: &str,
+        data: &D,
+    ) -> Result<<Self::Backend as Backend>::Dataset> {
+        self.deref().create_scalar_data(name, data)
+    }
+}
+
+impl LocationOp for Group {
+    type Backend = Zarr;
+
+    fn file(&self) -> Result<<Self::Backend as Backend>::File> {
+        self.deref().file()
+    }
+
+    fn path(&self) -> PathBuf {
+        self.deref().path()
+    }
+
+    fn write_array_attr<'a, A, D, Dim>(&self, name: &str, value: A) -> Result<()>
+    where
+        A: Into<ArrayView<'a, D, Dim>>,
+        D: BackendData,
+        Dim: RemoveAxis,
+    {
+        self.deref().write_array_attr(name, value)
+    }
+
+    fn write_scalar_attr<D: BackendData>(&self, name: &str, value: D) -> Result<()> {
+        self.deref().write_scalar_attr(name, value)
+    }
+
+    fn write_str_attr(&self, name: &str, value: &str) -> Result<()> {
+        self.deref().write_str_attr(name, value)
+    }
+
+    fn read_str_attr(&self, name: &str) -> Result<String> {
+        self.deref().read_str_attr(name)
+    }
+
+    fn read_scalar_attr<T: BackendData>(&self, name: &str) -> Result<T> {
+        self.deref().read_scalar_attr(name)
+    }
+
+    fn read_array_attr<T: BackendData, D: RemoveAxis>(&self, name: &str) -> Result<Array<T, D>> {
+        self.deref().read_array_attr(name)
+    }
+}
+
+impl LocationOp for Dataset {
+    type Backend = Zarr;
+
+    fn file(&self) -> Result<<Self::Backend as Backend>::File> {
+        self.deref().file()
+    }
+
+    fn path(&self) -> PathBuf {
+        self.deref().path()
+    }
+
+    fn write_array_attr<'a, A, D, Dim>(&self, name: &str, value: A) -> Result<()>
+    where
+        A: Into<ArrayView<'a, D, Dim>>,
+        D: BackendData,
+        Dim: RemoveAxis,
+    {
+        self.deref().write_array_attr(name, value)
+    }
+
+    fn write_scalar_attr<D: BackendData>(&self, name: &str, value: D) -> Result<()> {
+        self.deref().write_scalar_attr(name, value)
+    }
+
+    fn write_str_attr(&self, name: &str, value: &str) -> Result<()> {
+        self.deref().write_str_attr(name, value)
+    }
+
+    fn read_str_attr(&self, name: &str) -> Result<String> {
+        self.deref().read_str_attr(name)
+    }
+
+    fn read_scalar_attr<T: BackendData>(&self, name: &str) -> Result<T> {
+        self.deref().read_scalar_attr(name)
+    }
+
+    fn read_array_attr<T: BackendData, D: RemoveAxis>(&self, name: &str) -> Result<Array<T, D>> {
+        self.deref().read_array_attr(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anndata::data::SelectInfoElem;
+    use ndarray::{array, s, Array1, Array2, Axis, Ix1, Ix2};
+    use ndarray_rand::rand_distr::{Standard, Uniform};
+    use ndarray_rand::RandomExt;
+    use tempfile::tempdir;
+
+    pub fn with_tmp_dir<T, F: FnMut(PathBuf) -> T>(mut func: F) -> T {
+        let dir = tempdir().unwrap();
+        let path = dir.path().to_path_buf();
+        func(path)
+    }
+
+    fn with_tmp_path<T, F: Fn(PathBuf) -> T>(func: F) -> T {
+        with_tmp_dir(|dir| func(dir.join("temp.zarr")))
+    }
+
+    fn scalar_io<T: PartialEq + BackendData + std::fmt::Debug>(root: &Root, data: T) -> Result<()> {
+        let dataset = root.create_scalar_data("scalar", &data)?;
+        assert_eq!(dataset.read_scalar::<T>()?, data);
+        root.delete("scalar")?;
+        Ok(())
+    }
+
+    fn array_io<'a, A, T, D>(root: &Root, data: A, config: WriteConfig) -> Result<()>
+    where
+        A: Into<ArrayView<'a, T, D>>,
+        T: PartialEq + BackendData + std::fmt::Debug,
+        D: RemoveAxis,
+    {
+        let arr = data.into();
+        let dataset = root.create_array_data("array", &arr, config)?;
+        assert_eq!(dataset.read_array::<T, _>()?, arr);
+        assert_eq!(dataset.dtype()?, T::DTYPE);
+        root.delete("array")?;
+        Ok(())
+    }
+
+    fn array_attr_io<'a, A, T, D>(root: &Root, data: A) -> Result<()>
+    where
+        A: Into<ArrayView<'a, T, D>>,
+        T: PartialEq + BackendData + std::fmt::Debug,
+        D: RemoveAxis,
+    {
+        let arr = data.into();
+        let dataset = root.create_scalar_data("scalar", &1u8)?;
+        dataset.write_array_attr("test", &arr)?;
+        assert_eq!(dataset.read_array_attr::<T, D>("test")?, arr);
+        root.delete("scalar")?;
+        Ok(())
+    }
+
+    fn chunked(block_size: Vec<usize>, compression: Option<Compression>) -> WriteConfig {
+        WriteConfig {
+            compression,
+            block_size: Some(block_size.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_path() -> Result<()> {
+        with_tmp_path(|path| {
+            let file = Zarr::create(&path)?;
+            let group = file.create_group("group")?;
+            let subgroup = group.create_group("subgroup")?;
+
+            assert_eq!(subgroup.path(), PathBuf::from("/group/subgroup"));
+            assert!(path.join("group/subgroup/.zgroup").is_file());
+            assert_eq!(file.list()?, vec!["group".to_string()]);
+
+            group.rename("subgroup", "renamed")?;
+            assert_eq!(group.list()?, vec!["renamed".to_string()]);
+            group.delete("renamed")?;
+            file.delete("group")?;
+            assert!(file.list()?.is_empty());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_open() -> Result<()> {
+        with_tmp_path(|path| {
+            let file = Zarr::create(&path)?;
+            file.create_scalar_data("x", &1u8)?;
+
+            let file = Zarr::open(&path)?;
+            assert_eq!(file.open_dataset("x")?.read_scalar::<u8>()?, 1);
+            assert!(file.create_group("group").is_err());
+
+            assert!(Zarr::open(path.join("x")).is_err());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_scalar() -> Result<()> {
+        with_tmp_path(|path| {
+            let root = Zarr::create(path)?;
+
+            scalar_io(&root, 10u8)?;
+            scalar_io(&root, -10i64)?;
+            scalar_io(&root, 10usize)?;
+            scalar_io(&root, 1.5f32)?;
+            scalar_io(&root, f16::from_f32(2.5))?;
+            scalar_io(&root, Complex64::new(1.0, -2.0))?;
+            scalar_io(&root, true)?;
+            scalar_io(&root, "this is a test".to_string())?;
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_array() -> Result<()> {
+        with_tmp_path(|path| {
+            let root = Zarr::create(path)?;
+            let arr = Array::random((20, 7), Uniform::new(0, 100));
+            array_io(&root, &arr, Default::default())?;
+            array_io(&root, &arr.mapv(|x| x as f64 / 3.0), chunked(vec![3, 4], None))?;
+            array_io(&root, &arr.mapv(|x| x as u16), chunked(vec![6, 6], Some(Compression::Gzip(5))))?;
+            array_io(&root, &arr.mapv(|x| x as i8), chunked(vec![7, 2], Some(Compression::Zstd(3))))?;
+            array_io(&root, &arr.mapv(|x| x % 2 == 0), chunked(vec![4, 3], Some(Compression::Lz4)))?;
+            array_io(&root, &arr.mapv(|x| Complex32::new(x as f32, 1.0)), chunked(vec![5, 5], None))?;
+
+            let strings = Array::random((10, 3), Standard).map(|x: &[char; 10]| x.iter().collect::<String>());
+            array_io(&root, &strings, chunked(vec![4, 2], Some(Compression::Lz4)))?;
+
+            let empty = Array2::<u32>::zeros((0, 3));
+            array_io(&root, &empty, Default::default())?;
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_slice() -> Result<()> {
+        with_tmp_path(|path| {
+            let root = Zarr::create(path)?;
+            let arr = Array::random((23, 11), Uniform::new(-100, 100));
+            let dataset = root.create_array_data("array", &arr, chunked(vec![4, 3], Some(Compression::Zstd(1))))?;
+
+            let rows = SelectInfoElem::from(vec![20, 0, 5, 5, 13]);
+            let cols = SelectInfoElem::from(2..9);
+            let selected: Array2<i32> = dataset.read_array_slice(&[rows.clone(), cols.clone()])?;
+            assert_eq!(selected, arr.select(Axis(0), &[20, 0, 5, 5, 13]).slice(s![.., 2..9]));
+
+            let mut expected = arr.clone();
+            let update = Array::random((3, 4), Uniform::new(200, 300));
+            dataset.write_array_slice(&update, &[SelectInfoElem::from(5..8), SelectInfoElem::from(vec![10, 1, 4, 7])])?;
+            for (i, r) in (5..8).enumerate() {
+                for (j, c) in [10, 1, 4, 7].into_iter().enumerate() {
+                    expected[[r, c]] = update[[i, j]];
+                }
+            }
+            assert_eq!(dataset.read_array::<i32, _>()?, expected);
+
+            let out_of_bounds = SelectInfoElem::from(vec![23]);
+            assert!(dataset.read_array_slice::<i32, _, Ix2>(&[out_of_bounds, cols]).is_err());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_reshape() -> Result<()> {
+        with_tmp_path(|path| {
+            let root = Zarr::create(path)?;
+            let config = WriteConfig {
+                block_size: Some(vec![4, 4].into()),
+                fill_value: Some(DynScalar::I32(-1)),
+                ..Default::default()
+            };
+            let dataset = root.new_dataset::<i32>("array", &vec![0, 6].into(), config)?;
+            for i in 0..3 {
+                let shape = dataset.shape();
+                let block = Array2::from_elem((5, 6), i);
+                dataset.reshape(&vec![shape[0] + 5, 6].into())?;
+                dataset.write_array_slice(&block, &[SelectInfoElem::from(shape[0]..shape[0] + 5), SelectInfoElem::full()])?;
+            }
+            let arr = dataset.read_array::<i32, Ix2>()?;
+            assert_eq!(arr.shape(), &[15, 6]);
+            assert_eq!(arr.slice(s![5..10, ..]), Array2::from_elem((5, 6), 1));
+
+            // Shrinking and growing again exposes the fill value.
+            dataset.reshape(&vec![6, 5].into())?;
+            dataset.reshape(&vec![9, 6].into())?;
+            let arr = dataset.read_array::<i32, Ix2>()?;
+            assert_eq!(arr.slice(s![..5, ..5]), Array2::<i32>::zeros((5, 5)));
+            assert_eq!(arr.slice(s![5, ..5]), Array::from_elem(5, 1));
+            assert!(arr.slice(s![6.., ..]).iter().all(|x| *x == -1));
+            assert!(arr.slice(s![.., 5]).iter().all(|x| *x == -1));
+            assert_eq!(dataset.fill_value()?, Some(DynScalar::I32(-1)));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_attr() -> Result<()> {
+        with_tmp_path(|path| {
+            let root = Zarr::create(path)?;
+
+            root.write_str_attr("encoding-type", "anndata")?;
+            root.write_scalar_attr("n", 5usize)?;
+            root.write_scalar_attr("x", f64::NAN)?;
+            assert_eq!(root.read_str_attr("encoding-type")?, "anndata");
+            assert_eq!(root.read_scalar_attr::<usize>("n")?, 5);
+            assert!(root.read_scalar_attr::<f64>("x")?.is_nan());
+
+            let string_arr = Array::random((2, 5), Standard).map(|x: &[char; 10]| x.iter().collect::<String>());
+            array_attr_io(&root, &Array::random((2, 5), Uniform::new(0, 100)))?;
+            array_attr_io(&root, &Array::random(4, Uniform::new(-1.0, 1.0)))?;
+            array_attr_io(&root, &string_arr)?;
+            array_attr_io(&root, &Array1::<String>::default(0))?;
+
+            Ok(())
+        })
+    }
+
+    /// The metadata and chunks follow the layout of zarr-python.
+    #[test]
+    fn test_layout() -> Result<()> {
+        with_tmp_path(|path| {
+            let root = Zarr::create(&path)?;
+            let group = root.create_group("obs")?;
+            group.write_str_attr("encoding-type", "dataframe")?;
+            let arr = array![[1i32, 2, 3], [4, 5, 6]];
+            group.create_array_data("x", &arr, chunked(vec![2, 2], None))?;
+            group.create_array_data("s", &array!["a".to_string(), "bc".to_string()], chunked(vec![2], None))?;
+
+            let zattrs: Value = serde_json::from_slice(&fs::read(path.join("obs/.zattrs"))?)?;
+            assert_eq!(zattrs, json!({ "encoding-type": "dataframe" }));
+            let zarray: Value = serde_json::from_slice(&fs::read(path.join("obs/x/.zarray"))?)?;
+            assert_eq!(zarray, json!({
+                "chunks": [2, 2],
+                "compressor": null,
+                "dtype": "<i4",
+                "fill_value": null,
+                "filters": null,
+                "order": "C",
+                "shape": [2, 3],
+                "zarr_format": 2,
+            }));
+            let bytes = |x: &[i32]| x.iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<_>>();
+            assert_eq!(fs::read(path.join("obs/x/0.0"))?, bytes(&[1, 2, 4, 5]));
+            assert_eq!(fs::read(path.join("obs/x/0.1"))?, bytes(&[3, 0, 6, 0]));
+
+            let zarray: Value = serde_json::from_slice(&fs::read(path.join("obs/s/.zarray"))?)?;
+            assert_eq!(zarray["dtype"], "|O");
+            assert_eq!(zarray["filters"], json!([{ "id": "vlen-utf8" }]));
+            assert_eq!(fs::read(path.join("obs/s/0"))?, b"\x02\0\0\0\x01\0\0\0a\x02\0\0\0bc");
+
+            let scalar = root.create_scalar_data("n", &3u8)?;
+            assert_eq!(scalar.shape().ndim(), 0);
+            assert_eq!(fs::read(path.join("n/0"))?, vec![3]);
+            Ok(())
+        })
+    }
+
+    /// Read arrays written by other implementations, in layouts that are never
+    /// written here.
+    #[test]
+    fn test_foreign_layout() -> Result<()> {
+        with_tmp_path(|path| {
+            let root = Zarr::create(&path)?;
+            let write_array = |name: &str, meta: Value, chunks: &[(&str, Vec<u8>)]| -> Result<()> {
+                let dir = path.join(name);
+                fs::create_dir_all(&dir)?;
+                write_json(&dir.join(".zarray"), &meta)?;
+                for (key, bytes) in chunks {
+                    let file = dir.join(key);
+                    fs::create_dir_all(file.parent().unwrap())?;
+                    fs::write(file, bytes)?;
+                }
+                Ok(())
+            };
+            let meta = |dtype: &str, order: &str, compressor: Value| json!({
+                "chunks": [2, 2], "compressor": compressor, "dtype": dtype, "fill_value": 0,
+                "filters": null, "order": order, "shape": [2, 3], "zarr_format": 2,
+            });
+
+            // Big-endian integers in Fortran order, with a missing chunk.
+            let be = |x: &[i16]| x.iter().flat_map(|x| x.to_be_bytes()).collect::<Vec<_>>();
+            write_array("f", meta(">i2", "F", Value::Null), &[("0.0", be(&[1, 4, 2, 5]))])?;
+            let arr = root.open_dataset("f")?.read_array::<i16, Ix2>()?;
+            assert_eq!(arr, array![[1, 2, 0], [4, 5, 0]]);
+
+            // Nested chunk keys.
+            let mut m = meta("<f8", "C", Value::Null);
+            m["dimension_separator"] = json!("/");
+            let le = |x: &[f64]| x.iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<_>>();
+            write_array("n", m, &[("0/0", le(&[1.0, 2.0, 4.0, 5.0])), ("0/1", le(&[3.0, 0.0, 6.0, 0.0]))])?;
+            assert_eq!(root.open_dataset("n")?.read_array::<f64, Ix2>()?, array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+
+            // A Blosc frame whose block is split into one stream per byte of the
+            // elements, each stored uncompressed.
+            let values: Vec<u32> = (0..128).map(|x| x * 1000).collect();
+            let bytes: Vec<u8> = values.iter().flat_map(|x| x.to_le_bytes()).collect();
+            let mut frame = vec![2, 1, BLOSC_SHUFFLE_FLAG, 4];
+            frame.extend(512u32.to_le_bytes());
+            frame.extend(512u32.to_le_bytes());
+            frame.extend((16 + 4 + 4 * (4 + 128) as u32).to_le_bytes());
+            frame.extend(20u32.to_le_bytes());
+            for j in 0..4 {
+                frame.extend(128u32.to_le_bytes());
+                frame.extend((0..128).map(|i| bytes[i * 4 + j]));
+            }
+            let blosc = json!({ "id": "blosc", "cname": "lz4", "clevel": 5, "shuffle": 1, "blocksize": 0 });
+            let mut m = meta("<u4", "C", blosc);
+            m["shape"] = json!([128]);
+            m["chunks"] = json!([128]);
+            write_array("b", m, &[("0", frame)])?;
+            assert_eq!(root.open_dataset("b")?.read_array::<u32, Ix1>()?, Array::from(values));
+
+            // Structured arrays are rejected.
+            let mut m = meta("<i4", "C", Value::Null);
+            m["dtype"] = json!([["a", "<i4"], ["b", "<f8"]]);
+            write_array("r", m, &[])?;
+            assert!(root.open_dataset("r")?.dtype().is_err());
+            Ok(())
+        })
+    }
+
+    const BLOSC_SHUFFLE_FLAG: u8 = 0x1;
+
+    #[test]
+    fn test_codec() -> Result<()> {
+        let values: Vec<f64> = (0..1000).map(|x| (x % 17) as f64 * 0.5).collect();
+        let data = f64::encode(values.iter());
+        let configs = [
+            json!({ "id": "zlib", "level": 1 }),
+            json!({ "id": "gzip", "level": 5 }),
+            json!({ "id": "zstd", "level": 3 }),
+            json!({ "id": "lz4", "acceleration": 1 }),
+            json!({ "id": "blosc", "cname": "lz4", "clevel": 5, "shuffle": 1, "blocksize": 0 }),
+            json!({ "id": "blosc", "cname": "lz4hc", "clevel": 9, "shuffle": 0, "blocksize": 0 }),
+            json!({ "id": "blosc", "cname": "zlib", "clevel": 5, "shuffle": 1, "blocksize": 0 }),
+            json!({ "id": "blosc", "cname": "zstd", "clevel": 5, "shuffle": 1, "blocksize": 0 }),
+            json!({ "id": "blosc", "cname": "blosclz", "clevel": 5, "shuffle": 1, "blocksize": 0 }),
+            json!({ "id": "blosc", "cname": "lz4", "clevel": 0, "shuffle": 1, "blocksize": 0 }),
+        ];
+        for config in configs {
+            let compressed = codec::compress(&config, data.clone(), 8)?;
+            let decompressed = codec::decompress(&config, compressed)?;
+            assert_eq!(f64::decode(&decompressed, values.len(), false)?, values, "{}", config);
+        }
+        assert!(codec::compress(&json!({ "id": "bz2" }), data, 8).is_err());
+        Ok(())
+    }
+}
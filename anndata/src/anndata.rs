@@ -1,20 +1,44 @@
+mod builder;
+mod concat;
 mod dataset;
+mod describe;
+mod eq;
+mod filter;
+mod integrity;
+mod owned;
+mod split;
+mod stats;
+mod transpose;
+mod validate;
+mod x_writer;
 
+pub use builder::AnnDataBuilder;
+pub use concat::Join;
+pub use describe::{ArrayInfo, StructureInfo};
+pub use eq::anndata_eq;
+pub use filter::DataFrameRow;
+pub use integrity::IntegrityProblem;
+pub use owned::MemAnnData;
+pub use stats::Statistics;
+pub use validate::ValidationWarning;
+pub use x_writer::XWriter;
+pub(crate) use transpose::{transpose, ColumnMajor};
 pub use dataset::{AnnDataSet, StackedAnnData};
 use smallvec::SmallVec;
 
 use crate::{
-    backend::{Backend, DataContainer, FileOp, GroupOp},
+    backend::{memory::MemFile, Backend, BackendData, DataContainer, DataType, FileOp, GroupOp, InMemory, WriteConfig},
     container::{
-        Dim, ArrayElem, Axis, AxisArrays, DataFrameElem, ElemCollection,
-        InnerDataFrameElem, Slot,
+        Dim, ArrayElem, Axis, AxisArrays, CacheBudget, ChunkedArrayWithColumns, DataFrameElem,
+        ElemCollection, InnerDataFrameElem, Slot, collection::DimLock,
     },
     data::*,
     traits::AnnDataOp,
 };
 
-use anyhow::{anyhow, ensure, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use itertools::Itertools;
+use nalgebra_sparse::CsrMatrix;
 use polars::prelude::DataFrame;
 use std::path::{Path, PathBuf};
 
@@ -34,6 +58,7 @@ pub struct AnnData<B: Backend> {
     varp: AxisArrays<B>,
     uns: ElemCollection<B>,
     layers: AxisArrays<B>,
+    cache_budget: CacheBudget,
 }
 
 impl<B: Backend> std::fmt::Debug for AnnData<B> {
@@ -128,6 +153,18 @@ impl<B: Backend> AnnData<B> {
 
     /// Open an existing AnnData.
     pub fn open(file: B::File) -> Result<Self> {
+        Self::open_impl(file, false)
+    }
+
+    /// Open an existing AnnData without opening the elements of obsm, obsp,
+    /// varm, varp, layers and uns. Only their keys are listed, and each element
+    /// is opened on first access. This makes opening files with many elements
+    /// much faster when only a few of them are used.
+    pub fn open_lazy(file: B::File) -> Result<Self> {
+        Self::open_impl(file, true)
+    }
+
+    fn open_impl(file: B::File, lazy: bool) -> Result<Self> {
         let n_obs = Dim::empty();
         let n_vars = Dim::empty();
 
@@ -159,36 +196,25 @@ impl<B: Backend> AnnData<B> {
             Slot::empty()
         };
 
-        let obsm = match file.open_group("obsm").or(file.create_group("obsm")) {
-            Ok(group) => new_obsm(group, &n_obs)?,
-            _ => AxisArrays::empty(),
-        };
-
-        let obsp = match file.open_group("obsp").or(file.create_group("obsp")) {
-            Ok(group) => new_obsp(group, &n_obs)?,
-            _ => AxisArrays::empty(),
-        };
-
-        let varm = match file.open_group("varm").or(file.create_group("varm")) {
-            Ok(group) => new_varm(group, &n_vars)?,
-            _ => AxisArrays::empty(),
-        };
-
-        let varp = match file.open_group("varp").or(file.create_group("varp")) {
-            Ok(group) => new_varp(group, &n_vars)?,
-            _ => AxisArrays::empty(),
+        let axis_arrays = |name: &str, axis: Axis, dim1: &Dim, dim2: Option<&Dim>| {
+            match file.open_group(name).or(file.create_group(name)) {
+                Ok(group) if lazy => AxisArrays::new_lazy(group, axis, dim1, dim2),
+                Ok(group) => AxisArrays::new(group, axis, dim1, dim2),
+                _ => Ok(AxisArrays::empty()),
+            }
         };
+        let obsm = axis_arrays("obsm", Axis::Row, &n_obs, None)?;
+        let obsp = axis_arrays("obsp", Axis::Pairwise, &n_obs, None)?;
+        let varm = axis_arrays("varm", Axis::Row, &n_vars, None)?;
+        let varp = axis_arrays("varp", Axis::Pairwise, &n_vars, None)?;
+        let layers = axis_arrays("layers", Axis::RowColumn, &n_obs, Some(&n_vars))?;
 
         let uns = match file.open_group("uns").or(file.create_group("uns")) {
+            Ok(group) if lazy => ElemCollection::new_lazy(group)?,
             Ok(group) => ElemCollection::new(group)?,
             _ => ElemCollection::empty(),
         };
 
-        let layers = match file.open_group("layers").or(file.create_group("layers")) {
-            Ok(group) => new_layers(group, &n_obs, &n_vars)?,
-            _ => AxisArrays::empty(),
-        };
-
         Ok(Self {
             file,
             n_obs,
@@ -202,11 +228,18 @@ impl<B: Backend> AnnData<B> {
             varp,
             uns,
             layers,
-        })
+            cache_budget: CacheBudget::default(),
+        }
+        .with_cache_budget())
     }
 
     pub fn new<P: AsRef<Path>>(filename: P) -> Result<Self> {
-        let file = B::create(filename)?;
+        Self::create(B::create(filename)?)
+    }
+
+    /// Create an empty AnnData in a newly created file, e.g., an in-memory
+    /// file that has no path.
+    pub fn create(file: B::File) -> Result<Self> {
         let n_obs = Dim::empty();
         let n_vars = Dim::empty();
         Ok(Self {
@@ -222,10 +255,103 @@ impl<B: Backend> AnnData<B> {
             file,
             n_obs,
             n_vars,
-        })
+            cache_budget: CacheBudget::default(),
+        }
+        .with_cache_budget())
+    }
+
+    /// Count the data cached by X and the arrays in obsm, obsp, varm, varp
+    /// and layers towards the cache budget of the object.
+    fn with_cache_budget(self) -> Self {
+        self.x.set_cache_budget(self.cache_budget.clone());
+        [&self.obsm, &self.obsp, &self.varm, &self.varp, &self.layers]
+            .into_iter()
+            .for_each(|x| {
+                if let Some(x) = x.lock().as_mut() {
+                    x.set_cache_budget(self.cache_budget.clone());
+                }
+            });
+        self
+    }
+
+    /// Replace the 'X' element, keeping the slot shared by all references.
+    /// A new CSR matrix is stored transposed if `transposed` is true, i.e.,
+    /// if the element it replaces was.
+    fn replace_x(&self, elem: ArrayElem<B>, transposed: bool) -> Result<()> {
+        self.x.swap(&elem);
+        self.x.set_cache_budget(self.cache_budget.clone());
+        let is_csr = self
+            .x
+            .lock()
+            .as_ref()
+            .map_or(false, |x| matches!(x.dtype(), DataType::CsrMatrix(_)));
+        if transposed && is_csr {
+            self.x.set_transposed(true)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the CSR matrix in X is stored transposed, i.e., as a CSC
+    /// matrix. See `set_x_transposed`.
+    pub fn is_x_transposed(&self) -> bool {
+        self.x.lock().as_ref().map_or(false, |x| x.is_transposed())
+    }
+
+    /// Store the CSR matrix in X as a CSC matrix of the same shape, i.e., with
+    /// its entries re-sorted by variable, or back as a CSR matrix if
+    /// `transposed` is false. X is still read, iterated and subset as a CSR
+    /// matrix, but iterating over its columns by `chunked_cols` becomes the
+    /// cheap path, which suits per-variable access. X is rewritten if the
+    /// layout changes, and keeps it when X is replaced by another CSR matrix.
+    ///
+    /// Other readers see a standard CSC matrix of shape (n_obs, n_vars); an
+    /// extra attribute records that it is presented as a CSR matrix. `write`
+    /// and `write_select` produce CSR matrices.
+    pub fn set_x_transposed(&self, transposed: bool) -> Result<()> {
+        self.x.set_transposed(transposed)
+    }
+
+    /// Set X to a CSR matrix like `set_x`, but write it with multiple threads.
+    /// The values and column indices are split into blocks of `chunk_size`,
+    /// which are converted and written at their offsets in pre-sized datasets
+    /// by tasks on the rayon thread pool. The stored matrix is identical to
+    /// the one written by `set_x`.
+    pub fn set_x_par<T: BackendData>(&self, data: &CsrMatrix<T>, chunk_size: usize) -> Result<()> {
+        ensure!(chunk_size > 0, "chunk_size must be greater than 0");
+        self.set_x_shape(&data.shape())?;
+        let transposed = self.is_x_transposed();
+        self.del_x()?;
+        let new_elem = ArrayElem::try_from(write_csr_par(data, &self.file, "X", chunk_size)?)?;
+        self.replace_x(new_elem, transposed)
+    }
+
+    /// The number of bytes of data cached in memory by X and the arrays in
+    /// obsm, obsp, varm, varp and layers. Only elements with caching enabled
+    /// hold cached data.
+    pub fn cache_bytes(&self) -> usize {
+        let arrays = [&self.obsm, &self.obsp, &self.varm, &self.varp, &self.layers];
+        self.x.cached_bytes()
+            + arrays
+                .into_iter()
+                .map(|x| x.lock().as_ref().map_or(0, |x| x.cached_bytes()))
+                .sum::<usize>()
+    }
+
+    /// The maximum number of bytes of cached data, if any.
+    pub fn cache_budget(&self) -> Option<usize> {
+        self.cache_budget.limit()
+    }
+
+    /// Limit the number of bytes of cached data to `bytes`. When the limit is
+    /// exceeded, the cached data of the least recently used elements is
+    /// dropped; it is read from the file again on the next access. `None`
+    /// removes the limit.
+    pub fn set_cache_budget(&self, bytes: Option<usize>) {
+        self.cache_budget.set_limit(bytes);
     }
 
     pub fn write<O: Backend, P: AsRef<Path>>(&self, filename: P) -> Result<()> {
+        self.open_arrays()?;
         let file = O::create(filename)?;
         let _obs_lock = self.n_obs.lock();
         let _vars_lock = self.n_vars.lock();
@@ -274,11 +400,60 @@ impl<B: Backend> AnnData<B> {
             .as_mut()
             .map(|x| x.export::<O, _>(&file, "layers"))
             .transpose()?;
+        file.flush()?;
         file.close()?;
         Ok(())
     }
 
     pub fn write_select<O, S, P>(&self, selection: S, filename: P) -> Result<()>
+    where
+        O: Backend,
+        S: AsRef<[SelectInfoElem]>,
+        P: AsRef<Path>,
+    {
+        self.write_select_impl::<O, _, _>(selection, filename, None, &mut |_, _| {})
+    }
+
+    /// Like `write_select`, but `X` and `layers` are read and written in chunks
+    /// of `chunk_size` selected rows, so that the selection is never loaded into
+    /// memory as a whole.
+    pub fn write_select_by_chunk<O, S, P>(&self, selection: S, filename: P, chunk_size: usize) -> Result<()>
+    where
+        O: Backend,
+        S: AsRef<[SelectInfoElem]>,
+        P: AsRef<Path>,
+    {
+        self.write_select_by_chunk_with_progress::<O, _, _, _>(selection, filename, chunk_size, |_, _| {})
+    }
+
+    /// Like `write_select_by_chunk`, but `progress(done_rows, total_rows)` is
+    /// called each time a chunk has been written. The counts are cumulative
+    /// over `X` and all layers, so `total_rows` is the number of selected rows
+    /// times the number of these elements.
+    pub fn write_select_by_chunk_with_progress<O, S, P, F>(
+        &self,
+        selection: S,
+        filename: P,
+        chunk_size: usize,
+        mut progress: F,
+    ) -> Result<()>
+    where
+        O: Backend,
+        S: AsRef<[SelectInfoElem]>,
+        P: AsRef<Path>,
+        F: FnMut(usize, usize),
+    {
+        ensure!(chunk_size > 0, "chunk_size must be positive");
+        self.write_select_impl::<O, _, _>(selection, filename, Some(chunk_size), &mut progress)
+    }
+
+    fn write_select_impl<O, S, P>(
+        &self,
+        selection: S,
+        filename: P,
+        chunk_size: Option<usize>,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<()>
     where
         O: Backend,
         S: AsRef<[SelectInfoElem]>,
@@ -289,14 +464,29 @@ impl<B: Backend> AnnData<B> {
         selection.as_ref()[1].bound_check(self.n_vars())
             .map_err(|e| anyhow!("AnnData var {}", e))?;
         let slice: SmallVec<[_; 3]> = selection.as_ref().iter().collect();
+        self.open_arrays()?;
         let file = O::create(filename)?;
-        let _obs_lock = self.n_obs.lock();
+        let obs_lock = self.n_obs.lock();
         let _vars_lock = self.n_vars.lock();
+
+        let n_rows = BoundedSelectInfoElem::new(slice[0], obs_lock.get()).len();
+        let n_elems = usize::from(!self.x.is_empty())
+            + self.layers.lock().as_ref().map_or(0, |x| x.len());
+        let total = n_rows * n_elems;
+        let mut done = 0;
         self.get_x()
             .lock()
             .as_mut()
-            .map(|x| x.export_select::<O, _>(slice.as_slice(), &file, "X"))
+            .map(|x| match chunk_size {
+                None => x.export_select::<O, _>(slice.as_slice(), &file, "X"),
+                Some(n) => x.export_select_by_chunk_with_progress::<O, _, _>(
+                    slice.as_slice(), n, &file, "X", |i| progress(done + i, total)
+                ),
+            })
             .transpose()?;
+        if !self.x.is_empty() {
+            done += n_rows;
+        }
 
         self.get_obs()
             .lock()
@@ -336,21 +526,132 @@ impl<B: Backend> AnnData<B> {
         self.layers()
             .lock()
             .as_mut()
-            .map(|x| x.export_select(slice.as_slice(), &file, "layers"))
+            .map(|x| match chunk_size {
+                None => x.export_select(slice.as_slice(), &file, "layers"),
+                Some(n) => {
+                    let group = file.create_group("layers")?;
+                    x.iter()?.try_for_each(|(k, v)| {
+                        v.inner().export_select_by_chunk_with_progress::<O, _, _>(
+                            slice.as_slice(), n, &group, k, |i| progress(done + i, total)
+                        )?;
+                        done += n_rows;
+                        Ok::<_, anyhow::Error>(())
+                    })
+                }
+            })
             .transpose()?;
+        file.flush()?;
         file.close()?;
         Ok(())
     }
 
+    /// Set the 'X' element with the given dataset options, e.g., the chunk shape.
+    /// The existing 'X' element is removed first.
+    pub fn set_x_with_config<D>(&self, data: D, config: WriteConfig) -> Result<()>
+    where
+        D: WriteArrayData + Into<ArrayData> + HasShape,
+    {
+        self.set_x_shape(&data.shape())?;
+        let transposed = self.is_x_transposed();
+        self.x.clear()?;
+        let new_elem = ArrayElem::try_from(data.write_with_config(&self.file, "X", config)?)?;
+        self.replace_x(new_elem, transposed)
+    }
+
+    /// Append rows to `X`, e.g., to ingest data in batches, extending the stored
+    /// datasets in place. The new rows must have as many columns as `X`, and
+    /// `n_obs` grows by the number of appended rows. `X` is created if it does
+    /// not exist yet.
+    ///
+    /// The names of the new rows are given by `obs_names`, which is required if
+    /// the existing rows have names. `obs` must not have columns, and `obsm`,
+    /// `obsp` and `layers` must be empty, as they would no longer match the
+    /// number of observations. If an error occurs, `X` is left unchanged.
+    pub fn append_x_rows<I, D>(&self, iter: I, obs_names: Option<Vec<String>>) -> Result<()>
+    where
+        I: Iterator<Item = D>,
+        D: ArrayChunk + Into<ArrayData>,
+    {
+        if self.x.is_empty() {
+            self.set_x_from_iter(iter)?;
+            if let Some(names) = obs_names {
+                self.set_obs_names(names.into_iter().collect())?;
+            }
+            return Ok(());
+        }
+
+        let mut obs_lock = self.n_obs.lock();
+        ensure!(
+            self.obs.lock().as_ref().map_or(true, |x| x.width() == 0),
+            "cannot append rows to X as obs has columns"
+        );
+        for (name, elem) in [("obsm", &self.obsm), ("obsp", &self.obsp), ("layers", &self.layers)] {
+            ensure!(
+                elem.lock().as_ref().map_or(true, |x| x.is_empty()),
+                "cannot append rows to X as {} is not empty", name
+            );
+        }
+        let old_names = self.obs_names();
+        let n_obs = obs_lock.get();
+        ensure!(
+            old_names.is_empty() || obs_names.is_some(),
+            "obs_names of the new rows are required as the existing rows have names"
+        );
+        ensure!(
+            obs_names.is_none() || n_obs == 0 || !old_names.is_empty(),
+            "cannot name the new rows as the existing rows have no names"
+        );
+
+        let mut x = self.x.inner();
+        x.append_rows(iter, obs_names.as_ref().map(|x| x.len()))?;
+        obs_lock.set(x.shape()[0]);
+        if let Some(names) = obs_names {
+            let index = old_names.into_iter().chain(names).collect();
+            let df = InnerDataFrameElem::new(&self.file, "obs", index, &DataFrame::empty())?;
+            self.obs.insert(df);
+        }
+        Ok(())
+    }
+
+    fn set_x_shape(&self, shape: &Shape) -> Result<()> {
+        ensure!(
+            shape.ndim() >= 2,
+            "X must be a N dimensional array, where N >= 2"
+        );
+        try_set_x_shape(&mut self.n_obs.lock(), &mut self.n_vars.lock(), shape)
+    }
+
     pub fn filename(&self) -> PathBuf {
         self.file.filename()
     }
 
+    /// Write X, or the given layer, to a Matrix Market file, which is
+    /// gzip-compressed if `filename` ends with `.gz`. The data is streamed in
+    /// chunks of rows. Use `writer::MMWriter` to also write the observation
+    /// and variable names.
+    pub fn write_mtx<P: AsRef<Path>>(&self, filename: P, layer: Option<&str>) -> Result<()> {
+        let writer = crate::writer::MMWriter::to_path(filename);
+        match layer {
+            Some(key) => writer.layer(key).finish(self),
+            None => writer.finish(self),
+        }
+    }
+
+    /// Write buffered data to disk. Data written by earlier calls, e.g.,
+    /// `set_x` or `obsm().add`, is durable once this returns, so a long-running
+    /// job can call it to checkpoint its progress. Writes may otherwise stay
+    /// in the backend's buffers until the file is closed; a crash before that
+    /// can leave the file corrupted. Writing to a new file, e.g., by `write`,
+    /// flushes the new file before returning.
+    pub fn flush(&self) -> Result<()> {
+        self.file.flush()
+    }
+
     pub fn close(self) -> Result<()> {
         macro_rules! close {
             ($($name:ident),*) => {
                 $(
-                self.$name.lock().as_ref().map(|x| x.values().for_each(|x| x.drop()));
+                self.$name.lock().as_ref().map(|x| x.opened().for_each(|x| x.drop()));
                 self.$name.drop();
                 )*
             };
@@ -362,10 +663,30 @@ impl<B: Backend> AnnData<B> {
         self.file.close()
     }
 
+    /// Open the arrays that have not been accessed yet. Opening an array checks
+    /// its shape, which requires the dimension locks, so this must be done
+    /// before they are taken.
+    fn open_arrays(&self) -> Result<()> {
+        for arrays in [&self.obsm, &self.obsp, &self.varm, &self.varp, &self.layers] {
+            arrays.lock().as_ref().map(|x| x.open_all()).transpose()?;
+        }
+        Ok(())
+    }
+
     pub fn subset<S>(&self, selection: S) -> Result<()>
     where
         S: AsRef<[SelectInfoElem]>,
     {
+        // Opening an array checks its shape, which requires the dimension locks.
+        // External links cannot be written, so they are checked before anything
+        // is modified.
+        for arrays in [&self.obsm, &self.obsp, &self.varm, &self.varp, &self.layers] {
+            if let Some(arrays) = arrays.lock().as_ref() {
+                if let Some((key, _)) = arrays.iter()?.find(|(_, x)| x.inner().is_external()) {
+                    bail!("cannot subset '{}' in place, which is an external link to another file", key);
+                }
+            }
+        }
         let mut obs_lock = self.n_obs.lock();
         let mut vars_lock = self.n_vars.lock();
         let slice = selection.as_ref();
@@ -375,6 +696,12 @@ impl<B: Backend> AnnData<B> {
         );
         let obs_ix = &slice[0];
         let var_ix = &slice[1];
+        if !obs_lock.is_empty() {
+            obs_ix.bound_check(obs_lock.get()).map_err(|e| anyhow!("AnnData obs {}", e))?;
+        }
+        if !vars_lock.is_empty() {
+            var_ix.bound_check(vars_lock.get()).map_err(|e| anyhow!("AnnData var {}", e))?;
+        }
 
         self.x
             .lock()
@@ -385,7 +712,7 @@ impl<B: Backend> AnnData<B> {
         self.obs
             .lock()
             .as_mut()
-            .map(|x| x.subset_axis(0, obs_ix))
+            .map(|x| x.subset_rows(obs_ix))
             .transpose()?;
         self.obsm
             .lock()
@@ -401,7 +728,7 @@ impl<B: Backend> AnnData<B> {
         self.var
             .lock()
             .as_mut()
-            .map(|x| x.subset_axis(0, var_ix))
+            .map(|x| x.subset_rows(var_ix))
             .transpose()?;
         self.varm
             .lock()
@@ -429,6 +756,54 @@ impl<B: Backend> AnnData<B> {
 
         Ok(())
     }
+
+    /// Reorder the observations in place, so that the `i`-th observation
+    /// becomes the `perm[i]`-th observation of the original data. X, obs, obsm,
+    /// obsp (both axes) and the layers are rewritten. `perm` must be a
+    /// permutation of `0..n_obs`.
+    pub fn reorder_obs(&self, perm: &[usize]) -> Result<()> {
+        let n_obs = self.n_obs();
+        ensure!(
+            perm.len() == n_obs,
+            "the permutation has length {}, but n_obs is {}",
+            perm.len(),
+            n_obs,
+        );
+        let mut seen = vec![false; n_obs];
+        for &i in perm {
+            ensure!(i < n_obs, "index {} in the permutation is out of bounds for n_obs = {}", i, n_obs);
+            ensure!(!seen[i], "index {} appears more than once in the permutation", i);
+            seen[i] = true;
+        }
+        self.subset([SelectInfoElem::from(perm), SelectInfoElem::full()])
+    }
+
+    /// Iterate over chunks of `chunk_size` rows of X, each paired with the
+    /// same rows of the given obs columns. Only the rows of the current chunk
+    /// are read from obs.
+    pub fn chunked_with_obs<T>(&self, chunk_size: usize, columns: &[String]) -> Result<ChunkedArrayWithColumns<B, T>>
+    where
+        T: Into<ArrayData> + TryFrom<ArrayData> + ReadArrayData + Clone,
+    {
+        ensure!(!self.x.is_empty(), "X is empty");
+        if !columns.is_empty() {
+            let obs = self.obs.lock();
+            let names = obs.as_ref().context("obs is empty")?.get_column_names();
+            if let Some(name) = columns.iter().find(|x| !names.contains(*x)) {
+                bail!("column '{}' not found in obs", name);
+            }
+        }
+        Ok(ChunkedArrayWithColumns::new(self.x.clone(), self.obs.clone(), columns.to_vec(), chunk_size))
+    }
+}
+
+impl AnnData<InMemory> {
+    /// Create an empty AnnData in an in-memory file that has no path. The data
+    /// is freed when the object is dropped. `AnnData::new` takes a path for
+    /// every backend, which for `InMemory` names a file that can be reopened.
+    pub fn in_memory() -> Result<Self> {
+        Self::create(MemFile::new())
+    }
 }
 
 impl<B: Backend> AnnDataOp for AnnData<B> {
@@ -445,19 +820,14 @@ impl<B: Backend> AnnDataOp for AnnData<B> {
     fn set_x_from_iter<I: Iterator<Item = D>, D: ArrayChunk>(&self, iter: I) -> Result<()> {
         let mut obs_lock = self.n_obs.lock();
         let mut vars_lock = self.n_vars.lock();
+        let transposed = self.is_x_transposed();
         self.del_x()?;
         let new_elem =
             ArrayElem::try_from(ArrayChunk::write_by_chunk(iter, &self.file, "X")?)?;
         let shape = new_elem.inner().shape().clone();
 
-        match obs_lock
-            .try_set(shape[0])
-            .and(vars_lock.try_set(shape[1]))
-        {
-            Ok(_) => {
-                self.x.swap(&new_elem);
-                Ok(())
-            }
+        match try_set_x_shape(&mut obs_lock, &mut vars_lock, &shape) {
+            Ok(_) => self.replace_x(new_elem, transposed),
             Err(e) => {
                 new_elem.clear()?;
                 Err(e)
@@ -466,19 +836,12 @@ impl<B: Backend> AnnDataOp for AnnData<B> {
     }
 
     fn set_x<D: WriteArrayData + Into<ArrayData> + HasShape>(&self, data: D) -> Result<()> {
-        let shape = data.shape();
-        ensure!(
-            shape.ndim() >= 2,
-            "X must be a N dimensional array, where N >= 2"
-        );
-        self.n_obs.try_set(shape[0])?;
-        self.n_vars.try_set(shape[1])?;
-
+        self.set_x_shape(&data.shape())?;
         if !self.x.is_empty() {
             self.x.inner().save(data)?;
         } else {
             let new_elem = ArrayElem::try_from(data.write(&self.file, "X")?)?;
-            self.x.swap(&new_elem);
+            self.replace_x(new_elem, false)?;
         }
         Ok(())
     }
@@ -692,4 +1055,21 @@ impl<B: Backend> AnnDataOp for AnnData<B> {
     fn del_layers(&self) -> Result<()> {
         self.layers.clear()
     }
-}
\ No newline at end of file
+}
+
+/// Set `n_obs` and `n_vars` from the shape of a new X. Once a dimension has
+/// been established, e.g., by obs, var, obsm or a previous X, the new X must
+/// agree with it. Neither dimension is modified if the shape is rejected, so
+/// that the object is never left in an inconsistent state.
+fn try_set_x_shape(n_obs: &mut DimLock, n_vars: &mut DimLock, shape: &Shape) -> Result<()> {
+    let agrees = |dim: &DimLock, n: usize| dim.is_empty() || dim.get() == n;
+    ensure!(
+        agrees(n_obs, shape[0]) && agrees(n_vars, shape[1]),
+        "cannot set X with shape {}, expecting {} x {} (n_obs x n_vars)",
+        shape,
+        n_obs.get(),
+        n_vars.get(),
+    );
+    n_obs.try_set(shape[0])?;
+    n_vars.try_set(shape[1])
+}
@@ -0,0 +1,219 @@
+use crate::{
+    anndata::AnnData,
+    backend::Backend,
+    data::{ArrayData, Data, DataFrameIndex, HasShape},
+    traits::AnnDataOp,
+};
+
+use anyhow::{bail, Result};
+use polars::prelude::DataFrame;
+use std::{marker::PhantomData, path::Path};
+
+/// Collect the elements of an AnnData object in memory and write them to a new
+/// file in one pass. This mirrors the keyword arguments of the Python
+/// constructor:
+///
+/// ```ignore
+/// let adata = AnnDataBuilder::<H5>::new()
+///     .x(x)
+///     .obs(obs)
+///     .obsm("X_pca", pca)
+///     .build("data.h5ad")?;
+/// ```
+///
+/// The shapes of all elements are checked before the file is created, so an
+/// inconsistent builder does not leave a partially written file behind.
+pub struct AnnDataBuilder<B: Backend> {
+    x: Option<ArrayData>,
+    obs: Option<DataFrame>,
+    obs_names: Option<DataFrameIndex>,
+    var: Option<DataFrame>,
+    var_names: Option<DataFrameIndex>,
+    obsm: Vec<(String, ArrayData)>,
+    obsp: Vec<(String, ArrayData)>,
+    varm: Vec<(String, ArrayData)>,
+    varp: Vec<(String, ArrayData)>,
+    layers: Vec<(String, ArrayData)>,
+    uns: Vec<(String, Data)>,
+    backend: PhantomData<B>,
+}
+
+impl<B: Backend> Default for AnnDataBuilder<B> {
+    fn default() -> Self {
+        Self {
+            x: None,
+            obs: None,
+            obs_names: None,
+            var: None,
+            var_names: None,
+            obsm: Vec::new(),
+            obsp: Vec::new(),
+            varm: Vec::new(),
+            varp: Vec::new(),
+            layers: Vec::new(),
+            uns: Vec::new(),
+            backend: PhantomData,
+        }
+    }
+}
+
+impl<B: Backend> AnnDataBuilder<B> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn x<D: Into<ArrayData>>(mut self, data: D) -> Self {
+        self.x = Some(data.into());
+        self
+    }
+
+    pub fn obs(mut self, obs: DataFrame) -> Self {
+        self.obs = Some(obs);
+        self
+    }
+
+    pub fn obs_names(mut self, names: DataFrameIndex) -> Self {
+        self.obs_names = Some(names);
+        self
+    }
+
+    pub fn var(mut self, var: DataFrame) -> Self {
+        self.var = Some(var);
+        self
+    }
+
+    pub fn var_names(mut self, names: DataFrameIndex) -> Self {
+        self.var_names = Some(names);
+        self
+    }
+
+    pub fn obsm<D: Into<ArrayData>>(mut self, key: &str, data: D) -> Self {
+        self.obsm.push((key.to_string(), data.into()));
+        self
+    }
+
+    pub fn obsp<D: Into<ArrayData>>(mut self, key: &str, data: D) -> Self {
+        self.obsp.push((key.to_string(), data.into()));
+        self
+    }
+
+    pub fn varm<D: Into<ArrayData>>(mut self, key: &str, data: D) -> Self {
+        self.varm.push((key.to_string(), data.into()));
+        self
+    }
+
+    pub fn varp<D: Into<ArrayData>>(mut self, key: &str, data: D) -> Self {
+        self.varp.push((key.to_string(), data.into()));
+        self
+    }
+
+    pub fn layer<D: Into<ArrayData>>(mut self, key: &str, data: D) -> Self {
+        self.layers.push((key.to_string(), data.into()));
+        self
+    }
+
+    pub fn uns<D: Into<Data>>(mut self, key: &str, data: D) -> Self {
+        self.uns.push((key.to_string(), data.into()));
+        self
+    }
+
+    /// Check that all elements agree on the number of observations and variables.
+    fn check_shapes(&self) -> Result<()> {
+        let mut n_obs = AxisLen::new("observations");
+        let mut n_vars = AxisLen::new("variables");
+        if let Some(x) = &self.x {
+            let shape = x.shape();
+            if shape.ndim() != 2 {
+                bail!("X must be two-dimensional, found shape {}", shape);
+            }
+            n_obs.set("X", shape[0])?;
+            n_vars.set("X", shape[1])?;
+        }
+        if let Some(obs) = self.obs.as_ref().filter(|df| df.height() != 0) {
+            n_obs.set("obs", obs.height())?;
+        }
+        if let Some(names) = &self.obs_names {
+            n_obs.set("obs_names", names.len())?;
+        }
+        if let Some(var) = self.var.as_ref().filter(|df| df.height() != 0) {
+            n_vars.set("var", var.height())?;
+        }
+        if let Some(names) = &self.var_names {
+            n_vars.set("var_names", names.len())?;
+        }
+        for (key, data) in &self.obsm {
+            n_obs.set(&format!("obsm/{}", key), data.shape()[0])?;
+        }
+        for (key, data) in &self.obsp {
+            let shape = data.shape();
+            n_obs.set(&format!("obsp/{}", key), shape[0])?;
+            n_obs.set(&format!("obsp/{}", key), shape[1])?;
+        }
+        for (key, data) in &self.varm {
+            n_vars.set(&format!("varm/{}", key), data.shape()[0])?;
+        }
+        for (key, data) in &self.varp {
+            let shape = data.shape();
+            n_vars.set(&format!("varp/{}", key), shape[0])?;
+            n_vars.set(&format!("varp/{}", key), shape[1])?;
+        }
+        for (key, data) in &self.layers {
+            let shape = data.shape();
+            n_obs.set(&format!("layers/{}", key), shape[0])?;
+            n_vars.set(&format!("layers/{}", key), shape[1])?;
+        }
+        Ok(())
+    }
+
+    /// Create a new file at `filename` and write all the elements to it.
+    pub fn build<P: AsRef<Path>>(self, filename: P) -> Result<AnnData<B>> {
+        self.check_shapes()?;
+        let adata = AnnData::<B>::new(filename)?;
+        if let Some(x) = self.x {
+            adata.set_x(x)?;
+        }
+        if let Some(obs) = self.obs {
+            adata.set_obs(obs)?;
+        }
+        if let Some(names) = self.obs_names {
+            adata.set_obs_names(names)?;
+        }
+        if let Some(var) = self.var {
+            adata.set_var(var)?;
+        }
+        if let Some(names) = self.var_names {
+            adata.set_var_names(names)?;
+        }
+        adata.set_obsm(self.obsm.into_iter())?;
+        adata.set_obsp(self.obsp.into_iter())?;
+        adata.set_varm(self.varm.into_iter())?;
+        adata.set_varp(self.varp.into_iter())?;
+        adata.set_layers(self.layers.into_iter())?;
+        adata.set_uns(self.uns.into_iter())?;
+        Ok(adata)
+    }
+}
+
+/// The length of an axis, together with the element it was first taken from.
+struct AxisLen {
+    name: &'static str,
+    len: Option<(String, usize)>,
+}
+
+impl AxisLen {
+    fn new(name: &'static str) -> Self {
+        Self { name, len: None }
+    }
+
+    fn set(&mut self, element: &str, n: usize) -> Result<()> {
+        match &self.len {
+            Some((first, len)) if *len != n => bail!(
+                "'{}' has {} {}, but '{}' has {}",
+                element, n, self.name, first, len
+            ),
+            Some(_) => {}
+            None => self.len = Some((element.to_string(), n)),
+        }
+        Ok(())
+    }
+}
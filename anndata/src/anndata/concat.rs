@@ -0,0 +1,274 @@
+use crate::{
+    anndata::AnnData,
+    backend::Backend,
+    data::*,
+    traits::{AnnDataOp, ArrayElemOp, AxisArraysOp},
+};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use indexmap::IndexSet;
+use nalgebra_sparse::{CscMatrix, CsrMatrix};
+use ndarray::{ArrayD, Axis};
+use polars::prelude::DataFrame;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// How to align the variables of the AnnData objects being concatenated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Join {
+    /// Keep only the variables present in all objects.
+    Inner,
+    /// Keep the union of all variables. Missing entries are filled with zeros.
+    Outer,
+}
+
+impl<B: Backend> AnnData<B> {
+    /// Concatenate AnnData objects along the observation axis and save the
+    /// result in a new file.
+    ///
+    /// Variables are aligned by `var_names` according to `join`. `X`, `layers`
+    /// and `obsm` are stacked, keeping only the keys present in all objects.
+    /// `obs` keeps the columns shared by all objects. `varm` items must be
+    /// identical across objects and are only kept when no variable is missing
+    /// in any object. Variable annotations are taken from the first object
+    /// when using `Join::Inner`; otherwise only `var_names` are set.
+    /// `obsp`, `varp` and `uns` are not copied.
+    pub fn concat<P: AsRef<Path>>(adatas: &[&AnnData<B>], out: P, join: Join) -> Result<Self> {
+        ensure!(!adatas.is_empty(), "cannot concatenate an empty list of AnnData objects");
+
+        // For each object, the column in the object corresponding to each output variable.
+        let (var_names, mappings) = align_vars(adatas, join)?;
+        let adata = AnnData::new(out)?;
+
+        let xs = adatas
+            .iter()
+            .zip(&mappings)
+            .map(|(x, cols)| x.x().get::<ArrayData>()?.map(|x| reindex_columns(x, cols)).transpose())
+            .collect::<Result<Vec<_>>>()?;
+        if xs.iter().any(Option::is_some) {
+            let xs = xs
+                .into_iter()
+                .collect::<Option<Vec<_>>>()
+                .context("cannot concatenate: X is missing in some of the AnnData objects")?;
+            adata.set_x(ArrayData::vstack(xs.into_iter())?)?;
+        }
+
+        // obs
+        let obs = adatas.iter().map(|x| x.read_obs()).collect::<Result<Vec<_>>>()?;
+        let columns = common_keys(obs.iter().map(|x| {
+            x.get_column_names().into_iter().map(str::to_string).collect()
+        }));
+        if !columns.is_empty() {
+            let df = obs.iter().try_fold(None, |acc: Option<DataFrame>, x| -> Result<_> {
+                let x = x.select(&columns)?;
+                Ok(Some(match acc {
+                    None => x,
+                    Some(mut df) => {
+                        df.vstack_mut(&x)?;
+                        df
+                    }
+                }))
+            })?;
+            adata.set_obs(df.unwrap())?;
+        }
+        if adatas.iter().all(|x| x.obs_names().len() == x.n_obs()) {
+            let obs_names: DataFrameIndex = adatas.iter().flat_map(|x| x.obs_names()).collect();
+            if !obs_names.is_empty() {
+                adata.set_obs_names(obs_names)?;
+            }
+        }
+
+        // var
+        if let Some(var_names) = var_names {
+            if join == Join::Inner {
+                let var = adatas[0].read_var()?;
+                if var.width() > 0 {
+                    let rows: Vec<usize> = mappings[0].iter().map(|x| x.unwrap()).collect();
+                    adata.set_var(ArrayOp::select(&var, &[rows.into(), SelectInfoElem::full()]))?;
+                }
+            }
+            if !var_names.is_empty() {
+                adata.set_var_names(var_names.into_iter().collect())?;
+            }
+        }
+
+        for key in common_keys(adatas.iter().map(|x| x.obsm().keys())) {
+            let items = adatas
+                .iter()
+                .map(|x| x.obsm().get_item::<ArrayData>(&key)?.context("missing obsm item"))
+                .collect::<Result<Vec<_>>>()?;
+            adata.obsm().add(&key, ArrayData::vstack(items.into_iter())?)?;
+        }
+
+        for key in common_keys(adatas.iter().map(|x| x.layers().keys())) {
+            let items = adatas
+                .iter()
+                .zip(&mappings)
+                .map(|(x, cols)| {
+                    let item = x.layers().get_item::<ArrayData>(&key)?.context("missing layer")?;
+                    reindex_columns(item, cols)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            adata.layers().add(&key, ArrayData::vstack(items.into_iter())?)?;
+        }
+
+        if mappings.iter().all(|cols| cols.iter().all(Option::is_some)) {
+            for key in common_keys(adatas.iter().map(|x| x.varm().keys())) {
+                let mut items = adatas.iter().zip(&mappings).map(|(x, rows)| -> Result<ArrayData> {
+                    let item = x.varm().get_item::<ArrayData>(&key)?.context("missing varm item")?;
+                    let rows: Vec<usize> = rows.iter().map(|x| x.unwrap()).collect();
+                    Ok(select_axis(&item, 0, rows))
+                });
+                let first = items.next().unwrap()?;
+                for item in items {
+                    ensure!(item? == first, "varm['{}'] differs between AnnData objects", key);
+                }
+                adata.varm().add(&key, first)?;
+            }
+        }
+
+        Ok(adata)
+    }
+}
+
+/// Compute the output variable names and, for each object, the index of every
+/// output variable in that object. Objects without `var_names` are aligned by
+/// position and must have the same number of variables.
+#[allow(clippy::type_complexity)]
+fn align_vars<B: Backend>(
+    adatas: &[&AnnData<B>],
+    join: Join,
+) -> Result<(Option<Vec<String>>, Vec<Vec<Option<usize>>>)> {
+    if !adatas.iter().all(|x| x.var_names().len() == x.n_vars()) {
+        let n_vars = adatas[0].n_vars();
+        ensure!(
+            adatas.iter().all(|x| x.n_vars() == n_vars),
+            "var_names are required to concatenate AnnData objects with different numbers of variables"
+        );
+        return Ok((None, vec![(0..n_vars).map(Some).collect(); adatas.len()]));
+    }
+
+    let indices: Vec<HashMap<String, usize>> = adatas
+        .iter()
+        .map(|x| x.var_names().into_iter().enumerate().map(|(i, x)| (x, i)).collect())
+        .collect();
+    let mut names: IndexSet<String> = adatas[0].var_names().into_iter().collect();
+    adatas.iter().zip(&indices).skip(1).for_each(|(adata, index)| match join {
+        Join::Inner => names.retain(|x| index.contains_key(x)),
+        Join::Outer => names.extend(adata.var_names()),
+    });
+    let mappings = indices
+        .iter()
+        .map(|index| names.iter().map(|x| index.get(x).copied()).collect())
+        .collect();
+    Ok((Some(names.into_iter().collect()), mappings))
+}
+
+/// Keys present in all lists, in the order of the first list.
+fn common_keys<I: Iterator<Item = Vec<String>>>(mut keys: I) -> Vec<String> {
+    let first = keys.next().unwrap_or_default();
+    let others: Vec<HashSet<String>> = keys.map(|x| x.into_iter().collect()).collect();
+    first
+        .into_iter()
+        .filter(|k| others.iter().all(|x| x.contains(k)))
+        .collect()
+}
+
+fn select_axis(data: &ArrayData, axis: usize, indices: Vec<usize>) -> ArrayData {
+    let mut select = SelectInfo::all(data.shape().ndim());
+    select.0[axis] = indices.into();
+    data.select(select.as_ref())
+}
+
+/// Rearrange the columns of `data` so that the j-th output column is the
+/// `cols[j]`-th input column, or zeros if `cols[j]` is `None`.
+fn reindex_columns(data: ArrayData, cols: &[Option<usize>]) -> Result<ArrayData> {
+    if let Some(indices) = cols.iter().copied().collect::<Option<Vec<_>>>() {
+        return Ok(select_axis(&data, 1, indices));
+    }
+
+    macro_rules! reindex {
+        ($ty:ident, $data:expr, $fun:ident) => {
+            match $data {
+                $ty::I8(x) => $fun(&x, cols)?.into(),
+                $ty::I16(x) => $fun(&x, cols)?.into(),
+                $ty::I32(x) => $fun(&x, cols)?.into(),
+                $ty::I64(x) => $fun(&x, cols)?.into(),
+                $ty::U8(x) => $fun(&x, cols)?.into(),
+                $ty::U16(x) => $fun(&x, cols)?.into(),
+                $ty::U32(x) => $fun(&x, cols)?.into(),
+                $ty::U64(x) => $fun(&x, cols)?.into(),
+                $ty::Usize(x) => $fun(&x, cols)?.into(),
+                $ty::F16(x) => $fun(&x, cols)?.into(),
+                $ty::F32(x) => $fun(&x, cols)?.into(),
+                $ty::F64(x) => $fun(&x, cols)?.into(),
+                $ty::Bool(x) => $fun(&x, cols)?.into(),
+                $ty::String(x) => $fun(&x, cols)?.into(),
+                #[allow(unreachable_patterns)]
+                _ => bail!("cannot fill missing variables of categorical arrays"),
+            }
+        };
+    }
+
+    let data = match data {
+        ArrayData::Array(x) => reindex!(DynArray, x, reindex_array),
+        ArrayData::CsrMatrix(x) => reindex!(DynCsrMatrix, x, reindex_csr),
+        ArrayData::CscMatrix(x) => reindex!(DynCscMatrix, x, reindex_csc),
+        ArrayData::CsrNonCanonical(_) => bail!("cannot fill missing variables of non-canonical CSR matrices"),
+        ArrayData::RaggedArray(_) => bail!("cannot fill missing variables of ragged arrays"),
+        ArrayData::RecordArray(_) => bail!("cannot fill missing variables of record arrays"),
+        ArrayData::DataFrame(_) => bail!("cannot fill missing variables of dataframes"),
+    };
+    Ok(data)
+}
+
+fn reindex_array<T: Clone + Default>(arr: &ArrayD<T>, cols: &[Option<usize>]) -> Result<ArrayD<T>> {
+    let mut shape = arr.shape().to_vec();
+    shape[1] = cols.len();
+    let mut result = ArrayD::default(shape);
+    cols.iter().enumerate().for_each(|(j, c)| if let Some(c) = c {
+        result.index_axis_mut(Axis(1), j).assign(&arr.index_axis(Axis(1), *c));
+    });
+    Ok(result)
+}
+
+fn reindex_csr<T: Clone>(csr: &CsrMatrix<T>, cols: &[Option<usize>]) -> Result<CsrMatrix<T>> {
+    let mut new_cols = vec![None; csr.ncols()];
+    cols.iter().enumerate().for_each(|(j, c)| if let Some(c) = c {
+        new_cols[*c] = Some(j);
+    });
+    let mut indptr = Vec::with_capacity(csr.nrows() + 1);
+    let mut indices = Vec::new();
+    let mut data = Vec::new();
+    indptr.push(0);
+    csr.row_iter().for_each(|row| {
+        let mut entries: Vec<_> = row.col_indices().iter().zip(row.values())
+            .filter_map(|(c, v)| new_cols[*c].map(|j| (j, v.clone())))
+            .collect();
+        entries.sort_by_key(|x| x.0);
+        entries.into_iter().for_each(|(j, v)| {
+            indices.push(j);
+            data.push(v);
+        });
+        indptr.push(indices.len());
+    });
+    CsrMatrix::try_from_csr_data(csr.nrows(), cols.len(), indptr, indices, data)
+        .map_err(|e| anyhow!("{}", e))
+}
+
+fn reindex_csc<T: Clone>(csc: &CscMatrix<T>, cols: &[Option<usize>]) -> Result<CscMatrix<T>> {
+    let mut indptr = Vec::with_capacity(cols.len() + 1);
+    let mut indices = Vec::new();
+    let mut data = Vec::new();
+    indptr.push(0);
+    cols.iter().for_each(|c| {
+        if let Some(c) = c {
+            let col = csc.col(*c);
+            indices.extend_from_slice(col.row_indices());
+            data.extend_from_slice(col.values());
+        }
+        indptr.push(indices.len());
+    });
+    CscMatrix::try_from_csc_data(csc.nrows(), cols.len(), indptr, indices, data)
+        .map_err(|e| anyhow!("{}", e))
+}
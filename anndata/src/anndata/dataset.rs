@@ -1,8 +1,8 @@
 use crate::{
-    traits::{AnnDataOp, ElemCollectionOp},
+    traits::{AnnDataOp, AxisArraysOp, ElemCollectionOp},
     anndata::AnnData,
     backend::Backend,
-    container::{Slot, Dim, Axis, AxisArrays, StackedArrayElem, StackedAxisArrays, StackedDataFrame, ElemCollection},
+    container::{Slot, Dim, Axis, AxisArrays, InnerDataFrameElem, StackedArrayElem, StackedAxisArrays, StackedDataFrame, ElemCollection},
     data::*,
     data::index::VecVecIndex,
 };
@@ -10,7 +10,7 @@ use crate::{
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use indexmap::map::IndexMap;
 use itertools::Itertools;
-use polars::prelude::{DataFrame, NamedFrom, Series};
+use polars::prelude::{DataFrame, DataType, NamedFrom, Series};
 use rayon::iter::{IndexedParallelIterator, ParallelIterator};
 use std::{collections::{HashSet, HashMap}, path::Path};
 
@@ -191,6 +191,26 @@ impl<B: Backend> AnnDataSet<B> {
         })
     }
 
+    /// Make the obs_names of the dataset unique by prefixing the obs_names of
+    /// each component with its key followed by `sep`, e.g., `sample1_AAACGG`.
+    /// Only the annotation file is modified; the obs_names of the components
+    /// are left untouched. The names are recomputed from the components, so
+    /// this can be called again after `add_component`.
+    pub fn prefix_obs_names(&self, sep: &str) -> Result<()> {
+        let anndatas = self.anndatas.inner();
+        let mut obs_names = Vec::with_capacity(anndatas.n_obs);
+        for (key, adata) in anndatas.iter() {
+            let names = adata.obs_names();
+            ensure!(
+                names.len() == adata.n_obs(),
+                "cannot prefix obs_names: component '{}' has no obs_names",
+                key,
+            );
+            obs_names.extend(names.into_iter().map(|x| format!("{}{}{}", key, sep, x)));
+        }
+        self.annotation.set_obs_names(obs_names.into_iter().collect())
+    }
+
     pub fn open(file: B::File, adata_files_: Option<HashMap<String, String>>) -> Result<Self> {
         let annotation: AnnData<B> = AnnData::open(file)?;
         let file_path = annotation
@@ -291,13 +311,45 @@ impl<B: Backend> AnnDataSet<B> {
         Ok(obs_idx_order)
     }
 
-    /// Convert AnnDataSet to AnnData object
-    pub fn to_adata<O: Backend, P: AsRef<Path>>(&self, out: P, copy_x: bool) -> Result<AnnData<O>> {
+    /// Collapse the AnnDataSet into a single AnnData object saved in `out`.
+    ///
+    /// The annotations of the AnnDataSet are copied, and the data of the underlying
+    /// AnnData objects are concatenated: `X` and `obsm` are written `chunk_size`
+    /// rows at a time, and the `obs` columns shared by all objects are appended to
+    /// the `obs` of the AnnDataSet.
+    pub fn to_adata<O: Backend, P: AsRef<Path>>(&self, out: P, chunk_size: usize) -> Result<AnnData<O>> {
         self.annotation.write::<O, _>(&out)?;
         let adata = AnnData::open(O::open_rw(&out)?)?;
-        if copy_x {
-            adata
-                .set_x_from_iter::<_, ArrayData>(self.anndatas.inner().x.chunked(500).map(|x| x.0))?;
+        if adata.uns().keys().iter().any(|k| k == "AnnDataSet") {
+            adata.uns().remove("AnnDataSet")?;
+        }
+
+        let anndatas = self.anndatas.inner();
+        if !anndatas.x.is_empty() {
+            adata.set_x_from_iter::<_, ArrayData>(anndatas.x.chunked(chunk_size).map(|x| x.0))?;
+        }
+
+        let stacked_obs = anndatas.obs.data()?;
+        if stacked_obs.width() > 0 {
+            let mut obs = adata.read_obs()?;
+            for column in stacked_obs.get_columns() {
+                if obs.get_column_names().contains(&column.name()) {
+                    continue;
+                }
+                if obs.width() == 0 {
+                    obs = DataFrame::new(vec![column.clone()])?;
+                } else {
+                    obs.with_column(column.clone())?;
+                }
+            }
+            adata.set_obs(obs)?;
+        }
+
+        for key in (&anndatas.obsm).keys() {
+            if !adata.obsm().keys().contains(&key) {
+                let elem = (&anndatas.obsm).get(&key).unwrap();
+                adata.obsm().add_iter::<_, ArrayData>(&key, elem.chunked(chunk_size).map(|x| x.0))?;
+            }
         }
         Ok(adata)
     }
@@ -329,6 +381,112 @@ impl<B: Backend> AnnDataSet<B> {
         Ok(self.annotation)
     }
 
+    /// Add an AnnData object to the dataset under the key `name`. Its
+    /// observations are appended after those of the existing components, and
+    /// its `var_names` must match those of the dataset.
+    ///
+    /// The `obs` of the dataset is extended: the column holding the component
+    /// keys is filled with `name` and the other columns are taken from the `obs`
+    /// of the new component. The file location of the new component is recorded
+    /// so that it is found when the dataset is opened again.
+    pub fn add_component<S: ToString>(&self, name: S, adata: AnnData<B>) -> Result<()> {
+        let name = name.to_string();
+        let mut anndatas = self.anndatas.inner();
+        ensure!(!anndatas.elems.contains_key(&name), "component '{}' already exists", name);
+        ensure!(
+            adata.n_vars() == anndatas.n_vars,
+            "cannot add component '{}': expecting {} variables, found {}",
+            name, anndatas.n_vars, adata.n_vars(),
+        );
+        let var_names = self.annotation.var_names();
+        ensure!(
+            var_names.is_empty() || adata.var_names() == var_names,
+            "cannot add component '{}': var names mismatch",
+            name,
+        );
+        ensure!(
+            self.annotation.get_x().is_empty()
+                && self.annotation.obsm().keys().is_empty()
+                && self.annotation.obsp().keys().is_empty()
+                && self.annotation.layers().keys().is_empty(),
+            "cannot add components to an AnnDataSet having X, obsm, obsp or layers"
+        );
+
+        // Extend the obs of the annotation.
+        let obs = self.annotation.read_obs()?;
+        let key_column = {
+            let keys = anndatas
+                .iter()
+                .flat_map(|(k, v)| std::iter::repeat(k.as_str()).take(v.n_obs()));
+            obs.get_columns()
+                .iter()
+                .find(|x| x.utf8().map_or(false, |x| x.into_iter().eq(keys.clone().map(Some))))
+                .map(|x| x.name().to_string())
+        };
+        let mut new_obs = adata.read_obs()?;
+        let columns = obs
+            .get_columns()
+            .iter()
+            .map(|col| {
+                let new_col = if key_column.as_deref() == Some(col.name()) {
+                    Series::new(col.name(), vec![name.clone(); adata.n_obs()])
+                } else {
+                    new_obs
+                        .drop_in_place(col.name())
+                        .with_context(|| format!("cannot add component '{}': obs column '{}' is missing", name, col.name()))?
+                };
+                append_series(col, &new_col)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let n_obs = anndatas.n_obs + adata.n_obs();
+        let obs_names = self.annotation.obs_names();
+        let index: DataFrameIndex = if obs_names.len() == anndatas.n_obs && adata.obs_names().len() == adata.n_obs() {
+            obs_names.into_iter().chain(adata.obs_names()).collect()
+        } else {
+            n_obs.into()
+        };
+
+        let mut file_paths = read_anndata_locations(&self.annotation)?;
+        file_paths.insert(name.clone(), adata.filename().display().to_string());
+
+        anndatas.elems.insert(name, adata);
+        if let Err(e) = anndatas.restack() {
+            anndatas.elems.pop();
+            return Err(e);
+        }
+
+        let mut n_obs_lock = self.annotation.n_obs.lock();
+        if !self.annotation.obs.is_empty() {
+            let df = InnerDataFrameElem::new(&self.annotation.file, "obs", index, &DataFrame::new(columns)?)?;
+            self.annotation.obs.insert(df);
+        }
+        n_obs_lock.set(n_obs);
+        write_anndata_locations(&self.annotation, file_paths)
+    }
+
+    /// Remove the component `name` from the dataset, together with its
+    /// observations in the annotations of the dataset. The removed AnnData
+    /// object is returned.
+    pub fn remove_component(&self, name: &str) -> Result<AnnData<B>> {
+        let mut anndatas = self.anndatas.inner();
+        let i = anndatas.elems.get_index_of(name).with_context(|| format!("component '{}' does not exist", name))?;
+        ensure!(anndatas.len() > 1, "cannot remove the only component of an AnnDataSet");
+
+        let start = anndatas.elems.values().take(i).map(|x| x.n_obs()).sum::<usize>();
+        let end = start + anndatas.elems[i].n_obs();
+        let rows: SelectInfoElem = (0..start).chain(end..anndatas.n_obs).collect::<Vec<_>>().into();
+        self.annotation.subset([rows, SelectInfoElem::full()])?;
+
+        let mut file_paths = read_anndata_locations(&self.annotation)?;
+        file_paths.shift_remove(name);
+        write_anndata_locations(&self.annotation, file_paths)?;
+
+        let adata = anndatas.elems.shift_remove_index(i).unwrap().1;
+        anndatas.restack()?;
+        Ok(adata)
+    }
+
     pub fn close(self) -> Result<()> {
         self.annotation.close()?;
         for ann in self.anndatas.extract().unwrap().elems.into_values() {
@@ -338,6 +496,50 @@ impl<B: Backend> AnnDataSet<B> {
     }
 }
 
+/// The file locations of the AnnData objects recorded in the annotations.
+fn read_anndata_locations<B: Backend>(ann: &AnnData<B>) -> Result<IndexMap<String, String>> {
+    let df: DataFrame = ann
+        .uns().get_item("AnnDataSet")?
+        .context("key 'AnnDataSet' is not present")?;
+    let keys = df.column("keys")?.utf8()?;
+    let filenames = df.column("file_path")?.utf8()?;
+    Ok(keys
+        .into_iter()
+        .zip(filenames)
+        .map(|(k, v)| (k.unwrap().to_string(), v.unwrap().to_string()))
+        .collect())
+}
+
+fn write_anndata_locations<B: Backend>(
+    ann: &AnnData<B>,
+    locations: IndexMap<String, String>,
+) -> Result<()> {
+    let (keys, filenames): (Vec<_>, Vec<_>) = locations.into_iter().unzip();
+    let data = DataFrame::new(vec![
+        Series::new("keys", keys),
+        Series::new("file_path", filenames),
+    ])?;
+    ann.uns().add("AnnDataSet", data)?;
+    Ok(())
+}
+
+/// Concatenate two columns. Categorical columns are merged by their categories.
+fn append_series(a: &Series, b: &Series) -> Result<Series> {
+    let series = match a.dtype() {
+        DataType::Categorical(_) => {
+            let mut series = a.cast(&DataType::Utf8)?;
+            series.append(&b.cast(&DataType::Utf8)?)?;
+            series.cast(&DataType::Categorical(None))?
+        }
+        dtype => {
+            let mut series = a.clone();
+            series.append(&b.cast(dtype)?)?;
+            series
+        }
+    };
+    Ok(series)
+}
+
 fn update_anndata_locations<B: Backend>(
     ann: &AnnData<B>,
     new_locations: HashMap<String, String>,
@@ -519,6 +721,22 @@ impl<B: Backend> StackedAnnData<B> {
     {
         let adatas: IndexMap<String, AnnData<B>> =
             iter.into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+        let (x, obs, obsm) = Self::stack(&adatas)?;
+        Ok(Self {
+            index: adatas.values().map(|x| x.n_obs()).collect(),
+            n_obs: adatas.values().map(|x| x.n_obs()).sum(),
+            n_vars: adatas.values().next().unwrap().n_vars(),
+            elems: adatas,
+            x,
+            obs,
+            obsm,
+        })
+    }
+
+    /// Stack X, obs and obsm of the AnnData objects.
+    fn stack(
+        adatas: &IndexMap<String, AnnData<B>>,
+    ) -> Result<(StackedArrayElem<B>, StackedDataFrame<B>, StackedAxisArrays<B>)> {
         ensure!(!adatas.is_empty(), "no AnnData objects to stack");
 
         if let Some((_, first)) = adatas.first() {
@@ -545,16 +763,20 @@ impl<B: Backend> StackedAnnData<B> {
             let arrays: Vec<AxisArrays<_>> = adatas.values().map(|x| x.obsm.clone()).collect();
             StackedAxisArrays::new(Axis::Row, arrays)?
         };
-
-        Ok(Self {
-            index: adatas.values().map(|x| x.n_obs()).collect(),
-            n_obs: adatas.values().map(|x| x.n_obs()).sum(),
-            n_vars: adatas.values().next().unwrap().n_vars(),
-            elems: adatas,
-            x,
-            obs,
-            obsm,
-        })
+        Ok((x, obs, obsm))
+    }
+
+    /// Restack the AnnData objects after they have been added or removed.
+    /// Nothing is changed if they cannot be stacked.
+    fn restack(&mut self) -> Result<()> {
+        let (x, obs, obsm) = Self::stack(&self.elems)?;
+        self.index = self.elems.values().map(|x| x.n_obs()).collect();
+        self.n_obs = self.elems.values().map(|x| x.n_obs()).sum();
+        self.n_vars = self.elems.values().next().unwrap().n_vars();
+        self.x = x;
+        self.obs = obs;
+        self.obsm = obsm;
+        Ok(())
     }
 
     pub fn n_obs(&self) -> usize {
@@ -621,7 +843,7 @@ impl<B: Backend> StackedAnnData<B> {
                 let select = if let Some(s) = slices.get(&i) {
                     [s.clone(), slice[1].clone()]
                 } else {
-                    [Vec::new().into(), slice[1].clone()]
+                    [Vec::<usize>::new().into(), slice[1].clone()]
                 };
                 adata.write_select::<O, _, _>(select, file)?;
                 Ok((k.clone(), name))
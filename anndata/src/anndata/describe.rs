@@ -0,0 +1,94 @@
+use crate::{
+    anndata::AnnData,
+    backend::{Backend, DataType},
+    container::{AxisArrays, DataFrameElem, ElemCollection},
+    data::Shape,
+};
+
+use anyhow::Result;
+
+/// The data type and shape of an array element.
+#[derive(Debug, Clone)]
+pub struct ArrayInfo {
+    pub dtype: DataType,
+    pub shape: Shape,
+}
+
+/// The structure of an AnnData object, as returned by `AnnData::describe`.
+/// Keys are sorted alphabetically; dataframe columns keep their stored order.
+#[derive(Debug, Clone)]
+pub struct StructureInfo {
+    pub n_obs: usize,
+    pub n_vars: usize,
+    pub x: Option<ArrayInfo>,
+    pub obs: Vec<(String, DataType)>,
+    pub var: Vec<(String, DataType)>,
+    pub obsm: Vec<(String, ArrayInfo)>,
+    pub obsp: Vec<(String, ArrayInfo)>,
+    pub varm: Vec<(String, ArrayInfo)>,
+    pub varp: Vec<(String, ArrayInfo)>,
+    pub layers: Vec<(String, ArrayInfo)>,
+    pub uns: Vec<(String, DataType)>,
+}
+
+impl<B: Backend> AnnData<B> {
+    /// Return the data type and shape of every element, and the data type of
+    /// every column of `obs` and `var`. Only metadata is read; no data is loaded.
+    pub fn describe(&self) -> Result<StructureInfo> {
+        let x = self.x.with_inner(|x| ArrayInfo {
+            dtype: x.dtype(),
+            shape: x.shape().clone(),
+        });
+        Ok(StructureInfo {
+            n_obs: self.n_obs.get(),
+            n_vars: self.n_vars.get(),
+            x,
+            obs: column_types(&self.obs)?,
+            var: column_types(&self.var)?,
+            obsm: array_infos(&self.obsm)?,
+            obsp: array_infos(&self.obsp)?,
+            varm: array_infos(&self.varm)?,
+            varp: array_infos(&self.varp)?,
+            layers: array_infos(&self.layers)?,
+            uns: elem_types(&self.uns)?,
+        })
+    }
+}
+
+fn column_types<B: Backend>(df: &DataFrameElem<B>) -> Result<Vec<(String, DataType)>> {
+    df.with_inner(|x| x.column_types()).transpose().map(Option::unwrap_or_default)
+}
+
+fn array_infos<B: Backend>(arrays: &AxisArrays<B>) -> Result<Vec<(String, ArrayInfo)>> {
+    let mut infos: Vec<_> = arrays
+        .with_inner(|arrays| -> Result<Vec<_>> {
+            Ok(arrays
+                .iter()?
+                .filter_map(|(k, v)| {
+                    let info = v.with_inner(|x| ArrayInfo {
+                        dtype: x.dtype(),
+                        shape: x.shape().clone(),
+                    })?;
+                    Some((k.clone(), info))
+                })
+                .collect())
+        })
+        .transpose()?
+        .unwrap_or_default();
+    infos.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(infos)
+}
+
+fn elem_types<B: Backend>(elems: &ElemCollection<B>) -> Result<Vec<(String, DataType)>> {
+    let mut types: Vec<_> = elems
+        .with_inner(|elems| -> Result<Vec<_>> {
+            Ok(elems
+                .iter()?
+                .filter_map(|(k, v)| Some((k.clone(), v.with_inner(|x| x.dtype())?)))
+                .collect())
+        })
+        .transpose()?
+        .unwrap_or_default();
+    types.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(types)
+}
@@ -0,0 +1,221 @@
+use crate::{
+    anndata::AnnData,
+    backend::Backend,
+    data::{ArrayData, Data, DynArray, DynCscMatrix, DynCsrMatrix, DynCsrNonCanonical, DynScalar, Mapping},
+    traits::{AnnDataOp, ArrayElemOp, AxisArraysOp, ElemCollectionOp},
+};
+
+use anyhow::Result;
+use num::complex::Complex;
+use polars::{frame::DataFrame, prelude::DataType as PolarsDataType};
+
+/// Relative and absolute tolerances used when comparing floating point values,
+/// the same as the defaults of `numpy.isclose`.
+const RTOL: f64 = 1e-5;
+const ATOL: f64 = 1e-8;
+
+/// Check whether two AnnData objects, possibly using different backends, hold
+/// the same data. The shapes, obs/var names, obs, var, X, obsm, obsp, varm,
+/// varp, layers and uns are read and compared. Data types must match exactly,
+/// while floating point values are compared with a tolerance.
+pub fn anndata_eq<B1: Backend, B2: Backend>(a: &AnnData<B1>, b: &AnnData<B2>) -> Result<bool> {
+    if a.n_obs() != b.n_obs()
+        || a.n_vars() != b.n_vars()
+        || a.obs_names() != b.obs_names()
+        || a.var_names() != b.var_names()
+    {
+        return Ok(false);
+    }
+    Ok(dataframe_eq(&a.read_obs()?, &b.read_obs()?)?
+        && dataframe_eq(&a.read_var()?, &b.read_var()?)?
+        && option_eq(a.x().get()?, b.x().get()?, array_data_eq)?
+        && axis_arrays_eq(a.obsm(), b.obsm())?
+        && axis_arrays_eq(a.obsp(), b.obsp())?
+        && axis_arrays_eq(a.varm(), b.varm())?
+        && axis_arrays_eq(a.varp(), b.varp())?
+        && axis_arrays_eq(a.layers(), b.layers())?
+        && elem_collection_eq(a.uns(), b.uns())?)
+}
+
+fn option_eq<T, F>(a: Option<T>, b: Option<T>, f: F) -> Result<bool>
+where
+    F: Fn(&T, &T) -> Result<bool>,
+{
+    match (a, b) {
+        (None, None) => Ok(true),
+        (Some(x), Some(y)) => f(&x, &y),
+        _ => Ok(false),
+    }
+}
+
+fn sorted_keys(mut keys: Vec<String>) -> Vec<String> {
+    keys.sort();
+    keys
+}
+
+fn axis_arrays_eq<X: AxisArraysOp, Y: AxisArraysOp>(a: X, b: Y) -> Result<bool> {
+    let keys = sorted_keys(a.keys());
+    if keys != sorted_keys(b.keys()) {
+        return Ok(false);
+    }
+    for key in keys {
+        if !option_eq(a.get_item(&key)?, b.get_item(&key)?, array_data_eq)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn elem_collection_eq<X: ElemCollectionOp, Y: ElemCollectionOp>(a: X, b: Y) -> Result<bool> {
+    let keys = sorted_keys(a.keys());
+    if keys != sorted_keys(b.keys()) {
+        return Ok(false);
+    }
+    for key in keys {
+        if !option_eq(a.get_item(&key)?, b.get_item(&key)?, data_eq)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn data_eq(a: &Data, b: &Data) -> Result<bool> {
+    match (a, b) {
+        (Data::ArrayData(x), Data::ArrayData(y)) => array_data_eq(x, y),
+        (Data::Scalar(x), Data::Scalar(y)) => Ok(scalar_eq(x, y)),
+        (Data::Mapping(x), Data::Mapping(y)) => mapping_eq(x, y),
+        _ => Ok(false),
+    }
+}
+
+fn mapping_eq(a: &Mapping, b: &Mapping) -> Result<bool> {
+    if a.len() != b.len() {
+        return Ok(false);
+    }
+    for (key, x) in a.iter() {
+        match b.get(key) {
+            Some(y) if data_eq(x, y)? => {}
+            _ => return Ok(false),
+        }
+    }
+    Ok(true)
+}
+
+/// Compare two sparse matrices of the same format, using a tolerance for floats.
+macro_rules! sparse_eq {
+    ($a:expr, $b:expr, $ty:ident, $data:ident) => {
+        match ($a, $b) {
+            ($ty::F16(x), $ty::F16(y)) => x.ncols() == y.ncols() && compressed_eq(x.$data(), y.$data()),
+            ($ty::F32(x), $ty::F32(y)) => x.ncols() == y.ncols() && compressed_eq(x.$data(), y.$data()),
+            ($ty::F64(x), $ty::F64(y)) => x.ncols() == y.ncols() && compressed_eq(x.$data(), y.$data()),
+            (x, y) => x == y,
+        }
+    };
+}
+
+fn array_data_eq(a: &ArrayData, b: &ArrayData) -> Result<bool> {
+    let eq = match (a, b) {
+        (ArrayData::Array(x), ArrayData::Array(y)) => dyn_array_eq(x, y),
+        (ArrayData::CsrMatrix(x), ArrayData::CsrMatrix(y)) => sparse_eq!(x, y, DynCsrMatrix, csr_data),
+        (ArrayData::CsrNonCanonical(x), ArrayData::CsrNonCanonical(y)) => {
+            sparse_eq!(x, y, DynCsrNonCanonical, csr_data)
+        }
+        (ArrayData::CscMatrix(x), ArrayData::CscMatrix(y)) => sparse_eq!(x, y, DynCscMatrix, csc_data),
+        (ArrayData::RaggedArray(x), ArrayData::RaggedArray(y)) => {
+            x.offsets() == y.offsets() && dyn_array_eq(x.values(), y.values())
+        }
+        (ArrayData::RecordArray(x), ArrayData::RecordArray(y)) => {
+            x.fields().len() == y.fields().len()
+                && x.fields().iter().zip(y.fields()).all(|((n1, v1), (n2, v2))| n1 == n2 && dyn_array_eq(v1, v2))
+        }
+        (ArrayData::DataFrame(x), ArrayData::DataFrame(y)) => dataframe_eq(x, y)?,
+        _ => false,
+    };
+    Ok(eq)
+}
+
+fn dyn_array_eq(a: &DynArray, b: &DynArray) -> bool {
+    match (a, b) {
+        (DynArray::F16(x), DynArray::F16(y)) => x.shape() == y.shape() && values_eq(x, y),
+        (DynArray::F32(x), DynArray::F32(y)) => x.shape() == y.shape() && values_eq(x, y),
+        (DynArray::F64(x), DynArray::F64(y)) => x.shape() == y.shape() && values_eq(x, y),
+        (DynArray::ComplexF32(x), DynArray::ComplexF32(y)) => x.shape() == y.shape() && complex_eq(x, y),
+        (DynArray::ComplexF64(x), DynArray::ComplexF64(y)) => x.shape() == y.shape() && complex_eq(x, y),
+        _ => a == b,
+    }
+}
+
+/// Compare the offsets, indices and values of two compressed sparse matrices.
+fn compressed_eq<T: Copy + Into<f64>>(
+    a: (&[usize], &[usize], &[T]),
+    b: (&[usize], &[usize], &[T]),
+) -> bool {
+    a.0 == b.0 && a.1 == b.1 && a.2.len() == b.2.len() && values_eq(a.2, b.2)
+}
+
+fn scalar_eq(a: &DynScalar, b: &DynScalar) -> bool {
+    match (a, b) {
+        (DynScalar::F16(x), DynScalar::F16(y)) => float_eq((*x).into(), (*y).into()),
+        (DynScalar::F32(x), DynScalar::F32(y)) => float_eq((*x).into(), (*y).into()),
+        (DynScalar::F64(x), DynScalar::F64(y)) => float_eq(*x, *y),
+        (DynScalar::ComplexF32(x), DynScalar::ComplexF32(y)) => complex_eq([x], [y]),
+        (DynScalar::ComplexF64(x), DynScalar::ComplexF64(y)) => complex_eq([x], [y]),
+        _ => a == b,
+    }
+}
+
+fn dataframe_eq(a: &DataFrame, b: &DataFrame) -> Result<bool> {
+    if a.shape() != b.shape() || a.get_column_names() != b.get_column_names() {
+        return Ok(false);
+    }
+    for (x, y) in a.get_columns().iter().zip(b.get_columns()) {
+        let eq = if x.dtype() != y.dtype() {
+            false
+        } else if x.dtype().is_float() {
+            let x = x.cast(&PolarsDataType::Float64)?;
+            let y = y.cast(&PolarsDataType::Float64)?;
+            let eq = x.f64()?.into_iter().zip(y.f64()?).all(|pair| match pair {
+                (Some(x), Some(y)) => float_eq(x, y),
+                (None, None) => true,
+                _ => false,
+            });
+            eq
+        } else if let PolarsDataType::Categorical(_) = x.dtype() {
+            // The codes depend on the order in which the categories were
+            // built, so the values are compared instead.
+            let x = x.cast(&PolarsDataType::Utf8)?;
+            let y = y.cast(&PolarsDataType::Utf8)?;
+            x.series_equal_missing(&y)
+        } else {
+            x.series_equal_missing(y)
+        };
+        if !eq {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Compare two sequences of floats of the same length element-wise.
+fn values_eq<'a, T, I>(a: I, b: I) -> bool
+where
+    T: 'a + Copy + Into<f64>,
+    I: IntoIterator<Item = &'a T>,
+{
+    a.into_iter().zip(b).all(|(x, y)| float_eq((*x).into(), (*y).into()))
+}
+
+/// Compare the real and the imaginary parts separately.
+fn complex_eq<'a, T, I>(a: I, b: I) -> bool
+where
+    T: 'a + Copy + Into<f64>,
+    I: IntoIterator<Item = &'a Complex<T>>,
+{
+    a.into_iter()
+        .zip(b)
+        .all(|(x, y)| float_eq(x.re.into(), y.re.into()) && float_eq(x.im.into(), y.im.into()))
+}
+
+fn float_eq(a: f64, b: f64) -> bool {
+    a == b || (a.is_nan() && b.is_nan()) || (a - b).abs() <= ATOL + RTOL * a.abs().max(b.abs())
+}
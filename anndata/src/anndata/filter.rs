@@ -0,0 +1,290 @@
+use crate::{anndata::AnnData, backend::Backend, traits::AnnDataOp};
+
+use anyhow::{bail, ensure, Context, Result};
+use polars::frame::row::Row;
+use polars::prelude::{col, lit, AnyValue, DataFrame, DataType, Expr, IntoLazy};
+
+/// A row of the obs or var dataframe, as seen by the predicate of
+/// `AnnData::filter_obs` and `AnnData::filter_var`.
+pub struct DataFrameRow<'a> {
+    names: &'a [&'a str],
+    values: &'a [AnyValue<'a>],
+}
+
+impl<'a> DataFrameRow<'a> {
+    /// The value of a column, or `None` if the column does not exist.
+    pub fn get(&self, column: &str) -> Option<&AnyValue<'a>> {
+        self.names.iter().position(|x| *x == column).map(|i| &self.values[i])
+    }
+
+    /// The values of all columns, in the order of the dataframe.
+    pub fn values(&self) -> &[AnyValue<'a>] {
+        self.values
+    }
+}
+
+impl<B: Backend> AnnData<B> {
+    /// Return the indices of the observations for which `f` returns true. The
+    /// result can be passed to `subset`.
+    pub fn filter_obs<F>(&self, f: F) -> Result<Vec<usize>>
+    where
+        F: Fn(&DataFrameRow) -> bool,
+    {
+        filter_rows(&self.read_obs()?, self.n_obs(), f)
+    }
+
+    /// Return the indices of the variables for which `f` returns true.
+    pub fn filter_var<F>(&self, f: F) -> Result<Vec<usize>>
+    where
+        F: Fn(&DataFrameRow) -> bool,
+    {
+        filter_rows(&self.read_var()?, self.n_vars(), f)
+    }
+
+    /// Return the indices of the observations matching a filter expression
+    /// over the columns of obs, e.g., `n_genes > 500 & pct_mito < 0.1`.
+    ///
+    /// Comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`) between columns and
+    /// literals can be combined with `&`, `|`, `~` and parentheses. Literals
+    /// are numbers, quoted strings, `true` and `false`. Column names that are
+    /// not identifiers must be enclosed in backticks.
+    pub fn query_obs(&self, expr: &str) -> Result<Vec<usize>> {
+        query_rows(&self.read_obs()?, self.n_obs(), expr)
+    }
+
+    /// Return the indices of the variables matching a filter expression over
+    /// the columns of var. See `query_obs` for the syntax.
+    pub fn query_var(&self, expr: &str) -> Result<Vec<usize>> {
+        query_rows(&self.read_var()?, self.n_vars(), expr)
+    }
+}
+
+fn filter_rows<F>(df: &DataFrame, n: usize, f: F) -> Result<Vec<usize>>
+where
+    F: Fn(&DataFrameRow) -> bool,
+{
+    // A missing dataframe has no columns but `n` rows.
+    let n = if df.width() == 0 { n } else { df.height() };
+    let names = df.get_column_names();
+    let mut row = Row::new(vec![AnyValue::Null; df.width()]);
+    let mut result = Vec::new();
+    for i in 0..n {
+        df.get_row_amortized(i, &mut row)?;
+        let row = DataFrameRow {
+            names: &names,
+            values: &row.0,
+        };
+        if f(&row) {
+            result.push(i);
+        }
+    }
+    Ok(result)
+}
+
+fn query_rows(df: &DataFrame, n: usize, expr: &str) -> Result<Vec<usize>> {
+    let expr = parse_filter(expr)?;
+    // Categorical columns are compared as strings.
+    let columns = df
+        .get_columns()
+        .iter()
+        .map(|s| match s.dtype() {
+            DataType::Categorical(_) => s.cast(&DataType::Utf8),
+            _ => Ok(s.clone()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mask = DataFrame::new(columns)?
+        .lazy()
+        .select([expr.alias("mask")])
+        .collect()?;
+    let mask = mask.column("mask")?;
+    let mask = mask.bool().context("the filter expression must evaluate to booleans")?;
+    // Expressions without columns evaluate to a single value.
+    if mask.len() == 1 && n != 1 {
+        return Ok(if mask.into_iter().next().flatten().unwrap_or(false) { (0..n).collect() } else { Vec::new() });
+    }
+    Ok(mask
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, x)| if x.unwrap_or(false) { Some(i) } else { None })
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Column(String),
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let next = chars.get(i + 1).copied();
+        let token = match c {
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '&' => Token::Op("&"),
+            '|' => Token::Op("|"),
+            '~' => Token::Op("~"),
+            '=' if next == Some('=') => Token::Op("=="),
+            '!' if next == Some('=') => Token::Op("!="),
+            '<' if next == Some('=') => Token::Op("<="),
+            '>' if next == Some('=') => Token::Op(">="),
+            '<' => Token::Op("<"),
+            '>' => Token::Op(">"),
+            '\'' | '"' | '`' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|x| *x == c)
+                    .with_context(|| format!("unterminated {} in filter expression", c))?;
+                let s: String = chars[i + 1..i + 1 + end].iter().collect();
+                i += end + 2;
+                tokens.push(if c == '`' { Token::Column(s) } else { Token::Str(s) });
+                continue;
+            }
+            c if c.is_ascii_digit() || c == '.' || (c == '-' && next.map_or(false, |x| x.is_ascii_digit() || x == '.')) => {
+                let start = i;
+                i += 1;
+                while i < chars.len()
+                    && (chars[i].is_ascii_alphanumeric() || chars[i] == '.'
+                        || ((chars[i] == '-' || chars[i] == '+') && matches!(chars[i - 1], 'e' | 'E')))
+                {
+                    i += 1;
+                }
+                let s: String = chars[start..i].iter().collect();
+                let token = match s.parse::<i64>() {
+                    Ok(x) => Token::Int(x),
+                    Err(_) => Token::Float(s.parse().with_context(|| format!("invalid number '{}'", s))?),
+                };
+                tokens.push(token);
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                    i += 1;
+                }
+                let s: String = chars[start..i].iter().collect();
+                tokens.push(match s.as_str() {
+                    "true" | "True" => Token::Bool(true),
+                    "false" | "False" => Token::Bool(false),
+                    _ => Token::Column(s),
+                });
+                continue;
+            }
+            c => bail!("unexpected character '{}' in filter expression", c),
+        };
+        i += match token {
+            Token::Op(op) => op.len(),
+            _ => 1,
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Parse a filter expression into a polars expression. See
+/// `AnnData::query_obs` for the syntax.
+fn parse_filter(input: &str) -> Result<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+    };
+    ensure!(!parser.tokens.is_empty(), "empty filter expression");
+    let expr = parser.parse_or()?;
+    if let Some(token) = parser.peek() {
+        bail!("unexpected {:?} in filter expression '{}'", token, input);
+    }
+    Ok(expr)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self.tokens.get(self.pos).cloned().context("unexpected end of filter expression")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_and()?;
+        while self.peek() == Some(&Token::Op("|")) {
+            self.pos += 1;
+            expr = expr.or(self.parse_and()?);
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_not()?;
+        while self.peek() == Some(&Token::Op("&")) {
+            self.pos += 1;
+            expr = expr.and(self.parse_not()?);
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr> {
+        if self.peek() == Some(&Token::Op("~")) {
+            self.pos += 1;
+            return Ok(self.parse_not()?.not());
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr> {
+        let lhs = self.parse_operand()?;
+        let op = match self.peek() {
+            Some(Token::Op(op)) if !matches!(*op, "&" | "|" | "~") => *op,
+            _ => return Ok(lhs),
+        };
+        self.pos += 1;
+        let rhs = self.parse_operand()?;
+        Ok(match op {
+            "==" => lhs.eq(rhs),
+            "!=" => lhs.neq(rhs),
+            "<" => lhs.lt(rhs),
+            "<=" => lhs.lt_eq(rhs),
+            ">" => lhs.gt(rhs),
+            ">=" => lhs.gt_eq(rhs),
+            _ => unreachable!(),
+        })
+    }
+
+    fn parse_operand(&mut self) -> Result<Expr> {
+        let expr = match self.next()? {
+            Token::Column(x) => col(&x),
+            Token::Str(x) => lit(x),
+            Token::Int(x) => lit(x),
+            Token::Float(x) => lit(x),
+            Token::Bool(x) => lit(x),
+            Token::LParen => {
+                let expr = self.parse_or()?;
+                ensure!(self.next()? == Token::RParen, "missing ')' in filter expression");
+                expr
+            }
+            token => bail!("unexpected {:?} in filter expression", token),
+        };
+        Ok(expr)
+    }
+}
@@ -0,0 +1,285 @@
+use crate::{
+    anndata::AnnData,
+    backend::{Backend, DataContainer, DataType, DatasetOp, GroupOp, LocationOp, ScalarType},
+    data::{array::dataframe::{self, is_nullable}, ArrayData, Data, DynArray, RaggedArray, RecordArray, ReadArrayData, ReadData, SelectInfoElem},
+};
+
+use anyhow::{bail, Result};
+use ndarray::Array1;
+use polars::prelude::Series;
+
+/// The number of values read at a time.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// A problem with the values stored in an element, as reported by
+/// `AnnData::verify_integrity`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntegrityProblem {
+    /// The path of the element, e.g., `X`, `obs` or `uns/neighbors/params`.
+    pub element: String,
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl std::fmt::Display for IntegrityProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}': {}", self.element, self.message)
+    }
+}
+
+impl<B: Backend> AnnData<B> {
+    /// Read every stored element and check that its values are well-formed:
+    /// the `indptr` of sparse matrices starts at 0, is non-decreasing and ends
+    /// at the number of stored values, the indices are within the minor axis,
+    /// the codes of categorical arrays refer to existing categories, and the
+    /// columns of dataframes have as many rows as their index. NaN values are
+    /// reported in X and the layers, but allowed elsewhere. Elements that cannot
+    /// be read, e.g., because of a corrupted chunk, are reported as well.
+    ///
+    /// Unlike `validate`, which only compares shapes, this reads all the data,
+    /// in chunks for large arrays. Problems of all elements are returned at once.
+    pub fn verify_integrity(&self) -> Result<Vec<IntegrityProblem>> {
+        let mut problems = Vec::new();
+        if self.file.exists("X")? {
+            let result = DataContainer::open(&self.file, "X").and_then(|x| check_array(&x, true));
+            report(&mut problems, "X".to_string(), result);
+        }
+        for name in ["obs", "var"] {
+            if self.file.exists(name)? {
+                let result = DataContainer::open(&self.file, name).and_then(|x| check_dataframe(&x));
+                report(&mut problems, name.to_string(), result);
+            }
+        }
+        for (name, check_nan) in [("obsm", false), ("obsp", false), ("varm", false), ("varp", false), ("layers", true)] {
+            if self.file.exists(name)? {
+                let group = self.file.open_group(name)?;
+                for key in group.list()? {
+                    let result = DataContainer::open(&group, &key).and_then(|x| check_array(&x, check_nan));
+                    report(&mut problems, format!("{}/{}", name, key), result);
+                }
+            }
+        }
+        if self.file.exists("uns")? {
+            check_mapping(&self.file.open_group("uns")?, "uns", &mut problems)?;
+        }
+        Ok(problems)
+    }
+}
+
+fn report(problems: &mut Vec<IntegrityProblem>, element: String, result: Result<Vec<String>>) {
+    match result {
+        Ok(messages) => problems.extend(messages.into_iter().map(|message| IntegrityProblem {
+            element: element.clone(),
+            message,
+        })),
+        Err(e) => problems.push(IntegrityProblem {
+            element,
+            message: format!("cannot be read: {:#}", e),
+        }),
+    }
+}
+
+/// Check the elements of a mapping in uns, recursively.
+fn check_mapping<G: GroupOp>(group: &G, path: &str, problems: &mut Vec<IntegrityProblem>) -> Result<()> {
+    for key in group.list()? {
+        let element = format!("{}/{}", path, key);
+        match DataContainer::open(group, &key) {
+            Ok(container) => match container.encoding_type() {
+                Ok(DataType::Mapping) => check_mapping(container.as_group()?, &element, problems)?,
+                Ok(DataType::Scalar(_)) => report(problems, element, Data::read(&container).map(|_| Vec::new())),
+                Ok(DataType::DataFrame) => report(problems, element, check_dataframe(&container)),
+                _ => report(problems, element, check_array(&container, false)),
+            },
+            Err(e) => report(problems, element, Err(e)),
+        }
+    }
+    Ok(())
+}
+
+fn check_array<B: Backend>(container: &DataContainer<B>, check_nan: bool) -> Result<Vec<String>> {
+    if let DataContainer::Dataset(dataset) = container {
+        if dataset.is_record()? {
+            return RecordArray::read(container).map(|_| Vec::new());
+        }
+        return check_dense(dataset, check_nan);
+    }
+    match container.encoding_type()? {
+        DataType::Categorical => check_categorical(container.as_group()?),
+        DataType::CsrMatrix(_) => check_compressed(container.as_group()?, true, check_nan),
+        DataType::CscMatrix(_) => check_compressed(container.as_group()?, false, check_nan),
+        DataType::RaggedArray(_) => RaggedArray::read(container).map(|_| Vec::new()),
+        DataType::DataFrame => check_dataframe(container),
+        ty => bail!("expect an array, found {}", ty),
+    }
+}
+
+fn check_dense<D: DatasetOp>(dataset: &D, check_nan: bool) -> Result<Vec<String>> {
+    let shape = dataset.shape();
+    let mut n_nan = 0;
+    if shape.ndim() == 0 {
+        n_nan += count_nan(&read_slice(dataset, &[])?);
+    } else {
+        let row_size = shape.as_ref()[1..].iter().product::<usize>().max(1);
+        let step = (CHUNK_SIZE / row_size).max(1);
+        for start in (0..shape[0]).step_by(step) {
+            let mut selection = vec![SelectInfoElem::full(); shape.ndim()];
+            selection[0] = (start..(start + step).min(shape[0])).into();
+            n_nan += count_nan(&read_slice(dataset, &selection)?);
+        }
+    }
+    if check_nan && n_nan > 0 {
+        Ok(vec![format!("{} values are NaN", n_nan)])
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// Check a CSR (`by_row`) or CSC matrix.
+fn check_compressed<G: GroupOp + LocationOp>(group: &G, by_row: bool, check_nan: bool) -> Result<Vec<String>> {
+    let mut problems = Vec::new();
+    let shape = Array1::from(group.read_shape_attr()?);
+    if shape.len() != 2 {
+        bail!("expect a two-dimensional shape, found {:?}", shape.to_vec());
+    }
+    let (n_major, n_minor) = if by_row { (shape[0], shape[1]) } else { (shape[1], shape[0]) };
+
+    let indices = group.open_dataset("indices")?;
+    let data = group.open_dataset("data")?;
+    let nnz = indices.shape()[0];
+    let n_data = data.shape()[0];
+    if n_data != nnz {
+        problems.push(format!("data has {} values, but indices has {}", n_data, nnz));
+    }
+
+    let indptr = to_i64(read_slice(&group.open_dataset("indptr")?, &[SelectInfoElem::full()])?)?;
+    if indptr.len() != n_major + 1 {
+        problems.push(format!("indptr has {} entries, expect {}", indptr.len(), n_major + 1));
+    }
+    if indptr.first().map_or(false, |x| *x != 0) {
+        problems.push(format!("indptr starts with {}, expect 0", indptr[0]));
+    }
+    if let Some(i) = indptr.windows(2).position(|w| w[0] > w[1]) {
+        problems.push(format!("indptr decreases at position {}", i + 1));
+    }
+    if indptr.last().map_or(false, |x| *x != nnz as i64) {
+        problems.push(format!("indptr ends with {}, but there are {} stored values", indptr[indptr.len() - 1], nnz));
+    }
+
+    let mut n_out_of_range = 0;
+    let mut n_nan = 0;
+    for start in (0..nnz).step_by(CHUNK_SIZE) {
+        let selection = [SelectInfoElem::from(start..(start + CHUNK_SIZE).min(nnz))];
+        n_out_of_range += to_i64(read_slice(&indices, &selection)?)?
+            .into_iter()
+            .filter(|i| *i < 0 || *i >= n_minor as i64)
+            .count();
+        if start < n_data {
+            let selection = [SelectInfoElem::from(start..(start + CHUNK_SIZE).min(n_data))];
+            n_nan += count_nan(&read_slice(&data, &selection)?);
+        }
+    }
+    if n_out_of_range > 0 {
+        problems.push(format!("{} indices are out of the range 0..{}", n_out_of_range, n_minor));
+    }
+    if check_nan && n_nan > 0 {
+        problems.push(format!("{} values are NaN", n_nan));
+    }
+    Ok(problems)
+}
+
+fn check_categorical<G: GroupOp>(group: &G) -> Result<Vec<String>> {
+    let n_categories = group.open_dataset("categories")?.shape()[0] as i64;
+    let codes = to_i64(read_slice(&group.open_dataset("codes")?, &[SelectInfoElem::full()])?)?;
+    let n = codes.into_iter().filter(|x| *x < -1 || *x >= n_categories).count();
+    if n > 0 {
+        Ok(vec![format!("{} codes do not refer to any of the {} categories", n, n_categories)])
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+fn check_dataframe<B: Backend>(container: &DataContainer<B>) -> Result<Vec<String>> {
+    let group = container.as_group()?;
+    let index_name = dataframe::index_name(group)?;
+    let index = group.open_dataset(&index_name)?;
+    let n_rows = index.shape()[0];
+    let mut problems = check_dense(&index, false)?;
+    let columns: Array1<String> = group.read_array_attr("column-order")?;
+    for name in columns.iter() {
+        if !group.exists(name)? {
+            problems.push(format!("column '{}' is missing", name));
+            continue;
+        }
+        let column = DataContainer::open(group, name)?;
+        match Series::get_shape(&column) {
+            Ok(shape) if shape[0] != n_rows => problems.push(format!(
+                "column '{}' has {} rows, but the index has {}",
+                name, shape[0], n_rows
+            )),
+            Ok(_) => {}
+            Err(e) => problems.push(format!("column '{}' cannot be read: {:#}", name, e)),
+        }
+        let result = if is_nullable(&column) {
+            check_nullable(column.as_group()?)
+        } else {
+            check_array(&column, false)
+        };
+        match result {
+            Ok(messages) => problems.extend(messages.into_iter().map(|x| format!("column '{}': {}", name, x))),
+            Err(e) => problems.push(format!("column '{}' cannot be read: {:#}", name, e)),
+        }
+    }
+    Ok(problems)
+}
+
+/// Check a column with missing values, stored as values and a mask.
+fn check_nullable<G: GroupOp>(group: &G) -> Result<Vec<String>> {
+    let values = group.open_dataset("values")?;
+    let n_mask = group.open_dataset("mask")?.shape()[0];
+    let n_values = values.shape()[0];
+    let mut problems = check_dense(&values, false)?;
+    if n_values != n_mask {
+        problems.push(format!("mask has {} entries, but there are {} values", n_mask, n_values));
+    }
+    Ok(problems)
+}
+
+fn read_slice<D: DatasetOp>(dataset: &D, selection: &[SelectInfoElem]) -> Result<DynArray> {
+    let array = match dataset.dtype()? {
+        ScalarType::I8 => DynArray::I8(dataset.read_array_slice(selection)?),
+        ScalarType::I16 => DynArray::I16(dataset.read_array_slice(selection)?),
+        ScalarType::I32 => DynArray::I32(dataset.read_array_slice(selection)?),
+        ScalarType::I64 => DynArray::I64(dataset.read_array_slice(selection)?),
+        ScalarType::U8 => DynArray::U8(dataset.read_array_slice(selection)?),
+        ScalarType::U16 => DynArray::U16(dataset.read_array_slice(selection)?),
+        ScalarType::U32 => DynArray::U32(dataset.read_array_slice(selection)?),
+        ScalarType::U64 => DynArray::U64(dataset.read_array_slice(selection)?),
+        ScalarType::Usize => DynArray::Usize(dataset.read_array_slice(selection)?),
+        ScalarType::F16 => DynArray::F16(dataset.read_array_slice(selection)?),
+        ScalarType::F32 => DynArray::F32(dataset.read_array_slice(selection)?),
+        ScalarType::F64 => DynArray::F64(dataset.read_array_slice(selection)?),
+        ScalarType::ComplexF32 => DynArray::ComplexF32(dataset.read_array_slice(selection)?),
+        ScalarType::ComplexF64 => DynArray::ComplexF64(dataset.read_array_slice(selection)?),
+        ScalarType::Bool => DynArray::Bool(dataset.read_array_slice(selection)?),
+        ScalarType::String => DynArray::String(dataset.read_array_slice(selection)?),
+    };
+    Ok(array)
+}
+
+fn to_i64(array: DynArray) -> Result<Vec<i64>> {
+    match ArrayData::from(array).cast(ScalarType::I64)? {
+        ArrayData::Array(DynArray::I64(x)) => Ok(x.into_raw_vec()),
+        _ => unreachable!(),
+    }
+}
+
+fn count_nan(array: &DynArray) -> usize {
+    match array {
+        DynArray::F16(x) => x.iter().filter(|v| v.to_f32().is_nan()).count(),
+        DynArray::F32(x) => x.iter().filter(|v| v.is_nan()).count(),
+        DynArray::F64(x) => x.iter().filter(|v| v.is_nan()).count(),
+        DynArray::ComplexF32(x) => x.iter().filter(|v| v.is_nan()).count(),
+        DynArray::ComplexF64(x) => x.iter().filter(|v| v.is_nan()).count(),
+        _ => 0,
+    }
+}
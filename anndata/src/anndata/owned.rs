@@ -0,0 +1,80 @@
+use crate::{
+    anndata::AnnData,
+    backend::Backend,
+    data::{ArrayData, Data, DataFrameIndex},
+    traits::{AnnDataOp, ArrayElemOp, AxisArraysOp, ElemCollectionOp},
+};
+
+use anyhow::Result;
+use polars::prelude::DataFrame;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// An AnnData object whose elements are all read into memory, for code that
+/// only analyzes the data and has no use for the lazy, backed `AnnData`.
+///
+/// The fields are plain owned values. Arrays are stored as `ArrayData`, which
+/// can be converted into the concrete type with `try_into`, e.g.,
+/// `Array2<f64>` or `CsrMatrix<f32>`. Missing elements are empty: `x` is
+/// `None`, the dataframes have no columns and the collections have no keys.
+#[derive(Debug, Clone)]
+pub struct MemAnnData {
+    pub n_obs: usize,
+    pub n_vars: usize,
+    pub x: Option<ArrayData>,
+    pub obs: DataFrame,
+    pub obs_names: DataFrameIndex,
+    pub var: DataFrame,
+    pub var_names: DataFrameIndex,
+    pub obsm: HashMap<String, ArrayData>,
+    pub obsp: HashMap<String, ArrayData>,
+    pub varm: HashMap<String, ArrayData>,
+    pub varp: HashMap<String, ArrayData>,
+    pub layers: HashMap<String, ArrayData>,
+    pub uns: HashMap<String, Data>,
+}
+
+impl MemAnnData {
+    /// Open the file at `path` with the backend `B` and read all of its
+    /// elements into memory. The file is closed before returning.
+    pub fn read<B: Backend, P: AsRef<Path>>(path: P) -> Result<Self> {
+        let adata = AnnData::<B>::open(B::open(path)?)?;
+        let result = adata.to_memory();
+        adata.close()?;
+        result
+    }
+}
+
+impl<B: Backend> AnnData<B> {
+    /// Read all elements into memory. See [`MemAnnData`].
+    pub fn to_memory(&self) -> Result<MemAnnData> {
+        fn read_arrays<A: AxisArraysOp>(arrays: A) -> Result<HashMap<String, ArrayData>> {
+            arrays
+                .keys()
+                .into_iter()
+                .filter_map(|key| arrays.get_item(&key).transpose().map(|x| x.map(|x| (key, x))))
+                .collect()
+        }
+
+        let uns = self.uns();
+        Ok(MemAnnData {
+            n_obs: self.n_obs(),
+            n_vars: self.n_vars(),
+            x: self.x().get()?,
+            obs: self.read_obs()?,
+            obs_names: self.obs_names(),
+            var: self.read_var()?,
+            var_names: self.var_names(),
+            obsm: read_arrays(self.obsm())?,
+            obsp: read_arrays(self.obsp())?,
+            varm: read_arrays(self.varm())?,
+            varp: read_arrays(self.varp())?,
+            layers: read_arrays(self.layers())?,
+            uns: uns
+                .keys()
+                .into_iter()
+                .filter_map(|key| uns.get_item(&key).transpose().map(|x| x.map(|x| (key, x))))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
@@ -0,0 +1,59 @@
+use crate::{
+    anndata::{AnnData, AnnDataSet},
+    backend::Backend,
+    data::SelectInfoElem,
+};
+
+use anyhow::{ensure, Context, Result};
+use indexmap::map::IndexMap;
+use polars::prelude::DataType;
+use std::path::Path;
+
+impl<B: Backend> AnnData<B> {
+    /// Split the observations into groups by the values of the obs column
+    /// `obs_column`, and assemble the groups into an `AnnDataSet`. This is the
+    /// inverse of building a dataset from several AnnData objects.
+    ///
+    /// The rows of each group are written to `{out_dir}/{value}.h5ad`, using
+    /// `write_select` so that all elements, including `var`, are preserved. The
+    /// groups are ordered by the first appearance of their value, and the
+    /// dataset is written to `{out_dir}/dataset.h5ads` with `obs_column` as the
+    /// key of the components. Note that the observations of the dataset are
+    /// thus ordered by group.
+    pub fn split_by<P: AsRef<Path>>(&self, obs_column: &str, out_dir: P) -> Result<AnnDataSet<B>> {
+        let groups = self.group_obs_by(obs_column)?;
+        ensure!(!groups.is_empty(), "cannot split an AnnData object without observations");
+
+        let out_dir = out_dir.as_ref();
+        std::fs::create_dir_all(out_dir)?;
+        let components = groups
+            .into_iter()
+            .map(|(key, idx)| {
+                ensure!(
+                    !key.is_empty() && !key.contains(['/', '\\']),
+                    "'{}' cannot be used as a file name",
+                    key,
+                );
+                let filename = out_dir.join(format!("{}.h5ad", key));
+                let selection = [SelectInfoElem::from(idx), SelectInfoElem::full()];
+                self.write_select::<B, _, _>(selection, &filename)?;
+                Ok((key, AnnData::open(B::open(filename)?)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        AnnDataSet::new(components, out_dir.join("dataset.h5ads"), obs_column)
+    }
+
+    /// Group the indices of the observations by the values of an obs column.
+    fn group_obs_by(&self, obs_column: &str) -> Result<IndexMap<String, Vec<usize>>> {
+        let obs = self.get_obs();
+        ensure!(!obs.is_empty(), "obs is empty");
+        let column = obs.inner().column(obs_column)?.cast(&DataType::Utf8)?;
+        let mut groups: IndexMap<String, Vec<usize>> = IndexMap::new();
+        for (i, value) in column.utf8()?.into_iter().enumerate() {
+            let value = value
+                .with_context(|| format!("obs column '{}' contains missing values", obs_column))?;
+            groups.entry(value.to_string()).or_default().push(i);
+        }
+        Ok(groups)
+    }
+}
@@ -0,0 +1,317 @@
+use crate::{
+    anndata::AnnData,
+    backend::{Backend, ScalarType},
+    data::{ArrayData, DynArray, DynCscMatrix, DynCsrMatrix, DynCsrNonCanonical, HasShape, WriteData},
+    traits::{AnnDataOp, ArrayElemOp},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use ndarray::{Array1, Ix2};
+
+/// The number of rows of X read at a time.
+const CHUNK_SIZE: usize = 1000;
+
+impl<B: Backend> AnnData<B> {
+    /// The sum of each row of X, e.g., the library size of each cell.
+    pub fn x_row_sums(&self) -> Result<Array1<f64>> {
+        let mut sums = Array1::zeros(self.n_obs());
+        self.for_each_x_entry(|i, _, v| sums[i] += v)?;
+        Ok(sums)
+    }
+
+    /// The sum of each column of X.
+    pub fn x_col_sums(&self) -> Result<Array1<f64>> {
+        let mut sums = Array1::zeros(self.n_vars());
+        self.for_each_x_entry(|_, j, v| sums[j] += v)?;
+        Ok(sums)
+    }
+
+    /// The number of non-zero values in each row of X, e.g., the number of
+    /// genes detected in each cell.
+    pub fn x_nnz_per_row(&self) -> Result<Array1<usize>> {
+        let mut counts = Array1::zeros(self.n_obs());
+        self.for_each_x_entry(|i, _, v| if v != 0.0 { counts[i] += 1 })?;
+        Ok(counts)
+    }
+
+    /// The number of non-zero values in each column of X, e.g., the number of
+    /// cells in which each gene is detected.
+    pub fn x_nnz_per_col(&self) -> Result<Array1<usize>> {
+        let mut counts = Array1::zeros(self.n_vars());
+        self.for_each_x_entry(|_, j, v| if v != 0.0 { counts[j] += 1 })?;
+        Ok(counts)
+    }
+
+    /// Call `f(i, j, value)` on the entries of X, which is read in chunks of
+    /// rows. Only the stored entries of sparse matrices are visited, so they
+    /// are never densified.
+    fn for_each_x_entry<F: FnMut(usize, usize, f64)>(&self, mut f: F) -> Result<()> {
+        let x = self.x();
+        let shape = x.shape().context("X is empty")?;
+        ensure!(shape.ndim() == 2, "X must be two-dimensional, found shape {}", shape);
+        for (chunk, start, _) in x.iter::<ArrayData>(CHUNK_SIZE) {
+            for_each_entry(chunk, |i, j, v| f(start + i, j, v))?;
+        }
+        Ok(())
+    }
+}
+
+/// Call `f(i, j, value)` on the entries of a two-dimensional chunk. Only the
+/// stored entries of sparse matrices are visited.
+fn for_each_entry<F: FnMut(usize, usize, f64)>(chunk: ArrayData, mut f: F) -> Result<()> {
+    match chunk.cast(ScalarType::F64)? {
+        ArrayData::Array(DynArray::F64(x)) => x
+            .into_dimensionality::<Ix2>()?
+            .indexed_iter()
+            .for_each(|((i, j), v)| f(i, j, *v)),
+        ArrayData::CsrMatrix(DynCsrMatrix::F64(x)) => {
+            x.triplet_iter().for_each(|(i, j, v)| f(i, j, *v))
+        }
+        ArrayData::CscMatrix(DynCscMatrix::F64(x)) => {
+            x.triplet_iter().for_each(|(i, j, v)| f(i, j, *v))
+        }
+        ArrayData::CsrNonCanonical(DynCsrNonCanonical::F64(x)) => {
+            let (offsets, indices, values) = x.csr_data();
+            for (i, row) in offsets.windows(2).enumerate() {
+                (row[0]..row[1]).for_each(|k| f(i, indices[k], values[k]));
+            }
+        }
+        x => bail!("cannot compute statistics of X of type {}", x.data_type()),
+    }
+    Ok(())
+}
+
+/// Per-column statistics of a matrix accumulated in a single pass over chunks
+/// of rows, e.g., those produced by `ArrayElem::chunked`.
+///
+/// Only the stored entries of sparse chunks are visited; the implicit zeros
+/// are accounted for when the statistics are computed. Means and variances
+/// are exact (Welford's algorithm), whereas quantiles are estimated with a
+/// t-digest per column.
+///
+/// # Example
+///
+/// ```ignore
+/// let n_vars = adata.n_vars();
+/// let chunks = adata.x().chunked::<ArrayData>(500).map(|(x, _, _)| x);
+/// let stats = Statistics::from_chunks(n_vars, chunks)?;
+/// let median = stats.quantile(0.5)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Statistics {
+    n_rows: usize,
+    /// The number of stored entries in each column.
+    count: Vec<usize>,
+    /// The number of stored negative entries in each column.
+    negative: Vec<usize>,
+    mean: Vec<f64>,
+    m2: Vec<f64>,
+    min: Vec<f64>,
+    max: Vec<f64>,
+    digests: Vec<TDigest>,
+}
+
+impl Statistics {
+    /// Create an empty accumulator for a matrix with `n_cols` columns.
+    pub fn new(n_cols: usize) -> Self {
+        Self {
+            n_rows: 0,
+            count: vec![0; n_cols],
+            negative: vec![0; n_cols],
+            mean: vec![0.0; n_cols],
+            m2: vec![0.0; n_cols],
+            min: vec![f64::INFINITY; n_cols],
+            max: vec![f64::NEG_INFINITY; n_cols],
+            digests: vec![TDigest::default(); n_cols],
+        }
+    }
+
+    /// Accumulate the statistics of all chunks of a matrix with `n_cols` columns.
+    pub fn from_chunks<I>(n_cols: usize, chunks: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = ArrayData>,
+    {
+        let mut stats = Self::new(n_cols);
+        for chunk in chunks {
+            stats.update(chunk)?;
+        }
+        Ok(stats)
+    }
+
+    /// The number of columns.
+    pub fn n_cols(&self) -> usize {
+        self.count.len()
+    }
+
+    /// The number of rows accumulated so far.
+    pub fn n_rows(&self) -> usize {
+        self.n_rows
+    }
+
+    /// Add the rows of `chunk`, a dense array or a sparse matrix.
+    pub fn update(&mut self, chunk: ArrayData) -> Result<()> {
+        let shape = chunk.shape();
+        ensure!(
+            shape.ndim() == 2 && shape[1] == self.n_cols(),
+            "expecting chunks with {} columns, found shape {}",
+            self.n_cols(),
+            shape,
+        );
+        let n_rows = shape[0];
+        for_each_entry(chunk, |_, j, v| {
+            self.count[j] += 1;
+            if v < 0.0 {
+                self.negative[j] += 1;
+            }
+            let delta = v - self.mean[j];
+            self.mean[j] += delta / self.count[j] as f64;
+            self.m2[j] += delta * (v - self.mean[j]);
+            self.min[j] = self.min[j].min(v);
+            self.max[j] = self.max[j].max(v);
+            self.digests[j].buffer.push(v);
+        })?;
+        self.digests.iter_mut().for_each(|x| x.compress());
+        self.n_rows += n_rows;
+        Ok(())
+    }
+
+    /// The mean of each column.
+    pub fn mean(&self) -> Array1<f64> {
+        let n = self.n_rows as f64;
+        self.columns(|j| self.mean[j] * self.count[j] as f64 / n)
+    }
+
+    /// The sample variance, i.e., with `n - 1` degrees of freedom, of each column.
+    pub fn variance(&self) -> Array1<f64> {
+        let n = self.n_rows as f64;
+        self.columns(|j| {
+            // Merge the stored entries with the implicit zeros.
+            let k = self.count[j] as f64;
+            let m2 = self.m2[j] + self.mean[j].powi(2) * k * (n - k) / n;
+            m2 / (n - 1.0)
+        })
+    }
+
+    /// The minimum of each column.
+    pub fn min(&self) -> Array1<f64> {
+        self.columns(|j| {
+            if self.count[j] < self.n_rows { self.min[j].min(0.0) } else { self.min[j] }
+        })
+    }
+
+    /// The maximum of each column.
+    pub fn max(&self) -> Array1<f64> {
+        self.columns(|j| {
+            if self.count[j] < self.n_rows { self.max[j].max(0.0) } else { self.max[j] }
+        })
+    }
+
+    /// The approximate `q`-th quantile of each column, where `q` is between 0 and 1.
+    pub fn quantile(&self, q: f64) -> Result<Array1<f64>> {
+        ensure!((0.0..=1.0).contains(&q), "quantile must be between 0 and 1, found {}", q);
+        let rank = q * self.n_rows as f64;
+        Ok(self.columns(|j| {
+            let negative = self.negative[j] as f64;
+            let zeros = (self.n_rows - self.count[j]) as f64;
+            if rank < negative || zeros == 0.0 {
+                self.digests[j].quantile(rank)
+            } else if rank <= negative + zeros {
+                0.0
+            } else {
+                self.digests[j].quantile(rank - zeros)
+            }
+        }))
+    }
+
+    /// Compute a statistic for each column, which is NaN if there are no rows.
+    fn columns<F: Fn(usize) -> f64>(&self, f: F) -> Array1<f64> {
+        (0..self.n_cols())
+            .map(|j| if self.n_rows == 0 { f64::NAN } else { f(j) })
+            .collect()
+    }
+}
+
+/// The compression parameter of the t-digests. Larger values give more
+/// accurate quantiles at the cost of more centroids.
+const COMPRESSION: f64 = 100.0;
+
+/// A merging t-digest summarizing a distribution with weighted centroids,
+/// which are small near the tails and large near the median.
+#[derive(Debug, Clone, Default)]
+struct TDigest {
+    /// (mean, weight) of the centroids, sorted by their means.
+    centroids: Vec<(f64, f64)>,
+    /// Values not yet merged into the centroids.
+    buffer: Vec<f64>,
+    min: f64,
+    max: f64,
+}
+
+impl TDigest {
+    fn total(&self) -> f64 {
+        self.centroids.iter().map(|(_, w)| w).sum()
+    }
+
+    /// Merge the buffered values into the centroids.
+    fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let (min, max) = self
+            .buffer
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), x| (lo.min(*x), hi.max(*x)));
+        if self.centroids.is_empty() {
+            (self.min, self.max) = (min, max);
+        } else {
+            (self.min, self.max) = (self.min.min(min), self.max.max(max));
+        }
+        let mut points = std::mem::take(&mut self.centroids);
+        points.extend(self.buffer.drain(..).map(|x| (x, 1.0)));
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let total: f64 = points.iter().map(|(_, w)| w).sum();
+        let mut cum = 0.0;
+        let mut current = points[0];
+        for &(mean, weight) in &points[1..] {
+            let merged = current.1 + weight;
+            let q = (cum + merged / 2.0) / total;
+            if merged <= 4.0 * total * q * (1.0 - q) / COMPRESSION {
+                current.0 += (mean - current.0) * weight / merged;
+                current.1 = merged;
+            } else {
+                cum += current.1;
+                self.centroids.push(current);
+                current = (mean, weight);
+            }
+        }
+        self.centroids.push(current);
+    }
+
+    /// The value below which `rank` of the total weight lies, interpolated
+    /// linearly between the centers of adjacent centroids.
+    fn quantile(&self, rank: f64) -> f64 {
+        if self.centroids.is_empty() {
+            return f64::NAN;
+        }
+        let mut prev = (0.0, self.min);
+        let mut cum = 0.0;
+        for &(mean, weight) in &self.centroids {
+            let center = cum + weight / 2.0;
+            if rank <= center {
+                return interpolate(prev, (center, mean), rank);
+            }
+            prev = (center, mean);
+            cum += weight;
+        }
+        interpolate(prev, (self.total(), self.max), rank)
+    }
+}
+
+fn interpolate((x0, y0): (f64, f64), (x1, y1): (f64, f64), x: f64) -> f64 {
+    if x1 <= x0 {
+        y1
+    } else {
+        y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+    }
+}
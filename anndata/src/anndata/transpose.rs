@@ -0,0 +1,232 @@
+use crate::{
+    anndata::AnnData,
+    backend::Backend,
+    data::*,
+    traits::{AnnDataOp, ArrayElemOp, AxisArraysOp, ElemCollectionOp},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use nalgebra_sparse::{CscMatrix, CsrMatrix};
+use ndarray::ArrayD;
+use std::path::Path;
+
+/// Apply `$fun` to the typed matrix inside a dynamically typed sparse matrix.
+macro_rules! convert_sparse {
+    ($data:expr, $from:ident, $to:ident, $fun:ident) => {
+        match $data {
+            $from::I8(x) => $to::I8($fun(x)?),
+            $from::I16(x) => $to::I16($fun(x)?),
+            $from::I32(x) => $to::I32($fun(x)?),
+            $from::I64(x) => $to::I64($fun(x)?),
+            $from::U8(x) => $to::U8($fun(x)?),
+            $from::U16(x) => $to::U16($fun(x)?),
+            $from::U32(x) => $to::U32($fun(x)?),
+            $from::U64(x) => $to::U64($fun(x)?),
+            $from::Usize(x) => $to::Usize($fun(x)?),
+            $from::F16(x) => $to::F16($fun(x)?),
+            $from::F32(x) => $to::F32($fun(x)?),
+            $from::F64(x) => $to::F64($fun(x)?),
+            $from::Bool(x) => $to::Bool($fun(x)?),
+            $from::String(x) => $to::String($fun(x)?),
+        }
+    };
+}
+
+impl<B: Backend> AnnData<B> {
+    /// Save the transpose of the AnnData object, i.e., a variable-by-observation
+    /// matrix, in a new file.
+    ///
+    /// `X` and `layers` are transposed. Sparse matrices are transposed without
+    /// copying their structure: a CSR matrix becomes a CSC matrix and vice versa.
+    /// `obs` and `var`, `obsm` and `varm`, and `obsp` and `varp` are swapped.
+    /// `uns` is copied verbatim.
+    pub fn transpose<P: AsRef<Path>>(&self, out: P) -> Result<Self> {
+        let adata = AnnData::new(out)?;
+
+        if let Some(x) = self.x().get::<ArrayData>()? {
+            adata.set_x(transpose(x)?)?;
+        }
+
+        let var = self.read_var()?;
+        if var.width() > 0 {
+            adata.set_obs(var)?;
+        }
+        let var_names = self.var_names();
+        if !var_names.is_empty() {
+            adata.set_obs_names(var_names)?;
+        }
+        let obs = self.read_obs()?;
+        if obs.width() > 0 {
+            adata.set_var(obs)?;
+        }
+        let obs_names = self.obs_names();
+        if !obs_names.is_empty() {
+            adata.set_var_names(obs_names)?;
+        }
+
+        macro_rules! swap {
+            ($from:ident, $to:ident) => {
+                for key in self.$from().keys() {
+                    let item = self.$from().get_item::<ArrayData>(&key)?.context("missing item")?;
+                    adata.$to().add(&key, item)?;
+                }
+            };
+        }
+        swap!(obsm, varm);
+        swap!(varm, obsm);
+        swap!(obsp, varp);
+        swap!(varp, obsp);
+
+        for key in self.layers().keys() {
+            let item = self.layers().get_item::<ArrayData>(&key)?.context("missing layer")?;
+            adata.layers().add(&key, transpose(item)?)?;
+        }
+
+        for key in self.uns().keys() {
+            let item = self.uns().get_item::<Data>(&key)?.context("missing uns item")?;
+            adata.uns().add(&key, item)?;
+        }
+
+        Ok(adata)
+    }
+}
+
+/// Data whose sparse matrices can be stored in the other compressed layout.
+/// Unlike `transpose`, the conversions keep the shape and re-sort the entries.
+pub trait ColumnMajor: Sized {
+    /// Re-sort a CSR matrix by column into a CSC matrix of the same shape.
+    fn to_csc(self) -> Result<Self>;
+
+    /// Re-sort a CSC matrix by row into a CSR matrix of the same shape.
+    fn to_csr(self) -> Result<Self>;
+}
+
+impl ColumnMajor for ArrayData {
+    fn to_csc(self) -> Result<Self> {
+        let csr = match self {
+            ArrayData::CsrMatrix(x) => x,
+            ArrayData::CsrNonCanonical(x) => x
+                .canonicalize()
+                .map_err(|_| anyhow!("cannot convert a CSR matrix with duplicated entries"))?,
+            x => bail!("cannot convert {} to a CSC matrix", x.data_type()),
+        };
+        Ok(ArrayData::CscMatrix(convert_sparse!(csr, DynCsrMatrix, DynCscMatrix, csr_to_csc)))
+    }
+
+    fn to_csr(self) -> Result<Self> {
+        match self {
+            ArrayData::CscMatrix(x) => Ok(ArrayData::CsrMatrix(
+                convert_sparse!(x, DynCscMatrix, DynCsrMatrix, csc_to_csr),
+            )),
+            x => bail!("cannot convert {} to a CSR matrix", x.data_type()),
+        }
+    }
+}
+
+/// Swap the first two axes of an array.
+pub(crate) fn transpose(data: ArrayData) -> Result<ArrayData> {
+    let data = match data {
+        ArrayData::Array(x) => ArrayData::Array(match x {
+            DynArray::I8(x) => DynArray::I8(transpose_dense(x)),
+            DynArray::I16(x) => DynArray::I16(transpose_dense(x)),
+            DynArray::I32(x) => DynArray::I32(transpose_dense(x)),
+            DynArray::I64(x) => DynArray::I64(transpose_dense(x)),
+            DynArray::U8(x) => DynArray::U8(transpose_dense(x)),
+            DynArray::U16(x) => DynArray::U16(transpose_dense(x)),
+            DynArray::U32(x) => DynArray::U32(transpose_dense(x)),
+            DynArray::U64(x) => DynArray::U64(transpose_dense(x)),
+            DynArray::Usize(x) => DynArray::Usize(transpose_dense(x)),
+            DynArray::F16(x) => DynArray::F16(transpose_dense(x)),
+            DynArray::F32(x) => DynArray::F32(transpose_dense(x)),
+            DynArray::F64(x) => DynArray::F64(transpose_dense(x)),
+            DynArray::ComplexF32(x) => DynArray::ComplexF32(transpose_dense(x)),
+            DynArray::ComplexF64(x) => DynArray::ComplexF64(transpose_dense(x)),
+            DynArray::Bool(x) => DynArray::Bool(transpose_dense(x)),
+            DynArray::String(x) => DynArray::String(transpose_dense(x)),
+            DynArray::Categorical(x) => DynArray::Categorical(CategoricalArray {
+                codes: transpose_dense(x.codes),
+                categories: x.categories,
+                ordered: x.ordered,
+            }),
+        }),
+        ArrayData::CsrMatrix(x) => {
+            ArrayData::CscMatrix(convert_sparse!(x, DynCsrMatrix, DynCscMatrix, csr_transpose))
+        }
+        ArrayData::CscMatrix(x) => {
+            ArrayData::CsrMatrix(convert_sparse!(x, DynCscMatrix, DynCsrMatrix, csc_transpose))
+        }
+        ArrayData::CsrNonCanonical(x) => match x.canonicalize() {
+            Ok(x) => ArrayData::CscMatrix(convert_sparse!(x, DynCsrMatrix, DynCscMatrix, csr_transpose)),
+            Err(_) => bail!("cannot transpose a CSR matrix with duplicated entries"),
+        },
+        ArrayData::RaggedArray(_) => bail!("cannot transpose a ragged array"),
+        ArrayData::RecordArray(_) => bail!("cannot transpose a record array"),
+        ArrayData::DataFrame(_) => bail!("cannot transpose a dataframe"),
+    };
+    Ok(data)
+}
+
+fn transpose_dense<T: Clone>(mut arr: ArrayD<T>) -> ArrayD<T> {
+    arr.swap_axes(0, 1);
+    arr.as_standard_layout().into_owned()
+}
+
+/// The transpose of a CSR matrix is a CSC matrix with the same sparsity pattern.
+fn csr_transpose<T>(csr: CsrMatrix<T>) -> Result<CscMatrix<T>> {
+    let (pattern, values) = csr.into_pattern_and_values();
+    CscMatrix::try_from_pattern_and_values(pattern, values).map_err(|e| anyhow!("{}", e))
+}
+
+/// The transpose of a CSC matrix is a CSR matrix with the same sparsity pattern.
+fn csc_transpose<T>(csc: CscMatrix<T>) -> Result<CsrMatrix<T>> {
+    let (pattern, values) = csc.into_pattern_and_values();
+    CsrMatrix::try_from_pattern_and_values(pattern, values).map_err(|e| anyhow!("{}", e))
+}
+
+/// Re-sort the entries of a CSR matrix by column.
+fn csr_to_csc<T: Clone>(csr: CsrMatrix<T>) -> Result<CscMatrix<T>> {
+    let (offsets, indices, values) =
+        compress_minor(csr.ncols(), csr.row_offsets(), csr.col_indices(), csr.values());
+    CscMatrix::try_from_csc_data(csr.nrows(), csr.ncols(), offsets, indices, values)
+        .map_err(|e| anyhow!("{}", e))
+}
+
+/// Re-sort the entries of a CSC matrix by row.
+fn csc_to_csr<T: Clone>(csc: CscMatrix<T>) -> Result<CsrMatrix<T>> {
+    let (offsets, indices, values) =
+        compress_minor(csc.nrows(), csc.col_offsets(), csc.row_indices(), csc.values());
+    CsrMatrix::try_from_csr_data(csc.nrows(), csc.ncols(), offsets, indices, values)
+        .map_err(|e| anyhow!("{}", e))
+}
+
+/// Compress the entries of a compressed sparse matrix along its minor axis,
+/// which has length `n_minor`. Entries are visited lane by lane, so the
+/// indices within each new lane stay sorted.
+fn compress_minor<T: Clone>(
+    n_minor: usize,
+    offsets: &[usize],
+    indices: &[usize],
+    values: &[T],
+) -> (Vec<usize>, Vec<usize>, Vec<T>) {
+    let mut new_offsets = vec![0; n_minor + 1];
+    for &j in indices {
+        new_offsets[j + 1] += 1;
+    }
+    for j in 0..n_minor {
+        new_offsets[j + 1] += new_offsets[j];
+    }
+
+    let mut next = new_offsets[..n_minor].to_vec();
+    let mut new_indices = vec![0; indices.len()];
+    let mut order = vec![0; indices.len()];
+    for (i, lane) in offsets.windows(2).enumerate() {
+        for k in lane[0]..lane[1] {
+            let pos = &mut next[indices[k]];
+            new_indices[*pos] = i;
+            order[*pos] = k;
+            *pos += 1;
+        }
+    }
+    let new_values = order.into_iter().map(|k| values[k].clone()).collect();
+    (new_offsets, new_indices, new_values)
+}
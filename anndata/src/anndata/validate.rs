@@ -0,0 +1,76 @@
+use crate::{
+    anndata::{AnnData, ArrayInfo},
+    backend::Backend,
+    data::Shape,
+};
+
+use anyhow::Result;
+
+/// An element whose shape is inconsistent with the AnnData object, as
+/// reported by `AnnData::validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationWarning {
+    /// The element, e.g., `X`, `obs` or `obsm/X_pca`.
+    pub element: String,
+    /// The expected sizes of the leading axes.
+    pub expected: Vec<usize>,
+    /// The shape of the element.
+    pub found: Shape,
+}
+
+impl std::fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "'{}' has shape {}, expected the leading axes to be {}",
+            self.element,
+            self.found,
+            Shape::from(self.expected.clone()),
+        )
+    }
+}
+
+impl<B: Backend> AnnData<B> {
+    /// Check that the shapes of all elements are consistent with `n_obs` and
+    /// `n_vars`: `X` and the layers have shape `(n_obs, n_vars)`, obsm and varm
+    /// have `n_obs` and `n_vars` rows, obsp and varp are square, and obs and var
+    /// have `n_obs` and `n_vars` rows. All problems are returned, instead of
+    /// stopping at the first one. Only metadata is read.
+    pub fn validate(&self) -> Result<Vec<ValidationWarning>> {
+        let info = self.describe()?;
+        let (n_obs, n_vars) = (info.n_obs, info.n_vars);
+        let mut warnings = Vec::new();
+        let mut check = |element: String, expected: Vec<usize>, found: &Shape| {
+            let n = expected.len();
+            if found.ndim() < n || found.as_ref()[..n] != expected[..] {
+                warnings.push(ValidationWarning {
+                    element,
+                    expected,
+                    found: found.clone(),
+                });
+            }
+        };
+
+        if let Some(x) = info.x.as_ref() {
+            check("X".to_string(), vec![n_obs, n_vars], &x.shape);
+        }
+        for (name, df, n) in [("obs", &self.obs, n_obs), ("var", &self.var, n_vars)] {
+            if let Some(height) = df.with_inner(|x| x.height()) {
+                check(name.to_string(), vec![n], &height.into());
+            }
+        }
+        let arrays: [(&str, &Vec<(String, ArrayInfo)>, Vec<usize>); 5] = [
+            ("obsm", &info.obsm, vec![n_obs]),
+            ("obsp", &info.obsp, vec![n_obs, n_obs]),
+            ("varm", &info.varm, vec![n_vars]),
+            ("varp", &info.varp, vec![n_vars, n_vars]),
+            ("layers", &info.layers, vec![n_obs, n_vars]),
+        ];
+        for (name, infos, expected) in arrays {
+            for (key, info) in infos {
+                check(format!("{}/{}", name, key), expected.clone(), &info.shape);
+            }
+        }
+        Ok(warnings)
+    }
+}
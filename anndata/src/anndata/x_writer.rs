@@ -0,0 +1,134 @@
+use crate::{
+    anndata::AnnData,
+    backend::{Backend, DataContainer},
+    container::ArrayElem,
+    data::{ArrayChunk, ArrayData, HasShape},
+    traits::AnnDataOp,
+};
+
+use anyhow::{ensure, Context, Result};
+
+/// A sink assembling chunks of rows into X, see [`AnnData::x_writer`].
+///
+/// Chunks are written to the file as they arrive, so that a matrix can be
+/// streamed, and transformed on the way, from one backed AnnData to another
+/// without being loaded into memory:
+///
+/// ```ignore
+/// let chunks = source.x().chunked::<ArrayData>(500).map(|(x, _, _)| normalize(x));
+/// target.x_writer().write_all(chunks)?;
+/// ```
+///
+/// Dense chunks are stacked into a dense array, and the `indptr` of CSR
+/// chunks is shifted and concatenated into a single CSR matrix. The existing X
+/// is removed when the first chunk is written, and the new X is only
+/// registered by `finish`, after checking that its shape is consistent with
+/// `n_obs` and `n_vars`. Dropping the writer before `finish` discards the
+/// chunks written so far.
+pub struct XWriter<'a, B: Backend> {
+    adata: &'a AnnData<B>,
+    container: Option<DataContainer<B>>,
+    n_rows: usize,
+    n_cols: usize,
+    transposed: bool,
+    failed: bool,
+}
+
+impl<B: Backend> AnnData<B> {
+    /// Return a sink writing X chunk by chunk. See [`XWriter`].
+    pub fn x_writer(&self) -> XWriter<'_, B> {
+        XWriter {
+            adata: self,
+            container: None,
+            n_rows: 0,
+            n_cols: 0,
+            transposed: self.is_x_transposed(),
+            failed: false,
+        }
+    }
+}
+
+impl<B: Backend> XWriter<'_, B> {
+    /// The number of rows written so far.
+    pub fn n_rows(&self) -> usize {
+        self.n_rows
+    }
+
+    /// Append the rows of `chunk`. All chunks must have the same number of
+    /// columns and be of the same kind, i.e., all dense or all CSR. If writing
+    /// fails, the chunks written so far are discarded and the writer cannot
+    /// be used anymore.
+    pub fn write<D: Into<ArrayData>>(&mut self, chunk: D) -> Result<()> {
+        ensure!(!self.failed, "cannot write to X after a failed write");
+        let chunk = chunk.into();
+        let shape = chunk.shape();
+        ensure!(shape.ndim() == 2, "chunks of X must be two-dimensional, found shape {}", shape);
+        let result = if let Some(container) = self.container.as_ref() {
+            ensure!(
+                shape[1] == self.n_cols,
+                "expecting chunks with {} columns, found shape {}",
+                self.n_cols,
+                shape,
+            );
+            ArrayData::append_by_chunk(std::iter::once(chunk), container)
+        } else {
+            self.adata.del_x()?;
+            ArrayData::write_by_chunk(std::iter::once(chunk), &self.adata.file, "X")
+                .map(|container| self.container = Some(container))
+        };
+        match result {
+            Ok(()) => {
+                self.n_rows += shape[0];
+                self.n_cols = shape[1];
+                Ok(())
+            }
+            Err(e) => {
+                self.failed = true;
+                self.discard()?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Register the written chunks as X. An error is returned if no chunk was
+    /// written, or if the shape of the matrix does not match `n_obs` or `n_vars`,
+    /// in which case the chunks are discarded.
+    pub fn finish(mut self) -> Result<()> {
+        ensure!(!self.failed, "cannot finish X after a failed write");
+        let container = self.container.take().context("no chunks were written to X")?;
+        let result = self
+            .adata
+            .n_obs
+            .try_set(self.n_rows)
+            .and(self.adata.n_vars.try_set(self.n_cols));
+        match result {
+            Ok(_) => self.adata.replace_x(ArrayElem::try_from(container)?, self.transposed),
+            Err(e) => {
+                DataContainer::delete(container)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Write all chunks produced by `chunks` and then `finish`.
+    pub fn write_all<I, D>(mut self, chunks: I) -> Result<()>
+    where
+        I: IntoIterator<Item = D>,
+        D: Into<ArrayData>,
+    {
+        for chunk in chunks {
+            self.write(chunk)?;
+        }
+        self.finish()
+    }
+
+    fn discard(&mut self) -> Result<()> {
+        self.container.take().map_or(Ok(()), DataContainer::delete)
+    }
+}
+
+impl<B: Backend> Drop for XWriter<'_, B> {
+    fn drop(&mut self) {
+        let _ = self.discard();
+    }
+}
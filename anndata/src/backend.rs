@@ -1,24 +1,89 @@
+pub mod memory;
+pub use memory::InMemory;
+
 use crate::data::{DynArray, DynScalar, SelectInfo, SelectInfoElem, Shape};
+use crate::data::array::ragged_dtype;
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use core::fmt::{Display, Formatter, Debug};
-use ndarray::{Array, ArrayD, ArrayView, RemoveAxis};
+use half::f16;
+use num::complex::Complex;
+use ndarray::{Array, ArrayD, ArrayView, Dimension, Ix1, IxDyn, RemoveAxis, Slice};
 use std::path::{Path, PathBuf};
 
+/// Compression filters for datasets. Backends that do not support a filter
+/// may fall back to another one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Compression {
+    Gzip(u8),
+    Zstd(u8),
+    Lz4,
+}
+
 #[derive(Debug, Clone)]
 pub struct WriteConfig {
-    pub compression: Option<u8>,
+    /// The compression filter of the dataset, gzip level 1 by default. `None`
+    /// disables compression. Datasets with at most 100 elements are never compressed.
+    pub compression: Option<Compression>,
+    /// The chunk shape of the dataset. If `None`, it is chosen by
+    /// `default_block_size` based on the shape and element size of the data.
     pub block_size: Option<Shape>,
+    /// The value of the elements that are never written. It must have the data
+    /// type of the dataset. Blocks of an array that only contain the fill value
+    /// are not written, and are read back as the fill value, which saves space
+    /// when the array is mostly a constant background.
+    pub fill_value: Option<DynScalar>,
 }
 
 impl Default for WriteConfig {
     fn default() -> Self {
         Self {
-            compression: Some(1),
-            //compression: None,
+            compression: Some(Compression::Gzip(1)),
             block_size: None,
+            fill_value: None,
+        }
+    }
+}
+
+/// Guess a chunk shape for a dataset of the given shape and element size (in bytes).
+///
+/// The chunk size grows with the dataset size, from 8KiB to 1MiB, and the chunk
+/// is obtained by repeatedly halving each axis in turn. This is the heuristic
+/// used by h5py, so files written here are chunked the way h5py would chunk them.
+pub fn default_block_size(shape: &[usize], elem_size: usize) -> Shape {
+    const CHUNK_BASE: f64 = (16 * 1024) as f64;
+    const CHUNK_MIN: f64 = (8 * 1024) as f64;
+    const CHUNK_MAX: f64 = (1024 * 1024) as f64;
+
+    if shape.is_empty() {
+        return Shape::from(Vec::new());
+    }
+    let elem_size = elem_size.max(1) as f64;
+    // Unknown or empty dimensions are treated as 1024 elements.
+    let mut chunks: Vec<f64> = shape
+        .iter()
+        .map(|&x| if x == 0 { 1024.0 } else { x as f64 })
+        .collect();
+    let dset_size = chunks.iter().product::<f64>() * elem_size;
+    let target = (CHUNK_BASE * 2f64.powf((dset_size / CHUNK_MAX).log10()))
+        .clamp(CHUNK_MIN, CHUNK_MAX);
+
+    let mut idx = 0;
+    loop {
+        let chunk_bytes = chunks.iter().product::<f64>() * elem_size;
+        if (chunk_bytes < target || (chunk_bytes - target).abs() / target < 0.5)
+            && chunk_bytes < CHUNK_MAX
+        {
+            break;
         }
+        if chunks.iter().product::<f64>() == 1.0 {
+            break;
+        }
+        let i = idx % chunks.len();
+        chunks[i] = (chunks[i] / 2.0).ceil();
+        idx += 1;
     }
+    chunks.into_iter().map(|x| x as usize).collect()
 }
 
 pub trait Backend: 'static {
@@ -51,6 +116,13 @@ pub trait FileOp {
 
     /// Close the file.
     fn close(self) -> Result<()>;
+
+    /// Write buffered data to storage. Once this returns, everything written
+    /// before the call survives a crash of the process. Backends that do not
+    /// buffer writes can rely on the default, which does nothing.
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub trait GroupOp {
@@ -80,12 +152,44 @@ pub trait GroupOp {
     /// Check if a group or dataset exists.
     fn exists(&self, name: &str) -> Result<bool>;
 
+    /// Rename a group or dataset. It is an error if `new_name` already exists.
+    /// Backends that do not support renaming can rely on the default, which
+    /// returns an error.
+    fn rename(&self, name: &str, new_name: &str) -> Result<()> {
+        let _ = new_name;
+        bail!("cannot rename '{}': rename is not supported by the {} backend", name, Self::Backend::NAME)
+    }
+
+    /// Create a link named `name` to the group or dataset at `target_path` in
+    /// another file. Objects opened through the link are read from that file.
+    /// Backends that do not support external links can rely on the default,
+    /// which returns an error.
+    fn new_external_link(&self, name: &str, target_file: &Path, target_path: &str) -> Result<()> {
+        let _ = (target_file, target_path);
+        bail!("cannot create '{}': external links are not supported by the {} backend", name, Self::Backend::NAME)
+    }
+
     fn create_scalar_data<D: BackendData>(
         &self,
         name: &str,
         data: &D,
     ) -> Result<<Self::Backend as Backend>::Dataset>;
 
+    /// Create a compound dataset holding a one-dimensional array of records,
+    /// i.e., a NumPy structured array, from the names and values of its fields.
+    /// The values are one-dimensional arrays of numbers, booleans or strings of
+    /// the same length. Backends without compound types can rely on the default,
+    /// which returns an error.
+    fn create_record_data(
+        &self,
+        name: &str,
+        fields: &[(String, DynArray)],
+        config: WriteConfig,
+    ) -> Result<<Self::Backend as Backend>::Dataset> {
+        let _ = (fields, config);
+        bail!("cannot create '{}': compound datasets are not supported by the {} backend", name, Self::Backend::NAME)
+    }
+
     fn create_array_data<'a, A, D, Dim>(
         &self,
         name: &str,
@@ -99,26 +203,63 @@ pub trait GroupOp {
     {
         let arr_view = arr.into();
         let shape = arr_view.shape();
-        let block_size = config.block_size.unwrap_or_else(|| if shape.len() == 1 {
-            shape[0].min(10000).into()
-        } else {
-            shape.iter().map(|&x| x.min(100)).collect()
-        });
+        let block_size = config
+            .block_size
+            .unwrap_or_else(|| default_block_size(shape, std::mem::size_of::<D>()));
         let compression = if arr_view.len() > 100 {
             config.compression
         } else {
             None
         };
+        let fill = config
+            .fill_value
+            .clone()
+            .map(|x| D::from_dyn(x).with_context(|| format!("the fill value must have type {}", D::DTYPE)))
+            .transpose()?;
         let new_config = WriteConfig {
             compression: compression,
-            block_size: Some(block_size),
+            block_size: Some(block_size.clone()),
+            fill_value: config.fill_value,
         };
         let dataset = self.new_dataset::<D>(name, &shape.into(), new_config)?;
-        dataset.write_array(arr_view)?;
+        match fill {
+            Some(fill) => write_blocks(&dataset, arr_view, block_size.as_ref(), &fill.into_dyn())?,
+            None => dataset.write_array(arr_view)?,
+        }
         Ok(dataset)
     }
 }
 
+/// Write the blocks of `arr` that contain values other than `fill`. The other
+/// blocks are left unwritten, so that they are read as the fill value of the
+/// dataset.
+fn write_blocks<T, D, Dim>(dataset: &T, arr: ArrayView<'_, D, Dim>, block_size: &[usize], fill: &DynScalar) -> Result<()>
+where
+    T: DatasetOp,
+    D: BackendData,
+    Dim: RemoveAxis,
+{
+    let shape = arr.shape().to_vec();
+    if block_size.len() != shape.len() || block_size.contains(&0) || shape.contains(&0) {
+        return dataset.write_array(arr);
+    }
+    let grid: Vec<usize> = shape.iter().zip(block_size).map(|(n, b)| (n + b - 1) / b).collect();
+    for idx in ndarray::indices(IxDyn(&grid)) {
+        let ranges: Vec<_> = idx
+            .slice()
+            .iter()
+            .zip(block_size.iter().zip(&shape))
+            .map(|(i, (b, n))| i * b..((i + 1) * b).min(*n))
+            .collect();
+        let block = arr.slice_each_axis(|ax| Slice::from(ranges[ax.axis.index()].clone()));
+        if block.iter().any(|x| x.into_dyn() != *fill) {
+            let selection: Vec<SelectInfoElem> = ranges.into_iter().map(|x| Slice::from(x).into()).collect();
+            dataset.write_array_slice(block, selection.as_slice())?;
+        }
+    }
+    Ok(())
+}
+
 pub trait LocationOp {
     type Backend: Backend;
 
@@ -146,6 +287,17 @@ pub trait LocationOp {
     fn read_str_attr(&self, name: &str) -> Result<String> {
         self.read_scalar_attr(name)
     }
+    /// Read the shape of a sparse matrix from the `shape` attribute, or from
+    /// the `h5sparse_shape` attribute used by the legacy h5sparse layout.
+    fn read_shape_attr(&self) -> Result<Vec<usize>> {
+        match self.read_array_attr::<usize, Ix1>("shape") {
+            Ok(shape) => Ok(shape.to_vec()),
+            Err(e) => self
+                .read_array_attr::<usize, Ix1>("h5sparse_shape")
+                .map(|x| x.to_vec())
+                .map_err(|_| e),
+        }
+    }
 }
 
 pub trait DatasetOp {
@@ -157,6 +309,24 @@ pub trait DatasetOp {
 
     fn read_scalar<T: BackendData>(&self) -> Result<T>;
 
+    /// The value of the elements that have not been written, if it was set when
+    /// the dataset was created. Backends without fill values return `None`.
+    fn fill_value(&self) -> Result<Option<DynScalar>> {
+        Ok(None)
+    }
+
+    /// Whether the dataset has a compound type, i.e., holds records with named
+    /// fields. Complex numbers are not records.
+    fn is_record(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Read the names and values of the fields of a one-dimensional compound
+    /// dataset. See `GroupOp::create_record_data`.
+    fn read_record(&self) -> Result<Vec<(String, DynArray)>> {
+        bail!("compound datasets are not supported by the {} backend", Self::Backend::NAME)
+    }
+
     fn read_array<T: BackendData, D>(&self) -> Result<Array<T, D>>
     where
         D: RemoveAxis,
@@ -202,6 +372,8 @@ pub enum DataType {
     Categorical,
     CsrMatrix(ScalarType),
     CscMatrix(ScalarType),
+    RaggedArray(ScalarType),
+    RecordArray,
     DataFrame,
     Scalar(ScalarType),
     Mapping,
@@ -214,6 +386,8 @@ impl Display for DataType {
             DataType::Categorical => write!(f, "Categorical"),
             DataType::CsrMatrix(t) => write!(f, "CsrMatrix({})", t),
             DataType::CscMatrix(t) => write!(f, "CscMatrix({})", t),
+            DataType::RaggedArray(t) => write!(f, "RaggedArray({})", t),
+            DataType::RecordArray => write!(f, "RecordArray"),
             DataType::DataFrame => write!(f, "DataFrame"),
             DataType::Scalar(t) => write!(f, "Scalar({})", t),
             DataType::Mapping => write!(f, "Mapping"),
@@ -233,8 +407,13 @@ pub enum ScalarType {
     U32,
     U64,
     Usize,
+    F16,
     F32,
     F64,
+    /// Complex numbers with `f32` real and imaginary parts, `complex64` in NumPy.
+    ComplexF32,
+    /// Complex numbers with `f64` real and imaginary parts, `complex128` in NumPy.
+    ComplexF64,
     Bool,
     String,
 }
@@ -251,14 +430,101 @@ impl Display for ScalarType {
             ScalarType::U32 => write!(f, "u32"),
             ScalarType::U64 => write!(f, "u64"),
             ScalarType::Usize => write!(f, "usize"),
+            ScalarType::F16 => write!(f, "f16"),
             ScalarType::F32 => write!(f, "f32"),
             ScalarType::F64 => write!(f, "f64"),
+            ScalarType::ComplexF32 => write!(f, "complex<f32>"),
+            ScalarType::ComplexF64 => write!(f, "complex<f64>"),
             ScalarType::Bool => write!(f, "bool"),
             ScalarType::String => write!(f, "string"),
         }
     }
 }
 
+impl ScalarType {
+    /// The smallest type to which values of both `self` and `other` can be cast,
+    /// following the promotion rules of NumPy, e.g., `i32` and `i64` give `i64`,
+    /// `u8` and `i8` give `i16`, and `i32` and `f32` give `f64`. 64-bit integers
+    /// mixed with unsigned 64-bit integers or floats are promoted to `f64`.
+    /// Returns `None` if the types are incompatible, e.g., strings and floats.
+    pub fn promote(self, other: Self) -> Option<Self> {
+        use ScalarType::*;
+        /// The kind of a numeric type (0: unsigned, 1: signed, 2: float,
+        /// 3: complex) and its size in bytes.
+        fn kind(ty: ScalarType) -> Option<(u8, usize)> {
+            match ty {
+                U8 => Some((0, 1)),
+                U16 => Some((0, 2)),
+                U32 => Some((0, 4)),
+                U64 | Usize => Some((0, 8)),
+                I8 => Some((1, 1)),
+                I16 => Some((1, 2)),
+                I32 => Some((1, 4)),
+                I64 => Some((1, 8)),
+                F16 => Some((2, 2)),
+                F32 => Some((2, 4)),
+                F64 => Some((2, 8)),
+                ComplexF32 => Some((3, 8)),
+                ComplexF64 => Some((3, 16)),
+                Bool | String => None,
+            }
+        }
+        fn from_kind(kind: u8, size: usize) -> ScalarType {
+            match (kind, size) {
+                (0, 1) => U8,
+                (0, 2) => U16,
+                (0, 4) => U32,
+                (0, _) => U64,
+                (1, 1) => I8,
+                (1, 2) => I16,
+                (1, 4) => I32,
+                (1, _) => I64,
+                (2, 2) => F16,
+                (2, 4) => F32,
+                (2, _) => F64,
+                (_, 8) => ComplexF32,
+                _ => ComplexF64,
+            }
+        }
+
+        if self == other {
+            return Some(self);
+        }
+        let (k1, s1) = kind(self)?;
+        let (k2, s2) = kind(other)?;
+        let ((k1, s1), (k2, s2)) = if k1 <= k2 { ((k1, s1), (k2, s2)) } else { ((k2, s2), (k1, s1)) };
+        let ty = match (k1, k2) {
+            _ if k1 == k2 => from_kind(k1, s1.max(s2)),
+            // Signed integers need one more bit to hold all unsigned values.
+            (0, 1) if s1 < 8 => from_kind(1, (2 * s1).max(s2)),
+            (0, 1) => F64,
+            // A float of size `s` holds integers of size `s / 2` exactly.
+            (_, 2) => from_kind(2, (2 * s1).max(s2).min(8)),
+            // Complex numbers hold two floats.
+            (2, 3) => from_kind(3, (2 * s1).max(s2)),
+            _ => from_kind(3, (4 * s1).max(s2).min(16)),
+        };
+        Some(ty)
+    }
+}
+
+impl DataType {
+    /// The type of the array obtained by stacking arrays of type `self` and
+    /// `other`. Arrays of the same kind, e.g., two CSR matrices, are stacked
+    /// after promoting their scalar types with [`ScalarType::promote`].
+    /// Returns `None` if the arrays cannot be stacked together.
+    pub fn promote(self, other: Self) -> Option<Self> {
+        match (self, other) {
+            (DataType::Array(a), DataType::Array(b)) => a.promote(b).map(DataType::Array),
+            (DataType::CsrMatrix(a), DataType::CsrMatrix(b)) => a.promote(b).map(DataType::CsrMatrix),
+            (DataType::CscMatrix(a), DataType::CscMatrix(b)) => a.promote(b).map(DataType::CscMatrix),
+            (DataType::RaggedArray(a), DataType::RaggedArray(b)) => a.promote(b).map(DataType::RaggedArray),
+            (a, b) if a == b => Some(a),
+            _ => None,
+        }
+    }
+}
+
 pub enum DataContainer<B: Backend> {
     Group(B::Group),
     Dataset(B::Dataset),
@@ -339,12 +605,19 @@ impl<B: Backend> DataContainer<B> {
 
     pub fn encoding_type(&self) -> Result<DataType> {
         let enc = match self {
+            // Sparse matrices in the legacy h5sparse layout only have the
+            // `h5sparse_format` attribute, i.e., "csr" or "csc".
             DataContainer::Group(group) => group
                 .read_str_attr("encoding-type")
+                .or_else(|_| group.read_str_attr("h5sparse_format").map(|x| format!("{}_matrix", x)))
                 .unwrap_or("mapping".to_string()),
-            DataContainer::Dataset(dataset) => dataset
-                .read_str_attr("encoding-type")
-                .unwrap_or("numeric-scalar".to_string()),
+            // Structured arrays written by h5py are compound datasets without
+            // encoding attributes.
+            DataContainer::Dataset(dataset) => match dataset.read_str_attr("encoding-type") {
+                Ok(enc) => enc,
+                Err(_) if dataset.is_record()? => "rec-array".to_string(),
+                Err(_) => "numeric-scalar".to_string(),
+            },
         };
         let ty = match enc.as_str() {
             "string" => DataType::Scalar(ScalarType::String),
@@ -360,6 +633,8 @@ impl<B: Backend> DataContainer<B> {
                 let ty = self.as_group()?.open_dataset("data")?.dtype()?;
                 DataType::CsrMatrix(ty)
             },
+            "awkward-array" => DataType::RaggedArray(ragged_dtype(self.as_group()?)?),
+            "rec-array" => DataType::RecordArray,
             "dataframe" => DataType::DataFrame,
             "mapping" | "dict" => DataType::Mapping,
             ty => bail!("Unsupported type '{}'", ty),
@@ -642,6 +917,34 @@ impl BackendData for usize {
     }
 }
 
+impl BackendData for f16 {
+    const DTYPE: ScalarType = ScalarType::F16;
+
+    fn into_dyn(&self) -> DynScalar {
+        DynScalar::F16(*self)
+    }
+
+    fn into_dyn_arr<'a, D>(arr: ArrayView<'a, Self, D>) -> DynArrayView<'a, D> {
+        DynArrayView::F16(arr)
+    }
+
+    fn from_dyn(x: DynScalar) -> Result<Self> {
+        if let DynScalar::F16(x) = x {
+            Ok(x)
+        } else {
+            bail!("Expecting f16")
+        }
+    }
+
+    fn from_dyn_arr(x: DynArray) -> Result<ArrayD<Self>> {
+        if let DynArray::F16(x) = x {
+            Ok(x)
+        } else {
+            bail!("Expecting f16 array")
+        }
+    }
+}
+
 impl BackendData for f32 {
     const DTYPE: ScalarType = ScalarType::F32;
 
@@ -698,6 +1001,62 @@ impl BackendData for f64 {
     }
 }
 
+impl BackendData for Complex<f32> {
+    const DTYPE: ScalarType = ScalarType::ComplexF32;
+
+    fn into_dyn(&self) -> DynScalar {
+        DynScalar::ComplexF32(*self)
+    }
+
+    fn into_dyn_arr<'a, D>(arr: ArrayView<'a, Self, D>) -> DynArrayView<'a, D> {
+        DynArrayView::ComplexF32(arr)
+    }
+
+    fn from_dyn(x: DynScalar) -> Result<Self> {
+        if let DynScalar::ComplexF32(x) = x {
+            Ok(x)
+        } else {
+            bail!("Expecting complex<f32>")
+        }
+    }
+
+    fn from_dyn_arr(x: DynArray) -> Result<ArrayD<Self>> {
+        if let DynArray::ComplexF32(x) = x {
+            Ok(x)
+        } else {
+            bail!("Expecting complex<f32> array")
+        }
+    }
+}
+
+impl BackendData for Complex<f64> {
+    const DTYPE: ScalarType = ScalarType::ComplexF64;
+
+    fn into_dyn(&self) -> DynScalar {
+        DynScalar::ComplexF64(*self)
+    }
+
+    fn into_dyn_arr<'a, D>(arr: ArrayView<'a, Self, D>) -> DynArrayView<'a, D> {
+        DynArrayView::ComplexF64(arr)
+    }
+
+    fn from_dyn(x: DynScalar) -> Result<Self> {
+        if let DynScalar::ComplexF64(x) = x {
+            Ok(x)
+        } else {
+            bail!("Expecting complex<f64>")
+        }
+    }
+
+    fn from_dyn_arr(x: DynArray) -> Result<ArrayD<Self>> {
+        if let DynArray::ComplexF64(x) = x {
+            Ok(x)
+        } else {
+            bail!("Expecting complex<f64> array")
+        }
+    }
+}
+
 impl BackendData for String {
     const DTYPE: ScalarType = ScalarType::String;
 
@@ -764,8 +1123,11 @@ pub enum DynArrayView<'a, D> {
     U32(ArrayView<'a, u32, D>),
     U64(ArrayView<'a, u64, D>),
     Usize(ArrayView<'a, usize, D>),
+    F16(ArrayView<'a, f16, D>),
     F32(ArrayView<'a, f32, D>),
     F64(ArrayView<'a, f64, D>),
+    ComplexF32(ArrayView<'a, Complex<f32>, D>),
+    ComplexF64(ArrayView<'a, Complex<f64>, D>),
     String(ArrayView<'a, String, D>),
     Bool(ArrayView<'a, bool, D>),
 }
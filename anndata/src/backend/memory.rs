@@ -1,570 +1,803 @@
+//! An in-memory backend.
+//!
+//! A file created by `MemFile::new`, e.g., through `AnnData::in_memory`, has
+//! no name and is owned by its handles: the groups and datasets opened from it
+//! share the data, which is released once the last of them is closed or
+//! dropped.
+//!
+//! A file created by `InMemory::create` behaves like a file on disk instead.
+//! It is registered under its path, so that it can be reopened by
+//! `InMemory::open`, and it is kept until another file is created with the
+//! same path or it is removed by `InMemory::remove_file`. Nothing is ever
+//! written to disk; use `AnnData::write` with a persistent backend to dump the
+//! data.
+
 use crate::{
     backend::{
         Backend, BackendData, DatasetOp, DynArrayView, FileOp, GroupOp, LocationOp, ScalarType,
-        SelectInfoElem,
+        WriteConfig,
     },
-    data::{BoundedSelectInfo, DynArray, DynScalar, Shape, ArrayOp},
+    data::{ArrayOp, BoundedSelectInfo, DynArray, DynScalar, HasShape, RecordArray, SelectInfoElem, Shape},
 };
 
-use anyhow::{bail, Result};
-use ndarray::{Array, ArrayView, Dimension, SliceInfo};
+use anyhow::{bail, ensure, Context, Result};
+use half::f16;
+use ndarray::{Array, ArrayD, ArrayView, Dimension, Ix0, IxDyn, RemoveAxis, Slice, SliceInfo};
+use parking_lot::{const_mutex, Mutex, RwLock};
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+///////////////////////////////////////////////////////////////////////////////
+/// Type definitions
+///////////////////////////////////////////////////////////////////////////////
+
+/// A backend that keeps all groups and datasets in memory.
+pub struct InMemory;
+
+/// The files created by `InMemory::create`, keyed by their paths.
+static FILES: Mutex<BTreeMap<PathBuf, NodeRef>> = const_mutex(BTreeMap::new());
+
+impl InMemory {
+    /// Remove the file created by `InMemory::create` with the given path. The
+    /// handles opened from it keep the data until they are dropped.
+    pub fn remove_file<P: AsRef<Path>>(path: P) -> Result<()> {
+        let path = path.as_ref();
+        FILES.lock()
+            .remove(path)
+            .with_context(|| format!("No in-memory file named '{}'", path.display()))?;
+        Ok(())
+    }
+}
 
-/// In-memory backend.
-pub struct Mem;
+pub struct MemFile(Location);
 
-pub struct File;
-pub struct Group;
-pub struct Dataset;
+impl MemFile {
+    /// Create an empty in-memory file without a name.
+    pub fn new() -> Self {
+        MemFile(Location::root(new_node(Node::new_group()), PathBuf::new(), true))
+    }
+}
 
-impl Backend for Mem {
-    type File = File;
+impl Default for MemFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-    type Group = Group;
+pub struct MemGroup(Location);
 
-    type Dataset = Dataset;
+pub struct MemDataset(Location);
 
-    fn create<P: AsRef<Path>>(path: P) -> Result<Self::File> {
-        Ok(File::create(path)?)
-    }
+type Attributes = HashMap<String, DynArray>;
+
+/// A node shared by its parent group and the handles opened on it, so that a
+/// handle stays valid after the node is deleted or renamed.
+type NodeRef = Arc<RwLock<Node>>;
+
+fn new_node(node: Node) -> NodeRef {
+    Arc::new(RwLock::new(node))
 }
 
-impl FileOp for File {
-    type Backend = H5;
+enum Node {
+    Group {
+        members: BTreeMap<String, NodeRef>,
+        attrs: Attributes,
+    },
+    Dataset {
+        data: DynArray,
+        attrs: Attributes,
+    },
+    /// A compound dataset, see `GroupOp::create_record_data`.
+    Record {
+        fields: Vec<(String, DynArray)>,
+        attrs: Attributes,
+    },
+}
 
-    fn filename(&self) -> PathBuf {
-        hdf5::Location::filename(&self).into()
+impl Node {
+    fn new_group() -> Self {
+        Node::Group {
+            members: BTreeMap::new(),
+            attrs: HashMap::new(),
+        }
     }
 
-    fn close(self) -> Result<()> {
-        Ok(self.close()?)
+    fn new_dataset(data: DynArray) -> Self {
+        Node::Dataset {
+            data,
+            attrs: HashMap::new(),
+        }
     }
-}
 
-impl LocationOp for Group {
-    type Backend = H5;
+    fn members(&self) -> Result<&BTreeMap<String, NodeRef>> {
+        match self {
+            Node::Group { members, .. } => Ok(members),
+            _ => bail!("Expecting Group"),
+        }
+    }
 
-    fn file(&self) -> Result<<Self::Backend as Backend>::File> {
-        Ok(hdf5::Location::file(&self)?)
+    fn members_mut(&mut self) -> Result<&mut BTreeMap<String, NodeRef>> {
+        match self {
+            Node::Group { members, .. } => Ok(members),
+            _ => bail!("Expecting Group"),
+        }
     }
 
-    fn path(&self) -> PathBuf {
-        hdf5::Location::name(&self).into()
+    fn data(&self) -> Result<&DynArray> {
+        match self {
+            Node::Dataset { data, .. } => Ok(data),
+            Node::Record { .. } => bail!("Cannot access a compound dataset as an array"),
+            Node::Group { .. } => bail!("Expecting Dataset"),
+        }
     }
 
-    fn write_arr_attr<'a, A, D, Dim>(&self, name: &str, value: A) -> Result<()>
-    where
-        A: Into<ArrayView<'a, D, Dim>>,
-        D: BackendData,
-        Dim: Dimension,
-    {
-        write_arr_attr(self, name, value)
+    fn data_mut(&mut self) -> Result<&mut DynArray> {
+        match self {
+            Node::Dataset { data, .. } => Ok(data),
+            Node::Record { .. } => bail!("Cannot access a compound dataset as an array"),
+            Node::Group { .. } => bail!("Expecting Dataset"),
+        }
     }
 
-    fn write_str_attr(&self, name: &str, value: &str) -> Result<()> {
-        let value_: VarLenUnicode = value.parse().unwrap();
-        let attr = self
-            .attr(name)
-            .or(self.new_attr::<VarLenUnicode>().create(name))?;
-        attr.write_scalar(&value_)?;
-        Ok(())
+    fn fields(&self) -> Result<&Vec<(String, DynArray)>> {
+        match self {
+            Node::Record { fields, .. } => Ok(fields),
+            _ => bail!("Expecting compound dataset"),
+        }
     }
 
-    fn read_str_attr(&self, name: &str) -> Result<String> {
-        let container = self.attr(name)?;
-        match container.dtype()?.to_descriptor()? {
-            TypeDescriptor::VarLenAscii => {
-                let attr: VarLenAscii = container.read_scalar()?;
-                Ok(attr.parse().unwrap())
-            }
-            TypeDescriptor::VarLenUnicode => {
-                let attr: VarLenUnicode = container.read_scalar()?;
-                Ok(attr.parse().unwrap())
-            }
-            ty => {
-                panic!("Cannot read string from type '{}'", ty);
-            }
+    /// The shape of a dataset, or `None` for a group.
+    fn shape(&self) -> Option<Shape> {
+        match self {
+            Node::Dataset { data, .. } => Some(data.shape()),
+            Node::Record { fields, .. } => Some(fields[0].1.shape()),
+            Node::Group { .. } => None,
+        }
+    }
+
+    fn attrs(&self) -> &Attributes {
+        match self {
+            Node::Group { attrs, .. } | Node::Dataset { attrs, .. } | Node::Record { attrs, .. } => attrs,
         }
     }
 
-    fn read_arr_attr<T: BackendData, D: Dimension>(&self, name: &str) -> Result<Array<T, D>> {
-        read_arr_attr(self, name)
+    fn attrs_mut(&mut self) -> &mut Attributes {
+        match self {
+            Node::Group { attrs, .. } | Node::Dataset { attrs, .. } | Node::Record { attrs, .. } => attrs,
+        }
     }
 }
 
-impl LocationOp for Dataset {
-    type Backend = H5;
+/// Find the node at `path` from `root`.
+fn lookup(root: &NodeRef, path: &[String]) -> Result<NodeRef> {
+    path.iter().try_fold(root.clone(), |node, name| {
+        let guard = node.read();
+        let child = match &*guard {
+            Node::Group { members, .. } => members
+                .get(name)
+                .cloned()
+                .with_context(|| format!("No group or dataset named '{}'", name))?,
+            _ => bail!("Cannot find '{}' in a dataset", name),
+        };
+        Ok(child)
+    })
+}
+
+/// A handle to a node in the tree of an in-memory file.
+#[derive(Clone)]
+struct Location {
+    root: NodeRef,
+    node: NodeRef,
+    filename: PathBuf,
+    path: Vec<String>,
+    writable: bool,
+}
 
-    fn file(&self) -> Result<<Self::Backend as Backend>::File> {
-        Ok(hdf5::Location::file(&self)?)
+impl Location {
+    fn root(root: NodeRef, filename: PathBuf, writable: bool) -> Self {
+        Self {
+            node: root.clone(),
+            root,
+            filename,
+            path: Vec::new(),
+            writable,
+        }
     }
 
-    fn path(&self) -> PathBuf {
-        hdf5::Location::name(&self).into()
+    fn with_node(&self, path: Vec<String>, node: NodeRef) -> Self {
+        Self {
+            root: self.root.clone(),
+            node,
+            filename: self.filename.clone(),
+            path,
+            writable: self.writable,
+        }
     }
 
-    fn write_arr_attr<'a, A, D, Dim>(&self, name: &str, value: A) -> Result<()>
-    where
-        A: Into<ArrayView<'a, D, Dim>>,
-        D: BackendData,
-        Dim: Dimension,
-    {
-        write_arr_attr(self, name, value)
+    /// Open the node at `path` from the root.
+    fn at(&self, path: Vec<String>) -> Result<Self> {
+        let node = lookup(&self.root, &path)?;
+        Ok(self.with_node(path, node))
     }
 
-    fn write_str_attr(&self, name: &str, value: &str) -> Result<()> {
-        let value_: VarLenUnicode = value.parse().unwrap();
-        let attr = self
-            .attr(name)
-            .or(self.new_attr::<VarLenUnicode>().create(name))?;
-        attr.write_scalar(&value_)?;
-        Ok(())
+    /// Resolve `name` into a path from the root. Absolute names start with '/'.
+    fn resolve(&self, name: &str) -> Vec<String> {
+        let mut path = if name.starts_with('/') {
+            Vec::new()
+        } else {
+            self.path.clone()
+        };
+        path.extend(name.split('/').filter(|x| !x.is_empty()).map(str::to_string));
+        path
     }
 
-    fn read_str_attr(&self, name: &str) -> Result<String> {
-        let container = self.attr(name)?;
-        match container.dtype()?.to_descriptor()? {
-            TypeDescriptor::VarLenAscii => {
-                let attr: VarLenAscii = container.read_scalar()?;
-                Ok(attr.parse().unwrap())
-            }
-            TypeDescriptor::VarLenUnicode => {
-                let attr: VarLenUnicode = container.read_scalar()?;
-                Ok(attr.parse().unwrap())
-            }
-            ty => {
-                panic!("Cannot read string from type '{}'", ty);
-            }
-        }
+    fn read<R>(&self, f: impl FnOnce(&Node) -> Result<R>) -> Result<R> {
+        f(&self.node.read())
     }
 
-    fn read_arr_attr<T: BackendData, D: Dimension>(&self, name: &str) -> Result<Array<T, D>> {
-        read_arr_attr(self, name)
+    fn write<R>(&self, f: impl FnOnce(&mut Node) -> Result<R>) -> Result<R> {
+        ensure!(self.writable, "Cannot modify a file opened in read-only mode");
+        f(&mut self.node.write())
     }
-}
 
-impl GroupOp for Group {
-    type Backend = H5;
+    fn insert(&self, name: &str, node: NodeRef) -> Result<Location> {
+        let path = self.resolve(name);
+        let (last, parent) = path.split_last().context("Cannot replace the root group")?;
+        self.at(parent.to_vec())?.write(|p| {
+            let members = p.members_mut()?;
+            ensure!(!members.contains_key(last), "'{}' already exists", name);
+            members.insert(last.clone(), node.clone());
+            Ok(())
+        })?;
+        Ok(self.with_node(path, node))
+    }
 
     fn list(&self) -> Result<Vec<String>> {
-        Ok(self.member_names()?)
+        self.read(|node| Ok(node.members()?.keys().cloned().collect()))
+    }
+
+    fn open_group(&self, name: &str) -> Result<MemGroup> {
+        let loc = self.at(self.resolve(name))
+            .and_then(|loc| {
+                loc.read(|node| node.members().map(|_| ()))?;
+                Ok(loc)
+            })
+            .with_context(|| format!("Cannot open group '{}'", name))?;
+        Ok(MemGroup(loc))
+    }
+
+    fn open_dataset(&self, name: &str) -> Result<MemDataset> {
+        let loc = self.at(self.resolve(name))
+            .and_then(|loc| {
+                loc.read(|node| node.shape().map(|_| ()).context("Expecting Dataset"))?;
+                Ok(loc)
+            })
+            .with_context(|| format!("Cannot open dataset '{}'", name))?;
+        Ok(MemDataset(loc))
+    }
+
+    fn new_dataset<T: BackendData>(&self, name: &str, shape: &Shape, fill: Option<DynScalar>) -> Result<MemDataset> {
+        let shape = shape.as_ref();
+        if let Some(fill) = fill {
+            let fill = T::from_dyn(fill).with_context(|| format!("the fill value must have type {}", T::DTYPE))?;
+            let data = view_to_array(T::into_dyn_arr(ArrayD::from_elem(shape, fill).view()));
+            return self.insert(name, new_node(Node::new_dataset(data))).map(MemDataset);
+        }
+        let data = match T::DTYPE {
+            ScalarType::I8 => DynArray::I8(ArrayD::default(shape)),
+            ScalarType::I16 => DynArray::I16(ArrayD::default(shape)),
+            ScalarType::I32 => DynArray::I32(ArrayD::default(shape)),
+            ScalarType::I64 => DynArray::I64(ArrayD::default(shape)),
+            ScalarType::U8 => DynArray::U8(ArrayD::default(shape)),
+            ScalarType::U16 => DynArray::U16(ArrayD::default(shape)),
+            ScalarType::U32 => DynArray::U32(ArrayD::default(shape)),
+            ScalarType::U64 => DynArray::U64(ArrayD::default(shape)),
+            ScalarType::Usize => DynArray::Usize(ArrayD::default(shape)),
+            ScalarType::F16 => DynArray::F16(ArrayD::default(shape)),
+            ScalarType::F32 => DynArray::F32(ArrayD::default(shape)),
+            ScalarType::F64 => DynArray::F64(ArrayD::default(shape)),
+            ScalarType::ComplexF32 => DynArray::ComplexF32(ArrayD::default(shape)),
+            ScalarType::ComplexF64 => DynArray::ComplexF64(ArrayD::default(shape)),
+            ScalarType::Bool => DynArray::Bool(ArrayD::default(shape)),
+            ScalarType::String => DynArray::String(ArrayD::default(shape)),
+        };
+        self.insert(name, new_node(Node::new_dataset(data))).map(MemDataset)
     }
 
-    fn create_group(&self, name: &str) -> Result<<Self::Backend as Backend>::Group> {
-        Ok(hdf5::Group::create_group(self, name)?)
+    /// Unlink a member from its parent group and return it.
+    fn remove(&self, name: &str) -> Result<NodeRef> {
+        let path = self.resolve(name);
+        let (last, parent) = path.split_last().context("Cannot remove the root group")?;
+        self.at(parent.to_vec())?.write(|p| {
+            p.members_mut()?
+                .remove(last)
+                .with_context(|| format!("No group or dataset named '{}'", name))
+        })
     }
 
-    fn open_group(&self, name: &str) -> Result<<Self::Backend as Backend>::Group> {
-        Ok(self.group(name)?)
+    fn delete(&self, name: &str) -> Result<()> {
+        self.remove(name)?;
+        Ok(())
     }
 
-    fn open_dataset(&self, name: &str) -> Result<<Self::Backend as Backend>::Dataset> {
-        Ok(self.dataset(name)?)
+    fn exists(&self, name: &str) -> Result<bool> {
+        Ok(lookup(&self.root, &self.resolve(name)).is_ok())
     }
 
-    fn delete(&self, name: &str) -> Result<()> {
-        Ok(self.unlink(name)?)
+    fn rename(&self, name: &str, new_name: &str) -> Result<()> {
+        ensure!(!self.exists(new_name)?, "'{}' already exists", new_name);
+        let node = self.remove(name)?;
+        self.insert(new_name, node)?;
+        Ok(())
     }
 
-    fn exists(&self, name: &str) -> Result<bool> {
-        Ok(self.link_exists(name))
+    fn file(&self) -> MemFile {
+        MemFile(Location::root(self.root.clone(), self.filename.clone(), self.writable))
     }
 
-    fn write_scalar<D: BackendData>(
-        &self,
-        name: &str,
-        data: &D,
-    ) -> Result<<Self::Backend as Backend>::Dataset> {
-        write_scalar(self, name, data)
+    fn path(&self) -> PathBuf {
+        format!("/{}", self.path.join("/")).into()
     }
 
-    fn write_array<'a, A, D, Dim>(
-        &self,
-        name: &str,
-        data: A,
-    ) -> Result<<Self::Backend as Backend>::Dataset>
-    where
-        A: Into<ArrayView<'a, D, Dim>>,
-        D: BackendData,
-        Dim: Dimension,
-    {
-        write_array(self, name, data)
+    fn write_attr(&self, name: &str, value: DynArray) -> Result<()> {
+        self.write(|node| {
+            node.attrs_mut().insert(name.to_string(), value);
+            Ok(())
+        })
+    }
+
+    fn read_attr(&self, name: &str) -> Result<DynArray> {
+        self.read(|node| {
+            node.attrs()
+                .get(name)
+                .cloned()
+                .with_context(|| format!("No attribute named '{}'", name))
+        })
     }
 }
 
-impl GroupOp for File {
-    type Backend = H5;
+///////////////////////////////////////////////////////////////////////////////
+/// Conversions between dynamic arrays
+///////////////////////////////////////////////////////////////////////////////
+
+fn dtype_of(arr: &DynArray) -> Result<ScalarType> {
+    let ty = match arr {
+        DynArray::I8(_) => ScalarType::I8,
+        DynArray::I16(_) => ScalarType::I16,
+        DynArray::I32(_) => ScalarType::I32,
+        DynArray::I64(_) => ScalarType::I64,
+        DynArray::U8(_) => ScalarType::U8,
+        DynArray::U16(_) => ScalarType::U16,
+        DynArray::U32(_) => ScalarType::U32,
+        DynArray::U64(_) => ScalarType::U64,
+        DynArray::Usize(_) => ScalarType::Usize,
+        DynArray::F16(_) => ScalarType::F16,
+        DynArray::F32(_) => ScalarType::F32,
+        DynArray::F64(_) => ScalarType::F64,
+        DynArray::ComplexF32(_) => ScalarType::ComplexF32,
+        DynArray::ComplexF64(_) => ScalarType::ComplexF64,
+        DynArray::Bool(_) => ScalarType::Bool,
+        DynArray::String(_) => ScalarType::String,
+        DynArray::Categorical(_) => bail!("Categorical arrays cannot be stored in a dataset"),
+    };
+    Ok(ty)
+}
 
-    fn list(&self) -> Result<Vec<String>> {
-        Ok(self.member_names()?)
+/// Convert a stored array to the requested type. Numeric types are converted
+/// with `as`, mirroring the implicit conversions performed by HDF5.
+#[allow(clippy::unnecessary_cast)]
+fn cast<T: BackendData>(arr: DynArray) -> Result<ArrayD<T>> {
+    let from = dtype_of(&arr)?;
+    if from == T::DTYPE {
+        return T::from_dyn_arr(arr);
+    }
+
+    macro_rules! cast_to {
+        ($ty:ty) => {
+            match arr {
+                DynArray::I8(x) => x.mapv(|v| v as $ty),
+                DynArray::I16(x) => x.mapv(|v| v as $ty),
+                DynArray::I32(x) => x.mapv(|v| v as $ty),
+                DynArray::I64(x) => x.mapv(|v| v as $ty),
+                DynArray::U8(x) => x.mapv(|v| v as $ty),
+                DynArray::U16(x) => x.mapv(|v| v as $ty),
+                DynArray::U32(x) => x.mapv(|v| v as $ty),
+                DynArray::U64(x) => x.mapv(|v| v as $ty),
+                DynArray::Usize(x) => x.mapv(|v| v as $ty),
+                DynArray::F16(x) => x.mapv(|v| v.to_f64() as $ty),
+                DynArray::F32(x) => x.mapv(|v| v as $ty),
+                DynArray::F64(x) => x.mapv(|v| v as $ty),
+                DynArray::Bool(x) => x.mapv(|v| v as u8 as $ty),
+                _ => bail!("Cannot convert {} to {}", from, T::DTYPE),
+            }
+            .into()
+        };
     }
 
-    fn create_group(&self, name: &str) -> Result<<Self::Backend as Backend>::Group> {
-        Ok(hdf5::Group::create_group(self, name)?)
+    let arr: DynArray = match T::DTYPE {
+        ScalarType::I8 => cast_to!(i8),
+        ScalarType::I16 => cast_to!(i16),
+        ScalarType::I32 => cast_to!(i32),
+        ScalarType::I64 => cast_to!(i64),
+        ScalarType::U8 => cast_to!(u8),
+        ScalarType::U16 => cast_to!(u16),
+        ScalarType::U32 => cast_to!(u32),
+        ScalarType::U64 => cast_to!(u64),
+        ScalarType::Usize => cast_to!(usize),
+        ScalarType::F16 => cast::<f64>(arr)?.mapv(f16::from_f64).into(),
+        ScalarType::F32 => cast_to!(f32),
+        ScalarType::F64 => cast_to!(f64),
+        ScalarType::ComplexF32 | ScalarType::ComplexF64 | ScalarType::Bool | ScalarType::String => {
+            bail!("Cannot convert {} to {}", from, T::DTYPE)
+        }
+    };
+    T::from_dyn_arr(arr)
+}
+
+fn cast_scalar<T: BackendData>(arr: DynArray) -> Result<T> {
+    Ok(cast::<T>(arr)?.into_dimensionality::<Ix0>()?.into_scalar())
+}
+
+fn view_to_array<D: Dimension>(x: DynArrayView<'_, D>) -> DynArray {
+    match x {
+        DynArrayView::I8(x) => DynArray::I8(x.to_owned().into_dyn()),
+        DynArrayView::I16(x) => DynArray::I16(x.to_owned().into_dyn()),
+        DynArrayView::I32(x) => DynArray::I32(x.to_owned().into_dyn()),
+        DynArrayView::I64(x) => DynArray::I64(x.to_owned().into_dyn()),
+        DynArrayView::U8(x) => DynArray::U8(x.to_owned().into_dyn()),
+        DynArrayView::U16(x) => DynArray::U16(x.to_owned().into_dyn()),
+        DynArrayView::U32(x) => DynArray::U32(x.to_owned().into_dyn()),
+        DynArrayView::U64(x) => DynArray::U64(x.to_owned().into_dyn()),
+        DynArrayView::Usize(x) => DynArray::Usize(x.to_owned().into_dyn()),
+        DynArrayView::F16(x) => DynArray::F16(x.to_owned().into_dyn()),
+        DynArrayView::F32(x) => DynArray::F32(x.to_owned().into_dyn()),
+        DynArrayView::F64(x) => DynArray::F64(x.to_owned().into_dyn()),
+        DynArrayView::ComplexF32(x) => DynArray::ComplexF32(x.to_owned().into_dyn()),
+        DynArrayView::ComplexF64(x) => DynArray::ComplexF64(x.to_owned().into_dyn()),
+        DynArrayView::Bool(x) => DynArray::Bool(x.to_owned().into_dyn()),
+        DynArrayView::String(x) => DynArray::String(x.to_owned().into_dyn()),
     }
+}
 
-    fn open_group(&self, name: &str) -> Result<<Self::Backend as Backend>::Group> {
-        Ok(self.group(name)?)
+/// Resize an array, keeping the elements that fit in the new shape and
+/// filling the rest with default values.
+fn resize<T: Clone + Default>(arr: &ArrayD<T>, shape: &Shape) -> Result<ArrayD<T>> {
+    ensure!(
+        arr.ndim() == shape.ndim(),
+        "Cannot reshape a {}-dimensional dataset to {} dimensions",
+        arr.ndim(),
+        shape.ndim(),
+    );
+    let overlap = |ax: ndarray::AxisDescription| {
+        Slice::from(0..arr.len_of(ax.axis).min(shape[ax.axis.index()]))
+    };
+    let mut new = ArrayD::default(shape.as_ref());
+    new.slice_each_axis_mut(overlap)
+        .assign(&arr.slice_each_axis(overlap));
+    Ok(new)
+}
+
+fn write_select<T, D, S>(target: &mut ArrayD<T>, data: ArrayView<'_, T, D>, selection: &[S]) -> Result<()>
+where
+    T: Clone,
+    D: Dimension,
+    S: AsRef<SelectInfoElem>,
+{
+    let shape: Shape = target.shape().into();
+    let select = BoundedSelectInfo::new(&selection, &shape);
+    ensure!(
+        select.out_shape().as_ref() == data.shape(),
+        "Cannot write data of shape {:?} to a selection of shape {}",
+        data.shape(),
+        select.out_shape(),
+    );
+    if let Some(indices) = select.try_into_indices() {
+        indices.rows().into_iter().zip(data.iter()).for_each(|(idx, v)| {
+            target[idx.to_vec().as_slice()] = v.clone();
+        });
+    } else {
+        let info: SliceInfo<_, IxDyn, IxDyn> = select.try_into()?;
+        target.slice_mut(&info).assign(&data.into_dyn());
     }
+    Ok(())
+}
+
+///////////////////////////////////////////////////////////////////////////////
+/// Backend implementation
+///////////////////////////////////////////////////////////////////////////////
+
+impl Backend for InMemory {
+    const NAME: &'static str = "memory";
+
+    type File = MemFile;
+
+    type Group = MemGroup;
+
+    type Dataset = MemDataset;
 
-    fn open_dataset(&self, name: &str) -> Result<<Self::Backend as Backend>::Dataset> {
-        Ok(self.dataset(name)?)
+    /// Create an empty in-memory file registered under `path`, replacing the
+    /// file previously created with the same path, if any. Nothing is created
+    /// on disk.
+    fn create<P: AsRef<Path>>(path: P) -> Result<Self::File> {
+        let path = path.as_ref().to_path_buf();
+        let root = new_node(Node::new_group());
+        FILES.lock().insert(path.clone(), root.clone());
+        Ok(MemFile(Location::root(root, path, true)))
     }
 
-    fn delete(&self, name: &str) -> Result<()> {
-        Ok(self.unlink(name)?)
+    /// Open a file created by `InMemory::create` in read-only mode.
+    fn open<P: AsRef<Path>>(path: P) -> Result<Self::File> {
+        open_file(path.as_ref(), false)
     }
 
-    fn exists(&self, name: &str) -> Result<bool> {
-        Ok(self.link_exists(name))
+    /// Open a file created by `InMemory::create` in read-write mode.
+    fn open_rw<P: AsRef<Path>>(path: P) -> Result<Self::File> {
+        open_file(path.as_ref(), true)
     }
+}
+
+fn open_file(path: &Path, writable: bool) -> Result<MemFile> {
+    let root = FILES.lock()
+        .get(path)
+        .cloned()
+        .with_context(|| format!("Cannot open '{}': no in-memory file has this path", path.display()))?;
+    Ok(MemFile(Location::root(root, path.to_path_buf(), writable)))
+}
 
-    fn write_scalar<D: BackendData>(
-        &self,
-        name: &str,
-        data: &D,
-    ) -> Result<<Self::Backend as Backend>::Dataset> {
-        write_scalar(self, name, data)
+impl FileOp for MemFile {
+    type Backend = InMemory;
+
+    fn filename(&self) -> PathBuf {
+        self.0.filename.clone()
     }
 
-    fn write_array<'a, A, D, Dim>(
-        &self,
-        name: &str,
-        data: A,
-    ) -> Result<<Self::Backend as Backend>::Dataset>
-    where
-        A: Into<ArrayView<'a, D, Dim>>,
-        D: BackendData,
-        Dim: Dimension,
-    {
-        write_array(self, name, data)
+    /// Closing a file drops the handle. The data of a file without a name is
+    /// freed once the groups and datasets opened from it are dropped as well.
+    fn close(self) -> Result<()> {
+        Ok(())
     }
 }
 
-impl DatasetOp for Dataset {
-    type Backend = H5;
+impl DatasetOp for MemDataset {
+    type Backend = InMemory;
 
     fn dtype(&self) -> Result<ScalarType> {
-        let ty = match hdf5::Container::dtype(self)?.to_descriptor()? {
-            TypeDescriptor::Unsigned(U1) => ScalarType::U8,
-            TypeDescriptor::Unsigned(U2) => ScalarType::U16,
-            TypeDescriptor::Unsigned(U4) => ScalarType::U32,
-            TypeDescriptor::Unsigned(U8) => ScalarType::U64,
-            TypeDescriptor::Integer(U1) => ScalarType::I8,
-            TypeDescriptor::Integer(U2) => ScalarType::I16,
-            TypeDescriptor::Integer(U4) => ScalarType::I32,
-            TypeDescriptor::Integer(U8) => ScalarType::I64,
-            TypeDescriptor::Float(FloatSize::U4) => ScalarType::F32,
-            TypeDescriptor::Float(FloatSize::U8) => ScalarType::F64,
-            TypeDescriptor::Boolean => ScalarType::Bool,
-            TypeDescriptor::VarLenAscii => ScalarType::String,
-            TypeDescriptor::VarLenUnicode => ScalarType::String,
-            ty => bail!("Unsupported type: {:?}", ty),
-        };
-        Ok(ty)
+        self.0.read(|node| dtype_of(node.data()?))
+    }
+
+    fn shape(&self) -> Shape {
+        // Dataset handles are only opened on datasets, and keep their node
+        // alive even if it is deleted, so the shape is always known.
+        self.0.node.read().shape().unwrap_or_else(|| Vec::<usize>::new().into())
+    }
+
+    fn reshape(&self, shape: &Shape) -> Result<()> {
+        self.0.write(|node| {
+            let data = node.data_mut()?;
+            *data = match data {
+                DynArray::I8(x) => resize(x, shape)?.into(),
+                DynArray::I16(x) => resize(x, shape)?.into(),
+                DynArray::I32(x) => resize(x, shape)?.into(),
+                DynArray::I64(x) => resize(x, shape)?.into(),
+                DynArray::U8(x) => resize(x, shape)?.into(),
+                DynArray::U16(x) => resize(x, shape)?.into(),
+                DynArray::U32(x) => resize(x, shape)?.into(),
+                DynArray::U64(x) => resize(x, shape)?.into(),
+                DynArray::Usize(x) => resize(x, shape)?.into(),
+                DynArray::F16(x) => resize(x, shape)?.into(),
+                DynArray::F32(x) => resize(x, shape)?.into(),
+                DynArray::F64(x) => resize(x, shape)?.into(),
+                DynArray::ComplexF32(x) => resize(x, shape)?.into(),
+                DynArray::ComplexF64(x) => resize(x, shape)?.into(),
+                DynArray::Bool(x) => resize(x, shape)?.into(),
+                DynArray::String(x) => resize(x, shape)?.into(),
+                DynArray::Categorical(_) => bail!("Cannot reshape a categorical dataset"),
+            };
+            Ok(())
+        })
+    }
+
+    fn is_record(&self) -> Result<bool> {
+        self.0.read(|node| Ok(matches!(node, Node::Record { .. })))
     }
 
-    fn shape(&self) -> Result<Shape> {
-        Ok(hdf5::Container::shape(self).into())
+    fn read_record(&self) -> Result<Vec<(String, DynArray)>> {
+        self.0.read(|node| Ok(node.fields()?.clone()))
     }
 
     fn read_scalar<T: BackendData>(&self) -> Result<T> {
-        read_scalar(self)
+        cast_scalar(self.0.read(|node| Ok(node.data()?.clone()))?)
     }
 
-    fn read_array_slice<T: BackendData, S, E, D>(&self, selection: S) -> Result<Array<T, D>>
+    fn read_array_slice<T, S, D>(&self, selection: &[S]) -> Result<Array<T, D>>
     where
-        S: AsRef<[E]>,
-        E: AsRef<SelectInfoElem>,
-        D: Dimension,
+        T: BackendData,
+        S: AsRef<SelectInfoElem>,
+        D: RemoveAxis,
     {
-        read_array(self, selection)
+        let arr = self.0.read(|node| Ok(node.data()?.select(selection)))?;
+        Ok(cast::<T>(arr)?.into_dimensionality::<D>()?)
     }
-}
 
-fn read_scalar<T: BackendData>(dataset: &Dataset) -> Result<T> {
-    let val = match T::DTYPE {
-        ScalarType::Bool => hdf5::Container::read_scalar::<bool>(dataset)?.into_dyn(),
-        ScalarType::U8 => hdf5::Container::read_scalar::<u8>(dataset)?.into_dyn(),
-        ScalarType::U16 => hdf5::Container::read_scalar::<u16>(dataset)?.into_dyn(),
-        ScalarType::U32 => hdf5::Container::read_scalar::<u32>(dataset)?.into_dyn(),
-        ScalarType::U64 => hdf5::Container::read_scalar::<u64>(dataset)?.into_dyn(),
-        ScalarType::Usize => hdf5::Container::read_scalar::<usize>(dataset)?.into_dyn(),
-        ScalarType::I8 => hdf5::Container::read_scalar::<i8>(dataset)?.into_dyn(),
-        ScalarType::I16 => hdf5::Container::read_scalar::<i16>(dataset)?.into_dyn(),
-        ScalarType::I32 => hdf5::Container::read_scalar::<i32>(dataset)?.into_dyn(),
-        ScalarType::I64 => hdf5::Container::read_scalar::<i64>(dataset)?.into_dyn(),
-        ScalarType::F32 => hdf5::Container::read_scalar::<f32>(dataset)?.into_dyn(),
-        ScalarType::F64 => hdf5::Container::read_scalar::<f64>(dataset)?.into_dyn(),
-        ScalarType::String => {
-            let s = hdf5::Container::read_scalar::<VarLenUnicode>(dataset)?;
-            s.to_string().into_dyn()
-        }
-    };
-    BackendData::from_dyn(val)
+    fn write_array_slice<'a, A, S, T, D>(&self, data: A, selection: &[S]) -> Result<()>
+    where
+        A: Into<ArrayView<'a, T, D>>,
+        T: BackendData,
+        S: AsRef<SelectInfoElem>,
+        D: RemoveAxis,
+    {
+        let data = BackendData::into_dyn_arr(data.into());
+        self.0.write(|node| match (node.data_mut()?, data) {
+            (DynArray::I8(x), DynArrayView::I8(y)) => write_select(x, y, selection),
+            (DynArray::I16(x), DynArrayView::I16(y)) => write_select(x, y, selection),
+            (DynArray::I32(x), DynArrayView::I32(y)) => write_select(x, y, selection),
+            (DynArray::I64(x), DynArrayView::I64(y)) => write_select(x, y, selection),
+            (DynArray::U8(x), DynArrayView::U8(y)) => write_select(x, y, selection),
+            (DynArray::U16(x), DynArrayView::U16(y)) => write_select(x, y, selection),
+            (DynArray::U32(x), DynArrayView::U32(y)) => write_select(x, y, selection),
+            (DynArray::U64(x), DynArrayView::U64(y)) => write_select(x, y, selection),
+            (DynArray::Usize(x), DynArrayView::Usize(y)) => write_select(x, y, selection),
+            (DynArray::F16(x), DynArrayView::F16(y)) => write_select(x, y, selection),
+            (DynArray::F32(x), DynArrayView::F32(y)) => write_select(x, y, selection),
+            (DynArray::F64(x), DynArrayView::F64(y)) => write_select(x, y, selection),
+            (DynArray::ComplexF32(x), DynArrayView::ComplexF32(y)) => write_select(x, y, selection),
+            (DynArray::ComplexF64(x), DynArrayView::ComplexF64(y)) => write_select(x, y, selection),
+            (DynArray::Bool(x), DynArrayView::Bool(y)) => write_select(x, y, selection),
+            (DynArray::String(x), DynArrayView::String(y)) => write_select(x, y, selection),
+            (x, _) => bail!("Cannot write {} data to a {} dataset", T::DTYPE, dtype_of(x)?),
+        })
+    }
 }
 
-fn read_arr_attr<T, D>(loc: &Location, name: &str) -> Result<Array<T, D>>
-where
-    T: BackendData,
-    D: Dimension,
-{
-    let array: DynArray = match T::DTYPE {
-        ScalarType::I8 => loc.attr(name)?.read::<i8, D>()?.into(),
-        ScalarType::I16 => loc.attr(name)?.read::<i16, D>()?.into(),
-        ScalarType::I32 => loc.attr(name)?.read::<i32, D>()?.into(),
-        ScalarType::I64 => loc.attr(name)?.read::<i64, D>()?.into(),
-        ScalarType::U8 => loc.attr(name)?.read::<u8, D>()?.into(),
-        ScalarType::U16 => loc.attr(name)?.read::<u16, D>()?.into(),
-        ScalarType::U32 => loc.attr(name)?.read::<u32, D>()?.into(),
-        ScalarType::U64 => loc.attr(name)?.read::<u64, D>()?.into(),
-        ScalarType::Usize => loc.attr(name)?.read::<usize, D>()?.into(),
-        ScalarType::F32 => loc.attr(name)?.read::<f32, D>()?.into(),
-        ScalarType::F64 => loc.attr(name)?.read::<f64, D>()?.into(),
-        ScalarType::Bool => loc.attr(name)?.read::<bool, D>()?.into(),
-        ScalarType::String => {
-            let s = loc.attr(name)?.read::<VarLenUnicode, D>()?;
-            s.map(|s| s.to_string()).into()
+////////////////////////////////////////////////////////////////////////////////
+/// Derived implementations
+////////////////////////////////////////////////////////////////////////////////
+
+macro_rules! impl_group_op {
+    ($ty:ty) => {
+        impl GroupOp for $ty {
+            type Backend = InMemory;
+
+            fn list(&self) -> Result<Vec<String>> {
+                self.0.list()
+            }
+
+            fn create_group(&self, name: &str) -> Result<<Self::Backend as Backend>::Group> {
+                self.0.insert(name, new_node(Node::new_group())).map(MemGroup)
+            }
+
+            fn open_group(&self, name: &str) -> Result<<Self::Backend as Backend>::Group> {
+                self.0.open_group(name)
+            }
+
+            fn new_dataset<T: BackendData>(
+                &self,
+                name: &str,
+                shape: &Shape,
+                config: WriteConfig,
+            ) -> Result<<Self::Backend as Backend>::Dataset> {
+                self.0.new_dataset::<T>(name, shape, config.fill_value)
+            }
+
+            fn open_dataset(&self, name: &str) -> Result<<Self::Backend as Backend>::Dataset> {
+                self.0.open_dataset(name)
+            }
+
+            fn delete(&self, name: &str) -> Result<()> {
+                self.0.delete(name)
+            }
+
+            fn exists(&self, name: &str) -> Result<bool> {
+                self.0.exists(name)
+            }
+
+            fn rename(&self, name: &str, new_name: &str) -> Result<()> {
+                self.0.rename(name, new_name)
+            }
+
+            fn create_scalar_data<D: BackendData>(
+                &self,
+                name: &str,
+                data: &D,
+            ) -> Result<<Self::Backend as Backend>::Dataset> {
+                let node = Node::new_dataset(DynArray::from(data.into_dyn()));
+                self.0.insert(name, new_node(node)).map(MemDataset)
+            }
+
+            fn create_record_data(
+                &self,
+                name: &str,
+                fields: &[(String, DynArray)],
+                _config: WriteConfig,
+            ) -> Result<<Self::Backend as Backend>::Dataset> {
+                let fields = RecordArray::new(fields.to_vec())?.into_fields();
+                let node = Node::Record { fields, attrs: HashMap::new() };
+                self.0.insert(name, new_node(node)).map(MemDataset)
+            }
         }
     };
-    Ok(BackendData::from_dyn_arr(array)?.into_dimensionality::<D>()?)
 }
 
-fn read_array<T, S, E, D>(dataset: &Dataset, selection: S) -> Result<Array<T, D>>
-where
-    T: BackendData,
-    S: AsRef<[E]>,
-    E: AsRef<SelectInfoElem>,
-    D: Dimension,
-{
-    fn read_arr<T, S, E, D>(dataset: &Dataset, selection: S) -> Result<Array<T, D>>
-    where
-        T: H5Type + BackendData,
-        S: AsRef<[E]>,
-        E: AsRef<SelectInfoElem>,
-        D: Dimension,
-    {
-        if selection.as_ref().iter().any(|x| x.as_ref().is_index()) { // fancy indexing is too slow, just read all
-            let arr = hdf5::Container::read::<T, D>(dataset)?;
-            Ok(ArrayOp::select(&arr, selection))
-        } else {
-            let (select, shape) = into_selection(selection, dataset.shape()?);
-            if matches!(select, Selection::Points(_)) {
-                let slice_1d = hdf5::Container::read_slice_1d::<T, _>(dataset, select)?;
-                Ok(slice_1d.into_shape(shape.as_ref())?.into_dimensionality::<D>()?)
-            } else {
-                Ok(hdf5::Container::read_slice::<T, _, D>(dataset, select)?)
+impl_group_op!(MemFile);
+impl_group_op!(MemGroup);
+
+macro_rules! impl_location_op {
+    ($ty:ty) => {
+        impl LocationOp for $ty {
+            type Backend = InMemory;
+
+            fn file(&self) -> Result<<Self::Backend as Backend>::File> {
+                Ok(self.0.file())
             }
-        }
-    }
 
-    let array: DynArray = match T::DTYPE {
-        ScalarType::I8 => read_arr::<i8, _, _, D>(dataset, selection)?.into(),
-        ScalarType::I16 => read_arr::<i16, _, _, D>(dataset, selection)?.into(),
-        ScalarType::I32 => read_arr::<i32, _, _, D>(dataset, selection)?.into(),
-        ScalarType::I64 => read_arr::<i64, _, _, D>(dataset, selection)?.into(),
-        ScalarType::U8 => read_arr::<u8, _, _, D>(dataset, selection)?.into(),
-        ScalarType::U16 => read_arr::<u16, _, _, D>(dataset, selection)?.into(),
-        ScalarType::U32 => read_arr::<u32, _, _, D>(dataset, selection)?.into(),
-        ScalarType::U64 => read_arr::<u64, _, _, D>(dataset, selection)?.into(),
-        ScalarType::Usize => read_arr::<usize, _, _, D>(dataset, selection)?.into(),
-        ScalarType::F32 => read_arr::<f32, _, _, D>(dataset, selection)?.into(),
-        ScalarType::F64 => read_arr::<f64, _, _, D>(dataset, selection)?.into(),
-        ScalarType::Bool => read_arr::<bool, _, _, D>(dataset, selection)?.into(),
-        ScalarType::String => {
-            if selection.as_ref().iter().any(|x| x.as_ref().is_index()) { // fancy indexing is too slow, just read all
-                let arr = hdf5::Container::read::<VarLenUnicode, D>(dataset)?;
-                let arr_ = arr.map(|s| s.to_string());
-                let r: Result<_> = Ok(ArrayOp::select(&arr_, selection));
-                r
-            } else {
-                let (select, shape) = into_selection(selection, dataset.shape()?);
-                let arr: Result<_> = if matches!(select, Selection::Points(_)) {
-                    let slice_1d = hdf5::Container::read_slice_1d::<VarLenUnicode, _>(dataset, select)?;
-                    Ok(slice_1d.into_shape(shape.as_ref())?.into_dimensionality::<D>()?)
-                } else {
-                    Ok(hdf5::Container::read_slice::<VarLenUnicode, _, D>(dataset, select)?)
-                };
-                Ok(arr?.map(|s| s.to_string()))
-            }?.into()
-            /*
-            let arr = read_arr::<VarLenUnicode, _, _, D>(dataset, selection)?;
-            let arr = arr.map(|s| s.to_string());
-            arr.into()
-            */
-        },
-    };
-    Ok(BackendData::from_dyn_arr(array)?.into_dimensionality::<D>()?)
-}
+            fn path(&self) -> PathBuf {
+                self.0.path()
+            }
 
-fn write_scalar<D: BackendData>(group: &Group, name: &str, data: &D) -> Result<Dataset> {
-    match data.into_dyn() {
-        DynScalar::U8(x) => {
-            let dataset = group.new_dataset::<u8>().create(name)?;
-            dataset.write_scalar(&x)?;
-            Ok(dataset)
-        }
-        DynScalar::U16(x) => {
-            let dataset = group.new_dataset::<u16>().create(name)?;
-            dataset.write_scalar(&x)?;
-            Ok(dataset)
-        }
-        DynScalar::U32(x) => {
-            let dataset = group.new_dataset::<u32>().create(name)?;
-            dataset.write_scalar(&x)?;
-            Ok(dataset)
-        }
-        DynScalar::U64(x) => {
-            let dataset = group.new_dataset::<u64>().create(name)?;
-            dataset.write_scalar(&x)?;
-            Ok(dataset)
-        }
-        DynScalar::Usize(x) => {
-            let dataset = group.new_dataset::<usize>().create(name)?;
-            dataset.write_scalar(&x)?;
-            Ok(dataset)
-        }
-        DynScalar::I8(x) => {
-            let dataset = group.new_dataset::<i8>().create(name)?;
-            dataset.write_scalar(&x)?;
-            Ok(dataset)
-        }
-        DynScalar::I16(x) => {
-            let dataset = group.new_dataset::<i16>().create(name)?;
-            dataset.write_scalar(&x)?;
-            Ok(dataset)
-        }
-        DynScalar::I32(x) => {
-            let dataset = group.new_dataset::<i32>().create(name)?;
-            dataset.write_scalar(&x)?;
-            Ok(dataset)
-        }
-        DynScalar::I64(x) => {
-            let dataset = group.new_dataset::<i64>().create(name)?;
-            dataset.write_scalar(&x)?;
-            Ok(dataset)
-        }
-        DynScalar::F32(x) => {
-            let dataset = group.new_dataset::<f32>().create(name)?;
-            dataset.write_scalar(&x)?;
-            Ok(dataset)
-        }
-        DynScalar::F64(x) => {
-            let dataset = group.new_dataset::<f64>().create(name)?;
-            dataset.write_scalar(&x)?;
-            Ok(dataset)
-        }
-        DynScalar::Bool(x) => {
-            let dataset = group.new_dataset::<bool>().create(name)?;
-            dataset.write_scalar(&x)?;
-            Ok(dataset)
-        }
-        DynScalar::String(x) => {
-            let dataset = group.new_dataset::<VarLenUnicode>().create(name)?;
-            dataset.write_scalar(&x.parse::<VarLenUnicode>().unwrap())?;
-            Ok(dataset)
-        }
-    }
-}
+            fn write_scalar_attr<D: BackendData>(&self, name: &str, value: D) -> Result<()> {
+                self.0.write_attr(name, DynArray::from(value.into_dyn()))
+            }
 
-fn write_arr_attr<'a, A, D, Dim>(loc: &Location, name: &str, data: A) -> Result<()>
-where
-    A: Into<ArrayView<'a, D, Dim>>,
-    D: BackendData,
-    Dim: Dimension,
-{
-    match BackendData::into_dyn_arr(data.into()) {
-        DynArrayView::U8(x) => loc.new_attr_builder().with_data(x).create(name)?,
-        DynArrayView::U16(x) => loc.new_attr_builder().with_data(x).create(name)?,
-        DynArrayView::U32(x) => loc.new_attr_builder().with_data(x).create(name)?,
-        DynArrayView::U64(x) => loc.new_attr_builder().with_data(x).create(name)?,
-        DynArrayView::Usize(x) => loc.new_attr_builder().with_data(x).create(name)?,
-        DynArrayView::I8(x) => loc.new_attr_builder().with_data(x).create(name)?,
-        DynArrayView::I16(x) => loc.new_attr_builder().with_data(x).create(name)?,
-        DynArrayView::I32(x) => loc.new_attr_builder().with_data(x).create(name)?,
-        DynArrayView::I64(x) => loc.new_attr_builder().with_data(x).create(name)?,
-        DynArrayView::F32(x) => loc.new_attr_builder().with_data(x).create(name)?,
-        DynArrayView::F64(x) => loc.new_attr_builder().with_data(x).create(name)?,
-        DynArrayView::Bool(x) => loc.new_attr_builder().with_data(x).create(name)?,
-        DynArrayView::String(x) => {
-            let data: Array<VarLenUnicode, Dim> = x.map(|x| x.parse().unwrap());
-            loc.new_attr_builder().with_data(data.view()).create(name)?
+            fn write_array_attr<'a, A, D, Dim>(&self, name: &str, value: A) -> Result<()>
+            where
+                A: Into<ArrayView<'a, D, Dim>>,
+                D: BackendData,
+                Dim: RemoveAxis,
+            {
+                self.0.write_attr(name, view_to_array(BackendData::into_dyn_arr(value.into())))
+            }
+
+            fn read_scalar_attr<T: BackendData>(&self, name: &str) -> Result<T> {
+                cast_scalar(self.0.read_attr(name)?)
+            }
+
+            fn read_array_attr<T: BackendData, D: RemoveAxis>(&self, name: &str) -> Result<Array<T, D>> {
+                Ok(cast::<T>(self.0.read_attr(name)?)?.into_dimensionality::<D>()?)
+            }
         }
     };
-    Ok(())
 }
 
+impl_location_op!(MemGroup);
+impl_location_op!(MemDataset);
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn write_array<'a, A, D, Dim>(group: &Group, name: &str, data: A) -> Result<Dataset>
-where
-    A: Into<ArrayView<'a, D, Dim>>,
-    D: BackendData,
-    Dim: Dimension,
-{
-    fn write_array_impl<'a, D, Dim>(
-        group: &Group,
-        name: &str,
-        arr: ArrayView<'a, D, Dim>,
-    ) -> Result<Dataset>
-    where
-        D: H5Type,
-        Dim: Dimension,
-    {
-        let shape = arr.shape();
-        let chunk_size = if shape.len() == 1 {
-            vec![shape[0].min(100000)]
-        } else {
-            shape.iter().map(|&x| x.min(100)).collect()
-        };
-        let dataset = if arr.len() > 100 {
-            group
-                .new_dataset_builder()
-                .deflate(3)
-                .chunk(chunk_size)
-                .with_data(arr)
-                .create(name)?
-        } else {
-            group.new_dataset_builder().with_data(arr).create(name)?
-        };
-        Ok(dataset)
-    }
-
-    match BackendData::into_dyn_arr(data.into()) {
-        DynArrayView::U8(x) => write_array_impl(group, name, x),
-        DynArrayView::U16(x) => write_array_impl(group, name, x),
-        DynArrayView::U32(x) => write_array_impl(group, name, x),
-        DynArrayView::U64(x) => write_array_impl(group, name, x),
-        DynArrayView::Usize(x) => write_array_impl(group, name, x),
-        DynArrayView::I8(x) => write_array_impl(group, name, x),
-        DynArrayView::I16(x) => write_array_impl(group, name, x),
-        DynArrayView::I32(x) => write_array_impl(group, name, x),
-        DynArrayView::I64(x) => write_array_impl(group, name, x),
-        DynArrayView::F32(x) => write_array_impl(group, name, x),
-        DynArrayView::F64(x) => write_array_impl(group, name, x),
-        DynArrayView::Bool(x) => write_array_impl(group, name, x),
-        DynArrayView::String(x) => {
-            let data: Array<VarLenUnicode, Dim> = x.map(|x| x.parse().unwrap());
-            write_array_impl(group, name, data.view())
-        }
+    #[test]
+    fn test_close() {
+        let file = MemFile::new();
+        let group = file.create_group("obsm").unwrap();
+        let root = Arc::downgrade(&file.0.root);
+        file.close().unwrap();
+
+        // The data is kept alive by the open handles.
+        group.create_group("pca").unwrap();
+        assert!(group.exists("pca").unwrap());
+        drop(group);
+        assert!(root.upgrade().is_none());
     }
-}
 
-fn into_selection<S, E>(selection: S, shape: Shape) -> (Selection, Shape)
-where
-    S: AsRef<[E]>,
-    E: AsRef<SelectInfoElem>,
-{
-    if selection.as_ref().into_iter().all(|x| x.as_ref().is_full()) {
-        (Selection::All, shape)
-    } else {
-        let bounded_selection = BoundedSelectInfo::new(&selection, &shape).unwrap();
-        let out_shape = bounded_selection.out_shape();
-        if let Some(idx) = bounded_selection.try_into_indices() {
-            (Selection::from(idx), out_shape)
-        } else {
-            let slice: SliceInfo<_, _, _> = bounded_selection.try_into().unwrap();
-            (Selection::try_from(slice).unwrap(), out_shape)
-        }
+    #[test]
+    fn test_named_file() {
+        let path = "memory_test_named_file.h5ad";
+        let file = InMemory::create(path).unwrap();
+        let dataset = file.create_scalar_data("n", &3u8).unwrap();
+        file.close().unwrap();
+
+        let file = InMemory::open(path).unwrap();
+        assert_eq!(file.open_dataset("n").unwrap().read_scalar::<u8>().unwrap(), 3);
+        assert!(file.create_group("obsm").is_err());
+
+        // Creating a file with the same path replaces the previous one.
+        let file = InMemory::create(path).unwrap();
+        assert!(!file.exists("n").unwrap());
+
+        // Handles keep the data of deleted datasets.
+        assert_eq!(dataset.shape().ndim(), 0);
+        InMemory::remove_file(path).unwrap();
+        assert!(InMemory::open_rw(path).is_err());
     }
-}
\ No newline at end of file
+}
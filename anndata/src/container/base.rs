@@ -1,11 +1,13 @@
 use crate::{
+    anndata::ColumnMajor,
+    container::{cache::{CacheSize, Evict}, CacheBudget, ChunkCache},
     traits::ArrayElemOp,
-    backend::{Backend, DataContainer, DataType, GroupOp, LocationOp},
+    backend::{Backend, DataContainer, DataType, GroupOp, LocationOp, ScalarType, WriteConfig},
     data::*,
     data::index::VecVecIndex,
 };
 
-use anyhow::{bail, ensure, Result};
+use anyhow::{bail, ensure, Context, Result};
 use indexmap::set::IndexSet;
 use itertools::Itertools;
 use ndarray::Ix1;
@@ -13,12 +15,17 @@ use num::integer::div_rem;
 use parking_lot::{Mutex, MutexGuard};
 use polars::{
     frame::DataFrame,
-    prelude::{concat, IntoLazy, UnionArgs},
+    prelude::{concat, DataFrameJoinOps, IntoLazy, JoinArgs, JoinType, NamedFrom, UnionArgs},
     series::{Series, IntoSeries},
 };
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use smallvec::SmallVec;
-use std::{ops::{Deref, DerefMut}, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::{Deref, DerefMut},
+    path::Path,
+    sync::{mpsc, Arc, Weak},
+};
 
 /// Slot stores an optional object wrapped by Arc and Mutex.
 /// Encapsulating an object inside a slot allows us to drop the object from all references.
@@ -67,6 +74,20 @@ impl<T> Slot<T> {
         Inner(self.0.lock())
     }
 
+    /// Like `inner`, but return `None` instead of panicking later if the slot is empty.
+    pub fn try_inner(&self) -> Option<Inner<'_, T>> {
+        let guard = self.0.lock();
+        guard.is_some().then(|| Inner(guard))
+    }
+
+    /// Apply `f` to the data in the slot, or return `None` if the slot is empty.
+    pub fn with_inner<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        self.0.lock().as_mut().map(f)
+    }
+
     /// Insert data to the slot, and return the old data.
     pub fn insert(&self, data: T) -> Option<T> {
         std::mem::replace(self.0.lock().deref_mut(), Some(data))
@@ -114,6 +135,8 @@ impl<T> DerefMut for Inner<'_, T> {
 #[derive(Debug)]
 pub struct InnerDataFrameElem<B: Backend> {
     element: Option<DataFrame>,
+    /// Columns read individually while the whole dataframe is not in memory.
+    columns: HashMap<String, Series>,
     container: DataContainer<B>,
     column_names: IndexSet<String>,
     pub index: DataFrameIndex,
@@ -134,6 +157,7 @@ impl<B: Backend> InnerDataFrameElem<B> {
         let column_names = df.get_column_names().into_iter().map(|x| x.to_string()).collect();
         Ok(Self {
             element: None,
+            columns: HashMap::new(),
             container,
             column_names,
             index,
@@ -156,20 +180,172 @@ impl<B: Backend> InnerDataFrameElem<B> {
         self.index.len()
     }
 
+    /// Return a column of the dataframe. If the dataframe is not in memory,
+    /// only the requested column is read and cached.
     pub fn column(&mut self, name: &str) -> Result<&Series> {
-        self.data().and_then(|x| Ok(x.column(name)?))
+        if self.element.is_none() && !self.columns.contains_key(name) {
+            ensure!(self.column_names.contains(name), "column '{}' not found", name);
+            match dataframe::read_column(&self.container, name)? {
+                Some(series) => {
+                    self.columns.insert(name.to_string(), series);
+                }
+                None => {
+                    self.data()?;
+                }
+            }
+        }
+        match self.element {
+            Some(ref df) => Ok(df.column(name)?),
+            None => Ok(&self.columns[name]),
+        }
+    }
+
+    /// Return the selected rows of some columns. If the dataframe is not in
+    /// memory, only these rows are read and nothing is cached.
+    pub fn select_columns<S: AsRef<SelectInfoElem>>(&mut self, columns: &[String], rows: S) -> Result<DataFrame> {
+        let rows = rows.as_ref();
+        check_select(&[rows], &self.height().into())?;
+        let series = columns
+            .iter()
+            .map(|name| {
+                ensure!(self.column_names.contains(name), "column '{}' not found", name);
+                if let Some(series) = self.element.as_ref().map_or(self.columns.get(name), |df| df.column(name).ok()) {
+                    return Ok(series.select(&[rows]));
+                }
+                match dataframe::read_column_select(&self.container, name, rows)? {
+                    Some(series) => Ok(series),
+                    None => Ok(self.data()?.column(name)?.select(&[rows])),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(DataFrame::new(series)?)
     }
 
     pub fn get_column_names(&self) -> &IndexSet<String> {
         &self.column_names
     }
 
+    /// The data type of each column, read from the stored metadata.
+    pub fn column_types(&self) -> Result<Vec<(String, DataType)>> {
+        self.column_names
+            .iter()
+            .map(|x| Ok((x.clone(), dataframe::column_type(&self.container, x)?)))
+            .collect()
+    }
+
     /// Set a column with a Series.
-    //TODO: this is not efficient. We should be able to replace a column without reading the whole dataframe.
     pub fn set_column<S: IntoSeries>(&mut self, name: &str, new_col: S) -> Result<()> {
-        let mut df = self.data()?.clone();
-        df.replace_or_add(name, new_col)?;
-        self.save(df)
+        let mut series = new_col.into_series();
+        series.rename(name);
+        self.add_column(series)
+    }
+
+    /// Add a column named after the series, or replace the column of the same
+    /// name. Only the dataset of this column is written; the rest of the
+    /// dataframe is left untouched on disk.
+    pub fn add_column(&mut self, series: Series) -> Result<()> {
+        let name = series.name().to_string();
+        ensure!(
+            series.len() == self.index.len(),
+            "cannot add column '{}' of length {} to a dataframe of height {}",
+            name,
+            series.len(),
+            self.index.len()
+        );
+        ensure!(
+            name != self.index.index_name,
+            "cannot add column '{}' as it is the name of the index",
+            name
+        );
+        let group = self.container.as_group()?;
+        if group.exists(&name)? {
+            group.delete(&name)?;
+        }
+        series.write(group, &name)?;
+        if self.column_names.insert(name.clone()) {
+            let columns: Vec<String> = self.column_names.iter().cloned().collect();
+            self.container.write_array_attr("column-order", columns.as_slice())?;
+        }
+        if let Some(df) = self.element.as_mut() {
+            df.replace_or_add(&name, series)?;
+        } else {
+            self.columns.remove(&name);
+        }
+        Ok(())
+    }
+
+    /// Rename a column. It is an error if a column named `new_name` already exists.
+    pub fn rename_column(&mut self, name: &str, new_name: &str) -> Result<()> {
+        ensure!(self.column_names.contains(name), "column '{}' not found", name);
+        ensure!(!self.column_names.contains(new_name), "column '{}' already exists", new_name);
+        self.container.as_group()?.rename(name, new_name)?;
+        self.column_names = self
+            .column_names
+            .iter()
+            .map(|x| if x == name { new_name.to_string() } else { x.clone() })
+            .collect();
+        let columns: Vec<String> = self.column_names.iter().cloned().collect();
+        self.container.write_array_attr("column-order", columns.as_slice())?;
+        if let Some(df) = self.element.as_mut() {
+            df.rename(name, new_name)?;
+        }
+        if let Some(mut series) = self.columns.remove(name) {
+            series.rename(new_name);
+            self.columns.insert(new_name.to_string(), series);
+        }
+        Ok(())
+    }
+
+    /// Join an external table on the key `on`, which is either a column of
+    /// this dataframe or the name of its index. The rows of this dataframe
+    /// are kept in their order and only the columns coming from `other` are
+    /// written. It is an error if the join changes the number of rows, e.g.,
+    /// when `other` contains duplicated keys.
+    pub fn join(&mut self, mut other: DataFrame, on: &str, how: JoinType) -> Result<()> {
+        const ROW_ID: &str = "__anndata_row_id__";
+        let key = if on == self.index.index_name {
+            Series::new(on, self.index.clone().into_vec())
+        } else if self.column_names.contains(on) {
+            self.column(on)?.clone()
+        } else {
+            bail!("'{}' is neither a column nor the index of the dataframe", on);
+        };
+        let other_key = other
+            .column(on)
+            .with_context(|| format!("key '{}' not found in the table to join", on))?
+            .cast(key.dtype())?;
+        other.replace(on, other_key)?;
+        let new_columns: Vec<String> = other
+            .get_column_names()
+            .into_iter()
+            .filter(|x| *x != on)
+            .map(|x| x.to_string())
+            .collect();
+        for name in new_columns.iter() {
+            ensure!(
+                !self.column_names.contains(name) && *name != self.index.index_name && name != ROW_ID,
+                "column '{}' already exists in the dataframe",
+                name
+            );
+        }
+
+        let n = self.height();
+        let left = DataFrame::new(vec![key, Series::new(ROW_ID, (0..n as u32).collect::<Vec<_>>())])?;
+        let joined = left.join(&other, [on], [on], JoinArgs::new(how))?;
+        ensure!(
+            joined.height() == n,
+            "join changed the number of rows from {} to {}, the keys of the other table may be duplicated or missing",
+            n,
+            joined.height(),
+        );
+        let joined = joined.sort([ROW_ID], false, false)?;
+        ensure!(
+            joined.column(ROW_ID)?.u32()?.into_no_null_iter().eq(0..n as u32),
+            "join did not preserve the rows of the dataframe",
+        );
+        new_columns
+            .iter()
+            .try_for_each(|name| self.add_column(joined.column(name)?.clone()))
     }
 
     pub fn set_index(&mut self, index: DataFrameIndex) -> Result<()> {
@@ -190,6 +366,7 @@ impl<B: Backend> InnerDataFrameElem<B> {
             None => {
                 let df = DataFrame::read(&self.container)?;
                 self.element = Some(df);
+                self.columns.clear();
                 Ok(&self.element.as_ref().unwrap())
             }
         }
@@ -208,6 +385,15 @@ impl<B: Backend> InnerDataFrameElem<B> {
         Ok(())
     }
 
+    /// Write the dataframe to a Parquet file. The index is stored as the first column.
+    pub fn export_parquet<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let df = match self.element {
+            Some(ref df) => df.clone(),
+            None => DataFrame::read(&self.container)?,
+        };
+        dataframe::write_parquet(&df, &self.index, path)
+    }
+
     pub fn export_select<O, G>(
         &mut self,
         selection: &[&SelectInfoElem],
@@ -249,6 +435,7 @@ impl<B: Backend> InnerDataFrameElem<B> {
     where
         S: AsRef<SelectInfoElem>,
     {
+        check_select(selection, &(self.height(), self.width()).into())?;
         Ok(ArrayOp::select(self.data()?, selection))
     }
 
@@ -256,7 +443,9 @@ impl<B: Backend> InnerDataFrameElem<B> {
     where
         S: AsRef<SelectInfoElem>,
     {
-        Ok(ArrayOp::select_axis(self.data()?, axis, selection))
+        let full = SelectInfoElem::full();
+        let slice = selection.as_ref().set_axis(axis, 2, &full);
+        self.select(slice.as_slice())
     }
 
     pub fn save(&mut self, data: DataFrame) -> Result<()> {
@@ -270,6 +459,7 @@ impl<B: Backend> InnerDataFrameElem<B> {
         if self.element.is_some() {
             self.element = Some(data);
         }
+        self.columns.clear();
         Ok(())
     }
 
@@ -293,6 +483,25 @@ impl<B: Backend> InnerDataFrameElem<B> {
         let slice = selection.as_ref().set_axis(axis, 2, &full);
         self.subset(slice.as_slice())
     }
+
+    /// Subset the rows of the dataframe, keeping all columns.
+    pub fn subset_rows<S>(&mut self, selection: S) -> Result<()>
+    where
+        S: AsRef<SelectInfoElem>,
+    {
+        let selection = selection.as_ref();
+        if selection.is_full() {
+            return Ok(());
+        }
+        check_select(&[selection], &self.height().into())?;
+        let rows = BoundedSelectInfoElem::new(selection, self.height()).to_vec();
+        let df = self.data()?.take_iter(rows.into_iter())?;
+        self.index = self.index.select(selection);
+        replace_with::replace_with_or_abort(&mut self.container, |x| {
+            self.index.overwrite(x).unwrap()
+        });
+        self.save(df)
+    }
 }
 
 pub type DataFrameElem<B> = Slot<InnerDataFrameElem<B>>;
@@ -312,6 +521,7 @@ impl<B: Backend> TryFrom<DataContainer<B>> for DataFrameElem<B> {
                     .collect();
                 let df = InnerDataFrameElem {
                     element: None,
+                    columns: HashMap::new(),
                     container,
                     column_names,
                     index,
@@ -331,6 +541,39 @@ impl<B: Backend> DataFrameElem<B> {
         }
         Ok(())
     }
+
+    /// Rename a column. See `InnerDataFrameElem::rename_column`.
+    pub fn rename_column(&self, name: &str, new_name: &str) -> Result<()> {
+        self.lock()
+            .as_mut()
+            .context("cannot rename a column of an empty dataframe")?
+            .rename_column(name, new_name)
+    }
+
+    /// Add or replace a column. See `InnerDataFrameElem::add_column`.
+    pub fn add_column(&self, series: Series) -> Result<()> {
+        self.lock()
+            .as_mut()
+            .context("cannot add a column to an empty dataframe")?
+            .add_column(series)
+    }
+
+    /// Join an external table. See `InnerDataFrameElem::join`.
+    pub fn join(&self, other: DataFrame, on: &str, how: JoinType) -> Result<()> {
+        self.lock()
+            .as_mut()
+            .context("cannot join a table to an empty dataframe")?
+            .join(other, on, how)
+    }
+
+    /// Write the dataframe to a Parquet file. The index is stored as the first
+    /// column so that the file can be read back by `AnnDataOp::set_obs_from_parquet`.
+    pub fn export_parquet<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        match self.lock().as_ref() {
+            Some(df) => df.export_parquet(path),
+            None => bail!("cannot export an empty dataframe"),
+        }
+    }
 }
 
 /// Container holding general data types.
@@ -370,6 +613,22 @@ impl<B: Backend, T> InnerElem<B, T> {
         self.cache_enabled = false;
     }
 
+    /// Drop the cached data but keep the cache enabled, so that the data is
+    /// cached again on the next read.
+    pub fn evict(&mut self) {
+        self.clear_cache();
+    }
+
+    /// Point the element to its container after the container has been renamed.
+    pub(crate) fn relocate(&mut self, container: DataContainer<B>) {
+        self.container = container;
+    }
+
+    /// Drop the cached data, e.g., after the stored data was changed in place.
+    pub(crate) fn clear_cache(&mut self) {
+        self.element = None;
+    }
+
     pub(crate) fn save<D: WriteData + Into<T>>(&mut self, data: D) -> Result<()> {
         replace_with::replace_with_or_abort(&mut self.container, |x| data.overwrite(x).unwrap());
         self.dtype = data.data_type();
@@ -446,9 +705,28 @@ pub struct InnerArrayElem<B: Backend, T> {
     shape: Shape,
     cache_enabled: bool,
     container: DataContainer<B>,
-    element: Option<T>,
+    /// The cached data. It is shared with the handles returned by `cached`,
+    /// and is therefore never modified in place, only replaced.
+    element: Option<Arc<T>>,
+    chunk_cache: Option<ChunkCache>,
+    /// Whether the element is opened through an external link, in which case
+    /// the data lives in another file and cannot be written.
+    external: bool,
+    /// The budget that the cached data counts towards, if any.
+    cache_budget: Option<CacheBudget>,
+    /// The slot holding this element, through which the budget evicts the
+    /// cached data.
+    handle: Weak<dyn Evict>,
+    /// Whether a CSR matrix is stored as a CSC matrix of the same shape.
+    /// `dtype` describes the array as presented, not as stored.
+    transposed: bool,
 }
 
+/// The attribute marking a stored CSC matrix that is presented as a CSR
+/// matrix. The stored matrix is a standard CSC matrix of the same shape, so
+/// readers that do not know the attribute read the same matrix.
+const PRESENTED_ENCODING_ATTR: &str = "presented-encoding-type";
+
 impl<B: Backend, T> std::fmt::Display for InnerArrayElem<B, T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -461,7 +739,7 @@ impl<B: Backend, T> std::fmt::Display for InnerArrayElem<B, T> {
     }
 }
 
-impl<B: Backend, T> InnerArrayElem<B, T> {
+impl<B: Backend, T: CacheSize> InnerArrayElem<B, T> {
     pub fn dtype(&self) -> DataType {
         self.dtype
     }
@@ -475,35 +753,205 @@ impl<B: Backend, T> InnerArrayElem<B, T> {
     }
 
     pub fn disable_cache(&mut self) {
-        if self.element.is_some() {
-            self.element = None;
-        }
+        self.clear_cache();
         self.cache_enabled = false;
     }
 
+    /// Drop the cached data, including the cached row chunks, but keep the
+    /// cache enabled, so that the data is cached again on the next read.
+    pub fn evict(&mut self) {
+        self.clear_cache();
+        if let Some(cache) = self.chunk_cache.as_mut() {
+            cache.clear();
+        }
+    }
+
+    /// The number of bytes of the cached data.
+    pub fn cached_bytes(&self) -> usize {
+        self.element.as_ref().map_or(0, |x| x.cache_size())
+    }
+
+    /// Count the cached data towards `budget`. `handle` is the slot holding
+    /// this element, through which the budget evicts the data.
+    fn set_cache_budget(&mut self, budget: CacheBudget, handle: Weak<dyn Evict>) {
+        if let Some(old) = self.cache_budget.take() {
+            old.remove(&self.handle);
+        }
+        self.handle = handle;
+        if let Some(data) = self.element.as_ref() {
+            budget.admit(self.handle.clone(), data.cache_size());
+        }
+        self.cache_budget = Some(budget);
+    }
+
+    /// Cache `data` and count it towards the budget.
+    fn set_cached(&mut self, data: T) {
+        if let Some(budget) = self.cache_budget.as_ref() {
+            budget.admit(self.handle.clone(), data.cache_size());
+        }
+        self.element = Some(Arc::new(data));
+    }
+
+    /// Return a handle to the cached data, if any, without copying it. The
+    /// data behind the handle is never modified: writing to the element or
+    /// evicting the cache replaces or drops the element's own handle, and the
+    /// data is freed once all handles are dropped.
+    pub fn cached(&self) -> Option<Arc<T>> {
+        let data = self.element.clone()?;
+        if let Some(budget) = self.cache_budget.as_ref() {
+            budget.touch(&self.handle);
+        }
+        Some(data)
+    }
+
+    /// Drop the cached data, e.g., after the stored data was changed in place.
+    pub(crate) fn clear_cache(&mut self) {
+        if self.element.take().is_some() {
+            if let Some(budget) = self.cache_budget.as_ref() {
+                budget.remove(&self.handle);
+            }
+        }
+    }
+
+    /// Cache the row chunks read by `ChunkedArrayElem`, keeping at most `capacity`
+    /// bytes of the most recently used chunks.
+    pub fn enable_chunk_cache(&mut self, capacity: usize) {
+        self.chunk_cache = Some(ChunkCache::new(capacity));
+    }
+
+    pub fn disable_chunk_cache(&mut self) {
+        self.chunk_cache = None;
+    }
+
+    pub fn chunk_cache(&self) -> Option<&ChunkCache> {
+        self.chunk_cache.as_ref()
+    }
+
+    /// Whether the element is a link to data in another file.
+    pub fn is_external(&self) -> bool {
+        self.external
+    }
+
+    /// Whether the CSR matrix is stored in the CSC layout. See `set_transposed`.
+    pub fn is_transposed(&self) -> bool {
+        self.transposed
+    }
+
+    pub(crate) fn set_external(&mut self) {
+        self.external = true;
+    }
+
+    fn ensure_writable(&self) -> Result<()> {
+        ensure!(
+            !self.external,
+            "cannot write to {}, which is an external link to another file",
+            self.container.path().display(),
+        );
+        Ok(())
+    }
+
+    /// Point the element to its container after the container has been renamed.
+    pub(crate) fn relocate(&mut self, container: DataContainer<B>) {
+        self.container = container;
+    }
+}
+
+impl<B: Backend, T: WriteArrayData + ColumnMajor + Clone + CacheSize> InnerArrayElem<B, T> {
     pub(crate) fn save<D: HasShape + WriteArrayData + Into<T>>(&mut self, data: D) -> Result<()> {
-        replace_with::replace_with_or_abort(&mut self.container, |x| data.overwrite(x).unwrap());
-        self.dtype = data.data_type();
-        self.shape = data.shape();
-        if self.element.is_some() {
-            self.element = Some(data.into());
+        self.save_impl(data, None)
+    }
+
+    /// Like `save`, but write the data with the given dataset options.
+    pub(crate) fn save_with_config<D>(&mut self, data: D, config: WriteConfig) -> Result<()>
+    where
+        D: HasShape + WriteArrayData + Into<T>,
+    {
+        self.save_impl(data, Some(config))
+    }
+
+    fn save_impl<D>(&mut self, data: D, config: Option<WriteConfig>) -> Result<()>
+    where
+        D: HasShape + WriteArrayData + Into<T>,
+    {
+        self.ensure_writable()?;
+        let (dtype, shape) = (data.data_type(), data.shape());
+        if self.transposed && !matches!(dtype, DataType::CsrMatrix(_)) {
+            self.transposed = false;
+        }
+        if self.transposed {
+            let data: T = data.into();
+            self.overwrite(&data, config)?;
+            if self.element.is_some() {
+                self.set_cached(data);
+            }
+        } else {
+            replace_with::replace_with_or_abort(&mut self.container, |x| {
+                match config {
+                    Some(config) => data.overwrite_with_config(x, config),
+                    None => data.overwrite(x),
+                }
+                .unwrap()
+            });
+            if self.element.is_some() {
+                self.set_cached(data.into());
+            }
+        }
+        self.dtype = dtype;
+        self.shape = shape;
+        if let Some(cache) = self.chunk_cache.as_mut() {
+            cache.clear();
+        }
+        Ok(())
+    }
+
+    /// Replace the stored data by `data`, converted to the CSC layout if the
+    /// array is stored transposed.
+    fn overwrite(&mut self, data: &T, config: Option<WriteConfig>) -> Result<()> {
+        let stored;
+        let data = if self.transposed {
+            stored = data.clone().to_csc()?;
+            &stored
+        } else {
+            data
+        };
+        replace_with::replace_with_or_abort(&mut self.container, |x| {
+            match config {
+                Some(config) => data.overwrite_with_config(x, config),
+                None => data.overwrite(x),
+            }
+            .unwrap()
+        });
+        if self.transposed {
+            self.container.write_str_attr(PRESENTED_ENCODING_ATTR, "csr_matrix")?;
         }
         Ok(())
     }
 }
 
-impl<B: Backend, T: Clone> InnerArrayElem<B, T> {
+impl<B: Backend, T: ReadArrayData + ColumnMajor + Clone + CacheSize> InnerArrayElem<B, T> {
     pub fn data<D>(&mut self) -> Result<D>
     where
         D: Into<T> + ReadData + Clone + TryFrom<T>,
         <D as TryFrom<T>>::Error: Into<anyhow::Error>,
     {
         match self.element.as_ref() {
-            Some(data) => Ok(data.clone().try_into().map_err(Into::into)?),
+            Some(data) => {
+                if let Some(budget) = self.cache_budget.as_ref() {
+                    budget.touch(&self.handle);
+                }
+                Ok(T::clone(data).try_into().map_err(Into::into)?)
+            }
             None => {
-                let data = D::read(&self.container)?;
+                let data = if self.transposed {
+                    T::read(&self.container)?
+                        .to_csr()?
+                        .try_into()
+                        .map_err(Into::into)?
+                } else {
+                    D::read(&self.container)?
+                };
                 if self.cache_enabled {
-                    self.element = Some(data.clone().into());
+                    self.set_cached(data.clone().into());
                 }
                 Ok(data)
             }
@@ -511,7 +959,7 @@ impl<B: Backend, T: Clone> InnerArrayElem<B, T> {
     }
 }
 
-impl<B: Backend, T: ReadArrayData + WriteArrayData + Clone> InnerArrayElem<B, T> {
+impl<B: Backend, T: ReadArrayData + WriteArrayData + ColumnMajor + Clone + CacheSize> InnerArrayElem<B, T> {
     pub fn export<O: Backend, G: GroupOp<Backend = O>>(
         &self,
         location: &G,
@@ -519,13 +967,14 @@ impl<B: Backend, T: ReadArrayData + WriteArrayData + Clone> InnerArrayElem<B, T>
     ) -> Result<()> {
         match self.element.as_ref() {
             Some(data) => data.write(location, name)?,
+            None if self.transposed => T::read(&self.container)?.to_csr()?.write(location, name)?,
             None => T::read(&self.container)?.write(location, name)?,
         };
         Ok(())
     }
 }
 
-impl<B: Backend, T: ArrayOp + Clone> InnerArrayElem<B, T> {
+impl<B: Backend, T: ReadArrayData + ArrayOp + ColumnMajor + Clone + CacheSize> InnerArrayElem<B, T> {
     pub fn select<D, S>(&mut self, selection: &[S]) -> Result<D>
     where
         D: Into<T> + TryFrom<T> + ReadArrayData + Clone,
@@ -535,8 +984,13 @@ impl<B: Backend, T: ArrayOp + Clone> InnerArrayElem<B, T> {
         if selection.as_ref().iter().all(|x| x.as_ref().is_full()) {
             self.data()
         } else {
+            check_select(selection, &self.shape)?;
             match self.element.as_ref() {
                 Some(data) => Ok(data.select(selection).try_into().map_err(Into::into)?),
+                None if self.transposed => Ok(T::read_select(&self.container, selection)?
+                    .to_csr()?
+                    .try_into()
+                    .map_err(Into::into)?),
                 None => D::read_select(&self.container, selection),
             }
         }
@@ -556,7 +1010,254 @@ impl<B: Backend, T: ArrayOp + Clone> InnerArrayElem<B, T> {
     }
 }
 
-impl<B: Backend, T: ReadArrayData + WriteArrayData + ArrayOp + Clone> InnerArrayElem<B, T> {
+impl<B: Backend> InnerArrayElem<B, ArrayData> {
+    /// Store the CSR matrix as a CSC matrix of the same shape, i.e., with its
+    /// entries sorted by column, or back as a CSR matrix if `transposed` is
+    /// false. The matrix is presented as a CSR matrix either way, but reading
+    /// its columns, e.g., by `ArrayElem::chunked_cols`, becomes the cheap path
+    /// when it is stored transposed. The stored CSC matrix carries an
+    /// attribute recording that it is presented as a CSR matrix, and the data
+    /// is rewritten if the layout changes.
+    pub fn set_transposed(&mut self, transposed: bool) -> Result<()> {
+        if self.transposed == transposed {
+            return Ok(());
+        }
+        self.ensure_writable()?;
+        ensure!(
+            matches!(self.dtype, DataType::CsrMatrix(_)),
+            "only a CSR matrix can be stored transposed, not {}",
+            self.dtype
+        );
+        let data: ArrayData = self.data()?;
+        self.transposed = transposed;
+        let result = self.save(data);
+        if result.is_err() {
+            self.transposed = !transposed;
+        }
+        result
+    }
+
+    /// Append rows to the array. If `n_rows` is given, it must match the number
+    /// of appended rows. If an error occurs, the rows appended so far are
+    /// removed and the array is left unchanged.
+    pub(crate) fn append_rows<I, D>(&mut self, iter: I, n_rows: Option<usize>) -> Result<()>
+    where
+        I: Iterator<Item = D>,
+        D: ArrayChunk,
+    {
+        self.ensure_writable()?;
+        ensure!(!self.transposed, "cannot append rows to an array stored transposed");
+        let n = self.shape[0];
+        let result = D::append_by_chunk(iter, &self.container).and_then(|_| {
+            let appended = ArrayData::get_shape(&self.container)?[0] - n;
+            match n_rows {
+                Some(m) if m != appended => bail!("expect {} rows to be appended, but got {}", m, appended),
+                _ => Ok(()),
+            }
+        });
+        if let Err(e) = result {
+            truncate_rows(&self.container, n)?;
+            return Err(e);
+        }
+        self.shape = ArrayData::get_shape(&self.container)?;
+        self.clear_cache();
+        Ok(())
+    }
+
+    /// Apply `f` to each chunk of `chunk_size` rows and write the result back in
+    /// place. `f` must preserve the shape and the data type of the chunk, and,
+    /// for CSR matrices, the number of non-zero entries.
+    pub fn map_chunks<F>(&mut self, chunk_size: usize, mut f: F) -> Result<()>
+    where
+        F: FnMut(ArrayData) -> Result<ArrayData>,
+    {
+        ensure!(chunk_size > 0, "chunk_size must be positive");
+        self.ensure_writable()?;
+        ensure!(!self.transposed, "cannot transform an array stored transposed by chunk");
+        let n = self.shape[0];
+        let result = (0..n).step_by(chunk_size).try_for_each(|start| {
+            let end = (start + chunk_size).min(n);
+            let chunk: ArrayData = self.select_axis(0, SelectInfoElem::from(start..end))?;
+            let shape = chunk.shape();
+            let data = f(chunk)?;
+            ensure!(
+                data.shape().as_ref() == shape.as_ref(),
+                "the shape of rows {}..{} changed from {} to {}",
+                start, end, shape, data.shape(),
+            );
+            ensure!(
+                data.data_type() == self.dtype,
+                "the data type of rows {}..{} changed from {} to {}",
+                start, end, self.dtype, data.data_type(),
+            );
+            write_rows(&self.container, start, data)
+        });
+        self.clear_cache();
+        if let Some(cache) = self.chunk_cache.as_mut() {
+            cache.clear();
+        }
+        result
+    }
+
+    /// Overwrite the rows starting at `start` with the rows of `data`, which
+    /// must have the data type of the array and the same size in the other
+    /// dimensions. The rows of a CSR matrix can only be replaced by rows with
+    /// the same number of non-zero entries.
+    pub fn write_rows(&mut self, start: usize, data: ArrayData) -> Result<()> {
+        self.ensure_writable()?;
+        ensure!(!self.transposed, "cannot write the rows of an array stored transposed");
+        ensure!(
+            data.data_type() == self.dtype,
+            "cannot write {} to rows of {}",
+            data.data_type(), self.dtype,
+        );
+        let shape = data.shape();
+        ensure!(
+            shape.ndim() == self.shape.ndim() && shape.as_ref()[1..] == self.shape.as_ref()[1..],
+            "cannot write rows of shape {} to an array of shape {}",
+            shape, self.shape,
+        );
+        let end = start + shape[0];
+        ensure!(
+            end <= self.shape[0],
+            "rows {}..{} are out of bounds for an array with {} rows",
+            start, end, self.shape[0],
+        );
+        let result = write_rows(&self.container, start, data);
+        self.clear_cache();
+        if let Some(cache) = self.chunk_cache.as_mut() {
+            cache.clear();
+        }
+        result
+    }
+
+    /// Overwrite the columns `cidx` of a dense matrix with the columns of
+    /// `data`, which must be a dense `(n_rows, cidx.len())` array of the same
+    /// data type. Only the selected columns are written; the rest of the
+    /// dataset is left untouched.
+    pub fn write_columns(&mut self, cidx: &[usize], data: ArrayData) -> Result<()> {
+        self.ensure_writable()?;
+        ensure!(!self.transposed, "cannot write the columns of an array stored transposed");
+        ensure!(
+            matches!(self.dtype, DataType::Array(_)) && self.shape.ndim() == 2,
+            "columns can only be written to a dense matrix, found {} with shape {}",
+            self.dtype, self.shape,
+        );
+        let data = match data {
+            ArrayData::Array(x) => x,
+            x => bail!("expecting a dense array, found {}", x.data_type()),
+        };
+        ensure!(
+            data.data_type() == self.dtype,
+            "cannot write {} to columns of {}",
+            data.data_type(), self.dtype,
+        );
+        let shape = data.shape();
+        ensure!(
+            shape.ndim() == 2 && shape[0] == self.shape[0] && shape[1] == cidx.len(),
+            "expecting data with shape ({}, {}), found {}",
+            self.shape[0], cidx.len(), shape,
+        );
+        if let Some(i) = cidx.iter().find(|&&i| i >= self.shape[1]) {
+            bail!("column {} is out of bounds for a matrix with {} columns", i, self.shape[1]);
+        }
+        ensure!(cidx.iter().all_unique(), "column indices must be unique");
+        if cidx.is_empty() {
+            return Ok(());
+        }
+        let result = write_columns(&self.container, cidx, data);
+        self.clear_cache();
+        if let Some(cache) = self.chunk_cache.as_mut() {
+            cache.clear();
+        }
+        result
+    }
+
+    /// Like `export_select`, but the selected rows are read and written in
+    /// chunks of `chunk_size` rows. Dataframes and categorical arrays, which
+    /// cannot be written by chunk, are exported by `export_select`.
+    pub fn export_select_by_chunk<O, G>(
+        &mut self,
+        selection: &[&SelectInfoElem],
+        chunk_size: usize,
+        location: &G,
+        name: &str,
+    ) -> Result<()>
+    where
+        O: Backend,
+        G: GroupOp<Backend = O>,
+    {
+        self.export_select_by_chunk_with_progress::<O, _, _>(selection, chunk_size, location, name, |_| {})
+    }
+
+    /// Like `export_select_by_chunk`, but `progress` is called with the number
+    /// of rows written so far each time a chunk has been written.
+    pub fn export_select_by_chunk_with_progress<O, G, F>(
+        &mut self,
+        selection: &[&SelectInfoElem],
+        chunk_size: usize,
+        location: &G,
+        name: &str,
+        mut progress: F,
+    ) -> Result<()>
+    where
+        O: Backend,
+        G: GroupOp<Backend = O>,
+        F: FnMut(usize),
+    {
+        let rows = BoundedSelectInfoElem::new(selection[0], self.shape[0]);
+        let n = rows.len();
+        let by_chunk = matches!(self.dtype, DataType::Array(_) | DataType::CsrMatrix(_));
+        if !by_chunk || self.element.is_some() || n <= chunk_size {
+            self.export_select::<O, _>(selection, location, name)?;
+            progress(n);
+            return Ok(());
+        }
+
+        let mut error = None;
+        let chunks = (0..n).step_by(chunk_size).map_while(|i| {
+            // The chunks before `i` have been written when the next one is requested.
+            if i > 0 {
+                progress(i);
+            }
+            let end = (i + chunk_size).min(n);
+            let chunk: SelectInfoElem = match rows {
+                BoundedSelectInfoElem::Slice(ref s) if s.step == 1 => (s.start + i..s.start + end).into(),
+                _ => (i..end).map(|k| rows.index(k)).collect(),
+            };
+            let mut select = selection.to_vec();
+            select[0] = &chunk;
+            self.select::<ArrayData, _>(&select).map_err(|e| error = Some(e)).ok()
+        });
+        let result = ArrayChunk::write_by_chunk(chunks, location, name);
+        match error {
+            Some(e) => Err(e),
+            None => {
+                result?;
+                progress(n);
+                Ok(())
+            }
+        }
+    }
+
+    /// Read the rows `start..end`, using the chunk cache if it is enabled.
+    fn read_chunk<D>(&mut self, start: usize, end: usize) -> Result<D>
+    where
+        D: Into<ArrayData> + TryFrom<ArrayData> + ReadArrayData + Clone,
+        <D as TryFrom<ArrayData>>::Error: Into<anyhow::Error>,
+    {
+        if let Some(data) = self.chunk_cache.as_mut().and_then(|x| x.get(start, end)) {
+            return data.try_into().map_err(Into::into);
+        }
+        let data: D = self.select_axis(0, SelectInfoElem::from(start..end))?;
+        if let Some(cache) = self.chunk_cache.as_mut() {
+            cache.insert(start, end, data.clone().into());
+        }
+        Ok(data)
+    }
+}
+
+impl<B: Backend, T: ReadArrayData + WriteArrayData + ArrayOp + ColumnMajor + Clone + CacheSize> InnerArrayElem<B, T> {
     pub fn export_select<O, G>(
         &mut self,
         selection: &[&SelectInfoElem],
@@ -597,15 +1298,21 @@ impl<B: Backend, T: ReadArrayData + WriteArrayData + ArrayOp + Clone> InnerArray
     where
         S: AsRef<SelectInfoElem>,
     {
+        self.ensure_writable()?;
+        check_select(selection, &self.shape)?;
         let data = match self.element.as_ref() {
             Some(data) => data.select(selection),
+            None if self.transposed => T::read_select(&self.container, selection)?.to_csr()?,
             None => T::read_select(&self.container, selection)?,
         };
 
         self.shape = data.shape();
-        replace_with::replace_with_or_abort(&mut self.container, |x| data.overwrite(x).unwrap());
+        self.overwrite(&data, None)?;
         if self.element.is_some() {
-            self.element = Some(data);
+            self.set_cached(data);
+        }
+        if let Some(cache) = self.chunk_cache.as_mut() {
+            cache.clear();
         }
         Ok(())
     }
@@ -629,18 +1336,47 @@ impl<B: Backend> TryFrom<DataContainer<B>> for ArrayElem<B> {
     type Error = anyhow::Error;
 
     fn try_from(container: DataContainer<B>) -> Result<Self> {
-        let dtype = container.encoding_type()?;
+        let shape = ArrayData::get_shape(&container)?;
+        let (dtype, transposed) = match container.encoding_type()? {
+            DataType::CscMatrix(ty)
+                if container
+                    .read_str_attr(PRESENTED_ENCODING_ATTR)
+                    .map_or(false, |x| x == "csr_matrix") =>
+            {
+                (DataType::CsrMatrix(ty), true)
+            }
+            ty => (ty, false),
+        };
         let elem = InnerArrayElem {
             dtype,
-            shape: ArrayData::get_shape(&container)?,
+            shape,
             cache_enabled: false,
             element: None,
             container,
+            chunk_cache: None,
+            external: false,
+            cache_budget: None,
+            handle: Weak::<Mutex<Option<InnerArrayElem<B, ArrayData>>>>::new(),
+            transposed,
         };
         Ok(Slot::new(elem))
     }
 }
 
+impl<B: Backend> Evict for Mutex<Option<InnerArrayElem<B, ArrayData>>> {
+    fn try_evict(&self) -> bool {
+        match self.try_lock() {
+            Some(mut guard) => {
+                if let Some(elem) = guard.as_mut() {
+                    elem.element = None;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
 impl<B: Backend> ArrayElemOp for ArrayElem<B> {
     type ArrayIter<T> = ChunkedArrayElem<B, T>
         where
@@ -677,10 +1413,28 @@ impl<B: Backend> ArrayElemOp for ArrayElem<B> {
 }
 
 impl<B: Backend> ArrayElem<B> {
-    /// Delete and Remove the data from the element.
+    /// Count the data cached by the element towards `budget`. The least
+    /// recently used data is dropped when the budget is exceeded.
+    pub(crate) fn set_cache_budget(&self, budget: CacheBudget) {
+        let handle: Weak<dyn Evict> = Arc::downgrade(&self.0) as Weak<_>;
+        if let Some(elem) = self.lock().as_mut() {
+            elem.set_cache_budget(budget, handle);
+        }
+    }
+
+    /// The number of bytes of data cached by the element.
+    pub fn cached_bytes(&self) -> usize {
+        self.lock().as_ref().map_or(0, |x| x.cached_bytes())
+    }
+
+    /// Delete and Remove the data from the element. The data of an external
+    /// link is left untouched; the link itself is removed by its collection.
     pub fn clear(&self) -> Result<()> {
-        if let Some(elem) = self.extract() {
-            DataContainer::delete(elem.container)?;
+        if let Some(mut elem) = self.extract() {
+            elem.clear_cache();
+            if !elem.external {
+                DataContainer::delete(elem.container)?;
+            }
         }
         Ok(())
     }
@@ -691,6 +1445,98 @@ impl<B: Backend> ArrayElem<B> {
     {
         ChunkedArrayElem::new(self.clone(), chunk_size)
     }
+
+    /// Transform the array in place, chunk by chunk. See `InnerArrayElem::map_chunks`.
+    pub fn map_chunks<F>(&self, chunk_size: usize, f: F) -> Result<()>
+    where
+        F: FnMut(ArrayData) -> Result<ArrayData>,
+    {
+        self.lock()
+            .as_mut()
+            .context("cannot transform an empty element")?
+            .map_chunks(chunk_size, f)
+    }
+
+    /// Overwrite some rows of the array in place. See
+    /// `InnerArrayElem::write_rows`.
+    pub fn write_rows(&self, start: usize, data: ArrayData) -> Result<()> {
+        self.lock()
+            .as_mut()
+            .context("cannot write to an empty element")?
+            .write_rows(start, data)
+    }
+
+    /// Overwrite some columns of a dense matrix in place. See
+    /// `InnerArrayElem::write_columns`.
+    pub fn write_columns(&self, cidx: &[usize], data: ArrayData) -> Result<()> {
+        self.lock()
+            .as_mut()
+            .context("cannot write to an empty element")?
+            .write_columns(cidx, data)
+    }
+
+    /// Convert a sparse matrix to a dense array and rewrite it in place. See
+    /// [`ArrayData::to_dense`].
+    pub fn to_dense(&self) -> Result<()> {
+        let mut inner = self.lock();
+        let elem = inner.as_mut().context("cannot convert an empty element")?;
+        if matches!(elem.dtype(), DataType::Array(_) | DataType::Categorical) {
+            return Ok(());
+        }
+        let data: ArrayData = elem.data()?;
+        elem.save(data.to_dense()?)
+    }
+
+    /// Convert a sparse matrix to a dense array whose missing entries are set
+    /// to `fill`, and rewrite it in place. The dataset is written with `fill`
+    /// as its fill value, so blocks of the array that only contain `fill` are
+    /// not stored. Dense arrays are rewritten with the fill value as well. See
+    /// [`ArrayData::to_dense_with_fill`].
+    pub fn to_dense_with_fill(&self, fill: DynScalar) -> Result<()> {
+        let mut inner = self.lock();
+        let elem = inner.as_mut().context("cannot convert an empty element")?;
+        let data: ArrayData = elem.data()?;
+        let data = data.to_dense_with_fill(fill.clone())?;
+        let fill = match data.data_type() {
+            DataType::Array(ty) => fill.cast(ty)?,
+            ty => bail!("cannot set a fill value for {}", ty),
+        };
+        let config = WriteConfig {
+            fill_value: Some(fill),
+            ..Default::default()
+        };
+        elem.save_with_config(data, config)
+    }
+
+    /// Convert a dense array to a CSR matrix and rewrite it in place. See
+    /// [`ArrayData::to_sparse`].
+    pub fn to_sparse(&self, threshold: f64) -> Result<()> {
+        let mut inner = self.lock();
+        let elem = inner.as_mut().context("cannot convert an empty element")?;
+        if matches!(elem.dtype(), DataType::CsrMatrix(_) | DataType::CscMatrix(_)) {
+            return Ok(());
+        }
+        let data: ArrayData = elem.data()?;
+        elem.save(data.to_sparse(threshold)?)
+    }
+
+    /// Store the array transposed. See `InnerArrayElem::set_transposed`.
+    pub fn set_transposed(&self, transposed: bool) -> Result<()> {
+        self.lock()
+            .as_mut()
+            .context("cannot transpose an empty element")?
+            .set_transposed(transposed)
+    }
+
+    /// Return an iterator over the columns of the array, i.e., chunks of
+    /// `chunk_size` columns along the second axis. This is the cheap path if
+    /// the array is stored transposed.
+    pub fn chunked_cols<T>(&self, chunk_size: usize) -> ChunkedArrayElem<B, T>
+    where
+        T: Into<ArrayData> + TryFrom<ArrayData> + ReadArrayData + Clone,
+    {
+        ChunkedArrayElem::with_axis(self.clone(), 1, chunk_size)
+    }
 }
 
 /// Horizontal concatenated dataframe elements.
@@ -795,6 +1641,7 @@ impl<B: Backend> StackedDataFrame<B> {
     where
         S: AsRef<SelectInfoElem>,
     {
+        check_select(selection, &(self.height(), self.width()).into())?;
         let (indices, mapping) = self.index.split_select(selection.as_ref()[0].as_ref());
         let dfs = self
             .elems
@@ -837,6 +1684,8 @@ impl<B: Backend> StackedDataFrame<B> {
 
 pub struct InnerStackedArrayElem<B: Backend> {
     shape: Option<Shape>,
+    /// The common type of the elements, to which they are cast when stacked.
+    dtype: Option<DataType>,
     elems: SmallVec<[ArrayElem<B>; 96]>,
     index: VecVecIndex,
 }
@@ -851,7 +1700,7 @@ impl<B: Backend> std::fmt::Display for InnerStackedArrayElem<B> {
                 "{} stacked elements ({}) with {}",
                 self.shape.as_ref().unwrap(),
                 self.elems.len(),
-                self.elems[0].inner().dtype(),
+                self.dtype(),
             )
         }
     }
@@ -867,7 +1716,7 @@ impl<B: Backend> InnerStackedArrayElem<B> {
     }
 
     pub fn dtype(&self) -> DataType {
-        self.elems[0].inner().dtype()
+        self.dtype.unwrap()
     }
 
     pub fn shape(&self) -> &Option<Shape> {
@@ -886,7 +1735,7 @@ impl<B: Backend> InnerStackedArrayElem<B> {
                 .elems
                 .iter()
                 .flat_map(|x| x.lock().as_mut().map(|i| i.data::<ArrayData>()))
-                .process_results(|x| ArrayOp::vstack(x).unwrap())?;
+                .process_results(|x| ArrayOp::vstack(x))??;
             Some(array.try_into().map_err(Into::into)?)
         };
         Ok(data)
@@ -906,7 +1755,7 @@ impl<B: Backend> InnerStackedArrayElem<B> {
                 .flat_map(|x| x.lock().as_mut().map(|i| i.data::<ArrayData>()))
                 .collect::<Vec<_>>()
                 .into_iter()
-                .process_results(|x| ArrayOp::vstack(x).unwrap())?;
+                .process_results(|x| ArrayOp::vstack(x))??;
             Some(array.try_into().map_err(Into::into)?)
         };
         Ok(data)
@@ -937,6 +1786,9 @@ impl<B: Backend> InnerStackedArrayElem<B> {
         let data = if self.is_empty() {
             None
         } else {
+            if let Some(shape) = self.shape.as_ref() {
+                check_select(selection, shape)?;
+            }
             let (indices, mapping) = self.index.split_select(selection.as_ref()[0].as_ref());
             let array: ArrayData = self
                 .elems
@@ -950,7 +1802,7 @@ impl<B: Backend> InnerStackedArrayElem<B> {
                         el.inner().select(select.as_slice())
                     })
                 })
-                .process_results(|x| ArrayOp::vstack(x).unwrap())?;
+                .process_results(|x| ArrayOp::vstack(x))??;
             if let Some(m) = mapping {
                 Some(array
                     .select_axis(0, SelectInfoElem::from(reverse_mapping(m)))
@@ -971,6 +1823,9 @@ impl<B: Backend> InnerStackedArrayElem<B> {
         let data = if self.is_empty() {
             None
         } else {
+            if let Some(shape) = self.shape.as_ref() {
+                check_select(selection, shape)?;
+            }
             let (indices, mapping) = self.index.split_select(selection.as_ref()[0].as_ref());
             let array: ArrayData = self
                 .elems
@@ -986,7 +1841,7 @@ impl<B: Backend> InnerStackedArrayElem<B> {
                 })
                 .collect::<Vec<_>>()
                 .into_iter()
-                .process_results(|x| ArrayOp::vstack(x).unwrap())?;
+                .process_results(|x| ArrayOp::vstack(x))??;
             if let Some(m) = mapping {
                 Some(array
                     .select_axis(0, SelectInfoElem::from(reverse_mapping(m)))
@@ -1016,6 +1871,15 @@ impl<B: Backend> InnerStackedArrayElem<B> {
             }
         }
     }
+
+    /// Drop the cached data of all elements, keeping the cache enabled.
+    pub fn evict(&self) {
+        for el in self.elems.iter() {
+            if let Some(x) = el.lock().as_mut() {
+                x.evict();
+            }
+        }
+    }
 }
 
 pub struct StackedArrayElem<B: Backend>(Arc<InnerStackedArrayElem<B>>);
@@ -1079,19 +1943,22 @@ impl<B: Backend> StackedArrayElem<B> {
     pub fn empty() -> Self {
         Self(Arc::new(InnerStackedArrayElem {
             shape: None,
+            dtype: None,
             elems: SmallVec::new(),
             index: std::iter::empty().collect(),
         }))
     }
 
     pub(crate) fn new(elems: SmallVec<[ArrayElem<B>; 96]>) -> Result<Self> {
-        ensure!(
-            elems
-                .iter()
-                .map(|x| x.lock().as_ref().map(|x| x.dtype()))
-                .all_equal(),
-            "all elements must have the same dtype"
-        );
+        let dtype = elems
+            .iter()
+            .flat_map(|x| x.lock().as_ref().map(|x| x.dtype()))
+            .try_fold(None, |acc: Option<DataType>, ty| match acc {
+                None => Ok(Some(ty)),
+                Some(acc) => acc.promote(ty).map(Some).with_context(|| {
+                    format!("cannot stack elements of incompatible types {} and {}", acc, ty)
+                }),
+            })?;
 
         let shapes: Vec<_> = elems
             .iter()
@@ -1107,14 +1974,33 @@ impl<B: Backend> StackedArrayElem<B> {
             ss[0] = index.len();
             ss
         }));
-        Ok(Self(Arc::new(InnerStackedArrayElem { shape, elems, index })))
+        Ok(Self(Arc::new(InnerStackedArrayElem { shape, dtype, elems, index })))
     }
 
     pub fn chunked<T>(&self, chunk_size: usize) -> StackedChunkedArrayElem<B, T>
     where
         T: Into<ArrayData> + TryFrom<ArrayData> + ReadArrayData + Clone,
     {
-        StackedChunkedArrayElem::new(self.elems.iter().map(|x| x.clone()), chunk_size)
+        StackedChunkedArrayElem::new(self.elems.iter().map(|x| x.clone()), self.dtype, chunk_size)
+    }
+
+    /// Like `chunked`, but the chunks are read on the rayon thread pool. Up to
+    /// `prefetch` chunks, possibly from different components, are read ahead of
+    /// the consumer. Chunks are still yielded in order, and no new chunk is
+    /// requested until the consumer asks for the next one, so at most
+    /// `prefetch` chunks, including the one being consumed, are held in memory
+    /// at a time.
+    ///
+    /// Reading a chunk locks its component, so only chunks of different
+    /// components are read in parallel; the chunks of a single component are
+    /// read one after another. A chunk that cannot be read is yielded as an
+    /// error.
+    pub fn par_chunked<T>(&self, chunk_size: usize, prefetch: usize) -> ParStackedChunkedArrayElem<B, T>
+    where
+        T: Into<ArrayData> + TryFrom<ArrayData> + ReadArrayData + Clone + Send + 'static,
+        <T as TryFrom<ArrayData>>::Error: Into<anyhow::Error>,
+    {
+        ParStackedChunkedArrayElem::new(self.elems.iter().map(|x| x.clone()), self.dtype, chunk_size, prefetch)
     }
 }
 
@@ -1122,24 +2008,59 @@ impl<B: Backend> StackedArrayElem<B> {
 pub struct ChunkedArrayElem<B: Backend, T> {
     /// The underlying array element.
     elem: ArrayElem<B>,
+    /// The axis along which the array is chunked.
+    axis: usize,
     /// The chunk size.
     chunk_size: usize,
     num_items: usize,
     current_position: usize,
+    /// The scalar type to which the chunks are cast, if it differs from that
+    /// of the element.
+    dtype: Option<ScalarType>,
     type_marker: std::marker::PhantomData<T>,
 }
 
 impl<B: Backend, T> ChunkedArrayElem<B, T> {
     pub fn new(elem: ArrayElem<B>, chunk_size: usize) -> Self {
-        let num_items = elem.inner().shape()[0];
+        Self::with_axis(elem, 0, chunk_size)
+    }
+
+    /// Chunk the array along the given axis.
+    pub fn with_axis(elem: ArrayElem<B>, axis: usize, chunk_size: usize) -> Self {
+        let num_items = elem.inner().shape()[axis];
         Self {
             elem,
+            axis,
             chunk_size,
             num_items,
             current_position: 0,
+            dtype: None,
             type_marker: std::marker::PhantomData,
         }
     }
+
+    /// Cast the chunks to `dtype` if the element has a different type, e.g.,
+    /// to the promoted type of the elements of a stacked array.
+    pub(crate) fn with_dtype(mut self, dtype: Option<DataType>) -> Self {
+        if let Some(dtype) = dtype {
+            if self.elem.inner().dtype() != dtype {
+                self.dtype = scalar_type(dtype);
+            }
+        }
+        self
+    }
+
+    fn read<D>(&self, i: usize, j: usize) -> Result<D>
+    where
+        D: Into<ArrayData> + TryFrom<ArrayData> + ReadArrayData + Clone,
+        <D as TryFrom<ArrayData>>::Error: Into<anyhow::Error>,
+    {
+        if self.axis == 0 {
+            self.elem.inner().read_chunk(i, j)
+        } else {
+            self.elem.inner().select_axis(self.axis, SelectInfoElem::from(i..j))
+        }
+    }
 }
 
 impl<B, T> Iterator for ChunkedArrayElem<B, T>
@@ -1157,8 +2078,13 @@ where
             let i = self.current_position;
             let j = std::cmp::min(self.num_items, self.current_position + self.chunk_size);
             self.current_position = j;
-            let data = self.elem.inner().select_axis(0, SelectInfoElem::from(i..j)).unwrap();
-            Some((data, i, j))
+            let data = match self.dtype {
+                None => self.read(i, j),
+                Some(dtype) => self
+                    .read::<ArrayData>(i, j)
+                    .and_then(|x| cast_chunk(x, Some(dtype))),
+            };
+            Some((data.unwrap(), i, j))
         }
     }
 }
@@ -1179,6 +2105,59 @@ where
     }
 }
 
+/// Chunks of rows of an array, each paired with the same rows of some columns
+/// of a dataframe, e.g., X and obs. See `AnnData::chunked_with_obs`. A chunk
+/// whose columns cannot be read is yielded as an error.
+pub struct ChunkedArrayWithColumns<B: Backend, T> {
+    chunks: ChunkedArrayElem<B, T>,
+    df: DataFrameElem<B>,
+    columns: Vec<String>,
+}
+
+impl<B: Backend, T> ChunkedArrayWithColumns<B, T> {
+    pub fn new(elem: ArrayElem<B>, df: DataFrameElem<B>, columns: Vec<String>, chunk_size: usize) -> Self {
+        Self {
+            chunks: ChunkedArrayElem::new(elem, chunk_size),
+            df,
+            columns,
+        }
+    }
+}
+
+impl<B, T> Iterator for ChunkedArrayWithColumns<B, T>
+where
+    B: Backend,
+    T: Into<ArrayData> + TryFrom<ArrayData> + ReadArrayData + Clone,
+    <T as TryFrom<ArrayData>>::Error: Into<anyhow::Error>,
+{
+    type Item = Result<(T, DataFrame)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (data, i, j) = self.chunks.next()?;
+        if self.columns.is_empty() {
+            return Some(Ok((data, DataFrame::empty())));
+        }
+        let df = self
+            .df
+            .lock()
+            .as_mut()
+            .context("the dataframe is empty")
+            .and_then(|df| df.select_columns(&self.columns, SelectInfoElem::from(i..j)));
+        Some(df.map(|df| (data, df)))
+    }
+}
+
+impl<B, T> ExactSizeIterator for ChunkedArrayWithColumns<B, T>
+where
+    B: Backend,
+    T: Into<ArrayData> + TryFrom<ArrayData> + ReadArrayData + Clone,
+    <T as TryFrom<ArrayData>>::Error: Into<anyhow::Error>,
+{
+    fn len(&self) -> usize {
+        self.chunks.len()
+    }
+}
+
 pub struct StackedChunkedArrayElem<B: Backend, T> {
     arrays: SmallVec<[ChunkedArrayElem<B, T>; 96]>,
     current_position: usize,
@@ -1186,10 +2165,13 @@ pub struct StackedChunkedArrayElem<B: Backend, T> {
 }
 
 impl<B: Backend, T> StackedChunkedArrayElem<B, T> {
-    pub(crate) fn new<I: Iterator<Item = ArrayElem<B>>>(elems: I, chunk_size: usize) -> Self {
+    pub(crate) fn new<I>(elems: I, dtype: Option<DataType>, chunk_size: usize) -> Self
+    where
+        I: Iterator<Item = ArrayElem<B>>,
+    {
         Self {
             arrays: elems
-                .map(|x| ChunkedArrayElem::new(x, chunk_size))
+                .map(|x| ChunkedArrayElem::new(x, chunk_size).with_dtype(dtype))
                 .collect(),
             current_position: 0,
             current_array: 0,
@@ -1233,6 +2215,135 @@ where
     }
 }
 
+/// Chunks of a stacked array that are read in parallel, see
+/// `StackedArrayElem::par_chunked`.
+pub struct ParStackedChunkedArrayElem<B: Backend, T> {
+    elems: SmallVec<[ArrayElem<B>; 96]>,
+    /// Chunks that have not been requested yet, as the index of the component,
+    /// the rows of the chunk in the component, and the first row of the chunk
+    /// in the stacked array.
+    pending: VecDeque<(usize, usize, usize, usize)>,
+    /// Chunks being read or waiting to be consumed, in order.
+    in_flight: VecDeque<(mpsc::Receiver<Result<T>>, usize, usize)>,
+    /// The type of the stacked array, to which the chunks are cast.
+    dtype: Option<DataType>,
+    prefetch: usize,
+}
+
+impl<B: Backend, T> ParStackedChunkedArrayElem<B, T> {
+    pub(crate) fn new<I>(elems: I, dtype: Option<DataType>, chunk_size: usize, prefetch: usize) -> Self
+    where
+        I: Iterator<Item = ArrayElem<B>>,
+    {
+        let elems: SmallVec<[ArrayElem<B>; 96]> = elems.collect();
+        let chunk_size = chunk_size.max(1);
+        let mut pending = VecDeque::new();
+        let mut offset = 0;
+        for (k, elem) in elems.iter().enumerate() {
+            let n = elem.inner().shape()[0];
+            for i in (0..n).step_by(chunk_size) {
+                let j = (i + chunk_size).min(n);
+                pending.push_back((k, i, j, offset + i));
+            }
+            offset += n;
+        }
+        Self {
+            elems,
+            pending,
+            in_flight: VecDeque::new(),
+            dtype,
+            prefetch: prefetch.max(1),
+        }
+    }
+}
+
+impl<B, T> ParStackedChunkedArrayElem<B, T>
+where
+    B: Backend,
+    T: Into<ArrayData> + TryFrom<ArrayData> + ReadArrayData + Clone + Send + 'static,
+    <T as TryFrom<ArrayData>>::Error: Into<anyhow::Error>,
+{
+    /// Request chunks until `prefetch` chunks are in flight.
+    fn fill(&mut self) {
+        while self.in_flight.len() < self.prefetch {
+            let Some((k, i, j, start)) = self.pending.pop_front() else {
+                break;
+            };
+            let elem = self.elems[k].clone();
+            let dtype = self.dtype;
+            let (sender, receiver) = mpsc::sync_channel(1);
+            rayon::spawn(move || {
+                let data = match elem.lock().as_mut() {
+                    Some(x) if dtype.map_or(false, |ty| ty != x.dtype()) => x
+                        .read_chunk::<ArrayData>(i, j)
+                        .and_then(|data| cast_chunk(data, dtype.and_then(scalar_type))),
+                    Some(x) => x.read_chunk(i, j),
+                    None => Err(anyhow::anyhow!("the array element has been closed")),
+                };
+                // The receiver is gone if the iterator was dropped early.
+                let _ = sender.send(data);
+            });
+            self.in_flight.push_back((receiver, start, start + j - i));
+        }
+    }
+}
+
+impl<B, T> Iterator for ParStackedChunkedArrayElem<B, T>
+where
+    B: Backend,
+    T: Into<ArrayData> + TryFrom<ArrayData> + ReadArrayData + Clone + Send + 'static,
+    <T as TryFrom<ArrayData>>::Error: Into<anyhow::Error>,
+{
+    type Item = Result<(T, usize, usize)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Refill before handing out a chunk rather than after, so that the
+        // chunk held by the consumer counts towards `prefetch`.
+        self.fill();
+        let (receiver, start, stop) = self.in_flight.pop_front()?;
+        // The sender is dropped without sending if the reader panicked.
+        let data = receiver
+            .recv()
+            .map_err(|_| anyhow::anyhow!("failed to read rows {}..{}: the reader panicked", start, stop))
+            .and_then(|x| x);
+        Some(data.map(|x| (x, start, stop)))
+    }
+}
+
+impl<B, T> ExactSizeIterator for ParStackedChunkedArrayElem<B, T>
+where
+    B: Backend,
+    T: Into<ArrayData> + TryFrom<ArrayData> + ReadArrayData + Clone + Send + 'static,
+    <T as TryFrom<ArrayData>>::Error: Into<anyhow::Error>,
+{
+    fn len(&self) -> usize {
+        self.pending.len() + self.in_flight.len()
+    }
+}
+
+/// The scalar type of numeric arrays, sparse matrices and ragged arrays.
+fn scalar_type(dtype: DataType) -> Option<ScalarType> {
+    match dtype {
+        DataType::Array(ty) | DataType::CsrMatrix(ty) | DataType::CscMatrix(ty) | DataType::RaggedArray(ty) => Some(ty),
+        _ => None,
+    }
+}
+
+/// Convert a chunk of an element of a stacked array, casting it to `dtype`,
+/// the scalar type of the stacked array, as the elements may have different
+/// scalar types.
+fn cast_chunk<T>(data: ArrayData, dtype: Option<ScalarType>) -> Result<T>
+where
+    T: TryFrom<ArrayData>,
+    <T as TryFrom<ArrayData>>::Error: Into<anyhow::Error>,
+{
+    let data = match dtype {
+        Some(ty) => data.cast(ty)?,
+        None => data,
+    };
+    data.try_into().map_err(Into::into)
+}
+
 fn reverse_mapping(mapping: Vec<usize>) -> Vec<usize> {
     let mut res = vec![0; mapping.len()];
     for (i, x) in mapping.into_iter().enumerate() {
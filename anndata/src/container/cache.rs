@@ -0,0 +1,277 @@
+use crate::data::*;
+
+use indexmap::IndexMap;
+use nalgebra_sparse::{CscMatrix, CsrMatrix};
+use ndarray::ArrayD;
+use parking_lot::Mutex;
+use std::mem::size_of;
+use std::sync::{Arc, Weak};
+
+/// A least recently used cache of array chunks, keyed by the `(start, end)`
+/// range of rows of the chunk. The total size of the cached chunks never
+/// exceeds the capacity of the cache.
+#[derive(Debug, Clone)]
+pub struct ChunkCache {
+    capacity: usize,
+    size: usize,
+    /// Chunks and their sizes, from the least to the most recently used.
+    chunks: IndexMap<(usize, usize), (ArrayData, usize)>,
+}
+
+impl ChunkCache {
+    /// Create an empty cache holding at most `capacity` bytes.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            size: 0,
+            chunks: IndexMap::new(),
+        }
+    }
+
+    /// The maximum number of bytes held by the cache.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of bytes currently held by the cache.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The number of cached chunks.
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn contains(&self, start: usize, end: usize) -> bool {
+        self.chunks.contains_key(&(start, end))
+    }
+
+    /// Return the chunk of rows `start..end` and mark it as the most recently used.
+    pub fn get(&mut self, start: usize, end: usize) -> Option<ArrayData> {
+        let (data, nbytes) = self.chunks.shift_remove(&(start, end))?;
+        self.chunks.insert((start, end), (data.clone(), nbytes));
+        Some(data)
+    }
+
+    /// Insert the chunk of rows `start..end`, evicting the least recently used
+    /// chunks to make room for it. Chunks larger than the capacity are not cached.
+    pub fn insert(&mut self, start: usize, end: usize, data: ArrayData) {
+        if let Some((_, nbytes)) = self.chunks.shift_remove(&(start, end)) {
+            self.size -= nbytes;
+        }
+        let nbytes = nbytes(&data);
+        if nbytes > self.capacity {
+            return;
+        }
+        while self.size + nbytes > self.capacity {
+            let (_, (_, n)) = self.chunks.shift_remove_index(0).unwrap();
+            self.size -= n;
+        }
+        self.size += nbytes;
+        self.chunks.insert((start, end), (data, nbytes));
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.size = 0;
+    }
+}
+
+/// An element whose cached data can be dropped by a `CacheBudget`.
+pub(crate) trait Evict: Send + Sync {
+    /// Drop the cached data, unless the element is in use by another thread.
+    /// Return whether the data was dropped.
+    fn try_evict(&self) -> bool;
+}
+
+/// A memory budget for the data cached by the elements of an AnnData object.
+/// Elements register the data they cache, and when the total size exceeds the
+/// limit, the least recently used data of the other elements is dropped.
+/// Elements that are in use by another thread are skipped, so the limit may be
+/// exceeded temporarily.
+#[derive(Clone, Default)]
+pub struct CacheBudget(Arc<Mutex<BudgetState>>);
+
+#[derive(Default)]
+struct BudgetState {
+    limit: Option<usize>,
+    size: usize,
+    /// Cached elements and their sizes, from the least to the most recently
+    /// used, keyed by the address of the element.
+    entries: IndexMap<usize, (Weak<dyn Evict>, usize)>,
+}
+
+impl std::fmt::Debug for CacheBudget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.0.lock();
+        write!(f, "CacheBudget {{ limit: {:?}, size: {} }}", state.limit, state.size)
+    }
+}
+
+impl BudgetState {
+    /// Evict the least recently used elements, except `keep`, until the total
+    /// size is within the limit.
+    fn shrink(&mut self, keep: Option<usize>) {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return,
+        };
+        let mut i = 0;
+        while self.size > limit && i < self.entries.len() {
+            let (key, (elem, nbytes)) = self.entries.get_index(i).unwrap();
+            if Some(*key) == keep {
+                i += 1;
+                continue;
+            }
+            let nbytes = *nbytes;
+            match elem.upgrade() {
+                Some(elem) if !elem.try_evict() => i += 1,
+                _ => {
+                    self.entries.shift_remove_index(i);
+                    self.size -= nbytes;
+                }
+            }
+        }
+    }
+}
+
+impl CacheBudget {
+    /// The maximum number of bytes of cached data, if any.
+    pub fn limit(&self) -> Option<usize> {
+        self.0.lock().limit
+    }
+
+    /// Set the maximum number of bytes of cached data, evicting the least
+    /// recently used data if the limit is already exceeded.
+    pub fn set_limit(&self, limit: Option<usize>) {
+        let mut state = self.0.lock();
+        state.limit = limit;
+        state.shrink(None);
+    }
+
+    /// The number of bytes of registered cached data.
+    pub fn size(&self) -> usize {
+        self.0.lock().size
+    }
+
+    /// Register the data cached by `elem`, or update its size, and mark it as
+    /// the most recently used.
+    pub(crate) fn admit(&self, elem: Weak<dyn Evict>, nbytes: usize) {
+        let key = elem.as_ptr() as *const () as usize;
+        let mut state = self.0.lock();
+        if let Some((_, n)) = state.entries.shift_remove(&key) {
+            state.size -= n;
+        }
+        state.size += nbytes;
+        state.entries.insert(key, (elem, nbytes));
+        state.shrink(Some(key));
+    }
+
+    /// Mark the data cached by `elem` as the most recently used.
+    pub(crate) fn touch(&self, elem: &Weak<dyn Evict>) {
+        let key = elem.as_ptr() as *const () as usize;
+        let mut state = self.0.lock();
+        if let Some(entry) = state.entries.shift_remove(&key) {
+            state.entries.insert(key, entry);
+        }
+    }
+
+    /// Unregister the data cached by `elem` after it has been dropped.
+    pub(crate) fn remove(&self, elem: &Weak<dyn Evict>) {
+        let key = elem.as_ptr() as *const () as usize;
+        let mut state = self.0.lock();
+        if let Some((_, n)) = state.entries.shift_remove(&key) {
+            state.size -= n;
+        }
+    }
+}
+
+/// Data whose size can be counted towards a `CacheBudget`.
+pub trait CacheSize {
+    fn cache_size(&self) -> usize;
+}
+
+impl CacheSize for ArrayData {
+    fn cache_size(&self) -> usize {
+        nbytes(self)
+    }
+}
+
+/// The approximate number of bytes used by the data.
+fn nbytes(data: &ArrayData) -> usize {
+    macro_rules! sparse_bytes {
+        ($ty:ident, $x:expr, $fun:ident) => {
+            match $x {
+                $ty::I8(x) => $fun(x),
+                $ty::I16(x) => $fun(x),
+                $ty::I32(x) => $fun(x),
+                $ty::I64(x) => $fun(x),
+                $ty::U8(x) => $fun(x),
+                $ty::U16(x) => $fun(x),
+                $ty::U32(x) => $fun(x),
+                $ty::U64(x) => $fun(x),
+                $ty::Usize(x) => $fun(x),
+                $ty::F16(x) => $fun(x),
+                $ty::F32(x) => $fun(x),
+                $ty::F64(x) => $fun(x),
+                $ty::Bool(x) => $fun(x),
+                $ty::String(x) => $fun(x),
+            }
+        };
+    }
+
+    match data {
+        ArrayData::Array(x) => dense_bytes(x),
+        ArrayData::CsrMatrix(x) => sparse_bytes!(DynCsrMatrix, x, csr_bytes),
+        ArrayData::CscMatrix(x) => sparse_bytes!(DynCscMatrix, x, csc_bytes),
+        ArrayData::CsrNonCanonical(x) => sparse_bytes!(DynCsrNonCanonical, x, noncanonical_bytes),
+        ArrayData::RaggedArray(x) => dense_bytes(x.values()) + x.offsets().len() * size_of::<usize>(),
+        ArrayData::RecordArray(x) => x.fields().iter().map(|(_, values)| dense_bytes(values)).sum(),
+        ArrayData::DataFrame(x) => x.estimated_size(),
+    }
+}
+
+fn dense_bytes(x: &DynArray) -> usize {
+    match x {
+        DynArray::I8(x) => array_bytes(x),
+        DynArray::I16(x) => array_bytes(x),
+        DynArray::I32(x) => array_bytes(x),
+        DynArray::I64(x) => array_bytes(x),
+        DynArray::U8(x) => array_bytes(x),
+        DynArray::U16(x) => array_bytes(x),
+        DynArray::U32(x) => array_bytes(x),
+        DynArray::U64(x) => array_bytes(x),
+        DynArray::Usize(x) => array_bytes(x),
+        DynArray::F16(x) => array_bytes(x),
+        DynArray::F32(x) => array_bytes(x),
+        DynArray::F64(x) => array_bytes(x),
+        DynArray::ComplexF32(x) => array_bytes(x),
+        DynArray::ComplexF64(x) => array_bytes(x),
+        DynArray::Bool(x) => array_bytes(x),
+        DynArray::String(x) => array_bytes(x) + x.iter().map(String::len).sum::<usize>(),
+        DynArray::Categorical(x) => {
+            array_bytes(&x.codes) + x.categories.iter().map(|c| size_of::<String>() + c.len()).sum::<usize>()
+        }
+    }
+}
+
+fn array_bytes<T>(x: &ArrayD<T>) -> usize {
+    x.len() * size_of::<T>()
+}
+
+fn csr_bytes<T>(x: &CsrMatrix<T>) -> usize {
+    x.nnz() * (size_of::<T>() + size_of::<usize>()) + (x.nrows() + 1) * size_of::<usize>()
+}
+
+fn csc_bytes<T>(x: &CscMatrix<T>) -> usize {
+    x.nnz() * (size_of::<T>() + size_of::<usize>()) + (x.ncols() + 1) * size_of::<usize>()
+}
+
+fn noncanonical_bytes<T>(x: &CsrNonCanonical<T>) -> usize {
+    x.nnz() * (size_of::<T>() + size_of::<usize>()) + (x.nrows() + 1) * size_of::<usize>()
+}
@@ -1,24 +1,139 @@
 use crate::{
-    backend::{iter_containers, Backend, GroupOp, LocationOp},
-    container::base::*,
+    backend::{iter_containers, Backend, DataContainer, FileOp, GroupOp, LocationOp, ScalarType, WriteConfig},
+    container::{base::*, CacheBudget},
     data::*,
     AxisArraysOp, ElemCollectionOp,
 };
 
-use anyhow::{bail, ensure, Result};
+use anyhow::{bail, ensure, Context, Result};
 use itertools::Itertools;
 use parking_lot::{Mutex, MutexGuard};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use smallvec::{smallvec, SmallVec};
 use std::{
+    cell::OnceCell,
     collections::{HashMap, HashSet},
     fmt::Display,
     ops::{Deref, DerefMut},
+    path::Path,
     sync::Arc,
 };
 
+/// Elements of a group, keyed by name. Elements added with `insert_unopened`
+/// are only opened on first access, so that listing the keys of a group with
+/// many elements is cheap.
+struct ElemMap<T>(HashMap<String, OnceCell<T>>);
+
+impl<T> ElemMap<T> {
+    fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &String> {
+        self.0.keys()
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    /// Get an element, opening it with `open` if needed.
+    fn get_or_open<F: FnOnce() -> Result<T>>(&self, key: &str, open: F) -> Result<Option<&T>> {
+        match self.0.get(key) {
+            None => Ok(None),
+            Some(cell) => {
+                if cell.get().is_none() {
+                    let _ = cell.set(open()?);
+                }
+                Ok(cell.get())
+            }
+        }
+    }
+
+    /// The elements that have been opened.
+    fn opened(&self) -> impl Iterator<Item = &T> {
+        self.0.values().filter_map(|x| x.get())
+    }
+
+    fn insert(&mut self, key: String, elem: T) {
+        self.0.insert(key, OnceCell::from(elem));
+    }
+
+    fn insert_unopened(&mut self, key: String) {
+        self.0.insert(key, OnceCell::new());
+    }
+
+    fn remove(&mut self, key: &str) -> Option<OnceCell<T>> {
+        self.0.remove(key)
+    }
+}
+
+/// Implement the map-like accessors of a collection whose elements are
+/// stored in an `ElemMap` and opened with `open_elem`.
+macro_rules! impl_elem_map {
+    ($collection:ident, $elem:ident) => {
+        impl<B: Backend> $collection<B> {
+            pub fn keys(&self) -> impl Iterator<Item = &String> {
+                self.data.keys()
+            }
+
+            pub fn len(&self) -> usize {
+                self.data.len()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.data.len() == 0
+            }
+
+            pub fn contains_key(&self, key: &str) -> bool {
+                self.data.contains_key(key)
+            }
+
+            /// Get an element, opening it if it has not been accessed before.
+            pub fn try_get(&self, key: &str) -> Result<Option<&$elem<B>>> {
+                self.data.get_or_open(key, || self.open_elem(key))
+            }
+
+            /// Iterate over all elements, opening those that have not been
+            /// accessed before.
+            pub fn iter(&self) -> Result<impl Iterator<Item = (&String, &$elem<B>)>> {
+                self.open_all()?;
+                Ok(self.data.0.iter().filter_map(|(k, v)| v.get().map(|v| (k, v))))
+            }
+
+            pub fn values(&self) -> Result<impl Iterator<Item = &$elem<B>>> {
+                Ok(self.iter()?.map(|(_, v)| v))
+            }
+
+            /// Open all elements that have not been accessed before.
+            pub fn open_all(&self) -> Result<()> {
+                self.data.keys().try_for_each(|k| self.try_get(k).map(|_| ()))
+            }
+
+            /// The elements that have been opened.
+            pub fn opened(&self) -> impl Iterator<Item = &$elem<B>> {
+                self.data.opened()
+            }
+
+            fn insert(&mut self, key: String, elem: $elem<B>) {
+                self.data.insert(key, elem);
+            }
+
+            fn remove(&mut self, key: &str) -> Result<Option<$elem<B>>> {
+                self.try_get(key)?;
+                Ok(self.data.remove(key).and_then(|x| x.into_inner()))
+            }
+        }
+    };
+}
+
 pub struct InnerElemCollection<B: Backend> {
     container: B::Group,
-    data: HashMap<String, Elem<B>>,
+    data: ElemMap<Elem<B>>,
 }
 
 impl<B: Backend> std::fmt::Debug for InnerElemCollection<B> {
@@ -27,19 +142,7 @@ impl<B: Backend> std::fmt::Debug for InnerElemCollection<B> {
     }
 }
 
-impl<B: Backend> Deref for InnerElemCollection<B> {
-    type Target = HashMap<String, Elem<B>>;
-
-    fn deref(&self) -> &Self::Target {
-        &self.data
-    }
-}
-
-impl<B: Backend> DerefMut for InnerElemCollection<B> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.data
-    }
-}
+impl_elem_map!(InnerElemCollection, Elem);
 
 impl<B: Backend> std::fmt::Display for InnerElemCollection<B> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -53,8 +156,12 @@ impl<B: Backend> std::fmt::Display for InnerElemCollection<B> {
 }
 
 impl<B: Backend> InnerElemCollection<B> {
+    fn open_elem(&self, key: &str) -> Result<Elem<B>> {
+        DataContainer::open(&self.container, key)?.try_into()
+    }
+
     pub fn add_data<D: WriteData + Into<Data>>(&mut self, key: &str, data: D) -> Result<()> {
-        match self.get_mut(key) {
+        match self.try_get(key)? {
             None => {
                 let container = data.write(&self.container, key)?;
                 self.insert(key.to_string(), container.try_into()?);
@@ -65,23 +172,93 @@ impl<B: Backend> InnerElemCollection<B> {
     }
 
     pub fn remove_data(&mut self, key: &str) -> Result<()> {
-        self.remove(key).map(|x| x.clear()).transpose()?;
+        self.remove(key)?.map(|x| x.clear()).transpose()?;
         Ok(())
     }
 
+    /// Move an element to a new path. Paths are slash-delimited, e.g.,
+    /// `pca/variance` refers to the `variance` entry of the `pca` mapping.
+    pub fn move_data(&mut self, from: &str, to: &str) -> Result<()> {
+        let (from, to) = (from.trim_matches('/'), to.trim_matches('/'));
+        ensure!(!from.is_empty() && !to.is_empty(), "paths cannot be empty");
+        ensure!(
+            !to.starts_with(&format!("{}/", from)),
+            "cannot move '{}' into itself",
+            from
+        );
+        ensure!(self.path_exists(from)?, "'{}' not found", from);
+        ensure!(!self.path_exists(to)?, "'{}' already exists", to);
+        if let Some((parent, _)) = to.rsplit_once('/') {
+            ensure!(
+                self.path_exists(parent)? && self.container.open_group(parent).is_ok(),
+                "'{}' is not a mapping",
+                parent
+            );
+        }
+        self.container.rename(from, to)?;
+
+        // Update the top-level elements affected by the move.
+        let (from_key, to_key) = (top_level_key(from), top_level_key(to));
+        let elem = if from_key == from {
+            self.remove(from)?
+        } else {
+            if let Some(elem) = self.try_get(from_key)? {
+                elem.inner().clear_cache();
+            }
+            None
+        };
+        if to_key == to {
+            let container = DataContainer::open(&self.container, to)?;
+            match elem {
+                Some(elem) => {
+                    elem.inner().relocate(container);
+                    self.insert(to.to_string(), elem);
+                }
+                None => {
+                    self.insert(to.to_string(), container.try_into()?);
+                }
+            }
+        } else if let Some(elem) = self.try_get(to_key)? {
+            elem.inner().clear_cache();
+        }
+        Ok(())
+    }
+
+    /// Check whether `path` exists, without assuming that its parents exist.
+    fn path_exists(&self, path: &str) -> Result<bool> {
+        let mut prefix = String::new();
+        for name in path.split('/') {
+            if !prefix.is_empty() {
+                if self.container.open_group(&prefix).is_err() {
+                    return Ok(false);
+                }
+                prefix.push('/');
+            }
+            prefix.push_str(name);
+            if !self.container.exists(&prefix)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
     pub fn export<O: Backend, G: GroupOp<Backend = O>>(
         &self,
         location: &G,
         name: &str,
     ) -> Result<()> {
         let group = location.create_group(name)?;
-        for (key, val) in self.iter() {
+        for (key, val) in self.iter()? {
             val.inner().export::<O, _>(&group, key)?;
         }
         Ok(())
     }
 }
 
+fn top_level_key(path: &str) -> &str {
+    path.split('/').next().unwrap()
+}
+
 #[derive(Debug)]
 pub struct ElemCollection<B: Backend>(Slot<InnerElemCollection<B>>);
 
@@ -116,14 +293,20 @@ impl<B: Backend> ElemCollectionOp for &ElemCollection<B> {
         self.inner().keys().cloned().collect()
     }
 
+    fn contains(&self, key: &str) -> bool {
+        self.lock().as_ref().map_or(false, |x| x.contains_key(key))
+    }
+
     fn get_item<D>(&self, key: &str) -> Result<Option<D>>
     where
         D: ReadData + Into<Data> + TryFrom<Data> + Clone,
         <D as TryFrom<Data>>::Error: Into<anyhow::Error>,
     {
         self.lock()
-            .as_mut()
-            .and_then(|x| x.get_mut(key))
+            .as_ref()
+            .map(|x| x.try_get(key))
+            .transpose()?
+            .flatten()
             .map(|x| x.inner().data())
             .transpose()
     }
@@ -143,14 +326,18 @@ impl<B: Backend> ElemCollection<B> {
     }
 
     pub fn new(container: B::Group) -> Result<Self> {
-        let data: Result<HashMap<_, _>> = iter_containers(&container)
-            .map(|(k, v)| Ok((k, Elem::try_from(v)?)))
-            .collect();
-        let collection = InnerElemCollection {
-            container,
-            data: data?,
-        };
-        Ok(Self(Slot::new(collection)))
+        let mut data = ElemMap::new();
+        for (k, v) in iter_containers(&container) {
+            data.insert(k, Elem::try_from(v)?);
+        }
+        Ok(Self(Slot::new(InnerElemCollection { container, data })))
+    }
+
+    /// Like `new`, but only list the keys. Elements are opened on first access.
+    pub fn new_lazy(container: B::Group) -> Result<Self> {
+        let mut data = ElemMap::new();
+        container.list()?.into_iter().for_each(|k| data.insert_unopened(k));
+        Ok(Self(Slot::new(InnerElemCollection { container, data })))
     }
 
     pub fn clear(&self) -> Result<()> {
@@ -165,6 +352,14 @@ impl<B: Backend> ElemCollection<B> {
         self.0.drop();
         Ok(())
     }
+
+    /// Move or rename an element, e.g., `move_item("pca/variance", "pca/var_explained")`.
+    /// Paths are slash-delimited and may point into nested mappings. The data
+    /// is not read or rewritten. It is an error if the source does not exist
+    /// or the destination already exists.
+    pub fn move_item(&self, from_path: &str, to_path: &str) -> Result<()> {
+        self.inner().move_data(from_path, to_path)
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -251,7 +446,8 @@ pub struct InnerAxisArrays<B: Backend> {
     pub(crate) container: B::Group,
     pub(crate) dim1: Dim,
     pub(crate) dim2: Option<Dim>,
-    data: HashMap<String, ArrayElem<B>>,
+    data: ElemMap<ArrayElem<B>>,
+    cache_budget: Option<CacheBudget>,
 }
 
 impl<B: Backend> std::fmt::Debug for InnerAxisArrays<B> {
@@ -260,19 +456,7 @@ impl<B: Backend> std::fmt::Debug for InnerAxisArrays<B> {
     }
 }
 
-impl<B: Backend> Deref for InnerAxisArrays<B> {
-    type Target = HashMap<String, ArrayElem<B>>;
-
-    fn deref(&self) -> &Self::Target {
-        &self.data
-    }
-}
-
-impl<B: Backend> DerefMut for InnerAxisArrays<B> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.data
-    }
-}
+impl_elem_map!(InnerAxisArrays, ArrayElem);
 
 impl<B: Backend> std::fmt::Display for InnerAxisArrays<B> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -295,13 +479,75 @@ impl<B: Backend> InnerAxisArrays<B> {
         self.dim1.get()
     }
 
+    /// Open an element, checking that its shape is consistent with the others.
+    /// Elements stored in another file are marked as external links.
+    fn open_elem(&self, key: &str) -> Result<ArrayElem<B>> {
+        let container = DataContainer::open(&self.container, key)?;
+        let external = !same_file(&container.file()?.filename(), &self.container.file()?.filename());
+        let elem = ArrayElem::try_from(container)?;
+        if external {
+            elem.inner().set_external();
+        }
+        self.attach_budget(&elem);
+        let shape = elem.inner().shape().clone();
+        self.check_shape(&shape).with_context(|| format!("'{}' has an invalid shape", key))?;
+        Ok(elem)
+    }
+
     pub fn add_data<D: WriteArrayData + HasShape + Into<ArrayData>>(
         &mut self,
         key: &str,
         data: D,
     ) -> Result<()> {
-        // Check if the data is compatible with the current size
-        let shape = data.shape();
+        self.check_shape(&data.shape())?;
+        match self.try_get(key)? {
+            None => {
+                let container = data.write(&self.container, key)?;
+                let elem = container.try_into()?;
+                self.attach_budget(&elem);
+                self.insert(key.to_string(), elem);
+            }
+            Some(elem) => elem.inner().save(data)?,
+        }
+        Ok(())
+    }
+
+    /// Add data with the given dataset options. An existing item with the same
+    /// key is removed first, so that the new options take effect.
+    pub fn add_data_with_config<D: WriteArrayData + HasShape + Into<ArrayData>>(
+        &mut self,
+        key: &str,
+        data: D,
+        config: WriteConfig,
+    ) -> Result<()> {
+        self.check_shape(&data.shape())?;
+        self.remove_data(key)?;
+        let container = data.write_with_config(&self.container, key, config)?;
+        let elem = container.try_into()?;
+        self.attach_budget(&elem);
+        self.insert(key.to_string(), elem);
+        Ok(())
+    }
+
+    /// Count the data cached by the arrays towards `budget`.
+    pub(crate) fn set_cache_budget(&mut self, budget: CacheBudget) {
+        self.cache_budget = Some(budget);
+        self.opened().for_each(|x| self.attach_budget(x));
+    }
+
+    /// The number of bytes of data cached by the opened arrays.
+    pub fn cached_bytes(&self) -> usize {
+        self.opened().map(|x| x.cached_bytes()).sum()
+    }
+
+    fn attach_budget(&self, elem: &ArrayElem<B>) {
+        if let Some(budget) = self.cache_budget.as_ref() {
+            elem.set_cache_budget(budget.clone());
+        }
+    }
+
+    /// Check if the data is compatible with the current size.
+    fn check_shape(&self, shape: &Shape) -> Result<()> {
         match self.axis {
             Axis::Row => {
                 self.dim1.try_set(shape[0])?;
@@ -319,15 +565,6 @@ impl<B: Backend> InnerAxisArrays<B> {
                 self.dim1.try_set(shape[0])?;
             }
         }
-
-        match self.get_mut(key) {
-            None => {
-                let container = data.write(&self.container, key)?;
-                let elem = container.try_into()?;
-                self.insert(key.to_string(), elem);
-            }
-            Some(elem) => elem.inner().save(data)?,
-        }
         Ok(())
     }
 
@@ -336,10 +573,9 @@ impl<B: Backend> InnerAxisArrays<B> {
         I: Iterator<Item = D>,
         D: ArrayChunk,
     {
-        if let Some(elem) = self.get(key) {
-            elem.clear()?;
-        }
+        self.remove_data(key)?;
         let elem = ArrayElem::try_from(ArrayChunk::write_by_chunk(data, &self.container, key)?)?;
+        self.attach_budget(&elem);
 
         let shape = { elem.inner().shape().clone() };
         match self.axis {
@@ -380,8 +616,72 @@ impl<B: Backend> InnerAxisArrays<B> {
         }
     }
 
+    /// Create a dense array of the given shape without writing any data, so
+    /// that it can be populated incrementally. All elements read as `fill`,
+    /// converted to `dtype`, until they are written, e.g., by
+    /// `ArrayElem::write_rows` or `ArrayElem::write_columns`.
+    pub fn create_empty(&mut self, key: &str, shape: Shape, dtype: ScalarType, fill: DynScalar) -> Result<ArrayElem<B>> {
+        ensure!(shape.ndim() >= 2, "expecting an array with at least 2 dimensions, found shape {}", shape);
+        let fill = fill.cast(dtype)?;
+        self.check_shape(&shape)?;
+        self.remove_data(key)?;
+        let elem = ArrayElem::try_from(create_filled_array(&self.container, key, &shape, fill)?)?;
+        self.attach_budget(&elem);
+        self.insert(key.to_string(), elem.clone());
+        Ok(elem)
+    }
+
+    /// Remove an array. Only the link is removed if the array is an external
+    /// link, and the data in the other file is kept.
     pub fn remove_data(&mut self, key: &str) -> Result<()> {
-        self.remove(key).map(|x| x.clear()).transpose()?;
+        if let Some(elem) = self.remove(key)? {
+            let external = elem.lock().as_ref().map_or(false, |x| x.is_external());
+            elem.clear()?;
+            if external {
+                self.container.delete(key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add an external link named `key` to the array at `target_path` in
+    /// `target_file`. The array is read from `target_file` when accessed, and
+    /// cannot be modified through the link.
+    pub fn add_external_link(&mut self, key: &str, target_file: &Path, target_path: &str) -> Result<()> {
+        ensure!(!self.contains_key(key), "'{}' already exists", key);
+        let target_file = target_file
+            .canonicalize()
+            .with_context(|| format!("cannot open '{}'", target_file.display()))?;
+        ensure!(
+            !same_file(&target_file, &self.container.file()?.filename()),
+            "the target of an external link must be in another file",
+        );
+        self.container.new_external_link(key, &target_file, target_path)?;
+        match self.open_elem(key) {
+            Ok(elem) => {
+                self.insert(key.to_string(), elem);
+                Ok(())
+            }
+            Err(e) => {
+                self.container.delete(key)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Rename an array. It is an error if `new_key` already exists.
+    pub fn rename_data(&mut self, key: &str, new_key: &str) -> Result<()> {
+        ensure!(!self.contains_key(new_key), "'{}' already exists", new_key);
+        let elem = self.remove(key)?.with_context(|| format!("'{}' not found", key))?;
+        if let Err(e) = self.container.rename(key, new_key) {
+            self.insert(key.to_string(), elem);
+            return Err(e);
+        }
+        let container = DataContainer::open(&self.container, new_key)?;
+        if let Some(x) = elem.lock().as_mut() {
+            x.relocate(container);
+        }
+        self.insert(new_key.to_string(), elem);
         Ok(())
     }
 
@@ -391,7 +691,7 @@ impl<B: Backend> InnerAxisArrays<B> {
         name: &str,
     ) -> Result<()> {
         let group = location.create_group(name)?;
-        for (key, val) in self.iter() {
+        for (key, val) in self.iter()? {
             val.inner().export::<O, _>(&group, key)?;
         }
         Ok(())
@@ -416,7 +716,7 @@ impl<B: Backend> InnerAxisArrays<B> {
                     if selection.len() != 1 {
                         bail!("selection dimension must be 1 for row AxisArrays");
                     }
-                    self.iter().try_for_each(|(k, x)| {
+                    self.iter()?.try_for_each(|(k, x)| {
                         x.inner().export_axis::<O, _>(0, selection[0], &group, k)
                     })
                 }
@@ -424,7 +724,7 @@ impl<B: Backend> InnerAxisArrays<B> {
                     if selection.len() != 2 {
                         bail!("selection dimension must be 2 for row/column AxisArrays");
                     }
-                    self.iter().try_for_each(|(k, x)| {
+                    self.iter()?.try_for_each(|(k, x)| {
                         x.inner().export_select::<O, _>(selection, &group, k)
                     })
                 }
@@ -433,7 +733,7 @@ impl<B: Backend> InnerAxisArrays<B> {
                         bail!("selection dimension must be 1 for pairwise AxisArrays");
                     }
                     let s = vec![selection[0], selection[0]];
-                    self.iter().try_for_each(|(k, x)| {
+                    self.iter()?.try_for_each(|(k, x)| {
                         x.inner().export_select::<O, _>(s.as_ref(), &group, k)
                     })
                 }
@@ -447,7 +747,7 @@ impl<B: Backend> InnerAxisArrays<B> {
                 if selection.len() != 1 {
                     bail!("selection dimension must be 1 for row AxisArrays");
                 }
-                self.values()
+                self.values()?
                     .try_for_each(|x| x.inner().subset_axis(0, selection[0]))?;
                 if let Some(mut lock) = self.dim1.try_lock() {
                     lock.set(BoundedSelectInfoElem::new(selection[0], lock.get()).len());
@@ -457,7 +757,7 @@ impl<B: Backend> InnerAxisArrays<B> {
                 if selection.len() != 2 {
                     bail!("selection dimension must be 2 for row/column AxisArrays");
                 }
-                self.values()
+                self.values()?
                     .try_for_each(|x| x.inner().subset(selection))?;
                 if let Some(mut lock) = self.dim1.try_lock() {
                     lock.set(BoundedSelectInfoElem::new(selection[0], lock.get()).len());
@@ -470,7 +770,7 @@ impl<B: Backend> InnerAxisArrays<B> {
                 if selection.len() != 1 {
                     bail!("selection dimension must be 1 for pairwise AxisArrays");
                 }
-                self.values().try_for_each(|x| {
+                self.values()?.try_for_each(|x| {
                     let full = SelectInfoElem::full();
                     let mut slice: SmallVec<[_; 3]> = smallvec![&full; x.inner().shape().ndim()];
                     slice[0] = selection[0];
@@ -521,12 +821,12 @@ impl<B: Backend> AxisArrays<B> {
     }
 
     pub fn new(group: B::Group, axis: Axis, dim1: &Dim, dim2: Option<&Dim>) -> Result<Self> {
-        let data: HashMap<_, _> = iter_containers::<B>(&group)
+        let elems: Vec<_> = iter_containers::<B>(&group)
             .map(|(k, v)| (k, ArrayElem::try_from(v).unwrap()))
             .collect();
 
         // Get shapes of arrays
-        let shapes = data
+        let shapes = elems
             .iter()
             .map(|(_, v)| v.inner().shape().clone())
             .collect::<Vec<_>>();
@@ -547,16 +847,41 @@ impl<B: Backend> AxisArrays<B> {
             }
         }
 
+        let mut data = ElemMap::new();
+        elems.into_iter().for_each(|(k, v)| data.insert(k, v));
         let arrays = InnerAxisArrays {
             container: group,
             dim1: dim1.clone(),
             dim2: dim2.cloned(),
             axis,
             data,
+            cache_budget: None,
         };
         Ok(Self(Slot::new(arrays)))
     }
 
+    /// Like `new`, but only list the keys. Arrays are opened, and their shapes
+    /// checked, on first access. If the dimensions are not yet known, one
+    /// array is opened to determine them.
+    pub fn new_lazy(group: B::Group, axis: Axis, dim1: &Dim, dim2: Option<&Dim>) -> Result<Self> {
+        let mut data = ElemMap::new();
+        let keys = group.list()?;
+        keys.iter().for_each(|k| data.insert_unopened(k.clone()));
+        let arrays = InnerAxisArrays {
+            container: group,
+            dim1: dim1.clone(),
+            dim2: dim2.cloned(),
+            axis,
+            data,
+            cache_budget: None,
+        };
+        let unknown = dim1.lock().is_empty() || dim2.map_or(false, |x| x.lock().is_empty());
+        if let (true, Some(key)) = (unknown, keys.iter().min()) {
+            arrays.try_get(key)?;
+        }
+        Ok(Self(Slot::new(arrays)))
+    }
+
     pub fn clear(&self) -> Result<()> {
         self.0
             .lock()
@@ -569,6 +894,84 @@ impl<B: Backend> AxisArrays<B> {
         self.0.drop();
         Ok(())
     }
+
+    /// Rename an array. Only the name of the stored array changes; the data
+    /// is not read or rewritten.
+    pub fn rename(&self, key: &str, new_key: &str) -> Result<()> {
+        self.inner().rename_data(key, new_key)
+    }
+
+    /// Concatenate the arrays under `keys` column-wise and store the result
+    /// under `out_key`, e.g., to combine two embeddings in obsm. Sparse
+    /// matrices are converted to dense arrays. See [`ArrayData::hstack`].
+    pub fn hstack(&self, keys: &[&str], out_key: &str) -> Result<()> {
+        let mut inner = self.inner();
+        ensure!(
+            matches!(inner.axis, Axis::Row),
+            "only arrays in obsm or varm can be stacked horizontally"
+        );
+        let n = inner.size();
+        let arrays = keys
+            .iter()
+            .map(|key| {
+                let elem = inner.try_get(key)?.with_context(|| format!("key '{}' not found", key))?;
+                let data: ArrayData = elem.inner().data()?;
+                ensure!(
+                    data.shape()[0] == n,
+                    "'{}' has {} rows, but {} are expected",
+                    key,
+                    data.shape()[0],
+                    n,
+                );
+                Ok(data)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        inner.add_data(out_key, ArrayData::hstack(arrays)?)
+    }
+
+    /// Read the arrays under `keys` in parallel. Each array is read as with
+    /// `ArrayElem::data`, so arrays whose cache is enabled are cached.
+    pub fn read_many(&self, keys: &[&str]) -> Result<HashMap<String, ArrayData>> {
+        let elems = {
+            let inner = self.inner();
+            keys.iter()
+                .map(|key| {
+                    let elem = inner.try_get(key)?.with_context(|| format!("key '{}' not found", key))?;
+                    Ok((key.to_string(), elem.clone()))
+                })
+                .collect::<Result<Vec<_>>>()?
+        };
+        elems
+            .into_par_iter()
+            .map(|(key, elem)| {
+                let data = elem.inner().data()?;
+                Ok((key, data))
+            })
+            .collect()
+    }
+
+    /// Add an external link to an array in another file, e.g., to share a
+    /// layer between files without copying it. See
+    /// [`InnerAxisArrays::add_external_link`].
+    pub fn add_external_link<P: AsRef<Path>>(&self, key: &str, target_file: P, target_path: &str) -> Result<()> {
+        self.inner().add_external_link(key, target_file.as_ref(), target_path)
+    }
+
+    /// Create an array to be populated incrementally. See
+    /// [`InnerAxisArrays::create_empty`].
+    pub fn create_empty(&self, key: &str, shape: Shape, dtype: ScalarType, fill: DynScalar) -> Result<ArrayElem<B>> {
+        self.inner().create_empty(key, shape, dtype, fill)
+    }
+
+    /// Add an array with the given dataset options, e.g., the chunk shape.
+    pub fn add_with_config<D: WriteArrayData + HasShape + Into<ArrayData>>(
+        &self,
+        key: &str,
+        data: D,
+        config: WriteConfig,
+    ) -> Result<()> {
+        self.inner().add_data_with_config(key, data, config)
+    }
 }
 
 impl<B: Backend> AxisArraysOp for &AxisArrays<B> {
@@ -578,8 +981,12 @@ impl<B: Backend> AxisArraysOp for &AxisArrays<B> {
         self.inner().keys().cloned().collect()
     }
 
-    fn get(&self, key: &str) -> Option<Self::ArrayElem> {
-        self.lock().as_ref().and_then(|x| x.get(key).cloned())
+    fn contains(&self, key: &str) -> bool {
+        self.lock().as_ref().map_or(false, |x| x.contains_key(key))
+    }
+
+    fn try_get(&self, key: &str) -> Result<Option<Self::ArrayElem>> {
+        Ok(self.lock().as_ref().map(|x| x.try_get(key)).transpose()?.flatten().cloned())
     }
 
     fn add<D: WriteArrayData + HasShape + Into<ArrayData>>(
@@ -650,8 +1057,12 @@ impl<B: Backend> AxisArraysOp for &StackedAxisArrays<B> {
         self.data.keys().cloned().collect()
     }
 
-    fn get(&self, key: &str) -> Option<Self::ArrayElem> {
-        self.data.get(key).cloned()
+    fn contains(&self, key: &str) -> bool {
+        self.data.contains_key(key)
+    }
+
+    fn try_get(&self, key: &str) -> Result<Option<Self::ArrayElem>> {
+        Ok(self.data.get(key).cloned())
     }
 
     fn add<D: WriteArrayData + HasShape + Into<ArrayData>>(
@@ -704,8 +1115,8 @@ impl<B: Backend> StackedAxisArrays<B> {
             .map(|k| {
                 let elems = arrays
                     .iter()
-                    .map(|x| x.inner().get(&k).unwrap().clone())
-                    .collect();
+                    .map(|x| Ok(x.inner().try_get(&k)?.unwrap().clone()))
+                    .collect::<Result<_>>()?;
                 Ok((k, StackedArrayElem::new(elems)?))
             })
             .collect::<Result<HashMap<_, _>>>()?;
@@ -719,3 +1130,8 @@ impl<B: Backend> StackedAxisArrays<B> {
         self.data.contains_key(key)
     }
 }
+
+/// Whether two paths refer to the same file.
+fn same_file(a: &Path, b: &Path) -> bool {
+    a == b || matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
@@ -1,8 +1,11 @@
 pub(crate) mod base;
 pub(crate) mod collection;
+mod cache;
 
 pub use base::{
     InnerDataFrameElem, DataFrameElem, Elem, Inner, ArrayElem, Slot,
-    StackedDataFrame, StackedArrayElem, ChunkedArrayElem, StackedChunkedArrayElem,
+    StackedDataFrame, StackedArrayElem, ChunkedArrayElem, ChunkedArrayWithColumns, StackedChunkedArrayElem,
+    ParStackedChunkedArrayElem,
 };
+pub use cache::{CacheBudget, ChunkCache};
 pub use collection::{Dim, Axis, AxisArrays, ElemCollection, StackedAxisArrays};
\ No newline at end of file
@@ -13,6 +13,8 @@ use crate::backend::{Backend, DataContainer, DataType, GroupOp};
 
 use ::ndarray::{Array, RemoveAxis};
 use anyhow::{bail, Ok, Result};
+use half::f16;
+use num::complex::{Complex32, Complex64};
 use nalgebra_sparse::csr::CsrMatrix;
 use nalgebra_sparse::csc::CscMatrix;
 use polars::frame::DataFrame;
@@ -70,11 +72,31 @@ impl_into_data!(u8, U8);
 impl_into_data!(u16, U16);
 impl_into_data!(u32, U32);
 impl_into_data!(u64, U64);
+impl_into_data!(f16, F16);
 impl_into_data!(f32, F32);
 impl_into_data!(f64, F64);
 impl_into_data!(bool, Bool);
 impl_into_data!(String, String);
 
+/// Complex numbers are only supported in dense arrays.
+macro_rules! impl_into_data_dense {
+    ($from_type:ty, $to_type:ident) => {
+        impl From<$from_type> for Data {
+            fn from(data: $from_type) -> Self {
+                Data::Scalar(DynScalar::$to_type(data))
+            }
+        }
+        impl<D: RemoveAxis> From<Array<$from_type, D>> for Data {
+            fn from(data: Array<$from_type, D>) -> Self {
+                Data::ArrayData(ArrayData::Array(DynArray::$to_type(data.into_dyn())))
+            }
+        }
+    };
+}
+
+impl_into_data_dense!(Complex32, ComplexF32);
+impl_into_data_dense!(Complex64, ComplexF64);
+
 macro_rules! impl_into_data2 {
     ($from_type:ty, $to_type:ident) => {
         impl From<$from_type> for Data {
@@ -116,8 +138,8 @@ macro_rules! impl_try_from_for_scalar {
 }
 
 impl_try_from_for_scalar!(
-    I8, i8, I16, i16, I32, i32, I64, i64, U8, u8, U16, u16, U32, u32, U64, u64, F32, f32, F64, f64,
-    Bool, bool, String, String
+    I8, i8, I16, i16, I32, i32, I64, i64, U8, u8, U16, u16, U32, u32, U64, u64, F16, f16, F32, f32, F64, f64,
+    ComplexF32, Complex32, ComplexF64, Complex64, Bool, bool, String, String
 );
 
 impl TryFrom<Data> for DataFrame {
@@ -175,6 +197,12 @@ impl ReadData for Data {
             DataType::CscMatrix(_) => {
                 DynCscMatrix::read(container).map(|x| ArrayData::from(x).into())
             },
+            DataType::RaggedArray(_) => {
+                RaggedArray::read(container).map(|x| ArrayData::from(x).into())
+            },
+            DataType::RecordArray => {
+                RecordArray::read(container).map(|x| ArrayData::from(x).into())
+            },
             DataType::DataFrame => DataFrame::read(container).map(|x| ArrayData::from(x).into()),
             DataType::Scalar(_) => DynScalar::read(container).map(|x| x.into()),
             DataType::Mapping => Mapping::read(container).map(|x| x.into()),
@@ -4,20 +4,35 @@ pub mod dataframe;
 pub mod utils;
 mod sparse;
 mod chunks;
+mod record;
+mod cast;
+mod convert;
+mod ragged;
 
 pub use self::ndarray::{CategoricalArray, DynArray};
 pub use slice::{BoundedSelectInfo, BoundedSelectInfoElem, SelectInfo, SelectInfoElem, Shape};
 pub use sparse::{DynCsrMatrix, DynCscMatrix, DynCsrNonCanonical, CsrNonCanonical};
+pub(crate) use sparse::write_csr_par;
 pub use dataframe::DataFrameIndex;
 pub use chunks::ArrayChunk;
+pub use ragged::RaggedArray;
+pub use record::RecordArray;
+pub(crate) use ragged::ragged_dtype;
+pub(crate) use chunks::{truncate_rows, write_columns, write_rows};
+pub(crate) use self::ndarray::create_filled_array;
+pub(crate) use slice::check_select;
 
 use crate::backend::*;
-use crate::data::utils::from_csr_data;
+use crate::data::utils::{from_csr_data, read_cs_major};
+use slice::BoundedSlice;
 use crate::data::{data_traits::*, scalar::DynScalar, DataType};
 
 use polars::prelude::DataFrame;
 use ::ndarray::{Array, RemoveAxis, Ix1};
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
+use itertools::Itertools;
+use half::f16;
+use num::complex::{Complex32, Complex64};
 use nalgebra_sparse::csr::CsrMatrix;
 use nalgebra_sparse::csc::CscMatrix;
 
@@ -28,6 +43,8 @@ pub enum ArrayData {
     CsrMatrix(DynCsrMatrix),
     CsrNonCanonical(DynCsrNonCanonical),
     CscMatrix(DynCscMatrix),
+    RaggedArray(RaggedArray),
+    RecordArray(RecordArray),
     DataFrame(DataFrame),
 }
 
@@ -64,6 +81,17 @@ impl From<DynCscMatrix> for ArrayData {
         ArrayData::CscMatrix(data)
     }
 }
+impl From<RaggedArray> for ArrayData {
+    fn from(data: RaggedArray) -> Self {
+        ArrayData::RaggedArray(data)
+    }
+}
+
+impl From<RecordArray> for ArrayData {
+    fn from(data: RecordArray) -> Self {
+        ArrayData::RecordArray(data)
+    }
+}
 
 impl TryFrom<ArrayData> for DynArray {
     type Error = anyhow::Error;
@@ -106,6 +134,26 @@ impl TryFrom<ArrayData> for DynCscMatrix {
     }
 }
 
+impl TryFrom<ArrayData> for RaggedArray {
+    type Error = anyhow::Error;
+    fn try_from(value: ArrayData) -> Result<Self, Self::Error> {
+        match value {
+            ArrayData::RaggedArray(data) => Ok(data),
+            _ => bail!("Cannot convert {:?} to RaggedArray", value),
+        }
+    }
+}
+
+impl TryFrom<ArrayData> for RecordArray {
+    type Error = anyhow::Error;
+    fn try_from(value: ArrayData) -> Result<Self, Self::Error> {
+        match value {
+            ArrayData::RecordArray(data) => Ok(data),
+            _ => bail!("Cannot convert {:?} to RecordArray", value),
+        }
+    }
+}
+
 impl TryFrom<ArrayData> for DataFrame {
     type Error = anyhow::Error;
     fn try_from(value: ArrayData) -> Result<Self, Self::Error> {
@@ -181,7 +229,31 @@ macro_rules! impl_into_array_data {
     };
 }
 
-impl_into_array_data!(i8, i16, i32, i64, u8, u16, u32, u64, usize, f32, f64, bool, String);
+impl_into_array_data!(i8, i16, i32, i64, u8, u16, u32, u64, usize, f16, f32, f64, bool, String);
+
+/// Complex numbers are only supported in dense arrays.
+macro_rules! impl_into_array_data_dense {
+    ($($ty:ty),*) => {
+        $(
+            impl<D: RemoveAxis> From<Array<$ty, D>> for ArrayData {
+                fn from(data: Array<$ty, D>) -> Self {
+                    ArrayData::Array(data.into_dyn().into())
+                }
+            }
+            impl<D: RemoveAxis> TryFrom<ArrayData> for Array<$ty, D> {
+                type Error = anyhow::Error;
+                fn try_from(value: ArrayData) -> Result<Self, Self::Error> {
+                    match value {
+                        ArrayData::Array(data) => data.try_into(),
+                        _ => bail!("Cannot convert {:?} to $ty Array", value),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_into_array_data_dense!(Complex32, Complex64);
 
 impl WriteData for ArrayData {
     fn data_type(&self) -> DataType {
@@ -190,6 +262,8 @@ impl WriteData for ArrayData {
             ArrayData::CsrMatrix(data) => data.data_type(),
             ArrayData::CsrNonCanonical(data) => data.data_type(),
             ArrayData::CscMatrix(data) => data.data_type(),
+            ArrayData::RaggedArray(data) => data.data_type(),
+            ArrayData::RecordArray(data) => data.data_type(),
             ArrayData::DataFrame(data) => data.data_type(),
         }
     }
@@ -203,9 +277,26 @@ impl WriteData for ArrayData {
             ArrayData::CsrMatrix(data) => data.write(location, name),
             ArrayData::CsrNonCanonical(data) => data.write(location, name),
             ArrayData::CscMatrix(data) => data.write(location, name),
+            ArrayData::RaggedArray(data) => data.write(location, name),
+            ArrayData::RecordArray(data) => data.write(location, name),
             ArrayData::DataFrame(data) => data.write(location, name),
         }
     }
+    fn write_with_config<B: Backend, G: GroupOp<Backend = B>>(
+        &self,
+        location: &G,
+        name: &str,
+        config: WriteConfig,
+    ) -> Result<DataContainer<B>> {
+        match self {
+            ArrayData::Array(data) => data.write_with_config(location, name, config),
+            ArrayData::CsrMatrix(data) => data.write_with_config(location, name, config),
+            ArrayData::CsrNonCanonical(data) => data.write_with_config(location, name, config),
+            ArrayData::CscMatrix(data) => data.write_with_config(location, name, config),
+            ArrayData::RecordArray(data) => data.write_with_config(location, name, config),
+            _ => self.write(location, name),
+        }
+    }
 }
 
 impl ReadData for ArrayData {
@@ -216,6 +307,8 @@ impl ReadData for ArrayData {
             }
             DataType::CsrMatrix(_) => read_csr(container),
             DataType::CscMatrix(_) => DynCscMatrix::read(container).map(ArrayData::CscMatrix),
+            DataType::RaggedArray(_) => RaggedArray::read(container).map(ArrayData::RaggedArray),
+            DataType::RecordArray => RecordArray::read(container).map(ArrayData::RecordArray),
             DataType::DataFrame => DataFrame::read(container).map(ArrayData::DataFrame),
             ty => bail!("Cannot read type '{:?}' as matrix data", ty),
         }
@@ -229,6 +322,8 @@ impl HasShape for ArrayData {
             ArrayData::CsrMatrix(data) => data.shape(),
             ArrayData::CsrNonCanonical(data) => data.shape(),
             ArrayData::CscMatrix(data) => data.shape(),
+            ArrayData::RaggedArray(data) => data.shape(),
+            ArrayData::RecordArray(data) => data.shape(),
             ArrayData::DataFrame(data) => HasShape::shape(data),
         }
     }
@@ -241,6 +336,8 @@ impl ArrayOp for ArrayData {
             ArrayData::CsrMatrix(data) => data.get(index),
             ArrayData::CsrNonCanonical(data) => data.get(index),
             ArrayData::CscMatrix(data) => data.get(index),
+            ArrayData::RaggedArray(data) => data.get(index),
+            ArrayData::RecordArray(data) => data.get(index),
             ArrayData::DataFrame(data) => ArrayOp::get(data, index),
         }
     }
@@ -254,12 +351,33 @@ impl ArrayOp for ArrayData {
             ArrayData::CsrMatrix(data) => data.select(info).into(),
             ArrayData::CsrNonCanonical(data) => data.select(info).into(),
             ArrayData::CscMatrix(data) => data.select(info).into(),
+            ArrayData::RaggedArray(data) => data.select(info).into(),
+            ArrayData::RecordArray(data) => data.select(info).into(),
             ArrayData::DataFrame(data) => ArrayOp::select(data,info).into(),
         }
     }
 
+    /// Arrays of the same kind but with different scalar types, e.g., `i32`
+    /// and `i64` matrices, are cast to their common type before being stacked.
     fn vstack<I: Iterator<Item = Self>>(iter: I) -> Result<Self> {
-        let mut iter = iter.peekable();
+        let arrays: Vec<_> = iter.collect();
+        let dtype = arrays.iter().map(|x| x.data_type()).try_fold(None, |acc: Option<DataType>, ty| match acc {
+            None => Ok(Some(ty)),
+            Some(acc) => acc
+                .promote(ty)
+                .map(Some)
+                .ok_or_else(|| anyhow!("cannot stack arrays of type {} and {}", acc, ty)),
+        })?;
+        let arrays = match dtype {
+            None => bail!("cannot stack an empty list of arrays"),
+            Some(DataType::Array(ty) | DataType::CsrMatrix(ty) | DataType::CscMatrix(ty) | DataType::RaggedArray(ty))
+                if !arrays.iter().map(|x| x.data_type()).all_equal() =>
+            {
+                arrays.into_iter().map(|x| x.cast(ty)).collect::<Result<Vec<_>>>()?
+            }
+            _ => arrays,
+        };
+        let mut iter = arrays.into_iter().peekable();
         match iter.peek().unwrap() {
             ArrayData::Array(_) => DynArray::vstack(iter.map(|x| x.try_into().unwrap())).map(|x| x.into()),
             ArrayData::CsrMatrix(_) => DynCsrNonCanonical::vstack(iter.map(|x| x.try_into().unwrap()))
@@ -269,6 +387,8 @@ impl ArrayOp for ArrayData {
                 }),
             ArrayData::CsrNonCanonical(_) => DynCsrNonCanonical::vstack(iter.map(|x| x.try_into().unwrap())).map(|x| x.into()),
             ArrayData::CscMatrix(_) => DynCscMatrix::vstack(iter.map(|x| x.try_into().unwrap())).map(|x| x.into()),
+            ArrayData::RaggedArray(_) => RaggedArray::vstack(iter.map(|x| x.try_into().unwrap())).map(|x| x.into()),
+            ArrayData::RecordArray(_) => RecordArray::vstack(iter.map(|x| x.try_into().unwrap())).map(|x| x.into()),
             ArrayData::DataFrame(_) => <DataFrame as ArrayOp>::vstack(iter.map(|x| x.try_into().unwrap())).map(|x| x.into()),
         }
     }
@@ -280,6 +400,8 @@ impl ReadArrayData for ArrayData {
             DataType::Categorical | DataType::Array(_) => DynArray::get_shape(container),
             DataType::CsrMatrix(_) => DynCsrMatrix::get_shape(container),
             DataType::CscMatrix(_) => DynCscMatrix::get_shape(container),
+            DataType::RaggedArray(_) => RaggedArray::get_shape(container),
+            DataType::RecordArray => RecordArray::get_shape(container),
             DataType::DataFrame => DataFrame::get_shape(container),
             ty => bail!("Cannot read shape information from type '{}'", ty),
         }
@@ -296,6 +418,10 @@ impl ReadArrayData for ArrayData {
             DataType::CsrMatrix(_) => read_csr_select(container, info),
             DataType::CscMatrix(_) =>
                 DynCscMatrix::read_select(container, info).map(ArrayData::CscMatrix),
+            DataType::RaggedArray(_) =>
+                RaggedArray::read_select(container, info).map(ArrayData::RaggedArray),
+            DataType::RecordArray =>
+                RecordArray::read_select(container, info).map(ArrayData::RecordArray),
             DataType::DataFrame =>
                 DataFrame::read_select(container, info).map(ArrayData::DataFrame),
             ty => bail!("Cannot read type '{:?}' as matrix data", ty),
@@ -316,7 +442,7 @@ fn read_csr<B: Backend>(container: &DataContainer<B>) -> Result<ArrayData> {
         CsrNonCanonical<T>: Into<ArrayData>,
     {
         let group = container.as_group()?;
-        let shape: Vec<usize> = group.read_array_attr("shape")?.to_vec();
+        let shape: Vec<usize> = group.read_shape_attr()?;
         let data = group.open_dataset("data")?.read_array::<_, Ix1>()?.into_raw_vec();
         let indptr: Vec<usize> = group.open_dataset("indptr")?.read_array::<_, Ix1>()?.into_raw_vec();
         let indices: Vec<usize> = group.open_dataset("indices")?.read_array::<_, Ix1>()?.into_raw_vec();
@@ -334,8 +460,12 @@ fn read_csr<B: Backend>(container: &DataContainer<B>) -> Result<ArrayData> {
             ScalarType::U32 => _read_csr::<B, u32>(container),
             ScalarType::U64 => _read_csr::<B, u64>(container),
             ScalarType::Usize => _read_csr::<B, usize>(container),
+            ScalarType::F16 => _read_csr::<B, f16>(container),
             ScalarType::F32 => _read_csr::<B, f32>(container),
             ScalarType::F64 => _read_csr::<B, f64>(container),
+            ScalarType::ComplexF32 | ScalarType::ComplexF64 => {
+                bail!("sparse matrices of complex numbers are not supported")
+            }
             ScalarType::Bool => _read_csr::<B, bool>(container),
             ScalarType::String => _read_csr::<B, String>(container),
         },
@@ -343,6 +473,55 @@ fn read_csr<B: Backend>(container: &DataContainer<B>) -> Result<ArrayData> {
     }
 }
 
+/// The number of stored values read at a time by `read_csr_columns`.
+const CSR_COLUMN_BLOCK: usize = 1 << 22;
+
+/// Read the entries of the rows delimited by `indptr` that belong to the
+/// selected columns, rewriting `indptr` to index the returned indices and
+/// values. The rows are read in blocks of about `CSR_COLUMN_BLOCK` values, so
+/// that only the selected columns, rather than all of the rows, are held in
+/// memory. The column indices are still read in full, as CSR has no index by
+/// column.
+fn read_csr_columns<B: Backend, T: BackendData>(
+    group: &B::Group,
+    indptr: &mut [usize],
+    num_cols: usize,
+    select: &SelectInfoElem,
+) -> Result<(Vec<usize>, Vec<T>)> {
+    let mut mask = vec![false; num_cols];
+    BoundedSelectInfoElem::new(select, num_cols).iter().for_each(|i| mask[i] = true);
+    let indices_dataset = group.open_dataset("indices")?;
+    let data_dataset = group.open_dataset("data")?;
+
+    let num_rows = indptr.len() - 1;
+    let mut indices = Vec::new();
+    let mut data = Vec::new();
+    let mut start = 0;
+    while start < num_rows {
+        let mut end = start + 1;
+        while end < num_rows && indptr[end + 1] - indptr[start] <= CSR_COLUMN_BLOCK {
+            end += 1;
+        }
+        let slice = SelectInfoElem::from(indptr[start]..indptr[end]);
+        let block_indices: Vec<usize> = indices_dataset.read_array_slice(&[&slice])?.to_vec();
+        let block_data: Vec<T> = data_dataset.read_array_slice(&[&slice])?.to_vec();
+        let mut entries = block_indices.into_iter().zip(block_data);
+        for row in start..end {
+            let n = indptr[row + 1] - indptr[row];
+            indptr[row] = indices.len();
+            for (col, v) in entries.by_ref().take(n) {
+                if mask[col] {
+                    indices.push(col);
+                    data.push(v);
+                }
+            }
+        }
+        start = end;
+    }
+    indptr[num_rows] = indices.len();
+    Ok((indices, data))
+}
+
 fn read_csr_select<B: Backend, S>(container: &DataContainer<B>, info: &[S]) -> Result<ArrayData>
 where
     B: Backend,
@@ -362,23 +541,25 @@ where
             return read_csr(container);
         }
 
-        let data = if let SelectInfoElem::Slice(s) = info[0].as_ref()  {
-            let group = container.as_group()?;
-            let shape: Vec<usize> = group.read_array_attr("shape")?.to_vec();
-            let indptr_slice = if let Some(end) = s.end {
-                SelectInfoElem::from(s.start .. end + 1)
-            } else {
-                SelectInfoElem::from(s.start ..)
-            };
+        let group = container.as_group()?;
+        let shape: Vec<usize> = group.read_shape_attr()?;
+        let rows = BoundedSelectInfoElem::new(&info[0], shape[0]);
+        let data = if let BoundedSelectInfoElem::Slice(BoundedSlice { start, end, step: 1 }) = rows {
+            let indptr_slice = SelectInfoElem::from(start..end + 1);
             let mut indptr: Vec<usize> = group 
                 .open_dataset("indptr")?
                 .read_array_slice(&[indptr_slice])?
                 .to_vec();
-            let lo = indptr[0];
-            let slice = SelectInfoElem::from(lo .. indptr[indptr.len() - 1]);
-            let data: Vec<T> = group.open_dataset("data")?.read_array_slice(&[&slice])?.to_vec();
-            let indices: Vec<usize> = group.open_dataset("indices")?.read_array_slice(&[&slice])?.to_vec();
-            indptr.iter_mut().for_each(|x| *x -= lo);
+            let (indices, data) = if info[1].as_ref().is_full() {
+                let lo = indptr[0];
+                let slice = SelectInfoElem::from(lo .. indptr[indptr.len() - 1]);
+                let data: Vec<T> = group.open_dataset("data")?.read_array_slice(&[&slice])?.to_vec();
+                let indices: Vec<usize> = group.open_dataset("indices")?.read_array_slice(&[&slice])?.to_vec();
+                indptr.iter_mut().for_each(|x| *x -= lo);
+                (indices, data)
+            } else {
+                read_csr_columns::<B, T>(group, &mut indptr, shape[1], info[1].as_ref())?
+            };
 
             from_csr_data::<T>(
                 indptr.len() - 1,
@@ -388,7 +569,10 @@ where
                 data,
             ).unwrap().select_axis(1, info[1].as_ref())
         } else {
-            read_csr(container)?.select(info)
+            // Strided, reversed and indexed rows are read one run at a time.
+            let (indptr, indices, data) = read_cs_major::<_, T>(group, &rows)?;
+            from_csr_data::<T>(rows.len(), shape[1], indptr, indices, data)?
+                .select_axis(1, info[1].as_ref())
         };
         Ok(data)
     }
@@ -404,8 +588,12 @@ where
             ScalarType::U32 => _read_csr::<B, u32, _>(container, info),
             ScalarType::U64 => _read_csr::<B, u64, _>(container, info),
             ScalarType::Usize => _read_csr::<B, usize, _>(container, info),
+            ScalarType::F16 => _read_csr::<B, f16, _>(container, info),
             ScalarType::F32 => _read_csr::<B, f32, _>(container, info),
             ScalarType::F64 => _read_csr::<B, f64, _>(container, info),
+            ScalarType::ComplexF32 | ScalarType::ComplexF64 => {
+                bail!("sparse matrices of complex numbers are not supported")
+            }
             ScalarType::Bool => _read_csr::<B, bool, _>(container, info),
             ScalarType::String => _read_csr::<B, String, _>(container, info),
         },
@@ -0,0 +1,180 @@
+use crate::backend::{DataType, ScalarType};
+use crate::data::data_traits::WriteData;
+
+use super::{ArrayData, CsrNonCanonical, DynArray, DynCscMatrix, DynCsrMatrix, DynCsrNonCanonical, RaggedArray};
+
+use anyhow::{anyhow, bail, Context, Result};
+use half::f16;
+use nalgebra_sparse::csc::CscMatrix;
+use nalgebra_sparse::csr::CsrMatrix;
+use ndarray::ArrayD;
+use num::{complex::Complex, NumCast, ToPrimitive};
+
+/// Apply `$func::<_, $to>` to the numeric variants of `$data`. Extra match arms
+/// can be given for variants that only exist in some of the enums.
+macro_rules! dispatch {
+    ($data:expr, $enum:ident, $func:ident, $to:ty $(, $pat:pat => $arm:expr)*) => {
+        match $data {
+            $enum::I8(x) => $func::<_, $to>(x)?.into(),
+            $enum::I16(x) => $func::<_, $to>(x)?.into(),
+            $enum::I32(x) => $func::<_, $to>(x)?.into(),
+            $enum::I64(x) => $func::<_, $to>(x)?.into(),
+            $enum::U8(x) => $func::<_, $to>(x)?.into(),
+            $enum::U16(x) => $func::<_, $to>(x)?.into(),
+            $enum::U32(x) => $func::<_, $to>(x)?.into(),
+            $enum::U64(x) => $func::<_, $to>(x)?.into(),
+            $enum::Usize(x) => $func::<_, $to>(x)?.into(),
+            $enum::F16(x) => $func::<_, $to>(x)?.into(),
+            $enum::F32(x) => $func::<_, $to>(x)?.into(),
+            $enum::F64(x) => $func::<_, $to>(x)?.into(),
+            $enum::Bool(_) => bail!("cannot cast boolean values to a numeric type"),
+            $enum::String(_) => bail!("cannot cast strings to a numeric type"),
+            $($pat => $arm,)*
+        }
+    };
+}
+
+impl ArrayData {
+    /// Convert the values of a numeric array, sparse matrix or ragged array to `dtype`.
+    /// The conversion is checked: an error is returned if a value cannot be
+    /// represented in the new type, e.g., an out-of-range or NaN `f64` value
+    /// cast to `i32`. Data that already has the requested type is returned as is.
+    pub fn cast(self, dtype: ScalarType) -> Result<Self> {
+        match self.data_type() {
+            DataType::Array(ty)
+            | DataType::CsrMatrix(ty)
+            | DataType::CscMatrix(ty)
+            | DataType::RaggedArray(ty) if ty == dtype => {
+                return Ok(self)
+            }
+            _ => {}
+        }
+        match dtype {
+            ScalarType::I8 => self.cast_to::<i8>(),
+            ScalarType::I16 => self.cast_to::<i16>(),
+            ScalarType::I32 => self.cast_to::<i32>(),
+            ScalarType::I64 => self.cast_to::<i64>(),
+            ScalarType::U8 => self.cast_to::<u8>(),
+            ScalarType::U16 => self.cast_to::<u16>(),
+            ScalarType::U32 => self.cast_to::<u32>(),
+            ScalarType::U64 => self.cast_to::<u64>(),
+            ScalarType::Usize => self.cast_to::<usize>(),
+            ScalarType::F16 => self.cast_to::<f16>(),
+            ScalarType::F32 => self.cast_to::<f32>(),
+            ScalarType::F64 => self.cast_to::<f64>(),
+            ScalarType::ComplexF32 => self.cast_to_complex::<f32>(),
+            ScalarType::ComplexF64 => self.cast_to_complex::<f64>(),
+            ScalarType::Bool | ScalarType::String => bail!("cannot cast to {}, only numeric types are supported", dtype),
+        }
+        .with_context(|| format!("failed to cast data to {}", dtype))
+    }
+
+    fn cast_to<U>(self) -> Result<Self>
+    where
+        U: NumCast + Copy,
+        ArrayD<U>: Into<ArrayData>,
+        CsrMatrix<U>: Into<ArrayData>,
+        CscMatrix<U>: Into<ArrayData>,
+        CsrNonCanonical<U>: Into<ArrayData>,
+    {
+        let data = match self {
+            ArrayData::Array(x) => dispatch!(
+                x, DynArray, cast_array, U,
+                DynArray::ComplexF32(x) => cast_array::<_, U>(x)?.into(),
+                DynArray::ComplexF64(x) => cast_array::<_, U>(x)?.into(),
+                DynArray::Categorical(_) => bail!("cannot cast a categorical array")
+            ),
+            ArrayData::CsrMatrix(x) => dispatch!(x, DynCsrMatrix, cast_csr, U),
+            ArrayData::CsrNonCanonical(x) => dispatch!(x, DynCsrNonCanonical, cast_csr_noncanonical, U),
+            ArrayData::CscMatrix(x) => dispatch!(x, DynCscMatrix, cast_csc, U),
+            ArrayData::RaggedArray(x) => {
+                let (values, offsets) = x.into_parts();
+                let values = ArrayData::Array(values).cast_to::<U>()?.try_into()?;
+                RaggedArray::new(values, offsets)?.into()
+            }
+            ArrayData::RecordArray(_) => bail!("cannot cast a record array"),
+            ArrayData::DataFrame(_) => bail!("cannot cast a dataframe"),
+        };
+        Ok(data)
+    }
+
+    /// Complex numbers are only supported in dense arrays.
+    fn cast_to_complex<U>(self) -> Result<Self>
+    where
+        U: NumCast + Copy + num::Num,
+        ArrayD<Complex<U>>: Into<ArrayData>,
+    {
+        let data = match self {
+            ArrayData::Array(x) => dispatch!(
+                x, DynArray, cast_array, Complex<U>,
+                DynArray::ComplexF32(x) => cast_complex::<_, U>(x)?.into(),
+                DynArray::ComplexF64(x) => cast_complex::<_, U>(x)?.into(),
+                DynArray::Categorical(_) => bail!("cannot cast a categorical array")
+            ),
+            x => bail!("cannot cast {} to complex numbers, only dense arrays are supported", x.data_type()),
+        };
+        Ok(data)
+    }
+}
+
+fn cast_values<T, U, I>(values: I) -> Result<Vec<U>>
+where
+    T: ToPrimitive + Copy + std::fmt::Debug,
+    U: NumCast,
+    I: IntoIterator<Item = T>,
+{
+    values
+        .into_iter()
+        .map(|x| U::from(x).with_context(|| format!("value {:?} is out of range", x)))
+        .collect()
+}
+
+fn cast_array<T, U>(data: ArrayD<T>) -> Result<ArrayD<U>>
+where
+    T: ToPrimitive + Copy + std::fmt::Debug,
+    U: NumCast,
+{
+    Ok(ArrayD::from_shape_vec(data.raw_dim(), cast_values(data.iter().copied())?)?)
+}
+
+/// Cast both the real and the imaginary parts.
+fn cast_complex<T, U>(data: ArrayD<Complex<T>>) -> Result<ArrayD<Complex<U>>>
+where
+    T: ToPrimitive + Copy + std::fmt::Debug,
+    U: NumCast,
+{
+    let cast = |x: T| U::from(x).with_context(|| format!("value {:?} is out of range", x));
+    let values = data
+        .iter()
+        .map(|x| Ok(Complex::new(cast(x.re)?, cast(x.im)?)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(ArrayD::from_shape_vec(data.raw_dim(), values)?)
+}
+
+fn cast_csr<T, U>(data: CsrMatrix<T>) -> Result<CsrMatrix<U>>
+where
+    T: ToPrimitive + Copy + std::fmt::Debug,
+    U: NumCast,
+{
+    let (pattern, values) = data.into_pattern_and_values();
+    CsrMatrix::try_from_pattern_and_values(pattern, cast_values(values)?).map_err(|e| anyhow!("{}", e))
+}
+
+fn cast_csc<T, U>(data: CscMatrix<T>) -> Result<CscMatrix<U>>
+where
+    T: ToPrimitive + Copy + std::fmt::Debug,
+    U: NumCast,
+{
+    let (pattern, values) = data.into_pattern_and_values();
+    CscMatrix::try_from_pattern_and_values(pattern, cast_values(values)?).map_err(|e| anyhow!("{}", e))
+}
+
+fn cast_csr_noncanonical<T, U>(data: CsrNonCanonical<T>) -> Result<CsrNonCanonical<U>>
+where
+    T: ToPrimitive + Copy + std::fmt::Debug,
+    U: NumCast,
+{
+    let (nrows, ncols) = (data.nrows(), data.ncols());
+    let (offsets, indices, values) = data.disassemble();
+    Ok(CsrNonCanonical::from_csr_data(nrows, ncols, offsets, indices, cast_values(values)?))
+}
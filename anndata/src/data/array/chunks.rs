@@ -1,12 +1,15 @@
-use crate::backend::{Backend, DataContainer, GroupOp, LocationOp, BackendData, ScalarType};
+use crate::backend::{Backend, DataContainer, DataType, DatasetOp, GroupOp, LocationOp, BackendData, ScalarType};
 use crate::ArrayOp;
 use crate::data::{
-    ArrayData,
+    ArrayData, SelectInfo, SelectInfoElem,
     array::utils::ExtendableDataset,
 };
 
-use anyhow::{bail, Result, Context};
-use ndarray::{Array, ArrayView1, ArrayD, RemoveAxis};
+use anyhow::{bail, ensure, Result, Context};
+use half::f16;
+use num::complex::{Complex32, Complex64};
+use itertools::Itertools;
+use ndarray::{Array, ArrayView1, ArrayD, Ix1, RemoveAxis};
 use nalgebra_sparse::na::Scalar;
 use nalgebra_sparse::{CsrMatrix, CscMatrix};
 use super::{DynCsrMatrix, DynCscMatrix, DynArray, DynCsrNonCanonical, CsrNonCanonical};
@@ -17,6 +20,13 @@ pub trait ArrayChunk: ArrayOp {
         I: Iterator<Item = Self>,
         B: Backend,
         G: GroupOp<Backend = B>;
+
+    /// Append the chunks as new rows, i.e., along the first axis, of an existing
+    /// array, growing the stored datasets in place.
+    fn append_by_chunk<B, I>(iter: I, container: &DataContainer<B>) -> Result<()>
+    where
+        I: Iterator<Item = Self>,
+        B: Backend;
 }
 
 impl ArrayChunk for ArrayData {
@@ -32,9 +42,29 @@ impl ArrayChunk for ArrayData {
             ArrayData::CsrMatrix(_) | ArrayData::CsrNonCanonical(_) =>
                 DynCsrNonCanonical::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             ArrayData::CscMatrix(_) => DynCscMatrix::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
+            ArrayData::RaggedArray(_) => bail!("cannot write ragged arrays by chunk"),
+            ArrayData::RecordArray(_) => bail!("cannot write record arrays by chunk"),
             ArrayData::DataFrame(_) => todo!(),
         }
     }
+
+    fn append_by_chunk<B, I>(iter: I, container: &DataContainer<B>) -> Result<()>
+    where
+        I: Iterator<Item = Self>,
+        B: Backend,
+    {
+        let mut iter = iter.peekable();
+        match iter.peek() {
+            None => Ok(()),
+            Some(ArrayData::Array(_)) => DynArray::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(ArrayData::CsrMatrix(_)) | Some(ArrayData::CsrNonCanonical(_)) =>
+                DynCsrNonCanonical::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(ArrayData::CscMatrix(_)) => bail!("cannot append rows to a CSC matrix"),
+            Some(ArrayData::RaggedArray(_)) => bail!("cannot append rows to a ragged array"),
+            Some(ArrayData::RecordArray(_)) => bail!("cannot append rows to a record array"),
+            Some(ArrayData::DataFrame(_)) => bail!("cannot append rows to a dataframe"),
+        }
+    }
 }
 
 impl ArrayChunk for DynArray {
@@ -55,13 +85,44 @@ impl ArrayChunk for DynArray {
             DynArray::I16(_) => ArrayD::<i16>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynArray::I32(_) => ArrayD::<i32>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynArray::I64(_) => ArrayD::<i64>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
+            DynArray::F16(_) => ArrayD::<f16>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynArray::F32(_) => ArrayD::<f32>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynArray::F64(_) => ArrayD::<f64>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
+            DynArray::ComplexF32(_) => ArrayD::<Complex32>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
+            DynArray::ComplexF64(_) => ArrayD::<Complex64>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynArray::Bool(_) => ArrayD::<bool>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynArray::String(_) => ArrayD::<String>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynArray::Categorical(_) => todo!(),
         }
     }
+
+    fn append_by_chunk<B, I>(iter: I, container: &DataContainer<B>) -> Result<()>
+    where
+        I: Iterator<Item = Self>,
+        B: Backend,
+    {
+        let mut iter = iter.peekable();
+        match iter.peek() {
+            None => Ok(()),
+            Some(DynArray::U8(_)) => ArrayD::<u8>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynArray::U16(_)) => ArrayD::<u16>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynArray::U32(_)) => ArrayD::<u32>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynArray::U64(_)) => ArrayD::<u64>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynArray::Usize(_)) => ArrayD::<usize>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynArray::I8(_)) => ArrayD::<i8>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynArray::I16(_)) => ArrayD::<i16>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynArray::I32(_)) => ArrayD::<i32>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynArray::I64(_)) => ArrayD::<i64>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynArray::F16(_)) => ArrayD::<f16>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynArray::F32(_)) => ArrayD::<f32>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynArray::F64(_)) => ArrayD::<f64>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynArray::ComplexF32(_)) => ArrayD::<Complex32>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynArray::ComplexF64(_)) => ArrayD::<Complex64>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynArray::Bool(_)) => ArrayD::<bool>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynArray::String(_)) => ArrayD::<String>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynArray::Categorical(_)) => bail!("cannot append rows to a categorical array"),
+        }
+    }
 }
 
 impl<D: RemoveAxis, T: BackendData> ArrayChunk for Array<T, D> {
@@ -94,6 +155,36 @@ impl<D: RemoveAxis, T: BackendData> ArrayChunk for Array<T, D> {
         container.write_str_attr("encoding-version", "0.2.0")?;
         Ok(container)
     }
+
+    fn append_by_chunk<B, I>(mut iter: I, container: &DataContainer<B>) -> Result<()>
+    where
+        I: Iterator<Item = Self>,
+        B: Backend,
+    {
+        let dataset = container.as_dataset()?;
+        let dtype = dataset.dtype()?;
+        ensure!(dtype == T::DTYPE, "cannot append {} to an array of {}", T::DTYPE, dtype);
+        let mut shape = dataset.shape();
+        iter.try_for_each(|x| {
+            ensure!(
+                x.ndim() == shape.ndim() && x.shape()[1..] == shape.as_ref()[1..],
+                "cannot append an array of shape {} to an array of shape {}",
+                x.shape().iter().join(" x "), shape,
+            );
+            if x.shape()[0] == 0 {
+                return Ok(());
+            }
+            let start = shape[0];
+            shape[0] += x.shape()[0];
+            dataset.reshape(&shape)?;
+            if x.is_empty() {
+                return Ok(());
+            }
+            let mut select = SelectInfo::all(shape.ndim());
+            select.0[0] = (start..shape[0]).into();
+            dataset.write_array_slice(x.view(), select.as_ref())
+        })
+    }
 }
 
 impl ArrayChunk for DynCsrMatrix {
@@ -114,12 +205,38 @@ impl ArrayChunk for DynCsrMatrix {
             DynCsrMatrix::I16(_) => CsrMatrix::<i16>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCsrMatrix::I32(_) => CsrMatrix::<i32>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCsrMatrix::I64(_) => CsrMatrix::<i64>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
+            DynCsrMatrix::F16(_) => CsrMatrix::<f16>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCsrMatrix::F32(_) => CsrMatrix::<f32>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCsrMatrix::F64(_) => CsrMatrix::<f64>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCsrMatrix::Bool(_) => CsrMatrix::<bool>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCsrMatrix::String(_) => CsrMatrix::<String>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
         }
     }
+
+    fn append_by_chunk<B, I>(iter: I, container: &DataContainer<B>) -> Result<()>
+    where
+        I: Iterator<Item = Self>,
+        B: Backend,
+    {
+        let mut iter = iter.peekable();
+        match iter.peek() {
+            None => Ok(()),
+            Some(DynCsrMatrix::U8(_)) => CsrMatrix::<u8>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrMatrix::U16(_)) => CsrMatrix::<u16>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrMatrix::U32(_)) => CsrMatrix::<u32>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrMatrix::U64(_)) => CsrMatrix::<u64>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrMatrix::Usize(_)) => CsrMatrix::<usize>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrMatrix::I8(_)) => CsrMatrix::<i8>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrMatrix::I16(_)) => CsrMatrix::<i16>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrMatrix::I32(_)) => CsrMatrix::<i32>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrMatrix::I64(_)) => CsrMatrix::<i64>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrMatrix::F16(_)) => CsrMatrix::<f16>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrMatrix::F32(_)) => CsrMatrix::<f32>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrMatrix::F64(_)) => CsrMatrix::<f64>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrMatrix::Bool(_)) => CsrMatrix::<bool>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrMatrix::String(_)) => CsrMatrix::<String>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+        }
+    }
 }
 
 
@@ -172,6 +289,18 @@ impl<T: BackendData> ArrayChunk for CsrMatrix<T> {
         group.write_array_attr("shape", &[num_rows, num_cols.unwrap_or(0)])?;
         Ok(DataContainer::Group(group))
     }
+
+    fn append_by_chunk<B, I>(mut iter: I, container: &DataContainer<B>) -> Result<()>
+    where
+        I: Iterator<Item = Self>,
+        B: Backend,
+    {
+        let mut csr = CsrAppender::new(container)?;
+        iter.try_for_each(|x| {
+            let (indptr, indices, data) = x.csr_data();
+            csr.push(x.ncols(), indptr, indices, data)
+        })
+    }
 }
 
 impl ArrayChunk for DynCsrNonCanonical {
@@ -192,12 +321,38 @@ impl ArrayChunk for DynCsrNonCanonical {
             DynCsrNonCanonical::I16(_) => CsrNonCanonical::<i16>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCsrNonCanonical::I32(_) => CsrNonCanonical::<i32>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCsrNonCanonical::I64(_) => CsrNonCanonical::<i64>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
+            DynCsrNonCanonical::F16(_) => CsrNonCanonical::<f16>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCsrNonCanonical::F32(_) => CsrNonCanonical::<f32>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCsrNonCanonical::F64(_) => CsrNonCanonical::<f64>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCsrNonCanonical::Bool(_) => CsrNonCanonical::<bool>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCsrNonCanonical::String(_) => CsrNonCanonical::<String>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
         }
     }
+
+    fn append_by_chunk<B, I>(iter: I, container: &DataContainer<B>) -> Result<()>
+    where
+        I: Iterator<Item = Self>,
+        B: Backend,
+    {
+        let mut iter = iter.peekable();
+        match iter.peek() {
+            None => Ok(()),
+            Some(DynCsrNonCanonical::U8(_)) => CsrNonCanonical::<u8>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrNonCanonical::U16(_)) => CsrNonCanonical::<u16>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrNonCanonical::U32(_)) => CsrNonCanonical::<u32>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrNonCanonical::U64(_)) => CsrNonCanonical::<u64>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrNonCanonical::Usize(_)) => CsrNonCanonical::<usize>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrNonCanonical::I8(_)) => CsrNonCanonical::<i8>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrNonCanonical::I16(_)) => CsrNonCanonical::<i16>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrNonCanonical::I32(_)) => CsrNonCanonical::<i32>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrNonCanonical::I64(_)) => CsrNonCanonical::<i64>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrNonCanonical::F16(_)) => CsrNonCanonical::<f16>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrNonCanonical::F32(_)) => CsrNonCanonical::<f32>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrNonCanonical::F64(_)) => CsrNonCanonical::<f64>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrNonCanonical::Bool(_)) => CsrNonCanonical::<bool>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCsrNonCanonical::String(_)) => CsrNonCanonical::<String>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+        }
+    }
 }
 
 impl<T: BackendData> ArrayChunk for CsrNonCanonical<T> {
@@ -249,6 +404,18 @@ impl<T: BackendData> ArrayChunk for CsrNonCanonical<T> {
         group.write_array_attr("shape", &[num_rows, num_cols.unwrap_or(0)])?;
         Ok(DataContainer::Group(group))
     }
+
+    fn append_by_chunk<B, I>(mut iter: I, container: &DataContainer<B>) -> Result<()>
+    where
+        I: Iterator<Item = Self>,
+        B: Backend,
+    {
+        let mut csr = CsrAppender::new(container)?;
+        iter.try_for_each(|x| {
+            let (indptr, indices, data) = x.csr_data();
+            csr.push(x.ncols(), indptr, indices, data)
+        })
+    }
 }
 
 
@@ -270,37 +437,87 @@ impl ArrayChunk for DynCscMatrix {
             DynCscMatrix::I16(_) => CscMatrix::<i16>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCscMatrix::I32(_) => CscMatrix::<i32>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCscMatrix::I64(_) => CscMatrix::<i64>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
+            DynCscMatrix::F16(_) => CscMatrix::<f16>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCscMatrix::F32(_) => CscMatrix::<f32>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCscMatrix::F64(_) => CscMatrix::<f64>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCscMatrix::Bool(_) => CscMatrix::<bool>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
             DynCscMatrix::String(_) => CscMatrix::<String>::write_by_chunk(iter.map(|x| x.try_into().unwrap()), location, name),
         }
     }
+
+    fn append_by_chunk<B, I>(iter: I, container: &DataContainer<B>) -> Result<()>
+    where
+        I: Iterator<Item = Self>,
+        B: Backend,
+    {
+        let mut iter = iter.peekable();
+        match iter.peek() {
+            None => Ok(()),
+            Some(DynCscMatrix::U8(_)) => CscMatrix::<u8>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCscMatrix::U16(_)) => CscMatrix::<u16>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCscMatrix::U32(_)) => CscMatrix::<u32>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCscMatrix::U64(_)) => CscMatrix::<u64>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCscMatrix::Usize(_)) => CscMatrix::<usize>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCscMatrix::I8(_)) => CscMatrix::<i8>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCscMatrix::I16(_)) => CscMatrix::<i16>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCscMatrix::I32(_)) => CscMatrix::<i32>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCscMatrix::I64(_)) => CscMatrix::<i64>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCscMatrix::F16(_)) => CscMatrix::<f16>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCscMatrix::F32(_)) => CscMatrix::<f32>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCscMatrix::F64(_)) => CscMatrix::<f64>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCscMatrix::Bool(_)) => CscMatrix::<bool>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+            Some(DynCscMatrix::String(_)) => CscMatrix::<String>::append_by_chunk(iter.map(|x| x.try_into().unwrap()), container),
+        }
+    }
 }
 
 
+/// The number of stored values gathered at a time by `CscMatrix::write_by_chunk`.
+const CSC_COLUMN_BLOCK: usize = 1 << 22;
+
 impl<T: BackendData+Scalar> ArrayChunk for CscMatrix<T> {
-        // TODO! more efficent way should be implement
-        // Ok(iter.reduce(|acc, x| vstack_csc(acc, x)).unwrap())
-        /*
-        Ok(iter.map(|csc| csc.transpose())
-               .reduce(|acc, x| hstack_csc(acc, x))
-               .unwrap()
-               .transpose())
-        */
-
-    fn write_by_chunk<B, G, I>(_: I, _: &G, _: &str) -> Result<DataContainer<B>>
+    /// Chunks are stacked along the rows, which is the minor axis of a CSC
+    /// matrix. The chunks are first streamed into temporary datasets, and the
+    /// columns are then gathered from them in blocks of about
+    /// `CSC_COLUMN_BLOCK` values, so that neither the chunks nor the output
+    /// are held in memory. The temporary datasets are deleted afterwards.
+    fn write_by_chunk<B, G, I>(mut iter: I, location: &G, name: &str) -> Result<DataContainer<B>>
     where
         I: Iterator<Item = Self>,
         B: Backend,
         G: GroupOp<Backend = B>,
     {
-        todo!()
-        /*
         let group = location.create_group(name)?;
         group.write_str_attr("encoding-type", "csc_matrix")?;
         group.write_str_attr("encoding-version", "0.1.0")?;
-        group.write_str_attr("h5sparse_format", "csc")?;
+
+        let mut chunk_data: ExtendableDataset<B, T> = ExtendableDataset::with_capacity(
+            &group, "_chunk_data", 1000.into(),
+        )?;
+        let mut chunk_indices: ExtendableDataset<B, i64> = ExtendableDataset::with_capacity(
+            &group, "_chunk_indices", 1000.into(),
+        )?;
+        // The column offsets of each chunk in the temporary datasets, and the
+        // index of its first row in the output.
+        let mut chunks: Vec<(Vec<usize>, i64)> = Vec::new();
+        let mut num_rows = 0;
+        let mut num_cols: Option<usize> = None;
+        let mut nnz = 0;
+
+        iter.try_for_each(|csc| {
+            let c = *num_cols.get_or_insert(csc.ncols());
+            ensure!(c == csc.ncols(), "All matrices must have the same number of columns");
+            let (offsets, indices, data) = csc.csc_data();
+            chunks.push((offsets.iter().map(|x| x + nnz).collect(), num_rows as i64));
+            chunk_data.extend(0, ArrayView1::from(data))?;
+            chunk_indices.extend(0, ArrayView1::from(indices).mapv(|x| x as i64).view())?;
+            num_rows += csc.nrows();
+            nnz += data.len();
+            Ok(())
+        })?;
+        let chunk_data = chunk_data.finish()?;
+        let chunk_indices = chunk_indices.finish()?;
+        let num_cols = num_cols.unwrap_or(0);
 
         let mut data: ExtendableDataset<B, T> = ExtendableDataset::with_capacity(
             &group, "data", 1000.into(),
@@ -308,43 +525,275 @@ impl<T: BackendData+Scalar> ArrayChunk for CscMatrix<T> {
         let mut indices: ExtendableDataset<B, i64> = ExtendableDataset::with_capacity(
             &group, "indices", 1000.into(),
         )?;
-        let mut indptr: Vec<i64> = Vec::new();
-        let mut num_cols = 0;
-        let mut num_rows: Option<usize> = None;
-        let mut nnz = 0;
-
-        iter.try_for_each(|csc| {
-            let r = csc.nrows();
-            if num_rows.is_none() {
-                num_rows = Some(r);
+        let mut indptr: Vec<i64> = Vec::with_capacity(num_cols + 1);
+        indptr.push(0);
+        let block_nnz = |start: usize, end: usize| -> usize {
+            chunks.iter().map(|(offsets, _)| offsets[end] - offsets[start]).sum()
+        };
+        let mut start = 0;
+        while start < num_cols {
+            let mut end = start + 1;
+            while end < num_cols && block_nnz(start, end + 1) <= CSC_COLUMN_BLOCK {
+                end += 1;
             }
-            if num_rows.unwrap() == r {
-                num_cols += csc.ncols();
-                let (indptr_, indices_, data_) = csc.csc_data();
-                indptr_[..indptr_.len() - 1]
-                    .iter()
-                    .for_each(|x| indptr.push(i64::try_from(*x).unwrap() + nnz));
-                nnz += *indptr_.last().unwrap_or(&0) as i64;
-                data.extend(0, ArrayView1::from_shape(data_.len(), data_)?)?;
-                indices.extend(0, ArrayView1::from_shape(indices_.len(), indices_)?.mapv(|x| x as i64).view())
-            } else {
-                bail!("All matrices must have the same number of rows");
+            // Read the columns `start..end` of every chunk, which are stored
+            // contiguously in the temporary datasets.
+            let blocks = chunks.iter().map(|(offsets, row_offset)| {
+                let (lo, hi) = (offsets[start], offsets[end]);
+                if lo == hi {
+                    return Ok((Vec::new(), Vec::new()));
+                }
+                let slice = SelectInfoElem::from(lo..hi);
+                let block_data = chunk_data.read_array_slice::<T, _, Ix1>(&[&slice])?.to_vec();
+                let block_indices = chunk_indices.read_array_slice::<i64, _, Ix1>(&[&slice])?
+                    .mapv(|x: i64| x + row_offset).to_vec();
+                Ok((block_data, block_indices))
+            }).collect::<Result<Vec<_>>>()?;
+
+            let mut block_data = Vec::with_capacity(block_nnz(start, end));
+            let mut block_indices = Vec::with_capacity(block_nnz(start, end));
+            for j in start..end {
+                chunks.iter().zip(&blocks).for_each(|((offsets, _), (d, i))| {
+                    let (lo, hi) = (offsets[j] - offsets[start], offsets[j + 1] - offsets[start]);
+                    block_data.extend_from_slice(&d[lo..hi]);
+                    block_indices.extend_from_slice(&i[lo..hi]);
+                });
+                indptr.push(block_indices.len() as i64 + indptr[start]);
             }
-        })?;
+            data.extend(0, ArrayView1::from(&block_data))?;
+            indices.extend(0, ArrayView1::from(&block_indices))?;
+            start = end;
+        }
 
-        indices.finish()?;
         data.finish()?;
-        indptr.push(nnz);
+        indices.finish()?;
+        drop((chunk_data, chunk_indices));
+        group.delete("_chunk_data")?;
+        group.delete("_chunk_indices")?;
         group.create_array_data("indptr", &indptr, Default::default())?;
-        group.write_array_attr("shape", &[num_rows.unwrap_or(0), num_cols])?;
+        group.write_array_attr("shape", &[num_rows, num_cols])?;
         Ok(DataContainer::Group(group))
-        */
+    }
+
+    fn append_by_chunk<B, I>(iter: I, _container: &DataContainer<B>) -> Result<()>
+    where
+        I: Iterator<Item = Self>,
+        B: Backend,
+    {
+        if iter.peekable().peek().is_some() {
+            bail!("cannot append rows to a CSC matrix");
+        }
+        Ok(())
     }
 }
 
+/// Appends rows to a CSR matrix stored in a group. The shape attribute is
+/// updated after every chunk so that the stored matrix stays valid.
+struct CsrAppender<'a, B: Backend> {
+    group: &'a B::Group,
+    data: B::Dataset,
+    indices: B::Dataset,
+    indptr: B::Dataset,
+    num_rows: usize,
+    num_cols: usize,
+    nnz: usize,
+}
+
+impl<'a, B: Backend> CsrAppender<'a, B> {
+    fn new(container: &'a DataContainer<B>) -> Result<Self> {
+        let group = container.as_group()?;
+        let shape: Vec<usize> = group.read_shape_attr()?;
+        let data = group.open_dataset("data")?;
+        Ok(Self {
+            group,
+            nnz: data.shape()[0],
+            data,
+            indices: group.open_dataset("indices")?,
+            indptr: group.open_dataset("indptr")?,
+            num_rows: shape[0],
+            num_cols: shape[1],
+        })
+    }
+
+    fn push<T: BackendData>(&mut self, ncols: usize, indptr: &[usize], indices: &[usize], data: &[T]) -> Result<()> {
+        let dtype = self.data.dtype()?;
+        ensure!(dtype == T::DTYPE, "cannot append {} to a matrix of {}", T::DTYPE, dtype);
+        ensure!(
+            ncols == self.num_cols,
+            "cannot append a matrix with {} columns to a matrix with {} columns",
+            ncols, self.num_cols,
+        );
+        append_values(&self.data, ArrayView1::from(data))?;
+        write_indices(&self.indices, None, indices.iter().copied())?;
+        write_indices(&self.indptr, None, indptr[1..].iter().map(|x| x + self.nnz))?;
+        self.nnz += data.len();
+        self.num_rows += indptr.len() - 1;
+        self.group.write_array_attr("shape", &[self.num_rows, self.num_cols])
+    }
+}
+
+/// Remove the rows after the first `n` rows of an array appended by `append_by_chunk`.
+pub(crate) fn truncate_rows<B: Backend>(container: &DataContainer<B>, n: usize) -> Result<()> {
+    match container.encoding_type()? {
+        DataType::Array(_) => {
+            let dataset = container.as_dataset()?;
+            let mut shape = dataset.shape();
+            if shape[0] > n {
+                shape[0] = n;
+                dataset.reshape(&shape)?;
+            }
+        }
+        DataType::CsrMatrix(_) => {
+            let group = container.as_group()?;
+            let mut shape: Vec<usize> = group.read_shape_attr()?;
+            let indptr = group.open_dataset("indptr")?;
+            if indptr.shape()[0] > n + 1 {
+                let nnz = indptr.read_array_slice::<usize, _, Ix1>(&[SelectInfoElem::from(n)])?[0];
+                indptr.reshape(&(n + 1).into())?;
+                group.open_dataset("indices")?.reshape(&nnz.into())?;
+                group.open_dataset("data")?.reshape(&nnz.into())?;
+            }
+            shape[0] = shape[0].min(n);
+            group.write_array_attr("shape", shape.as_slice())?;
+        }
+        ty => bail!("cannot truncate the rows of {}", ty),
+    }
+    Ok(())
+}
+
+/// Overwrite the rows of an array starting at row `start` with `data`, which
+/// must have the data type of the stored array. The rows of a CSR matrix can
+/// only be replaced by rows with the same number of non-zero entries.
+pub(crate) fn write_rows<B: Backend>(container: &DataContainer<B>, start: usize, data: ArrayData) -> Result<()> {
+    macro_rules! dispatch {
+        ($data:expr, $ty:ident, $fun:ident) => {
+            match $data {
+                $ty::I8(x) => $fun(container, start, x),
+                $ty::I16(x) => $fun(container, start, x),
+                $ty::I32(x) => $fun(container, start, x),
+                $ty::I64(x) => $fun(container, start, x),
+                $ty::U8(x) => $fun(container, start, x),
+                $ty::U16(x) => $fun(container, start, x),
+                $ty::U32(x) => $fun(container, start, x),
+                $ty::U64(x) => $fun(container, start, x),
+                $ty::Usize(x) => $fun(container, start, x),
+                $ty::F16(x) => $fun(container, start, x),
+                $ty::F32(x) => $fun(container, start, x),
+                $ty::F64(x) => $fun(container, start, x),
+                $ty::Bool(x) => $fun(container, start, x),
+                $ty::String(x) => $fun(container, start, x),
+                #[allow(unreachable_patterns)]
+                _ => bail!("cannot overwrite the rows of a categorical array"),
+            }
+        };
+    }
+
+    match data {
+        ArrayData::Array(x) => dispatch!(x, DynArray, write_dense_rows),
+        ArrayData::CsrMatrix(x) => dispatch!(DynCsrNonCanonical::from(x), DynCsrNonCanonical, write_csr_rows),
+        ArrayData::CsrNonCanonical(x) => dispatch!(x, DynCsrNonCanonical, write_csr_rows),
+        ArrayData::CscMatrix(_) => bail!("cannot overwrite the rows of a CSC matrix"),
+        ArrayData::RaggedArray(_) => bail!("cannot overwrite the rows of a ragged array"),
+        ArrayData::RecordArray(_) => bail!("cannot overwrite the rows of a record array"),
+        ArrayData::DataFrame(_) => bail!("cannot overwrite the rows of a dataframe"),
+    }
+}
 
+fn write_dense_rows<B: Backend, T: BackendData>(container: &DataContainer<B>, start: usize, data: ArrayD<T>) -> Result<()> {
+    let mut select = SelectInfo::all(data.ndim());
+    select.0[0] = (start..start + data.shape()[0]).into();
+    container.as_dataset()?.write_array_slice(data.view(), select.as_ref())
+}
 
+fn write_csr_rows<B: Backend, T: BackendData>(
+    container: &DataContainer<B>,
+    start: usize,
+    data: CsrNonCanonical<T>,
+) -> Result<()> {
+    let group = container.as_group()?;
+    let end = start + data.nrows();
+    let indptr = group.open_dataset("indptr")?;
+    let offsets: Vec<usize> = indptr
+        .read_array_slice::<usize, _, Ix1>(&[SelectInfoElem::from(start..end + 1)])?
+        .to_vec();
+    let (offset, nnz) = (offsets[0], offsets[offsets.len() - 1] - offsets[0]);
+    ensure!(
+        nnz == data.nnz(),
+        "rows {}..{} have {} non-zero entries, but their replacement has {}",
+        start, end, nnz, data.nnz(),
+    );
+    let (row_offsets, indices, values) = data.csr_data();
+    write_values(&group.open_dataset("data")?, offset, ArrayView1::from(values))?;
+    write_indices(&group.open_dataset("indices")?, Some(offset), indices.iter().copied())?;
+    write_indices(&indptr, Some(start + 1), row_offsets[1..].iter().map(|x| x + offset))
+}
 
+/// Overwrite the columns `cidx` of a dense 2-D array with the columns of
+/// `data`, which must have the data type of the stored array.
+pub(crate) fn write_columns<B: Backend>(container: &DataContainer<B>, cidx: &[usize], data: DynArray) -> Result<()> {
+    let dataset = container.as_dataset()?;
+    let select = [SelectInfoElem::full(), SelectInfoElem::from(cidx.to_vec())];
+    match data {
+        DynArray::I8(x) => dataset.write_array_slice(x.view(), &select),
+        DynArray::I16(x) => dataset.write_array_slice(x.view(), &select),
+        DynArray::I32(x) => dataset.write_array_slice(x.view(), &select),
+        DynArray::I64(x) => dataset.write_array_slice(x.view(), &select),
+        DynArray::U8(x) => dataset.write_array_slice(x.view(), &select),
+        DynArray::U16(x) => dataset.write_array_slice(x.view(), &select),
+        DynArray::U32(x) => dataset.write_array_slice(x.view(), &select),
+        DynArray::U64(x) => dataset.write_array_slice(x.view(), &select),
+        DynArray::Usize(x) => dataset.write_array_slice(x.view(), &select),
+        DynArray::F16(x) => dataset.write_array_slice(x.view(), &select),
+        DynArray::F32(x) => dataset.write_array_slice(x.view(), &select),
+        DynArray::F64(x) => dataset.write_array_slice(x.view(), &select),
+        DynArray::ComplexF32(x) => dataset.write_array_slice(x.view(), &select),
+        DynArray::ComplexF64(x) => dataset.write_array_slice(x.view(), &select),
+        DynArray::Bool(x) => dataset.write_array_slice(x.view(), &select),
+        DynArray::String(x) => dataset.write_array_slice(x.view(), &select),
+        DynArray::Categorical(_) => bail!("cannot overwrite the columns of a categorical array"),
+    }
+}
 
+/// Append values to the end of a 1-D dataset.
+fn append_values<D: DatasetOp, T: BackendData>(dataset: &D, values: ArrayView1<T>) -> Result<()> {
+    let start = dataset.shape()[0];
+    if !values.is_empty() {
+        dataset.reshape(&(start + values.len()).into())?;
+    }
+    write_values(dataset, start, values)
+}
 
+/// Write values to a 1-D dataset, starting at position `start`.
+fn write_values<D: DatasetOp, T: BackendData>(dataset: &D, start: usize, values: ArrayView1<T>) -> Result<()> {
+    if !values.is_empty() {
+        dataset.write_array_slice(values, &[SelectInfoElem::from(start..start + values.len())])?;
+    }
+    Ok(())
+}
 
+/// Write integers to a 1-D dataset starting at position `start`, or append them
+/// if `start` is `None`, converting them to the integer type of the dataset.
+fn write_indices<D, I>(dataset: &D, start: Option<usize>, values: I) -> Result<()>
+where
+    D: DatasetOp,
+    I: Iterator<Item = usize>,
+{
+    macro_rules! write_as {
+        ($ty:ty) => {{
+            let values = values.map(<$ty>::try_from).collect::<Result<Vec<_>, _>>()?;
+            let values = ArrayView1::from(values.as_slice());
+            match start {
+                Some(i) => write_values(dataset, i, values),
+                None => append_values(dataset, values),
+            }
+        }};
+    }
+    match dataset.dtype()? {
+        ScalarType::I32 => write_as!(i32),
+        ScalarType::I64 => write_as!(i64),
+        ScalarType::U32 => write_as!(u32),
+        ScalarType::U64 => write_as!(u64),
+        ScalarType::Usize => write_as!(usize),
+        ty => bail!("cannot store indices as {}", ty),
+    }
+}
@@ -0,0 +1,203 @@
+use super::{ArrayData, DynArray, DynCscMatrix, DynCsrMatrix};
+use crate::backend::{BackendData, ScalarType};
+use crate::data::DynScalar;
+use crate::data::data_traits::{HasShape, WriteData};
+
+use anyhow::{anyhow, bail, ensure, Result};
+use itertools::Itertools;
+use nalgebra_sparse::csc::CscMatrix;
+use nalgebra_sparse::csr::CsrMatrix;
+use ndarray::{concatenate, Array2, ArrayD, Axis, Ix2};
+use num::ToPrimitive;
+
+/// Evaluate `$body` with `$x` bound to the matrix of every variant of a sparse
+/// matrix, returning a dense array.
+macro_rules! sparse_to_dense {
+    ($data:expr, $enum:ident, |$x:ident| $body:expr) => {
+        match $data {
+            $enum::I8($x) => $body.into(),
+            $enum::I16($x) => $body.into(),
+            $enum::I32($x) => $body.into(),
+            $enum::I64($x) => $body.into(),
+            $enum::U8($x) => $body.into(),
+            $enum::U16($x) => $body.into(),
+            $enum::U32($x) => $body.into(),
+            $enum::U64($x) => $body.into(),
+            $enum::Usize($x) => $body.into(),
+            $enum::F16($x) => $body.into(),
+            $enum::F32($x) => $body.into(),
+            $enum::F64($x) => $body.into(),
+            $enum::Bool($x) => $body.into(),
+            $enum::String($x) => $body.into(),
+        }
+    };
+}
+
+/// Concatenate dense arrays of the same variant along the second axis.
+macro_rules! hstack_dense {
+    ($arrays:expr, $($variant:ident),*) => {
+        match &$arrays[0] {
+            $(DynArray::$variant(_) => {
+                let views: Vec<_> = $arrays
+                    .iter()
+                    .map(|x| match x {
+                        DynArray::$variant(x) => x.view(),
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                concatenate(Axis(1), &views)?.into()
+            })*
+            DynArray::Categorical(_) => bail!("cannot stack categorical arrays horizontally"),
+        }
+    };
+}
+
+impl ArrayData {
+    /// Convert a sparse matrix to a dense array. Dense arrays are returned as is.
+    /// CSR matrices with duplicated entries cannot be converted.
+    pub fn to_dense(self) -> Result<Self> {
+        let data = match self {
+            ArrayData::Array(_) => self,
+            ArrayData::CsrMatrix(x) => sparse_to_dense!(x, DynCsrMatrix, |x| csr_to_dense(x)),
+            ArrayData::CscMatrix(x) => sparse_to_dense!(x, DynCscMatrix, |x| csc_to_dense(x)),
+            ArrayData::CsrNonCanonical(x) => match x.canonicalize() {
+                Ok(x) => ArrayData::CsrMatrix(x).to_dense()?,
+                Err(_) => bail!("cannot convert a CSR matrix with duplicated entries to a dense array"),
+            },
+            ArrayData::RaggedArray(_) => bail!("cannot convert a ragged array to a dense array"),
+            ArrayData::RecordArray(_) => bail!("cannot convert a record array to a dense array"),
+            ArrayData::DataFrame(_) => bail!("cannot convert a dataframe to a dense array"),
+        };
+        Ok(data)
+    }
+
+    /// Like `to_dense`, but the entries that are not stored in a sparse matrix
+    /// are set to `fill` instead of zero, e.g., a constant background. Numeric
+    /// fill values are cast to the data type of the matrix.
+    pub fn to_dense_with_fill(self, fill: DynScalar) -> Result<Self> {
+        let data = match self {
+            ArrayData::CsrMatrix(x) => {
+                sparse_to_dense!(x, DynCsrMatrix, |x| csr_to_dense_with_fill(x, fill.clone())?)
+            }
+            ArrayData::CscMatrix(x) => {
+                sparse_to_dense!(x, DynCscMatrix, |x| csc_to_dense_with_fill(x, fill.clone())?)
+            }
+            ArrayData::CsrNonCanonical(x) => match x.canonicalize() {
+                Ok(x) => ArrayData::CsrMatrix(x).to_dense_with_fill(fill)?,
+                Err(_) => bail!("cannot convert a CSR matrix with duplicated entries to a dense array"),
+            },
+            x => x.to_dense()?,
+        };
+        Ok(data)
+    }
+
+    /// Convert a two-dimensional dense array to a CSR matrix. Zeros and values
+    /// whose absolute value is below `threshold` are dropped. Sparse matrices
+    /// are returned as is.
+    pub fn to_sparse(self, threshold: f64) -> Result<Self> {
+        let data = match self {
+            ArrayData::Array(x) => match x {
+                DynArray::I8(x) => dense_to_csr(x, threshold)?.into(),
+                DynArray::I16(x) => dense_to_csr(x, threshold)?.into(),
+                DynArray::I32(x) => dense_to_csr(x, threshold)?.into(),
+                DynArray::I64(x) => dense_to_csr(x, threshold)?.into(),
+                DynArray::U8(x) => dense_to_csr(x, threshold)?.into(),
+                DynArray::U16(x) => dense_to_csr(x, threshold)?.into(),
+                DynArray::U32(x) => dense_to_csr(x, threshold)?.into(),
+                DynArray::U64(x) => dense_to_csr(x, threshold)?.into(),
+                DynArray::Usize(x) => dense_to_csr(x, threshold)?.into(),
+                DynArray::F16(x) => dense_to_csr(x, threshold)?.into(),
+                DynArray::F32(x) => dense_to_csr(x, threshold)?.into(),
+                DynArray::F64(x) => dense_to_csr(x, threshold)?.into(),
+                DynArray::Bool(x) => to_csr(x, |v| *v)?.into(),
+                DynArray::ComplexF32(_) | DynArray::ComplexF64(_) => {
+                    bail!("cannot convert an array of complex numbers to a sparse matrix")
+                }
+                DynArray::String(_) => bail!("cannot convert an array of strings to a sparse matrix"),
+                DynArray::Categorical(_) => bail!("cannot convert a categorical array to a sparse matrix"),
+            },
+            ArrayData::RaggedArray(_) => bail!("cannot convert a ragged array to a sparse matrix"),
+            ArrayData::RecordArray(_) => bail!("cannot convert a record array to a sparse matrix"),
+            ArrayData::DataFrame(_) => bail!("cannot convert a dataframe to a sparse matrix"),
+            x => x,
+        };
+        Ok(data)
+    }
+
+    /// Concatenate two-dimensional arrays along the second axis. Sparse
+    /// matrices are converted to dense arrays, and arrays of different types
+    /// are cast to `f64`.
+    pub fn hstack<I: IntoIterator<Item = Self>>(iter: I) -> Result<Self> {
+        let arrays = iter.into_iter().map(|x| x.to_dense()).collect::<Result<Vec<_>>>()?;
+        ensure!(!arrays.is_empty(), "cannot stack an empty list of arrays");
+        let same_type = arrays.iter().map(|x| x.data_type()).all_equal();
+        let arrays = arrays
+            .into_iter()
+            .map(|x| {
+                let x = if same_type { x } else { x.cast(ScalarType::F64)? };
+                match x {
+                    ArrayData::Array(x) if x.shape().ndim() == 2 => Ok(x),
+                    _ => bail!("only two-dimensional arrays can be stacked horizontally"),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let array: DynArray = hstack_dense!(
+            arrays, I8, I16, I32, I64, U8, U16, U32, U64, Usize, F16, F32, F64, ComplexF32, ComplexF64, Bool,
+            String
+        );
+        Ok(array.into())
+    }
+}
+
+fn csr_to_dense<T: Clone + Default>(csr: CsrMatrix<T>) -> ArrayD<T> {
+    let mut arr = Array2::from_elem((csr.nrows(), csr.ncols()), T::default());
+    csr.triplet_iter().for_each(|(i, j, v)| arr[[i, j]] = v.clone());
+    arr.into_dyn()
+}
+
+fn csc_to_dense<T: Clone + Default>(csc: CscMatrix<T>) -> ArrayD<T> {
+    let mut arr = Array2::from_elem((csc.nrows(), csc.ncols()), T::default());
+    csc.triplet_iter().for_each(|(i, j, v)| arr[[i, j]] = v.clone());
+    arr.into_dyn()
+}
+
+fn csr_to_dense_with_fill<T: BackendData>(csr: CsrMatrix<T>, fill: DynScalar) -> Result<ArrayD<T>> {
+    let mut arr = Array2::from_elem((csr.nrows(), csr.ncols()), T::from_dyn(fill.cast(T::DTYPE)?)?);
+    csr.triplet_iter().for_each(|(i, j, v)| arr[[i, j]] = v.clone());
+    Ok(arr.into_dyn())
+}
+
+fn csc_to_dense_with_fill<T: BackendData>(csc: CscMatrix<T>, fill: DynScalar) -> Result<ArrayD<T>> {
+    let mut arr = Array2::from_elem((csc.nrows(), csc.ncols()), T::from_dyn(fill.cast(T::DTYPE)?)?);
+    csc.triplet_iter().for_each(|(i, j, v)| arr[[i, j]] = v.clone());
+    Ok(arr.into_dyn())
+}
+
+fn dense_to_csr<T: ToPrimitive + Clone>(arr: ArrayD<T>, threshold: f64) -> Result<CsrMatrix<T>> {
+    to_csr(arr, |v| {
+        let v = v.to_f64().unwrap_or(f64::NAN);
+        v != 0.0 && !(v.abs() < threshold)
+    })
+}
+
+/// Build a CSR matrix from the entries of a two-dimensional array for which
+/// `keep` returns true.
+fn to_csr<T: Clone, F: Fn(&T) -> bool>(arr: ArrayD<T>, keep: F) -> Result<CsrMatrix<T>> {
+    ensure!(arr.ndim() == 2, "only two-dimensional arrays can be converted to sparse matrices");
+    let arr = arr.into_dimensionality::<Ix2>()?;
+    let (nrows, ncols) = arr.dim();
+    let mut indptr = Vec::with_capacity(nrows + 1);
+    let mut indices = Vec::new();
+    let mut values = Vec::new();
+    indptr.push(0);
+    for row in arr.rows() {
+        for (j, v) in row.iter().enumerate() {
+            if keep(v) {
+                indices.push(j);
+                values.push(v.clone());
+            }
+        }
+        indptr.push(indices.len());
+    }
+    CsrMatrix::try_from_csr_data(nrows, ncols, indptr, indices, values).map_err(|e| anyhow!("{}", e))
+}
@@ -1,6 +1,6 @@
 use std::ops::Deref;
 
-use crate::backend::{Backend, DataContainer, DatasetOp, GroupOp, LocationOp};
+use crate::backend::{Backend, DataContainer, DatasetOp, GroupOp, LocationOp, ScalarType};
 use crate::data::array::slice::{SelectInfoElem, Shape};
 use crate::data::array::{CategoricalArray, DynArray};
 use crate::data::data_traits::*;
@@ -8,14 +8,125 @@ use crate::data::index::{Index, Interval};
 use crate::data::scalar::DynScalar;
 
 use log::warn;
-use anyhow::{bail, Result};
-use ndarray::{Array1, Array2};
-use polars::datatypes::{CategoricalChunkedBuilder, DataType};
+use parking_lot::{const_rwlock, RwLock};
+use anyhow::{anyhow, bail, ensure, Result};
+use ndarray::{Array1, Array2, Ix1};
+use polars::datatypes::{ArrowDataType, CategoricalChunkedBuilder, DataType};
 use polars::prelude::IntoSeries;
-use polars::prelude::{DataFrame, Series};
+use polars::export::arrow::array::Utf8Array;
+use polars::prelude::{
+    BooleanChunked, CategoricalChunked, DataFrame, FillNullStrategy, NamedFrom,
+    ParquetReader, ParquetWriter, RevMapping, SerReader, Series, TimeUnit, UInt32Chunked, Utf8Chunked,
+};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::fs::File;
+use std::path::Path;
 
 use super::{BoundedSelectInfo, BoundedSelectInfoElem};
 
+/// Write a dataframe to a Parquet file. The index is stored as the first column,
+/// named after the index.
+pub fn write_parquet<P: AsRef<Path>>(df: &DataFrame, index: &DataFrameIndex, path: P) -> Result<()> {
+    let mut df = df.clone();
+    let index_col = Series::new(&index.index_name, index.clone().into_vec());
+    df.insert_at_idx(0, index_col)?;
+    ParquetWriter::new(File::create(path)?).finish(&mut df)?;
+    Ok(())
+}
+
+/// Read a dataframe from a Parquet file, using the first column as the index.
+pub fn read_parquet<P: AsRef<Path>>(path: P) -> Result<(DataFrame, DataFrameIndex)> {
+    let mut df = ParquetReader::new(File::open(path)?).finish()?;
+    let name = match df.get_column_names().first() {
+        Some(name) => name.to_string(),
+        None => bail!("cannot read the index from a Parquet file without columns"),
+    };
+    let column = df.drop_in_place(&name)?.cast(&DataType::Utf8)?;
+    let mut index: DataFrameIndex = column
+        .utf8()?
+        .into_iter()
+        .map(|x| x.unwrap_or_default().to_string())
+        .collect();
+    index.index_name = name;
+    Ok((df, index))
+}
+
+static FALLBACK_INDEX_NAMES: RwLock<Vec<String>> = const_rwlock(Vec::new());
+
+/// Set the names of the datasets tried, in order, as the index of a dataframe
+/// whose `_index` attribute is missing or refers to a dataset that does not
+/// exist, e.g., `["CellID"]` for files written by some tools. The datasets
+/// named `_index` and `index` are always tried first.
+pub fn set_fallback_index_names<I, S>(names: I)
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    *FALLBACK_INDEX_NAMES.write() = names.into_iter().map(Into::into).collect();
+}
+
+/// Name of the arrow extension type given to the categories of an ordered
+/// categorical series. Polars has no notion of ordered categories, so the flag
+/// is kept on the categories, which are shared by subsets of the series.
+const ORDERED_CATEGORIES: &str = "anndata.ordered_categories";
+
+/// Whether a series is an ordered categorical, see `set_categorical_ordered`.
+pub fn is_categorical_ordered(series: &Series) -> bool {
+    series.categorical().map_or(false, |ca| matches!(
+        ca.get_rev_map().get_categories().data_type(),
+        ArrowDataType::Extension(name, _, _) if name == ORDERED_CATEGORIES
+    ))
+}
+
+/// Mark a categorical series as ordered or unordered. The flag is written to
+/// the `ordered` attribute of the column and converted to and from pandas.
+pub fn set_categorical_ordered(series: &Series, ordered: bool) -> Result<Series> {
+    let mut out = with_ordered_flag(series.categorical()?, ordered).into_series();
+    out.rename(series.name());
+    Ok(out)
+}
+
+fn with_ordered_flag(ca: &CategoricalChunked, ordered: bool) -> CategoricalChunked {
+    let ca = ca.to_local();
+    let data_type = if ordered {
+        ArrowDataType::Extension(ORDERED_CATEGORIES.to_string(), Box::new(ArrowDataType::LargeUtf8), None)
+    } else {
+        ArrowDataType::LargeUtf8
+    };
+    let categories = ca.get_rev_map().get_categories().clone().to(data_type);
+    // Safety: the codes are unchanged and the categories are the same.
+    let mut out = unsafe {
+        CategoricalChunked::from_cats_and_rev_map_unchecked(
+            ca.logical().clone(),
+            Arc::new(RevMapping::Local(categories)),
+        )
+    };
+    out.set_lexical_ordering(ca.uses_lexical_ordering());
+    out
+}
+
+/// Return the name of the dataset storing the index of a dataframe. It is
+/// given by the `_index` attribute of the group, or, if that dataset does not
+/// exist, by the first existing fallback name. See `set_fallback_index_names`.
+pub(crate) fn index_name<G: GroupOp + LocationOp>(group: &G) -> Result<String> {
+    if let Ok(name) = group.read_str_attr("_index") {
+        if group.exists(&name)? {
+            return Ok(name);
+        }
+    }
+    let fallbacks = FALLBACK_INDEX_NAMES.read();
+    for name in ["_index", "index"].into_iter().chain(fallbacks.iter().map(String::as_str)) {
+        if group.exists(name)? {
+            return Ok(name.to_string());
+        }
+    }
+    bail!(
+        "cannot find the index of the dataframe, tried '_index', 'index' and {:?}",
+        *fallbacks,
+    )
+}
+
 impl WriteData for DataFrame {
     fn data_type(&self) -> crate::backend::DataType {
         crate::backend::DataType::DataFrame
@@ -49,7 +160,7 @@ impl WriteData for DataFrame {
     }
 
     fn overwrite<B: Backend>(&self, mut container: DataContainer<B>) -> Result<DataContainer<B>> {
-        if let Ok(index_name) = container.read_str_attr("_index") {
+        if let Ok(index_name) = index_name(container.as_group()?) {
             for obj in container.as_group()?.list()? {
                 if obj != index_name {
                     container.as_group()?.delete(&obj)?;
@@ -86,17 +197,39 @@ impl ReadData for DataFrame {
         let columns: Array1<String> = container.read_array_attr("column-order")?;
         columns
             .into_iter()
-            .map(|x| {
-                let name = x.as_str();
-                let series_container = DataContainer::<B>::open(container.as_group()?, name)?;
-                let mut series = Series::read::<B>(&series_container)?;
-                series.rename(name);
-                Ok(series)
-            })
+            .map(|x| read_column(container, &x)?.ok_or_else(|| anyhow!("column '{}' is missing", x)))
             .collect()
     }
 }
 
+/// Read a single column of a stored dataframe. Returns `None` if the column is
+/// not stored as a separate element of the dataframe group.
+pub fn read_column<B: Backend>(container: &DataContainer<B>, name: &str) -> Result<Option<Series>> {
+    let group = container.as_group()?;
+    if !group.exists(name)? {
+        return Ok(None);
+    }
+    let mut series = Series::read::<B>(&DataContainer::<B>::open(group, name)?)?;
+    series.rename(name);
+    Ok(Some(series))
+}
+
+/// Read the selected rows of a single column of a stored dataframe. Returns
+/// `None` if the column is not stored as a separate element.
+pub fn read_column_select<B, S>(container: &DataContainer<B>, name: &str, rows: S) -> Result<Option<Series>>
+where
+    B: Backend,
+    S: AsRef<SelectInfoElem>,
+{
+    let group = container.as_group()?;
+    if !group.exists(name)? {
+        return Ok(None);
+    }
+    let mut series = Series::read_select::<B, _>(&DataContainer::<B>::open(group, name)?, &[rows])?;
+    series.rename(name);
+    Ok(Some(series))
+}
+
 impl HasShape for DataFrame {
     fn shape(&self) -> Shape {
         self.shape().into()
@@ -136,7 +269,7 @@ impl ArrayOp for DataFrame {
 impl ReadArrayData for DataFrame {
     fn get_shape<B: Backend>(container: &DataContainer<B>) -> Result<Shape> {
         let group = container.as_group()?;
-        let index = group.read_str_attr("_index")?;
+        let index = index_name(group)?;
         let nrows = group.open_dataset(&index)?.shape()[0];
         let columns: Array1<String> = container.read_array_attr("column-order")?;
         Ok((nrows, columns.len()).into())
@@ -152,10 +285,7 @@ impl ReadArrayData for DataFrame {
             .iter()
             .map(|i| {
                 let name = &columns[i];
-                let mut series = container
-                    .as_group()?
-                    .open_dataset(name)
-                    .map(DataContainer::Dataset)
+                let mut series = DataContainer::<B>::open(container.as_group()?, name)
                     .and_then(|x| Series::read_select::<B, _>(&x, &info[..1]))?;
                 series.rename(name);
                 Ok(series)
@@ -175,7 +305,14 @@ impl WriteData for Series {
         location: &G,
         name: &str,
     ) -> Result<DataContainer<B>> {
-        let array: DynArray = match self.dtype() {
+        let dtype = self.dtype();
+        if matches!(dtype, DataType::Date | DataType::Datetime(..) | DataType::Duration(_)) {
+            return write_temporal(self, location, name);
+        }
+        if self.null_count() > 0 && (dtype.is_integer() || matches!(dtype, DataType::Boolean | DataType::Utf8)) {
+            return write_nullable(self, location, name);
+        }
+        let array: DynArray = match dtype {
             DataType::UInt8 => self
                 .u8()?
                 .into_iter()
@@ -235,14 +372,14 @@ impl WriteData for Series {
             DataType::Float32 => self
                 .f32()?
                 .into_iter()
-                .map(|x| x.unwrap())
+                .map(|x| x.unwrap_or(f32::NAN))
                 .collect::<Array1<_>>()
                 .into_dyn()
                 .into(),
             DataType::Float64 => self
                 .f64()?
                 .into_iter()
-                .map(|x| x.unwrap())
+                .map(|x| x.unwrap_or(f64::NAN))
                 .collect::<Array1<_>>()
                 .into_dyn()
                 .into(),
@@ -260,44 +397,200 @@ impl WriteData for Series {
                 .collect::<Array1<_>>()
                 .into_dyn()
                 .into(),
-            DataType::Categorical(_) => self
-                .categorical()?
-                .iter_str()
-                .map(|x| x.unwrap())
-                .collect::<CategoricalArray>()
-                .into(),
+            DataType::Categorical(_) => {
+                // Use the categories of the rev map so that their order and the
+                // unused categories are kept.
+                let ca = self.categorical()?.to_local();
+                let categories = ca
+                    .get_rev_map()
+                    .get_categories()
+                    .into_iter()
+                    .map(|x| x.unwrap_or_default().to_string())
+                    .collect();
+                let codes = ca
+                    .logical()
+                    .into_iter()
+                    .map(|x| x.ok_or_else(|| anyhow!("categorical column '{}' contains null values", self.name())))
+                    .collect::<Result<Array1<_>>>()?
+                    .into_dyn();
+                CategoricalArray { codes, categories, ordered: is_categorical_ordered(self) }.into()
+            }
             other => bail!("Unsupported series data type: {:?}", other),
         };
         array.write(location, name)
     }
 }
 
-impl ReadData for Series {
-    fn read<B: Backend>(container: &DataContainer<B>) -> Result<Self> {
-        match DynArray::read(container)? {
-            DynArray::I8(x) => Ok(x.iter().collect::<Series>()),
-            DynArray::I16(x) => Ok(x.iter().collect::<Series>()),
-            DynArray::I32(x) => Ok(x.iter().collect::<Series>()),
-            DynArray::I64(x) => Ok(x.iter().collect::<Series>()),
-            DynArray::U8(x) => Ok(x.iter().collect::<Series>()),
-            DynArray::U16(x) => Ok(x.iter().collect::<Series>()),
-            DynArray::U32(x) => Ok(x.iter().collect::<Series>()),
-            DynArray::U64(x) => Ok(x.iter().collect::<Series>()),
-            DynArray::Usize(x) => Ok(x.iter().map(|x| *x as u64).collect::<Series>()),
-            DynArray::F32(x) => Ok(x.iter().collect::<Series>()),
-            DynArray::F64(x) => Ok(x.iter().collect::<Series>()),
-            DynArray::Bool(x) => Ok(x.iter().collect::<Series>()),
-            DynArray::String(x) => Ok(x.iter().map(|x| x.as_str()).collect::<Series>()),
+impl TryFrom<DynArray> for Series {
+    type Error = anyhow::Error;
+
+    fn try_from(array: DynArray) -> Result<Self> {
+        let series = match array {
+            DynArray::I8(x) => x.iter().collect::<Series>(),
+            DynArray::I16(x) => x.iter().collect::<Series>(),
+            DynArray::I32(x) => x.iter().collect::<Series>(),
+            DynArray::I64(x) => x.iter().collect::<Series>(),
+            DynArray::U8(x) => x.iter().collect::<Series>(),
+            DynArray::U16(x) => x.iter().collect::<Series>(),
+            DynArray::U32(x) => x.iter().collect::<Series>(),
+            DynArray::U64(x) => x.iter().collect::<Series>(),
+            DynArray::Usize(x) => x.iter().map(|x| *x as u64).collect::<Series>(),
+            // Polars has no half-precision type.
+            DynArray::F16(x) => x.iter().map(|x| x.to_f32()).collect::<Series>(),
+            DynArray::F32(x) => x.iter().collect::<Series>(),
+            DynArray::F64(x) => x.iter().collect::<Series>(),
+            DynArray::ComplexF32(_) | DynArray::ComplexF64(_) => {
+                bail!("complex numbers cannot be stored in a dataframe")
+            }
+            DynArray::Bool(x) => x.iter().collect::<Series>(),
+            DynArray::String(x) => x.iter().map(|x| x.as_str()).collect::<Series>(),
             DynArray::Categorical(arr) => {
-                let mut builder = CategoricalChunkedBuilder::new("", arr.codes.len());
-                builder.drain_iter(
-                    arr.codes
-                        .into_iter()
-                        .map(|i| Some(arr.categories[i as usize].as_str())),
-                );
-                Ok(builder.finish().into_series())
+                let n = arr.categories.len() as u32;
+                let ordered = arr.ordered;
+                let ca = if arr.codes.iter().all(|x| *x < n) {
+                    let rev_map = RevMapping::Local(Utf8Array::from_iter_values(arr.categories.iter()));
+                    let codes = UInt32Chunked::from_vec("", arr.codes.iter().copied().collect());
+                    // Safety: all codes are in bounds of the categories.
+                    unsafe {
+                        CategoricalChunked::from_cats_and_rev_map_unchecked(codes, Arc::new(rev_map))
+                    }
+                } else {
+                    let mut builder = CategoricalChunkedBuilder::new("", arr.codes.len());
+                    builder.drain_iter(
+                        arr.codes
+                            .into_iter()
+                            .map(|i| arr.categories.get(i as usize).map(|x| x.as_str())),
+                    );
+                    builder.finish()
+                };
+                with_ordered_flag(&ca, ordered).into_series()
             }
+        };
+        Ok(series)
+    }
+}
+
+/// Write an integer, boolean or string series with missing values using the
+/// `nullable-integer`, `nullable-boolean` or `nullable-string-array` encoding:
+/// a group holding the values, where missing values are set to zero, false or
+/// the empty string, and a mask that is true for missing values.
+fn write_nullable<B: Backend, G: GroupOp<Backend = B>>(
+    series: &Series,
+    location: &G,
+    name: &str,
+) -> Result<DataContainer<B>> {
+    let group = location.create_group(name)?;
+    let encoding_type = match series.dtype() {
+        DataType::Boolean => "nullable-boolean",
+        DataType::Utf8 => "nullable-string-array",
+        _ => "nullable-integer",
+    };
+    group.write_str_attr("encoding-type", encoding_type)?;
+    group.write_str_attr("encoding-version", "0.1.0")?;
+    if series.dtype() == &DataType::Utf8 {
+        let values: Utf8Chunked = series.utf8()?.into_iter().map(|x| Some(x.unwrap_or(""))).collect();
+        values.into_series().write(&group, "values")?;
+    } else {
+        series.fill_null(FillNullStrategy::Zero)?.write(&group, "values")?;
+    }
+    let mask: Array1<bool> = series.is_null().into_iter().map(|x| x.unwrap_or(false)).collect();
+    mask.write(&group, "mask")?;
+    Ok(DataContainer::Group(group))
+}
+
+/// Write a `Date`, `Datetime` or `Duration` series as its underlying integers,
+/// i.e., days since the epoch for dates and counts of `unit` otherwise. The
+/// type is recorded in the `temporal-type`, `unit` and `timezone` attributes.
+fn write_temporal<B: Backend, G: GroupOp<Backend = B>>(
+    series: &Series,
+    location: &G,
+    name: &str,
+) -> Result<DataContainer<B>> {
+    let container = series.to_physical_repr().write(location, name)?;
+    let (ty, unit, timezone) = match series.dtype() {
+        DataType::Date => ("date", "d", None),
+        DataType::Datetime(unit, tz) => ("datetime", time_unit_str(*unit), tz.as_ref()),
+        DataType::Duration(unit) => ("duration", time_unit_str(*unit), None),
+        other => unreachable!("not a temporal type: {:?}", other),
+    };
+    container.write_str_attr("temporal-type", ty)?;
+    container.write_str_attr("unit", unit)?;
+    if let Some(tz) = timezone {
+        container.write_str_attr("timezone", tz)?;
+    }
+    Ok(container)
+}
+
+/// Convert the integers read from a column written by `write_temporal` back
+/// to the temporal type. Other columns are returned as is.
+fn read_temporal<B: Backend>(container: &DataContainer<B>, series: Series) -> Result<Series> {
+    let ty = match container.read_str_attr("temporal-type") {
+        Ok(ty) => ty,
+        Err(_) => return Ok(series),
+    };
+    let unit = || -> Result<TimeUnit> {
+        match container.read_str_attr("unit")?.as_str() {
+            "ns" => Ok(TimeUnit::Nanoseconds),
+            "us" => Ok(TimeUnit::Microseconds),
+            "ms" => Ok(TimeUnit::Milliseconds),
+            other => bail!("unknown time unit '{}'", other),
         }
+    };
+    let dtype = match ty.as_str() {
+        "date" => DataType::Date,
+        "datetime" => DataType::Datetime(unit()?, container.read_str_attr("timezone").ok()),
+        "duration" => DataType::Duration(unit()?),
+        other => bail!("unknown temporal type '{}'", other),
+    };
+    Ok(series.cast(&dtype)?)
+}
+
+fn time_unit_str(unit: TimeUnit) -> &'static str {
+    match unit {
+        TimeUnit::Nanoseconds => "ns",
+        TimeUnit::Microseconds => "us",
+        TimeUnit::Milliseconds => "ms",
+    }
+}
+
+fn read_nullable<B, S>(container: &DataContainer<B>, info: &[S]) -> Result<Series>
+where
+    B: Backend,
+    S: AsRef<SelectInfoElem>,
+{
+    let group = container.as_group()?;
+    let values: Series = DynArray::read_select(&DataContainer::open(group, "values")?, info)?.try_into()?;
+    let mask: Array1<bool> = group.open_dataset("mask")?.read_array_slice(info)?;
+    let valid: BooleanChunked = mask.iter().map(|x| !*x).collect();
+    let nulls = Series::full_null("", values.len(), values.dtype());
+    Ok(values.zip_with(&valid, &nulls)?)
+}
+
+/// The data type of a stored dataframe column, read from its metadata.
+pub fn column_type<B: Backend>(container: &DataContainer<B>, name: &str) -> Result<crate::backend::DataType> {
+    let column = DataContainer::<B>::open(container.as_group()?, name)?;
+    if is_nullable(&column) {
+        let dtype = column.as_group()?.open_dataset("values")?.dtype()?;
+        Ok(crate::backend::DataType::Array(dtype))
+    } else {
+        column.encoding_type()
+    }
+}
+
+pub(crate) fn is_nullable<B: Backend>(container: &DataContainer<B>) -> bool {
+    container.read_str_attr("encoding-type").map_or(false, |x| {
+        x == "nullable-integer" || x == "nullable-boolean" || x == "nullable-string-array"
+    })
+}
+
+impl ReadData for Series {
+    fn read<B: Backend>(container: &DataContainer<B>) -> Result<Self> {
+        let series = if is_nullable(container) {
+            read_nullable(container, &[SelectInfoElem::full()])?
+        } else {
+            DynArray::read(container)?.try_into()?
+        };
+        read_temporal(container, series)
     }
 }
 
@@ -327,7 +620,11 @@ impl ArrayOp for Series {
 
 impl ReadArrayData for Series {
     fn get_shape<B: Backend>(container: &DataContainer<B>) -> Result<Shape> {
-        Ok(container.as_dataset()?.shape().into())
+        if is_nullable(container) {
+            Ok(container.as_group()?.open_dataset("values")?.shape())
+        } else {
+            Ok(container.as_dataset()?.shape())
+        }
     }
 
     fn read_select<B, S>(container: &DataContainer<B>, info: &[S]) -> Result<Self>
@@ -335,7 +632,12 @@ impl ReadArrayData for Series {
         B: Backend,
         S: AsRef<SelectInfoElem>,
     {
-        Ok(Self::read(container)?.select(info))
+        let series = if is_nullable(container) {
+            read_nullable(container, info)?
+        } else {
+            DynArray::read_select(container, info)?.try_into()?
+        };
+        read_temporal(container, series)
     }
 }
 
@@ -343,11 +645,14 @@ impl ReadArrayData for Series {
 pub struct DataFrameIndex {
     pub index_name: String,
     index: Index,
+    /// The data type of the index values as stored on disk. Names are always
+    /// kept as strings in memory and converted back when the index is written.
+    dtype: ScalarType,
 }
 
 impl std::cmp::PartialEq for DataFrameIndex {
     fn eq(&self, other: &Self) -> bool {
-        self.index == other.index
+        self.index == other.index && self.dtype == other.dtype
     }
 }
 
@@ -356,9 +661,54 @@ impl DataFrameIndex {
         Self {
             index_name: "index".to_string(),
             index: Index::empty(),
+            dtype: ScalarType::String,
         }
     }
 
+    /// The data type of the index values, `ScalarType::String` unless the index
+    /// was created from or read as a non-string array.
+    pub fn dtype(&self) -> ScalarType {
+        self.dtype
+    }
+
+    /// Return the index values converted back to their original data type.
+    pub fn to_array(&self) -> Result<DynArray> {
+        macro_rules! parse {
+            ($variant:ident, $ty:ty) => {
+                DynArray::$variant(
+                    self.index
+                        .clone()
+                        .into_iter()
+                        .map(|x| {
+                            x.parse::<$ty>()
+                                .map_err(|e| anyhow!("cannot parse '{}' as {}: {}", x, self.dtype, e))
+                        })
+                        .collect::<Result<Array1<_>>>()?
+                        .into_dyn(),
+                )
+            };
+        }
+        let array = match self.dtype {
+            ScalarType::I8 => parse!(I8, i8),
+            ScalarType::I16 => parse!(I16, i16),
+            ScalarType::I32 => parse!(I32, i32),
+            ScalarType::I64 => parse!(I64, i64),
+            ScalarType::U8 => parse!(U8, u8),
+            ScalarType::U16 => parse!(U16, u16),
+            ScalarType::U32 => parse!(U32, u32),
+            ScalarType::U64 => parse!(U64, u64),
+            ScalarType::Usize => parse!(Usize, usize),
+            ScalarType::F16 => parse!(F16, half::f16),
+            ScalarType::F32 => parse!(F32, f32),
+            ScalarType::F64 => parse!(F64, f64),
+            ScalarType::ComplexF32 => parse!(ComplexF32, num::complex::Complex32),
+            ScalarType::ComplexF64 => parse!(ComplexF64, num::complex::Complex64),
+            ScalarType::Bool => parse!(Bool, bool),
+            ScalarType::String => parse!(String, String),
+        };
+        Ok(array)
+    }
+
     pub fn is_empty(&self) -> bool {
         self.index.is_empty()
     }
@@ -371,6 +721,43 @@ impl DataFrameIndex {
         self.index.get_index(k)
     }
 
+    /// Whether all names in the index are distinct.
+    pub fn is_unique(&self) -> bool {
+        self.index.is_unique()
+    }
+
+    /// Make the names unique by appending `-1`, `-2`, etc. to duplicated names,
+    /// as `anndata.utils.make_index_unique` does. The first occurrence of a name
+    /// is kept as is, and suffixes that would clash with existing names are skipped.
+    pub fn make_unique(self) -> Self {
+        if self.is_unique() {
+            return self;
+        }
+        let index_name = self.index_name.clone();
+        let names = self.into_vec();
+        let mut existing: HashSet<String> = names.iter().cloned().collect();
+        let mut seen = HashSet::new();
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        let mut index: DataFrameIndex = names
+            .into_iter()
+            .map(|name| {
+                if seen.insert(name.clone()) {
+                    return name;
+                }
+                let count = counts.entry(name.clone()).or_insert(0);
+                loop {
+                    *count += 1;
+                    let new_name = format!("{}-{}", name, count);
+                    if existing.insert(new_name.clone()) {
+                        return new_name;
+                    }
+                }
+            })
+            .collect();
+        index.index_name = index_name;
+        index
+    }
+
     pub fn into_vec(self) -> Vec<String> {
         self.index.into_vec()
     }
@@ -380,7 +767,49 @@ impl DataFrameIndex {
         Self {
             index_name: self.index_name.clone(),
             index,
+            dtype: self.dtype,
+        }
+    }
+}
+
+/// Build an index from a one-dimensional array. Non-string values are stored
+/// as strings, and the original data type is kept so the index can be written
+/// back with the same type.
+impl TryFrom<DynArray> for DataFrameIndex {
+    type Error = anyhow::Error;
+
+    fn try_from(array: DynArray) -> Result<Self> {
+        macro_rules! from_array {
+            ($arr:expr, $dtype:ident) => {{
+                ensure!($arr.ndim() == 1, "index must be one-dimensional, found {} dimensions", $arr.ndim());
+                let mut index: DataFrameIndex = $arr.iter().map(|x| x.to_string()).collect();
+                index.dtype = ScalarType::$dtype;
+                index
+            }};
         }
+        let index = match array {
+            DynArray::I8(x) => from_array!(x, I8),
+            DynArray::I16(x) => from_array!(x, I16),
+            DynArray::I32(x) => from_array!(x, I32),
+            DynArray::I64(x) => from_array!(x, I64),
+            DynArray::U8(x) => from_array!(x, U8),
+            DynArray::U16(x) => from_array!(x, U16),
+            DynArray::U32(x) => from_array!(x, U32),
+            DynArray::U64(x) => from_array!(x, U64),
+            DynArray::Usize(x) => from_array!(x, Usize),
+            DynArray::F16(x) => from_array!(x, F16),
+            DynArray::F32(x) => from_array!(x, F32),
+            DynArray::F64(x) => from_array!(x, F64),
+            DynArray::ComplexF32(x) => from_array!(x, ComplexF32),
+            DynArray::ComplexF64(x) => from_array!(x, ComplexF64),
+            DynArray::Bool(x) => from_array!(x, Bool),
+            DynArray::String(x) => from_array!(x, String),
+            DynArray::Categorical(x) => {
+                ensure!(x.codes.ndim() == 1, "index must be one-dimensional, found {} dimensions", x.codes.ndim());
+                x.codes.iter().map(|i| x.categories[*i as usize].clone()).collect()
+            }
+        };
+        Ok(index)
     }
 }
 
@@ -411,13 +840,24 @@ impl WriteData for DataFrameIndex {
     }
 
     fn overwrite<B: Backend>(&self, container: DataContainer<B>) -> Result<DataContainer<B>> {
-        if let Ok(index_name) = container.read_str_attr("_index") {
+        if let Ok(index_name) = index_name(container.as_group()?) {
             container.as_group()?.delete(&index_name)?;
         }
         container.write_str_attr("_index", &self.index_name)?;
         let group = container.as_group()?;
-        let arr: Array1<String> = self.clone().into_iter().collect();
-        let data = group.create_array_data(&self.index_name, &arr, Default::default())?;
+        let data = match &self.index {
+            Index::List(_) if self.dtype != ScalarType::String => {
+                let data = self.to_array()?.write(group, &self.index_name)?;
+                match data {
+                    DataContainer::Dataset(data) => data,
+                    DataContainer::Group(_) => bail!("index must be stored as a dataset"),
+                }
+            },
+            _ => {
+                let arr: Array1<String> = self.clone().into_iter().collect();
+                group.create_array_data(&self.index_name, &arr, Default::default())?
+            },
+        };
         match &self.index {
             Index::List(_) => { data.write_str_attr("index_type", "list")?; },
             Index::Intervals(intervals) => {
@@ -443,12 +883,14 @@ impl WriteData for DataFrameIndex {
 
 impl ReadData for DataFrameIndex {
     fn read<B: Backend>(container: &DataContainer<B>) -> Result<Self> {
-        let index_name = container.read_str_attr("_index")?;
+        let index_name = index_name(container.as_group()?)?;
         let dataset = container.as_group()?.open_dataset(&index_name)?;
         match dataset.read_str_attr("index_type").as_ref().map_or("list", |x| x.as_str()) {
             "list" => {
-                let data = dataset.read_array()?;
-                let mut index: DataFrameIndex = data.to_vec().into();
+                let mut index: DataFrameIndex = match dataset.dtype()? {
+                    ScalarType::String => dataset.read_array::<String, Ix1>()?.to_vec().into(),
+                    _ => DynArray::read(&DataContainer::<B>::Dataset(dataset))?.try_into()?,
+                };
                 index.index_name = index_name;
                 Ok(index)
             },
@@ -477,6 +919,7 @@ where
         Self {
             index_name: "index".to_owned(),
             index: data.into(),
+            dtype: ScalarType::String,
         }
     }
 }
@@ -492,6 +935,7 @@ where
         Self {
             index_name: "index".to_owned(),
             index: iter.into_iter().collect(),
+            dtype: ScalarType::String,
         }
     }
 }
\ No newline at end of file
@@ -3,11 +3,13 @@ use crate::{
     data::{
         data_traits::*,
         scalar::DynScalar,
-        slice::{Shape, SelectInfoElem, BoundedSelectInfoElem},
+        slice::{Shape, SelectInfoElem, BoundedSelectInfoElem, BoundedSlice},
     },
 };
 
 use anyhow::{bail, ensure, anyhow, Result};
+use half::f16;
+use num::complex::{Complex32, Complex64};
 use ndarray::{ArrayView, Array, Array1, ArrayD, RemoveAxis, SliceInfoElem, Dimension, Axis};
 use std::collections::HashMap;
 use std::ops::Index;
@@ -24,8 +26,11 @@ pub enum DynArray {
     U32(ArrayD<u32>),
     U64(ArrayD<u64>),
     Usize(ArrayD<usize>),
+    F16(ArrayD<f16>),
     F32(ArrayD<f32>),
     F64(ArrayD<f64>),
+    ComplexF32(ArrayD<Complex32>),
+    ComplexF64(ArrayD<Complex64>),
     Bool(ArrayD<bool>),
     String(ArrayD<String>),
     Categorical(CategoricalArray),
@@ -83,8 +88,11 @@ impl_dyn_array_convert!(u16, U16);
 impl_dyn_array_convert!(u32, U32);
 impl_dyn_array_convert!(u64, U64);
 impl_dyn_array_convert!(usize, Usize);
+impl_dyn_array_convert!(f16, F16);
 impl_dyn_array_convert!(f32, F32);
 impl_dyn_array_convert!(f64, F64);
+impl_dyn_array_convert!(Complex32, ComplexF32);
+impl_dyn_array_convert!(Complex64, ComplexF64);
 impl_dyn_array_convert!(bool, Bool);
 impl_dyn_array_convert!(String, String);
 
@@ -100,8 +108,11 @@ impl WriteData for DynArray {
             Self::U32(arr) => arr.data_type(),
             Self::U64(arr) => arr.data_type(),
             Self::Usize(arr) => arr.data_type(),
+            Self::F16(arr) => arr.data_type(),
             Self::F32(arr) => arr.data_type(),
             Self::F64(arr) => arr.data_type(),
+            Self::ComplexF32(arr) => arr.data_type(),
+            Self::ComplexF64(arr) => arr.data_type(),
             Self::Bool(arr) => arr.data_type(),
             Self::String(arr) => arr.data_type(),
             Self::Categorical(arr) => arr.data_type(),
@@ -122,13 +133,42 @@ impl WriteData for DynArray {
             Self::U32(array) => array.write(location, name),
             Self::U64(array) => array.write(location, name),
             Self::Usize(array) => array.write(location, name),
+            Self::F16(array) => array.write(location, name),
             Self::F32(array) => array.write(location, name),
             Self::F64(array) => array.write(location, name),
+            Self::ComplexF32(array) => array.write(location, name),
+            Self::ComplexF64(array) => array.write(location, name),
             Self::Bool(array) => array.write(location, name),
             Self::String(array) => array.write(location, name),
             Self::Categorical(array) => array.write(location, name),
         }
     }
+    fn write_with_config<B: Backend, G: GroupOp<Backend = B>>(
+        &self,
+        location: &G,
+        name: &str,
+        config: WriteConfig,
+    ) -> Result<DataContainer<B>> {
+        match self {
+            Self::I8(array) => array.write_with_config(location, name, config),
+            Self::I16(array) => array.write_with_config(location, name, config),
+            Self::I32(array) => array.write_with_config(location, name, config),
+            Self::I64(array) => array.write_with_config(location, name, config),
+            Self::U8(array) => array.write_with_config(location, name, config),
+            Self::U16(array) => array.write_with_config(location, name, config),
+            Self::U32(array) => array.write_with_config(location, name, config),
+            Self::U64(array) => array.write_with_config(location, name, config),
+            Self::Usize(array) => array.write_with_config(location, name, config),
+            Self::F16(array) => array.write_with_config(location, name, config),
+            Self::F32(array) => array.write_with_config(location, name, config),
+            Self::F64(array) => array.write_with_config(location, name, config),
+            Self::ComplexF32(array) => array.write_with_config(location, name, config),
+            Self::ComplexF64(array) => array.write_with_config(location, name, config),
+            Self::Bool(array) => array.write_with_config(location, name, config),
+            Self::String(array) => array.write_with_config(location, name, config),
+            Self::Categorical(array) => array.write(location, name),
+        }
+    }
 }
 
 impl ReadData for DynArray {
@@ -144,8 +184,11 @@ impl ReadData for DynArray {
                 ScalarType::U32 => Ok(Self::U32(dataset.read_array()?)),
                 ScalarType::U64 => Ok(Self::U64(dataset.read_array()?)),
                 ScalarType::Usize => Ok(Self::Usize(dataset.read_array()?)),
+                ScalarType::F16 => Ok(Self::F16(dataset.read_array()?)),
                 ScalarType::F32 => Ok(Self::F32(dataset.read_array()?)),
                 ScalarType::F64 => Ok(Self::F64(dataset.read_array()?)),
+                ScalarType::ComplexF32 => Ok(Self::ComplexF32(dataset.read_array()?)),
+                ScalarType::ComplexF64 => Ok(Self::ComplexF64(dataset.read_array()?)),
                 ScalarType::Bool => Ok(Self::Bool(dataset.read_array()?)),
                 ScalarType::String => Ok(Self::String(dataset.read_array()?)),
             },
@@ -167,8 +210,11 @@ impl HasShape for DynArray {
             DynArray::U32(array) => array.shape().to_vec(),
             DynArray::U64(array) => array.shape().to_vec(),
             DynArray::Usize(array) => array.shape().to_vec(),
+            DynArray::F16(array) => array.shape().to_vec(),
             DynArray::F32(array) => array.shape().to_vec(),
             DynArray::F64(array) => array.shape().to_vec(),
+            DynArray::ComplexF32(array) => array.shape().to_vec(),
+            DynArray::ComplexF64(array) => array.shape().to_vec(),
             DynArray::Bool(array) => array.shape().to_vec(),
             DynArray::String(array) => array.shape().to_vec(),
             DynArray::Categorical(array) => array.codes.shape().to_vec(),
@@ -189,8 +235,11 @@ impl ArrayOp for DynArray {
             DynArray::U32(array) => array.get(index).map(|x| (*x).into()),
             DynArray::U64(array) => array.get(index).map(|x| (*x).into()),
             DynArray::Usize(array) => array.get(index).map(|x| (*x).into()),
+            DynArray::F16(array) => array.get(index).map(|x| (*x).into()),
             DynArray::F32(array) => array.get(index).map(|x| (*x).into()),
             DynArray::F64(array) => array.get(index).map(|x| (*x).into()),
+            DynArray::ComplexF32(array) => array.get(index).map(|x| (*x).into()),
+            DynArray::ComplexF64(array) => array.get(index).map(|x| (*x).into()),
             DynArray::Bool(array) => array.get(index).map(|x| (*x).into()),
             DynArray::String(array) => array.get(index).map(|x| x.clone().into()),
             DynArray::Categorical(array) => array
@@ -214,13 +263,17 @@ impl ArrayOp for DynArray {
             DynArray::U32(array) => ArrayOp::select(array, info).into(),
             DynArray::U64(array) => ArrayOp::select(array, info).into(),
             DynArray::Usize(array) => ArrayOp::select(array, info).into(),
+            DynArray::F16(array) => ArrayOp::select(array, info).into(),
             DynArray::F32(array) => ArrayOp::select(array, info).into(),
             DynArray::F64(array) => ArrayOp::select(array, info).into(),
+            DynArray::ComplexF32(array) => ArrayOp::select(array, info).into(),
+            DynArray::ComplexF64(array) => ArrayOp::select(array, info).into(),
             DynArray::Bool(array) => ArrayOp::select(array, info).into(),
             DynArray::String(array) => ArrayOp::select(array, info).into(),
             DynArray::Categorical(array) => CategoricalArray {
                 codes: ArrayOp::select(&array.codes, info),
                 categories: array.categories.clone(),
+                ordered: array.ordered,
             }
             .into(),
         }
@@ -238,8 +291,11 @@ impl ArrayOp for DynArray {
             DynArray::I16(_) => ArrayD::<i16>::vstack(iter.map(|x| x.try_into().unwrap())).map(|x| x.into()),
             DynArray::I32(_) => ArrayD::<i32>::vstack(iter.map(|x| x.try_into().unwrap())).map(|x| x.into()),
             DynArray::I64(_) => ArrayD::<i64>::vstack(iter.map(|x| x.try_into().unwrap())).map(|x| x.into()),
+            DynArray::F16(_) => ArrayD::<f16>::vstack(iter.map(|x| x.try_into().unwrap())).map(|x| x.into()),
             DynArray::F32(_) => ArrayD::<f32>::vstack(iter.map(|x| x.try_into().unwrap())).map(|x| x.into()),
             DynArray::F64(_) => ArrayD::<f64>::vstack(iter.map(|x| x.try_into().unwrap())).map(|x| x.into()),
+            DynArray::ComplexF32(_) => ArrayD::<Complex32>::vstack(iter.map(|x| x.try_into().unwrap())).map(|x| x.into()),
+            DynArray::ComplexF64(_) => ArrayD::<Complex64>::vstack(iter.map(|x| x.try_into().unwrap())).map(|x| x.into()),
             DynArray::Bool(_) => ArrayD::<bool>::vstack(iter.map(|x| x.try_into().unwrap())).map(|x| x.into()),
             DynArray::String(_) => ArrayD::<String>::vstack(iter.map(|x| x.try_into().unwrap())).map(|x| x.into()),
             DynArray::Categorical(_) => todo!(),
@@ -269,8 +325,11 @@ impl ReadArrayData for DynArray {
                 ScalarType::U32 => Ok(Self::U32(dataset.read_array_slice(info)?)),
                 ScalarType::U64 => Ok(Self::U64(dataset.read_array_slice(info)?)),
                 ScalarType::Usize => Ok(Self::Usize(dataset.read_array_slice(info)?)),
+                ScalarType::F16 => Ok(Self::F16(dataset.read_array_slice(info)?)),
                 ScalarType::F32 => Ok(Self::F32(dataset.read_array_slice(info)?)),
                 ScalarType::F64 => Ok(Self::F64(dataset.read_array_slice(info)?)),
+                ScalarType::ComplexF32 => Ok(Self::ComplexF32(dataset.read_array_slice(info)?)),
+                ScalarType::ComplexF64 => Ok(Self::ComplexF64(dataset.read_array_slice(info)?)),
                 ScalarType::Bool => Ok(Self::Bool(dataset.read_array_slice(info)?)),
                 ScalarType::String => Ok(Self::String(dataset.read_array_slice(info)?)),
             },
@@ -290,7 +349,15 @@ impl<'a, T: BackendData, D: RemoveAxis> WriteData for ArrayView<'a, T, D> {
         location: &G,
         name: &str,
     ) -> Result<DataContainer<B>> {
-        let dataset = location.create_array_data(name, self, Default::default())?;
+        self.write_with_config(location, name, Default::default())
+    }
+    fn write_with_config<B: Backend, G: GroupOp<Backend = B>>(
+        &self,
+        location: &G,
+        name: &str,
+        config: WriteConfig,
+    ) -> Result<DataContainer<B>> {
+        let dataset = location.create_array_data(name, self, config)?;
         let encoding_type = if T::DTYPE == ScalarType::String {
             "string-array"
         } else {
@@ -314,6 +381,58 @@ impl<T: BackendData, D: RemoveAxis> WriteData for Array<T, D> {
     ) -> Result<DataContainer<B>> {
         self.view().write(location, name)
     }
+    fn write_with_config<B: Backend, G: GroupOp<Backend = B>>(
+        &self,
+        location: &G,
+        name: &str,
+        config: WriteConfig,
+    ) -> Result<DataContainer<B>> {
+        self.view().write_with_config(location, name, config)
+    }
+}
+
+/// Create a dense array of the given shape without writing any data. All
+/// elements read as `fill`, whose type is the data type of the array, until
+/// they are written. The dataset is chunked, and chunks that are never
+/// written take up no space.
+pub(crate) fn create_filled_array<B: Backend, G: GroupOp<Backend = B>>(
+    location: &G,
+    name: &str,
+    shape: &Shape,
+    fill: DynScalar,
+) -> Result<DataContainer<B>> {
+    macro_rules! create {
+        ($ty:ty) => {{
+            let config = WriteConfig {
+                block_size: Some(default_block_size(shape.as_ref(), std::mem::size_of::<$ty>())),
+                fill_value: Some(fill.clone()),
+                ..Default::default()
+            };
+            location.new_dataset::<$ty>(name, shape, config)?
+        }};
+    }
+    let dataset = match fill {
+        DynScalar::I8(_) => create!(i8),
+        DynScalar::I16(_) => create!(i16),
+        DynScalar::I32(_) => create!(i32),
+        DynScalar::I64(_) => create!(i64),
+        DynScalar::U8(_) => create!(u8),
+        DynScalar::U16(_) => create!(u16),
+        DynScalar::U32(_) => create!(u32),
+        DynScalar::U64(_) => create!(u64),
+        DynScalar::Usize(_) => create!(usize),
+        DynScalar::F16(_) => create!(f16),
+        DynScalar::F32(_) => create!(f32),
+        DynScalar::F64(_) => create!(f64),
+        DynScalar::ComplexF32(_) => create!(Complex32),
+        DynScalar::ComplexF64(_) => create!(Complex64),
+        DynScalar::Bool(_) => create!(bool),
+        DynScalar::String(_) => bail!("cannot create an empty array of strings"),
+    };
+    let container = DataContainer::<B>::Dataset(dataset);
+    container.write_str_attr("encoding-type", "array")?;
+    container.write_str_attr("encoding-version", "0.2.0")?;
+    Ok(container)
 }
 
 impl<T: BackendData, D: RemoveAxis> HasShape for Array<T, D> {
@@ -338,10 +457,10 @@ impl<T: BackendData, D: RemoveAxis> ArrayOp for Array<T, D> {
         S: AsRef<SelectInfoElem>,
     {
         let arr = self.view().into_dyn();
-        let slices = info.as_ref().into_iter().map(|x| match x.as_ref() {
-            SelectInfoElem::Slice(slice) => Some(SliceInfoElem::from(slice.clone())),
+        let slices = info.as_ref().into_iter().zip(self.shape()).map(|(x, n)| match x.as_ref() {
+            SelectInfoElem::Slice(slice) => Some(BoundedSlice::new(slice, *n).into()),
             _ => None,
-        }).collect::<Option<Vec<_>>>();
+        }).collect::<Option<Vec<SliceInfoElem>>>();
         if let Some(slices) = slices {
             arr.slice(slices.as_slice()).into_owned()
         } else {
@@ -393,6 +512,8 @@ impl<'a, T: BackendData, D: RemoveAxis> WriteArrayData for ArrayView<'a, T, D> {
 pub struct CategoricalArray {
     pub codes: ArrayD<u32>,
     pub categories: Array1<String>,
+    /// Whether the order of the categories is meaningful.
+    pub ordered: bool,
 }
 
 impl<'a> FromIterator<&'a str> for CategoricalArray {
@@ -422,6 +543,7 @@ impl<'a> FromIterator<&'a str> for CategoricalArray {
         CategoricalArray {
             codes: codes.into_dyn(),
             categories: categories.into_iter().map(|x| x.0).collect(),
+            ordered: false,
         }
     }
 }
@@ -438,7 +560,7 @@ impl WriteData for CategoricalArray {
         let group = location.create_group(name)?;
         group.write_str_attr("encoding-type", "categorical")?;
         group.write_str_attr("encoding-version", "0.2.0")?;
-        group.write_scalar_attr("ordered", false)?;
+        group.write_scalar_attr("ordered", self.ordered)?;
 
         group.create_array_data("codes", &self.codes, Default::default())?;
         group.create_array_data("categories", &self.categories, Default::default())?;
@@ -462,7 +584,8 @@ impl ReadData for CategoricalArray {
         let categories = group
             .open_dataset("categories")?
             .read_array()?;
-        Ok(CategoricalArray { codes, categories })
+        let ordered = read_ordered(group)?;
+        Ok(CategoricalArray { codes, categories, ordered })
     }
 }
 
@@ -483,6 +606,15 @@ impl ReadArrayData for CategoricalArray {
         let categories = group
             .open_dataset("categories")?
             .read_array()?;
-        Ok(CategoricalArray { codes, categories })
+        let ordered = read_ordered(group)?;
+        Ok(CategoricalArray { codes, categories, ordered })
+    }
+}
+
+/// Read the "ordered" attribute, which is missing in files written by old versions.
+fn read_ordered<G: LocationOp>(group: &G) -> Result<bool> {
+    match group.read_scalar_attr("ordered") {
+        Ok(ordered) => Ok(ordered),
+        Err(_) => Ok(false),
     }
 }
\ No newline at end of file
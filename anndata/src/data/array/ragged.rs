@@ -0,0 +1,282 @@
+use crate::backend::*;
+use crate::data::{
+    data_traits::*,
+    scalar::DynScalar,
+    slice::{SelectInfoElem, Shape},
+    BoundedSelectInfo, BoundedSelectInfoElem, DynArray,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use ndarray::{ArrayD, Ix1};
+
+/// A one-dimensional array of variable-length rows, e.g., a list of spatial
+/// neighbors for each cell. The rows are stored as a flat array of values and
+/// `len + 1` offsets, so that row `i` is `values[offsets[i]..offsets[i + 1]]`.
+///
+/// On disk, ragged arrays are stored using the `awkward-array` encoding of
+/// the Python anndata package, as a list of numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RaggedArray {
+    values: DynArray,
+    offsets: Vec<usize>,
+}
+
+impl RaggedArray {
+    /// Create a ragged array from the flat values and the row offsets. The
+    /// values must be a one-dimensional array of numbers or booleans, and the
+    /// offsets must start at 0, be non-decreasing and end at the number of values.
+    pub fn new(values: DynArray, offsets: Vec<usize>) -> Result<Self> {
+        ensure!(
+            values.shape().ndim() == 1,
+            "the values of a ragged array must be one-dimensional, found shape {}",
+            values.shape(),
+        );
+        match values {
+            DynArray::String(_) | DynArray::Categorical(_) => {
+                bail!("the values of a ragged array must be numbers or booleans")
+            }
+            _ => {}
+        }
+        ensure!(offsets.first() == Some(&0), "the offsets of a ragged array must start at 0");
+        ensure!(
+            offsets.windows(2).all(|w| w[0] <= w[1]),
+            "the offsets of a ragged array must be non-decreasing"
+        );
+        ensure!(
+            offsets[offsets.len() - 1] == values.shape()[0],
+            "the last offset ({}) does not match the number of values ({})",
+            offsets[offsets.len() - 1],
+            values.shape()[0],
+        );
+        Ok(Self { values, offsets })
+    }
+
+    /// Create a ragged array from its rows.
+    pub fn from_rows<T, I>(rows: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = Vec<T>>,
+        ArrayD<T>: Into<DynArray>,
+    {
+        let mut values = Vec::new();
+        let mut offsets = vec![0];
+        for row in rows {
+            values.extend(row);
+            offsets.push(values.len());
+        }
+        let values = ArrayD::from_shape_vec(vec![values.len()], values)?;
+        Self::new(values.into(), offsets)
+    }
+
+    /// The number of rows.
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn dtype(&self) -> ScalarType {
+        match self.values.data_type() {
+            DataType::Array(ty) => ty,
+            ty => unreachable!("invalid ragged array values: {}", ty),
+        }
+    }
+
+    /// The flat array of values.
+    pub fn values(&self) -> &DynArray {
+        &self.values
+    }
+
+    pub fn offsets(&self) -> &[usize] {
+        &self.offsets
+    }
+
+    /// The values of row `i`.
+    pub fn row(&self, i: usize) -> DynArray {
+        self.values.select(&[SelectInfoElem::from(self.offsets[i]..self.offsets[i + 1])])
+    }
+
+    pub fn into_parts(self) -> (DynArray, Vec<usize>) {
+        (self.values, self.offsets)
+    }
+
+    /// Select rows, remapping the offsets.
+    fn select_rows(&self, rows: &BoundedSelectInfoElem) -> Self {
+        let mut indices = Vec::new();
+        let mut offsets = Vec::with_capacity(rows.len() + 1);
+        offsets.push(0);
+        rows.iter().for_each(|i| {
+            indices.extend(self.offsets[i]..self.offsets[i + 1]);
+            offsets.push(indices.len());
+        });
+        Self {
+            values: self.values.select(&[SelectInfoElem::from(indices)]),
+            offsets,
+        }
+    }
+}
+
+impl WriteData for RaggedArray {
+    fn data_type(&self) -> DataType {
+        DataType::RaggedArray(self.dtype())
+    }
+
+    fn write<B: Backend, G: GroupOp<Backend = B>>(
+        &self,
+        location: &G,
+        name: &str,
+    ) -> Result<DataContainer<B>> {
+        let group = location.create_group(name)?;
+        group.write_str_attr("encoding-type", "awkward-array")?;
+        group.write_str_attr("encoding-version", "0.1.0")?;
+        group.write_str_attr("form", &awkward_form(self.dtype()))?;
+        group.write_scalar_attr("length", self.len() as i64)?;
+        let offsets: Vec<i64> = self.offsets.iter().map(|x| *x as i64).collect();
+        group.create_array_data("node0-offsets", &offsets, Default::default())?;
+        self.values.write(&group, "node1-data")?;
+        Ok(DataContainer::Group(group))
+    }
+}
+
+impl ReadData for RaggedArray {
+    fn read<B: Backend>(container: &DataContainer<B>) -> Result<Self> {
+        let group = container.as_group()?;
+        let (offsets, values) = ragged_datasets(group)?;
+        let offsets = group.open_dataset(&offsets)?.read_array::<usize, Ix1>()?.into_raw_vec();
+        let values = DynArray::read(&DataContainer::<B>::Dataset(group.open_dataset(&values)?))?;
+        Self::new(values, offsets)
+    }
+}
+
+impl HasShape for RaggedArray {
+    fn shape(&self) -> Shape {
+        self.len().into()
+    }
+}
+
+impl ArrayOp for RaggedArray {
+    /// Get the `j`-th value of row `i` with `index = [i, j]`.
+    fn get(&self, index: &[usize]) -> Option<DynScalar> {
+        if index.len() != 2 {
+            panic!("index must have length 2");
+        }
+        let (lo, hi) = (*self.offsets.get(index[0])?, *self.offsets.get(index[0] + 1)?);
+        if lo + index[1] < hi {
+            self.values.get(&[lo + index[1]])
+        } else {
+            None
+        }
+    }
+
+    fn select<S>(&self, info: &[S]) -> Self
+    where
+        S: AsRef<SelectInfoElem>,
+    {
+        let info = BoundedSelectInfo::new(&info, &self.shape());
+        if info.ndim() != 1 {
+            panic!("index must have length 1");
+        }
+        self.select_rows(&info.as_ref()[0])
+    }
+
+    fn vstack<I: Iterator<Item = Self>>(iter: I) -> Result<Self> {
+        let mut values = Vec::new();
+        let mut offsets = vec![0];
+        for x in iter {
+            let n = offsets[offsets.len() - 1];
+            offsets.extend(x.offsets[1..].iter().map(|o| o + n));
+            values.push(x.values);
+        }
+        ensure!(!values.is_empty(), "cannot vstack an empty iterator");
+        Self::new(DynArray::vstack(values.into_iter())?, offsets)
+    }
+}
+
+impl ReadArrayData for RaggedArray {
+    fn get_shape<B: Backend>(container: &DataContainer<B>) -> Result<Shape> {
+        let group = container.as_group()?;
+        let (offsets, _) = ragged_datasets(group)?;
+        let n = group.open_dataset(&offsets)?.shape()[0];
+        Ok(n.saturating_sub(1).into())
+    }
+
+    fn read_select<B, S>(container: &DataContainer<B>, info: &[S]) -> Result<Self>
+    where
+        B: Backend,
+        S: AsRef<SelectInfoElem>,
+    {
+        ensure!(info.len() == 1, "index must have length 1");
+        if info[0].as_ref().is_full() {
+            return Self::read(container);
+        }
+        // Only the values between the first and the last selected rows are read.
+        let group = container.as_group()?;
+        let (offsets, values) = ragged_datasets(group)?;
+        let offsets: Vec<usize> = group.open_dataset(&offsets)?.read_array::<usize, Ix1>()?.into_raw_vec();
+        let rows = BoundedSelectInfoElem::new(&info[0], offsets.len() - 1);
+        let (lo, hi) = match (rows.iter().min(), rows.iter().max()) {
+            (Some(first), Some(last)) => (offsets[first], offsets[last + 1]),
+            _ => (0, 0),
+        };
+        let values = DynArray::read_select(
+            &DataContainer::<B>::Dataset(group.open_dataset(&values)?),
+            &[SelectInfoElem::from(lo..hi)],
+        )?;
+        let offsets = offsets.iter().map(|o| (*o).clamp(lo, hi) - lo).collect();
+        Ok(Self::new(values, offsets)?.select_rows(&rows))
+    }
+}
+
+impl WriteArrayData for RaggedArray {}
+impl WriteArrayData for &RaggedArray {}
+
+/// Find the offsets and values datasets of an `awkward-array` group. Only
+/// lists of numbers, which have exactly one of each, are supported.
+pub(crate) fn ragged_datasets<G: GroupOp>(group: &G) -> Result<(String, String)> {
+    let names = group.list()?;
+    let offsets: Vec<_> = names.iter().filter(|x| x.ends_with("-offsets")).collect();
+    let values: Vec<_> = names.iter().filter(|x| x.ends_with("-data")).collect();
+    match (offsets.as_slice(), values.as_slice()) {
+        ([offsets], [values]) if names.len() == 2 => Ok((offsets.to_string(), values.to_string())),
+        _ => bail!(
+            "only awkward arrays of variable-length lists of numbers are supported, found datasets: {}",
+            names.join(", ")
+        ),
+    }
+}
+
+/// The awkward form of a list of numbers of type `dtype`.
+fn awkward_form(dtype: ScalarType) -> String {
+    let primitive = match dtype {
+        ScalarType::I8 => "int8",
+        ScalarType::I16 => "int16",
+        ScalarType::I32 => "int32",
+        ScalarType::I64 => "int64",
+        ScalarType::U8 => "uint8",
+        ScalarType::U16 => "uint16",
+        ScalarType::U32 => "uint32",
+        ScalarType::U64 | ScalarType::Usize => "uint64",
+        ScalarType::F16 => "float16",
+        ScalarType::F32 => "float32",
+        ScalarType::F64 => "float64",
+        ScalarType::ComplexF32 => "complex64",
+        ScalarType::ComplexF64 => "complex128",
+        ScalarType::Bool => "bool",
+        ScalarType::String => unreachable!("ragged arrays cannot contain strings"),
+    };
+    format!(
+        concat!(
+            r#"{{"class": "ListOffsetArray", "offsets": "i64", "content": {{"class": "NumpyArray", "#,
+            r#""primitive": "{}", "inner_shape": [], "parameters": {{}}, "form_key": "node1"}}, "#,
+            r#""parameters": {{}}, "form_key": "node0"}}"#,
+        ),
+        primitive
+    )
+}
+
+/// Read the dtype of the values of an `awkward-array` group.
+pub(crate) fn ragged_dtype<G: GroupOp>(group: &G) -> Result<ScalarType> {
+    let (_, values) = ragged_datasets(group)?;
+    group.open_dataset(&values)?.dtype().context("failed to read the dtype of the ragged array")
+}
@@ -0,0 +1,182 @@
+use crate::backend::*;
+use crate::data::{
+    data_traits::*,
+    scalar::DynScalar,
+    slice::{SelectInfoElem, Shape},
+    ArrayData, DynArray,
+};
+
+use anyhow::{bail, ensure, Result};
+use std::collections::HashSet;
+
+/// A one-dimensional array of records with named fields, i.e., a NumPy
+/// structured array. Each field is a one-dimensional array of numbers,
+/// booleans or strings, and all fields have the same length.
+///
+/// On disk, record arrays are stored as compound datasets using the
+/// `rec-array` encoding of the Python anndata package. Compound datasets
+/// written by h5py without encoding attributes are read as record arrays too.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordArray {
+    fields: Vec<(String, DynArray)>,
+}
+
+impl RecordArray {
+    /// Create a record array from its fields, given as pairs of names and values.
+    /// The names must be unique, and the values must be one-dimensional arrays
+    /// of the same length.
+    pub fn new(fields: Vec<(String, DynArray)>) -> Result<Self> {
+        ensure!(!fields.is_empty(), "a record array must have at least one field");
+        let mut names = HashSet::new();
+        for (name, values) in fields.iter() {
+            ensure!(names.insert(name.as_str()), "duplicate field '{}' in record array", name);
+            ensure!(
+                values.shape().ndim() == 1,
+                "field '{}' of a record array must be one-dimensional, found shape {}",
+                name,
+                values.shape(),
+            );
+            if !matches!(values.data_type(), DataType::Array(_)) {
+                bail!("field '{}' of a record array must hold numbers, booleans or strings", name);
+            }
+            ensure!(
+                values.shape()[0] == fields[0].1.shape()[0],
+                "field '{}' has length {}, but field '{}' has length {}",
+                name,
+                values.shape()[0],
+                fields[0].0,
+                fields[0].1.shape()[0],
+            );
+        }
+        Ok(Self { fields })
+    }
+
+    /// The number of records.
+    pub fn len(&self) -> usize {
+        self.fields[0].1.shape()[0]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The names and values of the fields, in the order in which they are stored.
+    pub fn fields(&self) -> &[(String, DynArray)] {
+        &self.fields
+    }
+
+    /// The values of the field `name`.
+    pub fn field(&self, name: &str) -> Option<&DynArray> {
+        self.fields.iter().find(|(x, _)| x == name).map(|(_, values)| values)
+    }
+
+    pub fn into_fields(self) -> Vec<(String, DynArray)> {
+        self.fields
+    }
+}
+
+impl WriteData for RecordArray {
+    fn data_type(&self) -> DataType {
+        DataType::RecordArray
+    }
+
+    fn write<B: Backend, G: GroupOp<Backend = B>>(
+        &self,
+        location: &G,
+        name: &str,
+    ) -> Result<DataContainer<B>> {
+        self.write_with_config(location, name, WriteConfig::default())
+    }
+
+    fn write_with_config<B: Backend, G: GroupOp<Backend = B>>(
+        &self,
+        location: &G,
+        name: &str,
+        config: WriteConfig,
+    ) -> Result<DataContainer<B>> {
+        let dataset = location.create_record_data(name, &self.fields, config)?;
+        dataset.write_str_attr("encoding-type", "rec-array")?;
+        dataset.write_str_attr("encoding-version", "0.2.0")?;
+        Ok(DataContainer::Dataset(dataset))
+    }
+}
+
+impl ReadData for RecordArray {
+    fn read<B: Backend>(container: &DataContainer<B>) -> Result<Self> {
+        Self::new(container.as_dataset()?.read_record()?)
+    }
+}
+
+impl HasShape for RecordArray {
+    fn shape(&self) -> Shape {
+        self.len().into()
+    }
+}
+
+impl ArrayOp for RecordArray {
+    /// Get field `j` of record `i` with `index = [i, j]`.
+    fn get(&self, index: &[usize]) -> Option<DynScalar> {
+        if index.len() != 2 {
+            panic!("index must have length 2");
+        }
+        self.fields.get(index[1])?.1.get(&index[..1])
+    }
+
+    fn select<S>(&self, info: &[S]) -> Self
+    where
+        S: AsRef<SelectInfoElem>,
+    {
+        if info.len() != 1 {
+            panic!("index must have length 1");
+        }
+        let fields = self
+            .fields
+            .iter()
+            .map(|(name, values)| (name.clone(), values.select(info)))
+            .collect();
+        Self { fields }
+    }
+
+    /// The arrays must have the same fields, in the same order. The values of
+    /// a field are cast to a common type as in `ArrayData::vstack`.
+    fn vstack<I: Iterator<Item = Self>>(iter: I) -> Result<Self> {
+        let arrays: Vec<_> = iter.collect();
+        ensure!(!arrays.is_empty(), "cannot vstack an empty iterator");
+        let names: Vec<_> = arrays[0].fields.iter().map(|(name, _)| name.clone()).collect();
+        for x in arrays.iter() {
+            ensure!(
+                x.fields.iter().map(|(name, _)| name).eq(names.iter()),
+                "cannot stack record arrays with different fields",
+            );
+        }
+        let fields = names
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let values = ArrayData::vstack(arrays.iter().map(|x| x.fields[i].1.clone().into()))?;
+                Ok((name, values.try_into()?))
+            })
+            .collect::<Result<_>>()?;
+        Self::new(fields)
+    }
+}
+
+impl ReadArrayData for RecordArray {
+    fn get_shape<B: Backend>(container: &DataContainer<B>) -> Result<Shape> {
+        Ok(container.as_dataset()?.shape())
+    }
+
+    /// Compound datasets are read in full before the records are selected, as
+    /// they are usually small.
+    fn read_select<B, S>(container: &DataContainer<B>, info: &[S]) -> Result<Self>
+    where
+        B: Backend,
+        S: AsRef<SelectInfoElem>,
+    {
+        ensure!(info.len() == 1, "index must have length 1");
+        Ok(Self::read(container)?.select(info))
+    }
+}
+
+impl WriteArrayData for RecordArray {}
+impl WriteArrayData for &RecordArray {}
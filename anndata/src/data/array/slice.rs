@@ -1,10 +1,11 @@
 use ndarray::{Array1, Array2, Slice, SliceInfo, SliceInfoElem, IxDyn};
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use itertools::Itertools;
+use std::borrow::Cow;
 use std::ops::{RangeFull, Range, Index, IndexMut, RangeFrom, RangeTo};
 use smallvec::{SmallVec, smallvec};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Shape(SmallVec<[usize; 3]>);
 
 impl Shape {
@@ -130,6 +131,8 @@ impl SelectInfo {
 pub enum SelectInfoElem {
     Index(Vec<usize>),
     Slice(Slice),
+    /// A boolean mask whose length must equal the length of the axis.
+    Mask(Vec<bool>),
 }
 
 impl FromIterator<usize> for SelectInfoElem {
@@ -168,6 +171,24 @@ impl From<&Vec<usize>> for SelectInfoElem {
     }
 }
 
+impl From<Vec<bool>> for SelectInfoElem {
+    fn from(x: Vec<bool>) -> Self {
+        Self::Mask(x)
+    }
+}
+
+impl From<&[bool]> for SelectInfoElem {
+    fn from(x: &[bool]) -> Self {
+        Self::Mask(x.to_vec())
+    }
+}
+
+impl From<Array1<bool>> for SelectInfoElem {
+    fn from(x: Array1<bool>) -> Self {
+        Self::Mask(x.to_vec())
+    }
+}
+
 impl From<Array1<usize>> for SelectInfoElem {
     fn from(x: Array1<usize>) -> Self {
         Self::Index(x.to_vec())
@@ -268,6 +289,11 @@ impl SelectInfoElem {
                     Ok(())
                 }
             }
+            SelectInfoElem::Mask(mask) => if mask.len() != bound {
+                bail!("mask length does not match the axis length: {} != {}", mask.len(), bound)
+            } else {
+                Ok(())
+            }
         }
     }
 
@@ -279,6 +305,10 @@ impl SelectInfoElem {
         matches!(self, SelectInfoElem::Slice(_))
     }
 
+    pub fn is_mask(&self) -> bool {
+        matches!(self, SelectInfoElem::Mask(_))
+    }
+
     pub fn full() -> Self {
         SelectInfoElem::Slice(Slice {
             start: 0,
@@ -304,6 +334,17 @@ impl SelectInfoElem {
         )
     }
 }
+
+/// Check a selection against `shape` before it is applied. Masks must match
+/// the length of their axis and indices must be in bounds. Slices are clipped
+/// to the axis and are always valid.
+pub(crate) fn check_select<S: AsRef<SelectInfoElem>>(select: &[S], shape: &Shape) -> Result<()> {
+    select.iter().zip(shape.as_ref()).enumerate().try_for_each(|(axis, (s, bound))| match s.as_ref() {
+        SelectInfoElem::Slice(_) => Ok(()),
+        s => s.bound_check(*bound).with_context(|| format!("invalid selection along axis {}", axis)),
+    })
+}
+
 pub struct BoundedSelectInfo<'a> {
     input_shape: Shape,
     select: Vec<BoundedSelectInfoElem<'a>>,
@@ -405,7 +446,7 @@ impl<'a> BoundedSelectInfo<'a> {
                             result[[r, c]] = values.next().unwrap();
                         }
                     } else {
-                        let mut values = (start..end).step_by(step.abs() as usize).rev().flat_map(|x| std::iter::repeat(x).take(n_repeat)).cycle();
+                        let mut values = (start..end).rev().step_by(step.unsigned_abs()).flat_map(|x| std::iter::repeat(x).take(n_repeat)).cycle();
                         for r in 0..nrows {
                             result[[r, c]] = values.next().unwrap();
                         }
@@ -418,15 +459,27 @@ impl<'a> BoundedSelectInfo<'a> {
 }
 
 pub enum BoundedSelectInfoElem<'a> {
-    Index(&'a [usize]),
+    /// Indices, borrowed from a `SelectInfoElem::Index` or computed from a mask.
+    Index(Cow<'a, [usize]>),
     Slice(BoundedSlice),
 }
 
 impl<'a> BoundedSelectInfoElem<'a> {
+    /// Will panic if the length of a mask differs from `bound`; selections
+    /// from users are checked with `check_select` first.
     pub fn new<S: AsRef<SelectInfoElem>>(select: &'a S, bound: usize) -> Self {
         match select.as_ref() {
-            SelectInfoElem::Index(idx) => Self::Index(idx.as_slice()),
+            SelectInfoElem::Index(idx) => Self::Index(Cow::Borrowed(idx.as_slice())),
             SelectInfoElem::Slice(slice) => Self::Slice(BoundedSlice::new(slice, bound)),
+            SelectInfoElem::Mask(mask) => {
+                assert!(
+                    mask.len() == bound,
+                    "mask length does not match the axis length: {} != {}",
+                    mask.len(),
+                    bound
+                );
+                Self::Index(mask_to_indices(mask).into())
+            }
         }
     }
 
@@ -457,18 +510,19 @@ impl<'a> BoundedSelectInfoElem<'a> {
             Self::Slice(slice) => if slice.step > 0 {
                 (slice.start..slice.end).step_by(slice.step as usize).collect()
             } else {
-                (slice.start..slice.end).step_by(slice.step.abs() as usize).rev().collect()
+                (slice.start..slice.end).rev().step_by(slice.step.unsigned_abs()).collect()
             },
         }
     }
 
     pub fn iter(&self) -> Box<dyn ExactSizeIterator<Item=usize> + 'a> {
         match self {
-            Self::Index(idx) => Box::new(idx.iter().copied()),
+            Self::Index(Cow::Borrowed(idx)) => Box::new(idx.iter().copied()),
+            Self::Index(Cow::Owned(idx)) => Box::new(idx.clone().into_iter()),
             Self::Slice(slice) => if slice.step > 0 {
                 Box::new((slice.start..slice.end).step_by(slice.step as usize))
             } else {
-                Box::new((slice.start..slice.end).step_by(slice.step.abs() as usize).rev())
+                Box::new((slice.start..slice.end).rev().step_by(slice.step.unsigned_abs()))
             },
         }
     }
@@ -494,24 +548,27 @@ impl Into<SliceInfoElem> for BoundedSlice {
 
 
 impl BoundedSlice {
+    /// Resolve the bounds of `slice` against an axis of length `bound`.
+    /// Negative bounds count from the end of the axis and out-of-range bounds
+    /// are clipped, as in NumPy. A slice whose end precedes its start is empty.
     pub(crate) fn new(slice: &Slice, bound: usize) -> Self {
         fn convert(x: isize, d: usize) -> usize {
             if x < 0 {
-                d.checked_add_signed(x).unwrap()
+                d.saturating_sub(x.unsigned_abs())
             } else {
-                x as usize
+                (x as usize).min(d)
             }
         }
 
-        Self {
-            start: convert(slice.start, bound),
-            end: slice.end.map_or(bound, |x| convert(x, bound)),
-            step: slice.step,
-        }
+        let start = convert(slice.start, bound);
+        let end = slice.end.map_or(bound, |x| convert(x, bound)).max(start);
+        Self { start, end, step: slice.step }
     }
 
+    /// The number of selected elements, i.e., `ceil((end - start) / |step|)`.
     pub(crate) fn len(&self) -> usize {
-        (self.end - self.start).checked_div(self.step.unsigned_abs()).unwrap()
+        let step = self.step.unsigned_abs();
+        (self.end.saturating_sub(self.start) + step - 1).checked_div(step).unwrap()
     }
 
     pub(crate) fn index(&self, i: usize) -> usize {
@@ -523,6 +580,11 @@ impl BoundedSlice {
     }
 }
 
+/// The positions of the `true` values of a mask.
+pub(crate) fn mask_to_indices(mask: &[bool]) -> Vec<usize> {
+    mask.iter().enumerate().filter_map(|(i, &x)| x.then_some(i)).collect()
+}
+
 pub const SLICE_FULL: Slice = Slice {
     start: 0,
     end: None,
@@ -1,13 +1,14 @@
 use crate::backend::*;
 use crate::data::{
-    array::utils::{cs_major_minor_index, cs_major_index, cs_major_slice},
+    array::utils::{cs_major_minor_index, cs_major_index, cs_major_slice, read_cs_major},
     data_traits::*,
     scalar::DynScalar,
     slice::{SelectInfoElem, Shape},
     BoundedSelectInfo, BoundedSelectInfoElem,
 };
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use half::f16;
 use nalgebra_sparse::csc::CscMatrix;
 use nalgebra_sparse::pattern::SparsityPattern;
 use ndarray::Ix1;
@@ -26,6 +27,7 @@ pub enum DynCscMatrix {
     U32(CscMatrix<u32>),
     U64(CscMatrix<u64>),
     Usize(CscMatrix<usize>),
+    F16(CscMatrix<f16>),
     F32(CscMatrix<f32>),
     F64(CscMatrix<f64>),
     Bool(CscMatrix<bool>),
@@ -70,6 +72,7 @@ impl TryFrom<DynCscMatrix> for CscMatrix<u32> {
             DynCscMatrix::U16(data) => Ok(cast_csc(data)?),
             DynCscMatrix::U64(data) => Ok(cast_csc(data)?),
             DynCscMatrix::Usize(data) => Ok(cast_csc(data)?),
+            DynCscMatrix::F16(_) => bail!("Cannot convert f16 to u32"),
             DynCscMatrix::F32(_) => bail!("Cannot convert f32 to u32"),
             DynCscMatrix::F64(_) => bail!("Cannot convert f64 to u32"),
             DynCscMatrix::Bool(_) => bail!("Cannot convert bool to f64"),
@@ -99,6 +102,7 @@ impl TryFrom<DynCscMatrix> for CscMatrix<f64> {
             DynCscMatrix::U32(data) => Ok(cast_csc(data)?),
             DynCscMatrix::U64(_) => bail!("Cannot convert u64 to f64"),
             DynCscMatrix::Usize(_) => bail!("Cannot convert usize to f64"),
+            DynCscMatrix::F16(data) => Ok(cast_csc(data)?),
             DynCscMatrix::F32(data) => Ok(cast_csc(data)?),
             DynCscMatrix::Bool(_) => bail!("Cannot convert bool to f64"),
             DynCscMatrix::String(_) => bail!("Cannot convert string to f64"),
@@ -114,6 +118,7 @@ impl_into_dyn_csc!(u8, U8);
 impl_into_dyn_csc!(u16, U16);
 impl_into_dyn_csc!(u64, U64);
 impl_into_dyn_csc!(usize, Usize);
+impl_into_dyn_csc!(f16, F16);
 impl_into_dyn_csc!(f32, F32);
 impl_into_dyn_csc!(bool, Bool);
 impl_into_dyn_csc!(String, String);
@@ -130,6 +135,7 @@ macro_rules! impl_dyn_csc_matrix {
             DynCscMatrix::U32(data) => $fun!(data),
             DynCscMatrix::U64(data) => $fun!(data),
             DynCscMatrix::Usize(data) => $fun!(data),
+            DynCscMatrix::F16(data) => $fun!(data),
             DynCscMatrix::F32(data) => $fun!(data),
             DynCscMatrix::F64(data) => $fun!(data),
             DynCscMatrix::Bool(data) => $fun!(data),
@@ -150,6 +156,7 @@ impl WriteData for DynCscMatrix {
             DynCscMatrix::U32(csc) => csc.data_type(),
             DynCscMatrix::U64(csc) => csc.data_type(),
             DynCscMatrix::Usize(csc) => csc.data_type(),
+            DynCscMatrix::F16(csc) => csc.data_type(),
             DynCscMatrix::F32(csc) => csc.data_type(),
             DynCscMatrix::F64(csc) => csc.data_type(),
             DynCscMatrix::Bool(csc) => csc.data_type(),
@@ -168,6 +175,19 @@ impl WriteData for DynCscMatrix {
         }
         impl_dyn_csc_matrix!(self, write_data)
     }
+    fn write_with_config<B: Backend, G: GroupOp<Backend = B>>(
+        &self,
+        location: &G,
+        name: &str,
+        config: WriteConfig,
+    ) -> Result<DataContainer<B>> {
+        macro_rules! write_data {
+            ($data:expr) => {
+                $data.write_with_config(location, name, config)
+            };
+        }
+        impl_dyn_csc_matrix!(self, write_data)
+    }
 }
 
 impl ReadData for DynCscMatrix {
@@ -183,8 +203,12 @@ impl ReadData for DynCscMatrix {
                 ScalarType::U32 => CscMatrix::<u32>::read(container).map(DynCscMatrix::U32),
                 ScalarType::U64 => CscMatrix::<u64>::read(container).map(DynCscMatrix::U64),
                 ScalarType::Usize => CscMatrix::<usize>::read(container).map(DynCscMatrix::Usize),
+                ScalarType::F16 => CscMatrix::<f16>::read(container).map(DynCscMatrix::F16),
                 ScalarType::F32 => CscMatrix::<f32>::read(container).map(DynCscMatrix::F32),
                 ScalarType::F64 => CscMatrix::<f64>::read(container).map(DynCscMatrix::F64),
+                ScalarType::ComplexF32 | ScalarType::ComplexF64 => {
+                    bail!("sparse matrices of complex numbers are not supported")
+                }
                 ScalarType::Bool => CscMatrix::<bool>::read(container).map(DynCscMatrix::Bool),
                 ScalarType::String => {
                     CscMatrix::<String>::read(container).map(DynCscMatrix::String)
@@ -240,6 +264,7 @@ impl ArrayOp for DynCscMatrix {
             DynCscMatrix::I16(_) => Ok(DynCscMatrix::I16(CscMatrix::<i16>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
             DynCscMatrix::I32(_) => Ok(DynCscMatrix::I32(CscMatrix::<i32>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
             DynCscMatrix::I64(_) => Ok(DynCscMatrix::I64(CscMatrix::<i64>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
+            DynCscMatrix::F16(_) => Ok(DynCscMatrix::F16(CscMatrix::<f16>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
             DynCscMatrix::F32(_) => Ok(DynCscMatrix::F32(CscMatrix::<f32>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
             DynCscMatrix::F64(_) => Ok(DynCscMatrix::F64(CscMatrix::<f64>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
             DynCscMatrix::Bool(_) => Ok(DynCscMatrix::Bool(CscMatrix::<bool>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
@@ -253,8 +278,7 @@ impl ReadArrayData for DynCscMatrix {
     fn get_shape<B: Backend>(container: &DataContainer<B>) -> Result<Shape> {
         Ok(container
             .as_group()?
-            .read_array_attr("shape")?
-            .to_vec()
+            .read_shape_attr()?
             .into())
     }
 
@@ -283,10 +307,15 @@ impl ReadArrayData for DynCscMatrix {
                     .map(Into::into),
                 ScalarType::Usize => CscMatrix::<usize>::read_select(container, info)
                     .map(Into::into),
+                ScalarType::F16 => CscMatrix::<f16>::read_select(container, info)
+                    .map(Into::into),
                 ScalarType::F32 => CscMatrix::<f32>::read_select(container, info)
                     .map(Into::into),
                 ScalarType::F64 => CscMatrix::<f64>::read_select(container, info)
                     .map(Into::into),
+                ScalarType::ComplexF32 | ScalarType::ComplexF64 => {
+                    bail!("sparse matrices of complex numbers are not supported")
+                }
                 ScalarType::Bool => CscMatrix::<bool>::read_select(container, info)
                     .map(Into::into),
                 ScalarType::String => CscMatrix::<String>::read_select(container, info)
@@ -309,8 +338,16 @@ impl<T: BackendData + Clone> ArrayOp for CscMatrix<T> {
         if index.len() != 2 {
             panic!("index must have length 2");
         }
-        todo!()
-        //self.get_entry(index[0], index[1]).map(|x| DynScalar::from(x.into_value()))
+        let (i, j) = (index[0], index[1]);
+        if i >= self.nrows() || j >= self.ncols() {
+            return None;
+        }
+        let (offsets, indices, data) = self.csc_data();
+        let (lo, hi) = (offsets[j], offsets[j + 1]);
+        match indices[lo..hi].binary_search(&i) {
+            Ok(k) => Some(data[lo + k].into_dyn()),
+            Err(_) => Some(DynScalar::zero(T::DTYPE)),
+        }
     }
 
     fn select<S>(&self, info: &[S]) -> Self
@@ -337,7 +374,7 @@ impl<T: BackendData + Clone> ArrayOp for CscMatrix<T> {
                         )
                     } else if step < 0 {
                         cs_major_index(
-                            (start..end).step_by(step.abs() as usize).rev(),
+                            (start..end).rev().step_by(step.unsigned_abs()),
                             col_offsets,
                             row_indices,
                             data,
@@ -365,8 +402,8 @@ impl<T: BackendData + Clone> ArrayOp for CscMatrix<T> {
                             &BoundedSelectInfoElem::Slice(row) => {
                                 if row.step < 0 {
                                     cs_major_minor_index(
-                                        (col_start..col_end).step_by(col_step.abs() as usize).rev(),
-                                        (row.start..row.end).step_by(row.step.abs() as usize).rev(),
+                                        (col_start..col_end).rev().step_by(col_step.unsigned_abs()),
+                                        (row.start..row.end).rev().step_by(row.step.unsigned_abs()),
                                         self.nrows(),
                                         col_offsets,
                                         row_indices,
@@ -374,7 +411,7 @@ impl<T: BackendData + Clone> ArrayOp for CscMatrix<T> {
                                     )
                                 } else {
                                     cs_major_minor_index(
-                                        (col_start..col_end).step_by(col_step.abs() as usize).rev(),
+                                        (col_start..col_end).rev().step_by(col_step.unsigned_abs()),
                                         (row.start..row.end).step_by(row.step as usize),
                                         self.nrows(),
                                         col_offsets,
@@ -384,7 +421,7 @@ impl<T: BackendData + Clone> ArrayOp for CscMatrix<T> {
                                 }
                             }
                             BoundedSelectInfoElem::Index(idx) => cs_major_minor_index(
-                                (col_start..col_end).step_by(col_step.abs() as usize).rev(),
+                                (col_start..col_end).rev().step_by(col_step.unsigned_abs()),
                                 idx.iter().copied(),
                                 self.nrows(),
                                 col_offsets,
@@ -399,7 +436,7 @@ impl<T: BackendData + Clone> ArrayOp for CscMatrix<T> {
                                 if row.step < 0 {
                                     cs_major_minor_index(
                                         (col_start..col_end).step_by(col_step as usize),
-                                        (row.start..row.end).step_by(row.step.abs() as usize).rev(),
+                                        (row.start..row.end).rev().step_by(row.step.unsigned_abs()),
                                         self.nrows(),
                                         col_offsets,
                                         row_indices,
@@ -432,7 +469,7 @@ impl<T: BackendData + Clone> ArrayOp for CscMatrix<T> {
                         if row.step < 0 {
                             cs_major_minor_index(
                                 i.iter().copied(),
-                                (row.start..row.end).step_by(row.step.abs() as usize).rev(),
+                                (row.start..row.end).rev().step_by(row.step.unsigned_abs()),
                                 self.nrows(),
                                 col_offsets,
                                 row_indices,
@@ -472,8 +509,44 @@ impl<T: BackendData + Clone> ArrayOp for CscMatrix<T> {
         CscMatrix::try_from_pattern_and_values(pattern, new_data).unwrap()
     }
 
-    fn vstack<I: Iterator<Item = Self>>(_iter: I) -> Result<Self> where Self: Sized {
-        todo!()
+    fn vstack<I: Iterator<Item = Self>>(iter: I) -> Result<Self> {
+        let mats: Vec<_> = iter.collect();
+        let num_cols = mats.first().context("cannot vstack an empty iterator")?.ncols();
+        if mats.iter().any(|x| x.ncols() != num_cols) {
+            bail!("All matrices must have the same number of columns");
+        }
+        // The row offset of each input in the output.
+        let row_offsets: Vec<usize> = mats
+            .iter()
+            .scan(0, |acc, x| {
+                let offset = *acc;
+                *acc += x.nrows();
+                Some(offset)
+            })
+            .collect();
+        let num_rows = mats.iter().map(|x| x.nrows()).sum();
+        let nnz = mats.iter().map(|x| x.nnz()).sum();
+
+        // Each column of the output is the concatenation of the corresponding
+        // columns of the inputs, with the row indices shifted.
+        let mut indptr = Vec::with_capacity(num_cols + 1);
+        let mut indices = Vec::with_capacity(nnz);
+        let mut data = Vec::with_capacity(nnz);
+        indptr.push(0);
+        (0..num_cols).for_each(|j| {
+            mats.iter().zip(&row_offsets).for_each(|(mat, row_offset)| {
+                let (offsets, indices_, data_) = mat.csc_data();
+                let (lo, hi) = (offsets[j], offsets[j + 1]);
+                indices.extend(indices_[lo..hi].iter().map(|i| i + row_offset));
+                data.extend_from_slice(&data_[lo..hi]);
+            });
+            indptr.push(indices.len());
+        });
+
+        let pattern = unsafe {
+            SparsityPattern::from_offset_and_indices_unchecked(num_cols, num_rows, indptr, indices)
+        };
+        Ok(CscMatrix::try_from_pattern_and_values(pattern, data).unwrap())
     }
 }
 
@@ -487,6 +560,17 @@ impl<T: BackendData> WriteData for CscMatrix<T> {
         location: &G,
         name: &str,
     ) -> Result<DataContainer<B>> {
+        self.write_with_config(location, name, Default::default())
+    }
+    /// Only the compression of `config` is used, as the chunk shape and the
+    /// fill value refer to the dense layout of the matrix.
+    fn write_with_config<B: Backend, G: GroupOp<Backend = B>>(
+        &self,
+        location: &G,
+        name: &str,
+        config: WriteConfig,
+    ) -> Result<DataContainer<B>> {
+        let config = WriteConfig { compression: config.compression, ..Default::default() };
         let group = location.create_group(name)?;
         let shape = self.shape();
 
@@ -494,7 +578,7 @@ impl<T: BackendData> WriteData for CscMatrix<T> {
         group.write_str_attr("encoding-version", "0.1.0")?;
         group.write_array_attr("shape", shape.as_ref())?;
 
-        group.create_array_data("data", &self.values(), Default::default())?;
+        group.create_array_data("data", &self.values(), config.clone())?;
 
         let num_rows = shape[0];
         // Use i32 or i64 as indices type in order to be compatible with scipy
@@ -505,7 +589,7 @@ impl<T: BackendData> WriteData for CscMatrix<T> {
                 .map(|x| (*x).try_into().ok())
                 .collect();
             if let Some(indptr_i32) = try_convert_indptr {
-                group.create_array_data("indptr", &indptr_i32, Default::default())?;
+                group.create_array_data("indptr", &indptr_i32, config.clone())?;
                 group.create_array_data(
                     "indices",
                     self.row_indices()
@@ -513,7 +597,7 @@ impl<T: BackendData> WriteData for CscMatrix<T> {
                         .map(|x| (*x) as i32)
                         .collect::<Vec<_>>()
                         .as_slice(),
-                    Default::default(),
+                    config.clone(),
                 )?;
             } else {
                 group.create_array_data(
@@ -523,7 +607,7 @@ impl<T: BackendData> WriteData for CscMatrix<T> {
                         .map(|x| TryInto::<i64>::try_into(*x).unwrap())
                         .collect::<Vec<_>>()
                         .as_slice(),
-                    Default::default(),
+                    config.clone(),
                 )?;
                 group.create_array_data(
                     "indices",
@@ -532,7 +616,7 @@ impl<T: BackendData> WriteData for CscMatrix<T> {
                         .map(|x| (*x) as i64)
                         .collect::<Vec<_>>()
                         .as_slice(),
-                    Default::default(),
+                    config.clone(),
                 )?;
             }
         } else if TryInto::<i64>::try_into(num_rows.saturating_sub(1)).is_ok() {
@@ -543,7 +627,7 @@ impl<T: BackendData> WriteData for CscMatrix<T> {
                     .map(|x| TryInto::<i64>::try_into(*x).unwrap())
                     .collect::<Vec<_>>()
                     .as_slice(),
-                Default::default(),
+                config.clone(),
             )?;
             group.create_array_data(
                 "indices",
@@ -552,7 +636,7 @@ impl<T: BackendData> WriteData for CscMatrix<T> {
                     .map(|x| (*x) as i64)
                     .collect::<Vec<_>>()
                     .as_slice(),
-                Default::default(),
+                config.clone(),
             )?;
         } else {
             panic!(
@@ -568,13 +652,13 @@ impl<T: BackendData> WriteData for CscMatrix<T> {
 impl<T: BackendData> ReadData for CscMatrix<T> {
     fn read<B: Backend>(container: &DataContainer<B>) -> Result<Self> {
         let group = container.as_group()?;
-        let shape: Vec<usize> = group.read_array_attr("shape")?.to_vec();
+        let shape: Vec<usize> = group.read_shape_attr()?;
         let data = group.open_dataset("data")?.read_array::<_, Ix1>()?.into_raw_vec();
         let indptr: Vec<usize> = group.open_dataset("indptr")?.read_array::<_, Ix1>()?.into_raw_vec();
         let indices: Vec<usize> = group.open_dataset("indices")?.read_array::<_, Ix1>()?.into_raw_vec();
         CscMatrix::try_from_csc_data(
             shape[0], shape[1], indptr, indices, data
-        ).map_err(|e| anyhow::anyhow!("{}", e))
+        ).map_err(|e| anyhow!("{}", e))
     }
 }
 
@@ -582,12 +666,10 @@ impl<T: BackendData> ReadArrayData for CscMatrix<T> {
     fn get_shape<B: Backend>(container: &DataContainer<B>) -> Result<Shape> {
         Ok(container
             .as_group()?
-            .read_array_attr("shape")?
-            .to_vec()
+            .read_shape_attr()?
             .into())
     }
 
-    // TODO: efficient implementation for slice
     fn read_select<B, S>(container: &DataContainer<B>, info: &[S]) -> Result<Self>
     where
         B: Backend,
@@ -601,33 +683,12 @@ impl<T: BackendData> ReadArrayData for CscMatrix<T> {
             return Self::read(container);
         }
 
-        let data = if let SelectInfoElem::Slice(s) = info[1].as_ref()  {
-            let group = container.as_group()?;
-            let indptr_slice = if let Some(end) = s.end {
-                SelectInfoElem::from(s.start .. end + 1)
-            } else {
-                SelectInfoElem::from(s.start ..)
-            };
-            let mut indptr: Vec<usize> = group 
-                .open_dataset("indptr")?
-                .read_array_slice(&[indptr_slice])?
-                .to_vec();
-            let lo = indptr[0];
-            let slice = SelectInfoElem::from(lo .. indptr[indptr.len() - 1]);
-            let data: Vec<T> = group.open_dataset("data")?.read_array_slice(&[&slice])?.to_vec();
-            let indices: Vec<usize> = group.open_dataset("indices")?.read_array_slice(&[&slice])?.to_vec();
-            indptr.iter_mut().for_each(|x| *x -= lo);
-            CscMatrix::try_from_csc_data(
-                Self::get_shape(container)?[0],
-                indptr.len() - 1,
-                indptr,
-                indices,
-                data,
-            ).unwrap().select_axis(0, info[0].as_ref()) // selct axis 1, then select axis 0 elements
-        } else {
-            Self::read(container)?.select(info)
-        };
-        Ok(data)
+        let shape = Self::get_shape(container)?;
+        let major_idx = BoundedSelectInfoElem::new(&info[1], shape[1]);
+        let (indptr, indices, data) = read_cs_major::<_, T>(container.as_group()?, &major_idx)?;
+        let data = CscMatrix::try_from_csc_data(shape[0], major_idx.len(), indptr, indices, data)
+            .map_err(|e| anyhow!("cannot read csc matrix: {}", e))?;
+        Ok(data.select_axis(0, info[0].as_ref()))
     }
 }
 
@@ -767,5 +828,14 @@ mod csc_matrix_index_tests {
             csc_matrix.select(s![&ridx, &cidx].as_ref()),
             csc_select(&csc_matrix, ridx.iter().cloned(), cidx.iter().cloned()),
         );
+
+        // Vertical stacking
+        assert_eq!(
+            CscMatrix::vstack([
+                csc_matrix.select(s![0..77, ..].as_ref()),
+                csc_matrix.select(s![77..200, ..].as_ref()),
+            ].into_iter()).unwrap(),
+            csc_matrix,
+        );
     }
-}
\ No newline at end of file
+}
@@ -1,16 +1,18 @@
 use crate::backend::*;
 use crate::data::{
-    array::utils::{cs_major_minor_index, cs_major_index, cs_major_slice},
+    array::utils::{cs_major_minor_index, cs_major_index, cs_major_slice, read_cs_major},
     data_traits::*,
     scalar::DynScalar,
     slice::{SelectInfoElem, Shape},
     BoundedSelectInfo, BoundedSelectInfoElem,
 };
 
-use anyhow::{bail, anyhow, Context, Result};
+use anyhow::{bail, anyhow, ensure, Context, Result};
+use half::f16;
 use nalgebra_sparse::csr::CsrMatrix;
 use nalgebra_sparse::pattern::SparsityPattern;
-use ndarray::Ix1;
+use ndarray::{Array1, ArrayView1, Ix1};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use num::FromPrimitive;
 
 use super::super::slice::BoundedSlice;
@@ -26,6 +28,7 @@ pub enum DynCsrMatrix {
     U32(CsrMatrix<u32>),
     U64(CsrMatrix<u64>),
     Usize(CsrMatrix<usize>),
+    F16(CsrMatrix<f16>),
     F32(CsrMatrix<f32>),
     F64(CsrMatrix<f64>),
     Bool(CsrMatrix<bool>),
@@ -70,6 +73,7 @@ impl TryFrom<DynCsrMatrix> for CsrMatrix<u32> {
             DynCsrMatrix::U16(data) => Ok(cast_csr(data)?),
             DynCsrMatrix::U64(data) => Ok(cast_csr(data)?),
             DynCsrMatrix::Usize(data) => Ok(cast_csr(data)?),
+            DynCsrMatrix::F16(_) => bail!("Cannot convert f16 to u32"),
             DynCsrMatrix::F32(_) => bail!("Cannot convert f32 to u32"),
             DynCsrMatrix::F64(_) => bail!("Cannot convert f64 to u32"),
             DynCsrMatrix::Bool(_) => bail!("Cannot convert bool to f64"),
@@ -99,6 +103,7 @@ impl TryFrom<DynCsrMatrix> for CsrMatrix<f64> {
             DynCsrMatrix::U32(data) => Ok(cast_csr(data)?),
             DynCsrMatrix::U64(_) => bail!("Cannot convert u64 to f64"),
             DynCsrMatrix::Usize(_) => bail!("Cannot convert usize to f64"),
+            DynCsrMatrix::F16(data) => Ok(cast_csr(data)?),
             DynCsrMatrix::F32(data) => Ok(cast_csr(data)?),
             DynCsrMatrix::Bool(_) => bail!("Cannot convert bool to f64"),
             DynCsrMatrix::String(_) => bail!("Cannot convert string to f64"),
@@ -114,6 +119,7 @@ impl_into_dyn_csr!(u8, U8);
 impl_into_dyn_csr!(u16, U16);
 impl_into_dyn_csr!(u64, U64);
 impl_into_dyn_csr!(usize, Usize);
+impl_into_dyn_csr!(f16, F16);
 impl_into_dyn_csr!(f32, F32);
 impl_into_dyn_csr!(bool, Bool);
 impl_into_dyn_csr!(String, String);
@@ -130,6 +136,7 @@ macro_rules! impl_dyn_csr_matrix {
             DynCsrMatrix::U32(data) => $fun!(data),
             DynCsrMatrix::U64(data) => $fun!(data),
             DynCsrMatrix::Usize(data) => $fun!(data),
+            DynCsrMatrix::F16(data) => $fun!(data),
             DynCsrMatrix::F32(data) => $fun!(data),
             DynCsrMatrix::F64(data) => $fun!(data),
             DynCsrMatrix::Bool(data) => $fun!(data),
@@ -159,6 +166,19 @@ impl WriteData for DynCsrMatrix {
         }
         impl_dyn_csr_matrix!(self, write_data)
     }
+    fn write_with_config<B: Backend, G: GroupOp<Backend = B>>(
+        &self,
+        location: &G,
+        name: &str,
+        config: WriteConfig,
+    ) -> Result<DataContainer<B>> {
+        macro_rules! write_data {
+            ($data:expr) => {
+                $data.write_with_config(location, name, config)
+            };
+        }
+        impl_dyn_csr_matrix!(self, write_data)
+    }
 }
 
 impl ReadData for DynCsrMatrix {
@@ -174,8 +194,12 @@ impl ReadData for DynCsrMatrix {
                 ScalarType::U32 => CsrMatrix::<u32>::read(container).map(DynCsrMatrix::U32),
                 ScalarType::U64 => CsrMatrix::<u64>::read(container).map(DynCsrMatrix::U64),
                 ScalarType::Usize => CsrMatrix::<usize>::read(container).map(DynCsrMatrix::Usize),
+                ScalarType::F16 => CsrMatrix::<f16>::read(container).map(DynCsrMatrix::F16),
                 ScalarType::F32 => CsrMatrix::<f32>::read(container).map(DynCsrMatrix::F32),
                 ScalarType::F64 => CsrMatrix::<f64>::read(container).map(DynCsrMatrix::F64),
+                ScalarType::ComplexF32 | ScalarType::ComplexF64 => {
+                    bail!("sparse matrices of complex numbers are not supported")
+                }
                 ScalarType::Bool => CsrMatrix::<bool>::read(container).map(DynCsrMatrix::Bool),
                 ScalarType::String => {
                     CsrMatrix::<String>::read(container).map(DynCsrMatrix::String)
@@ -231,6 +255,7 @@ impl ArrayOp for DynCsrMatrix {
             DynCsrMatrix::I16(_) => Ok(DynCsrMatrix::I16(CsrMatrix::<i16>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
             DynCsrMatrix::I32(_) => Ok(DynCsrMatrix::I32(CsrMatrix::<i32>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
             DynCsrMatrix::I64(_) => Ok(DynCsrMatrix::I64(CsrMatrix::<i64>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
+            DynCsrMatrix::F16(_) => Ok(DynCsrMatrix::F16(CsrMatrix::<f16>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
             DynCsrMatrix::F32(_) => Ok(DynCsrMatrix::F32(CsrMatrix::<f32>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
             DynCsrMatrix::F64(_) => Ok(DynCsrMatrix::F64(CsrMatrix::<f64>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
             DynCsrMatrix::Bool(_) => Ok(DynCsrMatrix::Bool(CsrMatrix::<bool>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
@@ -244,8 +269,7 @@ impl ReadArrayData for DynCsrMatrix {
     fn get_shape<B: Backend>(container: &DataContainer<B>) -> Result<Shape> {
         Ok(container
             .as_group()?
-            .read_array_attr("shape")?
-            .to_vec()
+            .read_shape_attr()?
             .into())
     }
 
@@ -274,10 +298,15 @@ impl ReadArrayData for DynCsrMatrix {
                     .map(Into::into),
                 ScalarType::Usize => CsrMatrix::<usize>::read_select(container, info)
                     .map(Into::into),
+                ScalarType::F16 => CsrMatrix::<f16>::read_select(container, info)
+                    .map(Into::into),
                 ScalarType::F32 => CsrMatrix::<f32>::read_select(container, info)
                     .map(Into::into),
                 ScalarType::F64 => CsrMatrix::<f64>::read_select(container, info)
                     .map(Into::into),
+                ScalarType::ComplexF32 | ScalarType::ComplexF64 => {
+                    bail!("sparse matrices of complex numbers are not supported")
+                }
                 ScalarType::Bool => CsrMatrix::<bool>::read_select(container, info)
                     .map(Into::into),
                 ScalarType::String => CsrMatrix::<String>::read_select(container, info)
@@ -300,8 +329,16 @@ impl<T: BackendData + Clone> ArrayOp for CsrMatrix<T> {
         if index.len() != 2 {
             panic!("index must have length 2");
         }
-        todo!()
-        //self.get_entry(index[0], index[1]).map(|x| DynScalar::from(x.into_value()))
+        let (i, j) = (index[0], index[1]);
+        if i >= self.nrows() || j >= self.ncols() {
+            return None;
+        }
+        let (offsets, indices, data) = self.csr_data();
+        let (lo, hi) = (offsets[i], offsets[i + 1]);
+        match indices[lo..hi].binary_search(&j) {
+            Ok(k) => Some(data[lo + k].into_dyn()),
+            Err(_) => Some(DynScalar::zero(T::DTYPE)),
+        }
     }
 
     fn select<S>(&self, info: &[S]) -> Self
@@ -328,7 +365,7 @@ impl<T: BackendData + Clone> ArrayOp for CsrMatrix<T> {
                         )
                     } else if step < 0 {
                         cs_major_index(
-                            (start..end).step_by(step.abs() as usize).rev(),
+                            (start..end).rev().step_by(step.unsigned_abs()),
                             row_offsets,
                             col_indices,
                             data,
@@ -354,8 +391,8 @@ impl<T: BackendData + Clone> ArrayOp for CsrMatrix<T> {
                             &BoundedSelectInfoElem::Slice(col) => {
                                 if col.step < 0 {
                                     cs_major_minor_index(
-                                        (row_start..row_end).step_by(row_step.abs() as usize).rev(),
-                                        (col.start..col.end).step_by(col.step.abs() as usize).rev(),
+                                        (row_start..row_end).rev().step_by(row_step.unsigned_abs()),
+                                        (col.start..col.end).rev().step_by(col.step.unsigned_abs()),
                                         self.ncols(),
                                         row_offsets,
                                         col_indices,
@@ -363,7 +400,7 @@ impl<T: BackendData + Clone> ArrayOp for CsrMatrix<T> {
                                     )
                                 } else {
                                     cs_major_minor_index(
-                                        (row_start..row_end).step_by(row_step.abs() as usize).rev(),
+                                        (row_start..row_end).rev().step_by(row_step.unsigned_abs()),
                                         (col.start..col.end).step_by(col.step as usize),
                                         self.ncols(),
                                         row_offsets,
@@ -373,7 +410,7 @@ impl<T: BackendData + Clone> ArrayOp for CsrMatrix<T> {
                                 }
                             }
                             BoundedSelectInfoElem::Index(idx) => cs_major_minor_index(
-                                (row_start..row_end).step_by(row_step.abs() as usize).rev(),
+                                (row_start..row_end).rev().step_by(row_step.unsigned_abs()),
                                 idx.iter().copied(),
                                 self.ncols(),
                                 row_offsets,
@@ -387,7 +424,7 @@ impl<T: BackendData + Clone> ArrayOp for CsrMatrix<T> {
                                 if col.step < 0 {
                                     cs_major_minor_index(
                                         (row_start..row_end).step_by(row_step as usize),
-                                        (col.start..col.end).step_by(col.step.abs() as usize).rev(),
+                                        (col.start..col.end).rev().step_by(col.step.unsigned_abs()),
                                         self.ncols(),
                                         row_offsets,
                                         col_indices,
@@ -420,7 +457,7 @@ impl<T: BackendData + Clone> ArrayOp for CsrMatrix<T> {
                         if col.step < 0 {
                             cs_major_minor_index(
                                 i.iter().copied(),
-                                (col.start..col.end).step_by(col.step.abs() as usize).rev(),
+                                (col.start..col.end).rev().step_by(col.step.unsigned_abs()),
                                 self.ncols(),
                                 row_offsets,
                                 col_indices,
@@ -491,6 +528,17 @@ impl<T: BackendData> WriteData for CsrMatrix<T> {
         location: &G,
         name: &str,
     ) -> Result<DataContainer<B>> {
+        self.write_with_config(location, name, Default::default())
+    }
+    /// Only the compression of `config` is used, as the chunk shape and the
+    /// fill value refer to the dense layout of the matrix.
+    fn write_with_config<B: Backend, G: GroupOp<Backend = B>>(
+        &self,
+        location: &G,
+        name: &str,
+        config: WriteConfig,
+    ) -> Result<DataContainer<B>> {
+        let config = WriteConfig { compression: config.compression, ..Default::default() };
         let group = location.create_group(name)?;
         let shape = self.shape();
 
@@ -498,7 +546,7 @@ impl<T: BackendData> WriteData for CsrMatrix<T> {
         group.write_str_attr("encoding-version", "0.1.0")?;
         group.write_array_attr("shape", shape.as_ref())?;
 
-        group.create_array_data("data", &self.values(), Default::default())?;
+        group.create_array_data("data", &self.values(), config.clone())?;
 
         let num_cols = shape[1];
         // Use i32 or i64 as indices type in order to be compatible with scipy
@@ -509,7 +557,7 @@ impl<T: BackendData> WriteData for CsrMatrix<T> {
                 .map(|x| (*x).try_into().ok())
                 .collect();
             if let Some(indptr_i32) = try_convert_indptr {
-                group.create_array_data("indptr", &indptr_i32, Default::default())?;
+                group.create_array_data("indptr", &indptr_i32, config.clone())?;
                 group.create_array_data(
                     "indices",
                     self.col_indices()
@@ -517,7 +565,7 @@ impl<T: BackendData> WriteData for CsrMatrix<T> {
                         .map(|x| (*x) as i32)
                         .collect::<Vec<_>>()
                         .as_slice(),
-                    Default::default(),
+                    config.clone(),
                 )?;
             } else {
                 group.create_array_data(
@@ -527,7 +575,7 @@ impl<T: BackendData> WriteData for CsrMatrix<T> {
                         .map(|x| TryInto::<i64>::try_into(*x).unwrap())
                         .collect::<Vec<_>>()
                         .as_slice(),
-                    Default::default(),
+                    config.clone(),
                 )?;
                 group.create_array_data(
                     "indices",
@@ -536,7 +584,7 @@ impl<T: BackendData> WriteData for CsrMatrix<T> {
                         .map(|x| (*x) as i64)
                         .collect::<Vec<_>>()
                         .as_slice(),
-                    Default::default(),
+                    config.clone(),
                 )?;
             }
         } else if TryInto::<i64>::try_into(num_cols.saturating_sub(1)).is_ok() {
@@ -547,7 +595,7 @@ impl<T: BackendData> WriteData for CsrMatrix<T> {
                     .map(|x| TryInto::<i64>::try_into(*x).unwrap())
                     .collect::<Vec<_>>()
                     .as_slice(),
-                Default::default(),
+                config.clone(),
             )?;
             group.create_array_data(
                 "indices",
@@ -556,7 +604,7 @@ impl<T: BackendData> WriteData for CsrMatrix<T> {
                     .map(|x| (*x) as i64)
                     .collect::<Vec<_>>()
                     .as_slice(),
-                Default::default(),
+                config.clone(),
             )?;
         } else {
             panic!(
@@ -569,10 +617,72 @@ impl<T: BackendData> WriteData for CsrMatrix<T> {
     }
 }
 
+/// Write a CSR matrix like `WriteData::write`, with the values and the column
+/// indices written by tasks on the rayon thread pool. The values are split
+/// into blocks of `chunk_size`, which are also the chunks of the pre-sized
+/// datasets, so that each task converts and writes whole chunks at their
+/// offsets and no two tasks write to the same chunk. Backends that compress
+/// the chunks as they are written, e.g., Zarr, compress them in parallel,
+/// while HDF5 serializes the writes through its global lock.
+pub(crate) fn write_csr_par<B, G, T>(
+    csr: &CsrMatrix<T>,
+    location: &G,
+    name: &str,
+    chunk_size: usize,
+) -> Result<DataContainer<B>>
+where
+    B: Backend,
+    G: GroupOp<Backend = B>,
+    T: BackendData,
+{
+    ensure!(chunk_size > 0, "chunk_size must be greater than 0");
+    let nnz = csr.nnz();
+    if nnz == 0 {
+        return csr.write(location, name);
+    }
+    let shape = csr.shape();
+    let group = location.create_group(name)?;
+    group.write_str_attr("encoding-type", "csr_matrix")?;
+    group.write_str_attr("encoding-version", "0.1.0")?;
+    group.write_array_attr("shape", shape.as_ref())?;
+
+    let block_size = chunk_size.min(nnz);
+    let config = WriteConfig { block_size: Some(vec![block_size].into()), ..Default::default() };
+    let data = group.new_dataset::<T>("data", &vec![nnz].into(), config.clone())?;
+    // The same index type as `write`, so that the output is identical.
+    let use_i32 = i32::try_from(shape[1].saturating_sub(1)).is_ok() && i32::try_from(nnz).is_ok();
+    let indices = if use_i32 {
+        group.new_dataset::<i32>("indices", &vec![nnz].into(), config)?
+    } else {
+        group.new_dataset::<i64>("indices", &vec![nnz].into(), config)?
+    };
+
+    let (indptr, col_indices, values) = csr.csr_data();
+    let num_blocks = (nnz + block_size - 1) / block_size;
+    (0..num_blocks).into_par_iter().try_for_each(|i| {
+        let range = i * block_size..((i + 1) * block_size).min(nnz);
+        let select = [SelectInfoElem::from(range.clone())];
+        data.write_array_slice(ArrayView1::from(&values[range.clone()]), &select)?;
+        let cols = &col_indices[range];
+        if use_i32 {
+            indices.write_array_slice(&Array1::from_iter(cols.iter().map(|x| *x as i32)), &select)
+        } else {
+            indices.write_array_slice(&Array1::from_iter(cols.iter().map(|x| *x as i64)), &select)
+        }
+    })?;
+
+    if use_i32 {
+        group.create_array_data("indptr", &indptr.iter().map(|x| *x as i32).collect::<Vec<_>>(), Default::default())?;
+    } else {
+        group.create_array_data("indptr", &indptr.iter().map(|x| *x as i64).collect::<Vec<_>>(), Default::default())?;
+    }
+    Ok(DataContainer::Group(group))
+}
+
 impl<T: BackendData> ReadData for CsrMatrix<T> {
     fn read<B: Backend>(container: &DataContainer<B>) -> Result<Self> {
         let group = container.as_group()?;
-        let shape: Vec<usize> = group.read_array_attr("shape")?.to_vec();
+        let shape: Vec<usize> = group.read_shape_attr()?;
         let data = group.open_dataset("data")?.read_array::<_, Ix1>()?.into_raw_vec();
         let indptr: Vec<usize> = group.open_dataset("indptr")?.read_array::<_, Ix1>()?.into_raw_vec();
         let indices: Vec<usize> = group.open_dataset("indices")?.read_array::<_, Ix1>()?.into_raw_vec();
@@ -586,12 +696,10 @@ impl<T: BackendData> ReadArrayData for CsrMatrix<T> {
     fn get_shape<B: Backend>(container: &DataContainer<B>) -> Result<Shape> {
         Ok(container
             .as_group()?
-            .read_array_attr("shape")?
-            .to_vec()
+            .read_shape_attr()?
             .into())
     }
 
-    // TODO: efficient implementation for slice
     fn read_select<B, S>(container: &DataContainer<B>, info: &[S]) -> Result<Self>
     where
         B: Backend,
@@ -605,33 +713,12 @@ impl<T: BackendData> ReadArrayData for CsrMatrix<T> {
             return Self::read(container);
         }
 
-        let data = if let SelectInfoElem::Slice(s) = info[0].as_ref()  {
-            let group = container.as_group()?;
-            let indptr_slice = if let Some(end) = s.end {
-                SelectInfoElem::from(s.start .. end + 1)
-            } else {
-                SelectInfoElem::from(s.start ..)
-            };
-            let mut indptr: Vec<usize> = group 
-                .open_dataset("indptr")?
-                .read_array_slice(&[indptr_slice])?
-                .to_vec();
-            let lo = indptr[0];
-            let slice = SelectInfoElem::from(lo .. indptr[indptr.len() - 1]);
-            let data: Vec<T> = group.open_dataset("data")?.read_array_slice(&[&slice])?.to_vec();
-            let indices: Vec<usize> = group.open_dataset("indices")?.read_array_slice(&[&slice])?.to_vec();
-            indptr.iter_mut().for_each(|x| *x -= lo);
-            CsrMatrix::try_from_csr_data(
-                indptr.len() - 1,
-                Self::get_shape(container)?[1],
-                indptr,
-                indices,
-                data,
-            ).unwrap().select_axis(1, info[1].as_ref())
-        } else {
-            Self::read(container)?.select(info)
-        };
-        Ok(data)
+        let shape = Self::get_shape(container)?;
+        let major_idx = BoundedSelectInfoElem::new(&info[0], shape[0]);
+        let (indptr, indices, data) = read_cs_major::<_, T>(container.as_group()?, &major_idx)?;
+        let data = CsrMatrix::try_from_csr_data(major_idx.len(), shape[1], indptr, indices, data)
+            .map_err(|e| anyhow!("cannot read csr matrix: {}", e))?;
+        Ok(data.select_axis(1, info[1].as_ref()))
     }
 }
 
@@ -1,6 +1,6 @@
 use crate::backend::*;
 use crate::data::{
-    array::utils::{cs_major_minor_index, cs_major_index, cs_major_slice},
+    array::utils::{cs_major_minor_index, cs_major_index, cs_major_slice, read_cs_major},
     data_traits::*,
     scalar::DynScalar,
     slice::{SelectInfoElem, Shape},
@@ -8,6 +8,7 @@ use crate::data::{
 };
 
 use anyhow::{bail, Result};
+use half::f16;
 use nalgebra_sparse::pattern::SparsityPattern;
 use nalgebra_sparse::{coo::CooMatrix, csr::CsrMatrix};
 use ndarray::Ix1;
@@ -26,6 +27,7 @@ pub enum DynCsrNonCanonical {
     U32(CsrNonCanonical<u32>),
     U64(CsrNonCanonical<u64>),
     Usize(CsrNonCanonical<usize>),
+    F16(CsrNonCanonical<f16>),
     F32(CsrNonCanonical<f32>),
     F64(CsrNonCanonical<f64>),
     Bool(CsrNonCanonical<bool>),
@@ -44,6 +46,7 @@ impl DynCsrNonCanonical {
             DynCsrNonCanonical::U32(data) => data.canonicalize().map(DynCsrMatrix::U32).map_err(Into::into),
             DynCsrNonCanonical::U64(data) => data.canonicalize().map(DynCsrMatrix::U64).map_err(Into::into),
             DynCsrNonCanonical::Usize(data) => data.canonicalize().map(DynCsrMatrix::Usize).map_err(Into::into),
+            DynCsrNonCanonical::F16(data) => data.canonicalize().map(DynCsrMatrix::F16).map_err(Into::into),
             DynCsrNonCanonical::F32(data) => data.canonicalize().map(DynCsrMatrix::F32).map_err(Into::into),
             DynCsrNonCanonical::F64(data) => data.canonicalize().map(DynCsrMatrix::F64).map_err(Into::into),
             DynCsrNonCanonical::Bool(data) => data.canonicalize().map(DynCsrMatrix::Bool).map_err(Into::into),
@@ -80,6 +83,7 @@ impl_into_dyn_csr!(u16, U16);
 impl_into_dyn_csr!(u32, U32);
 impl_into_dyn_csr!(u64, U64);
 impl_into_dyn_csr!(usize, Usize);
+impl_into_dyn_csr!(f16, F16);
 impl_into_dyn_csr!(f32, F32);
 impl_into_dyn_csr!(f64, F64);
 impl_into_dyn_csr!(bool, Bool);
@@ -97,6 +101,7 @@ macro_rules! impl_dyn_csr_matrix {
             DynCsrNonCanonical::U32(data) => $fun!(data),
             DynCsrNonCanonical::U64(data) => $fun!(data),
             DynCsrNonCanonical::Usize(data) => $fun!(data),
+            DynCsrNonCanonical::F16(data) => $fun!(data),
             DynCsrNonCanonical::F32(data) => $fun!(data),
             DynCsrNonCanonical::F64(data) => $fun!(data),
             DynCsrNonCanonical::Bool(data) => $fun!(data),
@@ -117,6 +122,7 @@ impl From<DynCsrMatrix> for DynCsrNonCanonical {
             DynCsrMatrix::U32(data) => DynCsrNonCanonical::U32(data.into()),
             DynCsrMatrix::U64(data) => DynCsrNonCanonical::U64(data.into()),
             DynCsrMatrix::Usize(data) => DynCsrNonCanonical::Usize(data.into()),
+            DynCsrMatrix::F16(data) => DynCsrNonCanonical::F16(data.into()),
             DynCsrMatrix::F32(data) => DynCsrNonCanonical::F32(data.into()),
             DynCsrMatrix::F64(data) => DynCsrNonCanonical::F64(data.into()),
             DynCsrMatrix::Bool(data) => DynCsrNonCanonical::Bool(data.into()),
@@ -146,6 +152,19 @@ impl WriteData for DynCsrNonCanonical {
         }
         impl_dyn_csr_matrix!(self, write_data)
     }
+    fn write_with_config<B: Backend, G: GroupOp<Backend = B>>(
+        &self,
+        location: &G,
+        name: &str,
+        config: WriteConfig,
+    ) -> Result<DataContainer<B>> {
+        macro_rules! write_data {
+            ($data:expr) => {
+                $data.write_with_config(location, name, config)
+            };
+        }
+        impl_dyn_csr_matrix!(self, write_data)
+    }
 }
 
 impl ReadData for DynCsrNonCanonical {
@@ -161,8 +180,12 @@ impl ReadData for DynCsrNonCanonical {
                 ScalarType::U32 => CsrNonCanonical::<u32>::read(container).map(DynCsrNonCanonical::U32),
                 ScalarType::U64 => CsrNonCanonical::<u64>::read(container).map(DynCsrNonCanonical::U64),
                 ScalarType::Usize => CsrNonCanonical::<usize>::read(container).map(DynCsrNonCanonical::Usize),
+                ScalarType::F16 => CsrNonCanonical::<f16>::read(container).map(DynCsrNonCanonical::F16),
                 ScalarType::F32 => CsrNonCanonical::<f32>::read(container).map(DynCsrNonCanonical::F32),
                 ScalarType::F64 => CsrNonCanonical::<f64>::read(container).map(DynCsrNonCanonical::F64),
+                ScalarType::ComplexF32 | ScalarType::ComplexF64 => {
+                    bail!("sparse matrices of complex numbers are not supported")
+                }
                 ScalarType::Bool => CsrNonCanonical::<bool>::read(container).map(DynCsrNonCanonical::Bool),
                 ScalarType::String => {
                     CsrNonCanonical::<String>::read(container).map(DynCsrNonCanonical::String)
@@ -218,6 +241,7 @@ impl ArrayOp for DynCsrNonCanonical {
             DynCsrNonCanonical::I16(_) => Ok(DynCsrNonCanonical::I16(CsrNonCanonical::<i16>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
             DynCsrNonCanonical::I32(_) => Ok(DynCsrNonCanonical::I32(CsrNonCanonical::<i32>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
             DynCsrNonCanonical::I64(_) => Ok(DynCsrNonCanonical::I64(CsrNonCanonical::<i64>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
+            DynCsrNonCanonical::F16(_) => Ok(DynCsrNonCanonical::F16(CsrNonCanonical::<f16>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
             DynCsrNonCanonical::F32(_) => Ok(DynCsrNonCanonical::F32(CsrNonCanonical::<f32>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
             DynCsrNonCanonical::F64(_) => Ok(DynCsrNonCanonical::F64(CsrNonCanonical::<f64>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
             DynCsrNonCanonical::Bool(_) => Ok(DynCsrNonCanonical::Bool(CsrNonCanonical::<bool>::vstack(iter.map(|x| x.try_into().unwrap()))?)),
@@ -231,8 +255,7 @@ impl ReadArrayData for DynCsrNonCanonical {
     fn get_shape<B: Backend>(container: &DataContainer<B>) -> Result<Shape> {
         Ok(container
             .as_group()?
-            .read_array_attr("shape")?
-            .to_vec()
+            .read_shape_attr()?
             .into())
     }
 
@@ -261,10 +284,15 @@ impl ReadArrayData for DynCsrNonCanonical {
                     .map(Into::into),
                 ScalarType::Usize => CsrNonCanonical::<usize>::read_select(container, info)
                     .map(Into::into),
+                ScalarType::F16 => CsrNonCanonical::<f16>::read_select(container, info)
+                    .map(Into::into),
                 ScalarType::F32 => CsrNonCanonical::<f32>::read_select(container, info)
                     .map(Into::into),
                 ScalarType::F64 => CsrNonCanonical::<f64>::read_select(container, info)
                     .map(Into::into),
+                ScalarType::ComplexF32 | ScalarType::ComplexF64 => {
+                    bail!("sparse matrices of complex numbers are not supported")
+                }
                 ScalarType::Bool => CsrNonCanonical::<bool>::read_select(container, info)
                     .map(Into::into),
                 ScalarType::String => CsrNonCanonical::<String>::read_select(container, info)
@@ -482,7 +510,7 @@ impl<T: BackendData + Clone> ArrayOp for CsrNonCanonical<T> {
                         )
                     } else if step < 0 {
                         cs_major_index(
-                            (start..end).step_by(step.abs() as usize).rev(),
+                            (start..end).rev().step_by(step.unsigned_abs()),
                             row_offsets,
                             col_indices,
                             data,
@@ -508,8 +536,8 @@ impl<T: BackendData + Clone> ArrayOp for CsrNonCanonical<T> {
                             &BoundedSelectInfoElem::Slice(col) => {
                                 if col.step < 0 {
                                     cs_major_minor_index(
-                                        (row_start..row_end).step_by(row_step.abs() as usize).rev(),
-                                        (col.start..col.end).step_by(col.step.abs() as usize).rev(),
+                                        (row_start..row_end).rev().step_by(row_step.unsigned_abs()),
+                                        (col.start..col.end).rev().step_by(col.step.unsigned_abs()),
                                         self.ncols(),
                                         row_offsets,
                                         col_indices,
@@ -517,7 +545,7 @@ impl<T: BackendData + Clone> ArrayOp for CsrNonCanonical<T> {
                                     )
                                 } else {
                                     cs_major_minor_index(
-                                        (row_start..row_end).step_by(row_step.abs() as usize).rev(),
+                                        (row_start..row_end).rev().step_by(row_step.unsigned_abs()),
                                         (col.start..col.end).step_by(col.step as usize),
                                         self.ncols(),
                                         row_offsets,
@@ -527,7 +555,7 @@ impl<T: BackendData + Clone> ArrayOp for CsrNonCanonical<T> {
                                 }
                             }
                             BoundedSelectInfoElem::Index(idx) => cs_major_minor_index(
-                                (row_start..row_end).step_by(row_step.abs() as usize).rev(),
+                                (row_start..row_end).rev().step_by(row_step.unsigned_abs()),
                                 idx.iter().copied(),
                                 self.ncols(),
                                 row_offsets,
@@ -541,7 +569,7 @@ impl<T: BackendData + Clone> ArrayOp for CsrNonCanonical<T> {
                                 if col.step < 0 {
                                     cs_major_minor_index(
                                         (row_start..row_end).step_by(row_step as usize),
-                                        (col.start..col.end).step_by(col.step.abs() as usize).rev(),
+                                        (col.start..col.end).rev().step_by(col.step.unsigned_abs()),
                                         self.ncols(),
                                         row_offsets,
                                         col_indices,
@@ -574,7 +602,7 @@ impl<T: BackendData + Clone> ArrayOp for CsrNonCanonical<T> {
                         if col.step < 0 {
                             cs_major_minor_index(
                                 i.iter().copied(),
-                                (col.start..col.end).step_by(col.step.abs() as usize).rev(),
+                                (col.start..col.end).rev().step_by(col.step.unsigned_abs()),
                                 self.ncols(),
                                 row_offsets,
                                 col_indices,
@@ -641,6 +669,17 @@ impl<T: BackendData> WriteData for CsrNonCanonical<T> {
         location: &G,
         name: &str,
     ) -> Result<DataContainer<B>> {
+        self.write_with_config(location, name, Default::default())
+    }
+    /// Only the compression of `config` is used, as the chunk shape and the
+    /// fill value refer to the dense layout of the matrix.
+    fn write_with_config<B: Backend, G: GroupOp<Backend = B>>(
+        &self,
+        location: &G,
+        name: &str,
+        config: WriteConfig,
+    ) -> Result<DataContainer<B>> {
+        let config = WriteConfig { compression: config.compression, ..Default::default() };
         let group = location.create_group(name)?;
         let shape = self.shape();
 
@@ -648,7 +687,7 @@ impl<T: BackendData> WriteData for CsrNonCanonical<T> {
         group.write_str_attr("encoding-version", "0.1.0")?;
         group.write_array_attr("shape", shape.as_ref())?;
 
-        group.create_array_data("data", &self.values(), Default::default())?;
+        group.create_array_data("data", &self.values(), config.clone())?;
 
         let num_cols = shape[1];
         // Use i32 or i64 as indices type in order to be compatible with scipy
@@ -659,7 +698,7 @@ impl<T: BackendData> WriteData for CsrNonCanonical<T> {
                 .map(|x| (*x).try_into().ok())
                 .collect();
             if let Some(indptr_i32) = try_convert_indptr {
-                group.create_array_data("indptr", &indptr_i32, Default::default())?;
+                group.create_array_data("indptr", &indptr_i32, config.clone())?;
                 group.create_array_data(
                     "indices",
                     self.col_indices()
@@ -667,7 +706,7 @@ impl<T: BackendData> WriteData for CsrNonCanonical<T> {
                         .map(|x| (*x) as i32)
                         .collect::<Vec<_>>()
                         .as_slice(),
-                    Default::default(),
+                    config.clone(),
                 )?;
             } else {
                 group.create_array_data(
@@ -677,7 +716,7 @@ impl<T: BackendData> WriteData for CsrNonCanonical<T> {
                         .map(|x| TryInto::<i64>::try_into(*x).unwrap())
                         .collect::<Vec<_>>()
                         .as_slice(),
-                    Default::default(),
+                    config.clone(),
                 )?;
                 group.create_array_data(
                     "indices",
@@ -686,7 +725,7 @@ impl<T: BackendData> WriteData for CsrNonCanonical<T> {
                         .map(|x| (*x) as i64)
                         .collect::<Vec<_>>()
                         .as_slice(),
-                    Default::default(),
+                    config.clone(),
                 )?;
             }
         } else if TryInto::<i64>::try_into(num_cols.saturating_sub(1)).is_ok() {
@@ -697,7 +736,7 @@ impl<T: BackendData> WriteData for CsrNonCanonical<T> {
                     .map(|x| TryInto::<i64>::try_into(*x).unwrap())
                     .collect::<Vec<_>>()
                     .as_slice(),
-                Default::default(),
+                config.clone(),
             )?;
             group.create_array_data(
                 "indices",
@@ -706,7 +745,7 @@ impl<T: BackendData> WriteData for CsrNonCanonical<T> {
                     .map(|x| (*x) as i64)
                     .collect::<Vec<_>>()
                     .as_slice(),
-                Default::default(),
+                config.clone(),
             )?;
         } else {
             panic!(
@@ -722,7 +761,7 @@ impl<T: BackendData> WriteData for CsrNonCanonical<T> {
 impl<T: BackendData> ReadData for CsrNonCanonical<T> {
     fn read<B: Backend>(container: &DataContainer<B>) -> Result<Self> {
         let group = container.as_group()?;
-        let shape: Vec<usize> = group.read_array_attr("shape")?.to_vec();
+        let shape: Vec<usize> = group.read_shape_attr()?;
         let data = group.open_dataset("data")?.read_array::<_, Ix1>()?.into_raw_vec();
         let indptr: Vec<usize> = group.open_dataset("indptr")?.read_array::<_, Ix1>()?.into_raw_vec();
         let indices: Vec<usize> = group.open_dataset("indices")?.read_array::<_, Ix1>()?.into_raw_vec();
@@ -734,12 +773,10 @@ impl<T: BackendData> ReadArrayData for CsrNonCanonical<T> {
     fn get_shape<B: Backend>(container: &DataContainer<B>) -> Result<Shape> {
         Ok(container
             .as_group()?
-            .read_array_attr("shape")?
-            .to_vec()
+            .read_shape_attr()?
             .into())
     }
 
-    // TODO: efficient implementation for slice
     fn read_select<B, S>(container: &DataContainer<B>, info: &[S]) -> Result<Self>
     where
         B: Backend,
@@ -753,33 +790,11 @@ impl<T: BackendData> ReadArrayData for CsrNonCanonical<T> {
             return Self::read(container);
         }
 
-        let data = if let SelectInfoElem::Slice(s) = info[0].as_ref()  {
-            let group = container.as_group()?;
-            let indptr_slice = if let Some(end) = s.end {
-                SelectInfoElem::from(s.start .. end + 1)
-            } else {
-                SelectInfoElem::from(s.start ..)
-            };
-            let mut indptr: Vec<usize> = group 
-                .open_dataset("indptr")?
-                .read_array_slice(&[indptr_slice])?
-                .to_vec();
-            let lo = indptr[0];
-            let slice = SelectInfoElem::from(lo .. indptr[indptr.len() - 1]);
-            let data: Vec<T> = group.open_dataset("data")?.read_array_slice(&[&slice])?.to_vec();
-            let indices: Vec<usize> = group.open_dataset("indices")?.read_array_slice(&[&slice])?.to_vec();
-            indptr.iter_mut().for_each(|x| *x -= lo);
-            Self::from_csr_data(
-                indptr.len() - 1,
-                Self::get_shape(container)?[1],
-                indptr,
-                indices,
-                data,
-            ).select_axis(1, info[1].as_ref())
-        } else {
-            Self::read(container)?.select(info)
-        };
-        Ok(data)
+        let shape = Self::get_shape(container)?;
+        let major_idx = BoundedSelectInfoElem::new(&info[0], shape[0]);
+        let (indptr, indices, data) = read_cs_major::<_, T>(container.as_group()?, &major_idx)?;
+        let data = Self::from_csr_data(major_idx.len(), shape[1], indptr, indices, data);
+        Ok(data.select_axis(1, info[1].as_ref()))
     }
 }
 
@@ -1,14 +1,16 @@
 use crate::backend::{Backend, BackendData, DatasetOp, GroupOp, WriteConfig};
-use crate::data::{SelectInfoElem, Shape};
+use crate::data::{BoundedSelectInfoElem, SelectInfoElem, Shape};
 use crate::ArrayData;
 
 use anyhow::{anyhow, Result};
 use itertools::Itertools;
-use ndarray::{ArrayView, RemoveAxis};
+use ndarray::{ArrayView, Ix1, RemoveAxis};
+use std::ops::Range;
 use smallvec::SmallVec;
 use nalgebra_sparse::{CsrMatrix, pattern::{ SparsityPattern, SparsityPatternFormatError}};
 
 use super::CsrNonCanonical;
+use super::slice::BoundedSlice;
 
 pub(crate) struct ExtendableDataset<B: Backend, T> {
     dataset: B::Dataset,
@@ -67,26 +69,28 @@ impl<B: Backend, T: BackendData> ExtendableDataset<B, T> {
         axis: usize,
         data: ArrayView<'a, T, D>,
     ) -> Result<()> {
+        let (new_size, slice): (Vec<usize>, SmallVec<[SelectInfoElem; 3]>) = self
+            .size
+            .as_ref()
+            .iter()
+            .zip(data.shape())
+            .enumerate()
+            .map(|(i, (x, y))| if i == axis {
+                let s = *x + *y;
+                (s, (*x..s).into())
+            } else if x == y || *x == 0 {
+                (*y, (0..*y).into())
+            } else {
+                panic!("Cannot concatenate arrays of different shapes");
+            }).unzip();
+        let new_size = new_size.into();
+        self.check_or_grow(&new_size, 10000)?;
+        // Chunks without elements still grow the size, e.g., rows of a
+        // matrix with zero columns.
         if !data.is_empty() {
-            let (new_size, slice): (Vec<usize>, SmallVec<[SelectInfoElem; 3]>) = self
-                .size
-                .as_ref()
-                .iter()
-                .zip(data.shape())
-                .enumerate()
-                .map(|(i, (x, y))| if i == axis {
-                    let s = *x + *y;
-                    (s, (*x..s).into())
-                } else if x == y || *x == 0 {
-                    (*y, (0..*y).into())
-                } else {
-                    panic!("Cannot concatenate arrays of different shapes");
-                }).unzip();
-            let new_size = new_size.into();
-            self.check_or_grow(&new_size, 10000)?;
             self.dataset.write_array_slice(data, slice.as_ref())?;
-            self.size = new_size;
         }
+        self.size = new_size;
         Ok(())
     }
 
@@ -213,6 +217,72 @@ where
     (new_offsets, new_indices, new_values)
 }
 
+/// Read the selected rows of a csr_matrix, or columns of a csc_matrix, from
+/// `group`. Runs of consecutive rows are read as contiguous ranges, so strided
+/// or scattered selections only read the entries they need. If the selected
+/// entries make up at least half of the range between the first and the last
+/// selected row, the whole range is read at once instead.
+pub(crate) fn read_cs_major<G, T>(
+    group: &G,
+    major_idx: &BoundedSelectInfoElem,
+) -> Result<(Vec<usize>, Vec<usize>, Vec<T>)>
+where
+    G: GroupOp,
+    T: BackendData,
+{
+    let read_range = |lo: usize, hi: usize| -> Result<(Vec<usize>, Vec<T>)> {
+        let slice = SelectInfoElem::from(lo..hi);
+        let indices = group.open_dataset("indices")?.read_array_slice(&[&slice])?.to_vec();
+        let data = group.open_dataset("data")?.read_array_slice(&[&slice])?.to_vec();
+        Ok((indices, data))
+    };
+
+    if let BoundedSelectInfoElem::Slice(BoundedSlice { start, end, step: 1 }) = major_idx {
+        let end = (*end).max(*start);
+        let offsets: Vec<usize> = group
+            .open_dataset("indptr")?
+            .read_array_slice(&[SelectInfoElem::from(*start..end + 1)])?
+            .to_vec();
+        let (lo, hi) = (offsets[0], offsets[offsets.len() - 1]);
+        let (indices, data) = read_range(lo, hi)?;
+        return Ok((offsets.into_iter().map(|x| x - lo).collect(), indices, data));
+    }
+
+    let offsets: Vec<usize> = group.open_dataset("indptr")?.read_array::<usize, Ix1>()?.into_raw_vec();
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for i in major_idx.iter() {
+        match runs.last_mut() {
+            Some(run) if run.end == i => run.end += 1,
+            _ => runs.push(i..i + 1),
+        }
+    }
+    let (first, last) = match (runs.iter().map(|x| x.start).min(), runs.iter().map(|x| x.end).max()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Ok((vec![0], Vec::new(), Vec::new())),
+    };
+    let nnz: usize = runs.iter().map(|x| offsets[x.end] - offsets[x.start]).sum();
+    let (lo, hi) = (offsets[first], offsets[last]);
+
+    if 2 * nnz >= hi - lo {
+        let (indices, data) = read_range(lo, hi)?;
+        let block_offsets: Vec<usize> = offsets[first..=last].iter().map(|x| x - lo).collect();
+        return Ok(cs_major_index(major_idx.iter().map(|i| i - first), &block_offsets, &indices, &data));
+    }
+
+    let mut new_offsets = Vec::with_capacity(major_idx.len() + 1);
+    let mut new_indices = Vec::with_capacity(nnz);
+    let mut new_data = Vec::with_capacity(nnz);
+    new_offsets.push(0);
+    for run in runs {
+        let (indices, data) = read_range(offsets[run.start], offsets[run.end])?;
+        let (n, base) = (new_indices.len(), offsets[run.start]);
+        new_offsets.extend(run.map(|i| n + offsets[i + 1] - base));
+        new_indices.extend(indices);
+        new_data.extend(data);
+    }
+    Ok((new_offsets, new_indices, new_data))
+}
+
 /// Converts matrix data given in triplet format to unsorted CSR/CSC, retaining any duplicated
 /// indices.
 ///
@@ -1,4 +1,4 @@
-use crate::backend::{Backend, DataContainer, GroupOp, LocationOp, DataType};
+use crate::backend::{Backend, DataContainer, GroupOp, LocationOp, DataType, WriteConfig};
 use crate::data::{
     array::slice::{SelectInfoElem, Shape},
     scalar::DynScalar,
@@ -21,6 +21,18 @@ pub trait WriteData {
         location: &G,
         name: &str,
     ) -> Result<DataContainer<B>>;
+    /// Write the data with the given dataset options, e.g., the chunk shape.
+    /// Sparse matrices only use the compression of `config`, and the other data
+    /// types that are not arrays are written as by `write`.
+    fn write_with_config<B: Backend, G: GroupOp<Backend = B>>(
+        &self,
+        location: &G,
+        name: &str,
+        config: WriteConfig,
+    ) -> Result<DataContainer<B>> {
+        let _ = config;
+        self.write(location, name)
+    }
     fn overwrite<B: Backend>(&self, container: DataContainer<B>) -> Result<DataContainer<B>> {
         let file = container.file()?;
         let path = container.path();
@@ -29,6 +41,19 @@ pub trait WriteData {
         group.delete(name)?;
         self.write(&group, name)
     }
+    /// Like `overwrite`, but write the data with the given dataset options.
+    fn overwrite_with_config<B: Backend>(
+        &self,
+        container: DataContainer<B>,
+        config: WriteConfig,
+    ) -> Result<DataContainer<B>> {
+        let file = container.file()?;
+        let path = container.path();
+        let group = file.open_group(path.parent().unwrap().to_str().unwrap())?;
+        let name = path.file_name().unwrap().to_str().unwrap();
+        group.delete(name)?;
+        self.write_with_config(&group, name, config)
+    }
 }
 
 impl<T> WriteData for &T
@@ -45,6 +70,14 @@ where
     ) -> Result<DataContainer<B>> {
             (*self).write(location, name)
     }
+    fn write_with_config<B: Backend, G: GroupOp<Backend = B>>(
+        &self,
+        location: &G,
+        name: &str,
+        config: WriteConfig,
+    ) -> Result<DataContainer<B>> {
+        (*self).write_with_config(location, name, config)
+    }
 }
 
 /// Anything that has a shape.
@@ -1,4 +1,4 @@
-use crate::data::array::slice::{SelectInfoElem, BoundedSlice};
+use crate::data::array::slice::{mask_to_indices, SelectInfoElem, BoundedSlice};
 
 use ndarray::Slice;
 use std::ops::Deref;
@@ -47,6 +47,14 @@ impl Index {
         }
     }
 
+    /// Whether all names in the index are distinct.
+    pub fn is_unique(&self) -> bool {
+        match self {
+            Index::List(list) => list.index_map.len() == list.items.len(),
+            Index::Intervals(_) | Index::Range(_) => true,
+        }
+    }
+
     pub fn get_index(&self, key: &str) -> Option<usize> {
         match self {
             Index::Intervals(map) => {
@@ -67,10 +75,13 @@ impl Index {
 
     pub fn select(&self, select: &SelectInfoElem) -> Self {
         match BoundedSelectInfoElem::new(select, self.len()) {
-            BoundedSelectInfoElem::Slice(slice) => self.slice(slice.start, slice.end),
-            BoundedSelectInfoElem::Index(index) => {
+            BoundedSelectInfoElem::Slice(slice) if slice.step == 1 && slice.start < slice.end =>
+                self.slice(slice.start, slice.end),
+            // Stepped and reversed slices select rows in the same order as
+            // the row data, see `BoundedSelectInfoElem::to_vec`.
+            elem => {
                 let vec = self.clone().into_vec();
-                index.into_iter().map(|i| vec[*i].clone()).collect()
+                elem.iter().map(|i| vec[i].clone()).collect()
             },
         }
     }
@@ -402,6 +413,15 @@ impl VecVecIndex {
         match select {
             SelectInfoElem::Slice(slice) => (self.split_slice(slice), None),
             SelectInfoElem::Index(index) => self.split_indices(index.as_slice()),
+            SelectInfoElem::Mask(mask) => {
+                assert!(
+                    mask.len() == self.len(),
+                    "mask length does not match the number of elements: {} != {}",
+                    mask.len(),
+                    self.len()
+                );
+                self.split_indices(mask_to_indices(mask).as_slice())
+            }
         }
     }
 
@@ -485,7 +505,7 @@ mod tests {
 
     fn select_strat(n: usize) -> BoxedStrategy<SelectInfoElem> {
         if n == 0 {
-            Just(Vec::new().into()).boxed()
+            Just(Vec::<usize>::new().into()).boxed()
         } else {
             let indices = proptest::collection::vec(0..n, 0..2*n).prop_map(|i| i.into());
             let slice = (0..n).prop_flat_map(move |start| (Just(start), (start+1)..=n).prop_map(|(start, stop)| (start..stop).into()));
@@ -1,7 +1,11 @@
 use crate::backend::*;
 use crate::data::data_traits::*;
+use crate::data::{ArrayData, DynArray};
 
 use anyhow::{Result, bail};
+use half::f16;
+use ndarray::arr0;
+use num::complex::{Complex32, Complex64};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum DynScalar {
@@ -14,12 +18,95 @@ pub enum DynScalar {
     U32(u32),
     U64(u64),
     Usize(usize),
+    F16(f16),
     F32(f32),
     F64(f64),
+    ComplexF32(Complex32),
+    ComplexF64(Complex64),
     Bool(bool),
     String(String),
 }
 
+impl DynScalar {
+    /// The value of the entries that are not stored in a sparse matrix of
+    /// `dtype`, i.e., zero, `false` or the empty string.
+    pub fn zero(dtype: ScalarType) -> Self {
+        match dtype {
+            ScalarType::I8 => DynScalar::I8(0),
+            ScalarType::I16 => DynScalar::I16(0),
+            ScalarType::I32 => DynScalar::I32(0),
+            ScalarType::I64 => DynScalar::I64(0),
+            ScalarType::U8 => DynScalar::U8(0),
+            ScalarType::U16 => DynScalar::U16(0),
+            ScalarType::U32 => DynScalar::U32(0),
+            ScalarType::U64 => DynScalar::U64(0),
+            ScalarType::Usize => DynScalar::Usize(0),
+            ScalarType::F16 => DynScalar::F16(f16::ZERO),
+            ScalarType::F32 => DynScalar::F32(0.0),
+            ScalarType::F64 => DynScalar::F64(0.0),
+            ScalarType::ComplexF32 => DynScalar::ComplexF32(Complex32::new(0.0, 0.0)),
+            ScalarType::ComplexF64 => DynScalar::ComplexF64(Complex64::new(0.0, 0.0)),
+            ScalarType::Bool => DynScalar::Bool(false),
+            ScalarType::String => DynScalar::String(String::new()),
+        }
+    }
+
+    /// Convert a numeric scalar to `dtype`, with the same checks as
+    /// [`ArrayData::cast`]. Scalars that already have the type are returned as is.
+    pub fn cast(self, dtype: ScalarType) -> Result<Self> {
+        fn first<T: BackendData>(arr: DynArray) -> Result<DynScalar> {
+            Ok(T::from_dyn_arr(arr)?.into_iter().next().unwrap().into_dyn())
+        }
+
+        let arr = match ArrayData::from(DynArray::from(self)).cast(dtype)? {
+            ArrayData::Array(x) => x,
+            _ => unreachable!(),
+        };
+        match dtype {
+            ScalarType::I8 => first::<i8>(arr),
+            ScalarType::I16 => first::<i16>(arr),
+            ScalarType::I32 => first::<i32>(arr),
+            ScalarType::I64 => first::<i64>(arr),
+            ScalarType::U8 => first::<u8>(arr),
+            ScalarType::U16 => first::<u16>(arr),
+            ScalarType::U32 => first::<u32>(arr),
+            ScalarType::U64 => first::<u64>(arr),
+            ScalarType::Usize => first::<usize>(arr),
+            ScalarType::F16 => first::<f16>(arr),
+            ScalarType::F32 => first::<f32>(arr),
+            ScalarType::F64 => first::<f64>(arr),
+            ScalarType::ComplexF32 => first::<Complex32>(arr),
+            ScalarType::ComplexF64 => first::<Complex64>(arr),
+            ScalarType::Bool => first::<bool>(arr),
+            ScalarType::String => first::<String>(arr),
+        }
+    }
+}
+
+/// A zero-dimensional array holding the scalar.
+impl From<DynScalar> for DynArray {
+    fn from(x: DynScalar) -> Self {
+        match x {
+            DynScalar::I8(x) => DynArray::I8(arr0(x).into_dyn()),
+            DynScalar::I16(x) => DynArray::I16(arr0(x).into_dyn()),
+            DynScalar::I32(x) => DynArray::I32(arr0(x).into_dyn()),
+            DynScalar::I64(x) => DynArray::I64(arr0(x).into_dyn()),
+            DynScalar::U8(x) => DynArray::U8(arr0(x).into_dyn()),
+            DynScalar::U16(x) => DynArray::U16(arr0(x).into_dyn()),
+            DynScalar::U32(x) => DynArray::U32(arr0(x).into_dyn()),
+            DynScalar::U64(x) => DynArray::U64(arr0(x).into_dyn()),
+            DynScalar::Usize(x) => DynArray::Usize(arr0(x).into_dyn()),
+            DynScalar::F16(x) => DynArray::F16(arr0(x).into_dyn()),
+            DynScalar::F32(x) => DynArray::F32(arr0(x).into_dyn()),
+            DynScalar::F64(x) => DynArray::F64(arr0(x).into_dyn()),
+            DynScalar::ComplexF32(x) => DynArray::ComplexF32(arr0(x).into_dyn()),
+            DynScalar::ComplexF64(x) => DynArray::ComplexF64(arr0(x).into_dyn()),
+            DynScalar::Bool(x) => DynArray::Bool(arr0(x).into_dyn()),
+            DynScalar::String(x) => DynArray::String(arr0(x).into_dyn()),
+        }
+    }
+}
+
 /// macro to implement `From` trait for `DynScalar`
 macro_rules! impl_from_dynscalar {
     ($($from:ident, $to:ident),*) => {
@@ -71,8 +158,11 @@ impl_from_dynscalar!(
     u32, U32,
     u64, U64,
     usize, Usize,
+    f16, F16,
     f32, F32,
     f64, F64,
+    Complex32, ComplexF32,
+    Complex64, ComplexF64,
     bool, Bool,
     String, String
 );
@@ -89,8 +179,11 @@ impl WriteData for DynScalar {
             DynScalar::U32(_) => DataType::Scalar(ScalarType::U32),
             DynScalar::U64(_) => DataType::Scalar(ScalarType::U64),
             DynScalar::Usize(_) => DataType::Scalar(ScalarType::Usize),
+            DynScalar::F16(_) => DataType::Scalar(ScalarType::F16),
             DynScalar::F32(_) => DataType::Scalar(ScalarType::F32),
             DynScalar::F64(_) => DataType::Scalar(ScalarType::F64),
+            DynScalar::ComplexF32(_) => DataType::Scalar(ScalarType::ComplexF32),
+            DynScalar::ComplexF64(_) => DataType::Scalar(ScalarType::ComplexF64),
             DynScalar::Bool(_) => DataType::Scalar(ScalarType::Bool),
             DynScalar::String(_) => DataType::Scalar(ScalarType::String),
         }
@@ -107,8 +200,11 @@ impl WriteData for DynScalar {
             DynScalar::U32(data) => data.write(location, name),
             DynScalar::U64(data) => data.write(location, name),
             DynScalar::Usize(data) => data.write(location, name),
+            DynScalar::F16(data) => data.write(location, name),
             DynScalar::F32(data) => data.write(location, name),
             DynScalar::F64(data) => data.write(location, name),
+            DynScalar::ComplexF32(data) => data.write(location, name),
+            DynScalar::ComplexF64(data) => data.write(location, name),
             DynScalar::Bool(data) => data.write(location, name),
             DynScalar::String(data) => data.write(location, name),
         }
@@ -128,8 +224,11 @@ impl ReadData for DynScalar {
             ScalarType::U32 => Ok(DynScalar::U32(dataset.read_scalar()?)),
             ScalarType::U64 => Ok(DynScalar::U64(dataset.read_scalar()?)),
             ScalarType::Usize => Ok(DynScalar::Usize(dataset.read_scalar()?)),
+            ScalarType::F16 => Ok(DynScalar::F16(dataset.read_scalar()?)),
             ScalarType::F32 => Ok(DynScalar::F32(dataset.read_scalar()?)),
             ScalarType::F64 => Ok(DynScalar::F64(dataset.read_scalar()?)),
+            ScalarType::ComplexF32 => Ok(DynScalar::ComplexF32(dataset.read_scalar()?)),
+            ScalarType::ComplexF64 => Ok(DynScalar::ComplexF64(dataset.read_scalar()?)),
             ScalarType::Bool => Ok(DynScalar::Bool(dataset.read_scalar()?)),
             ScalarType::String => Ok(DynScalar::String(dataset.read_scalar()?)),
         }
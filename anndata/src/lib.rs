@@ -4,9 +4,13 @@ pub mod backend;
 pub mod data;
 pub mod container;
 pub mod reader;
+pub mod writer;
 
 pub use traits::{AnnDataOp, AxisArraysOp, ElemCollectionOp, ArrayElemOp};
-pub use crate::anndata::{AnnData, AnnDataSet, StackedAnnData};
+pub use crate::anndata::{
+    anndata_eq, AnnData, AnnDataBuilder, AnnDataSet, ArrayInfo, DataFrameRow, IntegrityProblem, Join, MemAnnData,
+    Statistics, StackedAnnData, StructureInfo, ValidationWarning, XWriter,
+};
 pub use backend::Backend;
 pub use data::{HasShape, Data, ReadData, WriteData, ArrayData, WriteArrayData, ReadArrayData, ArrayOp};
 pub use container::{
@@ -1,17 +1,34 @@
+use crate::anndata::transpose;
+use crate::backend::{Backend, BackendData, DataContainer, DatasetOp, GroupOp, ScalarType};
 use crate::data::utils::to_csr_data;
-use crate::{data::array::DataFrameIndex, AnnDataOp, ArrayData};
+use crate::{
+    data::array::{DataFrameIndex, DynArray},
+    AnnDataOp, ArrayData, AxisArraysOp, HasShape, ReadData, WriteArrayData,
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use half::f16;
 use flate2::read::MultiGzDecoder;
 use itertools::Itertools;
 use nalgebra_sparse::{coo::CooMatrix, csr::CsrMatrix};
+use ndarray::{Array2, Ix1};
+use polars::prelude::{DataFrame, NamedFrom, SerReader, Series};
 use std::path::Path;
+use std::str::FromStr;
 use std::{error::Error, fmt, io};
 use std::{
     fs::File,
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, Read},
 };
 
+/// Reader for Matrix Market files, e.g., the `matrix.mtx.gz` produced by
+/// CellRanger. The matrix, barcodes and features files may be
+/// gzip-compressed, which is detected from their content rather than from
+/// their extensions, and are decompressed on the fly.
+///
+/// When the entries are sorted by rows (see `is_sorted`), the matrix is
+/// streamed into the output in chunks of rows. Otherwise it is read into
+/// memory as a whole.
 pub struct MMReader {
     reader: Box<dyn BufRead>,
     obs_names: Option<DataFrameIndex>,
@@ -82,51 +99,350 @@ impl MMReader {
     }
 }
 
+/// Open a file for buffered reading, decompressing it if it starts with the
+/// gzip magic number.
 fn open_file<P: AsRef<Path>>(file: P) -> Result<Box<dyn BufRead>> {
-    fn is_gzipped<P: AsRef<Path>>(file: P) -> Result<bool> {
-        Ok(MultiGzDecoder::new(File::open(file)?).header().is_some())
-    }
-
-    let reader: Box<dyn BufRead> = if is_gzipped(&file)? {
-        Box::new(BufReader::new(MultiGzDecoder::new(File::open(file)?)))
+    let path = file.as_ref();
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("cannot open file '{}'", path.display()))?,
+    );
+    let reader: Box<dyn BufRead> = if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Box::new(BufReader::new(MultiGzDecoder::new(reader)))
     } else {
-        Box::new(BufReader::new(File::open(file)?))
+        Box::new(reader)
     };
     Ok(reader)
 }
 
-/*
-// TODO: fix dataframe index
-pub fn import_csv<P>(
-    &self,
-    path: P,
+pub struct CsvReader {
+    reader: Box<dyn BufRead>,
+    delimiter: char,
     has_header: bool,
-    index_column: Option<usize>,
-    delimiter: u8,
-) -> Result<()>
+    has_index: bool,
+}
+
+impl CsvReader {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self {
+            reader: open_file(path)?,
+            delimiter: ',',
+            has_header: true,
+            has_index: true,
+        })
+    }
+
+    /// Set the field delimiter. Default is ','.
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Whether the first row contains the variable names. Default is true.
+    pub fn has_header(mut self, has_header: bool) -> Self {
+        self.has_header = has_header;
+        self
+    }
+
+    /// Whether the first column contains the observation names. Default is true.
+    pub fn has_index(mut self, has_index: bool) -> Self {
+        self.has_index = has_index;
+        self
+    }
+
+    /// Parse the values as `T` and store them as a dense X in `output`.
+    ///
+    /// The file is parsed by polars' CSV reader. Fields may be quoted, and the
+    /// whitespace around them is ignored. The header may or may not contain a
+    /// name for the index column.
+    pub fn finish<T, O>(mut self, output: &O) -> Result<()>
+    where
+        T: FromStr,
+        <T as FromStr>::Err: fmt::Display,
+        Array2<T>: WriteArrayData + Into<ArrayData> + HasShape,
+        O: AnnDataOp,
+    {
+        ensure!(
+            self.delimiter.is_ascii(),
+            "the delimiter must be an ASCII character, got '{}'",
+            self.delimiter
+        );
+        let delimiter = self.delimiter as u8;
+        let mut content = Vec::new();
+        self.reader.read_to_end(&mut content)?;
+        let (header, body) = if self.has_header {
+            let n = content.iter().position(|x| *x == b'\n').map_or(content.len(), |n| n + 1);
+            let body = content.split_off(n);
+            (Some(read_csv_fields(content, delimiter)?), body)
+        } else {
+            (None, content)
+        };
+        let first_line = if self.has_header { 2 } else { 1 };
+
+        let df = read_csv_fields(body, delimiter)?;
+        let mut columns = df.get_columns().iter().map(|x| x.utf8()).collect::<Result<Vec<_>, _>>()?;
+        let index = if self.has_index && !columns.is_empty() {
+            Some(columns.remove(0))
+        } else {
+            None
+        };
+        let num_rows = df.height();
+        let num_cols = columns.len();
+        let mut values = Vec::with_capacity(num_rows * num_cols);
+        for i in 0..num_rows {
+            let line = first_line + i;
+            for (j, column) in columns.iter().enumerate() {
+                let x = column.get(i).ok_or_else(|| anyhow!(
+                    "line {} has no value in column {}", line, j + 1
+                ))?.trim();
+                let val = x.parse::<T>().map_err(|e|
+                    anyhow!("cannot parse '{}' at line {}: {}", x, line, e)
+                )?;
+                values.push(val);
+            }
+        }
+
+        output.set_x(Array2::from_shape_vec((num_rows, num_cols), values)?)?;
+        if let Some(index) = index {
+            output.set_obs_names(
+                index.into_iter().map(|x| x.unwrap_or_default().trim().to_string()).collect()
+            )?;
+        }
+        if let Some(header) = header {
+            let mut var_names: Vec<_> = header.get_columns().iter()
+                .map(|x| Ok(x.utf8()?.get(0).unwrap_or_default().trim().to_string()))
+                .collect::<Result<_>>()?;
+            // The header may or may not contain a name for the index column.
+            if self.has_index && var_names.len() == num_cols + 1 {
+                var_names.remove(0);
+            }
+            ensure!(
+                var_names.len() == num_cols,
+                "the header has {} names, but there are {} columns",
+                var_names.len(),
+                num_cols
+            );
+            output.set_var_names(var_names.into_iter().collect())?;
+        }
+        Ok(())
+    }
+}
+
+/// Read CSV records as strings, so that the values can be parsed with line
+/// numbers in the error messages. Missing and empty fields are read as nulls.
+fn read_csv_fields(content: Vec<u8>, delimiter: u8) -> Result<DataFrame> {
+    if content.iter().all(u8::is_ascii_whitespace) {
+        return Ok(DataFrame::empty());
+    }
+    polars::prelude::CsvReader::new(io::Cursor::new(content))
+        .has_header(false)
+        .with_delimiter(delimiter)
+        .infer_schema(Some(0))
+        .finish()
+        .context("cannot read the CSV file")
+}
+
+/// Reader for Loom files, see http://linnarssonlab.org/loompy/format/.
+///
+/// Loom stores a gene-by-cell matrix, which is transposed into a cell-by-gene
+/// `X`. Column attributes become `obs` and row attributes become `var`;
+/// multidimensional attributes are stored in `obsm` and `varm` instead.
+pub struct LoomReader<B: Backend> {
+    file: B::File,
+    obs_names: String,
+    var_names: String,
+}
+
+impl<B: Backend> LoomReader<B> {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self {
+            file: B::open(path)?,
+            obs_names: "CellID".to_string(),
+            var_names: "Gene".to_string(),
+        })
+    }
+
+    /// Set the column attribute used as observation names. Default is "CellID".
+    pub fn obs_names(mut self, attr: &str) -> Self {
+        self.obs_names = attr.to_string();
+        self
+    }
+
+    /// Set the row attribute used as variable names. Default is "Gene".
+    pub fn var_names(mut self, attr: &str) -> Self {
+        self.var_names = attr.to_string();
+        self
+    }
+
+    pub fn finish<O: AnnDataOp>(self, output: &O) -> Result<()> {
+        let matrix = DynArray::read(&DataContainer::open(&self.file, "matrix")?)?;
+        output.set_x(transpose(matrix.into())?)?;
+
+        if self.file.exists("layers")? {
+            let layers = self.file.open_group("layers")?;
+            for name in layers.list()? {
+                let layer = DynArray::read(&DataContainer::open(&layers, &name)?)?;
+                output.layers().add(&name, transpose(layer.into())?)?;
+            }
+        }
+
+        let (obs, obs_names) = read_loom_attrs::<B, _>(
+            &self.file, "col_attrs", &self.obs_names, |k, v| output.obsm().add(k, v),
+        )?;
+        if let Some(obs) = obs {
+            output.set_obs(obs)?;
+        }
+        if let Some(obs_names) = obs_names {
+            output.set_obs_names(obs_names)?;
+        }
+
+        let (var, var_names) = read_loom_attrs::<B, _>(
+            &self.file, "row_attrs", &self.var_names, |k, v| output.varm().add(k, v),
+        )?;
+        if let Some(var) = var {
+            output.set_var(var)?;
+        }
+        if let Some(var_names) = var_names {
+            output.set_var_names(var_names)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read the attributes in `group`. One-dimensional attributes are returned as
+/// a dataframe, except `index` which is returned as the names. The others are
+/// passed to `add_multi`.
+fn read_loom_attrs<B, F>(
+    file: &B::File,
+    group: &str,
+    index: &str,
+    mut add_multi: F,
+) -> Result<(Option<DataFrame>, Option<DataFrameIndex>)>
 where
-    P: Into<PathBuf>,
+    B: Backend,
+    F: FnMut(&str, ArrayData) -> Result<()>,
 {
-    let mut df = polars::prelude::CsvReader::from_path(path)?
-        .has_header(has_header)
-        .with_delimiter(delimiter)
-        .finish()?;
-    let mut colnames = df.get_column_names_owned();
-    if let Some(idx_col) = index_column {
-        let series = df.drop_in_place(&colnames.remove(idx_col))?;
-        self.set_obs(Some(DataFrame::new(vec![series])?))?;
+    if !file.exists(group)? {
+        return Ok((None, None));
     }
-    if has_header {
-        self.set_var(Some(DataFrame::new(vec![Series::new("Index", colnames)])?))?;
+    let group = file.open_group(group)?;
+    let mut columns = Vec::new();
+    let mut names = None;
+    for name in group.list()? {
+        match DynArray::read(&DataContainer::<B>::open(&group, &name)?)? {
+            DynArray::String(x) if x.ndim() == 1 && name == index => {
+                names = Some(x.into_iter().collect());
+            }
+            x if x.shape().ndim() == 1 => {
+                let mut series = Series::try_from(x)?;
+                series.rename(&name);
+                columns.push(series);
+            }
+            x => add_multi(&name, x.into())?,
+        }
     }
-    let data: Box<dyn MatrixData> = Box::new(
-        df.to_ndarray::<polars::datatypes::Float64Type>()?
-            .into_dyn(),
-    );
-    self.set_x(Some(data))?;
-    Ok(())
+    let df = if columns.is_empty() {
+        None
+    } else {
+        Some(DataFrame::new(columns)?)
+    };
+    Ok((df, names))
+}
+
+/// Reader for the HDF5 files produced by 10x Genomics Cell Ranger, e.g.,
+/// `filtered_feature_bc_matrix.h5`.
+///
+/// Cell Ranger stores a feature-by-barcode matrix in CSC format, which is read
+/// as a barcode-by-feature CSR matrix. Barcodes become `obs_names` and feature
+/// names become `var_names`; feature ids, types and genomes are stored in `var`.
+/// Both the Cell Ranger v3 layout and the older layout with one group per genome
+/// are supported.
+pub struct TenxReader<B: Backend> {
+    file: B::File,
+    genome: Option<String>,
+}
+
+impl<B: Backend> TenxReader<B> {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self {
+            file: B::open(path)?,
+            genome: None,
+        })
+    }
+
+    /// Select the genome to read from files in the Cell Ranger v2 layout.
+    /// By default the first genome is read.
+    pub fn genome(mut self, genome: &str) -> Self {
+        self.genome = Some(genome.to_string());
+        self
+    }
+
+    pub fn finish<O: AnnDataOp>(self, output: &O) -> Result<()> {
+        let is_v3 = self.file.exists("matrix")?;
+        let group = if is_v3 {
+            self.file.open_group("matrix")?
+        } else {
+            let genome = match self.genome {
+                Some(genome) => genome,
+                None => self.file.list()?.into_iter().next()
+                    .ok_or_else(|| anyhow!("cannot find the count matrix"))?,
+            };
+            self.file.open_group(&genome)?
+        };
+
+        let shape: Vec<usize> = group.open_dataset("shape")?.read_array::<_, Ix1>()?.to_vec();
+        ensure!(shape.len() == 2, "the count matrix must be two-dimensional");
+        output.set_x(read_tenx_matrix::<B>(&group, shape[1], shape[0])?)?;
+        output.set_obs_names(read_strings::<B>(&group, "barcodes")?.into_iter().collect())?;
+
+        let (ids, names, mut columns) = if is_v3 {
+            let features = group.open_group("features")?;
+            let mut columns = vec![Series::new(
+                "feature_types",
+                read_strings::<B>(&features, "feature_type")?,
+            )];
+            if features.exists("genome")? {
+                columns.push(Series::new("genome", read_strings::<B>(&features, "genome")?));
+            }
+            (read_strings::<B>(&features, "id")?, read_strings::<B>(&features, "name")?, columns)
+        } else {
+            (read_strings::<B>(&group, "genes")?, read_strings::<B>(&group, "gene_names")?, Vec::new())
+        };
+        columns.insert(0, Series::new("gene_ids", ids));
+        output.set_var(DataFrame::new(columns)?)?;
+        output.set_var_names(names.into_iter().collect())?;
+        Ok(())
+    }
+}
+
+fn read_tenx_matrix<B: Backend>(group: &B::Group, nrows: usize, ncols: usize) -> Result<ArrayData> {
+    fn read<B: Backend, T: BackendData>(
+        group: &B::Group,
+        nrows: usize,
+        ncols: usize,
+    ) -> Result<CsrMatrix<T>> {
+        let data = group.open_dataset("data")?.read_array::<_, Ix1>()?.into_raw_vec();
+        let indptr: Vec<usize> = group.open_dataset("indptr")?.read_array::<_, Ix1>()?.into_raw_vec();
+        let indices: Vec<usize> = group.open_dataset("indices")?.read_array::<_, Ix1>()?.into_raw_vec();
+        CsrMatrix::try_from_csr_data(nrows, ncols, indptr, indices, data)
+            .map_err(|e| anyhow!("cannot read the count matrix: {}", e))
+    }
+
+    let x = match group.open_dataset("data")?.dtype()? {
+        ScalarType::I32 => read::<B, i32>(group, nrows, ncols)?.into(),
+        ScalarType::I64 => read::<B, i64>(group, nrows, ncols)?.into(),
+        ScalarType::U32 => read::<B, u32>(group, nrows, ncols)?.into(),
+        ScalarType::U64 => read::<B, u64>(group, nrows, ncols)?.into(),
+        ScalarType::F16 => read::<B, f16>(group, nrows, ncols)?.into(),
+        ScalarType::F32 => read::<B, f32>(group, nrows, ncols)?.into(),
+        ScalarType::F64 => read::<B, f64>(group, nrows, ncols)?.into(),
+        ty => bail!("unsupported data type of the count matrix: {}", ty),
+    };
+    Ok(x)
+}
+
+fn read_strings<B: Backend>(group: &B::Group, name: &str) -> Result<Vec<String>> {
+    Ok(group.open_dataset(name)?.read_array::<String, Ix1>()?.into_raw_vec())
 }
-*/
 
 #[derive(Debug)]
 pub(crate) enum IoError {
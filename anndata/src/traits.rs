@@ -1,8 +1,9 @@
 use crate::data::*;
 
-use anyhow::Result;
+use anyhow::{ensure, Context, Result};
 use polars::prelude::DataFrame;
 use smallvec::SmallVec;
+use std::path::Path;
 
 /// AnnData container operations.
 pub trait AnnDataOp {
@@ -21,6 +22,21 @@ pub trait AnnDataOp {
 
     fn set_x<D: WriteArrayData + Into<ArrayData> + HasShape>(&self, data: D) -> Result<()>;
 
+    /// Read the rows `start..stop` of X in a single call, e.g., to grab a
+    /// window of observations without iterating over chunks. Only these rows
+    /// are read, which is cheap for dense arrays and CSR matrices.
+    fn read_x_range(&self, start: usize, stop: usize) -> Result<ArrayData> {
+        let x = self.x();
+        let n_rows = x.shape().context("X is empty")?[0];
+        ensure!(
+            start <= stop && stop <= n_rows,
+            "cannot read rows {}..{} of X, which has {} rows",
+            start, stop, n_rows,
+        );
+        x.slice_axis::<ArrayData, _>(0, SelectInfoElem::from(start..stop))?
+            .context("X is empty")
+    }
+
     /// Delete the 'X' element.
     fn del_x(&self) -> Result<()>;
 
@@ -39,6 +55,23 @@ pub trait AnnDataOp {
     /// Chagne the names of variables.
     fn set_var_names(&self, index: DataFrameIndex) -> Result<()>;
 
+    /// Make the names of observations unique. See [`DataFrameIndex::make_unique`].
+    fn obs_names_make_unique(&self) -> Result<()> {
+        let names = self.obs_names();
+        if !names.is_unique() {
+            self.set_obs_names(names.make_unique())?;
+        }
+        Ok(())
+    }
+    /// Make the names of variables unique. See [`DataFrameIndex::make_unique`].
+    fn var_names_make_unique(&self) -> Result<()> {
+        let names = self.var_names();
+        if !names.is_unique() {
+            self.set_var_names(names.make_unique())?;
+        }
+        Ok(())
+    }
+
     fn obs_ix<'a, I: IntoIterator<Item = &'a str>>(&self, names: I) -> Result<Vec<usize>>;
     fn var_ix<'a, I: IntoIterator<Item = &'a str>>(&self, names: I) -> Result<Vec<usize>>;
 
@@ -51,6 +84,27 @@ pub trait AnnDataOp {
     /// Change the variable annotations.
     fn set_var(&self, var: DataFrame) -> Result<()>;
 
+    /// Set the observation annotations and names from a Parquet file. The first
+    /// column is used as the observation names, as written by
+    /// `DataFrameElem::export_parquet`.
+    fn set_obs_from_parquet<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let (obs, index) = dataframe::read_parquet(path)?;
+        if obs.width() > 0 {
+            self.set_obs(obs)?;
+        }
+        self.set_obs_names(index)
+    }
+
+    /// Set the variable annotations and names from a Parquet file. The first
+    /// column is used as the variable names.
+    fn set_var_from_parquet<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let (var, index) = dataframe::read_parquet(path)?;
+        if var.width() > 0 {
+            self.set_var(var)?;
+        }
+        self.set_var_names(index)
+    }
+
     /// Delete the observation annotations.
     fn del_obs(&self) -> Result<()>;
 
@@ -64,6 +118,43 @@ pub trait AnnDataOp {
     fn varp(&self) -> Self::AxisArraysRef<'_>;
     fn layers(&self) -> Self::AxisArraysRef<'_>;
 
+    /// Read a layer, returning `None` if it does not exist. An error is returned
+    /// if the shape of the layer is not `(n_obs, n_vars)`.
+    fn read_layer(&self, key: &str) -> Result<Option<ArrayData>> {
+        self.layers()
+            .try_get(key)?
+            .map(|elem| {
+                check_layer_shape(key, elem.shape(), self.n_obs(), self.n_vars())?;
+                elem.get()
+            })
+            .transpose()
+            .map(Option::flatten)
+    }
+
+    /// Read a slice of a layer, returning `None` if it does not exist. See
+    /// [`AnnDataOp::read_layer`].
+    fn read_layer_slice<S: AsRef<[SelectInfoElem]>>(&self, key: &str, select: S) -> Result<Option<ArrayData>> {
+        self.layers()
+            .try_get(key)?
+            .map(|elem| {
+                check_layer_shape(key, elem.shape(), self.n_obs(), self.n_vars())?;
+                elem.slice(select)
+            })
+            .transpose()
+            .map(Option::flatten)
+    }
+
+    /// Add a layer, writing only the new array. Unlike [`AnnDataOp::set_layers`],
+    /// X and the existing layers are left untouched. An error is returned if
+    /// the layer already exists or its shape is not `(n_obs, n_vars)`.
+    fn add_layer<D: WriteArrayData + HasShape + Into<ArrayData>>(&self, key: &str, data: D) -> Result<()> {
+        let layers = self.layers();
+        ensure!(!layers.contains(key), "layer '{}' already exists", key);
+        let shape = data.shape();
+        ensure!(shape.ndim() == 2, "layer '{}' must be two-dimensional, found shape {}", key, shape);
+        layers.add(key, data)
+    }
+
     fn set_uns<I: Iterator<Item = (String, Data)>>(&self, mut data: I) -> Result<()> {
         self.del_uns()?;
         let uns = self.uns();
@@ -103,9 +194,26 @@ pub trait AnnDataOp {
     fn del_layers(&self) -> Result<()>;
 }
 
+fn check_layer_shape(key: &str, shape: Option<Shape>, n_obs: usize, n_vars: usize) -> Result<()> {
+    if let Some(shape) = shape {
+        ensure!(
+            shape.as_ref() == [n_obs, n_vars],
+            "layer '{}' has shape {}, which does not match the shape of X: {} x {}",
+            key, shape, n_obs, n_vars,
+        );
+    }
+    Ok(())
+}
+
 pub trait ElemCollectionOp {
     fn keys(&self) -> Vec<String>;
 
+    /// Whether an item named `key` exists. Use `get_item` to read it, which
+    /// returns `None` if it does not exist.
+    fn contains(&self, key: &str) -> bool {
+        self.keys().iter().any(|x| x == key)
+    }
+
     fn get_item<D>(&self, key: &str) -> Result<Option<D>>
     where
         D: ReadData + Into<Data> + TryFrom<Data> + Clone,
@@ -117,6 +225,8 @@ pub trait ElemCollectionOp {
         data: D,
     ) -> Result<()>;
 
+    /// Remove the item named `key` from the collection and the underlying
+    /// storage. Removing a key that does not exist is a no-op.
     fn remove(&self, key: &str) -> Result<()>;
 }
 
@@ -126,14 +236,27 @@ pub trait AxisArraysOp {
 
     fn keys(&self) -> Vec<String>;
 
-    fn get(&self, key: &str) -> Option<Self::ArrayElem>;
+    /// Whether an array named `key` exists.
+    fn contains(&self, key: &str) -> bool {
+        self.keys().iter().any(|x| x == key)
+    }
+
+    /// Get the array named `key`. An array that cannot be opened is reported
+    /// as missing; use `try_get` to get the error instead.
+    fn get(&self, key: &str) -> Option<Self::ArrayElem> {
+        self.try_get(key).ok().flatten()
+    }
+
+    /// Get the array named `key`, or the error raised when opening it.
+    fn try_get(&self, key: &str) -> Result<Option<Self::ArrayElem>>;
 
     fn get_item<D>(&self, key: &str) -> Result<Option<D>>
     where
         D: ReadData + Into<ArrayData> + TryFrom<ArrayData> + Clone,
         <D as TryFrom<ArrayData>>::Error: Into<anyhow::Error>,
     {
-        self.get(key).and_then(|x| x.get().transpose()).transpose()
+        self.try_get(key)
+            .and_then(|x| x.and_then(|x| x.get().transpose()).transpose())
             .map_err(|e| e.context(format!("key: {}", key)))
     }
 
@@ -143,7 +266,7 @@ pub trait AxisArraysOp {
         S: AsRef<[SelectInfoElem]>,
         <D as TryFrom<ArrayData>>::Error: Into<anyhow::Error>,
     {
-        self.get(key).and_then(|x| x.slice(slice).transpose()).transpose()
+        self.try_get(key)?.and_then(|x| x.slice(slice).transpose()).transpose()
     }
 
     fn get_item_iter<T>(&self, key: &str, chunk_size: usize
@@ -0,0 +1,203 @@
+use crate::backend::{DataType, ScalarType};
+use crate::data::{CsrNonCanonical, DynCsrNonCanonical};
+use crate::{AnnDataOp, ArrayData, ArrayElemOp, AxisArraysOp, WriteData};
+
+use anyhow::{bail, Context, Result};
+use flate2::{write::GzEncoder, Compression};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Apply `$body` to the CSR matrix `$x` if it has a numeric type.
+macro_rules! with_csr {
+    ($data:expr, $x:ident => $body:expr) => {
+        match $data {
+            DynCsrNonCanonical::I8($x) => $body,
+            DynCsrNonCanonical::I16($x) => $body,
+            DynCsrNonCanonical::I32($x) => $body,
+            DynCsrNonCanonical::I64($x) => $body,
+            DynCsrNonCanonical::U8($x) => $body,
+            DynCsrNonCanonical::U16($x) => $body,
+            DynCsrNonCanonical::U32($x) => $body,
+            DynCsrNonCanonical::U64($x) => $body,
+            DynCsrNonCanonical::Usize($x) => $body,
+            DynCsrNonCanonical::F16($x) => $body,
+            DynCsrNonCanonical::F32($x) => $body,
+            DynCsrNonCanonical::F64($x) => $body,
+            x => bail!("cannot write values of type {} to a Matrix Market file", x.data_type()),
+        }
+    };
+}
+
+/// Writer for Matrix Market files, the format used by CellRanger.
+///
+/// The matrix is streamed in chunks of rows, so the data is never fully loaded
+/// into memory. The number of non-zero entries, which appears in the header,
+/// is computed in a first pass over the data. Files whose names end with `.gz`
+/// are gzip-compressed.
+pub struct MMWriter {
+    path: PathBuf,
+    layer: Option<String>,
+    obs_names: Option<PathBuf>,
+    var_names: Option<PathBuf>,
+    chunk_size: usize,
+}
+
+impl MMWriter {
+    pub fn to_path<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            layer: None,
+            obs_names: None,
+            var_names: None,
+            chunk_size: 1000,
+        }
+    }
+
+    /// Write a layer instead of X.
+    pub fn layer(mut self, key: &str) -> Self {
+        self.layer = Some(key.to_string());
+        self
+    }
+
+    /// Write the observation names to `path`, one per line, e.g., `barcodes.tsv.gz`.
+    pub fn obs_names<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.obs_names = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Write the variable names to `path`, e.g., `features.tsv.gz`. As in
+    /// CellRanger's output, each line has three tab-separated columns: the
+    /// name is used as both the feature ID and the feature name, and the
+    /// feature type is "Gene Expression".
+    pub fn var_names<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.var_names = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the number of rows read at a time. Default is 1000.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    pub fn finish<O: AnnDataOp>(self, input: &O) -> Result<()> {
+        match &self.layer {
+            None => self.write_matrix(&input.x()).context("failed to write X")?,
+            Some(key) => {
+                let layer = input.layers().try_get(key)?.with_context(|| format!("layer '{}' not found", key))?;
+                self.write_matrix(&layer).with_context(|| format!("failed to write layer '{}'", key))?;
+            }
+        }
+        if let Some(path) = &self.obs_names {
+            let mut writer = Output::create(path)?;
+            for name in input.obs_names() {
+                writeln!(writer, "{}", name)?;
+            }
+            writer.finish()?;
+        }
+        if let Some(path) = &self.var_names {
+            let mut writer = Output::create(path)?;
+            for name in input.var_names() {
+                writeln!(writer, "{}\t{}\tGene Expression", name, name)?;
+            }
+            writer.finish()?;
+        }
+        Ok(())
+    }
+
+    fn write_matrix<E: ArrayElemOp>(&self, elem: &E) -> Result<()> {
+        let shape = elem.shape().context("the matrix is empty")?;
+        if shape.ndim() != 2 {
+            bail!("only two-dimensional arrays can be written, found shape {}", shape);
+        }
+
+        // First pass: find the number of non-zero entries and the value type.
+        let mut nnz = 0;
+        let mut field = "real";
+        for (chunk, _, _) in elem.iter::<ArrayData>(self.chunk_size) {
+            let chunk = to_csr(chunk)?;
+            field = match chunk.data_type() {
+                DataType::CsrMatrix(ScalarType::F16 | ScalarType::F32 | ScalarType::F64) => "real",
+                _ => "integer",
+            };
+            nnz += with_csr!(chunk, x => x.nnz());
+        }
+
+        let mut writer = Output::create(&self.path)?;
+        writeln!(writer, "%%MatrixMarket matrix coordinate {} general", field)?;
+        writeln!(writer, "{} {} {}", shape[0], shape[1], nnz)?;
+        for (chunk, start, _) in elem.iter::<ArrayData>(self.chunk_size) {
+            with_csr!(to_csr(chunk)?, x => write_csr(&mut writer, &x, start)?);
+        }
+        writer.finish()
+    }
+}
+
+/// Convert a chunk of rows to CSR format. Zeros in dense arrays are dropped.
+fn to_csr(data: ArrayData) -> Result<DynCsrNonCanonical> {
+    let data = match data {
+        ArrayData::Array(_) => data.to_sparse(0.0)?,
+        ArrayData::CscMatrix(_) => data.to_dense()?.to_sparse(0.0)?,
+        _ => data,
+    };
+    data.try_into()
+}
+
+/// Write the entries of a chunk whose first row is row `start` of the matrix.
+/// Matrix Market indices are 1-based.
+fn write_csr<W: Write, T: Display>(writer: &mut W, csr: &CsrNonCanonical<T>, start: usize) -> Result<()> {
+    let (offsets, indices, values) = csr.csr_data();
+    for (i, row) in offsets.windows(2).enumerate() {
+        for k in row[0]..row[1] {
+            writeln!(writer, "{} {} {}", start + i + 1, indices[k] + 1, values[k])?;
+        }
+    }
+    Ok(())
+}
+
+/// A buffered output file, which is gzip-compressed if its name ends with `.gz`.
+enum Output {
+    Plain(BufWriter<File>),
+    Gzip(BufWriter<GzEncoder<File>>),
+}
+
+impl Output {
+    fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("cannot create file '{}'", path.display()))?;
+        if path.extension().map_or(false, |x| x == "gz") {
+            Ok(Output::Gzip(BufWriter::new(GzEncoder::new(file, Compression::default()))))
+        } else {
+            Ok(Output::Plain(BufWriter::new(file)))
+        }
+    }
+
+    /// Flush the buffer and write the gzip trailer.
+    fn finish(self) -> Result<()> {
+        match self {
+            Output::Plain(mut w) => w.flush()?,
+            Output::Gzip(w) => {
+                w.into_inner().map_err(|e| e.into_error())?.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Output::Plain(w) => w.write(buf),
+            Output::Gzip(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::Plain(w) => w.flush(),
+            Output::Gzip(w) => w.flush(),
+        }
+    }
+}
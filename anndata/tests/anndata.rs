@@ -2,8 +2,16 @@ mod common;
 use common::*;
 
 use proptest::prelude::*;
-use anndata::*;
+use anndata::{*, data::{CategoricalArray, DataFrameIndex, DynArray, DynScalar, Mapping, RaggedArray, SelectInfoElem}};
+use nalgebra::DMatrix;
+use nalgebra_sparse::{CscMatrix, CsrMatrix};
+use ndarray::{array, Array1, Array2, Ix1};
+use polars::prelude::*;
+use anndata::backend::{DataContainer, DatasetOp, GroupOp, FileOp, LocationOp, InMemory, ScalarType};
 use anndata_hdf5::H5;
+use anndata_zarr::Zarr;
+use half::f16;
+use num::complex::{Complex32, Complex64};
 
 fn test_basic<B: Backend>() {
     with_tmp_dir(|dir| {
@@ -17,6 +25,44 @@ fn test_basic<B: Backend>() {
     })
 }
 
+fn test_read_csv<B: Backend>() {
+    use anndata::reader::CsvReader;
+    use ndarray::{array, Array2};
+    with_tmp_dir(|dir| {
+        let csv = dir.join("matrix.csv");
+        std::fs::write(&csv, "cell,g1,\"g,2\",g3\nc1,1,2,3\nc2, 4.5 ,5,6\n").unwrap();
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        CsvReader::from_path(&csv).unwrap().finish::<f64, _>(&adata).unwrap();
+        assert_eq!(adata.obs_names().into_vec(), ["c1", "c2"]);
+        assert_eq!(adata.var_names().into_vec(), ["g1", "g,2", "g3"]);
+        assert_eq!(adata.x().get::<Array2<f64>>().unwrap().unwrap(), array![[1.0, 2.0, 3.0], [4.5, 5.0, 6.0]]);
+
+        std::fs::write(&csv, "1\t2\n3\t4\n").unwrap();
+        let adata = AnnData::<B>::new(dir.join("test2.h5ad")).unwrap();
+        CsvReader::from_path(&csv).unwrap()
+            .delimiter('\t')
+            .has_header(false)
+            .has_index(false)
+            .finish::<i32, _>(&adata)
+            .unwrap();
+        assert_eq!(adata.x().get::<Array2<i32>>().unwrap().unwrap(), array![[1, 2], [3, 4]]);
+
+        let read = |content: &str| {
+            std::fs::write(&csv, content).unwrap();
+            let adata = AnnData::<B>::new(dir.join("test3.h5ad")).unwrap();
+            let result = CsvReader::from_path(&csv).unwrap().finish::<f32, _>(&adata);
+            result.map_err(|e| format!("{:#}", e))
+        };
+        let err = read("cell,g1,g2\nc1,1,2\nc2,3,x\n").unwrap_err();
+        assert!(err.contains("cannot parse 'x' at line 3"), "{}", err);
+        let err = read("cell,g1,g2\nc1,1,2\nc2,3\n").unwrap_err();
+        assert!(err.contains("line 3 has no value in column 2"), "{}", err);
+        let err = read("cell,g1,g2\nc1,1,2\nc2,3,4,5\n").unwrap_err();
+        assert!(err.contains("cannot read the CSV file"), "{}", err);
+        assert!(CsvReader::from_path(dir.join("missing.csv")).is_err());
+    })
+}
+
 fn test_save<B: Backend>() {
     with_tmp_dir(|dir| {
         let input = dir.join("input.h5ad");
@@ -47,12 +93,3192 @@ fn test_save<B: Backend>() {
     });
 }
 
-#[test]
-fn test_basic_h5() {
-    test_basic::<H5>()
+fn test_concat<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let names = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>().into();
+        let csr = |x: &Array2<i32>| CsrMatrix::from(&DMatrix::from_row_slice(x.nrows(), x.ncols(), x.as_slice().unwrap()));
+
+        let x1 = array![[1, 2, 3], [4, 5, 6]];
+        let ann1 = AnnData::<B>::new(dir.join("ann1.h5ad")).unwrap();
+        ann1.set_x(&x1).unwrap();
+        ann1.set_obs_names(names(&["c1", "c2"])).unwrap();
+        ann1.set_var_names(names(&["a", "b", "c"])).unwrap();
+        ann1.obsm().add("pca", array![[1.0, 2.0], [3.0, 4.0]]).unwrap();
+        ann1.layers().add("counts", csr(&x1)).unwrap();
+
+        let x2 = array![[7, 8, 9]];
+        let ann2 = AnnData::<B>::new(dir.join("ann2.h5ad")).unwrap();
+        ann2.set_x(&x2).unwrap();
+        ann2.set_obs_names(names(&["c3"])).unwrap();
+        ann2.set_var_names(names(&["b", "c", "d"])).unwrap();
+        ann2.obsm().add("pca", array![[5.0, 6.0]]).unwrap();
+        ann2.layers().add("counts", csr(&x2)).unwrap();
+
+        let inner = AnnData::concat(&[&ann1, &ann2], dir.join("inner.h5ad"), Join::Inner).unwrap();
+        let expected = array![[2, 3], [5, 6], [7, 8]];
+        assert_eq!(inner.var_names().into_vec(), vec!["b", "c"]);
+        assert_eq!(inner.obs_names().into_vec(), vec!["c1", "c2", "c3"]);
+        assert_eq!(inner.x().get::<Array2<i32>>().unwrap().unwrap(), expected);
+        assert_eq!(inner.layers().get_item::<CsrMatrix<i32>>("counts").unwrap().unwrap(), csr(&expected));
+        assert_eq!(
+            inner.obsm().get_item::<Array2<f64>>("pca").unwrap().unwrap(),
+            array![[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]],
+        );
+
+        let outer = AnnData::concat(&[&ann1, &ann2], dir.join("outer.h5ad"), Join::Outer).unwrap();
+        let expected = array![[1, 2, 3, 0], [4, 5, 6, 0], [0, 7, 8, 9]];
+        assert_eq!(outer.var_names().into_vec(), vec!["a", "b", "c", "d"]);
+        assert_eq!(outer.x().get::<Array2<i32>>().unwrap().unwrap(), expected);
+        assert_eq!(outer.layers().get_item::<CsrMatrix<i32>>("counts").unwrap().unwrap(), csr(&expected));
+    })
 }
 
-#[test]
-fn test_save_h5() {
-    test_save::<H5>()
-}
\ No newline at end of file
+fn test_transpose<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let names = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>().into();
+        let x = array![[1, 0, 3], [0, 5, 6]];
+        let csr = CsrMatrix::from(&DMatrix::from_row_slice(2, 3, x.as_slice().unwrap()));
+        let adata = AnnData::<B>::new(dir.join("input.h5ad")).unwrap();
+        adata.set_x(&csr).unwrap();
+        adata.set_obs_names(names(&["c1", "c2"])).unwrap();
+        adata.set_var_names(names(&["a", "b", "c"])).unwrap();
+        adata.obsm().add("pca", array![[1.0, 2.0], [3.0, 4.0]]).unwrap();
+        adata.layers().add("dense", &x).unwrap();
+
+        let t = adata.transpose(dir.join("output.h5ad")).unwrap();
+        let x_t = x.t().as_standard_layout().into_owned();
+        assert_eq!(t.obs_names().into_vec(), vec!["a", "b", "c"]);
+        assert_eq!(t.var_names().into_vec(), vec!["c1", "c2"]);
+        assert_eq!(
+            t.x().get::<CscMatrix<i32>>().unwrap().unwrap(),
+            CscMatrix::from(&DMatrix::from_row_slice(3, 2, x_t.as_slice().unwrap())),
+        );
+        assert_eq!(t.layers().get_item::<Array2<i32>>("dense").unwrap().unwrap(), x_t);
+        assert_eq!(
+            t.varm().get_item::<Array2<f64>>("pca").unwrap().unwrap(),
+            array![[1.0, 2.0], [3.0, 4.0]],
+        );
+        assert!(t.obsm().keys().is_empty());
+    })
+}
+
+fn test_write_config<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let x = Array2::from_shape_fn((50, 40), |(i, j)| (i * 40 + j) as i32);
+        let config = |block: (usize, usize)| backend::WriteConfig {
+            block_size: Some(block.into()),
+            ..Default::default()
+        };
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.set_x_with_config(&x, config((7, 40))).unwrap();
+        adata.obsm().add_with_config("dense", &x, config((50, 3))).unwrap();
+
+        // Row slices are independent of the chunk layout.
+        let rows = s![5..20, ..];
+        assert_eq!(
+            adata.x().slice::<Array2<i32>, _>(&rows).unwrap().unwrap(),
+            x.slice(ndarray::s![5..20, ..]),
+        );
+        assert_eq!(
+            adata.obsm().get_item_slice::<Array2<i32>, _>("dense", &rows).unwrap().unwrap(),
+            x.slice(ndarray::s![5..20, ..]),
+        );
+
+        // Overwriting with a different chunk shape.
+        adata.set_x_with_config(&x, config((50, 1))).unwrap();
+        assert_eq!(adata.x().get::<Array2<i32>>().unwrap().unwrap(), x);
+
+        // The compression is given per call.
+        let uncompressed = || backend::WriteConfig {
+            compression: None,
+            ..Default::default()
+        };
+        let csr = CsrMatrix::from(&DMatrix::from_fn(50, 40, |i, j| {
+            if (i + j) % 3 == 0 { (i * 40 + j) as f64 } else { 0.0 }
+        }));
+        adata.set_x_with_config(&csr, uncompressed()).unwrap();
+        assert_eq!(adata.x().get::<CsrMatrix<f64>>().unwrap().unwrap(), csr);
+        let csc = CscMatrix::from(&csr);
+        adata.obsm().add_with_config("csc", &csc, uncompressed()).unwrap();
+        assert_eq!(adata.obsm().get_item::<CscMatrix<f64>>("csc").unwrap().unwrap(), csc);
+    })
+}
+
+fn test_chunk_cache<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let x = Array2::from_shape_fn((100, 10), |(i, j)| (i * 10 + j) as i32);
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.set_x(&x).unwrap();
+
+        // Room for three chunks of 10 rows.
+        let capacity = 3 * 10 * 10 * std::mem::size_of::<i32>();
+        let elem = adata.x();
+        elem.inner().enable_chunk_cache(capacity);
+        for _ in 0..2 {
+            for (chunk, i, j) in elem.iter::<Array2<i32>>(10) {
+                assert_eq!(chunk, x.slice(ndarray::s![i..j, ..]));
+                let inner = elem.inner();
+                let cache = inner.chunk_cache().unwrap();
+                assert!(cache.size() <= cache.capacity());
+                assert!(cache.contains(i, j));
+            }
+        }
+        let inner = elem.inner();
+        let cache = inner.chunk_cache().unwrap();
+        assert_eq!(cache.len(), 3);
+        assert!(!cache.contains(0, 10));
+        assert!(cache.contains(70, 80) && cache.contains(80, 90) && cache.contains(90, 100));
+        drop(inner);
+
+        // Chunks larger than the capacity are never cached.
+        assert_eq!(elem.iter::<Array2<i32>>(50).count(), 2);
+        assert!(!elem.inner().chunk_cache().unwrap().contains(0, 50));
+
+        // Overwriting X invalidates the cache.
+        adata.set_x(&x).unwrap();
+        assert!(elem.inner().chunk_cache().unwrap().is_empty());
+
+        // So does subsetting the AnnData object.
+        assert_eq!(elem.iter::<Array2<i32>>(10).count(), 10);
+        let rows: Vec<usize> = (0..100).rev().collect();
+        adata.subset([data::SelectInfoElem::from(rows.clone()), data::SelectInfoElem::full()]).unwrap();
+        assert!(elem.inner().chunk_cache().unwrap().is_empty());
+        let x = x.select(ndarray::Axis(0), &rows);
+        for (chunk, i, j) in elem.iter::<Array2<i32>>(10) {
+            assert_eq!(chunk, x.slice(ndarray::s![i..j, ..]));
+        }
+    })
+}
+
+fn test_chunked_cols<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let x = Array2::from_shape_fn((20, 47), |(i, j)| {
+            if (i + j) % 4 == 0 { (i * 47 + j) as i32 } else { 0 }
+        });
+        let csr = CsrMatrix::from(&DMatrix::from_fn(20, 47, |i, j| x[[i, j]]));
+        let csc = CscMatrix::from(&csr);
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.set_x(&x).unwrap();
+        adata.layers().add("csr", &csr).unwrap();
+        adata.layers().add("csc", &csc).unwrap();
+
+        let chunks = adata.x().chunked_cols::<Array2<i32>>(10);
+        assert_eq!(chunks.len(), 5);
+        let mut n = 0;
+        for (chunk, i, j) in chunks {
+            assert_eq!(i, n);
+            assert_eq!(chunk, x.slice(ndarray::s![.., i..j]));
+            n = j;
+        }
+        assert_eq!(n, 47);
+
+        let chunks = adata.layers().get("csr").unwrap().chunked_cols::<CsrMatrix<i32>>(10);
+        assert_eq!(chunks.len(), 5);
+        for (chunk, i, j) in chunks {
+            assert_eq!(chunk, CsrMatrix::from(&DMatrix::from_fn(20, j - i, |r, c| x[[r, i + c]])));
+        }
+        let chunks = adata.layers().get("csc").unwrap().chunked_cols::<CscMatrix<i32>>(10);
+        for (chunk, i, j) in chunks {
+            assert_eq!(chunk, CscMatrix::from(&DMatrix::from_fn(20, j - i, |r, c| x[[r, i + c]])));
+        }
+    })
+}
+
+fn test_dataset_to_adata<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let x1 = Array2::from_shape_fn((3, 4), |(i, j)| (i * 4 + j) as i32);
+        let x2 = Array2::from_shape_fn((2, 4), |(i, j)| (100 + i * 4 + j) as i32);
+        let ann1 = AnnData::<B>::new(dir.join("ann1.h5ad")).unwrap();
+        ann1.set_x(&x1).unwrap();
+        ann1.set_obs(df!("a" => &[1, 2, 3]).unwrap()).unwrap();
+        ann1.obsm().add("pca", &x1).unwrap();
+        let ann2 = AnnData::<B>::new(dir.join("ann2.h5ad")).unwrap();
+        ann2.set_x(&x2).unwrap();
+        ann2.set_obs(df!("a" => &[4, 5], "b" => &[0, 0]).unwrap()).unwrap();
+        ann2.obsm().add("pca", &x2).unwrap();
+        let dataset = AnnDataSet::<B>::new(
+            [("ann1", ann1), ("ann2", ann2)],
+            dir.join("dataset.h5ads"),
+            "sample",
+        ).unwrap();
+
+        let adata = dataset.to_adata::<B, _>(dir.join("out.h5ad"), 2).unwrap();
+        let x = ndarray::concatenate(ndarray::Axis(0), &[x1.view(), x2.view()]).unwrap();
+        assert_eq!(adata.x().get::<Array2<i32>>().unwrap().unwrap(), x);
+        assert_eq!(adata.obsm().get_item::<Array2<i32>>("pca").unwrap().unwrap(), x);
+        let obs = adata.read_obs().unwrap();
+        assert_eq!(obs.get_column_names(), vec!["sample", "a"]);
+        let a: Vec<_> = obs.column("a").unwrap().i32().unwrap().into_no_null_iter().collect();
+        assert_eq!(a, vec![1, 2, 3, 4, 5]);
+        assert!(!adata.uns().keys().contains(&"AnnDataSet".to_string()));
+    })
+}
+
+fn test_dataset_components<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let x1 = Array2::from_shape_fn((3, 4), |(i, j)| (i * 4 + j) as i32);
+        let x2 = Array2::from_shape_fn((2, 4), |(i, j)| (100 + i * 4 + j) as i32);
+        let x3 = Array2::from_shape_fn((4, 4), |(i, j)| (200 + i * 4 + j) as i32);
+        let new_adata = |name: &str, x: &Array2<i32>| {
+            let adata = AnnData::<B>::new(dir.join(name)).unwrap();
+            adata.set_x(x).unwrap();
+            adata.set_obs_names((0..x.nrows()).map(|i| format!("{}_{}", name, i)).collect()).unwrap();
+            adata
+        };
+        let dataset = AnnDataSet::<B>::new(
+            [("ann1", new_adata("ann1.h5ad", &x1)), ("ann2", new_adata("ann2.h5ad", &x2))],
+            dir.join("dataset.h5ads"),
+            "sample",
+        ).unwrap();
+
+        dataset.add_component("ann3", new_adata("ann3.h5ad", &x3)).unwrap();
+        assert_eq!(dataset.n_obs(), 9);
+        let x = ndarray::concatenate(ndarray::Axis(0), &[x1.view(), x2.view(), x3.view()]).unwrap();
+        assert_eq!(dataset.adatas().inner().get_x().data::<Array2<i32>>().unwrap().unwrap(), x);
+        let samples: Vec<_> = dataset.read_obs().unwrap().column("sample").unwrap()
+            .utf8().unwrap().into_no_null_iter().map(|x| x.to_string()).collect();
+        assert_eq!(samples, ["ann1", "ann1", "ann1", "ann2", "ann2", "ann3", "ann3", "ann3", "ann3"]);
+        assert_eq!(dataset.obs_names().into_vec()[5], "ann3.h5ad_0");
+
+        let bad = AnnData::<B>::new(dir.join("bad.h5ad")).unwrap();
+        bad.set_x(Array2::<i32>::zeros((2, 3))).unwrap();
+        assert!(dataset.add_component("bad", bad).is_err());
+        assert!(dataset.add_component("ann1", new_adata("dup.h5ad", &x1)).is_err());
+
+        dataset.remove_component("ann2").unwrap().close().unwrap();
+        assert_eq!(dataset.n_obs(), 7);
+        let x = ndarray::concatenate(ndarray::Axis(0), &[x1.view(), x3.view()]).unwrap();
+        assert_eq!(dataset.adatas().inner().get_x().data::<Array2<i32>>().unwrap().unwrap(), x);
+        assert_eq!(dataset.obs_names().len(), 7);
+        let locations: DataFrame = dataset.get_anno().uns().get_item("AnnDataSet").unwrap().unwrap();
+        let keys: Vec<_> = locations.column("keys").unwrap().utf8().unwrap().into_no_null_iter().collect();
+        assert_eq!(keys, ["ann1", "ann3"]);
+        assert!(dataset.remove_component("ann2").is_err());
+    })
+}
+
+fn test_ragged<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let rows = vec![vec![1i32, 2], vec![], vec![3, 4, 5], vec![6]];
+        let ragged = RaggedArray::from_rows(rows.clone()).unwrap();
+        assert_eq!(ragged.offsets(), [0, 2, 2, 5, 6]);
+        assert_eq!(ragged.get(&[2, 1]), Some(4i32.into()));
+        assert_eq!(ragged.get(&[1, 0]), None);
+        assert!(RaggedArray::new(DynArray::from(array![1.0f64, 2.0]), vec![0, 3]).is_err());
+
+        adata.obsm().add("neighbors", &ragged).unwrap();
+        assert_eq!(adata.n_obs(), 4);
+        assert_eq!(
+            adata.obsm().get("neighbors").unwrap().inner().dtype(),
+            anndata::backend::DataType::RaggedArray(ScalarType::I32),
+        );
+        assert_eq!(adata.obsm().get_item::<RaggedArray>("neighbors").unwrap().unwrap(), ragged);
+
+        let expected = RaggedArray::from_rows(vec![rows[3].clone(), rows[1].clone(), rows[2].clone()]).unwrap();
+        let select = [SelectInfoElem::from(vec![3, 1, 2])];
+        assert_eq!(adata.obsm().get_item_slice::<RaggedArray, _>("neighbors", &select).unwrap().unwrap(), expected);
+        let expected = RaggedArray::from_rows(rows[1..3].to_vec()).unwrap();
+        let select = [SelectInfoElem::from(1..3)];
+        assert_eq!(adata.obsm().get_item_slice::<RaggedArray, _>("neighbors", &select).unwrap().unwrap(), expected);
+
+        let stacked = RaggedArray::vstack([ragged.clone(), expected.clone()].into_iter()).unwrap();
+        assert_eq!(stacked.len(), 6);
+        assert_eq!(stacked.row(5), DynArray::from(array![3, 4, 5]));
+
+        let ragged = ArrayData::from(ragged).cast(ScalarType::F64).unwrap();
+        assert_eq!(ragged.data_type(), anndata::backend::DataType::RaggedArray(ScalarType::F64));
+    })
+}
+
+fn test_record_array<B: Backend>() {
+    use anndata::data::{RecordArray, SelectInfoElem};
+    with_tmp_dir(|dir| {
+        let path = dir.join("test.h5ad");
+        let adata = AnnData::<B>::new(&path).unwrap();
+        let spatial = RecordArray::new(vec![
+            ("x".to_string(), DynArray::from(array![1.5f64, 3.0, 5.0])),
+            ("y".to_string(), DynArray::from(array![2.0f32, 4.5, 6.0])),
+            ("label".to_string(), DynArray::from(array!["a".to_string(), "b".to_string(), "c".to_string()])),
+        ]).unwrap();
+        assert_eq!(spatial.len(), 3);
+        assert_eq!(spatial.get(&[1, 2]), Some("b".to_string().into()));
+        assert!(RecordArray::new(vec![
+            ("x".to_string(), DynArray::from(array![1.0f64, 2.0])),
+            ("x".to_string(), DynArray::from(array![1i32, 2])),
+        ]).is_err());
+        assert!(RecordArray::new(vec![
+            ("x".to_string(), DynArray::from(array![1.0f64, 2.0])),
+            ("y".to_string(), DynArray::from(array![1i32])),
+        ]).is_err());
+
+        adata.obsm().add("spatial", &spatial).unwrap();
+        assert_eq!(adata.n_obs(), 3);
+        assert_eq!(
+            adata.obsm().get("spatial").unwrap().inner().dtype(),
+            anndata::backend::DataType::RecordArray,
+        );
+        assert_eq!(adata.obsm().get_item::<RecordArray>("spatial").unwrap().unwrap(), spatial);
+
+        let select = [SelectInfoElem::from(vec![2, 0])];
+        let expected = spatial.select(&select);
+        assert_eq!(expected.field("label").unwrap(), &DynArray::from(array!["c".to_string(), "a".to_string()]));
+        assert_eq!(adata.obsm().get_item_slice::<RecordArray, _>("spatial", &select).unwrap().unwrap(), expected);
+
+        adata.subset(&[vec![2, 0].into(), SelectInfoElem::full()]).unwrap();
+        assert_eq!(adata.obsm().get_item::<RecordArray>("spatial").unwrap().unwrap(), expected);
+        adata.close().unwrap();
+
+        let adata = AnnData::<B>::open(B::open(&path).unwrap()).unwrap();
+        assert_eq!(adata.obsm().get_item::<RecordArray>("spatial").unwrap().unwrap(), expected);
+    })
+}
+
+fn test_f16<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = array![[1.5f32, 0.0, -2.25], [0.0, 4.0, 0.5]].mapv(f16::from_f32);
+        adata.set_x(x.clone()).unwrap();
+        assert_eq!(adata.x().get::<Array2<f16>>().unwrap().unwrap(), x);
+        assert_eq!(
+            adata.x().slice::<Array2<f16>, _>(s![1..2, ..]).unwrap().unwrap(),
+            x.slice(ndarray::s![1..2, ..]),
+        );
+
+        let csr = CsrMatrix::from(&DMatrix::from_row_slice(2, 3, x.as_slice().unwrap()));
+        adata.obsm().add("csr", csr.clone()).unwrap();
+        assert_eq!(adata.obsm().get_item::<CsrMatrix<f16>>("csr").unwrap().unwrap(), csr);
+    })
+}
+
+fn test_append_x_rows<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let names = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        let x = Array2::from_shape_fn((6, 3), |(i, j)| (i * 3 + j) as i32);
+        let adata = AnnData::<B>::new(dir.join("dense.h5ad")).unwrap();
+        adata.append_x_rows([x.slice(ndarray::s![..2, ..]).to_owned()].into_iter(), Some(names(&["a", "b"]))).unwrap();
+        adata.append_x_rows(
+            [x.slice(ndarray::s![2..3, ..]).to_owned(), x.slice(ndarray::s![3..6, ..]).to_owned()].into_iter(),
+            Some(names(&["c", "d", "e", "f"])),
+        ).unwrap();
+        assert_eq!(adata.n_obs(), 6);
+        assert_eq!(adata.x().get::<Array2<i32>>().unwrap().unwrap(), x);
+        assert_eq!(adata.obs_names().into_vec(), names(&["a", "b", "c", "d", "e", "f"]));
+
+        // Failed appends leave X unchanged.
+        assert!(adata.append_x_rows([Array2::<i32>::zeros((1, 3))].into_iter(), None).is_err());
+        assert!(adata.append_x_rows([Array2::<i32>::zeros((1, 3))].into_iter(), Some(names(&["g", "h"]))).is_err());
+        assert!(adata.append_x_rows([Array2::<i32>::zeros((1, 2))].into_iter(), Some(names(&["g"]))).is_err());
+        assert_eq!(adata.n_obs(), 6);
+        assert_eq!(adata.x().get::<Array2<i32>>().unwrap().unwrap(), x);
+
+        let csr = CsrMatrix::from(&DMatrix::from_row_slice(6, 3, &[
+            1, 0, 2,
+            0, 0, 0,
+            0, 3, 0,
+            4, 0, 5,
+            0, 0, 6,
+            7, 0, 0,
+        ]));
+        let chunk = |start, end| {
+            let x: ArrayData = csr.clone().into();
+            x.select_axis(0, &SelectInfoElem::from(start..end))
+        };
+        let adata = AnnData::<B>::new(dir.join("sparse.h5ad")).unwrap();
+        adata.set_x(chunk(0, 2)).unwrap();
+        adata.append_x_rows([chunk(2, 5), chunk(5, 6)].into_iter(), None).unwrap();
+        assert_eq!(adata.n_obs(), 6);
+        assert_eq!(adata.x().get::<CsrMatrix<i32>>().unwrap().unwrap(), csr);
+        assert!(adata.append_x_rows([Array2::<i32>::zeros((1, 3))].into_iter(), None).is_err());
+        assert_eq!(adata.x().get::<CsrMatrix<i32>>().unwrap().unwrap(), csr);
+    })
+}
+
+fn test_write_select_by_chunk<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let x = Array2::from_shape_fn((30, 5), |(i, j)| if (i + j) % 3 == 0 { 0 } else { (i * 5 + j) as i32 });
+        let csr = CsrMatrix::from(&DMatrix::from_row_slice(30, 5, x.as_slice().unwrap()));
+        let adata = AnnData::<B>::new(dir.join("input.h5ad")).unwrap();
+        adata.set_x(csr.clone()).unwrap();
+        adata.layers().add("dense", x.clone()).unwrap();
+
+        let rows = vec![29, 4, 4, 0, 17, 8, 22, 11];
+        let selections = [
+            [rows.clone().into(), vec![4, 0, 2].into()],
+            [(3..27).into(), SelectInfoElem::full()],
+        ];
+        for (i, select) in selections.iter().enumerate() {
+            let out = dir.join(format!("output{}.h5ad", i));
+            adata.write_select_by_chunk::<B, _, _>(select, &out, 3).unwrap();
+            let expected = ArrayData::from(x.clone()).select(select);
+            let subset = AnnData::<B>::open(B::open(&out).unwrap()).unwrap();
+            assert_eq!(subset.x().get::<ArrayData>().unwrap().unwrap(), ArrayData::from(csr.clone()).select(select));
+            assert_eq!(subset.layers().get_item::<ArrayData>("dense").unwrap().unwrap(), expected);
+        }
+    })
+}
+
+fn test_string_array<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let labels = array![["T", "CD4"], ["B", "naive"], ["NK", ""], ["T", "CD8"]].mapv(|x| x.to_string());
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.obsm().add("labels", labels.clone()).unwrap();
+        adata.uns().add("notes", array!["a", "b"].mapv(|x| x.to_string())).unwrap();
+        assert_eq!(adata.obsm().get_item::<Array2<String>>("labels").unwrap().unwrap(), labels);
+        assert_eq!(
+            adata.uns().get_item::<ndarray::Array1<String>>("notes").unwrap().unwrap(),
+            array!["a", "b"].mapv(|x| x.to_string()),
+        );
+
+        adata.subset(&[vec![3, 0].into(), SelectInfoElem::full()]).unwrap();
+        assert_eq!(
+            adata.obsm().get_item::<Array2<String>>("labels").unwrap().unwrap(),
+            labels.select(ndarray::Axis(0), &[3, 0]),
+        );
+    })
+}
+
+fn test_nullable<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let obs = df!(
+            "count" => &[Some(1i64), None, Some(3)],
+            "flag" => &[None, Some(true), Some(false)],
+            "score" => &[Some(0.5f64), None, Some(1.5)],
+            "batch" => &[Some("a"), None, Some("")],
+        ).unwrap();
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.set_obs(obs.clone()).unwrap();
+        let obs_in = adata.read_obs().unwrap();
+        assert!(obs_in.column("count").unwrap().series_equal_missing(obs.column("count").unwrap()));
+        assert!(obs_in.column("flag").unwrap().series_equal_missing(obs.column("flag").unwrap()));
+        // Missing strings are distinct from empty strings.
+        assert_eq!(
+            obs_in.column("batch").unwrap().utf8().unwrap().into_iter().collect::<Vec<_>>(),
+            vec![Some("a"), None, Some("")],
+        );
+        assert!(adata.verify_integrity().unwrap().is_empty());
+        // Missing floats are stored as NaN.
+        assert!(obs_in.column("score").unwrap().f64().unwrap().get(1).unwrap().is_nan());
+
+        let subset = adata.get_obs().inner().select_axis(0, SelectInfoElem::from(vec![2, 1])).unwrap();
+        assert_eq!(
+            subset.column("count").unwrap().i64().unwrap().into_iter().collect::<Vec<_>>(),
+            vec![Some(3), None],
+        );
+        assert_eq!(
+            subset.column("batch").unwrap().utf8().unwrap().into_iter().collect::<Vec<_>>(),
+            vec![Some(""), None],
+        );
+    })
+}
+
+fn test_map_chunks<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let x = Array2::from_shape_fn((7, 3), |(i, j)| (i * 3 + j) as f64);
+        let adata = AnnData::<B>::new(dir.join("dense.h5ad")).unwrap();
+        adata.set_x(x.clone()).unwrap();
+        adata.x().map_chunks(3, |chunk| {
+            let chunk: Array2<f64> = chunk.try_into()?;
+            Ok((chunk + 1.0).into())
+        }).unwrap();
+        assert_eq!(adata.x().get::<Array2<f64>>().unwrap().unwrap(), x.clone() + 1.0);
+
+        // Changing the number of columns or the data type is an error.
+        assert!(adata.x().map_chunks(3, |chunk| {
+            let chunk: Array2<f64> = chunk.try_into()?;
+            Ok(chunk.slice(ndarray::s![.., ..2]).to_owned().into())
+        }).is_err());
+        assert!(adata.x().map_chunks(3, |chunk| {
+            let chunk: Array2<f64> = chunk.try_into()?;
+            Ok(chunk.mapv(|x| x as i32).into())
+        }).is_err());
+
+        let csr = CsrMatrix::from(&DMatrix::from_row_slice(5, 3, &[
+            1, 0, 2,
+            0, 0, 0,
+            0, 3, 0,
+            4, 0, 5,
+            0, 0, 6,
+        ]));
+        let adata = AnnData::<B>::new(dir.join("sparse.h5ad")).unwrap();
+        adata.set_x(csr.clone()).unwrap();
+        adata.x().map_chunks(2, |chunk| {
+            let chunk: CsrMatrix<i32> = chunk.try_into()?;
+            Ok((chunk * 10).into())
+        }).unwrap();
+        assert_eq!(adata.x().get::<CsrMatrix<i32>>().unwrap().unwrap(), csr * 10);
+    })
+}
+
+fn test_obs_column<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let obs = df!(
+            "a" => &[1i32, 2, 3],
+            "b" => &["x", "y", "z"],
+        ).unwrap();
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.set_obs(obs.clone()).unwrap();
+        let obs_elem = adata.get_obs();
+        assert_eq!(obs_elem.inner().column("b").unwrap(), obs.column("b").unwrap());
+        assert!(obs_elem.inner().column("c").is_err());
+
+        // The cached column is updated when the dataframe changes.
+        obs_elem.inner().set_column("b", Series::new("b", &["u", "v", "w"])).unwrap();
+        assert_eq!(obs_elem.inner().column("b").unwrap(), &Series::new("b", &["u", "v", "w"]));
+        assert_eq!(obs_elem.inner().column("a").unwrap(), obs.column("a").unwrap());
+    })
+}
+
+fn test_rename<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = Array2::from_shape_fn((3, 2), |(i, j)| (i * 2 + j) as i32);
+        adata.set_x(x.clone()).unwrap();
+        adata.set_obs(df!("a" => &[1i32, 2, 3], "b" => &["x", "y", "z"]).unwrap()).unwrap();
+        adata.obsm().add("pca", x.clone()).unwrap();
+        adata.obsm().add("umap", x.clone()).unwrap();
+
+        adata.obsm().rename("pca", "X_pca").unwrap();
+        assert!(adata.obsm().rename("umap", "X_pca").is_err());
+        assert!(adata.obsm().rename("missing", "other").is_err());
+        let mut keys = adata.obsm().keys();
+        keys.sort();
+        assert_eq!(keys, vec!["X_pca", "umap"]);
+        assert_eq!(adata.obsm().get_item::<Array2<i32>>("X_pca").unwrap().unwrap(), x);
+
+        let obs = adata.get_obs();
+        obs.rename_column("a", "count").unwrap();
+        assert!(obs.rename_column("b", "count").is_err());
+        assert!(obs.rename_column("missing", "c").is_err());
+        assert_eq!(adata.read_obs().unwrap().get_column_names(), vec!["count", "b"]);
+        assert_eq!(obs.inner().column("count").unwrap(), &Series::new("count", &[1i32, 2, 3]));
+        assert_eq!(adata.x().get::<Array2<i32>>().unwrap().unwrap(), x);
+    })
+}
+
+fn test_try_inner<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.set_x(Array2::<i32>::zeros((3, 2))).unwrap();
+        let x = adata.get_x().clone();
+        assert!(x.try_inner().is_some());
+        assert_eq!(x.with_inner(|x| x.shape()[0]), Some(3));
+        adata.close().unwrap();
+        assert!(x.try_inner().is_none());
+        assert_eq!(x.with_inner(|x| x.shape()[0]), None);
+    })
+}
+
+fn test_subset_obsp<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let dist = Array2::from_shape_fn((5, 5), |(i, j)| (i as f64 - j as f64).abs());
+        let graph = CsrMatrix::from(&DMatrix::from_row_slice(5, 5, &[
+            0, 1, 0, 0, 2,
+            1, 0, 3, 0, 0,
+            0, 3, 0, 4, 0,
+            0, 0, 4, 0, 5,
+            2, 0, 0, 5, 0,
+        ]));
+        let dense_graph = Array2::from_shape_fn((5, 5), |(i, j)| {
+            graph.get_entry(i, j).unwrap().into_value()
+        });
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.set_x(Array2::<f64>::zeros((5, 2))).unwrap();
+        adata.obsp().add("distances", dist.clone()).unwrap();
+        adata.obsp().add("connectivities", graph).unwrap();
+
+        let idx = vec![4, 0, 2, 0];
+        fn submatrix<T: Copy>(x: &Array2<T>, idx: &[usize]) -> Array2<T> {
+            Array2::from_shape_fn((idx.len(), idx.len()), |(i, j)| x[[idx[i], idx[j]]])
+        }
+        let select = [idx.clone().into(), SelectInfoElem::full()];
+
+        adata.write_select::<B, _, _>(&select, dir.join("out.h5ad")).unwrap();
+        let out = AnnData::<B>::open(B::open(dir.join("out.h5ad")).unwrap()).unwrap();
+        assert_eq!(out.obsp().get_item::<Array2<f64>>("distances").unwrap().unwrap(), submatrix(&dist, &idx));
+        let csr: CsrMatrix<i32> = out.obsp().get_item("connectivities").unwrap().unwrap();
+        assert_eq!(DMatrix::from(&csr), DMatrix::from_row_slice(4, 4, submatrix(&dense_graph, &idx).as_slice().unwrap()));
+        out.close().unwrap();
+
+        adata.subset(select).unwrap();
+        assert_eq!(adata.obsp().get_item::<Array2<f64>>("distances").unwrap().unwrap(), submatrix(&dist, &idx));
+        let csr: CsrMatrix<i32> = adata.obsp().get_item("connectivities").unwrap().unwrap();
+        assert_eq!(DMatrix::from(&csr), DMatrix::from_row_slice(4, 4, submatrix(&dense_graph, &idx).as_slice().unwrap()));
+    })
+}
+
+fn test_describe<B: Backend>() {
+    use anndata::backend::{DataType, ScalarType};
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let info = adata.describe().unwrap();
+        assert!(info.x.is_none() && info.obs.is_empty() && info.uns.is_empty());
+
+        adata.set_x(Array2::<f32>::zeros((3, 2))).unwrap();
+        adata.set_obs(df!(
+            "a" => &[1i32, 2, 3],
+            "b" => &[Some(true), None, Some(false)],
+        ).unwrap()).unwrap();
+        adata.obsm().add("umap", Array2::<f64>::zeros((3, 2))).unwrap();
+        adata.obsm().add("pca", Array2::<f64>::zeros((3, 5))).unwrap();
+        adata.layers().add("counts", CsrMatrix::<u32>::zeros(3, 2)).unwrap();
+        adata.uns().add("title", "test".to_string()).unwrap();
+
+        let info = adata.describe().unwrap();
+        assert_eq!((info.n_obs, info.n_vars), (3, 2));
+        let x = info.x.unwrap();
+        assert_eq!(x.dtype, DataType::Array(ScalarType::F32));
+        assert_eq!(x.shape.as_ref(), &[3, 2]);
+        assert_eq!(info.obs, vec![
+            ("a".to_string(), DataType::Array(ScalarType::I32)),
+            ("b".to_string(), DataType::Array(ScalarType::Bool)),
+        ]);
+        assert_eq!(info.obsm.iter().map(|(k, v)| (k.as_str(), v.shape[1])).collect::<Vec<_>>(), vec![("pca", 5), ("umap", 2)]);
+        assert_eq!(info.layers[0].1.dtype, DataType::CsrMatrix(ScalarType::U32));
+        assert_eq!(info.uns, vec![("title".to_string(), DataType::Scalar(ScalarType::String))]);
+    })
+}
+
+fn test_add_column<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.set_obs(df!("a" => &[1i32, 2, 3], "b" => &["x", "y", "z"]).unwrap()).unwrap();
+        let obs = adata.get_obs();
+
+        obs.add_column(Series::new("c", &[0.5f64, 1.5, 2.5])).unwrap();
+        obs.add_column(Series::new("a", &[4i32, 5, 6])).unwrap();
+        assert!(obs.add_column(Series::new("d", &[1i32, 2])).is_err());
+        assert_eq!(
+            obs.inner().get_column_names().iter().collect::<Vec<_>>(),
+            ["a", "b", "c"],
+        );
+        assert_eq!(
+            obs.inner().data().unwrap(),
+            &df!(
+                "a" => &[4i32, 5, 6],
+                "b" => &["x", "y", "z"],
+                "c" => &[0.5f64, 1.5, 2.5],
+            ).unwrap(),
+        );
+
+        // Columns added while the dataframe is in memory are also kept in sync.
+        obs.add_column(Series::new("b", &["u", "v", "w"])).unwrap();
+        assert_eq!(obs.inner().column("b").unwrap(), &Series::new("b", &["u", "v", "w"]));
+    })
+}
+
+fn test_anndata_eq<B: Backend>() {
+    with_tmp_dir(|dir| {
+        fn fill<B: Backend>(adata: &AnnData<B>, eps: f64) {
+            let x = Array2::from_shape_fn((3, 2), |(i, j)| (i * 2 + j) as f64 + eps);
+            adata.set_x(x.clone()).unwrap();
+            adata.set_obs(df!("a" => &[1i32, 2, 3], "b" => &[0.1f64 + eps, 0.2, 0.3]).unwrap()).unwrap();
+            adata.obsm().add("pca", x.mapv(|v| v as f32)).unwrap();
+            adata.layers().add("counts", x.mapv(|v| v as i64)).unwrap();
+            adata.uns().add("scale", 0.5f64 + eps).unwrap();
+        }
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        fill(&adata, 0.0);
+
+        // Equal across backends, up to a small floating point error.
+        let other = AnnData::<InMemory>::in_memory().unwrap();
+        fill(&other, 1e-9);
+        assert!(anndata_eq(&adata, &other).unwrap());
+
+        other.layers().add("counts", Array2::<i64>::zeros((3, 2))).unwrap();
+        assert!(!anndata_eq(&adata, &other).unwrap());
+
+        let other = AnnData::<InMemory>::in_memory().unwrap();
+        fill(&other, 1e-3);
+        assert!(!anndata_eq(&adata, &other).unwrap());
+
+        let other = AnnData::<InMemory>::in_memory().unwrap();
+        fill(&other, 0.0);
+        other.uns().add("extra", 1i32).unwrap();
+        assert!(!anndata_eq(&adata, &other).unwrap());
+    })
+}
+
+fn test_mask<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = Array2::from_shape_fn((4, 3), |(i, j)| (i * 3 + j) as i32);
+        let csr = CsrMatrix::from(&DMatrix::from_row_slice(4, 3, x.as_slice().unwrap()));
+        adata.set_x(x.clone()).unwrap();
+        adata.layers().add("csr", csr).unwrap();
+        adata.set_obs_names(["a", "b", "c", "d"].into_iter().map(String::from).collect()).unwrap();
+
+        let mask: SelectInfoElem = vec![true, false, true, true].into();
+        let select = [mask.clone(), vec![false, true, true].into()];
+        let expected = array![[1, 2], [7, 8], [10, 11]];
+        assert_eq!(adata.x().slice::<Array2<i32>, _>(&select).unwrap().unwrap(), expected);
+        let sub: CsrMatrix<i32> = adata.layers().get_item_slice("csr", &select).unwrap().unwrap();
+        assert_eq!(
+            sub,
+            CsrMatrix::from(&DMatrix::from_row_slice(3, 2, &[1, 2, 7, 8, 10, 11])),
+        );
+
+        // The mask must cover the whole axis.
+        let short: SelectInfoElem = vec![true, false].into();
+        let long: SelectInfoElem = vec![true; 5].into();
+        adata.set_obs(df!("n" => &[1, 2, 3, 4]).unwrap()).unwrap();
+        for bad in [&short, &long] {
+            let select = [bad.clone(), SelectInfoElem::full()];
+            assert!(adata.x().slice::<Array2<i32>, _>(&select).is_err());
+            assert!(adata.layers().get_item_slice::<CsrMatrix<i32>, _>("csr", &select).is_err());
+            assert!(adata.get_obs().inner().select_axis(0, bad).is_err());
+            assert!(adata.subset(&select).is_err());
+        }
+        adata.layers().get("csr").unwrap().inner().enable_cache();
+        adata.layers().get_item::<CsrMatrix<i32>>("csr").unwrap();
+        assert!(adata.layers().get_item_slice::<CsrMatrix<i32>, _>("csr", [long, SelectInfoElem::full()]).is_err());
+
+        adata.subset(&[mask, SelectInfoElem::full()]).unwrap();
+        assert_eq!(adata.obs_names().into_vec(), ["a", "c", "d"]);
+        assert_eq!(adata.x().get::<Array2<i32>>().unwrap().unwrap(), x.select(ndarray::Axis(0), &[0, 2, 3]));
+    })
+}
+
+fn test_write_progress<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let x = Array2::from_shape_fn((30, 5), |(i, j)| (i * 5 + j) as i32);
+        let csr = CsrMatrix::from(&DMatrix::from_row_slice(30, 5, x.as_slice().unwrap()));
+        let adata = AnnData::<B>::new(dir.join("input.h5ad")).unwrap();
+        adata.set_x(csr).unwrap();
+        adata.layers().add("dense", x).unwrap();
+
+        let mut calls = Vec::new();
+        let select = [(3..27).into(), SelectInfoElem::full()];
+        adata.write_select_by_chunk_with_progress::<B, _, _, _>(
+            &select, dir.join("output.h5ad"), 5, |done, total| calls.push((done, total))
+        ).unwrap();
+        let expected: Vec<_> = [5, 10, 15, 20, 24, 29, 34, 39, 44, 48].into_iter().map(|x| (x, 48)).collect();
+        assert_eq!(calls, expected);
+    })
+}
+
+fn test_typed_index<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let path = dir.join("test.h5ad");
+        let adata = AnnData::<B>::new(&path).unwrap();
+        let obs_names = DataFrameIndex::try_from(DynArray::from(array![10i64, 20, 30].into_dyn())).unwrap();
+        let var_names = DataFrameIndex::try_from(DynArray::from(array![0.5f64, 1.0].into_dyn())).unwrap();
+        adata.set_obs_names(obs_names).unwrap();
+        adata.set_var_names(var_names).unwrap();
+
+        assert_eq!(adata.obs_ix(["20"]).unwrap(), vec![1]);
+        assert_eq!(adata.obs_names().dtype(), ScalarType::I64);
+        adata.close().unwrap();
+
+        let file = B::open(&path).unwrap();
+        let index = file.open_group("obs").unwrap().open_dataset("index").unwrap();
+        assert_eq!(index.dtype().unwrap(), ScalarType::I64);
+        assert_eq!(index.read_array::<i64, Ix1>().unwrap(), array![10, 20, 30]);
+        file.close().unwrap();
+
+        let adata = AnnData::<B>::open(B::open(&path).unwrap()).unwrap();
+        assert_eq!(adata.obs_names().dtype(), ScalarType::I64);
+        assert_eq!(adata.obs_names().to_array().unwrap(), DynArray::from(array![10i64, 20, 30].into_dyn()));
+        assert_eq!(adata.var_names().dtype(), ScalarType::F64);
+        assert_eq!(adata.var_names().into_vec(), vec!["0.5", "1"]);
+        assert_eq!(adata.var_ix(["1"]).unwrap(), vec![1]);
+
+        // Renaming duplicated names turns the index into strings.
+        let index = DataFrameIndex::try_from(DynArray::from(array![1u8, 1].into_dyn())).unwrap();
+        assert_eq!(index.make_unique().dtype(), ScalarType::String);
+    })
+}
+
+fn test_read_layer<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let path = dir.join("test.h5ad");
+        let x = Array2::from_shape_fn((4, 3), |(i, j)| (i * 3 + j) as i32);
+        let adata = AnnData::<B>::new(&path).unwrap();
+        adata.set_x(&x).unwrap();
+        adata.layers().add("counts", x.mapv(|v| v as f64)).unwrap();
+        adata.layers().add("bad", x.mapv(|v| v as f64)).unwrap();
+
+        assert_eq!(adata.read_layer("counts").unwrap().unwrap(), ArrayData::from(x.mapv(|v| v as f64)));
+        assert_eq!(
+            adata.read_layer_slice("counts", s![1..3, ..]).unwrap().unwrap(),
+            ArrayData::from(x.slice(ndarray::s![1..3, ..]).mapv(|v| v as f64)),
+        );
+        assert!(adata.read_layer("missing").unwrap().is_none());
+        adata.close().unwrap();
+
+        // Replace a layer with a 3-dimensional array behind the AnnData's back.
+        let file = B::open_rw(&path).unwrap();
+        let layers = file.open_group("layers").unwrap();
+        layers.delete("bad").unwrap();
+        ndarray::ArrayD::<f64>::zeros(vec![4, 3, 2]).write(&layers, "bad").unwrap();
+        file.close().unwrap();
+
+        let adata = AnnData::<B>::open(B::open(&path).unwrap()).unwrap();
+        assert!(adata.read_layer("counts").unwrap().is_some());
+        assert!(adata.read_layer("bad").is_err());
+        assert!(adata.read_layer_slice("bad", s![0..1, ..]).is_err());
+    })
+}
+
+fn test_cast<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = ArrayData::from(array![[1i64, 2], [3, 4]]);
+        adata.set_x(x.cast(ScalarType::F32).unwrap()).unwrap();
+        assert_eq!(adata.x().get::<ArrayData>().unwrap().unwrap(), ArrayData::from(array![[1f32, 2.0], [3.0, 4.0]]));
+
+        let csr = CsrMatrix::try_from_csr_data(2, 3, vec![0, 1, 2], vec![0, 2], vec![1.5f64, 2.0]).unwrap();
+        let expected = CsrMatrix::try_from_csr_data(2, 3, vec![0, 1, 2], vec![0, 2], vec![1i32, 2]).unwrap();
+        adata.obsm().add("counts", ArrayData::from(csr).cast(ScalarType::I32).unwrap()).unwrap();
+        assert_eq!(adata.obsm().get_item::<CsrMatrix<i32>>("counts").unwrap().unwrap(), expected);
+
+        assert!(ArrayData::from(array![1e10f64]).cast(ScalarType::I32).is_err());
+        assert!(ArrayData::from(array![f64::NAN]).cast(ScalarType::I64).is_err());
+        assert!(ArrayData::from(array![-1i8]).cast(ScalarType::U8).is_err());
+        assert!(ArrayData::from(array![1i8]).cast(ScalarType::Bool).is_err());
+        assert!(ArrayData::from(array![true]).cast(ScalarType::F32).is_err());
+    })
+}
+
+fn test_dense_sparse<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = array![[0.0f64, 0.5, 0.0], [2.0, 0.0, 0.01]];
+        let csr = CsrMatrix::try_from_csr_data(2, 3, vec![0, 1, 3], vec![1, 0, 2], vec![0.5f64, 2.0, 0.01]).unwrap();
+        assert_eq!(ArrayData::from(x.clone()).to_sparse(0.0).unwrap(), ArrayData::from(csr.clone()));
+        assert_eq!(ArrayData::from(csr.clone()).to_dense().unwrap(), ArrayData::from(x.clone()));
+        assert_eq!(ArrayData::from(CscMatrix::from(&csr)).to_dense().unwrap(), ArrayData::from(x.clone()));
+        assert!(ArrayData::from(array![1.0f64, 2.0]).to_sparse(0.0).is_err());
+
+        adata.set_x(&csr).unwrap();
+        adata.x().to_dense().unwrap();
+        assert_eq!(adata.x().inner().dtype(), anndata::backend::DataType::Array(ScalarType::F64));
+        assert_eq!(adata.x().get::<Array2<f64>>().unwrap().unwrap(), x);
+
+        adata.x().to_sparse(0.1).unwrap();
+        let expected = CsrMatrix::try_from_csr_data(2, 3, vec![0, 1, 2], vec![1, 0], vec![0.5f64, 2.0]).unwrap();
+        assert_eq!(adata.x().inner().dtype(), anndata::backend::DataType::CsrMatrix(ScalarType::F64));
+        assert_eq!(adata.x().shape().unwrap().as_ref(), [2, 3]);
+        assert_eq!(adata.x().get::<CsrMatrix<f64>>().unwrap().unwrap(), expected);
+    })
+}
+
+fn test_validate<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        assert!(adata.validate().unwrap().is_empty());
+
+        let x = Array2::<f64>::zeros((3, 2));
+        adata.set_x(&x).unwrap();
+        adata.set_obs(df!("a" => &[1i32, 2, 3]).unwrap()).unwrap();
+        adata.obsm().add("pca", Array2::<f32>::zeros((3, 5))).unwrap();
+        adata.obsp().add("dist", Array2::<f32>::zeros((3, 3))).unwrap();
+        adata.varm().add("loadings", Array2::<f32>::zeros((2, 5))).unwrap();
+        adata.varp().add("corr", Array2::<f32>::zeros((2, 2))).unwrap();
+        adata.layers().add("counts", x.clone()).unwrap();
+        assert!(adata.validate().unwrap().is_empty());
+
+        let warning = ValidationWarning {
+            element: "obsp/dist".to_string(),
+            expected: vec![3, 3],
+            found: vec![3, 4].into(),
+        };
+        assert_eq!(warning.to_string(), "'obsp/dist' has shape 3 x 4, expected the leading axes to be 3 x 3");
+    })
+}
+
+fn test_zarr<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let input = dir.join("input.h5ad");
+        let zarr = dir.join("output.zarr");
+        let output = dir.join("output.h5ad");
+        let anndatas = ((0 as usize ..30), (0 as usize ..30))
+            .prop_flat_map(|(n_obs, n_vars)| anndata_strat::<B, _>(&input, n_obs, n_vars));
+        proptest!(ProptestConfig::with_cases(10), |(adata in anndatas)| {
+            let n = adata.n_obs();
+            let obs = df!(
+                "n_genes" => (0..n as i32).collect::<Vec<_>>(),
+                "cell_type" => (0..n).map(|i| ["T", "B", "NK"][i % 3]).collect::<Vec<_>>(),
+            ).unwrap().lazy()
+                .with_column(col("cell_type").cast(DataType::Categorical(None)))
+                .collect().unwrap();
+            adata.set_obs(obs).unwrap();
+            adata.uns().add("method", "leiden".to_string()).unwrap();
+            adata.uns().add("resolution", 0.5f64).unwrap();
+            adata.uns().add("pcs", array![1.5f32, 2.5]).unwrap();
+
+            // X and layers are streamed into the Zarr store and back.
+            let full = [SelectInfoElem::full(), SelectInfoElem::full()];
+            adata.write_select_by_chunk::<Zarr, _, _>(&full, &zarr, 7).unwrap();
+            let adata_zarr = AnnData::<Zarr>::open(Zarr::open(&zarr).unwrap()).unwrap();
+            prop_assert!(anndata_eq(&adata, &adata_zarr).unwrap());
+
+            adata_zarr.write_select_by_chunk::<B, _, _>(&full, &output, 7).unwrap();
+            let adata_out = AnnData::<B>::open(B::open(&output).unwrap()).unwrap();
+            prop_assert!(anndata_eq(&adata, &adata_out).unwrap());
+            adata_out.close().unwrap();
+        });
+    })
+}
+
+fn test_move_uns<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let pca: Mapping = [
+            ("variance".to_string(), Data::from(array![3.0f64, 1.0])),
+            ("n".to_string(), Data::from(2i32)),
+        ].into_iter().collect::<std::collections::HashMap<_, _>>().into();
+        adata.uns().add("pca", pca).unwrap();
+        adata.uns().add("title", "test".to_string()).unwrap();
+
+        adata.uns().move_item("pca/variance", "pca/var_explained").unwrap();
+        let pca: Mapping = adata.uns().get_item("pca").unwrap().unwrap();
+        assert!(pca.contains_key("var_explained") && !pca.contains_key("variance"));
+
+        adata.uns().move_item("pca/n", "n_pcs").unwrap();
+        assert_eq!(adata.uns().get_item::<i32>("n_pcs").unwrap().unwrap(), 2);
+        adata.uns().move_item("title", "pca/title").unwrap();
+        assert!(!adata.uns().keys().contains(&"title".to_string()));
+        let pca: Mapping = adata.uns().get_item("pca").unwrap().unwrap();
+        assert_eq!(pca.len(), 2);
+        adata.uns().move_item("pca", "pca_result").unwrap();
+        assert!(adata.uns().get_item::<Mapping>("pca_result").unwrap().is_some());
+
+        assert!(adata.uns().move_item("missing", "other").is_err());
+        assert!(adata.uns().move_item("pca_result/missing", "other").is_err());
+        assert!(adata.uns().move_item("n_pcs", "pca_result/title").is_err());
+        assert!(adata.uns().move_item("pca_result", "pca_result/sub").is_err());
+        assert!(adata.uns().move_item("n_pcs", "n_pcs/sub").is_err());
+        assert_eq!(adata.uns().get_item::<i32>("n_pcs").unwrap().unwrap(), 2);
+    })
+}
+
+fn test_write_mtx<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let names = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>().into();
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let csr = CsrMatrix::try_from_csr_data(3, 4, vec![0, 2, 2, 3], vec![0, 3, 1], vec![1.5f64, 2.0, 3.0]).unwrap();
+        adata.set_x(&csr).unwrap();
+        adata.set_obs_names(vec!["c1".to_string(), "c2".to_string(), "c3".to_string()].into()).unwrap();
+        adata.set_var_names(names(&["g1", "g2", "g3", "g4"])).unwrap();
+        adata.layers().add("counts", array![[1u32, 0, 0, 2], [0, 0, 0, 0], [0, 3, 0, 0]]).unwrap();
+
+        adata.write_mtx(dir.join("x.mtx"), None).unwrap();
+        let content = std::fs::read_to_string(dir.join("x.mtx")).unwrap();
+        assert_eq!(
+            content,
+            "%%MatrixMarket matrix coordinate real general\n3 4 3\n1 1 1.5\n1 4 2\n3 2 3\n",
+        );
+
+        anndata::writer::MMWriter::to_path(dir.join("matrix.mtx.gz"))
+            .layer("counts")
+            .obs_names(dir.join("barcodes.tsv.gz"))
+            .var_names(dir.join("features.tsv"))
+            .chunk_size(2)
+            .finish(&adata)
+            .unwrap();
+        let features = std::fs::read_to_string(dir.join("features.tsv")).unwrap();
+        assert_eq!(features.lines().next().unwrap(), "g1\tg1\tGene Expression");
+
+        let output = AnnData::<B>::new(dir.join("output.h5ad")).unwrap();
+        anndata::reader::MMReader::from_path(dir.join("matrix.mtx.gz")).unwrap()
+            .obs_names(dir.join("barcodes.tsv.gz")).unwrap()
+            .var_names(dir.join("features.tsv")).unwrap()
+            .finish(&output)
+            .unwrap();
+        assert_eq!(output.obs_names(), adata.obs_names());
+        assert_eq!(output.var_names(), adata.var_names());
+        let expected = CsrMatrix::try_from_csr_data(3, 4, vec![0, 2, 2, 3], vec![0, 3, 1], vec![1.0f64, 2.0, 3.0]).unwrap();
+        assert_eq!(output.x().get::<CsrMatrix<f64>>().unwrap().unwrap(), expected);
+
+        assert!(adata.write_mtx(dir.join("y.mtx"), Some("missing")).is_err());
+    })
+}
+
+fn test_par_chunked<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let xs: Vec<_> = [3, 2, 4].iter().enumerate()
+            .map(|(k, n)| Array2::from_shape_fn((*n, 3), |(i, j)| (k * 100 + i * 3 + j) as i32))
+            .collect();
+        let adatas = xs.iter().enumerate().map(|(k, x)| {
+            let adata = AnnData::<B>::new(dir.join(format!("{}.h5ad", k))).unwrap();
+            adata.set_x(x).unwrap();
+            (k.to_string(), adata)
+        });
+        let dataset = AnnDataSet::<B>::new(adatas, dir.join("dataset.h5ads"), "sample").unwrap();
+        let x = dataset.adatas().inner().get_x().clone();
+
+        let expected: Vec<_> = x.chunked::<Array2<i32>>(2).collect();
+        let bounds: Vec<_> = expected.iter().map(|(_, i, j)| (*i, *j)).collect();
+        assert_eq!(bounds, [(0, 2), (2, 3), (3, 5), (5, 7), (7, 9)]);
+        for prefetch in [1, 3, 100] {
+            let mut iter = x.par_chunked::<Array2<i32>>(2, prefetch);
+            assert_eq!(iter.len(), 5);
+            iter.next().unwrap().unwrap();
+            assert_eq!(iter.len(), 4);
+            let chunks: Vec<_> = x.par_chunked::<Array2<i32>>(2, prefetch).collect::<Result<_, _>>().unwrap();
+            assert_eq!(chunks, expected);
+        }
+    })
+}
+
+fn test_complex<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = Array2::from_shape_fn((4, 3), |(i, j)| Complex64::new(i as f64, -(j as f64)));
+        adata.obsm().add("complex", &x).unwrap();
+        let data = adata.obsm().get_item::<ArrayData>("complex").unwrap().unwrap();
+        assert_eq!(data.data_type(), anndata::backend::DataType::Array(ScalarType::ComplexF64));
+        assert_eq!(adata.obsm().get_item::<Array2<Complex64>>("complex").unwrap().unwrap(), x);
+
+        let idx = vec![3, 1];
+        let selected: Array2<Complex64> = adata.obsm().get("complex").unwrap()
+            .slice_axis(0, SelectInfoElem::from(idx.clone())).unwrap().unwrap();
+        assert_eq!(selected, x.select(ndarray::Axis(0), &idx));
+
+        let y = x.mapv(|v| Complex32::new(v.re as f32, v.im as f32));
+        adata.uns().add("complex32", y.clone()).unwrap();
+        assert_eq!(adata.uns().get_item::<Array2<Complex32>>("complex32").unwrap().unwrap(), y);
+        adata.uns().add("scalar", Complex64::new(1.5, -2.0)).unwrap();
+        assert_eq!(adata.uns().get_item::<Complex64>("scalar").unwrap().unwrap(), Complex64::new(1.5, -2.0));
+
+        let column = DynArray::ComplexF64(x.column(0).to_owned().into_dyn());
+        assert!(Series::try_from(column).is_err());
+    })
+}
+
+fn test_filter_obs<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.set_x(Array2::<f32>::zeros((4, 2))).unwrap();
+        assert_eq!(adata.filter_obs(|_| true).unwrap(), [0, 1, 2, 3]);
+
+        let obs = df!(
+            "n_genes" => &[100, 600, 800, 1000],
+            "pct_mito" => &[0.05, 0.2, 0.05, 0.01],
+            "cell type" => &["T", "B", "T", "NK"],
+        ).unwrap().lazy()
+            .with_column(col("cell type").cast(DataType::Categorical(None)))
+            .collect().unwrap();
+        adata.set_obs(obs).unwrap();
+
+        let idx = adata.filter_obs(|row| {
+            row.get("n_genes").and_then(|x| x.extract::<i64>()).map_or(false, |x| x > 500)
+        }).unwrap();
+        assert_eq!(idx, [1, 2, 3]);
+        assert_eq!(adata.query_obs("n_genes > 500 & pct_mito < 0.1").unwrap(), [2, 3]);
+        assert_eq!(adata.query_obs("~(n_genes > 500) | `cell type` == 'NK'").unwrap(), [0, 3]);
+        assert_eq!(adata.query_obs("pct_mito >= 5e-2").unwrap(), [0, 1, 2]);
+        assert_eq!(adata.query_obs("1 < 2").unwrap(), [0, 1, 2, 3]);
+        assert_eq!(adata.query_var("true").unwrap(), [0, 1]);
+        assert!(adata.query_obs("n_genes >").is_err());
+        assert!(adata.query_obs("missing > 1").is_err());
+        assert!(adata.query_obs("n_genes").is_err());
+
+        adata.subset(&[SelectInfoElem::from(idx), SelectInfoElem::full()]).unwrap();
+        assert_eq!(adata.n_obs(), 3);
+    })
+}
+
+fn test_reorder_obs<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = CsrMatrix::try_from_csr_data(
+            3, 2, vec![0, 1, 2, 4], vec![0, 1, 0, 1], vec![1, 2, 3, 4],
+        ).unwrap();
+        adata.set_x(&x).unwrap();
+        adata.set_obs_names(["a", "b", "c"].into_iter().map(|x| x.to_string()).collect()).unwrap();
+        adata.set_obs(df!("n" => &[0, 1, 2]).unwrap()).unwrap();
+        let obsp = Array2::from_shape_fn((3, 3), |(i, j)| (i * 3 + j) as i32);
+        adata.obsm().add("pca", array![[0.0, 0.5], [1.0, 1.5], [2.0, 2.5]]).unwrap();
+        adata.obsp().add("distances", &obsp).unwrap();
+        adata.layers().add("counts", array![[0, 1], [2, 3], [4, 5]]).unwrap();
+
+        assert!(adata.reorder_obs(&[0, 1]).is_err());
+        assert!(adata.reorder_obs(&[0, 1, 1]).is_err());
+        assert!(adata.reorder_obs(&[0, 1, 3]).is_err());
+
+        let perm = [2, 0, 1];
+        adata.reorder_obs(&perm).unwrap();
+        assert_eq!(adata.obs_names().into_vec(), ["c", "a", "b"]);
+        assert_eq!(adata.read_obs().unwrap(), df!("n" => &[2, 0, 1]).unwrap());
+        let x: CsrMatrix<i32> = adata.x().get().unwrap().unwrap();
+        assert_eq!(x.triplet_iter().map(|(i, j, v)| (i, j, *v)).collect::<Vec<_>>(), [(0, 0, 3), (0, 1, 4), (1, 0, 1), (2, 1, 2)]);
+        assert_eq!(
+            adata.obsm().get_item::<Array2<f64>>("pca").unwrap().unwrap(),
+            array![[2.0, 2.5], [0.0, 0.5], [1.0, 1.5]],
+        );
+        assert_eq!(
+            adata.obsp().get_item::<Array2<i32>>("distances").unwrap().unwrap(),
+            obsp.select(ndarray::Axis(0), &perm).select(ndarray::Axis(1), &perm),
+        );
+        assert_eq!(
+            adata.layers().get_item::<Array2<i32>>("counts").unwrap().unwrap(),
+            array![[4, 5], [0, 1], [2, 3]],
+        );
+    })
+}
+
+fn test_open_lazy<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let path = dir.join("test.h5ad");
+        let adata = AnnData::<B>::new(&path).unwrap();
+        adata.set_x(Array2::<f32>::zeros((3, 2))).unwrap();
+        adata.obsm().add("pca", array![[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]]).unwrap();
+        adata.obsm().add("umap", array![[0, 1], [2, 3], [4, 5]]).unwrap();
+        adata.layers().add("counts", array![[1, 2], [3, 4], [5, 6]]).unwrap();
+        adata.uns().add("name", "test".to_string()).unwrap();
+        adata.close().unwrap();
+
+        let adata = AnnData::<B>::open_lazy(B::open_rw(&path).unwrap()).unwrap();
+        assert_eq!((adata.n_obs(), adata.n_vars()), (3, 2));
+        let mut keys = adata.obsm().keys();
+        keys.sort();
+        assert_eq!(keys, ["pca", "umap"]);
+        assert_eq!(adata.obsm().inner().opened().count(), 0);
+        assert_eq!(adata.uns().get_item::<String>("name").unwrap().unwrap(), "test");
+        assert_eq!(
+            adata.obsm().get_item::<Array2<i32>>("umap").unwrap().unwrap(),
+            array![[0, 1], [2, 3], [4, 5]],
+        );
+        assert_eq!(adata.obsm().inner().opened().count(), 1);
+        adata.write::<B, _>(dir.join("lazy.h5ad")).unwrap();
+        adata.subset([SelectInfoElem::from(vec![2, 0]), SelectInfoElem::full()]).unwrap();
+        assert_eq!(
+            adata.obsm().get_item::<Array2<f64>>("pca").unwrap().unwrap(),
+            array![[5.0, 6.0], [1.0, 2.0]],
+        );
+        adata.close().unwrap();
+
+        // An array with an inconsistent shape is only reported when accessed.
+        let file = B::open_rw(&path).unwrap();
+        let obsm = file.open_group("obsm").unwrap();
+        Array2::<f64>::zeros((5, 2)).write(&obsm, "bad").unwrap();
+        file.close().unwrap();
+        assert!(AnnData::<B>::open(B::open(&path).unwrap()).is_err());
+        let adata = AnnData::<B>::open_lazy(B::open(&path).unwrap()).unwrap();
+        assert!(adata.obsm().inner().try_get("bad").is_err());
+        assert!(adata.obsm().get_item::<Array2<f64>>("pca").unwrap().is_some());
+        assert!(adata.obsm().try_get("bad").is_err());
+        assert!(adata.obsm().get("bad").is_none());
+        assert!(adata.obsm().get_item::<Array2<f64>>("bad").is_err());
+        assert!(adata.describe().is_err());
+        assert!(adata.write::<B, _>(dir.join("out.h5ad")).is_err());
+    })
+}
+
+fn test_chunked_with_obs<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = Array2::from_shape_fn((5, 2), |(i, j)| (i * 2 + j) as i32);
+        adata.set_x(&x).unwrap();
+        adata.set_obs(df!(
+            "label" => &["a", "b", "c", "d", "e"],
+            "count" => &[Some(1i64), None, Some(3), Some(4), None],
+            "score" => &[0.1, 0.2, 0.3, 0.4, 0.5],
+        ).unwrap()).unwrap();
+        let columns = vec!["label".to_string(), "count".to_string()];
+        assert!(adata.chunked_with_obs::<Array2<i32>>(2, &["foo".to_string()]).is_err());
+
+        let check = || {
+            let iter = adata.chunked_with_obs::<Array2<i32>>(2, &columns).unwrap();
+            assert_eq!(iter.len(), 3);
+            let (chunks, dfs): (Vec<_>, Vec<_>) = iter.map(Result::unwrap).unzip();
+            assert_eq!(chunks[2], x.slice(ndarray::s![4.., ..]));
+            assert_eq!(dfs[1].get_column_names(), ["label", "count"]);
+            assert_eq!(dfs[1].column("label").unwrap(), &Series::new("label", &["c", "d"]));
+            assert_eq!(
+                dfs.iter().map(|df| df.height()).collect::<Vec<_>>(),
+                vec![2, 2, 1],
+            );
+            assert_eq!(
+                dfs[0].column("count").unwrap().i64().unwrap().into_iter().collect::<Vec<_>>(),
+                vec![Some(1), None],
+            );
+        };
+        check();
+        // Same result once obs is in memory.
+        adata.read_obs().unwrap();
+        check();
+
+        let (_, df) = adata.chunked_with_obs::<Array2<i32>>(5, &[]).unwrap().next().unwrap().unwrap();
+        assert_eq!(df.width(), 0);
+    })
+}
+
+fn test_hstack_obsm<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.set_x(Array2::<f64>::zeros((3, 2))).unwrap();
+        let pca = array![[1.0f32, 2.0], [3.0, 4.0], [5.0, 6.0]];
+        let batch = CsrMatrix::try_from_csr_data(3, 1, vec![0, 1, 1, 2], vec![0, 0], vec![7.0f64, 8.0]).unwrap();
+        adata.obsm().add("pca", &pca).unwrap();
+        adata.obsm().add("batch", batch).unwrap();
+
+        adata.obsm().hstack(&["pca", "batch"], "joint").unwrap();
+        assert_eq!(
+            adata.obsm().get_item::<Array2<f64>>("joint").unwrap().unwrap(),
+            array![[1.0, 2.0, 7.0], [3.0, 4.0, 0.0], [5.0, 6.0, 8.0]],
+        );
+        adata.obsm().hstack(&["pca", "pca"], "pca2").unwrap();
+        assert_eq!(
+            adata.obsm().get_item::<Array2<f32>>("pca2").unwrap().unwrap(),
+            array![[1.0, 2.0, 1.0, 2.0], [3.0, 4.0, 3.0, 4.0], [5.0, 6.0, 5.0, 6.0]],
+        );
+        assert!(adata.obsm().hstack(&["pca", "missing"], "out").is_err());
+        assert!(adata.obsm().hstack(&[], "out").is_err());
+        assert!(!adata.obsm().keys().contains(&"out".to_string()));
+    })
+}
+
+fn test_temporal_columns<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let obs = df!(
+            "date" => &[18000i32, 18001, 18002],
+            "time" => &[Some(1_600_000_000_000i64), None, Some(1_600_000_360_000)],
+            "elapsed" => &[0i64, 1000, 2000],
+        ).unwrap().lazy().with_columns([
+            col("date").cast(DataType::Date),
+            col("time").cast(DataType::Datetime(TimeUnit::Milliseconds, Some("UTC".to_string()))),
+            col("elapsed").cast(DataType::Duration(TimeUnit::Microseconds)),
+        ]).collect().unwrap();
+        adata.set_obs(obs.clone()).unwrap();
+        let obs_in = adata.read_obs().unwrap();
+        assert!(obs_in.frame_equal_missing(&obs));
+        assert_eq!(obs_in.column("time").unwrap().dtype(), obs.column("time").unwrap().dtype());
+
+        let rows = adata.get_obs().inner().select_columns(&["date".to_string()], SelectInfoElem::from(1..3)).unwrap();
+        assert_eq!(rows.column("date").unwrap(), &obs.column("date").unwrap().slice(1, 2));
+    })
+}
+
+fn test_prefix_obs_names<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let new_adata = |name: &str, n: usize| {
+            let adata = AnnData::<B>::new(dir.join(name)).unwrap();
+            adata.set_x(Array2::<i32>::zeros((n, 2))).unwrap();
+            adata.set_obs_names((0..n).map(|i| format!("cell{}", i)).collect()).unwrap();
+            adata
+        };
+        let dataset = AnnDataSet::<B>::new(
+            [("s1", new_adata("s1.h5ad", 2)), ("s2", new_adata("s2.h5ad", 1))],
+            dir.join("dataset.h5ads"),
+            "sample",
+        ).unwrap();
+        dataset.prefix_obs_names(":").unwrap();
+        assert_eq!(dataset.obs_names().into_vec(), ["s1:cell0", "s1:cell1", "s2:cell0"]);
+        assert_eq!(dataset.obs_ix(["s2:cell0"]).unwrap(), [2]);
+        assert_eq!(dataset.adatas().inner().values().next().unwrap().obs_names().into_vec(), ["cell0", "cell1"]);
+
+        dataset.add_component("s3", new_adata("s3.h5ad", 1)).unwrap();
+        dataset.prefix_obs_names("_").unwrap();
+        assert_eq!(dataset.obs_names().into_vec(), ["s1_cell0", "s1_cell1", "s2_cell0", "s3_cell0"]);
+        let samples: Vec<_> = dataset.read_obs().unwrap().column("sample").unwrap()
+            .utf8().unwrap().into_no_null_iter().map(|x| x.to_string()).collect();
+        assert_eq!(samples, ["s1", "s1", "s2", "s3"]);
+    })
+}
+
+fn test_strided_sparse_select<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let strat = (1usize..40, 1usize..10).prop_flat_map(|(n, m)| (
+            proptest::collection::vec(prop_oneof![3 => Just(0i64), 1 => 1i64..100], n * m)
+                .prop_map(move |x| Array2::from_shape_vec((n, m), x).unwrap()),
+            0..=n,
+            0..=n,
+            prop_oneof![1isize..6, -5isize..0],
+        ));
+        proptest!(ProptestConfig::with_cases(100), |((x, a, b, step) in strat)| {
+            let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+            adata.set_x(ArrayData::from(x.clone()).to_sparse(0.0).unwrap()).unwrap();
+            let slice = ndarray::Slice::new(a.min(b) as isize, Some(a.max(b) as isize), step);
+            let expected = x.slice_axis(ndarray::Axis(0), slice).to_owned();
+
+            let rows = [SelectInfoElem::from(slice), SelectInfoElem::full()];
+            let csr = adata.x().slice::<CsrMatrix<i64>, _>(&rows).unwrap().unwrap();
+            let dense: Array2<i64> = ArrayData::from(csr).to_dense().unwrap().try_into().unwrap();
+            prop_assert_eq!(dense, expected.clone());
+
+            // Scattered rows take the same path.
+            let indices: Vec<usize> = (0..x.nrows()).rev().step_by(step.unsigned_abs()).collect();
+            let rows = [SelectInfoElem::from(indices.clone()), SelectInfoElem::full()];
+            let csr = adata.x().slice::<CsrMatrix<i64>, _>(&rows).unwrap().unwrap();
+            let dense: Array2<i64> = ArrayData::from(csr).to_dense().unwrap().try_into().unwrap();
+            prop_assert_eq!(dense, x.select(ndarray::Axis(0), &indices));
+        });
+    })
+}
+
+fn test_read_mtx_gz<B: Backend>() {
+    use std::io::Write;
+    with_tmp_dir(|dir| {
+        // Compression is detected from the content, not from the extension.
+        let mtx = "%%MatrixMarket matrix coordinate integer general\n2 3 2\n1 2 5\n2 3 7\n";
+        let mut encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(dir.join("matrix.mtx")).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(mtx.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+        std::fs::write(dir.join("barcodes.tsv.gz"), "c1\nc2\n").unwrap();
+
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        anndata::reader::MMReader::from_path(dir.join("matrix.mtx")).unwrap()
+            .obs_names(dir.join("barcodes.tsv.gz")).unwrap()
+            .is_sorted()
+            .finish(&adata)
+            .unwrap();
+        assert_eq!(adata.obs_names().into_vec(), ["c1", "c2"]);
+        let expected = CsrMatrix::try_from_csr_data(2, 3, vec![0, 1, 2], vec![1, 2], vec![5.0f64, 7.0]).unwrap();
+        assert_eq!(adata.x().get::<CsrMatrix<f64>>().unwrap().unwrap(), expected);
+
+        assert!(anndata::reader::MMReader::from_path(dir.join("missing.mtx.gz")).is_err());
+    })
+}
+
+fn test_x_sums<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        assert!(adata.x_row_sums().is_err());
+
+        let x = array![[1u32, 0, 2], [0, 0, 0], [3, 4, 0], [0, 5, 0]];
+        let check = |adata: &AnnData<B>| {
+            assert_eq!(adata.x_row_sums().unwrap(), array![3.0, 0.0, 7.0, 5.0]);
+            assert_eq!(adata.x_col_sums().unwrap(), array![4.0, 9.0, 2.0]);
+            assert_eq!(adata.x_nnz_per_row().unwrap(), array![2, 0, 2, 1]);
+            assert_eq!(adata.x_nnz_per_col().unwrap(), array![2, 2, 1]);
+        };
+        adata.set_x(x.clone()).unwrap();
+        check(&adata);
+        adata.set_x(ArrayData::from(x.into_dyn()).to_sparse(0.0).unwrap()).unwrap();
+        check(&adata);
+    })
+}
+
+fn test_stack_mixed_dtypes<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let new_adata = |name: &str, x: ArrayData| {
+            let adata = AnnData::<B>::new(dir.join(name)).unwrap();
+            adata.set_x(x).unwrap();
+            adata
+        };
+
+        let x1 = array![[1i32, 2], [3, 4]];
+        let x2 = array![[i64::MAX, 6]];
+        let dataset = AnnDataSet::<B>::new(
+            [("s1", new_adata("s1.h5ad", x1.into())), ("s2", new_adata("s2.h5ad", x2.into()))],
+            dir.join("dataset.h5ads"),
+            "sample",
+        ).unwrap();
+        let adatas = dataset.adatas().inner();
+        let x = adatas.get_x();
+        assert_eq!(x.dtype(), anndata::backend::DataType::Array(ScalarType::I64));
+        assert_eq!(x.data::<Array2<i64>>().unwrap().unwrap(), array![[1, 2], [3, 4], [i64::MAX, 6]]);
+        assert_eq!(
+            x.select::<Array2<i64>, _>(&[SelectInfoElem::from(vec![0, 2]), SelectInfoElem::full()]).unwrap().unwrap(),
+            array![[1, 2], [i64::MAX, 6]],
+        );
+        // Chunks of every element are cast to the stacked type.
+        let expected = [array![[1, 2], [3, 4]], array![[i64::MAX, 6]]];
+        let chunks: Vec<_> = x.chunked::<Array2<i64>>(2).map(|(x, _, _)| x).collect();
+        assert_eq!(chunks, expected);
+        let chunks: Vec<_> = x.par_chunked::<Array2<i64>>(2, 2).map(|x| x.unwrap().0).collect();
+        assert_eq!(chunks, expected);
+        drop(adatas);
+        let adata = dataset.to_adata::<B, _>(dir.join("stacked.h5ad"), 1).unwrap();
+        assert_eq!(adata.x().get::<Array2<i64>>().unwrap().unwrap(), array![[1, 2], [3, 4], [i64::MAX, 6]]);
+
+        let x1 = ArrayData::from(array![[0.5f32, 0.0]]).to_sparse(0.0).unwrap();
+        let x2 = ArrayData::from(array![[0.0f64, 0.25]]).to_sparse(0.0).unwrap();
+        let dataset = AnnDataSet::<B>::new(
+            [("s1", new_adata("s3.h5ad", x1)), ("s2", new_adata("s4.h5ad", x2))],
+            dir.join("dataset2.h5ads"),
+            "sample",
+        ).unwrap();
+        let x: CsrMatrix<f64> = dataset.adatas().inner().get_x().data().unwrap().unwrap();
+        assert_eq!(x.triplet_iter().map(|(i, j, v)| (i, j, *v)).collect::<Vec<_>>(), [(0, 0, 0.5), (1, 1, 0.25)]);
+
+        let x1 = array![["a".to_string(), "b".to_string()]];
+        let x2 = array![[1.0f64, 2.0]];
+        assert!(AnnDataSet::<B>::new(
+            [("s1", new_adata("s5.h5ad", x1.into())), ("s2", new_adata("s6.h5ad", x2.into()))],
+            dir.join("dataset3.h5ads"),
+            "sample",
+        ).is_err());
+
+        let x = ArrayData::vstack([array![[1u8]].into(), array![[-1i8]].into()].into_iter()).unwrap();
+        assert_eq!(x, ArrayData::from(array![[1i16], [-1]].into_dyn()));
+        assert!(ArrayData::vstack([array![[true]].into(), array![[1u8]].into()].into_iter()).is_err());
+        assert_eq!(ScalarType::I32.promote(ScalarType::F32), Some(ScalarType::F64));
+        assert_eq!(ScalarType::U64.promote(ScalarType::I8), Some(ScalarType::F64));
+        assert_eq!(ScalarType::F32.promote(ScalarType::ComplexF32), Some(ScalarType::ComplexF32));
+        assert_eq!(ScalarType::String.promote(ScalarType::F64), None);
+    })
+}
+
+fn test_read_many<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let pca = Array2::from_shape_fn((5, 3), |(i, j)| (i * 3 + j) as f32);
+        let umap = Array2::from_shape_fn((5, 2), |(i, j)| (i + j) as f64);
+        adata.obsm().add("X_pca", pca.clone()).unwrap();
+        adata.obsm().add("X_umap", umap.clone()).unwrap();
+        adata.obsm().add("X_harmony", pca.clone()).unwrap();
+        adata.obsm().get("X_umap").unwrap().inner().enable_cache();
+
+        let arrays = adata.obsm().read_many(&["X_pca", "X_umap"]).unwrap();
+        assert_eq!(arrays.len(), 2);
+        assert_eq!(arrays["X_pca"], ArrayData::from(pca.into_dyn()));
+        assert_eq!(arrays["X_umap"], ArrayData::from(umap.into_dyn()));
+        assert!(adata.obsm().get("X_umap").unwrap().inner().to_string().ends_with("cached: yes"));
+        assert!(adata.obsm().get("X_pca").unwrap().inner().to_string().ends_with("cached: no"));
+
+        assert!(adata.obsm().read_many(&["X_pca", "X_tsne"]).is_err());
+    })
+}
+
+fn test_external_link<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let shared = dir.join("shared.h5ad");
+        let path = dir.join("test.h5ad");
+        let x = array![[1i32, 2], [3, 4], [5, 6]];
+        let reference = AnnData::<B>::new(&shared).unwrap();
+        reference.set_x(x.clone()).unwrap();
+        reference.obsm().add("wide", Array2::<i32>::zeros((3, 5))).unwrap();
+        reference.close().unwrap();
+
+        let adata = AnnData::<B>::new(&path).unwrap();
+        adata.set_x(Array2::<i32>::zeros((3, 2))).unwrap();
+        let result = adata.layers().add_external_link("shared", &shared, "/X");
+        if B::NAME == InMemory::NAME {
+            assert!(result.is_err());
+            return;
+        }
+        result.unwrap();
+        assert_eq!(adata.layers().get_item::<Array2<i32>>("shared").unwrap().unwrap(), x);
+        assert!(adata.layers().get("shared").unwrap().inner().is_external());
+        assert!(adata.layers().add_external_link("shared", &shared, "/X").is_err());
+        assert!(adata.layers().add_external_link("wide", &shared, "/obsm/wide").is_err());
+        assert!(!adata.layers().keys().contains(&"wide".to_string()));
+        assert!(adata.layers().add("shared", Array2::<i32>::zeros((3, 2))).is_err());
+        assert!(adata.subset(&[SelectInfoElem::from(vec![0, 1]), SelectInfoElem::full()]).is_err());
+        adata.close().unwrap();
+
+        let adata = AnnData::<B>::open(B::open_rw(&path).unwrap()).unwrap();
+        assert_eq!(adata.layers().get_item::<Array2<i32>>("shared").unwrap().unwrap(), x);
+        assert!(adata.layers().get("shared").unwrap().inner().is_external());
+        adata.layers().remove("shared").unwrap();
+        assert!(adata.layers().keys().is_empty());
+        adata.close().unwrap();
+
+        let reference = AnnData::<B>::open(B::open(&shared).unwrap()).unwrap();
+        assert_eq!(reference.x().get::<Array2<i32>>().unwrap().unwrap(), x);
+    })
+}
+
+fn test_verify_integrity<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let path = dir.join("test.h5ad");
+        let adata = AnnData::<B>::new(&path).unwrap();
+        let csr = CsrMatrix::try_from_csr_data(3, 3, vec![0, 1, 2, 3], vec![0, 2, 1], vec![1.0f64, 2.0, 3.0]).unwrap();
+        adata.set_x(&csr).unwrap();
+        adata.set_obs(df!("a" => &[1i32, 2, 3]).unwrap()).unwrap();
+        adata.obsm().add("pca", array![[f64::NAN], [1.0], [2.0]]).unwrap();
+        assert!(adata.verify_integrity().unwrap().is_empty());
+        adata.layers().add("counts", array![[1.0f32, f32::NAN, 0.0], [0.0, 0.0, 0.0], [0.0, 0.0, 0.0]]).unwrap();
+        adata.close().unwrap();
+
+        // Corrupt X and obs behind the AnnData's back.
+        let file = B::open_rw(&path).unwrap();
+        let x = file.open_group("X").unwrap();
+        x.delete("indptr").unwrap();
+        array![0i64, 2, 1, 3].write(&x, "indptr").unwrap();
+        x.delete("indices").unwrap();
+        array![0i64, 5, 1].write(&x, "indices").unwrap();
+        let obs = file.open_group("obs").unwrap();
+        obs.delete("a").unwrap();
+        array![1i32, 2].write(&obs, "a").unwrap();
+        file.close().unwrap();
+
+        let adata = AnnData::<B>::open(B::open(&path).unwrap()).unwrap();
+        let problems: Vec<_> = adata.verify_integrity().unwrap().into_iter().map(|x| x.to_string()).collect();
+        assert_eq!(
+            problems,
+            [
+                "'X': indptr decreases at position 2",
+                "'X': 1 indices are out of the range 0..3",
+                "'obs': column 'a' has 2 rows, but the index has 3",
+                "'layers/counts': 1 values are NaN",
+            ]
+        );
+    })
+}
+
+fn test_builder<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let x = array![[1.0f64, 2.0], [3.0, 4.0], [5.0, 6.0]];
+        let adata = AnnDataBuilder::<B>::new()
+            .x(x.clone())
+            .obs(df!("a" => &[1i32, 2, 3]).unwrap())
+            .obs_names(["c1", "c2", "c3"].map(String::from).into_iter().collect())
+            .var_names(["g1", "g2"].map(String::from).into_iter().collect())
+            .obsm("pca", Array2::<f32>::zeros((3, 4)))
+            .varp("corr", Array2::<f32>::zeros((2, 2)))
+            .layer("counts", x.mapv(|v| v as i32))
+            .uns("version", Data::from(1i64))
+            .build(dir.join("test.h5ad"))
+            .unwrap();
+        assert_eq!(adata.n_obs(), 3);
+        assert_eq!(adata.n_vars(), 2);
+        assert_eq!(adata.x().get::<Array2<f64>>().unwrap().unwrap(), x);
+        assert_eq!(adata.obs_names().into_vec(), ["c1", "c2", "c3"]);
+        assert_eq!(adata.read_obs().unwrap().column("a").unwrap().len(), 3);
+        assert_eq!(adata.obsm().keys(), ["pca"]);
+        assert_eq!(adata.varp().keys(), ["corr"]);
+        assert_eq!(adata.layers().get_item::<Array2<i32>>("counts").unwrap().unwrap(), x.mapv(|v| v as i32));
+        assert_eq!(adata.uns().get_item::<i64>("version").unwrap(), Some(1));
+        adata.close().unwrap();
+
+        let path = dir.join("bad.h5ad");
+        let err = AnnDataBuilder::<B>::new()
+            .x(x.clone())
+            .obsm("pca", Array2::<f32>::zeros((4, 2)))
+            .build(&path)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "'obsm/pca' has 4 observations, but 'X' has 3");
+        assert!(!path.exists());
+    })
+}
+
+fn test_negative_select<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = Array2::from_shape_fn((20, 4), |(i, j)| if (i + j) % 3 == 0 { 0 } else { (i * 4 + j) as i64 });
+        adata.set_x(&x).unwrap();
+        adata.layers().add("counts", ArrayData::from(x.clone()).to_sparse(0.0).unwrap()).unwrap();
+
+        for slice in [
+            ndarray::Slice::new(-10, None, 1),
+            ndarray::Slice::new(0, None, -1),
+            ndarray::Slice::new(5, Some(-5), 1),
+            ndarray::Slice::new(5, Some(-5), -3),
+            ndarray::Slice::new(-30, Some(30), 2),
+        ] {
+            let expected = x.slice_axis(ndarray::Axis(0), ndarray::Slice::new(
+                slice.start.max(-20),
+                slice.end.map(|e| e.min(20)),
+                slice.step,
+            )).to_owned();
+            let rows = [SelectInfoElem::from(slice), SelectInfoElem::full()];
+            assert_eq!(adata.x().slice::<Array2<i64>, _>(&rows).unwrap().unwrap(), expected);
+            let csr = adata.layers().get_item_slice::<CsrMatrix<i64>, _>("counts", &rows).unwrap().unwrap();
+            let dense: Array2<i64> = ArrayData::from(csr).to_dense().unwrap().try_into().unwrap();
+            assert_eq!(dense, expected);
+        }
+
+        // A slice whose end precedes its start is empty.
+        let rows = [SelectInfoElem::from(ndarray::Slice::new(-5, Some(5), 1)), SelectInfoElem::full()];
+        assert_eq!(adata.x().slice::<Array2<i64>, _>(&rows).unwrap().unwrap().nrows(), 0);
+    })
+}
+
+fn test_cache_budget<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = Array2::<f64>::ones((10, 20));
+        adata.obsm().add("a", x.clone()).unwrap();
+        adata.obsm().add("b", x.clone()).unwrap();
+        adata.layers().add("c", x.clone()).unwrap();
+        let size = 10 * 20 * 8;
+        assert_eq!(adata.cache_bytes(), 0);
+
+        for elem in [adata.obsm().get("a"), adata.obsm().get("b")] {
+            let elem = elem.unwrap();
+            elem.inner().enable_cache();
+            elem.inner().data::<ArrayData>().unwrap();
+        }
+        assert_eq!(adata.cache_bytes(), 2 * size);
+
+        // Touch "a" so that "b" becomes the least recently used.
+        adata.obsm().get("a").unwrap().inner().data::<ArrayData>().unwrap();
+        adata.set_cache_budget(Some(size));
+        assert_eq!(adata.cache_budget(), Some(size));
+        assert_eq!(adata.cache_bytes(), size);
+        assert!(adata.obsm().get("a").unwrap().inner().to_string().ends_with("cached: yes"));
+        assert!(adata.obsm().get("b").unwrap().inner().to_string().ends_with("cached: no"));
+
+        // Evicted data is read from the file again.
+        let layer = adata.layers().get("c").unwrap();
+        layer.inner().enable_cache();
+        assert_eq!(layer.inner().data::<Array2<f64>>().unwrap(), x);
+        assert_eq!(adata.cache_bytes(), size);
+        assert!(adata.obsm().get("a").unwrap().inner().to_string().ends_with("cached: no"));
+        assert_eq!(adata.obsm().get("a").unwrap().inner().data::<Array2<f64>>().unwrap(), x);
+        assert_eq!(adata.cache_bytes(), size);
+
+        adata.set_cache_budget(None);
+        adata.obsm().get("b").unwrap().inner().data::<ArrayData>().unwrap();
+        assert_eq!(adata.cache_bytes(), 2 * size);
+        adata.obsm().remove("a").unwrap();
+        assert_eq!(adata.cache_bytes(), size);
+    })
+}
+
+fn test_fill_value<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let csr = CsrMatrix::try_from_csr_data(2, 3, vec![0, 1, 3], vec![1, 0, 2], vec![5i32, 6, 0]).unwrap();
+        adata.set_x(&csr).unwrap();
+        adata.x().to_dense_with_fill(DynScalar::I64(-1)).unwrap();
+        assert_eq!(adata.x().inner().dtype(), anndata::backend::DataType::Array(ScalarType::I32));
+        assert_eq!(adata.x().get::<Array2<i32>>().unwrap().unwrap(), array![[-1, 5, -1], [6, -1, 0]]);
+        assert!(adata.x().to_dense_with_fill(DynScalar::String("a".into())).is_err());
+
+        // Blocks that only contain the fill value are read back as the fill value.
+        let mut x = Array2::from_elem((40, 30), 7.0f64);
+        x[[33, 2]] = 1.0;
+        let config = backend::WriteConfig {
+            block_size: Some((10, 10).into()),
+            fill_value: Some(DynScalar::F64(7.0)),
+            ..Default::default()
+        };
+        let adata = AnnData::<B>::new(dir.join("background.h5ad")).unwrap();
+        adata.obsm().add_with_config("background", &x, config).unwrap();
+        let elem = adata.obsm().get("background").unwrap();
+        assert_eq!(elem.inner().data::<Array2<f64>>().unwrap(), x);
+
+        let config = backend::WriteConfig {
+            fill_value: Some(DynScalar::I32(7)),
+            ..Default::default()
+        };
+        assert!(adata.obsm().add_with_config("mismatch", &x, config).is_err());
+
+        let csc = CscMatrix::try_from_csc_data(2, 2, vec![0, 1, 1], vec![1], vec![true]).unwrap();
+        let dense = ArrayData::from(csc).to_dense_with_fill(DynScalar::Bool(true)).unwrap();
+        assert_eq!(dense, ArrayData::from(array![[true, true], [true, true]].into_dyn()));
+    })
+}
+
+fn test_join<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.set_obs(df!("gene_id" => &["g1", "g2", "g3"]).unwrap()).unwrap();
+        adata.set_obs_names(vec!["c1".to_string(), "c2".to_string(), "c3".to_string()].into()).unwrap();
+        let obs = adata.get_obs();
+
+        // The row order is kept and keys absent from the dataframe are ignored.
+        let meta = df!("gene_id" => &["g3", "g4", "g1", "g2"], "score" => &[3.0f64, 4.0, 1.0, 2.0]).unwrap();
+        obs.join(meta, "gene_id", JoinType::Left).unwrap();
+        assert_eq!(
+            obs.inner().column("score").unwrap(),
+            &Series::new("score", &[1.0f64, 2.0, 3.0]),
+        );
+
+        // Join on the index.
+        let meta = df!("index" => &["c2", "c3", "c1"], "group" => &["b", "c", "a"]).unwrap();
+        obs.join(meta, "index", JoinType::Left).unwrap();
+        assert_eq!(
+            obs.inner().data().unwrap(),
+            &df!(
+                "gene_id" => &["g1", "g2", "g3"],
+                "score" => &[1.0f64, 2.0, 3.0],
+                "group" => &["a", "b", "c"],
+            ).unwrap(),
+        );
+
+        // Duplicated keys, existing columns and unknown keys are rejected.
+        let meta = df!("gene_id" => &["g1", "g1"], "x" => &[1i32, 2]).unwrap();
+        assert!(obs.join(meta, "gene_id", JoinType::Left).is_err());
+        let meta = df!("gene_id" => &["g1"], "score" => &[1i32]).unwrap();
+        assert!(obs.join(meta, "gene_id", JoinType::Left).is_err());
+        let meta = df!("other" => &["g1"], "y" => &[1i32]).unwrap();
+        assert!(obs.join(meta, "other", JoinType::Left).is_err());
+        assert_eq!(obs.inner().width(), 3);
+    })
+}
+
+fn test_transposed_x<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let x = Array2::from_shape_fn((20, 47), |(i, j)| {
+            if (i + j) % 4 == 0 { (i * 47 + j) as i32 } else { 0 }
+        });
+        let dense = |x: &Array2<i32>| DMatrix::from_fn(x.nrows(), x.ncols(), |i, j| x[[i, j]]);
+        let csr = CsrMatrix::from(&dense(&x));
+        let file = dir.join("test.h5ad");
+        let adata = AnnData::<B>::new(&file).unwrap();
+        adata.set_x(&csr).unwrap();
+        adata.set_x_transposed(true).unwrap();
+        assert!(adata.is_x_transposed());
+
+        // The matrix is presented as a CSR matrix of the same shape.
+        assert_eq!(adata.x().shape(), Some((20, 47).into()));
+        assert_eq!(adata.x().get::<CsrMatrix<i32>>().unwrap(), Some(csr.clone()));
+        assert_eq!(
+            adata.x().slice::<CsrMatrix<i32>, _>(s![3..10, 5..40]).unwrap(),
+            Some(CsrMatrix::from(&DMatrix::from_fn(7, 35, |i, j| x[[i + 3, j + 5]]))),
+        );
+        for (chunk, i, j) in adata.x().chunked_cols::<CsrMatrix<i32>>(10) {
+            assert_eq!(chunk, CsrMatrix::from(&DMatrix::from_fn(20, j - i, |r, c| x[[r, i + c]])));
+        }
+        for (chunk, i, j) in adata.x().chunked::<CsrMatrix<i32>>(7) {
+            assert_eq!(chunk, CsrMatrix::from(&DMatrix::from_fn(j - i, 47, |r, c| x[[i + r, c]])));
+        }
+
+        // The orientation is kept when X is replaced by a CSR matrix and
+        // after reopening, but not by other arrays.
+        adata.set_x(&csr).unwrap();
+        assert!(adata.is_x_transposed());
+        adata.close().unwrap();
+        let adata = AnnData::<B>::open(B::open_rw(&file).unwrap()).unwrap();
+        assert!(adata.is_x_transposed());
+        assert_eq!(adata.x().get::<CsrMatrix<i32>>().unwrap(), Some(csr.clone()));
+
+        adata.subset([(2..8).into(), SelectInfoElem::from(vec![4, 0, 9])]).unwrap();
+        let expected = x.select(ndarray::Axis(0), &[2, 3, 4, 5, 6, 7]).select(ndarray::Axis(1), &[4, 0, 9]);
+        let expected_csr = CsrMatrix::from(&dense(&expected));
+        assert_eq!(adata.x().get::<CsrMatrix<i32>>().unwrap(), Some(expected_csr.clone()));
+        assert!(adata.append_x_rows([expected_csr.clone()].into_iter(), None).is_err());
+
+        // The stored matrix is a standard CSC matrix of the same shape.
+        adata.close().unwrap();
+        let stored = ArrayData::read(&DataContainer::<B>::Group(
+            B::open(&file).unwrap().open_group("X").unwrap(),
+        )).unwrap();
+        assert_eq!(stored, ArrayData::from(CscMatrix::from(&dense(&expected))));
+        let adata = AnnData::<B>::open(B::open_rw(&file).unwrap()).unwrap();
+
+        // Exported files store X as a CSR matrix.
+        let out = dir.join("out.h5ad");
+        adata.write::<B, _>(&out).unwrap();
+        let exported = AnnData::<B>::open(B::open(&out).unwrap()).unwrap();
+        assert!(!exported.is_x_transposed());
+        assert_eq!(exported.x().get::<CsrMatrix<i32>>().unwrap(), Some(expected_csr.clone()));
+
+        adata.set_x_transposed(false).unwrap();
+        assert!(!adata.is_x_transposed());
+        assert_eq!(adata.x().get::<CsrMatrix<i32>>().unwrap(), Some(expected_csr));
+
+        // Only CSR matrices can be stored transposed.
+        adata.set_x(&expected).unwrap();
+        assert!(adata.set_x_transposed(true).is_err());
+        assert!(!adata.is_x_transposed());
+        assert_eq!(adata.x().get::<Array2<i32>>().unwrap(), Some(expected));
+    })
+}
+
+fn test_evict<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = Array2::<f64>::ones((10, 20));
+        adata.set_x(&x).unwrap();
+        adata.uns().add("scale", 0.5f64).unwrap();
+
+        let elem = adata.x();
+        elem.inner().enable_cache();
+        elem.inner().data::<ArrayData>().unwrap();
+        assert_eq!(adata.cache_bytes(), 10 * 20 * 8);
+        elem.inner().evict();
+        assert_eq!(adata.cache_bytes(), 0);
+        assert!(elem.inner().to_string().ends_with("cache_enabled: yes, cached: no"));
+        assert_eq!(elem.inner().data::<Array2<f64>>().unwrap(), x);
+        assert_eq!(adata.cache_bytes(), 10 * 20 * 8);
+
+        let elem = adata.uns().inner().get("scale").unwrap().clone();
+        elem.inner().enable_cache();
+        elem.inner().data::<f64>().unwrap();
+        elem.inner().evict();
+        assert!(elem.inner().to_string().ends_with("cache_enabled: yes, cached: no"));
+        assert_eq!(elem.inner().data::<f64>().unwrap(), 0.5);
+        assert!(elem.inner().to_string().ends_with("cached: yes"));
+    })
+}
+
+fn test_obsm_dataframe<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.set_x(Array2::<f64>::zeros((4, 2))).unwrap();
+        let spatial = df!(
+            "x" => &[0.5f64, 1.5, 2.5, 3.5],
+            "y" => &[1i32, 2, 3, 4],
+            "region" => &["a", "b", "a", "c"],
+        ).unwrap();
+        adata.obsm().add("spatial", spatial.clone()).unwrap();
+        assert!(adata.obsm().add("bad", spatial.head(Some(3))).is_err());
+        assert_eq!(adata.obsm().get_item::<DataFrame>("spatial").unwrap(), Some(spatial.clone()));
+        assert_eq!(
+            adata.obsm().get_item_slice::<DataFrame, _>("spatial", s![1..3, ..]).unwrap(),
+            Some(spatial.slice(1, 2)),
+        );
+
+        adata.subset([SelectInfoElem::from(vec![3, 1]), SelectInfoElem::full()]).unwrap();
+        assert_eq!(
+            adata.obsm().get_item::<DataFrame>("spatial").unwrap(),
+            Some(df!(
+                "x" => &[3.5f64, 1.5],
+                "y" => &[4i32, 2],
+                "region" => &["c", "b"],
+            ).unwrap()),
+        );
+        assert!(adata.validate().unwrap().is_empty());
+    })
+}
+
+fn test_column_stats<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = array![[1i32, 0, -2], [0, 0, 0], [3, 4, 0], [0, 5, 0], [2, 0, 1]];
+        let close = |a: Array1<f64>, b: Array1<f64>| (a - b).iter().all(|x| x.abs() < 1e-9);
+        let check = |adata: &AnnData<B>, chunk_size: usize| {
+            let chunks = adata.x().chunked::<ArrayData>(chunk_size).map(|(x, _, _)| x);
+            let stats = Statistics::from_chunks(3, chunks).unwrap();
+            assert_eq!(stats.n_rows(), 5);
+            assert!(close(stats.mean(), array![1.2, 1.8, -0.2]));
+            assert!(close(stats.variance(), array![1.7, 6.2, 1.2]));
+            assert_eq!(stats.min(), array![0.0, 0.0, -2.0]);
+            assert_eq!(stats.max(), array![3.0, 5.0, 1.0]);
+            assert_eq!(stats.quantile(0.0).unwrap(), array![0.0, 0.0, -2.0]);
+            assert_eq!(stats.quantile(0.5).unwrap(), array![1.0, 0.0, 0.0]);
+            assert_eq!(stats.quantile(1.0).unwrap(), array![3.0, 5.0, 1.0]);
+            assert!(stats.quantile(1.5).is_err());
+        };
+        adata.set_x(x.clone()).unwrap();
+        check(&adata, 2);
+        check(&adata, 5);
+        adata.set_x(ArrayData::from(x.into_dyn()).to_sparse(0.0).unwrap()).unwrap();
+        check(&adata, 2);
+        check(&adata, 5);
+
+        let mut stats = Statistics::new(2);
+        assert!(stats.mean().iter().all(|x| x.is_nan()));
+        assert!(stats.update(ArrayData::from(array![[1.0, 2.0, 3.0]].into_dyn())).is_err());
+    })
+}
+
+fn test_add_layer<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = array![[1.0f64, 0.0, 2.0], [0.0, 3.0, 0.0]];
+        adata.set_x(x.clone()).unwrap();
+        adata.add_layer("counts", x.clone()).unwrap();
+        adata.add_layer("log1p", x.mapv(f64::ln_1p)).unwrap();
+        assert_eq!(adata.layers().keys().len(), 2);
+        assert_eq!(adata.read_layer("counts").unwrap().unwrap(), ArrayData::from(x.clone().into_dyn()));
+        assert_eq!(adata.x().get::<Array2<f64>>().unwrap().unwrap(), x);
+
+        assert!(adata.add_layer("counts", x.clone()).is_err());
+        assert!(adata.add_layer("wrong", array![[1.0f64, 2.0]]).is_err());
+        assert!(adata.add_layer("flat", array![1.0f64, 2.0]).is_err());
+        assert_eq!(adata.layers().keys().len(), 2);
+    })
+}
+
+fn test_x_writer<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let source = AnnData::<B>::new(dir.join("source.h5ad")).unwrap();
+        let x = array![[1.0f64, 0.0, 2.0], [0.0, 0.0, 0.0], [3.0, 4.0, 0.0], [0.0, 5.0, 0.0], [6.0, 0.0, 0.0]];
+        for data in [ArrayData::from(x.clone().into_dyn()), ArrayData::from(x.clone().into_dyn()).to_sparse(0.0).unwrap()] {
+            source.set_x(data.clone()).unwrap();
+            let target = AnnData::<B>::new(dir.join("target.h5ad")).unwrap();
+            let chunks = source.x().chunked::<ArrayData>(2).map(|(x, _, _)| x);
+            target.x_writer().write_all(chunks).unwrap();
+            assert_eq!(target.n_obs(), 5);
+            assert_eq!(target.n_vars(), 3);
+            assert_eq!(target.x().get::<ArrayData>().unwrap().unwrap(), data);
+        }
+
+        // The number of rows must match the observations.
+        let target = AnnData::<B>::new(dir.join("target.h5ad")).unwrap();
+        target.set_obs_names(vec!["a".to_string(), "b".to_string()].into()).unwrap();
+        let mut writer = target.x_writer();
+        writer.write(x.slice(ndarray::s![0..2, ..]).to_owned()).unwrap();
+        writer.write(x.slice(ndarray::s![2..3, ..]).to_owned()).unwrap();
+        assert_eq!(writer.n_rows(), 3);
+        assert!(writer.finish().is_err());
+        assert!(target.x().is_empty());
+        let chunks = [x.slice(ndarray::s![0..1, ..]).to_owned(), x.slice(ndarray::s![4..5, ..]).to_owned()];
+        target.x_writer().write_all(chunks).unwrap();
+        assert_eq!(target.x().get::<Array2<f64>>().unwrap().unwrap(), array![[1.0, 0.0, 2.0], [6.0, 0.0, 0.0]]);
+
+        // Chunks must have the same number of columns.
+        let mut writer = target.x_writer();
+        writer.write(x.slice(ndarray::s![0..2, ..]).to_owned()).unwrap();
+        assert!(writer.write(array![[1.0f64, 2.0]]).is_err());
+        drop(writer);
+        assert!(target.x().is_empty());
+        assert!(target.x_writer().finish().is_err());
+    })
+}
+
+fn test_set_x_par<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let (n, m) = (40, 30);
+        let mut indptr = vec![0];
+        let mut indices = Vec::new();
+        let mut values = Vec::new();
+        for i in 0..n {
+            for j in (i % 3..m).step_by(i % 4 + 2) {
+                indices.push(j);
+                values.push((i * m + j) as f32);
+            }
+            indptr.push(indices.len());
+        }
+        let x = CsrMatrix::try_from_csr_data(n, m, indptr, indices, values).unwrap();
+
+        let read_x = |path: std::path::PathBuf| {
+            let file = B::open(path).unwrap();
+            let group = file.open_group("X").unwrap();
+            ["indptr", "indices", "data"].map(|name| {
+                let dataset = group.open_dataset(name).unwrap();
+                DynArray::read(&DataContainer::<B>::Dataset(dataset)).unwrap()
+            })
+        };
+        let adata = AnnData::<B>::new(dir.join("expected.h5ad")).unwrap();
+        adata.set_x(x.clone()).unwrap();
+        adata.close().unwrap();
+        let expected = read_x(dir.join("expected.h5ad"));
+
+        // The chunk size may or may not divide the number of values.
+        for chunk_size in [7, 1000000] {
+            let path = dir.join(format!("par_{}.h5ad", chunk_size));
+            let adata = AnnData::<B>::new(&path).unwrap();
+            adata.set_x_par(&x, chunk_size).unwrap();
+            assert_eq!(adata.x().get::<CsrMatrix<f32>>().unwrap().unwrap(), x);
+            assert!(adata.set_x_par(&x, 0).is_err());
+            assert_eq!(adata.x().get::<CsrMatrix<f32>>().unwrap().unwrap(), x);
+            adata.close().unwrap();
+            assert_eq!(read_x(path), expected);
+        }
+
+        let adata = AnnData::<B>::new(dir.join("empty.h5ad")).unwrap();
+        adata.set_x_par(&CsrMatrix::<f32>::zeros(3, 2), 7).unwrap();
+        assert_eq!(adata.x().get::<CsrMatrix<f32>>().unwrap().unwrap(), CsrMatrix::zeros(3, 2));
+    })
+}
+
+fn test_legacy_sparse<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let path = dir.join("test.h5ad");
+        let adata = AnnData::<B>::new(&path).unwrap();
+        adata.set_x(array![[1.0f64, 0.0, 2.0], [0.0, 0.0, 3.0]]).unwrap();
+        adata.close().unwrap();
+
+        // Rewrite X with only the attributes of the legacy h5sparse layout.
+        let file = B::open_rw(&path).unwrap();
+        file.delete("X").unwrap();
+        let group = file.create_group("X").unwrap();
+        group.create_array_data("data", &array![1.0f64, 2.0, 3.0], Default::default()).unwrap();
+        group.create_array_data("indices", &array![0i64, 2, 2], Default::default()).unwrap();
+        group.create_array_data("indptr", &array![0i64, 2, 3], Default::default()).unwrap();
+        group.write_str_attr("h5sparse_format", "csr").unwrap();
+        group.write_array_attr("h5sparse_shape", &[2usize, 3]).unwrap();
+        file.close().unwrap();
+
+        let adata = AnnData::<B>::open(B::open(&path).unwrap()).unwrap();
+        assert_eq!(adata.x().inner().dtype(), anndata::backend::DataType::CsrMatrix(ScalarType::F64));
+        assert_eq!((adata.n_obs(), adata.n_vars()), (2, 3));
+        let expected = CsrMatrix::try_from_csr_data(2, 3, vec![0, 2, 3], vec![0, 2, 2], vec![1.0f64, 2.0, 3.0]).unwrap();
+        assert_eq!(adata.x().get::<CsrMatrix<f64>>().unwrap().unwrap(), expected);
+        assert_eq!(
+            adata.x().slice::<CsrMatrix<f64>, _>(s![1..2, ..]).unwrap().unwrap(),
+            CsrMatrix::try_from_csr_data(1, 3, vec![0, 1], vec![2], vec![3.0f64]).unwrap(),
+        );
+    })
+}
+
+fn test_contains<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.obsm().add("pca", array![[1.0f64, 2.0], [3.0, 4.0]]).unwrap();
+        adata.uns().add("n_pcs", Data::from(2i64)).unwrap();
+        assert!(adata.obsm().contains("pca"));
+        assert!(!adata.obsm().contains("umap"));
+        assert!(adata.obsm().get_item::<Array2<f64>>("umap").unwrap().is_none());
+        assert!(adata.uns().contains("n_pcs"));
+        assert!(!adata.uns().contains("missing"));
+        assert!(adata.uns().get_item::<Data>("missing").unwrap().is_none());
+        adata.obsm().remove("pca").unwrap();
+        assert!(!adata.obsm().contains("pca"));
+    })
+}
+
+fn test_read_x_range<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        assert!(adata.read_x_range(0, 0).is_err());
+
+        let x = array![[1.0f64, 0.0, 2.0], [0.0, 0.0, 0.0], [3.0, 4.0, 0.0], [0.0, 5.0, 0.0], [6.0, 0.0, 0.0]];
+        let dense = ArrayData::from(x.clone().into_dyn());
+        let expected = ArrayData::from(x.slice(ndarray::s![1..4, ..]).to_owned().into_dyn());
+        adata.set_x(dense.clone()).unwrap();
+        assert_eq!(adata.read_x_range(1, 4).unwrap(), expected);
+        assert_eq!(adata.read_x_range(2, 2).unwrap().shape()[0], 0);
+
+        adata.set_x(dense.clone().to_sparse(0.0).unwrap()).unwrap();
+        let rows = adata.read_x_range(1, 4).unwrap();
+        assert!(matches!(rows, ArrayData::CsrMatrix(_)));
+        assert_eq!(rows, expected.to_sparse(0.0).unwrap());
+        assert_eq!(adata.read_x_range(0, 5).unwrap(), dense.to_sparse(0.0).unwrap());
+
+        assert!(adata.read_x_range(3, 2).is_err());
+        assert!(adata.read_x_range(4, 6).is_err());
+    })
+}
+
+fn test_uns_remove<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let path = dir.join("test.h5ad");
+        let adata = AnnData::<B>::new(&path).unwrap();
+        adata.uns().add("a", Data::from(1i64)).unwrap();
+        adata.uns().add("b", Data::from("b".to_string())).unwrap();
+        adata.uns().remove("a").unwrap();
+        adata.uns().remove("missing").unwrap();
+        assert_eq!(adata.uns().keys(), vec!["b".to_string()]);
+        adata.close().unwrap();
+
+        let adata = AnnData::<B>::open(B::open(&path).unwrap()).unwrap();
+        assert_eq!(adata.uns().keys(), vec!["b".to_string()]);
+        assert!(adata.uns().get_item::<Data>("a").unwrap().is_none());
+    })
+}
+
+fn test_split_by<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = Array2::from_shape_fn((5, 3), |(i, j)| (i * 3 + j) as i32);
+        adata.set_x(&x).unwrap();
+        adata.set_obs(df!("batch" => &["b", "a", "b", "c", "a"]).unwrap()).unwrap();
+        adata.set_obs_names((0..5).map(|i| format!("cell_{}", i)).collect()).unwrap();
+        adata.set_var_names(vec!["g1".to_string(), "g2".to_string(), "g3".to_string()].into()).unwrap();
+
+        let dataset = adata.split_by("batch", dir.join("split")).unwrap();
+        assert_eq!(dataset.n_obs(), 5);
+        assert_eq!(dataset.var_names().into_vec(), ["g1", "g2", "g3"]);
+        assert_eq!(dataset.obs_names().into_vec(), ["cell_0", "cell_2", "cell_1", "cell_4", "cell_3"]);
+        let batches: Vec<_> = dataset.read_obs().unwrap().column("batch").unwrap()
+            .utf8().unwrap().into_no_null_iter().map(|x| x.to_string()).collect();
+        assert_eq!(batches, ["b", "b", "a", "a", "c"]);
+        let expected = x.select(ndarray::Axis(0), &[0, 2, 1, 4, 3]);
+        assert_eq!(dataset.adatas().inner().get_x().data::<Array2<i32>>().unwrap().unwrap(), expected);
+
+        let a = AnnData::<B>::open(B::open(dir.join("split").join("a.h5ad")).unwrap()).unwrap();
+        assert_eq!(a.x().get::<Array2<i32>>().unwrap().unwrap(), x.select(ndarray::Axis(0), &[1, 4]));
+        assert_eq!(a.var_names().into_vec(), ["g1", "g2", "g3"]);
+
+        assert!(adata.split_by("missing", dir.join("split2")).is_err());
+    })
+}
+
+fn test_set_x_shape<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.set_var_names(vec!["a".to_string(), "b".to_string()].into()).unwrap();
+
+        // A rejected X does not establish n_obs.
+        assert!(adata.set_x(Array2::<f64>::zeros((4, 3))).is_err());
+        assert_eq!(adata.n_obs(), 0);
+        assert!(adata.x().is_empty());
+
+        let x = Array2::<f64>::ones((4, 2));
+        adata.set_x(&x).unwrap();
+        adata.obsm().add("pca", Array2::<f64>::zeros((4, 5))).unwrap();
+        assert!(adata.set_x(Array2::<f64>::zeros((5, 2))).is_err());
+        assert!(adata.set_x(Array2::<f64>::zeros((4, 3))).is_err());
+        assert!(adata.set_x_from_iter(std::iter::once(Array2::<f64>::zeros((3, 2)))).is_err());
+        assert_eq!((adata.n_obs(), adata.n_vars()), (4, 2));
+
+        adata.set_x(Array2::<f64>::zeros((4, 2))).unwrap();
+        assert_eq!(adata.x().get::<Array2<f64>>().unwrap().unwrap(), Array2::<f64>::zeros((4, 2)));
+    })
+}
+
+fn test_to_memory<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let path = dir.join("test.h5ad");
+        let adata = AnnData::<B>::new(&path).unwrap();
+        let x = array![[1.0f64, 0.0, 2.0], [0.0, 3.0, 0.0]];
+        let counts = ArrayData::from(x.clone().into_dyn()).to_sparse(0.0).unwrap();
+        adata.set_x(&x).unwrap();
+        adata.set_obs(df!("n_genes" => &[2, 1]).unwrap()).unwrap();
+        adata.set_obs_names(vec!["c1".to_string(), "c2".to_string()].into()).unwrap();
+        adata.obsm().add("pca", array![[1.0f32], [2.0]]).unwrap();
+        adata.layers().add("counts", counts.clone()).unwrap();
+        adata.uns().add("n_pcs", Data::from(1i64)).unwrap();
+        adata.close().unwrap();
+
+        let mem = MemAnnData::read::<B, _>(&path).unwrap();
+        assert_eq!((mem.n_obs, mem.n_vars), (2, 3));
+        let mem_x: Array2<f64> = mem.x.clone().unwrap().try_into().unwrap();
+        assert_eq!(mem_x, x);
+        assert_eq!(mem.obs.column("n_genes").unwrap().i32().unwrap().into_no_null_iter().collect::<Vec<_>>(), [2, 1]);
+        assert_eq!(mem.obs_names.into_vec(), ["c1", "c2"]);
+        assert_eq!(mem.var.width(), 0);
+        assert_eq!(mem.obsm["pca"], ArrayData::from(array![[1.0f32], [2.0]].into_dyn()));
+        let mem_counts: CsrMatrix<f64> = mem.layers["counts"].clone().try_into().unwrap();
+        assert_eq!(ArrayData::from(mem_counts), counts);
+        assert!(mem.varm.is_empty() && mem.obsp.is_empty());
+        assert_eq!(mem.uns["n_pcs"], Data::from(1i64));
+    })
+}
+
+fn test_write_columns<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = Array2::from_shape_fn((3, 4), |(i, j)| (i * 4 + j) as f64);
+        adata.layers().add("imputed", &x).unwrap();
+        let layer = adata.layers().get("imputed").unwrap();
+        layer.lock().as_mut().unwrap().enable_cache();
+        assert_eq!(layer.get::<Array2<f64>>().unwrap().unwrap(), x);
+
+        let cols = array![[-1.0f64, -2.0], [-3.0, -4.0], [-5.0, -6.0]];
+        layer.write_columns(&[3, 1], ArrayData::from(cols.clone().into_dyn())).unwrap();
+        let mut expected = x.clone();
+        expected.column_mut(3).assign(&cols.column(0));
+        expected.column_mut(1).assign(&cols.column(1));
+        assert_eq!(layer.get::<Array2<f64>>().unwrap().unwrap(), expected);
+        assert_eq!(adata.read_layer("imputed").unwrap().unwrap(), ArrayData::from(expected.into_dyn()));
+
+        let write = |cidx: &[usize], data: ArrayData| layer.write_columns(cidx, data);
+        assert!(write(&[0], Array2::<f64>::zeros((2, 1)).into()).is_err());
+        assert!(write(&[0, 1], Array2::<f64>::zeros((3, 1)).into()).is_err());
+        assert!(write(&[4], Array2::<f64>::zeros((3, 1)).into()).is_err());
+        assert!(write(&[0, 0], Array2::<f64>::zeros((3, 2)).into()).is_err());
+        assert!(write(&[0], Array2::<i32>::zeros((3, 1)).into()).is_err());
+
+        adata.layers().add("counts", ArrayData::from(x.clone().into_dyn()).to_sparse(0.0).unwrap()).unwrap();
+        let counts = adata.layers().get("counts").unwrap();
+        assert!(counts.write_columns(&[0], Array2::<f64>::zeros((3, 1)).into()).is_err());
+    })
+}
+
+fn test_create_empty<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.set_x(Array2::<f64>::zeros((3, 4))).unwrap();
+        let layer = adata.layers()
+            .create_empty("imputed", vec![3, 4].into(), ScalarType::F32, DynScalar::F64(-1.0))
+            .unwrap();
+        assert_eq!(layer.get::<Array2<f32>>().unwrap().unwrap(), Array2::from_elem((3, 4), -1.0f32));
+
+        layer.write_rows(1, array![[1.0f32, 2.0, 3.0, 4.0]].into()).unwrap();
+        layer.write_columns(&[3], array![[5.0f32], [6.0], [7.0]].into()).unwrap();
+        let expected = array![[-1.0f32, -1.0, -1.0, 5.0], [1.0, 2.0, 3.0, 6.0], [-1.0, -1.0, -1.0, 7.0]];
+        assert_eq!(adata.read_layer("imputed").unwrap().unwrap(), ArrayData::from(expected.into_dyn()));
+
+        assert!(layer.write_rows(3, array![[1.0f32, 2.0, 3.0, 4.0]].into()).is_err());
+        assert!(layer.write_rows(0, array![[1.0f32, 2.0]].into()).is_err());
+        assert!(layer.write_rows(0, array![[1.0f64, 2.0, 3.0, 4.0]].into()).is_err());
+        assert!(adata.layers().create_empty("wrong", vec![2, 4].into(), ScalarType::F32, DynScalar::F32(0.0)).is_err());
+        assert!(adata.layers().create_empty("text", vec![3, 4].into(), ScalarType::String, DynScalar::String("".into())).is_err());
+        assert_eq!(adata.layers().keys(), vec!["imputed".to_string()]);
+    })
+}
+
+fn test_index_fallback<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let path = dir.join("test.h5ad");
+        let names = || vec!["c1".to_string(), "c2".to_string()];
+        let rename_index = |to: &str| {
+            let adata = AnnData::<B>::new(&path).unwrap();
+            adata.set_x(Array2::<f64>::zeros((2, 3))).unwrap();
+            adata.set_obs(df!("n_genes" => &[1, 2]).unwrap()).unwrap();
+            adata.set_obs_names(names().into()).unwrap();
+            adata.close().unwrap();
+
+            // The `_index` attribute refers to a dataset that does not exist.
+            let file = B::open_rw(&path).unwrap();
+            let obs = file.open_group("obs").unwrap();
+            let index = obs.read_str_attr("_index").unwrap();
+            if index != to {
+                obs.rename(&index, to).unwrap();
+            }
+            obs.write_str_attr("_index", "missing").unwrap();
+            file.close().unwrap();
+        };
+
+        rename_index("index");
+        let adata = AnnData::<B>::open(B::open(&path).unwrap()).unwrap();
+        assert_eq!(adata.obs_names().into_vec(), names());
+        assert_eq!(adata.read_obs().unwrap().column("n_genes").unwrap().len(), 2);
+        adata.close().unwrap();
+
+        // The fallback names are global, and are left set as other tests do
+        // not depend on them.
+        rename_index("CellID");
+        anndata::data::dataframe::set_fallback_index_names(["CellID"]);
+        let adata = AnnData::<B>::open(B::open(&path).unwrap()).unwrap();
+        assert_eq!(adata.obs_names().into_vec(), names());
+    })
+}
+
+fn test_cached_handle<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = array![[1.0f64, 2.0], [3.0, 4.0]];
+        adata.set_x(&x).unwrap();
+        let elem = adata.x();
+        assert!(elem.inner().cached().is_none());
+        elem.lock().as_mut().unwrap().enable_cache();
+        assert_eq!(elem.get::<Array2<f64>>().unwrap().unwrap(), x);
+
+        let handle = elem.inner().cached().unwrap();
+        assert!(std::sync::Arc::ptr_eq(&handle, &elem.inner().cached().unwrap()));
+        assert_eq!(*handle, ArrayData::from(x.clone().into_dyn()));
+
+        // Writing replaces the cached data, and the handle keeps the old data.
+        let y = x.mapv(|v| v * 10.0);
+        adata.set_x(&y).unwrap();
+        assert_eq!(*handle, ArrayData::from(x.clone().into_dyn()));
+        assert_eq!(*elem.inner().cached().unwrap(), ArrayData::from(y.clone().into_dyn()));
+        elem.lock().as_mut().unwrap().evict();
+        assert!(elem.inner().cached().is_none());
+        assert_eq!(*handle, ArrayData::from(x.into_dyn()));
+    })
+}
+
+fn test_subset_strided<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let x = Array2::from_shape_fn((10, 3), |(i, j)| if (i + j) % 2 == 0 { 0 } else { (i * 3 + j) as i64 });
+        adata.set_x(ArrayData::from(x.clone()).to_sparse(0.0).unwrap()).unwrap();
+        adata.set_obs(df!("i" => (0..10).map(|i| i as i64).collect::<Vec<_>>()).unwrap()).unwrap();
+
+        let slice = ndarray::Slice::new(0, None, 3);
+        adata.subset([slice.into(), SelectInfoElem::full()]).unwrap();
+        assert_eq!(adata.n_obs(), 4);
+        assert_eq!(adata.x().shape(), Some((4, 3).into()));
+        assert_eq!(adata.read_obs().unwrap().height(), 4);
+        let csr: CsrMatrix<i64> = adata.x().get().unwrap().unwrap();
+        let dense: Array2<i64> = ArrayData::from(csr).to_dense().unwrap().try_into().unwrap();
+        assert_eq!(dense, x.slice_axis(ndarray::Axis(0), slice).to_owned());
+    })
+}
+
+fn test_subset_step<B: Backend>() {
+    with_tmp_dir(|dir| {
+        for slice in [ndarray::Slice::new(0, None, -1), ndarray::Slice::new(0, None, 3)] {
+            let adata = AnnData::<B>::new(dir.join(format!("step{}.h5ad", slice.step))).unwrap();
+            let x = Array2::from_shape_fn((6, 2), |(i, j)| (i * 2 + j) as i64);
+            adata.set_x(&x).unwrap();
+            adata.set_obs_names((0..6).map(|i| format!("c{}", i)).collect()).unwrap();
+            adata.set_obs(df!("i" => (0..6).map(|i| i as i64).collect::<Vec<_>>()).unwrap()).unwrap();
+
+            adata.subset([slice.into(), SelectInfoElem::full()]).unwrap();
+            let x: Array2<i64> = adata.x().get().unwrap().unwrap();
+            let obs = adata.read_obs().unwrap();
+            let rows: Vec<i64> = obs.column("i").unwrap().i64().unwrap().into_no_null_iter().collect();
+            assert_eq!(x.column(0).iter().map(|v| v / 2).collect::<Vec<_>>(), rows);
+            assert_eq!(
+                adata.obs_names().into_vec(),
+                rows.iter().map(|i| format!("c{}", i)).collect::<Vec<_>>(),
+            );
+        }
+    })
+}
+
+fn test_csr_select_columns<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let csr = CsrMatrix::try_from_csr_data(
+            4, 4, vec![0, 2, 3, 3, 6], vec![0, 2, 1, 0, 2, 3], vec![1.0f64, 2.0, 3.0, 4.0, 5.0, 6.0],
+        ).unwrap();
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        adata.set_x(&csr).unwrap();
+
+        let select = |rows: SelectInfoElem, cols: Vec<usize>| -> DMatrix<f64> {
+            let data: CsrMatrix<f64> = adata.x().slice([rows, cols.into()]).unwrap().unwrap();
+            DMatrix::from(&data)
+        };
+        assert_eq!(
+            select(SelectInfoElem::full(), vec![2, 0]),
+            DMatrix::from_row_slice(4, 2, &[2.0, 1.0, 0.0, 0.0, 0.0, 0.0, 5.0, 4.0]),
+        );
+        assert_eq!(
+            select((1..4).into(), vec![3, 3]),
+            DMatrix::from_row_slice(3, 2, &[0.0, 0.0, 0.0, 0.0, 6.0, 6.0]),
+        );
+    })
+}
+
+fn test_parquet<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let obs = df!(
+            "n_genes" => &[10, 20, 30],
+            "cell_type" => &["T", "B", "T"],
+        ).unwrap().lazy()
+            .with_column(col("cell_type").cast(DataType::Categorical(None)))
+            .collect().unwrap();
+        let adata = AnnData::<B>::new(dir.join("input.h5ad")).unwrap();
+        adata.set_obs(obs.clone()).unwrap();
+        adata.set_obs_names(["c1", "c2", "c3"].into_iter().map(|x| x.to_string()).collect()).unwrap();
+        adata.get_obs().export_parquet(dir.join("obs.parquet")).unwrap();
+
+        let adata = AnnData::<B>::new(dir.join("output.h5ad")).unwrap();
+        adata.set_obs_from_parquet(dir.join("obs.parquet")).unwrap();
+        assert_eq!(adata.obs_names().into_vec(), vec!["c1", "c2", "c3"]);
+        let obs_in = adata.read_obs().unwrap();
+        assert_eq!(obs_in.column("cell_type").unwrap().dtype(), &DataType::Categorical(None));
+        let to_str = |df: DataFrame| df.lazy()
+            .with_column(col("cell_type").cast(DataType::Utf8))
+            .collect().unwrap();
+        assert!(to_str(obs_in).frame_equal(&to_str(obs)));
+    })
+}
+
+fn test_categorical<B: Backend>() {
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<B>::new(dir.join("test.h5ad")).unwrap();
+        let cell_type = CategoricalArray {
+            codes: array![0u32, 2, 0].into_dyn(),
+            categories: array!["T".to_string(), "NK".to_string(), "B".to_string()],
+            ordered: true,
+        };
+        let item = ArrayData::Array(DynArray::Categorical(cell_type.clone()));
+        adata.uns().add("cell_type", item).unwrap();
+        match adata.uns().get_item::<Data>("cell_type").unwrap().unwrap() {
+            Data::ArrayData(ArrayData::Array(DynArray::Categorical(x))) => assert_eq!(x, cell_type),
+            _ => panic!("expect a categorical array"),
+        }
+
+        let mut column = Series::try_from(DynArray::Categorical(cell_type)).unwrap();
+        column.rename("cell_type");
+        let obs = DataFrame::new(vec![column]).unwrap();
+        adata.set_obs(obs.clone()).unwrap();
+        let obs_in = adata.read_obs().unwrap();
+        let categories = |df: &DataFrame| df.column("cell_type").unwrap().categorical().unwrap()
+            .get_rev_map().get_categories().values_iter().map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(categories(&obs_in), vec!["T", "NK", "B"]);
+        let values = |df: &DataFrame| df.column("cell_type").unwrap().cast(&DataType::Utf8).unwrap();
+        assert!(values(&obs_in).series_equal(&values(&obs)));
+
+        let column = obs_in.column("cell_type").unwrap();
+        assert!(data::dataframe::is_categorical_ordered(column));
+        let mut column = data::dataframe::set_categorical_ordered(column, false).unwrap();
+        assert!(!data::dataframe::is_categorical_ordered(&column));
+        assert!(values(&obs_in).series_equal(&column.cast(&DataType::Utf8).unwrap()));
+        column.rename("unordered");
+        adata.get_obs().inner().set_column("unordered", column).unwrap();
+        let column = adata.read_obs().unwrap().column("unordered").unwrap().clone();
+        assert!(!data::dataframe::is_categorical_ordered(&column));
+    })
+}
+
+#[test]
+fn test_make_unique() {
+    let names = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<DataFrameIndex>();
+    let index = names(&["a", "b", "a", "a-1", "a", "b"]);
+    assert!(!index.is_unique());
+    let index = index.make_unique();
+    assert!(index.is_unique());
+    assert_eq!(index.into_vec(), vec!["a", "b", "a-2", "a-1", "a-3", "b-1"]);
+
+    with_tmp_dir(|dir| {
+        let adata = AnnData::<InMemory>::new(dir.join("test.h5ad")).unwrap();
+        adata.set_x(Array2::<i32>::zeros((3, 2))).unwrap();
+        adata.set_obs_names(names(&["x", "x", "y"])).unwrap();
+        adata.set_var_names(names(&["g1", "g2"])).unwrap();
+        adata.obs_names_make_unique().unwrap();
+        adata.var_names_make_unique().unwrap();
+        assert_eq!(adata.obs_names().into_vec(), vec!["x", "x-1", "y"]);
+        assert_eq!(adata.var_names().into_vec(), vec!["g1", "g2"]);
+    })
+}
+
+#[test]
+fn test_basic_h5() {
+    test_basic::<H5>()
+}
+
+#[test]
+fn test_read_csv_h5() {
+    test_read_csv::<H5>()
+}
+
+#[test]
+fn test_save_h5() {
+    test_save::<H5>()
+}
+
+#[test]
+fn test_concat_h5() {
+    test_concat::<H5>()
+}
+
+#[test]
+fn test_transpose_h5() {
+    test_transpose::<H5>()
+}
+
+#[test]
+fn test_write_config_h5() {
+    test_write_config::<H5>()
+}
+
+#[test]
+fn test_chunk_cache_h5() {
+    test_chunk_cache::<H5>()
+}
+
+#[test]
+fn test_chunked_cols_h5() {
+    test_chunked_cols::<H5>()
+}
+
+#[test]
+fn test_dataset_to_adata_h5() {
+    test_dataset_to_adata::<H5>()
+}
+
+#[test]
+fn test_dataset_components_h5() {
+    test_dataset_components::<H5>()
+}
+
+#[test]
+fn test_ragged_h5() {
+    test_ragged::<H5>()
+}
+
+#[test]
+fn test_record_array_h5() {
+    test_record_array::<H5>()
+}
+
+#[test]
+fn test_f16_h5() {
+    test_f16::<H5>()
+}
+
+#[test]
+fn test_append_x_rows_h5() {
+    test_append_x_rows::<H5>()
+}
+
+#[test]
+fn test_write_select_by_chunk_h5() {
+    test_write_select_by_chunk::<H5>()
+}
+
+#[test]
+fn test_string_array_h5() {
+    test_string_array::<H5>()
+}
+
+#[test]
+fn test_nullable_h5() {
+    test_nullable::<H5>()
+}
+
+#[test]
+fn test_map_chunks_h5() {
+    test_map_chunks::<H5>()
+}
+
+#[test]
+fn test_obs_column_h5() {
+    test_obs_column::<H5>()
+}
+
+#[test]
+fn test_rename_h5() {
+    test_rename::<H5>()
+}
+
+#[test]
+fn test_try_inner_h5() {
+    test_try_inner::<H5>()
+}
+
+#[test]
+fn test_subset_obsp_h5() {
+    test_subset_obsp::<H5>()
+}
+
+#[test]
+fn test_describe_h5() {
+    test_describe::<H5>()
+}
+
+#[test]
+fn test_add_column_h5() {
+    test_add_column::<H5>()
+}
+
+#[test]
+fn test_anndata_eq_h5() {
+    test_anndata_eq::<H5>()
+}
+
+#[test]
+fn test_mask_h5() {
+    test_mask::<H5>()
+}
+
+#[test]
+fn test_write_progress_h5() {
+    test_write_progress::<H5>()
+}
+
+#[test]
+fn test_typed_index_h5() {
+    test_typed_index::<H5>()
+}
+
+#[test]
+fn test_read_layer_h5() {
+    test_read_layer::<H5>()
+}
+
+#[test]
+fn test_cast_h5() {
+    test_cast::<H5>()
+}
+
+#[test]
+fn test_dense_sparse_h5() {
+    test_dense_sparse::<H5>()
+}
+
+#[test]
+fn test_validate_h5() {
+    test_validate::<H5>()
+}
+
+#[test]
+fn test_zarr_h5() {
+    test_zarr::<H5>()
+}
+
+#[test]
+fn test_move_uns_h5() {
+    test_move_uns::<H5>()
+}
+
+#[test]
+fn test_write_mtx_h5() {
+    test_write_mtx::<H5>()
+}
+
+#[test]
+fn test_par_chunked_h5() {
+    test_par_chunked::<H5>()
+}
+
+#[test]
+fn test_complex_h5() {
+    test_complex::<H5>()
+}
+
+#[test]
+fn test_filter_obs_h5() {
+    test_filter_obs::<H5>()
+}
+
+#[test]
+fn test_reorder_obs_h5() {
+    test_reorder_obs::<H5>()
+}
+
+#[test]
+fn test_open_lazy_h5() {
+    test_open_lazy::<H5>()
+}
+
+#[test]
+fn test_chunked_with_obs_h5() {
+    test_chunked_with_obs::<H5>()
+}
+
+#[test]
+fn test_hstack_obsm_h5() {
+    test_hstack_obsm::<H5>()
+}
+
+#[test]
+fn test_temporal_columns_h5() {
+    test_temporal_columns::<H5>()
+}
+
+#[test]
+fn test_prefix_obs_names_h5() {
+    test_prefix_obs_names::<H5>()
+}
+
+#[test]
+fn test_strided_sparse_select_h5() {
+    test_strided_sparse_select::<H5>()
+}
+
+#[test]
+fn test_read_mtx_gz_h5() {
+    test_read_mtx_gz::<H5>()
+}
+
+#[test]
+fn test_x_sums_h5() {
+    test_x_sums::<H5>()
+}
+
+#[test]
+fn test_stack_mixed_dtypes_h5() {
+    test_stack_mixed_dtypes::<H5>()
+}
+
+#[test]
+fn test_read_many_h5() {
+    test_read_many::<H5>()
+}
+
+#[test]
+fn test_external_link_h5() {
+    test_external_link::<H5>()
+}
+
+#[test]
+fn test_verify_integrity_h5() {
+    test_verify_integrity::<H5>()
+}
+
+#[test]
+fn test_builder_h5() {
+    test_builder::<H5>()
+}
+
+#[test]
+fn test_negative_select_h5() {
+    test_negative_select::<H5>()
+}
+
+#[test]
+fn test_cache_budget_h5() {
+    test_cache_budget::<H5>()
+}
+
+#[test]
+fn test_fill_value_h5() {
+    test_fill_value::<H5>()
+}
+
+#[test]
+fn test_join_h5() {
+    test_join::<H5>()
+}
+
+#[test]
+fn test_transposed_x_h5() {
+    test_transposed_x::<H5>()
+}
+
+#[test]
+fn test_evict_h5() {
+    test_evict::<H5>()
+}
+
+#[test]
+fn test_obsm_dataframe_h5() {
+    test_obsm_dataframe::<H5>()
+}
+
+#[test]
+fn test_column_stats_h5() {
+    test_column_stats::<H5>()
+}
+
+#[test]
+fn test_add_layer_h5() {
+    test_add_layer::<H5>()
+}
+
+#[test]
+fn test_x_writer_h5() {
+    test_x_writer::<H5>()
+}
+
+#[test]
+fn test_set_x_par_h5() {
+    test_set_x_par::<H5>()
+}
+
+#[test]
+fn test_legacy_sparse_h5() {
+    test_legacy_sparse::<H5>()
+}
+
+#[test]
+fn test_contains_h5() {
+    test_contains::<H5>()
+}
+
+#[test]
+fn test_read_x_range_h5() {
+    test_read_x_range::<H5>()
+}
+
+#[test]
+fn test_uns_remove_h5() {
+    test_uns_remove::<H5>()
+}
+
+#[test]
+fn test_split_by_h5() {
+    test_split_by::<H5>()
+}
+
+#[test]
+fn test_set_x_shape_h5() {
+    test_set_x_shape::<H5>()
+}
+
+#[test]
+fn test_to_memory_h5() {
+    test_to_memory::<H5>()
+}
+
+#[test]
+fn test_write_columns_h5() {
+    test_write_columns::<H5>()
+}
+
+#[test]
+fn test_create_empty_h5() {
+    test_create_empty::<H5>()
+}
+
+#[test]
+fn test_index_fallback_h5() {
+    test_index_fallback::<H5>()
+}
+
+#[test]
+fn test_cached_handle_h5() {
+    test_cached_handle::<H5>()
+}
+
+#[test]
+fn test_subset_strided_h5() {
+    test_subset_strided::<H5>()
+}
+
+#[test]
+fn test_subset_step_h5() {
+    test_subset_step::<H5>()
+}
+
+#[test]
+fn test_csr_select_columns_h5() {
+    test_csr_select_columns::<H5>()
+}
+
+#[test]
+fn test_parquet_h5() {
+    test_parquet::<H5>()
+}
+
+#[test]
+fn test_categorical_h5() {
+    test_categorical::<H5>()
+}
+
+#[test]
+fn test_basic_mem() {
+    test_basic::<InMemory>()
+}
+
+#[test]
+fn test_save_mem() {
+    test_save::<InMemory>()
+}
+
+#[test]
+fn test_concat_mem() {
+    test_concat::<InMemory>()
+}
+
+#[test]
+fn test_transpose_mem() {
+    test_transpose::<InMemory>()
+}
+
+#[test]
+fn test_write_config_mem() {
+    test_write_config::<InMemory>()
+}
+
+#[test]
+fn test_chunk_cache_mem() {
+    test_chunk_cache::<InMemory>()
+}
+
+#[test]
+fn test_chunked_cols_mem() {
+    test_chunked_cols::<InMemory>()
+}
+
+#[test]
+fn test_dataset_to_adata_mem() {
+    test_dataset_to_adata::<InMemory>()
+}
+
+#[test]
+fn test_dataset_components_mem() {
+    test_dataset_components::<InMemory>()
+}
+
+#[test]
+fn test_ragged_mem() {
+    test_ragged::<InMemory>()
+}
+
+#[test]
+fn test_record_array_mem() {
+    test_record_array::<InMemory>()
+}
+
+#[test]
+fn test_f16_mem() {
+    test_f16::<InMemory>()
+}
+
+#[test]
+fn test_append_x_rows_mem() {
+    test_append_x_rows::<InMemory>()
+}
+
+#[test]
+fn test_write_select_by_chunk_mem() {
+    test_write_select_by_chunk::<InMemory>()
+}
+
+#[test]
+fn test_string_array_mem() {
+    test_string_array::<InMemory>()
+}
+
+#[test]
+fn test_nullable_mem() {
+    test_nullable::<InMemory>()
+}
+
+#[test]
+fn test_map_chunks_mem() {
+    test_map_chunks::<InMemory>()
+}
+
+#[test]
+fn test_obs_column_mem() {
+    test_obs_column::<InMemory>()
+}
+
+#[test]
+fn test_rename_mem() {
+    test_rename::<InMemory>()
+}
+
+#[test]
+fn test_try_inner_mem() {
+    test_try_inner::<InMemory>()
+}
+
+#[test]
+fn test_subset_obsp_mem() {
+    test_subset_obsp::<InMemory>()
+}
+
+#[test]
+fn test_describe_mem() {
+    test_describe::<InMemory>()
+}
+
+#[test]
+fn test_add_column_mem() {
+    test_add_column::<InMemory>()
+}
+
+#[test]
+fn test_anndata_eq_mem() {
+    test_anndata_eq::<InMemory>()
+}
+
+#[test]
+fn test_mask_mem() {
+    test_mask::<InMemory>()
+}
+
+#[test]
+fn test_write_progress_mem() {
+    test_write_progress::<InMemory>()
+}
+
+#[test]
+fn test_typed_index_mem() {
+    test_typed_index::<InMemory>()
+}
+
+#[test]
+fn test_read_layer_mem() {
+    test_read_layer::<InMemory>()
+}
+
+#[test]
+fn test_cast_mem() {
+    test_cast::<InMemory>()
+}
+
+#[test]
+fn test_dense_sparse_mem() {
+    test_dense_sparse::<InMemory>()
+}
+
+#[test]
+fn test_validate_mem() {
+    test_validate::<InMemory>()
+}
+
+#[test]
+fn test_zarr_mem() {
+    test_zarr::<InMemory>()
+}
+
+#[test]
+fn test_move_uns_mem() {
+    test_move_uns::<InMemory>()
+}
+
+#[test]
+fn test_write_mtx_mem() {
+    test_write_mtx::<InMemory>()
+}
+
+#[test]
+fn test_par_chunked_mem() {
+    test_par_chunked::<InMemory>()
+}
+
+#[test]
+fn test_complex_mem() {
+    test_complex::<InMemory>()
+}
+
+#[test]
+fn test_filter_obs_mem() {
+    test_filter_obs::<InMemory>()
+}
+
+#[test]
+fn test_reorder_obs_mem() {
+    test_reorder_obs::<InMemory>()
+}
+
+#[test]
+fn test_open_lazy_mem() {
+    test_open_lazy::<InMemory>()
+}
+
+#[test]
+fn test_chunked_with_obs_mem() {
+    test_chunked_with_obs::<InMemory>()
+}
+
+#[test]
+fn test_hstack_obsm_mem() {
+    test_hstack_obsm::<InMemory>()
+}
+
+#[test]
+fn test_temporal_columns_mem() {
+    test_temporal_columns::<InMemory>()
+}
+
+#[test]
+fn test_prefix_obs_names_mem() {
+    test_prefix_obs_names::<InMemory>()
+}
+
+#[test]
+fn test_strided_sparse_select_mem() {
+    test_strided_sparse_select::<InMemory>()
+}
+
+#[test]
+fn test_read_mtx_gz_mem() {
+    test_read_mtx_gz::<InMemory>()
+}
+
+#[test]
+fn test_x_sums_mem() {
+    test_x_sums::<InMemory>()
+}
+
+#[test]
+fn test_stack_mixed_dtypes_mem() {
+    test_stack_mixed_dtypes::<InMemory>()
+}
+
+#[test]
+fn test_read_many_mem() {
+    test_read_many::<InMemory>()
+}
+
+#[test]
+fn test_external_link_mem() {
+    test_external_link::<InMemory>()
+}
+
+#[test]
+fn test_verify_integrity_mem() {
+    test_verify_integrity::<InMemory>()
+}
+
+#[test]
+fn test_builder_mem() {
+    test_builder::<InMemory>()
+}
+
+#[test]
+fn test_negative_select_mem() {
+    test_negative_select::<InMemory>()
+}
+
+#[test]
+fn test_cache_budget_mem() {
+    test_cache_budget::<InMemory>()
+}
+
+#[test]
+fn test_fill_value_mem() {
+    test_fill_value::<InMemory>()
+}
+
+#[test]
+fn test_join_mem() {
+    test_join::<InMemory>()
+}
+
+#[test]
+fn test_transposed_x_mem() {
+    test_transposed_x::<InMemory>()
+}
+
+#[test]
+fn test_evict_mem() {
+    test_evict::<InMemory>()
+}
+
+#[test]
+fn test_obsm_dataframe_mem() {
+    test_obsm_dataframe::<InMemory>()
+}
+
+#[test]
+fn test_column_stats_mem() {
+    test_column_stats::<InMemory>()
+}
+
+#[test]
+fn test_add_layer_mem() {
+    test_add_layer::<InMemory>()
+}
+
+#[test]
+fn test_x_writer_mem() {
+    test_x_writer::<InMemory>()
+}
+
+#[test]
+fn test_set_x_par_mem() {
+    test_set_x_par::<InMemory>()
+}
+
+#[test]
+fn test_legacy_sparse_mem() {
+    test_legacy_sparse::<InMemory>()
+}
+
+#[test]
+fn test_contains_mem() {
+    test_contains::<InMemory>()
+}
+
+#[test]
+fn test_read_x_range_mem() {
+    test_read_x_range::<InMemory>()
+}
+
+#[test]
+fn test_uns_remove_mem() {
+    test_uns_remove::<InMemory>()
+}
+
+#[test]
+fn test_split_by_mem() {
+    test_split_by::<InMemory>()
+}
+
+#[test]
+fn test_set_x_shape_mem() {
+    test_set_x_shape::<InMemory>()
+}
+
+#[test]
+fn test_to_memory_mem() {
+    test_to_memory::<InMemory>()
+}
+
+#[test]
+fn test_write_columns_mem() {
+    test_write_columns::<InMemory>()
+}
+
+#[test]
+fn test_create_empty_mem() {
+    test_create_empty::<InMemory>()
+}
+
+#[test]
+fn test_index_fallback_mem() {
+    test_index_fallback::<InMemory>()
+}
+
+#[test]
+fn test_cached_handle_mem() {
+    test_cached_handle::<InMemory>()
+}
+
+#[test]
+fn test_subset_strided_mem() {
+    test_subset_strided::<InMemory>()
+}
+
+#[test]
+fn test_subset_step_mem() {
+    test_subset_step::<InMemory>()
+}
+
+#[test]
+fn test_csr_select_columns_mem() {
+    test_csr_select_columns::<InMemory>()
+}
+
+#[test]
+fn test_parquet_mem() {
+    test_parquet::<InMemory>()
+}
+
+#[test]
+fn test_categorical_mem() {
+    test_categorical::<InMemory>()
+}
+
+#[test]
+fn test_write_mem_to_h5() {
+    with_tmp_dir(|dir| {
+        let output = dir.join("output.h5ad");
+        let anndatas = ((0 as usize ..50), (0 as usize ..50))
+            .prop_flat_map(|(n_obs, n_vars)| anndata_strat::<InMemory, _>("input", n_obs, n_vars));
+        proptest!(ProptestConfig::with_cases(20), |(adata in anndatas)| {
+            adata.write::<H5, _>(&output).unwrap();
+            let adata_in = AnnData::<H5>::open(H5::open(&output).unwrap()).unwrap();
+            prop_assert!(anndata_eq(&adata, &adata_in).unwrap());
+            adata_in.close().unwrap();
+        });
+    });
+}
@@ -3,9 +3,12 @@
 mod common;
 use common::*;
 
-use ndarray::Array2;
+use ndarray::{Array, Array2};
+use ndarray_rand::RandomExt;
+use ndarray_rand::rand_distr::Uniform;
 use proptest::prelude::*;
-use anndata::{*, data::{DynCscMatrix, CsrNonCanonical}};
+use anndata::{*, data::{DynCscMatrix, CsrNonCanonical, SelectInfoElem}};
+use anndata::backend::InMemory;
 use anndata_hdf5::H5;
 use std::path::Path;
 use nalgebra_sparse::{CooMatrix, CscMatrix, CsrMatrix};
@@ -103,6 +106,52 @@ where
     });
 }
 
+fn test_bool<F, T>(adata_gen: F)
+where
+    F: Fn() -> T,
+    T: AnnDataOp,
+{
+    let shapes = proptest::collection::vec(0 as usize ..50, 2..4);
+    proptest!(ProptestConfig::with_cases(64), |(shape in shapes)| {
+        let x: ArrayData = Array::random(shape.clone(), Uniform::new(0u8, 2u8))
+            .mapv(|x| x == 1)
+            .into();
+        let adata = adata_gen();
+        adata.set_x(&x).unwrap();
+        prop_assert_eq!(adata.x().get::<ArrayData>().unwrap().unwrap(), x.clone());
+
+        adata.obsm().add("mask", &x).unwrap();
+        prop_assert_eq!(adata.obsm().get_item::<ArrayData>("mask").unwrap().unwrap(), x.clone());
+
+        adata.layers().add("mask", &x).unwrap();
+        prop_assert_eq!(adata.layers().get_item::<ArrayData>("mask").unwrap().unwrap(), x);
+    });
+}
+
+fn test_subset_var_cached<F, B>(adata_gen: F)
+where
+    F: Fn() -> AnnData<B>,
+    B: Backend,
+{
+    // Column indices may be duplicated and out of order.
+    let arrays = (1 as usize..50, 1 as usize..50).prop_flat_map(|(n, m)|
+        (csr_strat(n, m), proptest::collection::vec(0..m, 0..2 * m))
+    );
+    proptest!(ProptestConfig::with_cases(256), |((x, cols) in arrays)| {
+        let adata = adata_gen();
+        adata.set_x(&x).unwrap();
+        adata.get_x().inner().enable_cache();
+        adata.x().get::<ArrayData>().unwrap();
+
+        let select = [SelectInfoElem::full(), cols.into()];
+        adata.subset(select.as_slice()).unwrap();
+        prop_assert_eq!(
+            adata.x().get::<ArrayData>().unwrap().unwrap(),
+            array_select(&x, select.as_slice())
+        );
+    });
+}
+
 
 ////////////////////////////////////////////////////////////////////////////////
 /// Test HDF5 backend
@@ -151,4 +200,81 @@ fn test_iterator_h5() {
         let adata_gen = || AnnData::<H5>::new(&file).unwrap();
         test_iterator(|| adata_gen());
     })
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_subset_var_cached_h5() {
+    with_tmp_dir(|dir| {
+        let file = dir.join("test.h5");
+        let adata_gen = || AnnData::<H5>::new(&file).unwrap();
+        test_subset_var_cached(|| adata_gen());
+    })
+}
+
+#[test]
+fn test_bool_h5() {
+    with_tmp_dir(|dir| {
+        let file = dir.join("test.h5");
+        let adata_gen = || AnnData::<H5>::new(&file).unwrap();
+        test_bool(|| adata_gen());
+    })
+}
+
+
+////////////////////////////////////////////////////////////////////////////////
+/// Test in-memory backend
+////////////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn test_speacial_cases_mem() {
+    let adata_gen = || AnnData::<InMemory>::in_memory().unwrap();
+    test_speacial_cases(|| adata_gen());
+}
+
+#[test]
+fn test_noncanonical_mem() {
+    let adata_gen = || AnnData::<InMemory>::in_memory().unwrap();
+    test_noncanonical(|| adata_gen());
+}
+
+#[test]
+fn test_io_mem() {
+    let adata_gen = || AnnData::<InMemory>::in_memory().unwrap();
+    test_io(|| adata_gen());
+}
+
+#[test]
+fn test_index_mem() {
+    let adata_gen = || AnnData::<InMemory>::in_memory().unwrap();
+    test_index(|| adata_gen());
+}
+
+#[test]
+fn test_iterator_mem() {
+    let adata_gen = || AnnData::<InMemory>::in_memory().unwrap();
+    test_iterator(|| adata_gen());
+}
+
+#[test]
+fn test_subset_var_cached_mem() {
+    let adata_gen = || AnnData::<InMemory>::in_memory().unwrap();
+    test_subset_var_cached(|| adata_gen());
+}
+
+#[test]
+fn test_bool_mem() {
+    let adata_gen = || AnnData::<InMemory>::in_memory().unwrap();
+    test_bool(|| adata_gen());
+}
+
+#[test]
+fn test_sparse_get() {
+    // Indices equal to the shape are out of bounds.
+    let arrays = (1 as usize..50, 1 as usize..50).prop_flat_map(|(n, m)|
+        (prop_oneof![csr_strat(n, m), csc_strat(n, m)], 0..=n, 0..=m)
+    );
+    proptest!(ProptestConfig::with_cases(256), |((x, i, j) in arrays)| {
+        let dense = x.clone().to_dense().unwrap();
+        prop_assert_eq!(x.get(&[i, j]), dense.get(&[i, j]));
+    });
+}
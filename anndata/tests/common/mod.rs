@@ -2,7 +2,6 @@ use anndata::*;
 
 use anndata::data::index::Interval;
 use anndata::data::{BoundedSelectInfoElem, DataFrameIndex, SelectInfoElem};
-use anyhow::Result;
 use itertools::Itertools;
 use nalgebra::base::DMatrix;
 use nalgebra::{ClosedAdd, Scalar};
@@ -317,7 +316,7 @@ where
 
 pub fn select_strat(n: usize) -> BoxedStrategy<SelectInfoElem> {
     if n == 0 {
-        Just(Vec::new().into()).boxed()
+        Just(Vec::<usize>::new().into()).boxed()
     } else {
         let indices = proptest::collection::vec(0..n, 0..2 * n).prop_map(|i| i.into());
         let slice = (0..n).prop_flat_map(move |start| {
@@ -327,42 +326,6 @@ pub fn select_strat(n: usize) -> BoxedStrategy<SelectInfoElem> {
     }
 }
 
-////////////////////////////////////////////////////////////////////////////////
-/// AnnData operations
-////////////////////////////////////////////////////////////////////////////////
-
-pub fn anndata_eq<B1: Backend, B2: Backend>(
-    adata1: &AnnData<B1>,
-    adata2: &AnnData<B2>,
-) -> Result<bool> {
-    let is_equal = adata1.n_obs() == adata2.n_obs()
-        && adata1.n_vars() == adata2.n_vars()
-        && adata1.obs_names() == adata2.obs_names()
-        && adata1.var_names() == adata2.var_names()
-        && adata1.read_obs()? == adata2.read_obs()?
-        && adata1.read_var()? == adata2.read_var()?
-        && adata1.x().get::<ArrayData>()? == adata2.x().get()?
-        && adata1.obsm().keys().iter().all(|k| {
-            adata1.obsm().get_item::<ArrayData>(k).unwrap() == adata2.obsm().get_item(k).unwrap()
-        })
-        && adata1.obsp().keys().iter().all(|k| {
-            adata1.obsp().get_item::<ArrayData>(k).unwrap() == adata2.obsp().get_item(k).unwrap()
-        })
-        && adata1.varm().keys().iter().all(|k| {
-            adata1.varm().get_item::<ArrayData>(k).unwrap() == adata2.varm().get_item(k).unwrap()
-        })
-        && adata1.varp().keys().iter().all(|k| {
-            adata1.varp().get_item::<ArrayData>(k).unwrap() == adata2.varp().get_item(k).unwrap()
-        })
-        && adata1.uns().keys().iter().all(|k| {
-            adata1.uns().get_item::<Data>(k).unwrap() == adata2.uns().get_item(k).unwrap()
-        });
-    adata1.layers().keys().iter().all(|k| {
-        adata1.layers().get_item::<ArrayData>(k).unwrap() == adata2.layers().get_item(k).unwrap()
-    });
-    Ok(is_equal)
-}
-
 ////////////////////////////////////////////////////////////////////////////////
 /// Array operations
 ////////////////////////////////////////////////////////////////////////////////
@@ -3,15 +3,16 @@ pub mod memory;
 mod dataset;
 
 pub use backed::AnnData;
-pub use memory::PyAnnData;
+pub use memory::{ElemSelect, KeySelect, PyAnnData};
 pub use dataset::AnnDataSet;
 
 use anndata;
-use anndata::Backend;
+use anndata::{AnnDataOp, Backend, reader::{CsvReader, LoomReader, TenxReader}};
 use anndata_hdf5::H5;
+use anndata_zarr::Zarr;
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 use std::{path::PathBuf, collections::HashMap};
-use anyhow::Result;
 
 /// Read `.h5ad`-formatted hdf5 file.
 ///
@@ -19,31 +20,103 @@ use anyhow::Result;
 /// ----------
 ///
 /// filename: Path
-///     File name of data file.
-/// backed: Literal['r', 'r+'] | None
+///     File name of data file. An `s3://`, `http://` or `https://` URL opens a
+///     remote file read-only, fetching only the data that is accessed. This
+///     requires `backed='r'` and a build with the `ros3` feature.
+/// backed: Literal['r', 'r+', 'a', 'w'] | None
 ///     Default is `r+`.
 ///     If `'r'`, the file is opened in read-only mode.
 ///     If `'r+'`, the file is opened in read/write mode.
+///     If `'a'`, the file is opened in read/write mode if it exists, and an
+///     empty AnnData object is created otherwise.
+///     If `'w'`, an empty AnnData object is created, replacing any existing file.
 ///     If `None`, the AnnData object is read into memory.
-/// backend: Literal['hdf5'] | None
+/// backend: Literal['hdf5', 'zarr'] | None
+///     Default is `'hdf5'`. With `'zarr'`, `filename` is the directory of a
+///     Zarr store, see `read_zarr`.
+/// mmap: bool
+///     If `True`, rows of dense arrays selected by a list of indices are read
+///     through a memory map of the file instead of reading the whole array.
+///     This only applies to arrays stored contiguously without compression,
+///     i.e., written without chunking; other arrays are read as usual.
+///     Requires `backed='r'`.
+/// lazy: bool
+///     If `True`, the elements of `obsm`, `obsp`, `varm`, `varp`, `layers` and
+///     `uns` are not opened when the file is read. Only their keys are listed,
+///     and each element is opened when it is first accessed. This speeds up
+///     opening files with many elements. Requires a backed AnnData object.
 #[pyfunction]
 #[pyo3(
-    signature = (filename, backed="r+", backend=None),
-    text_signature = "(filename, backed='r+', backend=None)",
+    signature = (filename, backed="r+", backend=None, mmap=false, lazy=false),
+    text_signature = "(filename, backed='r+', backend=None, mmap=False, lazy=False)",
 )]
-pub fn read<'py>(py: Python<'py>, filename: PathBuf, backed: Option<&str>, backend: Option<&str>) -> Result<PyObject> {
+pub fn read<'py>(
+    py: Python<'py>,
+    filename: PathBuf,
+    backed: Option<&str>,
+    backend: Option<&str>,
+    mmap: bool,
+    lazy: bool,
+) -> PyResult<PyObject> {
     let adata = match backed {
-        Some(m) => AnnData::new_from(filename, m, backend).unwrap().into_py(py),
-        None => PyModule::import(py, "anndata")?
-            .getattr("read_h5ad")?
-            .call1((filename,))?
-            .to_object(py),
+        Some(m) => AnnData::open_with(filename, m, backend, mmap, lazy)?.into_py(py),
+        None if mmap => return Err(PyValueError::new_err("memory mapping requires a backed AnnData object")),
+        None if lazy => return Err(PyValueError::new_err("lazy opening requires a backed AnnData object")),
+        None => {
+            let reader = match backend.unwrap_or(H5::NAME) {
+                Zarr::NAME => "read_zarr",
+                _ => "read_h5ad",
+            };
+            PyModule::import(py, "anndata")?
+                .getattr(reader)?
+                .call1((filename,))?
+                .to_object(py)
+        }
     };
     Ok(adata)
 }
 
+/// Read a Zarr store written by `AnnData.write(..., backend='zarr')` or by the
+/// `write_zarr` method of the anndata package.
+///
+/// Elements are stored with the same encodings as in `.h5ad` files, so that
+/// `AnnData.write` converts a backed AnnData object between the two formats.
+///
+/// Parameters
+/// ----------
+///
+/// filename: Path
+///     Directory of the Zarr store.
+/// backed: Literal['r', 'r+', 'a', 'w'] | None
+///     Default is `r+`.
+///     If `'r'`, the store is opened in read-only mode.
+///     If `'r+'`, the store is opened in read/write mode.
+///     If `'a'`, the store is opened in read/write mode if it exists, and an
+///     empty AnnData object is created otherwise.
+///     If `'w'`, an empty AnnData object is created, replacing any existing store.
+///     If `None`, the AnnData object is read into memory by `anndata.read_zarr`.
+/// lazy: bool
+///     If `True`, the elements of `obsm`, `obsp`, `varm`, `varp`, `layers` and
+///     `uns` are only opened when they are first accessed. Requires a backed
+///     AnnData object.
+///
+/// Returns
+/// -------
+/// AnnData
+#[pyfunction]
+#[pyo3(
+    signature = (filename, backed="r+", lazy=false),
+    text_signature = "(filename, backed='r+', lazy=False)",
+)]
+pub fn read_zarr(py: Python<'_>, filename: PathBuf, backed: Option<&str>, lazy: bool) -> PyResult<PyObject> {
+    read(py, filename, backed, Some(Zarr::NAME), false, lazy)
+}
+
 /// Read Matrix Market file.
 ///
+/// The input files may be gzip-compressed, e.g., `matrix.mtx.gz` and
+/// `barcodes.tsv.gz` as produced by CellRanger.
+///
 /// Parameters
 /// ----------
 ///
@@ -73,7 +146,7 @@ pub fn read_mtx(
     file: Option<PathBuf>,
     backend: Option<&str>,
     sorted: bool,
-) -> Result<PyObject> {
+) -> PyResult<PyObject> {
     let mut reader = anndata::reader::MMReader::from_path(mtx_file)?;
     if let Some(obs_names) = obs_names {
         reader = reader.obs_names(obs_names)?;
@@ -91,7 +164,171 @@ pub fn read_mtx(
                 reader.finish(&adata)?;
                 Ok(AnnData::from(adata).into_py(py))
             },
-            backend => todo!("Backend {} is not supported", backend),
+            x => return Err(PyValueError::new_err(format!("Unsupported backend: {}", x))),
+        }
+    } else {
+        let adata = PyAnnData::new(py)?;
+        reader.finish(&adata)?;
+        Ok(adata.to_object(py))
+    }
+}
+
+/// Read a dense matrix from a delimited text file.
+///
+/// Parameters
+/// ----------
+///
+/// csv_file
+///     File name of the input CSV file. Gzipped files are supported.
+/// delimiter
+///     Field delimiter.
+/// has_header
+///     If true, the first row is used as the variable names.
+/// has_index
+///     If true, the first column is used as the observation names.
+/// dtype
+///     Data type of the values: "float32", "float64" or "int32".
+/// file
+///     File name of the output ".h5ad" file.
+/// backend
+///     Backend to use for writing the output file.
+#[pyfunction]
+#[pyo3(
+    signature = (csv_file, *, delimiter=',', has_header=true, has_index=true, dtype="float32", file=None, backend=None),
+    text_signature = "(csv_file, *, delimiter=',', has_header=True, has_index=True, dtype='float32', file=None, backend=None)",
+)]
+pub fn read_csv(
+    py: Python<'_>,
+    csv_file: PathBuf,
+    delimiter: char,
+    has_header: bool,
+    has_index: bool,
+    dtype: &str,
+    file: Option<PathBuf>,
+    backend: Option<&str>,
+) -> PyResult<PyObject> {
+    fn finish<O: AnnDataOp>(reader: CsvReader, dtype: &str, output: &O) -> PyResult<()> {
+        match dtype {
+            "float32" => reader.finish::<f32, _>(output)?,
+            "float64" => reader.finish::<f64, _>(output)?,
+            "int32" => reader.finish::<i32, _>(output)?,
+            _ => return Err(PyValueError::new_err(format!("unsupported dtype: {}", dtype))),
+        }
+        Ok(())
+    }
+
+    let reader = CsvReader::from_path(csv_file)?
+        .delimiter(delimiter)
+        .has_header(has_header)
+        .has_index(has_index);
+    if let Some(file) = file {
+        match backend.unwrap_or(H5::NAME) {
+            H5::NAME => {
+                let adata = anndata::AnnData::<H5>::new(file)?;
+                finish(reader, dtype, &adata)?;
+                Ok(AnnData::from(adata).into_py(py))
+            },
+            x => return Err(PyValueError::new_err(format!("Unsupported backend: {}", x))),
+        }
+    } else {
+        let adata = PyAnnData::new(py)?;
+        finish(reader, dtype, &adata)?;
+        Ok(adata.to_object(py))
+    }
+}
+
+/// Read Loom file.
+///
+/// The gene-by-cell matrix is transposed into a cell-by-gene `X`. Column
+/// attributes are stored in `obs` and row attributes in `var`, except
+/// multidimensional attributes, which are stored in `obsm` and `varm`.
+///
+/// Parameters
+/// ----------
+///
+/// filename
+///     File name of the input Loom file.
+/// obs_names_attr
+///     Column attribute used as the observation names.
+/// var_names_attr
+///     Row attribute used as the variable names.
+/// file
+///     File name of the output ".h5ad" file.
+/// backend
+///     Backend to use for writing the output file.
+#[pyfunction]
+#[pyo3(
+    signature = (filename, *, obs_names_attr="CellID", var_names_attr="Gene", file=None, backend=None),
+    text_signature = "(filename, *, obs_names_attr='CellID', var_names_attr='Gene', file=None, backend=None)",
+)]
+pub fn read_loom(
+    py: Python<'_>,
+    filename: PathBuf,
+    obs_names_attr: &str,
+    var_names_attr: &str,
+    file: Option<PathBuf>,
+    backend: Option<&str>,
+) -> PyResult<PyObject> {
+    let reader = LoomReader::<H5>::from_path(filename)?
+        .obs_names(obs_names_attr)
+        .var_names(var_names_attr);
+    if let Some(file) = file {
+        match backend.unwrap_or(H5::NAME) {
+            H5::NAME => {
+                let adata = anndata::AnnData::<H5>::new(file)?;
+                reader.finish(&adata)?;
+                Ok(AnnData::from(adata).into_py(py))
+            },
+            x => return Err(PyValueError::new_err(format!("Unsupported backend: {}", x))),
+        }
+    } else {
+        let adata = PyAnnData::new(py)?;
+        reader.finish(&adata)?;
+        Ok(adata.to_object(py))
+    }
+}
+
+/// Read the HDF5 file produced by 10x Genomics Cell Ranger.
+///
+/// Barcodes are stored as `obs_names` and feature names as `var_names`.
+/// Feature ids, types and genomes are stored in `var`.
+///
+/// Parameters
+/// ----------
+///
+/// filename
+///     File name of the input file, e.g., "filtered_feature_bc_matrix.h5".
+/// genome
+///     Genome to read from files produced by Cell Ranger 2 or earlier.
+///     If None, the first genome is read.
+/// file
+///     File name of the output ".h5ad" file.
+/// backend
+///     Backend to use for writing the output file.
+#[pyfunction]
+#[pyo3(
+    signature = (filename, *, genome=None, file=None, backend=None),
+    text_signature = "(filename, *, genome=None, file=None, backend=None)",
+)]
+pub fn read_10x_h5(
+    py: Python<'_>,
+    filename: PathBuf,
+    genome: Option<&str>,
+    file: Option<PathBuf>,
+    backend: Option<&str>,
+) -> PyResult<PyObject> {
+    let mut reader = TenxReader::<H5>::from_path(filename)?;
+    if let Some(genome) = genome {
+        reader = reader.genome(genome);
+    }
+    if let Some(file) = file {
+        match backend.unwrap_or(H5::NAME) {
+            H5::NAME => {
+                let adata = anndata::AnnData::<H5>::new(file)?;
+                reader.finish(&adata)?;
+                Ok(AnnData::from(adata).into_py(py))
+            },
+            x => return Err(PyValueError::new_err(format!("Unsupported backend: {}", x))),
         }
     } else {
         let adata = PyAnnData::new(py)?;
@@ -128,16 +365,16 @@ pub fn read_dataset(
     update_data_locations: Option<HashMap<String, String>>,
     mode: &str,
     backend: Option<&str>,
-) -> Result<AnnDataSet> {
+) -> PyResult<AnnDataSet> {
     match backend.unwrap_or(H5::NAME) {
         H5::NAME => {
             let file = match mode {
                 "r" => H5::open(filename)?,
                 "r+" => H5::open_rw(filename)?,
-                _ => panic!("Unkown mode"),
+                _ => return Err(PyValueError::new_err(format!("Unknown mode: {}", mode))),
             };
             Ok(anndata::AnnDataSet::<H5>::open(file, update_data_locations)?.into())
         },
-        _ => todo!(),
+        x => return Err(PyValueError::new_err(format!("Unknown backend: {}", x))),
     }
 }
\ No newline at end of file
@@ -1,17 +1,25 @@
-use crate::container::{PyArrayElem, PyAxisArrays, PyDataFrameElem, PyElemCollection, PyChunkedArray};
-use crate::data::{to_select_elem, PyArrayData, PyData, PyDataFrame};
-use crate::anndata::PyAnnData;
+use crate::container::{
+    PyArrayElem, PyAxisArrays, PyDataFrameElem, PyElemCollection, PyChunkedArray, PyChunkedArrayWithObs,
+};
+use crate::data::{to_index, to_select_elem, IntoPython, PyArrayData, PyData, PyDataFrame};
+use crate::anndata::{ElemSelect, KeySelect, PyAnnData};
 
 use anndata;
 use anndata::container::Slot;
+use anndata::writer::MMWriter;
 use anndata::data::{DataFrameIndex, SelectInfoElem};
-use anndata::{AnnDataOp, ArrayData, Backend};
+use anndata::{AnnDataOp, ArrayData, ArrayElemOp, AxisArraysOp, Backend};
 use anndata_hdf5::H5;
-use anyhow::{bail, Result};
+use anndata_zarr::Zarr;
+use anyhow::{bail, ensure, Context, Result};
 use downcast_rs::{impl_downcast, Downcast};
+use ndarray::Array1;
+use numpy::{IntoPyArray, PyArray1};
+use pyo3::exceptions::{PyKeyError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyDict, PyTuple};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::ops::Deref;
 
 /** An annotated data matrix.
@@ -36,10 +44,14 @@ use std::ops::Deref;
         Key-indexed unstructured annotation.
     obsm
         Key-indexed multi-dimensional observations annotation of length #observations.
-        If passing a :class:`~numpy.ndarray`, it needs to have a structured datatype.
+        A one-dimensional :class:`~numpy.ndarray` with a structured datatype is stored
+        as an HDF5 compound dataset, and is read back as a structured array with the
+        same field names and types.
     varm
         Key-indexed multi-dimensional variables annotation of length #variables.
-        If passing a :class:`~numpy.ndarray`, it needs to have a structured datatype.
+        A one-dimensional :class:`~numpy.ndarray` with a structured datatype is stored
+        as an HDF5 compound dataset, and is read back as a structured array with the
+        same field names and types.
     filename
         Name of backing file.
 
@@ -76,31 +88,78 @@ impl AnnData {
         self.0.downcast_ref::<InnerAnnData<B>>().expect("downcast to anndata failed").adata.inner()
     }
 
-    pub fn new_from(filename: PathBuf, mode: &str, backend: Option<&str>) -> Result<Self> {
+    pub fn new_from(filename: PathBuf, mode: &str, backend: Option<&str>) -> PyResult<Self> {
+        Self::open_with(filename, mode, backend, false, false)
+    }
+
+    /// Open a file. With `mmap`, the file must be opened read-only and rows of
+    /// dense datasets selected by indices are read through a memory map. With
+    /// `lazy`, elements of obsm, obsp, varm, varp, layers and uns are only
+    /// opened on first access.
+    pub fn open_with(filename: PathBuf, mode: &str, backend: Option<&str>, mmap: bool, lazy: bool) -> PyResult<Self> {
+        fn open<B: Backend>(file: B::File, lazy: bool) -> PyResult<AnnData> {
+            let adata = if lazy {
+                anndata::AnnData::<B>::open_lazy(file)?
+            } else {
+                anndata::AnnData::<B>::open(file)?
+            };
+            Ok(adata.into())
+        }
+
         match backend.unwrap_or(H5::NAME) {
+            H5::NAME if is_remote(&filename) => {
+                if mode != "r" {
+                    return Err(PyValueError::new_err("remote files can only be opened in read-only mode ('r')"));
+                }
+                if mmap {
+                    return Err(PyValueError::new_err("remote files cannot be memory mapped"));
+                }
+                open::<H5>(open_remote(&filename)?, lazy)
+            }
             H5::NAME => {
                 let file = match mode {
+                    "r" if mmap => H5::open_mmap(filename)?,
                     "r" => H5::open(filename)?,
+                    "r+" | "a" | "w" if mmap => {
+                        return Err(PyValueError::new_err("memory mapping requires the file to be opened in 'r' mode"));
+                    }
                     "r+" => H5::open_rw(filename)?,
-                    _ => bail!("Unknown mode: {}", mode),
+                    "a" if filename.exists() => H5::open_rw(filename)?,
+                    "a" | "w" => return Ok(anndata::AnnData::<H5>::new(filename)?.into()),
+                    _ => return Err(PyValueError::new_err(format!("Unknown mode: {}", mode))),
                 };
-                anndata::AnnData::<H5>::open(file).map(|adata| adata.into())
+                open::<H5>(file, lazy)
             }
-            x => bail!("Unknown backend: {}", x),
+            Zarr::NAME => {
+                if is_remote(&filename) {
+                    return Err(PyValueError::new_err("remote files are only supported by the hdf5 backend"));
+                }
+                if mmap {
+                    return Err(PyValueError::new_err("memory mapping is only supported by the hdf5 backend"));
+                }
+                let file = match mode {
+                    "r" => Zarr::open(filename)?,
+                    "r+" => Zarr::open_rw(filename)?,
+                    "a" if filename.exists() => Zarr::open_rw(filename)?,
+                    "a" | "w" => return Ok(anndata::AnnData::<Zarr>::new(filename)?.into()),
+                    _ => return Err(PyValueError::new_err(format!("Unknown mode: {}", mode))),
+                };
+                open::<Zarr>(file, lazy)
+            }
+            x => Err(PyValueError::new_err(format!("Unknown backend: {}", x))),
         }
     }
 
     fn select_obs(&self, ix: &PyAny) -> PyResult<SelectInfoElem> {
-        let from_iter = ix.iter().and_then(|iter| 
-            iter.map(|x| x.unwrap().extract::<String>()).collect::<PyResult<Vec<_>>>()
-        ).map(|names| {
-            let index = self.0.obs_names();
-            names.into_iter().map(|name| index.get_index(&name)
-                .expect(&format!("Unknown obs name: {}", name))
-            ).collect::<Vec<_>>()
-        });
+        let from_iter = ix.iter().and_then(|iter|
+            iter.map(|x| x?.extract::<String>()).collect::<PyResult<Vec<_>>>()
+        );
 
-        if let Ok(indices) = from_iter {
+        if let Ok(names) = from_iter {
+            let index = self.0.obs_names();
+            let indices = names.into_iter().map(|name| index.get_index(&name)
+                .ok_or_else(|| PyKeyError::new_err(format!("Unknown obs name: {}", name)))
+            ).collect::<PyResult<Vec<_>>>()?;
             Ok(indices.into())
         } else {
             let n = self.n_obs();
@@ -109,16 +168,15 @@ impl AnnData {
     }
 
     fn select_var(&self, ix: &PyAny) -> PyResult<SelectInfoElem> {
-        let from_iter = ix.iter().and_then(|iter| 
-            iter.map(|x| x.unwrap().extract::<String>()).collect::<PyResult<Vec<_>>>()
-        ).map(|names| {
-            let index = self.0.var_names();
-            names.into_iter().map(|name| index.get_index(&name)
-                .expect(&format!("Unknown var name: {}", name))
-            ).collect::<Vec<_>>()
-        });
+        let from_iter = ix.iter().and_then(|iter|
+            iter.map(|x| x?.extract::<String>()).collect::<PyResult<Vec<_>>>()
+        );
 
-        if let Ok(indices) = from_iter {
+        if let Ok(names) = from_iter {
+            let index = self.0.var_names();
+            let indices = names.into_iter().map(|name| index.get_index(&name)
+                .ok_or_else(|| PyKeyError::new_err(format!("Unknown var name: {}", name)))
+            ).collect::<PyResult<Vec<_>>>()?;
             Ok(indices.into())
         } else {
             let n = self.n_vars();
@@ -150,10 +208,11 @@ impl AnnData {
         varm: Option<HashMap<String, PyArrayData>>,
         uns: Option<HashMap<String, PyData>>,
         backend: Option<&str>,
-    ) -> Result<Self> {
+    ) -> PyResult<Self> {
         let adata: AnnData = match backend.unwrap_or(H5::NAME) {
             H5::NAME => anndata::AnnData::<H5>::new(filename)?.into(),
-            backend => bail!("Unknown backend: {}", backend),
+            Zarr::NAME => anndata::AnnData::<Zarr>::new(filename)?.into(),
+            backend => return Err(PyValueError::new_err(format!("Unknown backend: {}", backend))),
         };
 
         if X.is_some() {
@@ -218,7 +277,56 @@ impl AnnData {
     }
     #[setter(obs_names)]
     pub fn set_obs_names(&self, names: &PyAny) -> Result<()> {
-        self.0.set_obs_names(names)
+        self.0.set_obs_names(names, false)
+    }
+
+    /// Return the names of observations.
+    ///
+    /// Parameters
+    /// ----------
+    /// typed : bool
+    ///     If True, return the names as a numpy array with the data type they
+    ///     are stored with, e.g., integers for numeric indices. Otherwise,
+    ///     return them as strings.
+    ///
+    /// Returns
+    /// -------
+    /// list[str] | np.ndarray
+    #[pyo3(
+        signature = (*, typed=false),
+        text_signature = "($self, *, typed=False)",
+    )]
+    fn get_obs_names(&self, py: Python<'_>, typed: bool) -> Result<PyObject> {
+        let index = self.0.obs_names();
+        if typed {
+            Ok(index.to_array()?.into_python(py)?)
+        } else {
+            Ok(index.into_vec().into_py(py))
+        }
+    }
+
+    /// Set the names of observations.
+    ///
+    /// Parameters
+    /// ----------
+    /// names : list[str] | np.ndarray
+    ///     The new names. Numeric names are stored with their data type.
+    /// check_unique : bool
+    ///     If True, raise an error when the names contain duplicates.
+    #[pyo3(
+        name = "set_obs_names",
+        signature = (names, *, check_unique=false),
+        text_signature = "($self, names, *, check_unique=False)",
+    )]
+    fn set_obs_names_checked(&self, names: &PyAny, check_unique: bool) -> Result<()> {
+        self.0.set_obs_names(names, check_unique)
+    }
+
+    /// Make the names of observations unique by appending `-1`, `-2`, etc.
+    /// to duplicated names.
+    #[pyo3(text_signature = "($self)")]
+    fn obs_names_make_unique(&self) -> Result<()> {
+        self.0.obs_names_make_unique()
     }
 
     #[pyo3(text_signature = "($self, names)")]
@@ -235,7 +343,56 @@ impl AnnData {
     }
     #[setter(var_names)]
     pub fn set_var_names(&self, names: &PyAny) -> Result<()> {
-        self.0.set_var_names(names)
+        self.0.set_var_names(names, false)
+    }
+
+    /// Return the names of variables.
+    ///
+    /// Parameters
+    /// ----------
+    /// typed : bool
+    ///     If True, return the names as a numpy array with the data type they
+    ///     are stored with, e.g., integers for numeric indices. Otherwise,
+    ///     return them as strings.
+    ///
+    /// Returns
+    /// -------
+    /// list[str] | np.ndarray
+    #[pyo3(
+        signature = (*, typed=false),
+        text_signature = "($self, *, typed=False)",
+    )]
+    fn get_var_names(&self, py: Python<'_>, typed: bool) -> Result<PyObject> {
+        let index = self.0.var_names();
+        if typed {
+            Ok(index.to_array()?.into_python(py)?)
+        } else {
+            Ok(index.into_vec().into_py(py))
+        }
+    }
+
+    /// Set the names of variables.
+    ///
+    /// Parameters
+    /// ----------
+    /// names : list[str] | np.ndarray
+    ///     The new names. Numeric names are stored with their data type.
+    /// check_unique : bool
+    ///     If True, raise an error when the names contain duplicates.
+    #[pyo3(
+        name = "set_var_names",
+        signature = (names, *, check_unique=false),
+        text_signature = "($self, names, *, check_unique=False)",
+    )]
+    fn set_var_names_checked(&self, names: &PyAny, check_unique: bool) -> Result<()> {
+        self.0.set_var_names(names, check_unique)
+    }
+
+    /// Make the names of variables unique by appending `-1`, `-2`, etc.
+    /// to duplicated names.
+    #[pyo3(text_signature = "($self)")]
+    fn var_names_make_unique(&self) -> Result<()> {
+        self.0.var_names_make_unique()
     }
 
     #[pyo3(text_signature = "($self, names)")]
@@ -255,8 +412,30 @@ impl AnnData {
         self.0.set_x(data)
     }
 
+    /// Set the data matrix, optionally converting its values to another type.
+    ///
+    /// Parameters
+    /// ----------
+    /// data : np.ndarray | sp.csr_matrix | sp.csc_matrix
+    ///     The new data matrix.
+    /// dtype : np.dtype | str | None
+    ///     If given, the values are converted to this numeric type before
+    ///     writing. An error is raised if a value cannot be represented in the
+    ///     new type, e.g., a float that is out of the range of `int32`.
+    #[pyo3(
+        name = "set_x",
+        signature = (data, *, dtype=None),
+        text_signature = "($self, data, *, dtype=None)",
+    )]
+    fn set_x_with_dtype(&self, data: PyArrayData, dtype: Option<&PyAny>) -> Result<()> {
+        self.0.set_x(Some(data.cast(dtype)?))
+    }
+
     /// Observation annotations.
     ///
+    /// If a pandas DataFrame whose index is not the default range index is
+    /// assigned, the index sets `obs_names`.
+    ///
     /// Returns
     /// -------
     /// PyDataFrameElem
@@ -271,6 +450,9 @@ impl AnnData {
 
     /// Variable annotations.
     ///
+    /// If a pandas DataFrame whose index is not the default range index is
+    /// assigned, the index sets `var_names`.
+    ///
     /// Returns
     /// -------
     /// PyDataFrameElem
@@ -283,6 +465,90 @@ impl AnnData {
         self.0.set_var(var)
     }
 
+    /// Read a single column of the observation annotations. Only the requested
+    /// column is read from the file.
+    ///
+    /// Parameters
+    /// ----------
+    /// name : str
+    ///     The name of the column.
+    ///
+    /// Returns
+    /// -------
+    /// polars.Series
+    #[pyo3(text_signature = "($self, name)")]
+    fn get_obs_column(&self, py: Python<'_>, name: &str) -> Result<PyObject> {
+        self.0.get_obs_column(py, name)
+    }
+
+    /// Read a single column of the variable annotations. Only the requested
+    /// column is read from the file.
+    ///
+    /// Parameters
+    /// ----------
+    /// name : str
+    ///     The name of the column.
+    ///
+    /// Returns
+    /// -------
+    /// polars.Series
+    #[pyo3(text_signature = "($self, name)")]
+    fn get_var_column(&self, py: Python<'_>, name: &str) -> Result<PyObject> {
+        self.0.get_var_column(py, name)
+    }
+
+    /// Return an observation annotation or the values of a variable across
+    /// all observations.
+    ///
+    /// If `key` is a column of `obs`, the column is returned. Otherwise `key`
+    /// must be one of `var_names`, and the corresponding column of `X`, or of
+    /// `layer`, is returned. Only that column is read from a dense array. For
+    /// a CSR matrix, the rows are scanned in blocks and only the values of
+    /// that column are kept in memory.
+    ///
+    /// Parameters
+    /// ----------
+    /// key : str
+    ///     A column of `obs` or a variable name.
+    /// layer : str | None
+    ///     The layer to read the variable from. If `None`, `X` is used.
+    ///
+    /// Returns
+    /// -------
+    /// polars.Series | numpy.ndarray
+    #[pyo3(
+        signature = (key, layer=None),
+        text_signature = "($self, key, layer=None)",
+    )]
+    fn obs_vector(&self, py: Python<'_>, key: &str, layer: Option<&str>) -> PyResult<PyObject> {
+        self.0.obs_vector(py, key, layer)
+    }
+
+    /// Return a variable annotation or the values of an observation across
+    /// all variables.
+    ///
+    /// If `key` is a column of `var`, the column is returned. Otherwise `key`
+    /// must be one of `obs_names`, and the corresponding row of `X`, or of
+    /// `layer`, is returned. Only that row is read from the file.
+    ///
+    /// Parameters
+    /// ----------
+    /// key : str
+    ///     A column of `var` or an observation name.
+    /// layer : str | None
+    ///     The layer to read the observation from. If `None`, `X` is used.
+    ///
+    /// Returns
+    /// -------
+    /// polars.Series | numpy.ndarray
+    #[pyo3(
+        signature = (key, layer=None),
+        text_signature = "($self, key, layer=None)",
+    )]
+    fn var_vector(&self, py: Python<'_>, key: &str, layer: Option<&str>) -> PyResult<PyObject> {
+        self.0.var_vector(py, key, layer)
+    }
+
     /// Unstructured annotation (ordered dictionary).
     ///
     /// Returns
@@ -354,13 +620,20 @@ impl AnnData {
     ///     File name of the output `.h5ad` file. If provided, the result will be
     ///     saved to a new file and the original AnnData object remains unchanged.
     /// backend: str | None
+    /// chunk_size: int
+    ///     When `out` is provided, X and layers are copied in chunks of
+    ///     `chunk_size` rows, so that the selection is never loaded into memory
+    ///     as a whole. Default: 500.
+    /// progress: bool
+    ///     When `out` is provided, print a progress bar while X and layers are
+    ///     being copied. Default: False.
     ///
     /// Returns
     /// -------
     /// Optional[AnnData]
     #[pyo3(
-        signature = (obs_indices=None, var_indices=None, out=None, backend=None),
-        text_signature = "($self, obs_indices=None, var_indices=None, out=None, backend=None)",
+        signature = (obs_indices=None, var_indices=None, out=None, backend=None, chunk_size=500, progress=false),
+        text_signature = "($self, obs_indices=None, var_indices=None, out=None, backend=None, chunk_size=500, progress=False)",
     )]
     pub fn subset(
         &self,
@@ -368,14 +641,68 @@ impl AnnData {
         var_indices: Option<&PyAny>,
         out: Option<PathBuf>,
         backend: Option<&str>,
-    ) -> Result<Option<AnnData>> {
+        chunk_size: usize,
+        progress: bool,
+    ) -> PyResult<Option<AnnData>> {
         let i = obs_indices
-            .map(|x| self.select_obs(x).unwrap())
+            .map(|x| self.select_obs(x))
+            .transpose()?
             .unwrap_or(SelectInfoElem::full());
         let j = var_indices
-            .map(|x| self.select_var(x).unwrap())
+            .map(|x| self.select_var(x))
+            .transpose()?
             .unwrap_or(SelectInfoElem::full());
-        self.0.subset(&[i, j], out, backend)
+        self.0.subset(&[i, j], out, backend, chunk_size, progress)
+    }
+
+    /// Subsetting the AnnData object by the names of observations and variables.
+    ///
+    /// The result follows the order of the given names rather than the
+    /// original order. An error listing the offending name is raised if a name
+    /// does not exist.
+    ///
+    /// Parameters
+    /// ----------
+    /// obs_names: list[str] | None
+    ///     Names of the observations to keep. All observations are kept if None.
+    /// var_names: list[str] | None
+    ///     Names of the variables to keep. All variables are kept if None.
+    /// out: Path | None
+    ///     File name of the output `.h5ad` file. If provided, the result will be
+    ///     saved to a new file and the original AnnData object remains unchanged.
+    /// backend: str | None
+    /// chunk_size: int
+    ///     When `out` is provided, X and layers are copied in chunks of
+    ///     `chunk_size` rows. Default: 500.
+    /// progress: bool
+    ///     When `out` is provided, print a progress bar while X and layers are
+    ///     being copied. Default: False.
+    ///
+    /// Returns
+    /// -------
+    /// Optional[AnnData]
+    #[pyo3(
+        signature = (obs_names=None, var_names=None, out=None, backend=None, chunk_size=500, progress=false),
+        text_signature = "($self, obs_names=None, var_names=None, out=None, backend=None, chunk_size=500, progress=False)",
+    )]
+    pub fn subset_by_names(
+        &self,
+        obs_names: Option<&PyAny>,
+        var_names: Option<&PyAny>,
+        out: Option<PathBuf>,
+        backend: Option<&str>,
+        chunk_size: usize,
+        progress: bool,
+    ) -> PyResult<Option<AnnData>> {
+        let i = obs_names
+            .map(|x| self.0.obs_ix(x))
+            .transpose()?
+            .map_or(SelectInfoElem::full(), SelectInfoElem::from);
+        let j = var_names
+            .map(|x| self.0.var_ix(x))
+            .transpose()?
+            .map_or(SelectInfoElem::full(), SelectInfoElem::from);
+        self.0.subset(&[i, j], out, backend, chunk_size, progress)
     }
 
     /// Return an iterator over the rows of the data matrix X.
@@ -397,6 +724,67 @@ impl AnnData {
         self.0.chunked_x(chunk_size)
     }
 
+    /// Return an iterator over the rows of an element in obsm.
+    ///
+    /// Parameters
+    /// ----------
+    /// key : str
+    ///     The key of the element.
+    /// chunk_size : int
+    ///     Row size of a single chunk. Default: 500.
+    ///
+    /// Returns
+    /// -------
+    /// PyChunkedMatrix
+    #[pyo3(
+        signature = (key, chunk_size=500),
+        text_signature = "($self, key, chunk_size=500)",
+    )]
+    pub fn chunked_obsm(&self, key: &str, chunk_size: usize) -> Result<PyChunkedArray> {
+        self.0.chunked_obsm(key, chunk_size)
+    }
+
+    /// Return an iterator over the rows of a layer.
+    ///
+    /// Parameters
+    /// ----------
+    /// key : str
+    ///     The name of the layer.
+    /// chunk_size : int
+    ///     Row size of a single chunk. Default: 500.
+    ///
+    /// Returns
+    /// -------
+    /// PyChunkedMatrix
+    #[pyo3(
+        signature = (key, chunk_size=500),
+        text_signature = "($self, key, chunk_size=500)",
+    )]
+    pub fn chunked_layer(&self, key: &str, chunk_size: usize) -> Result<PyChunkedArray> {
+        self.0.chunked_layer(key, chunk_size)
+    }
+
+    /// Return an iterator over the rows of X together with the same rows of
+    /// some obs columns. Only the rows of the current chunk are read from obs.
+    ///
+    /// Parameters
+    /// ----------
+    /// columns : list[str]
+    ///     The obs columns to return.
+    /// chunk_size : int
+    ///     Row size of a single chunk. Default: 500.
+    ///
+    /// Returns
+    /// -------
+    /// Iterator over tuples of a chunk of X and a polars.DataFrame.
+    #[pyo3(
+        signature = (columns, chunk_size=500),
+        text_signature = "($self, columns, chunk_size=500)",
+    )]
+    pub fn chunked_with_obs(&self, columns: Vec<String>, chunk_size: usize) -> Result<PyChunkedArrayWithObs> {
+        self.0.chunked_with_obs(chunk_size, &columns)
+    }
+
     /// Filename of the backing .h5ad file.
     ///
     /// Returns
@@ -432,57 +820,450 @@ impl AnnData {
         self.0.is_closed()
     }
 
+    /// Write buffered data to disk.
+    ///
+    /// Everything written before the call, e.g., by setting `X` or adding
+    /// items to `obsm`, is durable once it returns. Otherwise, data may stay
+    /// buffered until the object is closed, and a crash in between can corrupt
+    /// the file. Long-running jobs can call this to checkpoint their progress.
+    /// `write` and `copy` flush the new file before returning.
+    #[pyo3(text_signature = "($self)")]
+    pub fn flush(&self) -> Result<()> {
+        self.0.flush()
+    }
+
     /// Close the AnnData object.
     #[pyo3(text_signature = "($self)")]
     pub fn close(&self) -> Result<()> {
         self.0.close()
     }
 
+    /// Return the object itself, so that it can be used in a `with` statement,
+    /// which closes it on exit.
+    ///
+    /// Examples
+    /// --------
+    /// >>> with read("data.h5ad", backed="r+") as adata:
+    /// ...     adata.obs["label"] = labels
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Flush and close the object, even if an exception was raised in the
+    /// `with` block. Exceptions are not suppressed.
+    fn __exit__(
+        &self,
+        _exc_type: Option<&PyAny>,
+        _exc_value: Option<&PyAny>,
+        _traceback: Option<&PyAny>,
+    ) -> Result<bool> {
+        let flushed = if self.0.is_closed() { Ok(()) } else { self.0.flush() };
+        self.0.close()?;
+        flushed?;
+        Ok(false)
+    }
+
     /// Reopen a closed AnnData object.
+    ///
+    /// Parameters
+    /// ----------
+    /// mode: Literal['r', 'r+', 'a', 'w']
+    ///     `'r'` opens the file read-only and `'r+'` in read/write mode. `'a'`
+    ///     opens it in read/write mode, or creates an empty AnnData if the file
+    ///     no longer exists. `'w'` replaces the file with an empty AnnData.
     #[pyo3(
         signature = (mode="r"),
         text_signature = "($self, mode='r')",
     )]
-    pub fn open(&self, mode: &str) -> Result<()> {
+    pub fn open(&self, mode: &str) -> PyResult<()> {
         self.0.open(mode)
     }
 
-    /// Write .h5ad-formatted hdf5 file.
+    /// Write .h5ad-formatted hdf5 file, or a Zarr store.
+    ///
+    /// When the output backend differs from the backend of this object, e.g.,
+    /// when converting an `.h5ad` file to a Zarr store, X and layers are copied
+    /// in chunks of `chunk_size` rows, without loading them into memory.
     ///
     /// Parameters
     /// ----------
     /// filename: Path
-    ///     File name of the output `.h5ad` file.
-    /// backend: str | None
-    #[pyo3(text_signature = "($self, filename, backend)")]
-    pub fn write(&self, filename: PathBuf, backend: Option<&str>) -> Result<()> {
-        self.0.write(filename, backend)
+    ///     File name of the output `.h5ad` file, or directory of the Zarr store.
+    /// backend: Literal['hdf5', 'zarr'] | None
+    ///     Default is `'hdf5'`.
+    /// chunk_size: int
+    ///     Row size of a single chunk when converting between backends. Default: 500.
+    #[pyo3(
+        signature = (filename, backend=None, chunk_size=500),
+        text_signature = "($self, filename, backend=None, chunk_size=500)",
+    )]
+    pub fn write(&self, filename: PathBuf, backend: Option<&str>, chunk_size: usize) -> PyResult<()> {
+        self.0.write(filename, backend, chunk_size)
+    }
+
+    /// Write X, or a layer, to a Matrix Market file.
+    ///
+    /// The matrix is written in chunks of rows, without loading it into memory.
+    /// Files whose names end with `.gz` are gzip-compressed.
+    ///
+    /// Parameters
+    /// ----------
+    /// filename: Path
+    ///     File name of the output `.mtx` or `.mtx.gz` file.
+    /// layer: str | None
+    ///     Write this layer instead of X.
+    /// obs_names: Path | None
+    ///     If given, write the observation names to this file, e.g., `barcodes.tsv.gz`.
+    /// var_names: Path | None
+    ///     If given, write the variable names to this file, e.g., `features.tsv.gz`.
+    #[pyo3(
+        signature = (filename, *, layer=None, obs_names=None, var_names=None),
+        text_signature = "($self, filename, *, layer=None, obs_names=None, var_names=None)",
+    )]
+    pub fn write_mtx(
+        &self,
+        filename: PathBuf,
+        layer: Option<&str>,
+        obs_names: Option<PathBuf>,
+        var_names: Option<PathBuf>,
+    ) -> Result<()> {
+        self.0.write_mtx(filename, layer, obs_names, var_names)
     }
 
     /// Copy the AnnData object.
     ///
+    /// If `obs_indices` or `var_indices` is given, only the selected
+    /// observations and variables are written to the new file. X and layers
+    /// are then copied in chunks of `chunk_size` rows, without loading the
+    /// selection into memory as a whole.
+    ///
     /// Parameters
     /// ----------
     /// filename
     ///     File name of the output `.h5ad` file.
     /// backend: str | None
+    /// obs_indices
+    ///     obs indices
+    /// var_indices
+    ///     var indices
+    /// chunk_size: int
+    ///     Row size of a single chunk when copying a selection. Default: 500.
+    /// progress: bool
+    ///     Print a progress bar while X and layers of a selection are being
+    ///     copied. Default: False.
     ///
     /// Returns
     /// -------
     /// AnnData
-    #[pyo3(text_signature = "($self, filename, backend)")]
-    fn copy(&self, filename: PathBuf, backend: Option<&str>) -> Result<Self> {
-        self.0.copy(filename, backend)
+    #[pyo3(
+        signature = (filename, backend=None, *, obs_indices=None, var_indices=None, chunk_size=500, progress=false),
+        text_signature = "($self, filename, backend=None, *, obs_indices=None, var_indices=None, chunk_size=500, progress=False)",
+    )]
+    fn copy(
+        &self,
+        filename: PathBuf,
+        backend: Option<&str>,
+        obs_indices: Option<&PyAny>,
+        var_indices: Option<&PyAny>,
+        chunk_size: usize,
+        progress: bool,
+    ) -> PyResult<Self> {
+        let i = obs_indices
+            .map(|x| self.select_obs(x))
+            .transpose()?
+            .unwrap_or(SelectInfoElem::full());
+        let j = var_indices
+            .map(|x| self.select_var(x))
+            .transpose()?
+            .unwrap_or(SelectInfoElem::full());
+        self.0.copy(filename, backend, &[i, j], chunk_size, progress)
     }
 
-    /// Return a new AnnData object with all backed arrays loaded into memory.
+    /// Return a new AnnData object with the backed arrays loaded into memory.
+    ///
+    /// By default all elements are loaded. Elements that are not requested are
+    /// left absent from the result, while `obs_names` and `var_names` are
+    /// always loaded.
+    ///
+    /// Parameters
+    /// ----------
+    /// X: bool
+    ///     Whether to load `X`.
+    /// obs: bool
+    ///     Whether to load the columns of `obs`.
+    /// var: bool
+    ///     Whether to load the columns of `var`.
+    /// uns: bool | list[str]
+    ///     Whether to load `uns`, or the keys of `uns` to load.
+    /// obsm: bool | list[str]
+    ///     Whether to load `obsm`, or the keys of `obsm` to load.
+    /// obsp: bool | list[str]
+    ///     Whether to load `obsp`, or the keys of `obsp` to load.
+    /// varm: bool | list[str]
+    ///     Whether to load `varm`, or the keys of `varm` to load.
+    /// varp: bool | list[str]
+    ///     Whether to load `varp`, or the keys of `varp` to load.
+    /// layers: bool | list[str]
+    ///     Whether to load `layers`, or the keys of `layers` to load.
     ///
     /// Returns
     /// -------
     /// AnnData
+    #[pyo3(
+        signature = (
+            *, X=true, obs=true, var=true, uns=KeySelect::All(true), obsm=KeySelect::All(true),
+            obsp=KeySelect::All(true), varm=KeySelect::All(true), varp=KeySelect::All(true),
+            layers=KeySelect::All(true),
+        ),
+        text_signature = "($self, *, X=True, obs=True, var=True, uns=True, obsm=True, obsp=True, varm=True, varp=True, layers=True)",
+    )]
+    pub fn to_memory<'py>(
+        &self,
+        py: Python<'py>,
+        X: bool,
+        obs: bool,
+        var: bool,
+        uns: KeySelect,
+        obsm: KeySelect,
+        obsp: KeySelect,
+        varm: KeySelect,
+        varp: KeySelect,
+        layers: KeySelect,
+    ) -> PyResult<PyAnnData<'py>> {
+        let select = ElemSelect { x: X, obs, var, uns, obsm, obsp, varm, varp, layers };
+        self.0.to_memory(py, &select)
+    }
+
+    /// Return `X` or a layer as a pandas DataFrame, indexed by `obs_names`
+    /// and with `var_names` as columns.
+    ///
+    /// Sparse matrices are densified, and a warning is issued when doing so.
+    ///
+    /// Parameters
+    /// ----------
+    /// layer: str | None
+    ///     The layer to export. If `None`, `X` is exported.
+    ///
+    /// Returns
+    /// -------
+    /// pandas.DataFrame
+    #[pyo3(
+        signature = (layer=None),
+        text_signature = "($self, layer=None)",
+    )]
+    pub fn to_df(&self, py: Python<'_>, layer: Option<&str>) -> Result<PyObject> {
+        self.0.to_df(py, layer)
+    }
+
+    /// Sum of each row of `X`, e.g., the library size of each cell.
+    ///
+    /// `X` is read in chunks and sparse matrices are not densified.
+    ///
+    /// Returns
+    /// -------
+    /// np.ndarray
+    #[pyo3(text_signature = "($self)")]
+    pub fn x_row_sums<'py>(&self, py: Python<'py>) -> Result<&'py PyArray1<f64>> {
+        Ok(self.0.x_row_sums()?.into_pyarray(py))
+    }
+
+    /// Sum of each column of `X`.
+    ///
+    /// Returns
+    /// -------
+    /// np.ndarray
+    #[pyo3(text_signature = "($self)")]
+    pub fn x_col_sums<'py>(&self, py: Python<'py>) -> Result<&'py PyArray1<f64>> {
+        Ok(self.0.x_col_sums()?.into_pyarray(py))
+    }
+
+    /// Number of non-zero values in each row of `X`, e.g., the number of
+    /// genes detected in each cell.
+    ///
+    /// Returns
+    /// -------
+    /// np.ndarray
+    #[pyo3(text_signature = "($self)")]
+    pub fn x_nnz_per_row<'py>(&self, py: Python<'py>) -> Result<&'py PyArray1<usize>> {
+        Ok(self.0.x_nnz_per_row()?.into_pyarray(py))
+    }
+
+    /// Number of non-zero values in each column of `X`, e.g., the number of
+    /// cells in which each gene is detected.
+    ///
+    /// Returns
+    /// -------
+    /// np.ndarray
+    #[pyo3(text_signature = "($self)")]
+    pub fn x_nnz_per_col<'py>(&self, py: Python<'py>) -> Result<&'py PyArray1<usize>> {
+        Ok(self.0.x_nnz_per_col()?.into_pyarray(py))
+    }
+
+    /// Describe the structure of the AnnData object without reading any data.
+    ///
+    /// Returns
+    /// -------
+    /// dict
+    ///     A dictionary with `n_obs`, `n_vars`, the dtype and shape of `X`
+    ///     (or `None`), the dtypes of the columns of `obs` and `var`, the dtype
+    ///     and shape of every element in `obsm`, `obsp`, `varm`, `varp` and
+    ///     `layers`, and the dtype of every element in `uns`.
+    #[pyo3(text_signature = "($self)")]
+    pub fn describe(&self, py: Python<'_>) -> Result<PyObject> {
+        self.0.describe(py)
+    }
+
+    /// Check that the shapes of all elements are consistent with `n_obs` and
+    /// `n_vars`. All problems are reported at once.
+    ///
+    /// Returns
+    /// -------
+    /// list[str]
+    ///     A description of every inconsistent element. Empty if the object
+    ///     is valid.
     #[pyo3(text_signature = "($self)")]
-    pub fn to_memory<'py>(&self, py: Python<'py>) -> Result<PyAnnData<'py>> {
-        self.0.to_memory(py)
+    pub fn validate(&self) -> Result<Vec<String>> {
+        self.0.validate()
+    }
+
+    /// Read all stored data and check that the values are well-formed.
+    ///
+    /// This checks that the `indptr` of sparse matrices is non-decreasing,
+    /// sparse indices are within range, categorical codes are valid,
+    /// dataframe columns have as many rows as the index, and X and the layers
+    /// do not contain NaN values. Unlike `validate`, which only compares
+    /// shapes, this reads every element, which may take a while.
+    ///
+    /// Returns
+    /// -------
+    /// list[str]
+    ///     A description of every problem found. Empty if no problem is found.
+    #[pyo3(text_signature = "($self)")]
+    pub fn verify_integrity(&self) -> Result<Vec<String>> {
+        self.0.verify_integrity()
+    }
+
+    /// The number of bytes of data cached in memory by X and the arrays in
+    /// obsm, obsp, varm, varp and layers.
+    ///
+    /// Returns
+    /// -------
+    /// int
+    #[pyo3(text_signature = "($self)")]
+    pub fn cache_bytes(&self) -> Result<usize> {
+        self.0.cache_bytes()
+    }
+
+    /// Limit the memory used by cached data.
+    ///
+    /// When the cached data exceeds the budget, the data of the least recently
+    /// used elements is dropped and read from the file again on the next access.
+    ///
+    /// Parameters
+    /// ----------
+    /// bytes: int | None
+    ///     The maximum number of bytes of cached data. `None` removes the limit.
+    #[pyo3(
+        signature = (bytes=None),
+        text_signature = "($self, bytes=None)",
+    )]
+    pub fn set_cache_budget(&self, bytes: Option<usize>) -> Result<()> {
+        self.0.set_cache_budget(bytes)
+    }
+
+    /// Whether the CSR matrix in X is stored transposed, i.e., as a CSC matrix.
+    ///
+    /// Returns
+    /// -------
+    /// bool
+    #[pyo3(text_signature = "($self)")]
+    pub fn is_x_transposed(&self) -> Result<bool> {
+        self.0.is_x_transposed()
+    }
+
+    /// Store the CSR matrix in X as a CSC matrix of the same shape.
+    ///
+    /// X is still read, iterated and subset as a CSR matrix, but iterating
+    /// over its columns becomes the cheap path, which suits per-variable
+    /// access. X is rewritten if the layout changes. Other readers, e.g.,
+    /// h5py or anndata, see a standard CSC matrix of shape (n_obs, n_vars),
+    /// and files produced by `write` store X as a CSR matrix.
+    ///
+    /// Parameters
+    /// ----------
+    /// transposed: bool
+    ///     Whether to store X as a CSC matrix, or back as a CSR matrix.
+    #[pyo3(text_signature = "($self, transposed)")]
+    pub fn set_x_transposed(&self, transposed: bool) -> Result<()> {
+        self.0.set_x_transposed(transposed)
+    }
+
+    /// Add a layer without rewriting X or the existing layers.
+    ///
+    /// Only the new array is written to the file, whereas assigning to
+    /// `layers` replaces the whole collection.
+    ///
+    /// Parameters
+    /// ----------
+    /// key: str
+    ///     The name of the layer, which must not exist yet.
+    /// data
+    ///     A #observations × #variables matrix.
+    #[pyo3(text_signature = "($self, key, data)")]
+    pub fn add_layer(&self, key: &str, data: PyArrayData) -> Result<()> {
+        self.0.add_layer(key, data)
+    }
+
+    /// Reorder the observations in place.
+    ///
+    /// X, obs, obsm, obsp (both axes) and the layers are rewritten on disk, so
+    /// that the `i`-th observation becomes the `perm[i]`-th observation of the
+    /// original data.
+    ///
+    /// Parameters
+    /// ----------
+    /// perm: list[int]
+    ///     A permutation of `range(n_obs)`.
+    #[pyo3(text_signature = "($self, perm)")]
+    pub fn reorder_obs(&self, perm: Vec<usize>) -> Result<()> {
+        self.0.reorder_obs(&perm)
+    }
+
+    /// Return the indices of the observations matching a filter expression.
+    ///
+    /// Comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`) between obs columns and
+    /// literals can be combined with `&`, `|`, `~` and parentheses, e.g.,
+    /// `"n_genes > 500 & pct_mito < 0.1"`. Column names that are not
+    /// identifiers must be enclosed in backticks.
+    ///
+    /// Parameters
+    /// ----------
+    /// expr: str
+    ///     The filter expression.
+    ///
+    /// Returns
+    /// -------
+    /// list[int]
+    ///     The indices of the matching observations, which can be passed to `subset`.
+    #[pyo3(text_signature = "($self, expr)")]
+    pub fn filter_obs(&self, expr: &str) -> Result<Vec<usize>> {
+        self.0.filter_obs(expr)
+    }
+
+    /// Return the indices of the variables matching a filter expression over
+    /// the columns of var. See `filter_obs` for the syntax.
+    ///
+    /// Parameters
+    /// ----------
+    /// expr: str
+    ///     The filter expression.
+    ///
+    /// Returns
+    /// -------
+    /// list[int]
+    #[pyo3(text_signature = "($self, expr)")]
+    pub fn filter_var(&self, expr: &str) -> Result<Vec<usize>> {
+        self.0.filter_var(expr)
     }
 
     fn __repr__(&self) -> String {
@@ -497,10 +1278,12 @@ impl AnnData {
 trait AnnDataTrait: Send + Downcast {
     fn shape(&self) -> (usize, usize);
     fn obs_names(&self) -> DataFrameIndex;
-    fn set_obs_names(&self, names: &PyAny) -> Result<()>;
+    fn set_obs_names(&self, names: &PyAny, check_unique: bool) -> Result<()>;
+    fn obs_names_make_unique(&self) -> Result<()>;
     fn obs_ix(&self, index: &PyAny) -> Result<Vec<usize>>;
     fn var_names(&self) -> DataFrameIndex;
-    fn set_var_names(&self, names: &PyAny) -> Result<()>;
+    fn set_var_names(&self, names: &PyAny, check_unique: bool) -> Result<()>;
+    fn var_names_make_unique(&self) -> Result<()>;
     fn var_ix(&self, index: &PyAny) -> Result<Vec<usize>>;
 
     fn get_x(&self) -> Option<PyArrayElem>;
@@ -512,6 +1295,10 @@ trait AnnDataTrait: Send + Downcast {
     fn get_varm(&self) -> Option<PyAxisArrays>;
     fn get_varp(&self) -> Option<PyAxisArrays>;
     fn get_layers(&self) -> Option<PyAxisArrays>;
+    fn get_obs_column(&self, py: Python<'_>, name: &str) -> Result<PyObject>;
+    fn get_var_column(&self, py: Python<'_>, name: &str) -> Result<PyObject>;
+    fn obs_vector(&self, py: Python<'_>, key: &str, layer: Option<&str>) -> PyResult<PyObject>;
+    fn var_vector(&self, py: Python<'_>, key: &str, layer: Option<&str>) -> PyResult<PyObject>;
 
     fn set_x(&self, data: Option<PyArrayData>) -> Result<()>;
     fn set_obs(&self, obs: Option<PyDataFrame>) -> Result<()>;
@@ -528,13 +1315,48 @@ trait AnnDataTrait: Send + Downcast {
         slice: &[SelectInfoElem],
         out: Option<PathBuf>,
         backend: Option<&str>,
-    ) -> Result<Option<AnnData>>;
+        chunk_size: usize,
+        progress: bool,
+    ) -> PyResult<Option<AnnData>>;
 
     fn chunked_x(&self, chunk_size: usize) -> PyChunkedArray;
+    fn chunked_obsm(&self, key: &str, chunk_size: usize) -> Result<PyChunkedArray>;
+    fn chunked_layer(&self, key: &str, chunk_size: usize) -> Result<PyChunkedArray>;
+    fn chunked_with_obs(&self, chunk_size: usize, columns: &[String]) -> Result<PyChunkedArrayWithObs>;
 
-    fn write(&self, filename: PathBuf, backend: Option<&str>) -> Result<()>;
-    fn copy(&self, filename: PathBuf, backend: Option<&str>) -> Result<AnnData>;
-    fn to_memory<'py>(&self, py: Python<'py>) -> Result<PyAnnData<'py>>;
+    fn write(&self, filename: PathBuf, backend: Option<&str>, chunk_size: usize) -> PyResult<()>;
+    fn write_mtx(
+        &self,
+        filename: PathBuf,
+        layer: Option<&str>,
+        obs_names: Option<PathBuf>,
+        var_names: Option<PathBuf>,
+    ) -> Result<()>;
+    fn copy(
+        &self,
+        filename: PathBuf,
+        backend: Option<&str>,
+        slice: &[SelectInfoElem],
+        chunk_size: usize,
+        progress: bool,
+    ) -> PyResult<AnnData>;
+    fn to_memory<'py>(&self, py: Python<'py>, select: &ElemSelect) -> PyResult<PyAnnData<'py>>;
+    fn to_df(&self, py: Python<'_>, layer: Option<&str>) -> Result<PyObject>;
+    fn x_row_sums(&self) -> Result<Array1<f64>>;
+    fn x_col_sums(&self) -> Result<Array1<f64>>;
+    fn x_nnz_per_row(&self) -> Result<Array1<usize>>;
+    fn x_nnz_per_col(&self) -> Result<Array1<usize>>;
+    fn describe(&self, py: Python<'_>) -> Result<PyObject>;
+    fn validate(&self) -> Result<Vec<String>>;
+    fn verify_integrity(&self) -> Result<Vec<String>>;
+    fn cache_bytes(&self) -> Result<usize>;
+    fn set_cache_budget(&self, bytes: Option<usize>) -> Result<()>;
+    fn is_x_transposed(&self) -> Result<bool>;
+    fn set_x_transposed(&self, transposed: bool) -> Result<()>;
+    fn add_layer(&self, key: &str, data: PyArrayData) -> Result<()>;
+    fn reorder_obs(&self, perm: &[usize]) -> Result<()>;
+    fn filter_obs(&self, expr: &str) -> Result<Vec<usize>>;
+    fn filter_var(&self, expr: &str) -> Result<Vec<usize>>;
 
     fn filename(&self) -> PathBuf;
     fn backend(&self) -> &str;
@@ -542,7 +1364,8 @@ trait AnnDataTrait: Send + Downcast {
     fn show(&self) -> String;
 
     /// Reopen a closed AnnData object.
-    fn open(&self, mode: &str) -> Result<()>;
+    fn open(&self, mode: &str) -> PyResult<()>;
+    fn flush(&self) -> Result<()>;
     fn close(&self) -> Result<()>;
     fn clone_ref(&self) -> Box<dyn AnnDataTrait>;
 }
@@ -574,15 +1397,21 @@ impl<B: Backend> AnnDataTrait for InnerAnnData<B> {
     }
 
     fn obs_ix(&self, index: &PyAny) -> Result<Vec<usize>> {
-        self.adata.inner().obs_ix(
-            index.iter()?.map(|x| x.unwrap().extract::<&str>().unwrap())
-        )
+        let names = index.iter()?.map(|x| x?.extract::<&str>()).collect::<PyResult<Vec<_>>>()?;
+        self.adata.inner().obs_ix(names)
+    }
+
+    fn set_obs_names(&self, names: &PyAny, check_unique: bool) -> Result<()> {
+        let obs_names = to_index(names)?;
+        ensure!(
+            !check_unique || obs_names.is_unique(),
+            "obs_names contain duplicates, use `obs_names_make_unique` to make them unique"
+        );
+        self.adata.inner().set_obs_names(obs_names)
     }
 
-    fn set_obs_names(&self, names: &PyAny) -> Result<()> {
-        let obs_names: Result<DataFrameIndex> =
-            names.iter()?.map(|x| Ok(x?.extract::<String>()?)).collect();
-        self.adata.inner().set_obs_names(obs_names?)
+    fn obs_names_make_unique(&self) -> Result<()> {
+        self.adata.inner().obs_names_make_unique()
     }
 
     fn var_names(&self) -> DataFrameIndex {
@@ -590,19 +1419,25 @@ impl<B: Backend> AnnDataTrait for InnerAnnData<B> {
     }
 
     fn var_ix(&self, index: &PyAny) -> Result<Vec<usize>> {
-        self.adata.inner().var_ix(
-            index.iter()?.map(|x| x.unwrap().extract::<&str>().unwrap())
-        )
+        let names = index.iter()?.map(|x| x?.extract::<&str>()).collect::<PyResult<Vec<_>>>()?;
+        self.adata.inner().var_ix(names)
     }
 
-    fn set_var_names(&self, names: &PyAny) -> Result<()> {
-        let var_names: Result<DataFrameIndex> =
-            names.iter()?.map(|x| Ok(x?.extract::<String>()?)).collect();
-        self.adata.inner().set_var_names(var_names?)
+    fn set_var_names(&self, names: &PyAny, check_unique: bool) -> Result<()> {
+        let var_names = to_index(names)?;
+        ensure!(
+            !check_unique || var_names.is_unique(),
+            "var_names contain duplicates, use `var_names_make_unique` to make them unique"
+        );
+        self.adata.inner().set_var_names(var_names)
+    }
+
+    fn var_names_make_unique(&self) -> Result<()> {
+        self.adata.inner().var_names_make_unique()
     }
 
     fn get_x(&self) -> Option<PyArrayElem> {
-        let inner = self.adata.inner();
+        let inner = self.adata.try_inner()?;
         let x = inner.get_x();
         if x.is_empty() {
             None
@@ -611,7 +1446,7 @@ impl<B: Backend> AnnDataTrait for InnerAnnData<B> {
         }
     }
     fn get_obs(&self) -> Option<PyDataFrameElem> {
-        let inner = self.adata.inner();
+        let inner = self.adata.try_inner()?;
         let obs = inner.get_obs();
         if obs.is_empty() {
             None
@@ -620,7 +1455,7 @@ impl<B: Backend> AnnDataTrait for InnerAnnData<B> {
         }
     }
     fn get_var(&self) -> Option<PyDataFrameElem> {
-        let inner = self.adata.inner();
+        let inner = self.adata.try_inner()?;
         let var = inner.get_var();
         if var.is_empty() {
             None
@@ -628,8 +1463,30 @@ impl<B: Backend> AnnDataTrait for InnerAnnData<B> {
             Some(var.clone().into())
         }
     }
+    fn get_obs_column(&self, py: Python<'_>, name: &str) -> Result<PyObject> {
+        let inner = self.adata.try_inner().context("AnnData object is closed")?;
+        let obs = inner.get_obs();
+        ensure!(!obs.is_empty(), "obs is empty");
+        let column = obs.inner().column(name)?.into_python(py)?;
+        Ok(column)
+    }
+    fn get_var_column(&self, py: Python<'_>, name: &str) -> Result<PyObject> {
+        let inner = self.adata.try_inner().context("AnnData object is closed")?;
+        let var = inner.get_var();
+        ensure!(!var.is_empty(), "var is empty");
+        let column = var.inner().column(name)?.into_python(py)?;
+        Ok(column)
+    }
+    fn obs_vector(&self, py: Python<'_>, key: &str, layer: Option<&str>) -> PyResult<PyObject> {
+        let inner = self.adata.try_inner().context("AnnData object is closed")?;
+        axis_vector(py, &inner, 0, key, layer)
+    }
+    fn var_vector(&self, py: Python<'_>, key: &str, layer: Option<&str>) -> PyResult<PyObject> {
+        let inner = self.adata.try_inner().context("AnnData object is closed")?;
+        axis_vector(py, &inner, 1, key, layer)
+    }
     fn get_uns(&self) -> Option<PyElemCollection> {
-        let inner = self.adata.inner();
+        let inner = self.adata.try_inner()?;
         let uns = inner.uns();
         if uns.is_empty() {
             None
@@ -638,16 +1495,19 @@ impl<B: Backend> AnnDataTrait for InnerAnnData<B> {
         }
     }
     fn get_obsm(&self) -> Option<PyAxisArrays> {
-        let inner = self.adata.inner();
+        let inner = self.adata.try_inner()?;
         let obsm = inner.obsm();
         if obsm.is_empty() {
             None
         } else {
-            Some(obsm.clone().into())
+            let adata = self.adata.clone();
+            Some(PyAxisArrays::from(obsm.clone()).with_row_names(move || {
+                adata.lock().as_ref().map_or(DataFrameIndex::empty(), |x| x.obs_names())
+            }))
         }
     }
     fn get_obsp(&self) -> Option<PyAxisArrays> {
-        let inner = self.adata.inner();
+        let inner = self.adata.try_inner()?;
         let obsp = inner.obsp();
         if obsp.is_empty() {
             None
@@ -656,16 +1516,19 @@ impl<B: Backend> AnnDataTrait for InnerAnnData<B> {
         }
     }
     fn get_varm(&self) -> Option<PyAxisArrays> {
-        let inner = self.adata.inner();
+        let inner = self.adata.try_inner()?;
         let varm = inner.varm();
         if varm.is_empty() {
             None
         } else {
-            Some(varm.clone().into())
+            let adata = self.adata.clone();
+            Some(PyAxisArrays::from(varm.clone()).with_row_names(move || {
+                adata.lock().as_ref().map_or(DataFrameIndex::empty(), |x| x.var_names())
+            }))
         }
     }
     fn get_varp(&self) -> Option<PyAxisArrays> {
-        let inner = self.adata.inner();
+        let inner = self.adata.try_inner()?;
         let varp = inner.varp();
         if varp.is_empty() {
             None
@@ -675,7 +1538,7 @@ impl<B: Backend> AnnDataTrait for InnerAnnData<B> {
     }
 
     fn get_layers(&self) -> Option<PyAxisArrays> {
-        let inner = self.adata.inner();
+        let inner = self.adata.try_inner()?;
         let layers = inner.layers();
         if layers.is_empty() {
             None
@@ -696,7 +1559,14 @@ impl<B: Backend> AnnDataTrait for InnerAnnData<B> {
     fn set_obs(&self, obs: Option<PyDataFrame>) -> Result<()> {
         let inner = self.adata.inner();
         if let Some(o) = obs {
-            inner.set_obs(o.into())?;
+            let (df, index) = o.into_parts();
+            if let Some(index) = index.as_ref() {
+                check_df_index("obs", index, inner.n_obs(), inner.obs_names())?;
+            }
+            inner.set_obs(df)?;
+            if let Some(index) = index {
+                inner.set_obs_names(index)?;
+            }
         } else {
             inner.del_obs()?;
         }
@@ -705,7 +1575,14 @@ impl<B: Backend> AnnDataTrait for InnerAnnData<B> {
     fn set_var(&self, var: Option<PyDataFrame>) -> Result<()> {
         let inner = self.adata.inner();
         if let Some(v) = var {
-            inner.set_var(v.into())?;
+            let (df, index) = v.into_parts();
+            if let Some(index) = index.as_ref() {
+                check_df_index("var", index, inner.n_vars(), inner.var_names())?;
+            }
+            inner.set_var(df)?;
+            if let Some(index) = index {
+                inner.set_var_names(index)?;
+            }
         } else {
             inner.del_var()?;
         }
@@ -771,14 +1648,24 @@ impl<B: Backend> AnnDataTrait for InnerAnnData<B> {
         slice: &[SelectInfoElem],
         out: Option<PathBuf>,
         backend: Option<&str>,
-    ) -> Result<Option<AnnData>> {
+        chunk_size: usize,
+        progress: bool,
+    ) -> PyResult<Option<AnnData>> {
         if let Some(out) = out {
             match backend.unwrap_or(H5::NAME) {
                 H5::NAME => {
-                    self.adata.inner().write_select::<H5, _, _>(slice, &out)?;
+                    self.adata.inner().write_select_by_chunk_with_progress::<H5, _, _, _>(
+                        slice, &out, chunk_size, |done, total| if progress { print_progress(done, total) }
+                    )?;
+                    Ok(Some(AnnData::new_from(out, "r+", backend)?))
+                }
+                Zarr::NAME => {
+                    self.adata.inner().write_select_by_chunk_with_progress::<Zarr, _, _, _>(
+                        slice, &out, chunk_size, |done, total| if progress { print_progress(done, total) }
+                    )?;
                     Ok(Some(AnnData::new_from(out, "r+", backend)?))
                 }
-                x => bail!("Unsupported backend: {}", x),
+                x => Err(PyValueError::new_err(format!("Unsupported backend: {}", x))),
             }
         } else {
             self.adata.inner().subset(slice)?;
@@ -790,20 +1677,214 @@ impl<B: Backend> AnnDataTrait for InnerAnnData<B> {
         self.adata.inner().get_x().chunked(chunk_size).into()
     }
 
-    fn write(&self, filename: PathBuf, backend: Option<&str>) -> Result<()> {
+    fn chunked_obsm(&self, key: &str, chunk_size: usize) -> Result<PyChunkedArray> {
+        let elem = self
+            .adata
+            .inner()
+            .obsm()
+            .try_get(key)?
+            .with_context(|| format!("obsm['{}'] does not exist", key))?;
+        Ok(elem.chunked(chunk_size).into())
+    }
+
+    fn chunked_layer(&self, key: &str, chunk_size: usize) -> Result<PyChunkedArray> {
+        let elem = self
+            .adata
+            .inner()
+            .layers()
+            .try_get(key)?
+            .with_context(|| format!("layer '{}' does not exist", key))?;
+        Ok(elem.chunked(chunk_size).into())
+    }
+
+    fn chunked_with_obs(&self, chunk_size: usize, columns: &[String]) -> Result<PyChunkedArrayWithObs> {
+        Ok(self.adata.inner().chunked_with_obs::<ArrayData>(chunk_size, columns)?.into())
+    }
+
+    fn write(&self, filename: PathBuf, backend: Option<&str>, chunk_size: usize) -> PyResult<()> {
+        // Elements are read as a whole by `write`, so conversions between
+        // backends stream the full selection in chunks instead.
+        let full = [SelectInfoElem::full(), SelectInfoElem::full()];
         match backend.unwrap_or(H5::NAME) {
-            H5::NAME => self.adata.inner().write::<H5, _>(filename),
-            x => bail!("Unsupported backend: {}", x),
+            H5::NAME if B::NAME == H5::NAME => self.adata.inner().write::<H5, _>(filename)?,
+            H5::NAME => self.adata.inner().write_select_by_chunk::<H5, _, _>(full, filename, chunk_size)?,
+            Zarr::NAME if B::NAME == Zarr::NAME => self.adata.inner().write::<Zarr, _>(filename)?,
+            Zarr::NAME => self.adata.inner().write_select_by_chunk::<Zarr, _, _>(full, filename, chunk_size)?,
+            x => return Err(PyValueError::new_err(format!("Unsupported backend: {}", x))),
+        }
+        Ok(())
+    }
+
+    fn write_mtx(
+        &self,
+        filename: PathBuf,
+        layer: Option<&str>,
+        obs_names: Option<PathBuf>,
+        var_names: Option<PathBuf>,
+    ) -> Result<()> {
+        let mut writer = MMWriter::to_path(filename);
+        if let Some(key) = layer {
+            writer = writer.layer(key);
+        }
+        if let Some(path) = obs_names {
+            writer = writer.obs_names(path);
+        }
+        if let Some(path) = var_names {
+            writer = writer.var_names(path);
         }
+        writer.finish(self.adata.try_inner().context("AnnData object is closed")?.deref())
     }
 
-    fn copy(&self, filename: PathBuf, backend: Option<&str>) -> Result<AnnData> {
-        AnnDataTrait::write(self, filename.clone(), backend)?;
+    fn copy(
+        &self,
+        filename: PathBuf,
+        backend: Option<&str>,
+        slice: &[SelectInfoElem],
+        chunk_size: usize,
+        progress: bool,
+    ) -> PyResult<AnnData> {
+        if slice.iter().all(|x| x.is_full()) {
+            AnnDataTrait::write(self, filename.clone(), backend, chunk_size)?;
+        } else {
+            match backend.unwrap_or(H5::NAME) {
+                H5::NAME => self.adata.inner().write_select_by_chunk_with_progress::<H5, _, _, _>(
+                    slice, &filename, chunk_size, |done, total| if progress { print_progress(done, total) }
+                )?,
+                Zarr::NAME => self.adata.inner().write_select_by_chunk_with_progress::<Zarr, _, _, _>(
+                    slice, &filename, chunk_size, |done, total| if progress { print_progress(done, total) }
+                )?,
+                x => return Err(PyValueError::new_err(format!("Unsupported backend: {}", x))),
+            }
+        }
         AnnData::new_from(filename, "r+", backend)
     }
 
-    fn to_memory<'py>(&self, py: Python<'py>) -> Result<PyAnnData<'py>> {
-        Ok(PyAnnData::from_anndata(py, self.adata.inner().deref())?)
+    fn to_memory<'py>(&self, py: Python<'py>, select: &ElemSelect) -> PyResult<PyAnnData<'py>> {
+        PyAnnData::from_anndata_select(py, self.adata.inner().deref(), select)
+    }
+
+    fn x_row_sums(&self) -> Result<Array1<f64>> {
+        self.adata.inner().x_row_sums()
+    }
+
+    fn x_col_sums(&self) -> Result<Array1<f64>> {
+        self.adata.inner().x_col_sums()
+    }
+
+    fn x_nnz_per_row(&self) -> Result<Array1<usize>> {
+        self.adata.inner().x_nnz_per_row()
+    }
+
+    fn x_nnz_per_col(&self) -> Result<Array1<usize>> {
+        self.adata.inner().x_nnz_per_col()
+    }
+
+    fn to_df(&self, py: Python<'_>, layer: Option<&str>) -> Result<PyObject> {
+        let inner = self.adata.inner();
+        let data = match layer {
+            None => inner.x().get::<ArrayData>()?.context("X is empty")?,
+            Some(key) => inner
+                .layers()
+                .get_item::<ArrayData>(key)?
+                .with_context(|| format!("layer '{}' does not exist", key))?,
+        };
+        let mut array = PyArrayData::from(data).into_py(py);
+        if py.import("scipy.sparse")?.call_method1("issparse", (array.clone_ref(py),))?.is_true()? {
+            let msg = format!(
+                "densifying a sparse matrix of shape {} x {}",
+                inner.n_obs(),
+                inner.n_vars()
+            );
+            py.import("warnings")?.call_method1("warn", (msg,))?;
+            array = array.call_method0(py, "toarray")?;
+        }
+
+        let names = |index: DataFrameIndex| if index.is_empty() {
+            py.None()
+        } else {
+            index.into_vec().into_py(py)
+        };
+        let kwargs = [("index", names(inner.obs_names())), ("columns", names(inner.var_names()))];
+        Ok(py
+            .import("pandas")?
+            .getattr("DataFrame")?
+            .call((array,), Some(kwargs.into_py_dict(py)))?
+            .into())
+    }
+
+    fn validate(&self) -> Result<Vec<String>> {
+        let warnings = self.adata.try_inner().context("AnnData object is closed")?.validate()?;
+        Ok(warnings.into_iter().map(|x| x.to_string()).collect())
+    }
+
+    fn verify_integrity(&self) -> Result<Vec<String>> {
+        let problems = self.adata.try_inner().context("AnnData object is closed")?.verify_integrity()?;
+        Ok(problems.into_iter().map(|x| x.to_string()).collect())
+    }
+
+    fn cache_bytes(&self) -> Result<usize> {
+        Ok(self.adata.try_inner().context("AnnData object is closed")?.cache_bytes())
+    }
+
+    fn set_cache_budget(&self, bytes: Option<usize>) -> Result<()> {
+        self.adata.try_inner().context("AnnData object is closed")?.set_cache_budget(bytes);
+        Ok(())
+    }
+
+    fn is_x_transposed(&self) -> Result<bool> {
+        Ok(self.adata.try_inner().context("AnnData object is closed")?.is_x_transposed())
+    }
+
+    fn set_x_transposed(&self, transposed: bool) -> Result<()> {
+        self.adata.try_inner().context("AnnData object is closed")?.set_x_transposed(transposed)
+    }
+
+    fn add_layer(&self, key: &str, data: PyArrayData) -> Result<()> {
+        let inner = self.adata.try_inner().context("AnnData object is closed")?;
+        inner.add_layer(key, ArrayData::from(data))
+    }
+
+    fn reorder_obs(&self, perm: &[usize]) -> Result<()> {
+        self.adata.try_inner().context("AnnData object is closed")?.reorder_obs(perm)
+    }
+
+    fn filter_obs(&self, expr: &str) -> Result<Vec<usize>> {
+        self.adata.try_inner().context("AnnData object is closed")?.query_obs(expr)
+    }
+
+    fn filter_var(&self, expr: &str) -> Result<Vec<usize>> {
+        self.adata.try_inner().context("AnnData object is closed")?.query_var(expr)
+    }
+
+    fn describe(&self, py: Python<'_>) -> Result<PyObject> {
+        let info = self.adata.try_inner().context("AnnData object is closed")?.describe()?;
+        let array_info = |x: &anndata::ArrayInfo| -> PyObject {
+            let shape: &[usize] = x.shape.as_ref();
+            [
+                ("dtype", x.dtype.to_string().into_py(py)),
+                ("shape", PyTuple::new(py, shape).into_py(py)),
+            ].into_py_dict(py).into()
+        };
+        let columns = |x: &[(String, anndata::backend::DataType)]| -> PyObject {
+            x.iter().map(|(k, v)| (k, v.to_string())).into_py_dict(py).into()
+        };
+        let arrays = |x: &[(String, anndata::ArrayInfo)]| -> PyObject {
+            x.iter().map(|(k, v)| (k, array_info(v))).into_py_dict(py).into()
+        };
+
+        let dict = PyDict::new(py);
+        dict.set_item("n_obs", info.n_obs)?;
+        dict.set_item("n_vars", info.n_vars)?;
+        dict.set_item("X", info.x.as_ref().map(array_info))?;
+        dict.set_item("obs", columns(&info.obs))?;
+        dict.set_item("var", columns(&info.var))?;
+        dict.set_item("obsm", arrays(&info.obsm))?;
+        dict.set_item("obsp", arrays(&info.obsp))?;
+        dict.set_item("varm", arrays(&info.varm))?;
+        dict.set_item("varp", arrays(&info.varp))?;
+        dict.set_item("layers", arrays(&info.layers))?;
+        dict.set_item("uns", columns(&info.uns))?;
+        Ok(dict.into())
     }
 
     fn filename(&self) -> PathBuf {
@@ -826,18 +1907,27 @@ impl<B: Backend> AnnDataTrait for InnerAnnData<B> {
         }
     }
 
-    fn open(&self, mode: &str) -> Result<()> {
+    fn open(&self, mode: &str) -> PyResult<()> {
         if self.is_closed() {
             let file = match mode {
                 "r" => B::open(self.filename())?,
                 "r+" => B::open_rw(self.filename())?,
-                _ => bail!("Unknown mode: {}", mode),
+                "a" if self.filename().exists() => B::open_rw(self.filename())?,
+                "a" | "w" => {
+                    self.adata.insert(anndata::AnnData::<B>::new(self.filename())?);
+                    return Ok(());
+                }
+                _ => return Err(PyValueError::new_err(format!("Unknown mode: {}", mode))),
             };
             self.adata.insert(anndata::AnnData::<B>::open(file)?);
         }
         Ok(())
     }
 
+    fn flush(&self) -> Result<()> {
+        self.adata.try_inner().context("AnnData object is closed")?.flush()
+    }
+
     fn close(&self) -> Result<()> {
         if let Some(inner) = self.adata.extract() {
             inner.close()?;
@@ -917,4 +2007,106 @@ impl<B: Backend> StackedAnnDataTrait for Slot<anndata::StackedAnnData<B>> {
             format!("{}", self.inner().deref())
         }
     }
-}
\ No newline at end of file
+}
+
+/// Return the column `key` of obs (`axis` = 0) or var (`axis` = 1), or else
+/// the values of X, or of a layer, along `axis` at the variable (`axis` = 0) or
+/// observation (`axis` = 1) named `key`, as a 1-D numpy array.
+fn axis_vector<B: Backend>(
+    py: Python<'_>,
+    adata: &anndata::AnnData<B>,
+    axis: usize,
+    key: &str,
+    layer: Option<&str>,
+) -> PyResult<PyObject> {
+    let (df, names) = if axis == 0 {
+        (adata.get_obs(), adata.var_names())
+    } else {
+        (adata.get_var(), adata.obs_names())
+    };
+    if !df.is_empty() && df.inner().get_column_names().contains(key) {
+        return Ok(df.inner().column(key)?.into_python(py)?);
+    }
+    let i = names.get_index(key).ok_or_else(|| {
+        let (columns, names) = if axis == 0 { ("obs", "var_names") } else { ("var", "obs_names") };
+        PyKeyError::new_err(format!("'{}' is neither a column of {} nor in {}", key, columns, names))
+    })?;
+    let mut select = vec![SelectInfoElem::full(); 2];
+    select[1 - axis] = SelectInfoElem::from(i..i + 1);
+    let data: ArrayData = match layer {
+        None => adata.x().slice(select)?.context("X is empty")?,
+        Some(layer) => adata
+            .layers()
+            .try_get(layer)?
+            .with_context(|| format!("layer '{}' does not exist", layer))?
+            .slice(select)?
+            .with_context(|| format!("layer '{}' is empty", layer))?,
+    };
+    let mut array = PyArrayData::from(data).into_py(py);
+    if py.import("scipy.sparse")?.call_method1("issparse", (array.clone_ref(py),))?.is_true()? {
+        array = array.call_method0(py, "toarray")?;
+    }
+    Ok(array.call_method0(py, "ravel")?)
+}
+
+/// Print a tqdm-style progress bar to `sys.stderr`. A newline is printed once
+/// `done` reaches `total`.
+fn print_progress(done: usize, total: usize) {
+    const WIDTH: usize = 40;
+    let frac = if total == 0 { 1.0 } else { done as f64 / total as f64 };
+    let filled = (frac * WIDTH as f64).round() as usize;
+    let mut bar = format!(
+        "\r{:3.0}%|{}{}| {}/{} rows",
+        frac * 100.0,
+        "█".repeat(filled),
+        " ".repeat(WIDTH - filled),
+        done,
+        total,
+    );
+    if done >= total {
+        bar.push('\n');
+    }
+    Python::with_gil(|py| {
+        let _ = py
+            .import("sys")
+            .and_then(|sys| sys.getattr("stderr"))
+            .and_then(|stderr| stderr.call_method1("write", (bar,)));
+    });
+}
+
+/// Whether the filename is an `s3://`, `http://` or `https://` URL.
+fn is_remote(filename: &Path) -> bool {
+    filename
+        .to_str()
+        .map_or(false, |x| ["s3://", "http://", "https://"].iter().any(|scheme| x.starts_with(scheme)))
+}
+
+#[cfg(feature = "ros3")]
+fn open_remote(url: &Path) -> Result<<H5 as Backend>::File> {
+    H5::open_remote(url.to_str().context("the URL is not valid UTF-8")?)
+}
+
+#[cfg(not(feature = "ros3"))]
+fn open_remote(_url: &Path) -> Result<<H5 as Backend>::File> {
+    bail!("reading remote files requires building with the 'ros3' feature")
+}
+
+/// Check the index of a pandas dataframe assigned to obs or var against the
+/// size of the axis. The index replaces the current names, so a warning is
+/// issued if they are set and differ from the index.
+fn check_df_index(axis: &str, index: &DataFrameIndex, n: usize, current: DataFrameIndex) -> Result<()> {
+    ensure!(
+        n == 0 || index.len() == n,
+        "the index of {} has length {}, but the AnnData object has {} {}",
+        axis, index.len(), n, if axis == "obs" { "observations" } else { "variables" },
+    );
+    let is_default = current == DataFrameIndex::from(current.len());
+    if !current.is_empty() && !is_default && current != *index {
+        Python::with_gil(|py| -> Result<()> {
+            let msg = format!("{}_names are replaced by the index of the dataframe", axis);
+            py.import("warnings")?.call_method1("warn", (msg,))?;
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
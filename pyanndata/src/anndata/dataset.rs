@@ -10,8 +10,9 @@ use anndata::{self, ArrayElemOp, Data, ArrayOp};
 use anndata::{AnnDataOp, Backend};
 use anndata::{AxisArraysOp, ElemCollectionOp};
 use anndata_hdf5::H5;
-use anyhow::{bail, Result};
+use anyhow::{Context, Result};
 use downcast_rs::{impl_downcast, Downcast};
+use pyo3::exceptions::{PyKeyError, PyValueError};
 use pyo3::prelude::*;
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -31,6 +32,10 @@ use super::backed::StackedAnnData;
         File name of the output file containing the AnnDataSet object.
     add_key: str
         The column name in obs to store the keys
+    prefix_sep: str | None
+        If set, the obs_names of each AnnData object are prefixed with its key
+        followed by this separator, so that the obs_names of the AnnDataSet are
+        unique across the objects. The underlying files are not modified.
     backend: str
         The backend to use for the AnnDataSet object.
 
@@ -71,16 +76,15 @@ impl AnnDataSet {
     }
 
     fn select_obs(&self, ix: &PyAny) -> PyResult<SelectInfoElem> {
-        let from_iter = ix.iter().and_then(|iter| 
-            iter.map(|x| x.unwrap().extract::<String>()).collect::<PyResult<Vec<_>>>()
-        ).map(|names| {
-            let index = self.0.obs_names();
-            names.into_iter().map(|name| index.get_index(&name)
-                .expect(&format!("Unknown obs name: {}", name))
-            ).collect::<Vec<_>>()
-        });
+        let from_iter = ix.iter().and_then(|iter|
+            iter.map(|x| x?.extract::<String>()).collect::<PyResult<Vec<_>>>()
+        );
 
-        if let Ok(indices) = from_iter {
+        if let Ok(names) = from_iter {
+            let index = self.0.obs_names();
+            let indices = names.into_iter().map(|name| index.get_index(&name)
+                .ok_or_else(|| PyKeyError::new_err(format!("Unknown obs name: {}", name)))
+            ).collect::<PyResult<Vec<_>>>()?;
             Ok(indices.into())
         } else {
             let n = self.n_obs();
@@ -89,16 +93,15 @@ impl AnnDataSet {
     }
 
     fn select_var(&self, ix: &PyAny) -> PyResult<SelectInfoElem> {
-        let from_iter = ix.iter().and_then(|iter| 
-            iter.map(|x| x.unwrap().extract::<String>()).collect::<PyResult<Vec<_>>>()
-        ).map(|names| {
-            let index = self.0.var_names();
-            names.into_iter().map(|name| index.get_index(&name)
-                .expect(&format!("Unknown obs name: {}", name))
-            ).collect::<Vec<_>>()
-        });
+        let from_iter = ix.iter().and_then(|iter|
+            iter.map(|x| x?.extract::<String>()).collect::<PyResult<Vec<_>>>()
+        );
 
-        if let Ok(indices) = from_iter {
+        if let Ok(names) = from_iter {
+            let index = self.0.var_names();
+            let indices = names.into_iter().map(|name| index.get_index(&name)
+                .ok_or_else(|| PyKeyError::new_err(format!("Unknown var name: {}", name)))
+            ).collect::<PyResult<Vec<_>>>()?;
             Ok(indices.into())
         } else {
             let n = self.n_vars();
@@ -116,27 +119,33 @@ pub enum AnnDataFile<'py> {
 #[pymethods]
 impl AnnDataSet {
     #[new]
-    #[pyo3(signature = (adatas, *, filename, add_key="sample", backend=None))]
+    #[pyo3(signature = (adatas, *, filename, add_key="sample", prefix_sep=None, backend=None))]
     pub fn new(
         adatas: Vec<(String, AnnDataFile)>,
         filename: PathBuf,
         add_key: &str,
+        prefix_sep: Option<&str>,
         backend: Option<&str>,
-    ) -> Result<Self> {
+    ) -> PyResult<Self> {
         match backend.unwrap_or(H5::NAME) {
             H5::NAME => {
                 let anndatas = adatas.into_iter().map(|(key, data_file)| {
                     let adata = match data_file {
-                        AnnDataFile::Data(data) => data.borrow().take_inner::<H5>().unwrap(),
-                        AnnDataFile::Path(path) => {
-                            anndata::AnnData::open(H5::open(path).unwrap()).unwrap()
-                        }
+                        AnnDataFile::Data(data) => data
+                            .borrow()
+                            .take_inner::<H5>()
+                            .with_context(|| format!("AnnData object '{}' is closed", key))?,
+                        AnnDataFile::Path(path) => anndata::AnnData::open(H5::open(path)?)?,
                     };
-                    (key, adata)
-                });
-                Ok(anndata::AnnDataSet::new(anndatas, filename, add_key)?.into())
+                    Ok((key, adata))
+                }).collect::<Result<Vec<_>>>()?;
+                let dataset = anndata::AnnDataSet::new(anndatas, filename, add_key)?;
+                if let Some(sep) = prefix_sep {
+                    dataset.prefix_obs_names(sep)?;
+                }
+                Ok(dataset.into())
             }
-            _ => todo!(),
+            x => Err(PyValueError::new_err(format!("Unknown backend: {}", x))),
         }
     }
 
@@ -330,18 +339,18 @@ impl AnnDataSet {
         var_indices: Option<&PyAny>,
         out: Option<PathBuf>,
         backend: Option<&str>,
-    ) -> Result<(AnnDataSet, Option<Vec<usize>>)> {
-        if out.is_none() {
-            bail!("AnnDataSet cannot be subsetted in place. Please provide an output directory.");
-        }
+    ) -> PyResult<(AnnDataSet, Option<Vec<usize>>)> {
+        let out = out.context("AnnDataSet cannot be subsetted in place. Please provide an output directory.")?;
         let i = obs_indices
-            .map(|x| self.select_obs(x).unwrap())
+            .map(|x| self.select_obs(x))
+            .transpose()?
             .unwrap_or(SelectInfoElem::full());
         let j = var_indices
-            .map(|x| self.select_var(x).unwrap())
+            .map(|x| self.select_var(x))
+            .transpose()?
             .unwrap_or(SelectInfoElem::full());
         self.0
-            .subset(&[i, j], out.unwrap(), backend)
+            .subset(&[i, j], out, backend)
     }
 
     /// View into the component AnnData objects.
@@ -367,12 +376,14 @@ impl AnnDataSet {
         copy_x: bool,
         file: Option<PathBuf>,
         backend: Option<&str>,
-    ) -> Result<PyObject> {
+    ) -> PyResult<PyObject> {
         let i = obs_indices
-            .map(|x| self.select_obs(x).unwrap())
+            .map(|x| self.select_obs(x))
+            .transpose()?
             .unwrap_or(SelectInfoElem::full());
         let j = var_indices
-            .map(|x| self.select_var(x).unwrap())
+            .map(|x| self.select_var(x))
+            .transpose()?
             .unwrap_or(SelectInfoElem::full());
         self.0
             .to_adata(py, &[i, j], copy_x, file, backend)
@@ -471,7 +482,7 @@ trait AnnDataSetTrait: Send + Downcast {
         slice: &[SelectInfoElem],
         out: PathBuf,
         backend: Option<&str>,
-    ) -> Result<(AnnDataSet, Option<Vec<usize>>)>;
+    ) -> PyResult<(AnnDataSet, Option<Vec<usize>>)>;
 
     fn to_adata(
         &self,
@@ -480,7 +491,7 @@ trait AnnDataSetTrait: Send + Downcast {
         copy_x: bool,
         file: Option<PathBuf>,
         backend: Option<&str>,
-    ) -> Result<PyObject>;
+    ) -> PyResult<PyObject>;
 
     fn chunked_x(&self, chunk_size: usize) -> PyChunkedArray;
 
@@ -510,9 +521,8 @@ impl<B: Backend> AnnDataSetTrait for Slot<anndata::AnnDataSet<B>> {
     }
 
     fn obs_ix(&self, index: &PyAny) -> Result<Vec<usize>> {
-        self.inner().obs_ix(
-            index.iter()?.map(|x| x.unwrap().extract::<&str>().unwrap())
-        )
+        let names = index.iter()?.map(|x| x?.extract::<&str>()).collect::<PyResult<Vec<_>>>()?;
+        self.inner().obs_ix(names)
     }
 
     fn var_names(&self) -> DataFrameIndex {
@@ -526,9 +536,8 @@ impl<B: Backend> AnnDataSetTrait for Slot<anndata::AnnDataSet<B>> {
     }
 
     fn var_ix(&self, index: &PyAny) -> Result<Vec<usize>> {
-        self.inner().var_ix(
-            index.iter()?.map(|x| x.unwrap().extract::<&str>().unwrap())
-        )
+        let names = index.iter()?.map(|x| x?.extract::<&str>()).collect::<PyResult<Vec<_>>>()?;
+        self.inner().var_ix(names)
     }
 
     fn get_x(&self) -> Option<PyArrayElem> {
@@ -567,7 +576,10 @@ impl<B: Backend> AnnDataSetTrait for Slot<anndata::AnnDataSet<B>> {
         if obsm.is_empty() {
             None
         } else {
-            Some(obsm.clone().into())
+            let adata = self.clone();
+            Some(PyAxisArrays::from(obsm.clone()).with_row_names(move || {
+                adata.lock().as_ref().map_or(DataFrameIndex::empty(), |x| x.obs_names())
+            }))
         }
     }
     fn get_obsp(&self) -> Option<PyAxisArrays> {
@@ -585,7 +597,10 @@ impl<B: Backend> AnnDataSetTrait for Slot<anndata::AnnDataSet<B>> {
         if varm.is_empty() {
             None
         } else {
-            Some(varm.clone().into())
+            let adata = self.clone();
+            Some(PyAxisArrays::from(varm.clone()).with_row_names(move || {
+                adata.lock().as_ref().map_or(DataFrameIndex::empty(), |x| x.var_names())
+            }))
         }
     }
     fn get_varp(&self) -> Option<PyAxisArrays> {
@@ -671,14 +686,14 @@ impl<B: Backend> AnnDataSetTrait for Slot<anndata::AnnDataSet<B>> {
         slice: &[SelectInfoElem],
         out: PathBuf,
         backend: Option<&str>,
-    ) -> Result<(AnnDataSet, Option<Vec<usize>>)> {
+    ) -> PyResult<(AnnDataSet, Option<Vec<usize>>)> {
         match backend.unwrap_or(H5::NAME) {
             H5::NAME => {
                 let order = self.inner().write_select::<H5, _, _>(slice, &out)?;
                 let file = H5::open_rw(out.join("_dataset.h5ads"))?;
                 Ok((anndata::AnnDataSet::<H5>::open(file, None)?.into(), order))
             }
-            x => bail!("Unsupported backend: {}", x),
+            x => Err(PyValueError::new_err(format!("Unsupported backend: {}", x))),
         }
     }
 
@@ -689,14 +704,12 @@ impl<B: Backend> AnnDataSetTrait for Slot<anndata::AnnDataSet<B>> {
         copy_x: bool,
         file: Option<PathBuf>,
         backend: Option<&str>,
-    ) -> Result<PyObject> {
+    ) -> PyResult<PyObject> {
         let inner = self.inner();
         if let Some(file) = file {
             match backend.unwrap_or(H5::NAME) {
-                H5::NAME => inner
-                    .to_adata_select::<H5, _, _>(slice, file, copy_x)
-                    .map(|x| AnnData::from(x).into_py(py)),
-                x => bail!("Unsupported backend: {}", x),
+                H5::NAME => Ok(AnnData::from(inner.to_adata_select::<H5, _, _>(slice, file, copy_x)?).into_py(py)),
+                x => Err(PyValueError::new_err(format!("Unsupported backend: {}", x))),
             }
         } else {
             let adata = PyAnnData::new(py)?;
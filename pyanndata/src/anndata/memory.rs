@@ -1,14 +1,14 @@
-use crate::data::{isinstance_of_pyanndata, isinstance_of_polars, PyArrayData, PyData, PyDataFrame};
+use crate::data::{isinstance_of_pyanndata, isinstance_of_polars, PyArrayData, PyData, from_pandas, to_pandas};
 
 use std::ops::Deref;
 use polars::prelude::DataFrame;
 use pyo3::prelude::*;
-use pyo3::exceptions::PyTypeError;
+use pyo3::exceptions::{PyKeyError, PyTypeError};
 use pyo3::types::IntoPyDict;
 use anndata::{self, ArrayOp, ElemCollectionOp, ArrayElemOp};
 use anndata::{AnnDataOp, AxisArraysOp, ArrayData, Data, ReadArrayData, ReadData, Backend, WriteArrayData, HasShape};
 use anndata::data::{DataFrameIndex, SelectInfoElem, ArrayChunk, Shape};
-use anyhow::{Result, bail};
+use anyhow::{Result, bail, ensure};
 
 pub struct PyAnnData<'py>(&'py PyAny);
 
@@ -45,6 +45,59 @@ impl IntoPy<PyObject> for PyAnnData<'_> {
     }
 }
 
+/// The keys of an element collection to load: all of them (`True`), none of
+/// them (`False`), or a list of keys.
+#[derive(FromPyObject, Clone, Debug)]
+pub enum KeySelect {
+    All(bool),
+    Keys(Vec<String>),
+}
+
+impl KeySelect {
+    fn keys(&self, name: &str, available: Vec<String>) -> PyResult<Vec<String>> {
+        match self {
+            KeySelect::All(true) => Ok(available),
+            KeySelect::All(false) => Ok(Vec::new()),
+            KeySelect::Keys(keys) => {
+                if let Some(k) = keys.iter().find(|k| !available.contains(k)) {
+                    return Err(PyKeyError::new_err(format!("'{}' is not in {}", k, name)));
+                }
+                Ok(keys.clone())
+            }
+        }
+    }
+}
+
+/// The elements loaded by `PyAnnData::from_anndata_select`.
+#[derive(Clone, Debug)]
+pub struct ElemSelect {
+    pub x: bool,
+    pub obs: bool,
+    pub var: bool,
+    pub uns: KeySelect,
+    pub obsm: KeySelect,
+    pub obsp: KeySelect,
+    pub varm: KeySelect,
+    pub varp: KeySelect,
+    pub layers: KeySelect,
+}
+
+impl Default for ElemSelect {
+    fn default() -> Self {
+        Self {
+            x: true,
+            obs: true,
+            var: true,
+            uns: KeySelect::All(true),
+            obsm: KeySelect::All(true),
+            obsp: KeySelect::All(true),
+            varm: KeySelect::All(true),
+            varp: KeySelect::All(true),
+            layers: KeySelect::All(true),
+        }
+    }
+}
+
 impl<'py> PyAnnData<'py> {
     pub fn new(py: Python<'py>) -> PyResult<Self> {
         PyModule::import(py, "anndata")?
@@ -53,60 +106,82 @@ impl<'py> PyAnnData<'py> {
     }
 
     pub fn from_anndata<B: Backend>(py: Python<'py>, inner: &anndata::AnnData<B>) -> Result<Self> {
+        Ok(Self::from_anndata_select(py, inner, &ElemSelect::default())?)
+    }
+
+    /// Convert a backed AnnData, loading only the elements in `select`. The
+    /// obs_names and var_names are always loaded.
+    pub fn from_anndata_select<B: Backend>(
+        py: Python<'py>,
+        inner: &anndata::AnnData<B>,
+        select: &ElemSelect,
+    ) -> PyResult<Self> {
         let adata = PyAnnData::new(py)?;
         {
             // Set X
             adata.set_n_obs(inner.n_obs())?;
             adata.set_n_vars(inner.n_vars())?;
-            if let Some(x) = inner.x().get::<ArrayData>()? {
-                adata.set_x(x)?;
+            if select.x {
+                if let Some(x) = inner.x().get::<ArrayData>()? {
+                    adata.set_x(x)?;
+                }
             }
         }
         {
             // Set obs and var
             adata.set_obs_names(inner.obs_names().into())?;
             adata.set_var_names(inner.var_names().into())?;
-            adata.set_obs(inner.read_obs()?)?;
-            adata.set_var(inner.read_var()?)?;
+            if select.obs {
+                adata.set_obs(inner.read_obs()?)?;
+            }
+            if select.var {
+                adata.set_var(inner.read_var()?)?;
+            }
         }
         {
             // Set uns
-            inner
-                .uns().keys()
+            select.uns.keys("uns", inner.uns().keys())?
                 .into_iter()
                 .try_for_each(|k| adata.uns().add(&k, inner.uns().get_item::<Data>(&k)?.unwrap()))?;
         }
-        {
-            // Set obsm
-            inner
-                .obsm().keys()
-                .into_iter()
-                .try_for_each(|k| adata.obsm().add(&k, inner.obsm().get_item::<ArrayData>(&k)?.unwrap()))?;
-        }
-        {
-            // Set obsp
-            inner
-                .obsp().keys()
-                .into_iter()
-                .try_for_each(|k| adata.obsp().add(&k, inner.obsp().get_item::<ArrayData>(&k)?.unwrap()))?;
-        }
-        {
-            // Set varm
-            inner
-                .varm().keys()
-                .into_iter()
-                .try_for_each(|k| adata.varm().add(&k, inner.varm().get_item::<ArrayData>(&k)?.unwrap()))?;
-        }
-        {
-            // Set varp
-            inner
-                .varp().keys()
+        // Set obsm, obsp, varm, varp and layers
+        let collections = [
+            ("obsm", &select.obsm, inner.obsm(), adata.obsm()),
+            ("obsp", &select.obsp, inner.obsp(), adata.obsp()),
+            ("varm", &select.varm, inner.varm(), adata.varm()),
+            ("varp", &select.varp, inner.varp(), adata.varp()),
+            ("layers", &select.layers, inner.layers(), adata.layers()),
+        ];
+        for (name, keys, src, dst) in collections {
+            keys.keys(name, src.keys())?
                 .into_iter()
-                .try_for_each(|k| adata.varp().add(&k, inner.varp().get_item::<ArrayData>(&k)?.unwrap()))?;
+                .try_for_each(|k| dst.add(&k, src.get_item::<ArrayData>(&k)?.unwrap()))?;
         }
         Ok(adata)
     }
 
+    /// Get the pairwise annotation of observations stored under `key`.
+    pub fn get_obsp(&self, key: &str) -> Result<Option<ArrayData>> {
+        self.obsp().get_item(key)
+    }
+
+    /// Store a pairwise annotation of observations, which must be a square
+    /// matrix of size `n_obs`.
+    pub fn set_obsp<D: HasShape + Into<ArrayData>>(&self, key: &str, data: D) -> Result<()> {
+        self.obsp().add(key, data)
+    }
+
+    /// Get the pairwise annotation of variables stored under `key`.
+    pub fn get_varp(&self, key: &str) -> Result<Option<ArrayData>> {
+        self.varp().get_item(key)
+    }
+
+    /// Store a pairwise annotation of variables, which must be a square
+    /// matrix of size `n_vars`.
+    pub fn set_varp<D: HasShape + Into<ArrayData>>(&self, key: &str, data: D) -> Result<()> {
+        self.varp().add(key, data)
+    }
+
     pub(crate) fn set_n_obs(&self, n_obs: usize) -> Result<()> {
         let n = self.n_obs();
         if n == n_obs {
@@ -124,6 +199,26 @@ impl<'py> PyAnnData<'py> {
         }
     }
 
+    /// Read `obs_names` or `var_names`. Names that are not strings are
+    /// converted to strings.
+    fn axis_names(&self, attr: &str) -> Result<DataFrameIndex> {
+        let names: Vec<String> = self.0.getattr(attr)?.call_method1("astype", ("str",))?.extract()?;
+        Ok(names.into())
+    }
+
+    /// Look up the positions of `names` in `obs_names` or `var_names`.
+    fn axis_ix<'a, I: IntoIterator<Item = &'a str>>(&self, attr: &str, names: I) -> Result<Vec<usize>> {
+        let index = self.axis_names(attr)?;
+        names
+            .into_iter()
+            .map(|name| {
+                index.get_index(name).ok_or_else(|| {
+                    PyKeyError::new_err(format!("'{}' does not exist in {}", name, attr)).into()
+                })
+            })
+            .collect()
+    }
+
     pub(crate) fn set_n_vars(&self, n_vars: usize) -> Result<()> {
         let n = self.n_vars();
         if n == n_vars {
@@ -148,7 +243,7 @@ impl<'py> AnnDataOp for PyAnnData<'py> {
     type AxisArraysRef<'a> = AxisArrays<'a> where Self: 'a;
 
     fn x(&self) -> Self::X {
-        ArrayElem(self.0.getattr("X").unwrap())
+        ArrayElem(self.0.getattr("X"))
     }
 
     fn set_x_from_iter<I, D>(&self, iter: I) -> Result<()>
@@ -180,17 +275,17 @@ impl<'py> AnnDataOp for PyAnnData<'py> {
     }
 
     fn n_obs(&self) -> usize {
-        self.0.getattr("n_obs").unwrap().extract().unwrap()
+        self.0.getattr("n_obs").and_then(|x| x.extract()).unwrap_or(0)
     }
     fn n_vars(&self) -> usize {
-        self.0.getattr("n_vars").unwrap().extract().unwrap()
+        self.0.getattr("n_vars").and_then(|x| x.extract()).unwrap_or(0)
     }
 
     fn obs_names(&self) -> DataFrameIndex {
-        self.0.getattr("obs_names").unwrap().extract::<Vec<String>>().unwrap().into()
+        self.axis_names("obs_names").unwrap_or_else(|_| DataFrameIndex::empty())
     }
     fn var_names(&self) -> DataFrameIndex {
-        self.0.getattr("var_names").unwrap().extract::<Vec<String>>().unwrap().into()
+        self.axis_names("var_names").unwrap_or_else(|_| DataFrameIndex::empty())
     }
 
     fn set_obs_names(&self, index: DataFrameIndex) -> Result<()> {
@@ -222,24 +317,18 @@ impl<'py> AnnDataOp for PyAnnData<'py> {
         Ok(())
     }
 
-    fn obs_ix<'a, I: IntoIterator<Item = &'a str>>(&self, names: I) -> Result<Vec<usize>> {todo!()}
-    fn var_ix<'a, I: IntoIterator<Item = &'a str>>(&self, names: I) -> Result<Vec<usize>> {todo!()}
+    fn obs_ix<'a, I: IntoIterator<Item = &'a str>>(&self, names: I) -> Result<Vec<usize>> {
+        self.axis_ix("obs_names", names)
+    }
+    fn var_ix<'a, I: IntoIterator<Item = &'a str>>(&self, names: I) -> Result<Vec<usize>> {
+        self.axis_ix("var_names", names)
+    }
 
     fn read_obs(&self) -> Result<DataFrame> {
-        let py = self.py();
-        let df: PyDataFrame = py
-            .import("polars")?
-            .call_method1("from_pandas", (self.0.getattr("obs")?,))?
-            .extract()?;
-        Ok(df.into())
+        Ok(from_pandas(self.0.getattr("obs")?)?)
     }
     fn read_var(&self) -> Result<DataFrame> {
-        let py = self.py();
-        let df: PyDataFrame = py
-            .import("polars")?
-            .call_method1("from_pandas", (self.0.getattr("var")?,))?
-            .extract()?;
-        Ok(df.into())
+        Ok(from_pandas(self.0.getattr("var")?)?)
     }
 
     fn set_obs(&self, obs: DataFrame) -> Result<()> {
@@ -250,8 +339,7 @@ impl<'py> AnnDataOp for PyAnnData<'py> {
                 .call_method1("DataFrame", (py.None(), index))?
                 .into_py(py)
         } else {
-            PyDataFrame::from(obs).into_py(py)
-                .call_method0(py, "to_pandas")?
+            to_pandas(py, obs)?
                 .call_method1(py, "set_index", (index,))?
         };
         self.setattr("obs", df)?;
@@ -266,8 +354,7 @@ impl<'py> AnnDataOp for PyAnnData<'py> {
                 .call_method1("DataFrame", (py.None(), index))?
                 .into_py(py)
         } else {
-            PyDataFrame::from(var).into_py(py)
-                .call_method0(py, "to_pandas")?
+            to_pandas(py, var)?
                 .call_method1(py, "set_index", (index,))?
         };
         self.setattr("var", df)?;
@@ -285,42 +372,47 @@ impl<'py> AnnDataOp for PyAnnData<'py> {
     }
 
     fn uns(&self) -> Self::ElemCollectionRef<'_> {
-        ElemCollection(self.getattr("uns").unwrap())
+        ElemCollection(self.getattr("uns"))
     }
     fn obsm(&self) -> Self::AxisArraysRef<'_> {
         AxisArrays {
-            arrays: self.getattr("obsm").unwrap(),
+            arrays: self.getattr("obsm"),
             adata: self,
             axis: 0,
+            pairwise: false,
         }
     }
     fn obsp(&self) -> Self::AxisArraysRef<'_> {
         AxisArrays {
-            arrays: self.getattr("obsp").unwrap(),
+            arrays: self.getattr("obsp"),
             adata: self,
             axis: 0,
+            pairwise: true,
         }
     }
     fn varm(&self) -> Self::AxisArraysRef<'_> {
         AxisArrays {
-            arrays: self.getattr("varm").unwrap(),
+            arrays: self.getattr("varm"),
             adata: self,
             axis: 1,
+            pairwise: false,
         }
     }
     fn varp(&self) -> Self::AxisArraysRef<'_> {
         AxisArrays {
-            arrays: self.getattr("varp").unwrap(),
+            arrays: self.getattr("varp"),
             adata: self,
             axis: 1,
+            pairwise: true,
         }
     }
 
     fn layers(&self) -> Self::AxisArraysRef<'_> {
         AxisArrays {
-            arrays: self.getattr("layers").unwrap(),
+            arrays: self.getattr("layers"),
             adata: self,
             axis: 2,
+            pairwise: false,
         }
     }
 
@@ -412,11 +504,24 @@ where
     }
 }
 
-pub struct ElemCollection<'a>(&'a PyAny);
+/// The result of looking up an attribute of the AnnData object. The error, if
+/// any, is raised by the first fallible operation on the element.
+fn lookup<'a>(attr: &PyResult<&'a PyAny>) -> Result<&'a PyAny> {
+    match attr {
+        Ok(x) => Ok(*x),
+        Err(e) => Python::with_gil(|py| Err(e.clone_ref(py).into())),
+    }
+}
+
+fn keys_of(mapping: &PyAny) -> Result<Vec<String>> {
+    Ok(mapping.call_method0("keys")?.iter()?.map(|x| x?.extract()).collect::<PyResult<_>>()?)
+}
+
+pub struct ElemCollection<'a>(PyResult<&'a PyAny>);
 
 impl ElemCollectionOp for ElemCollection<'_> {
     fn keys(&self) -> Vec<String> {
-        self.0.call_method0("keys").unwrap().iter().unwrap().map(|x| x.unwrap().extract().unwrap()).collect()
+        lookup(&self.0).and_then(keys_of).unwrap_or_default()
     }
 
     fn get_item<D>(&self, key: &str) -> Result<Option<D>>
@@ -424,7 +529,7 @@ impl ElemCollectionOp for ElemCollection<'_> {
             D: Into<Data> + TryFrom<Data> + Clone,
             <D as TryFrom<Data>>::Error: Into<anyhow::Error>
     {
-        self.0.call_method1("__getitem__", (key,)).ok().map(|x| {
+        lookup(&self.0)?.call_method1("__getitem__", (key,)).ok().map(|x| {
             let data: Data = x.extract::<PyData>()?.into();
             data.try_into().map_err(Into::into)
         }).transpose()
@@ -436,38 +541,66 @@ impl ElemCollectionOp for ElemCollection<'_> {
             data: D,
         ) -> Result<()>
     {
-        let py = self.0.py();
+        let uns = lookup(&self.0)?;
+        let py = uns.py();
         let d = PyData::from(data.into()).into_py(py);
         let new_d = if isinstance_of_polars(py, d.as_ref(py))? {
             d.call_method0(py, "to_pandas")?
         } else {
             d
         };
-        self.0.call_method1("__setitem__", (key, new_d))?;
+        uns.call_method1("__setitem__", (key, new_d))?;
         Ok(())
     }
 
     fn remove(&self, key: &str) -> Result<()> {
-        self.0.call_method1("__delitem__", (key,))?;
+        lookup(&self.0)?.call_method1("__delitem__", (key,))?;
         Ok(())
     }
 }
 
 pub struct AxisArrays<'a> {
-    arrays: &'a PyAny,
+    arrays: PyResult<&'a PyAny>,
     adata: &'a PyAnnData<'a>,
     axis: u8,
+    /// Whether the arrays are square matrices aligned to the axis on both
+    /// dimensions, i.e., obsp and varp.
+    pairwise: bool,
+}
+
+impl AxisArrays<'_> {
+    /// Check that an array of `shape` can be added, setting n_obs and n_vars
+    /// if they are not set yet.
+    fn set_shape(&self, shape: &Shape) -> Result<()> {
+        if self.pairwise {
+            ensure!(
+                shape.ndim() == 2 && shape[0] == shape[1],
+                "pairwise arrays must be square matrices, found shape {}",
+                shape,
+            );
+        }
+        if self.axis == 0 {
+            self.adata.set_n_obs(shape[0])?;
+        } else if self.axis == 1 {
+            self.adata.set_n_vars(shape[0])?;
+        } else {
+            self.adata.set_n_obs(shape[0])?;
+            self.adata.set_n_vars(shape[1])?;
+        }
+        Ok(())
+    }
 }
 
 impl<'py> AxisArraysOp for AxisArrays<'py> {
     type ArrayElem = ArrayElem<'py>;
 
     fn keys(&self) -> Vec<String> {
-        self.arrays.call_method0("keys").unwrap().iter().unwrap().map(|x| x.unwrap().extract().unwrap()).collect()
+        lookup(&self.arrays).and_then(keys_of).unwrap_or_default()
     }
 
-    fn get(&self, key: &str) -> Option<Self::ArrayElem> {
-        self.arrays.call_method1("__getitem__", (key,)).ok().map(ArrayElem)
+    fn try_get(&self, key: &str) -> Result<Option<Self::ArrayElem>> {
+        let arrays = lookup(&self.arrays)?;
+        Ok(arrays.call_method1("__getitem__", (key,)).ok().map(|x| ArrayElem(Ok(x))))
     }
 
     fn add<D: HasShape + Into<ArrayData>>(
@@ -476,23 +609,21 @@ impl<'py> AxisArraysOp for AxisArrays<'py> {
             data: D,
         ) -> Result<()>
     {
-        let py = self.arrays.py();
-        let shape = data.shape();
-        if self.axis == 0 {
-            self.adata.set_n_obs(shape[0])?;
-        } else if self.axis == 1 {
-            self.adata.set_n_vars(shape[0])?;
-        } else {
-            self.adata.set_n_obs(shape[0])?;
-            self.adata.set_n_vars(shape[1])?;
-        }
-        let d = PyArrayData::from(data.into()).into_py(py);
-        let new_d = if isinstance_of_polars(py, d.as_ref(py))? {
-            d.call_method0(py, "to_pandas")?
-        } else {
-            d
+        let arrays = lookup(&self.arrays)?;
+        let py = arrays.py();
+        self.set_shape(&data.shape())?;
+        let d = match data.into() {
+            ArrayData::DataFrame(df) => {
+                let df = to_pandas(py, df)?;
+                if !self.pairwise {
+                    let names = if self.axis == 0 { "obs_names" } else { "var_names" };
+                    df.setattr(py, "index", self.adata.getattr(names)?)?;
+                }
+                df
+            }
+            data => PyArrayData::from(data).into_py(py),
         };
-        self.arrays.call_method1("__setitem__", (key, new_d))?;
+        arrays.call_method1("__setitem__", (key, d))?;
         Ok(())
     }
 
@@ -501,30 +632,23 @@ impl<'py> AxisArraysOp for AxisArrays<'py> {
             I: Iterator<Item = D>,
             D: ArrayChunk + Into<ArrayData>,
     {
-        let py = self.arrays.py();
+        let arrays = lookup(&self.arrays)?;
+        let py = arrays.py();
         let array = ArrayOp::vstack(data)?;
-        let shape = array.shape();
-        if self.axis == 0 {
-            self.adata.set_n_obs(shape[0])?;
-        } else if self.axis == 1 {
-            self.adata.set_n_vars(shape[0])?;
-        } else {
-            self.adata.set_n_obs(shape[0])?;
-            self.adata.set_n_vars(shape[1])?;
-        }
-        self.arrays
+        self.set_shape(&array.shape())?;
+        arrays
             .call_method1("__setitem__", (key, PyArrayData::from(array.into()).into_py(py)))?;
         Ok(())
     }
 
     fn remove(&self, key: &str) -> Result<()> {
-        self.arrays.call_method1("__delitem__", (key,))?;
+        lookup(&self.arrays)?.call_method1("__delitem__", (key,))?;
         Ok(())
     }
 
 }
 
-pub struct ArrayElem<'a>(&'a PyAny);
+pub struct ArrayElem<'a>(PyResult<&'a PyAny>);
 
 impl ArrayElemOp for ArrayElem<'_> {
     type ArrayIter<T> = PyArrayIterator<T>
@@ -533,7 +657,7 @@ impl ArrayElemOp for ArrayElem<'_> {
         <T as TryFrom<ArrayData>>::Error: Into<anyhow::Error>;
 
     fn shape(&self) -> Option<Shape> {
-        let shape: Vec<usize> = self.0.getattr("shape").unwrap().extract().unwrap();
+        let shape: Vec<usize> = self.0.as_ref().ok()?.getattr("shape").ok()?.extract().ok()?;
         Some(shape.into())
     }
 
@@ -542,7 +666,7 @@ impl ArrayElemOp for ArrayElem<'_> {
         D: ReadData + Into<ArrayData> + TryFrom<ArrayData> + Clone,
         <D as TryFrom<ArrayData>>::Error: Into<anyhow::Error>,
     {
-        let data: Option<ArrayData> = self.0.extract::<Option<PyArrayData>>()?.map(Into::into);
+        let data: Option<ArrayData> = lookup(&self.0)?.extract::<Option<PyArrayData>>()?.map(Into::into);
         data.map(|x| x.try_into().map_err(Into::into)).transpose()
     }
 
@@ -563,7 +687,7 @@ impl ArrayElemOp for ArrayElem<'_> {
         T: Into<ArrayData> + TryFrom<ArrayData> + ReadArrayData + Clone,
         <T as TryFrom<ArrayData>>::Error: Into<anyhow::Error>,
     {
-        let array = self.0.extract::<PyArrayData>().unwrap();
+        let array = lookup(&self.0).unwrap().extract::<PyArrayData>().unwrap();
         PyArrayIterator::new(array, chunk_size).unwrap()
     }
 }
\ No newline at end of file
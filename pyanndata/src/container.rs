@@ -1,12 +1,20 @@
 mod traits;
 
-use crate::data::{PyData, PyArrayData, PySeries};
+use crate::data::{to_pandas, FromPython, PyData, PyArrayData, PyDataFrame, PySeries};
 
+use anndata::data::{ArrayData, DataFrameIndex, DynScalar};
+
+use ndarray::Array2;
+use numpy::IntoPyArray;
 use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use traits::{ElemTrait, ArrayElemTrait, DataFrameElemTrait, AxisArrayTrait};
-use anyhow::Result;
+use anyhow::{bail, Result};
+use polars::prelude::JoinType;
 
-use self::traits::{ElemCollectionTrait, ChunkedArrayTrait};
+use self::traits::{ElemCollectionTrait, ChunkedArrayTrait, ChunkedArrayWithColumnsTrait};
 
 
 #[pyclass]
@@ -29,6 +37,10 @@ impl PyElem {
         self.0.disable_cache();
     }
 
+    fn evict(&self) {
+        self.0.evict();
+    }
+
     fn is_scalar(&self) -> bool {
         self.0.is_scalar()
     }
@@ -74,14 +86,53 @@ impl PyArrayElem {
         self.0.disable_cache();
     }
 
+    /// Clear the in-memory cache but keep caching enabled, so that the data
+    /// is cached again the next time it is accessed.
+    #[pyo3(text_signature = "($self)")]
+    fn evict(&self) {
+        self.0.evict();
+    }
+
     /// Shape of array.
     #[getter]
     fn shape(&self) -> Vec<usize> {
         self.0.shape()
     }
 
-    fn __getitem__(&self, subscript: &PyAny) -> Result<PyArrayData> {
-        self.0.get(subscript)
+    /// Read the selected part of the array. If the whole array is selected,
+    /// and it is a dense array held in the cache, a read-only view of the
+    /// cached data is returned instead of a copy. The view stays valid, and
+    /// keeps the data it refers to alive, after the cache is evicted or the
+    /// array is written, but it then no longer reflects the array.
+    fn __getitem__(&self, py: Python<'_>, subscript: &PyAny) -> Result<PyObject> {
+        if let Some(view) = self.0.cached_view(py, subscript)? {
+            return Ok(view);
+        }
+        Ok(self.0.get(subscript)?.into_py(py))
+    }
+
+    /// Read a contiguous range of rows in a single call.
+    ///
+    /// Unlike iterating over `chunked`, only the rows `start:stop` are read,
+    /// which is cheap for both dense arrays and CSR matrices.
+    ///
+    /// Parameters
+    /// ----------
+    /// start
+    ///     Index of the first row.
+    /// stop
+    ///     Index after the last row.
+    ///
+    /// Returns
+    /// -------
+    /// A array
+    #[pyo3(text_signature = "($self, start, stop)")]
+    fn get_rows(&self, start: usize, stop: usize) -> Result<PyArrayData> {
+        let n_rows = self.0.shape().first().copied().unwrap_or(0);
+        if start > stop || stop > n_rows {
+            bail!("cannot read rows {}..{} of an array with {} rows", start, stop, n_rows);
+        }
+        self.0.get_rows(start, stop).map(PyArrayData::from)
     }
 
     /// Return a chunk of the matrix with random indices.
@@ -126,6 +177,101 @@ impl PyArrayElem {
         self.0.chunked(chunk_size)
     }
 
+    /// Transform the array in place, chunk by chunk.
+    ///
+    /// Each chunk of rows is passed to `func`, and the returned array is written
+    /// back in place of the chunk. The returned array must have the same shape
+    /// and dtype as the chunk. For sparse matrices, it must also have the same
+    /// number of non-zero entries.
+    ///
+    /// Parameters
+    /// ----------
+    /// func
+    ///     A function taking a chunk of rows and returning the transformed chunk.
+    /// chunk_size
+    ///     Number of rows of a single chunk.
+    #[pyo3(
+        signature = (func, chunk_size=500),
+        text_signature = "($self, func, chunk_size=500)",
+    )]
+    fn transform(&self, func: &PyAny, chunk_size: usize) -> Result<()> {
+        self.0.transform(func, chunk_size)
+    }
+
+    /// Convert a sparse matrix to a dense array, rewriting the data in place.
+    /// Nothing is done if the array is already dense and `fill_value` is None.
+    ///
+    /// Parameters
+    /// ----------
+    /// fill_value
+    ///     The value of the entries that are not stored in the sparse matrix,
+    ///     zero if None. It is also stored as the fill value of the dataset, so
+    ///     that blocks containing only this value take no space in the file.
+    #[pyo3(
+        signature = (fill_value=None),
+        text_signature = "($self, fill_value=None)",
+    )]
+    fn to_dense(&self, fill_value: Option<&PyAny>) -> Result<()> {
+        let fill = fill_value.map(DynScalar::from_python).transpose()?;
+        self.0.to_dense(fill)
+    }
+
+    /// Convert a dense array to a CSR matrix, rewriting the data in place.
+    /// Nothing is done if the array is already sparse.
+    ///
+    /// Parameters
+    /// ----------
+    /// threshold
+    ///     Values whose absolute value is below `threshold` are treated as zeros.
+    #[pyo3(
+        signature = (threshold=0.0),
+        text_signature = "($self, threshold=0.0)",
+    )]
+    fn to_sparse(&self, threshold: f64) -> Result<()> {
+        self.0.to_sparse(threshold)
+    }
+
+    /// Compute the mean, variance, minimum, maximum and quantiles of each
+    /// column in a single pass over chunks of rows. Sparse matrices are not
+    /// densified, and their implicit zeros are taken into account.
+    ///
+    /// Parameters
+    /// ----------
+    /// chunk_size
+    ///     Number of rows read at a time.
+    /// quantiles
+    ///     The quantiles to estimate, between 0 and 1. Quantiles are
+    ///     approximated with t-digests, whereas the other statistics are exact.
+    ///
+    /// Returns
+    /// -------
+    /// dict[str, np.ndarray]
+    ///     "mean", "variance" (with one degree of freedom), "min" and "max" of
+    ///     each column, and "quantiles", a matrix with one row per quantile.
+    #[pyo3(
+        signature = (chunk_size=500, quantiles=vec![0.25, 0.5, 0.75]),
+        text_signature = "($self, chunk_size=500, quantiles=[0.25, 0.5, 0.75])",
+    )]
+    fn column_stats<'py>(
+        &self,
+        py: Python<'py>,
+        chunk_size: usize,
+        quantiles: Vec<f64>,
+    ) -> Result<&'py PyDict> {
+        let stats = self.0.column_stats(chunk_size)?;
+        let mut values = Array2::zeros((quantiles.len(), stats.n_cols()));
+        for (mut row, q) in values.rows_mut().into_iter().zip(quantiles) {
+            row.assign(&stats.quantile(q)?);
+        }
+        let result = PyDict::new(py);
+        result.set_item("mean", stats.mean().into_pyarray(py))?;
+        result.set_item("variance", stats.variance().into_pyarray(py))?;
+        result.set_item("min", stats.min().into_pyarray(py))?;
+        result.set_item("max", stats.max().into_pyarray(py))?;
+        result.set_item("quantiles", values.into_pyarray(py))?;
+        Ok(result)
+    }
+
     fn __repr__(&self) -> String {
         self.0.show()
     }
@@ -156,6 +302,48 @@ impl PyDataFrameElem {
         self.0.set(key, data.into())
     }
 
+    /// Add a column, or replace the column of the same name. Only the new
+    /// column is written to disk.
+    ///
+    /// Parameters
+    /// ----------
+    /// name: str
+    ///     The name of the column.
+    /// values
+    ///     The values of the column, anything that can be converted to a
+    ///     `polars.Series`. The length must equal the height of the dataframe.
+    #[pyo3(text_signature = "($self, name, values)")]
+    fn add_column(&self, name: &str, values: PySeries) -> Result<()> {
+        self.0.set(name, values.into())
+    }
+
+    /// Join an external table, e.g., gene annotations, to the dataframe.
+    /// Only the new columns are written to disk and the row order is kept.
+    ///
+    /// Parameters
+    /// ----------
+    /// df: polars.DataFrame | pandas.DataFrame
+    ///     The table to join. Its columns other than `on` must not exist in
+    ///     the dataframe.
+    /// on: str
+    ///     The key, either a column of the dataframe or the name of its index.
+    /// how: Literal['left', 'inner', 'outer']
+    ///     The join strategy. An error is raised if the join changes the number
+    ///     of rows, e.g., when the keys of `df` are duplicated.
+    #[pyo3(
+        signature = (df, on, how="left"),
+        text_signature = "($self, df, on, how='left')",
+    )]
+    fn join(&self, df: PyDataFrame, on: &str, how: &str) -> Result<()> {
+        let how = match how {
+            "left" => JoinType::Left,
+            "inner" => JoinType::Inner,
+            "outer" => JoinType::Outer,
+            x => bail!("unknown join type: '{}'", x),
+        };
+        self.0.join(df.into(), on, how)
+    }
+
     fn __contains__(&self, key: &str) -> bool {
         self.0.contains(key)
     }
@@ -187,13 +375,30 @@ impl PyDataFrameElem {
 ///       [13.247231  , -4.200884  ]], dtype=float32)
 /// >>> data.obsm.el('X_umap')
 /// Array(Float(U4)) element, cache_enabled: no, cached: no
+///
+/// Dataframes, e.g., spatial coordinates with named columns, are returned as
+/// `pandas.DataFrame` indexed by the names of the rows, i.e., `obs_names` for
+/// obsm and `var_names` for varm.
 #[pyclass]
-#[repr(transparent)]
-pub struct PyAxisArrays(Box<dyn AxisArrayTrait>);
+pub struct PyAxisArrays(Box<dyn AxisArrayTrait>, Option<RowNames>);
+
+/// Return the names of the rows of the arrays, read when they are needed.
+type RowNames = Box<dyn Fn() -> DataFrameIndex + Send>;
 
 impl<T: AxisArrayTrait + 'static> From<T> for PyAxisArrays {
     fn from(elem: T) -> Self {
-        Self(Box::new(elem))
+        Self(Box::new(elem), None)
+    }
+}
+
+impl PyAxisArrays {
+    /// Use the names returned by `row_names` as the index of the dataframes.
+    pub(crate) fn with_row_names<F>(mut self, row_names: F) -> Self
+    where
+        F: Fn() -> DataFrameIndex + Send + 'static,
+    {
+        self.1 = Some(Box::new(row_names));
+        self
     }
 }
 
@@ -213,8 +418,43 @@ impl PyAxisArrays {
         self.0.contains(key)
     }
 
-    fn __getitem__(&self, key: &str) -> Result<PyArrayData> {
-        self.0.get(key)
+    fn __getitem__(&self, py: Python<'_>, key: &str) -> Result<PyObject> {
+        match self.0.get(key)?.into() {
+            ArrayData::DataFrame(df) => {
+                let df = to_pandas(py, df)?;
+                if let Some(names) = self.1.as_ref().map(|f| f()).filter(|x| x.len() > 0) {
+                    df.setattr(py, "index", names.into_vec())?;
+                }
+                Ok(df)
+            }
+            data => Ok(PyArrayData::from(data).into_py(py)),
+        }
+    }
+
+    /// Return the array of `key`, or `default` if there is no such key.
+    #[pyo3(
+        signature = (key, default=None),
+        text_signature = "($self, key, default=None)",
+    )]
+    fn get(&self, py: Python<'_>, key: &str, default: Option<PyObject>) -> Result<Option<PyObject>> {
+        if self.0.contains(key) {
+            self.__getitem__(py, key).map(Some)
+        } else {
+            Ok(default)
+        }
+    }
+
+    /// Return the (key, array) pairs. All arrays are read into memory.
+    #[pyo3(text_signature = "($self)")]
+    fn items(&self, py: Python<'_>) -> Result<Vec<(String, PyObject)>> {
+        self.0
+            .keys()
+            .into_iter()
+            .map(|key| {
+                let value = self.__getitem__(py, &key)?;
+                Ok((key, value))
+            })
+            .collect()
     }
 
     /// Provide a lazy access to the elements.
@@ -256,6 +496,87 @@ impl PyAxisArrays {
         self.0.set(key, data)
     }
 
+    /// Add an array, optionally converting its values to another type.
+    ///
+    /// Parameters
+    /// ----------
+    /// key : str
+    ///     The name of the array.
+    /// data : np.ndarray | sp.csr_matrix | sp.csc_matrix
+    ///     The array to add.
+    /// dtype : np.dtype | str | None
+    ///     If given, the values are converted to this numeric type before
+    ///     writing. An error is raised if a value cannot be represented in the
+    ///     new type.
+    #[pyo3(
+        signature = (key, data, *, dtype=None),
+        text_signature = "($self, key, data, *, dtype=None)",
+    )]
+    fn add(&self, key: &str, data: PyArrayData, dtype: Option<&PyAny>) -> Result<()> {
+        self.0.set(key, data.cast(dtype)?)
+    }
+
+    /// Concatenate arrays column-wise and store the result under a new key,
+    /// e.g., to combine two embeddings in obsm. Sparse matrices are converted
+    /// to dense arrays, and arrays of different types are cast to float64.
+    ///
+    /// Parameters
+    /// ----------
+    /// keys : list[str]
+    ///     The names of the arrays to concatenate.
+    /// out_key : str
+    ///     The name of the result.
+    #[pyo3(text_signature = "($self, keys, out_key)")]
+    fn hstack(&self, keys: Vec<&str>, out_key: &str) -> Result<()> {
+        self.0.hstack(&keys, out_key)
+    }
+
+    /// Add a link to an array stored in another `.h5ad` file, e.g., to share a
+    /// reference matrix between files without copying it. The linked array is
+    /// read from the other file, and cannot be modified through the link.
+    /// Deleting the key removes only the link.
+    ///
+    /// Parameters
+    /// ----------
+    /// key : str
+    ///     The name of the link.
+    /// target_file : Path
+    ///     The file containing the array.
+    /// target_path : str
+    ///     The path of the array in `target_file`, e.g., "/X".
+    #[pyo3(text_signature = "($self, key, target_file, target_path)")]
+    fn add_external_link(&self, key: &str, target_file: PathBuf, target_path: &str) -> Result<()> {
+        self.0.add_external_link(key, target_file, target_path)
+    }
+
+    /// Clear the in-memory cache of an array but keep caching enabled. See
+    /// `PyArrayElem.evict`.
+    ///
+    /// Parameters
+    /// ----------
+    /// key : str
+    ///     The name of the array.
+    #[pyo3(text_signature = "($self, key)")]
+    fn evict(&self, key: &str) -> Result<()> {
+        self.0.el(key)?.0.evict();
+        Ok(())
+    }
+
+    /// Read several arrays at once. The arrays are read in parallel.
+    ///
+    /// Parameters
+    /// ----------
+    /// keys : list[str]
+    ///     The names of the arrays to read.
+    ///
+    /// Returns
+    /// -------
+    /// dict[str, np.ndarray | sp.csr_matrix | sp.csc_matrix]
+    #[pyo3(text_signature = "($self, keys)")]
+    fn read_many(&self, keys: Vec<&str>) -> Result<HashMap<String, PyArrayData>> {
+        self.0.read_many(&keys)
+    }
+
     fn __repr__(&self) -> String {
         self.0.show()
     }
@@ -294,6 +615,71 @@ impl PyElemCollection {
         self.0.set(key, data)
     }
 
+    fn __delitem__(&self, key: &str) -> Result<()> {
+        self.0.remove(key)
+    }
+
+    /// Remove `key` and return its element. If there is no such key, `default`
+    /// is returned if given, otherwise a `KeyError` is raised.
+    #[pyo3(
+        signature = (key, *default),
+        text_signature = "($self, key, default)",
+    )]
+    fn pop(&self, py: Python<'_>, key: &str, default: &PyTuple) -> Result<PyObject> {
+        if default.len() > 1 {
+            bail!("pop expected at most 2 arguments, got {}", default.len() + 1);
+        }
+        if self.0.contains(key) {
+            let value = self.0.get(key)?.into_py(py);
+            self.0.remove(key)?;
+            Ok(value)
+        } else if let Ok(default) = default.get_item(0) {
+            Ok(default.into_py(py))
+        } else {
+            self.0.remove(key).map(|_| py.None())
+        }
+    }
+
+    /// Return the element of `key`, or `default` if there is no such key.
+    #[pyo3(
+        signature = (key, default=None),
+        text_signature = "($self, key, default=None)",
+    )]
+    fn get(&self, py: Python<'_>, key: &str, default: Option<PyObject>) -> Result<Option<PyObject>> {
+        if self.0.contains(key) {
+            Ok(Some(self.0.get(key)?.into_py(py)))
+        } else {
+            Ok(default)
+        }
+    }
+
+    /// Return the (key, element) pairs. All elements are read into memory.
+    #[pyo3(text_signature = "($self)")]
+    fn items(&self) -> Result<Vec<(String, PyData)>> {
+        self.0
+            .keys()
+            .into_iter()
+            .map(|key| {
+                let value = self.0.get(&key)?;
+                Ok((key, value))
+            })
+            .collect()
+    }
+
+    /// Move or rename an item without reading or rewriting its data.
+    ///
+    /// Parameters
+    /// ----------
+    /// from_path : str
+    ///     The slash-delimited path of the item, e.g., "pca/variance" for the
+    ///     "variance" entry of the "pca" dictionary.
+    /// to_path : str
+    ///     The new path, e.g., "pca/var_explained". It must not exist yet.
+    #[pyo3(text_signature = "($self, from_path, to_path)")]
+    fn move_item(&self, from_path: &str, to_path: &str) -> Result<()> {
+        self.0.move_item(from_path, to_path)
+    }
+
     fn __repr__(&self) -> String {
         self.0.show()
     }
@@ -327,4 +713,31 @@ impl<T: ChunkedArrayTrait + 'static> From<T> for PyChunkedArray {
     fn from(elem: T) -> Self {
         Self(Box::new(elem))
     }
-}
\ No newline at end of file
+}
+
+/// Iterator over chunks of rows of X, each paired with the same rows of some
+/// obs columns.
+#[pyclass]
+#[repr(transparent)]
+pub struct PyChunkedArrayWithObs(Box<dyn ChunkedArrayWithColumnsTrait>);
+
+#[pymethods]
+impl PyChunkedArrayWithObs {
+    fn n_chunks(&self) -> usize {
+        self.0.len()
+    }
+
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<Self>) -> Result<Option<(PyArrayData, PyDataFrame)>> {
+        slf.0.next().transpose().map(|x| x.map(|(data, df)| (data.into(), df.into())))
+    }
+}
+
+impl<T: ChunkedArrayWithColumnsTrait + 'static> From<T> for PyChunkedArrayWithObs {
+    fn from(elem: T) -> Self {
+        Self(Box::new(elem))
+    }
+}
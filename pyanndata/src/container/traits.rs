@@ -1,18 +1,21 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::ops::Deref;
 
 use crate::data::{
-    is_none_slice, to_select_info, IntoPython, PyArrayData, PyData, PyDataFrame,
+    cached_array_view, is_none_slice, to_select_info, IntoPython, PyArrayData, PyData, PyDataFrame,
 };
 
 use anndata::backend::DataType;
-use anndata::data::SelectInfoElem;
+use anndata::data::{DynScalar, SelectInfoElem};
 use anndata::{
     ArrayData, ArrayElem, AxisArrays, Backend, Data,
-    DataFrameElem, Elem, ElemCollection, StackedArrayElem, StackedDataFrame, StackedAxisArrays,
+    DataFrameElem, Elem, ElemCollection, StackedArrayElem, StackedDataFrame, StackedAxisArrays, Statistics,
 };
-use anndata::container::{ChunkedArrayElem, StackedChunkedArrayElem};
-use anyhow::{bail, Context, Result};
-use polars::series::Series;
+use anndata::container::{ChunkedArrayElem, ChunkedArrayWithColumns, StackedChunkedArrayElem};
+use anyhow::{bail, ensure, Context, Result};
+use polars::{frame::DataFrame, prelude::JoinType, series::Series};
+use pyo3::exceptions::PyKeyError;
 use pyo3::prelude::*;
 use rand::Rng;
 use rand::SeedableRng;
@@ -23,6 +26,7 @@ use super::{PyArrayElem, PyElem, PyChunkedArray};
 pub trait ElemTrait: Send {
     fn enable_cache(&self);
     fn disable_cache(&self);
+    fn evict(&self);
     fn is_scalar(&self) -> bool;
     fn get<'py>(&self, py: Python<'py>, subscript: &'py PyAny) -> Result<PyData>;
     fn show(&self) -> String;
@@ -37,6 +41,10 @@ impl<B: Backend> ElemTrait for Elem<B> {
         self.lock().as_mut().map(|x| x.disable_cache());
     }
 
+    fn evict(&self) {
+        self.lock().as_mut().map(|x| x.evict());
+    }
+
     fn is_scalar(&self) -> bool {
         match self.inner().dtype() {
             DataType::Scalar(_) => true,
@@ -46,7 +54,8 @@ impl<B: Backend> ElemTrait for Elem<B> {
 
     fn get<'py>(&self, py: Python<'py>, slice: &'py PyAny) -> Result<PyData> {
         if is_none_slice(py, slice)? {
-            Ok(self.inner().data::<Data>()?.into())
+            let mut inner = self.try_inner().context("cannot access a closed element")?;
+            Ok(inner.data::<Data>()?.into())
         } else {
             bail!("Please use None slice to retrieve data.")
         }
@@ -60,8 +69,13 @@ impl<B: Backend> ElemTrait for Elem<B> {
 pub trait ArrayElemTrait: Send {
     fn enable_cache(&self);
     fn disable_cache(&self);
+    fn evict(&self);
     fn show(&self) -> String;
     fn get(&self, subscript: &PyAny) -> Result<PyArrayData>;
+    /// A read-only view of the cached data if `subscript` selects the whole
+    /// array. See `cached_array_view`.
+    fn cached_view(&self, py: Python<'_>, subscript: &PyAny) -> Result<Option<PyObject>>;
+    fn get_rows(&self, start: usize, stop: usize) -> Result<ArrayData>;
     fn shape(&self) -> Vec<usize>;
     fn chunk(
         &self,
@@ -70,6 +84,10 @@ pub trait ArrayElemTrait: Send {
         seed: u64,
     ) -> Result<ArrayData>;
     fn chunked(&self, chunk_size: usize) -> PyChunkedArray;
+    fn transform(&self, f: &PyAny, chunk_size: usize) -> Result<()>;
+    fn to_dense(&self, fill: Option<DynScalar>) -> Result<()>;
+    fn to_sparse(&self, threshold: f64) -> Result<()>;
+    fn column_stats(&self, chunk_size: usize) -> Result<Statistics>;
 }
 
 impl<B: Backend + 'static> ArrayElemTrait for ArrayElem<B> {
@@ -81,11 +99,30 @@ impl<B: Backend + 'static> ArrayElemTrait for ArrayElem<B> {
         self.lock().as_mut().map(|x| x.disable_cache());
     }
 
+    fn evict(&self) {
+        self.lock().as_mut().map(|x| x.evict());
+    }
+
     fn get(&self, subscript: &PyAny) -> Result<PyArrayData> {
-        let slice = to_select_info(subscript, self.inner().shape())?;
-        self.inner()
-            .select::<ArrayData, _>(slice.as_ref())
-            .map(|x| x.into())
+        let mut inner = self.try_inner().context("cannot access a closed element")?;
+        let slice = to_select_info(subscript, inner.shape())?;
+        inner.select::<ArrayData, _>(slice.as_ref()).map(|x| x.into())
+    }
+
+    fn cached_view(&self, py: Python<'_>, subscript: &PyAny) -> Result<Option<PyObject>> {
+        let inner = self.try_inner().context("cannot access a closed element")?;
+        let slice = to_select_info(subscript, inner.shape())?;
+        if !slice.iter().all(|x| x.is_full()) {
+            return Ok(None);
+        }
+        match inner.cached() {
+            Some(data) => Ok(cached_array_view(py, data)?),
+            None => Ok(None),
+        }
+    }
+
+    fn get_rows(&self, start: usize, stop: usize) -> Result<ArrayData> {
+        self.inner().select_axis::<ArrayData, _>(0, &SelectInfoElem::from(start..stop))
     }
 
     fn show(&self) -> String {
@@ -117,6 +154,31 @@ impl<B: Backend + 'static> ArrayElemTrait for ArrayElem<B> {
     fn chunked(&self, chunk_size: usize) -> PyChunkedArray {
         self.chunked::<ArrayData>(chunk_size).into()
     }
+
+    fn transform(&self, f: &PyAny, chunk_size: usize) -> Result<()> {
+        self.map_chunks(chunk_size, |chunk| {
+            let result = f.call1((PyArrayData::from(chunk).into_py(f.py()),))?;
+            Ok(result.extract::<PyArrayData>()?.into())
+        })
+    }
+
+    fn to_dense(&self, fill: Option<DynScalar>) -> Result<()> {
+        match fill {
+            Some(fill) => ArrayElem::to_dense_with_fill(self, fill),
+            None => ArrayElem::to_dense(self),
+        }
+    }
+
+    fn to_sparse(&self, threshold: f64) -> Result<()> {
+        ArrayElem::to_sparse(self, threshold)
+    }
+
+    fn column_stats(&self, chunk_size: usize) -> Result<Statistics> {
+        let shape = self.shape();
+        ensure!(shape.len() == 2, "expecting a matrix, found shape {:?}", shape);
+        let chunks = self.chunked::<ArrayData>(chunk_size).map(|(x, _, _)| x);
+        Statistics::from_chunks(shape[1], chunks)
+    }
 }
 
 impl<B: Backend + 'static> ArrayElemTrait for StackedArrayElem<B> {
@@ -128,10 +190,25 @@ impl<B: Backend + 'static> ArrayElemTrait for StackedArrayElem<B> {
         self.deref().disable_cache();
     }
 
+    fn evict(&self) {
+        self.deref().evict();
+    }
+
     fn get(&self, subscript: &PyAny) -> Result<PyArrayData> {
-        let slice = to_select_info(subscript, self.deref().shape().as_ref().unwrap())?;
-        self.select::<ArrayData, _>(slice.as_ref())
-            .map(|x| x.unwrap().into())
+        let shape = self.deref().shape().as_ref().context("the stacked array is empty")?;
+        let slice = to_select_info(subscript, shape)?;
+        Ok(self.select::<ArrayData, _>(slice.as_ref())?
+            .context("the stacked array is empty")?
+            .into())
+    }
+
+    fn cached_view(&self, _py: Python<'_>, _subscript: &PyAny) -> Result<Option<PyObject>> {
+        Ok(None)
+    }
+
+    fn get_rows(&self, start: usize, stop: usize) -> Result<ArrayData> {
+        self.select_axis::<ArrayData, _>(0, &SelectInfoElem::from(start..stop))?
+            .context("the stacked array is empty")
     }
 
     fn show(&self) -> String {
@@ -139,7 +216,7 @@ impl<B: Backend + 'static> ArrayElemTrait for StackedArrayElem<B> {
     }
 
     fn shape(&self) -> Vec<usize> {
-        self.deref().shape().as_ref().unwrap().as_ref().to_vec()
+        self.deref().shape().as_ref().map_or(Vec::new(), |x| x.as_ref().to_vec())
     }
 
     fn chunk(
@@ -157,18 +234,38 @@ impl<B: Backend + 'static> ArrayElemTrait for StackedArrayElem<B> {
         } else {
             rand::seq::index::sample(&mut rng, length, size).into_vec()
         };
-        self.select_axis::<ArrayData, _>(0, &SelectInfoElem::from(idx))
-            .map(|x| x.unwrap())
+        self.select_axis::<ArrayData, _>(0, &SelectInfoElem::from(idx))?
+            .context("the stacked array is empty")
     }
 
     fn chunked(&self, chunk_size: usize) -> PyChunkedArray {
         self.chunked::<ArrayData>(chunk_size).into()
     }
+
+    fn transform(&self, _f: &PyAny, _chunk_size: usize) -> Result<()> {
+        bail!("cannot transform a stacked array in place")
+    }
+
+    fn to_dense(&self, _fill: Option<DynScalar>) -> Result<()> {
+        bail!("cannot convert a stacked array in place")
+    }
+
+    fn to_sparse(&self, _threshold: f64) -> Result<()> {
+        bail!("cannot convert a stacked array in place")
+    }
+
+    fn column_stats(&self, chunk_size: usize) -> Result<Statistics> {
+        let shape = self.shape();
+        ensure!(shape.len() == 2, "expecting a matrix, found shape {:?}", shape);
+        let chunks = self.chunked::<ArrayData>(chunk_size).map(|(x, _, _)| x);
+        Statistics::from_chunks(shape[1], chunks)
+    }
 }
 
 pub trait DataFrameElemTrait: Send {
     fn get(&self, subscript: &PyAny) -> Result<PyObject>;
     fn set(&self, key: &str, data: Series) -> Result<()>;
+    fn join(&self, other: DataFrame, on: &str, how: JoinType) -> Result<()>;
     fn contains(&self, key: &str) -> bool;
     fn show(&self) -> String;
 }
@@ -176,21 +273,27 @@ pub trait DataFrameElemTrait: Send {
 impl<B: Backend> DataFrameElemTrait for DataFrameElem<B> {
     fn get(&self, subscript: &PyAny) -> Result<PyObject> {
         let py = subscript.py();
+        let mut inner = self.try_inner().context("cannot access a closed element")?;
         if let Ok(key) = subscript.extract::<&str>() {
-            Ok(self.inner().column(key)?.into_python(py)?)
+            Ok(inner.column(key)?.into_python(py)?)
         } else {
-            let width = self.inner().width();
-            let height = self.inner().height();
+            let width = inner.width();
+            let height = inner.height();
             let shape = [width, height].as_slice().into();
             let slice = to_select_info(subscript, &shape)?;
-            let df = self.inner().select(slice.as_ref())?;
+            let df = inner.select(slice.as_ref())?;
             Ok(PyDataFrame::from(df).into_py(py))
         }
     }
 
     fn set(&self, key: &str, mut data: Series) -> Result<()> {
         data.rename(key);
-        self.inner().set_column(key, data)
+        self.with_inner(|x| x.set_column(key, data))
+            .context("cannot modify a closed element")?
+    }
+
+    fn join(&self, other: DataFrame, on: &str, how: JoinType) -> Result<()> {
+        DataFrameElem::join(self, other, on, how)
     }
 
     fn contains(&self, key: &str) -> bool {
@@ -224,6 +327,10 @@ impl<B: Backend> DataFrameElemTrait for StackedDataFrame<B> {
         bail!("Cannot set column in stacked dataframe")
     }
 
+    fn join(&self, _: DataFrame, _: &str, _: JoinType) -> Result<()> {
+        bail!("Cannot join a table to a stacked dataframe")
+    }
+
     fn contains(&self, key: &str) -> bool {
         self.get_column_names().contains(key)
     }
@@ -233,12 +340,20 @@ impl<B: Backend> DataFrameElemTrait for StackedDataFrame<B> {
     }
 }
 
+/// The error raised in Python when a key is missing, i.e., `KeyError`.
+fn no_such_key(key: &str) -> PyErr {
+    PyKeyError::new_err(key.to_string())
+}
+
 pub trait AxisArrayTrait: Send {
     fn keys(&self) -> Vec<String>;
     fn contains(&self, key: &str) -> bool;
     fn get(&self, key: &str) -> Result<PyArrayData>;
     fn el(&self, key: &str) -> Result<PyArrayElem>;
     fn set(&self, key: &str, data: PyArrayData) -> Result<()>;
+    fn hstack(&self, keys: &[&str], out_key: &str) -> Result<()>;
+    fn read_many(&self, keys: &[&str]) -> Result<HashMap<String, PyArrayData>>;
+    fn add_external_link(&self, key: &str, target_file: PathBuf, target_path: &str) -> Result<()>;
     fn show(&self) -> String;
 }
 
@@ -254,8 +369,8 @@ impl<B: Backend + 'static> AxisArrayTrait for AxisArrays<B> {
     fn get(&self, key: &str) -> Result<PyArrayData> {
         Ok(self
             .inner()
-            .get(key)
-            .context(format!("No such key: {}", key))?
+            .try_get(key)?
+            .ok_or_else(|| no_such_key(key))?
             .inner()
             .data::<ArrayData>()?
             .into())
@@ -264,8 +379,8 @@ impl<B: Backend + 'static> AxisArrayTrait for AxisArrays<B> {
     fn el(&self, key: &str) -> Result<PyArrayElem> {
         Ok(self
             .inner()
-            .get(key)
-            .context(format!("No such key: {}", key))?
+            .try_get(key)?
+            .ok_or_else(|| no_such_key(key))?
             .clone()
             .into())
     }
@@ -274,6 +389,21 @@ impl<B: Backend + 'static> AxisArrayTrait for AxisArrays<B> {
         self.inner().add_data::<ArrayData>(key, data.into())
     }
 
+    fn hstack(&self, keys: &[&str], out_key: &str) -> Result<()> {
+        AxisArrays::hstack(self, keys, out_key)
+    }
+
+    fn read_many(&self, keys: &[&str]) -> Result<HashMap<String, PyArrayData>> {
+        Ok(AxisArrays::read_many(self, keys)?
+            .into_iter()
+            .map(|(k, v)| (k, v.into()))
+            .collect())
+    }
+
+    fn add_external_link(&self, key: &str, target_file: PathBuf, target_path: &str) -> Result<()> {
+        AxisArrays::add_external_link(self, key, target_file, target_path)
+    }
+
     fn show(&self) -> String {
         format!("{}", self)
     }
@@ -292,8 +422,9 @@ impl<B: Backend + 'static> AxisArrayTrait for StackedAxisArrays<B> {
         Ok(self
             .deref()
             .get(key)
-            .context(format!("No such key: {}", key))?
-            .data::<ArrayData>()?.unwrap()
+            .ok_or_else(|| no_such_key(key))?
+            .data::<ArrayData>()?
+            .context("the stacked array is empty")?
             .into())
     }
 
@@ -301,7 +432,7 @@ impl<B: Backend + 'static> AxisArrayTrait for StackedAxisArrays<B> {
         Ok(self
             .deref()
             .get(key)
-            .context(format!("No such key: {}", key))?
+            .ok_or_else(|| no_such_key(key))?
             .clone()
             .into())
     }
@@ -310,6 +441,20 @@ impl<B: Backend + 'static> AxisArrayTrait for StackedAxisArrays<B> {
         bail!("mutations are not allowed on stacked axis arrays")
     }
 
+    fn hstack(&self, _: &[&str], _: &str) -> Result<()> {
+        bail!("mutations are not allowed on stacked axis arrays")
+    }
+
+    fn read_many(&self, keys: &[&str]) -> Result<HashMap<String, PyArrayData>> {
+        keys.iter()
+            .map(|key| Ok((key.to_string(), AxisArrayTrait::get(self, key)?)))
+            .collect()
+    }
+
+    fn add_external_link(&self, _: &str, _: PathBuf, _: &str) -> Result<()> {
+        bail!("mutations are not allowed on stacked axis arrays")
+    }
+
     fn show(&self) -> String {
         format!("{}", self)
     }
@@ -323,6 +468,8 @@ pub trait ElemCollectionTrait: Send {
     fn get(&self, key: &str) -> Result<PyData>;
     fn el(&self, key: &str) -> Result<PyElem>;
     fn set(&self, key: &str, data: PyData) -> Result<()>;
+    fn remove(&self, key: &str) -> Result<()>;
+    fn move_item(&self, from_path: &str, to_path: &str) -> Result<()>;
     fn show(&self) -> String;
 }
 
@@ -338,8 +485,8 @@ impl<B: Backend + 'static> ElemCollectionTrait for ElemCollection<B> {
     fn get(&self, key: &str) -> Result<PyData> {
         Ok(self
             .inner()
-            .get(key)
-            .context(format!("No such key: {}", key))?
+            .try_get(key)?
+            .ok_or_else(|| no_such_key(key))?
             .inner()
             .data::<Data>()?
             .into())
@@ -348,8 +495,8 @@ impl<B: Backend + 'static> ElemCollectionTrait for ElemCollection<B> {
     fn el(&self, key: &str) -> Result<PyElem> {
         Ok(self
             .inner()
-            .get(key)
-            .context(format!("No such key: {}", key))?
+            .try_get(key)?
+            .ok_or_else(|| no_such_key(key))?
             .clone()
             .into())
     }
@@ -358,6 +505,17 @@ impl<B: Backend + 'static> ElemCollectionTrait for ElemCollection<B> {
         self.inner().add_data::<Data>(key, data.into())
     }
 
+    fn remove(&self, key: &str) -> Result<()> {
+        if !self.inner().contains_key(key) {
+            return Err(no_such_key(key).into());
+        }
+        self.inner().remove_data(key)
+    }
+
+    fn move_item(&self, from_path: &str, to_path: &str) -> Result<()> {
+        ElemCollection::move_item(self, from_path, to_path)
+    }
+
     fn show(&self) -> String {
         format!("{}", self)
     }
@@ -366,4 +524,8 @@ impl<B: Backend + 'static> ElemCollectionTrait for ElemCollection<B> {
 pub trait ChunkedArrayTrait: ExactSizeIterator<Item = (ArrayData, usize, usize)> + Send {}
 
 impl<B: Backend> ChunkedArrayTrait for ChunkedArrayElem<B, ArrayData> {}
-impl<B: Backend> ChunkedArrayTrait for StackedChunkedArrayElem<B, ArrayData> {}
\ No newline at end of file
+impl<B: Backend> ChunkedArrayTrait for StackedChunkedArrayElem<B, ArrayData> {}
+
+pub trait ChunkedArrayWithColumnsTrait: ExactSizeIterator<Item = Result<(ArrayData, DataFrame)>> + Send {}
+
+impl<B: Backend> ChunkedArrayWithColumnsTrait for ChunkedArrayWithColumns<B, ArrayData> {}
@@ -3,14 +3,19 @@ mod slice;
 mod dataframe;
 mod array;
 
-pub use dataframe::{PyDataFrame, PySeries};
+pub use dataframe::{PyDataFrame, PySeries, from_pandas, to_index, to_pandas};
 pub(crate) use instance::*;
 pub use slice::{to_select_info, to_select_elem};
+use array::csr_from_coo;
+pub(crate) use array::cached_array_view;
 
+use half::f16;
+use numpy::{Complex32, Complex64};
 use polars::prelude::DataFrame;
 use std::{collections::HashMap, ops::Deref};
-use pyo3::{prelude::*, types::PyDict};
-use anndata::data::{Data, ArrayData, DynArray, DynCsrMatrix, DynCscMatrix, DynScalar, Mapping, DynCsrNonCanonical};
+use pyo3::{prelude::*, types::{PyComplex, PyDict}};
+use anndata::backend::ScalarType;
+use anndata::data::{Data, ArrayData, DynArray, DynCsrMatrix, DynCscMatrix, DynScalar, Mapping, DynCsrNonCanonical, RaggedArray, RecordArray};
 
 pub(crate) trait FromPython<'source>: Sized {
     fn from_python(ob: &'source PyAny) -> PyResult<Self>;
@@ -46,7 +51,11 @@ impl<'py> FromPyObject<'py> for PyArrayData {
     fn extract(ob: &'py PyAny) -> PyResult<Self> {
         let py = ob.py();
         if isinstance_of_arr(py, ob)? {
-            Ok(ArrayData::from(DynArray::from_python(ob)?).into())
+            if ob.getattr("dtype")?.getattr("names")?.is_none() {
+                Ok(ArrayData::from(DynArray::from_python(ob)?).into())
+            } else {
+                Ok(ArrayData::from(RecordArray::from_python(ob)?).into())
+            }
         } else if isinstance_of_csr(py, ob)? {
             if ob.getattr("has_canonical_format")?.extract()? {
                 Ok(ArrayData::from(DynCsrMatrix::from_python(ob)?).into())
@@ -55,8 +64,12 @@ impl<'py> FromPyObject<'py> for PyArrayData {
             }
         } else if isinstance_of_csc(py, ob)? {
             Ok(ArrayData::from(DynCscMatrix::from_python(ob)?).into())
+        } else if isinstance_of_coo(py, ob)? {
+            Ok(ArrayData::from(csr_from_coo(ob)?).into())
         } else if isinstance_of_pandas(py, ob)? || isinstance_of_polars(py, ob)? {
             Ok(ArrayData::from(DataFrame::from(PyDataFrame::extract(ob)?)).into())
+        } else if isinstance_of_awkward(ob)? || ob.is_instance_of::<pyo3::types::PyList>() {
+            Ok(ArrayData::from(RaggedArray::from_python(ob)?).into())
         } else {
             Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
                 format!("Could not convert Python type {} to Rust data", ob.get_type())
@@ -65,6 +78,37 @@ impl<'py> FromPyObject<'py> for PyArrayData {
     }
 }
 
+impl PyArrayData {
+    /// Convert the values to `dtype`, which can be anything accepted by
+    /// `numpy.dtype`, e.g., `"float32"` or `np.int64`. Nothing is done if
+    /// `dtype` is `None`.
+    pub(crate) fn cast(self, dtype: Option<&PyAny>) -> anyhow::Result<Self> {
+        let dtype = match dtype {
+            Some(dtype) => dtype,
+            None => return Ok(self),
+        };
+        let name: String = dtype.py().import("numpy")?
+            .call_method1("dtype", (dtype,))?.getattr("name")?.extract()?;
+        let ty = match name.as_str() {
+            "int8" => ScalarType::I8,
+            "int16" => ScalarType::I16,
+            "int32" => ScalarType::I32,
+            "int64" => ScalarType::I64,
+            "uint8" => ScalarType::U8,
+            "uint16" => ScalarType::U16,
+            "uint32" => ScalarType::U32,
+            "uint64" => ScalarType::U64,
+            "float16" => ScalarType::F16,
+            "float32" => ScalarType::F32,
+            "float64" => ScalarType::F64,
+            "complex64" => ScalarType::ComplexF32,
+            "complex128" => ScalarType::ComplexF64,
+            other => anyhow::bail!("cannot cast to '{}', only numeric types are supported", other),
+        };
+        Ok(self.0.cast(ty)?.into())
+    }
+}
+
 impl IntoPy<PyObject> for PyArrayData {
     fn into_py(self, py: Python<'_>) -> PyObject {
         match self.0 {
@@ -72,6 +116,8 @@ impl IntoPy<PyObject> for PyArrayData {
             ArrayData::CsrMatrix(csr) => csr.into_python(py).unwrap(),
             ArrayData::CsrNonCanonical(csr) => csr.into_python(py).unwrap(),
             ArrayData::CscMatrix(csc) => csc.into_python(py).unwrap(),
+            ArrayData::RaggedArray(x) => x.into_python(py).unwrap(),
+            ArrayData::RecordArray(x) => x.into_python(py).unwrap(),
             ArrayData::DataFrame(df) => PyDataFrame::from(df).into_py(py),
         }
     }
@@ -118,6 +164,11 @@ impl IntoPy<PyObject> for PyData {
 
 impl FromPython<'_> for DynScalar {
     fn from_python(ob: &PyAny) -> PyResult<Self> {
+        // NumPy scalars keep their dtype. Checked first as `numpy.float64` is
+        // also a Python float.
+        if ob.is_instance(ob.py().import("numpy")?.getattr("generic")?)? {
+            return numpy_scalar(ob);
+        }
         if ob.is_instance_of::<pyo3::types::PyBool>() {
             ob.extract::<bool>().map(Into::into)
         } else if ob.is_instance_of::<pyo3::types::PyInt>() {
@@ -126,6 +177,8 @@ impl FromPython<'_> for DynScalar {
             ob.extract::<String>().map(Into::into)
         } else if ob.is_instance_of::<pyo3::types::PyFloat>() {
             ob.extract::<f64>().map(Into::into)
+        } else if let Ok(x) = ob.downcast::<PyComplex>() {
+            Ok(Complex64::new(x.real(), x.imag()).into())
         } else {
             Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
                 "Could not convert to Scalar"
@@ -134,6 +187,30 @@ impl FromPython<'_> for DynScalar {
     }
 }
 
+fn numpy_scalar(ob: &PyAny) -> PyResult<DynScalar> {
+    let scalar = match ob.getattr("dtype")?.getattr("name")?.extract::<&str>()? {
+        "int8" => ob.extract::<i8>()?.into(),
+        "int16" => ob.extract::<i16>()?.into(),
+        "int32" => ob.extract::<i32>()?.into(),
+        "int64" => ob.extract::<i64>()?.into(),
+        "uint8" => ob.extract::<u8>()?.into(),
+        "uint16" => ob.extract::<u16>()?.into(),
+        "uint32" => ob.extract::<u32>()?.into(),
+        "uint64" => ob.extract::<u64>()?.into(),
+        "float16" => f16::from_f32(ob.extract::<f32>()?).into(),
+        "float32" => ob.extract::<f32>()?.into(),
+        "float64" => ob.extract::<f64>()?.into(),
+        "complex64" => Complex32::new(ob.getattr("real")?.extract()?, ob.getattr("imag")?.extract()?).into(),
+        "complex128" => Complex64::new(ob.getattr("real")?.extract()?, ob.getattr("imag")?.extract()?).into(),
+        "bool" => ob.is_true()?.into(),
+        x if x.starts_with("str") => ob.str()?.to_str()?.to_string().into(),
+        x => return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+            format!("Could not convert a NumPy scalar of type '{}' to Scalar", x)
+        )),
+    };
+    Ok(scalar)
+}
+
 impl FromPython<'_> for Mapping {
     fn from_python(ob: &PyAny) -> PyResult<Self> {
         let data: HashMap<String, PyData> = ob.extract()?;
@@ -156,8 +233,11 @@ impl IntoPython for DynScalar {
             DynScalar::U32(s) => Ok(s.into_py(py)),
             DynScalar::U64(s) => Ok(s.into_py(py)),
             DynScalar::Usize(s) => Ok(s.into_py(py)),
+            DynScalar::F16(s) => Ok(s.to_f32().into_py(py)),
             DynScalar::F32(s) => Ok(s.into_py(py)),
             DynScalar::F64(s) => Ok(s.into_py(py)),
+            DynScalar::ComplexF32(s) => Ok(PyComplex::from_doubles(py, s.re.into(), s.im.into()).into_py(py)),
+            DynScalar::ComplexF64(s) => Ok(PyComplex::from_doubles(py, s.re, s.im).into_py(py)),
             DynScalar::Bool(s) => Ok(s.into_py(py)),
             DynScalar::String(s) => Ok(s.into_py(py)),
         }
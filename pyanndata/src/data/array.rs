@@ -1,10 +1,13 @@
-use crate::data::{FromPython, IntoPython};
+use crate::data::{FromPython, IntoPython, isinstance_of_awkward};
 
+use half::f16;
 use ndarray::ArrayD;
 use nalgebra_sparse::{CsrMatrix, CscMatrix};
-use pyo3::prelude::*;
-use anndata::data::{DynArray, DynCsrMatrix, DynCscMatrix, DynCsrNonCanonical, CsrNonCanonical};
-use numpy::{PyReadonlyArrayDyn, IntoPyArray};
+use pyo3::{exceptions::{PyTypeError, PyValueError}, prelude::*, types::{IntoPyDict, PyCapsule, PySlice}};
+use anndata::ArrayData;
+use anndata::data::{DynArray, DynCsrMatrix, DynCscMatrix, DynCsrNonCanonical, CsrNonCanonical, RaggedArray, RecordArray};
+use numpy::{PyArray, PyReadonlyArrayDyn, IntoPyArray, Complex32, Complex64};
+use std::sync::Arc;
 
 macro_rules! proc_py_numeric {
     ($dtype:expr, $data:expr, $ty_anno:tt) => {
@@ -41,6 +44,10 @@ macro_rules! proc_py_numeric {
                 let x: $ty_anno<u64> = $data;
                 x.into()
             }
+            "float16" => {
+                let x: $ty_anno<f16> = $data;
+                x.into()
+            }
             "float32" => {
                 let x: $ty_anno<f32> = $data;
                 x.into()
@@ -53,47 +60,51 @@ macro_rules! proc_py_numeric {
                 let x: $ty_anno<bool> = $data;
                 x.into()
             }
-            other => panic!("converting python type '{}' is not supported", other),
+            other => return Err(PyTypeError::new_err(format!("converting python type '{}' is not supported", other))),
         }
     };
 }
 
 impl FromPython<'_> for DynArray {
     fn from_python(ob: &PyAny) -> PyResult<Self> {
+        // Unicode and object arrays are stored as variable-length strings.
+        let kind = ob.getattr("dtype")?.getattr("kind")?.extract::<&str>()?;
+        if kind == "U" || kind == "O" {
+            let shape: Vec<usize> = ob.getattr("shape")?.extract()?;
+            let data: Vec<String> = ob.call_method0("ravel")?.call_method0("tolist")?.extract()?;
+            let arr = ArrayD::from_shape_vec(shape, data).map_err(|e|
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string())
+            )?;
+            return Ok(arr.into());
+        }
         let ty = ob.getattr("dtype")?.getattr("name")?.extract::<&str>()?;
-        let arr = proc_py_numeric!(ty, ob.extract::<PyReadonlyArrayDyn<_>>()?.to_owned_array(), ArrayD);
+        // Complex numbers are only supported in dense arrays.
+        let arr = match ty {
+            "complex64" => ob.extract::<PyReadonlyArrayDyn<Complex32>>()?.to_owned_array().into(),
+            "complex128" => ob.extract::<PyReadonlyArrayDyn<Complex64>>()?.to_owned_array().into(),
+            _ => proc_py_numeric!(ty, ob.extract::<PyReadonlyArrayDyn<_>>()?.to_owned_array(), ArrayD),
+        };
         Ok(arr)
     }
 }
 
+/// Read the indices or index pointers of a sparse matrix.
+fn extract_indices(indices: &PyAny) -> PyResult<Vec<usize>> {
+    fn to_usize<T: Copy + TryInto<usize> + std::fmt::Display>(x: &T) -> PyResult<usize> {
+        (*x).try_into().map_err(|_| PyValueError::new_err(format!("invalid index: {}", x)))
+    }
+    match indices.getattr("dtype")?.getattr("name")?.extract::<&str>()? {
+        "int32" => indices.extract::<PyReadonlyArrayDyn<i32>>()?.as_array().iter().map(to_usize).collect(),
+        "int64" => indices.extract::<PyReadonlyArrayDyn<i64>>()?.as_array().iter().map(to_usize).collect(),
+        other => Err(PyTypeError::new_err(format!("sparse matrix indices of type '{}' are not supported", other))),
+    }
+}
+
 impl FromPython<'_> for DynCsrMatrix {
     fn from_python(ob: &PyAny) -> PyResult<Self> {
-        fn extract_csr_indicies(indicies: &PyAny) -> PyResult<Vec<usize>> {
-            let res = match indicies
-                .getattr("dtype")?
-                .getattr("name")?
-                .extract::<&str>()?
-            {
-                "int32" => indicies
-                    .extract::<PyReadonlyArrayDyn<i32>>()?
-                    .as_array()
-                    .iter()
-                    .map(|x| (*x).try_into().unwrap())
-                    .collect(),
-                "int64" => indicies
-                    .extract::<PyReadonlyArrayDyn<i64>>()?
-                    .as_array()
-                    .iter()
-                    .map(|x| (*x).try_into().unwrap())
-                    .collect(),
-                other => panic!("CSR indicies type '{}' is not supported", other),
-            };
-            Ok(res)
-        }
-
         let shape: Vec<usize> = ob.getattr("shape")?.extract()?;
-        let indices = extract_csr_indicies(ob.getattr("indices")?)?;
-        let indptr = extract_csr_indicies(ob.getattr("indptr")?)?;
+        let indices = extract_indices(ob.getattr("indices")?)?;
+        let indptr = extract_indices(ob.getattr("indptr")?)?;
         let ty = ob.getattr("data")?.getattr("dtype")?.getattr("name")?.extract::<&str>()?;
 
         let csr = proc_py_numeric!(
@@ -103,42 +114,94 @@ impl FromPython<'_> for DynCsrMatrix {
                 shape[1],
                 indptr,
                 indices,
-                ob.getattr("data")?.extract::<PyReadonlyArrayDyn<_>>()?.to_vec().unwrap()
-            ).unwrap(),
+                ob.getattr("data")?.extract::<PyReadonlyArrayDyn<_>>()?.to_vec()?
+            ).map_err(|e| PyValueError::new_err(e.to_string()))?,
             CsrMatrix
         );
          Ok(csr)
     }
 }
 
-impl FromPython<'_> for DynCsrNonCanonical {
-    fn from_python(ob: &PyAny) -> PyResult<Self> {
-        fn extract_csr_indicies(indicies: &PyAny) -> PyResult<Vec<usize>> {
-            let res = match indicies
-                .getattr("dtype")?
-                .getattr("name")?
-                .extract::<&str>()?
-            {
-                "int32" => indicies
-                    .extract::<PyReadonlyArrayDyn<i32>>()?
-                    .as_array()
-                    .iter()
-                    .map(|x| (*x).try_into().unwrap())
-                    .collect(),
-                "int64" => indicies
-                    .extract::<PyReadonlyArrayDyn<i64>>()?
-                    .as_array()
-                    .iter()
-                    .map(|x| (*x).try_into().unwrap())
-                    .collect(),
-                other => panic!("CSR indicies type '{}' is not supported", other),
-            };
-            Ok(res)
+/// Values of sparse matrices whose duplicate entries can be summed.
+trait SumDuplicates: Copy {
+    fn sum(&mut self, other: Self);
+}
+
+macro_rules! impl_sum_duplicates {
+    ($($ty:ty),*) => {
+        $(impl SumDuplicates for $ty {
+            fn sum(&mut self, other: Self) {
+                *self += other;
+            }
+        })*
+    };
+}
+
+impl_sum_duplicates!(i8, i16, i32, i64, u8, u16, u32, u64, f16, f32, f64);
+
+impl SumDuplicates for bool {
+    fn sum(&mut self, other: Self) {
+        *self |= other;
+    }
+}
+
+/// Convert the coordinates and values of a COO matrix to a CSR matrix, sorting
+/// the entries by row then column and summing the values of duplicate entries.
+fn coo_to_csr<T: SumDuplicates>(
+    shape: &[usize],
+    rows: Vec<usize>,
+    cols: Vec<usize>,
+    data: Vec<T>,
+) -> PyResult<CsrMatrix<T>> {
+    let (nrows, ncols) = (shape[0], shape[1]);
+    let mut order: Vec<usize> = (0..data.len()).collect();
+    order.sort_unstable_by_key(|&i| (rows[i], cols[i]));
+    let mut indptr = vec![0; nrows + 1];
+    let mut indices = Vec::with_capacity(data.len());
+    let mut values: Vec<T> = Vec::with_capacity(data.len());
+    let mut last = None;
+    for i in order {
+        let (row, col) = (rows[i], cols[i]);
+        if last == Some((row, col)) {
+            values.last_mut().unwrap().sum(data[i]);
+        } else {
+            if row >= nrows || col >= ncols {
+                return Err(PyValueError::new_err(format!(
+                    "entry ({}, {}) is out of bounds for a matrix of shape {:?}", row, col, shape,
+                )));
+            }
+            indptr[row + 1] += 1;
+            indices.push(col);
+            values.push(data[i]);
+            last = Some((row, col));
         }
+    }
+    for i in 0..nrows {
+        indptr[i + 1] += indptr[i];
+    }
+    CsrMatrix::try_from_csr_data(nrows, ncols, indptr, indices, values)
+        .map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// Read a `scipy.sparse.coo_matrix` as a CSR matrix with the same data type.
+pub(crate) fn csr_from_coo(ob: &PyAny) -> PyResult<DynCsrMatrix> {
+    let shape: Vec<usize> = ob.getattr("shape")?.extract()?;
+    let rows = extract_indices(ob.getattr("row")?)?;
+    let cols = extract_indices(ob.getattr("col")?)?;
+    let ty = ob.getattr("data")?.getattr("dtype")?.getattr("name")?.extract::<&str>()?;
+    let csr = proc_py_numeric!(
+        ty,
+        coo_to_csr(&shape, rows, cols, ob.getattr("data")?.extract::<PyReadonlyArrayDyn<_>>()?.to_vec()?)?,
+        CsrMatrix
+    );
+    Ok(csr)
+}
 
+impl FromPython<'_> for DynCsrNonCanonical {
+    fn from_python(ob: &PyAny) -> PyResult<Self> {
         let shape: Vec<usize> = ob.getattr("shape")?.extract()?;
-        let indices = extract_csr_indicies(ob.getattr("indices")?)?;
-        let indptr = extract_csr_indicies(ob.getattr("indptr")?)?;
+        let indices = extract_indices(ob.getattr("indices")?)?;
+        let indptr = extract_indices(ob.getattr("indptr")?)?;
         let ty = ob.getattr("data")?.getattr("dtype")?.getattr("name")?.extract::<&str>()?;
 
         let csr = proc_py_numeric!(
@@ -148,7 +211,7 @@ impl FromPython<'_> for DynCsrNonCanonical {
                 shape[1],
                 indptr,
                 indices,
-                ob.getattr("data")?.extract::<PyReadonlyArrayDyn<_>>()?.to_vec().unwrap()
+                ob.getattr("data")?.extract::<PyReadonlyArrayDyn<_>>()?.to_vec()?
             ),
             CsrNonCanonical
         );
@@ -158,32 +221,9 @@ impl FromPython<'_> for DynCsrNonCanonical {
 
 impl FromPython<'_> for DynCscMatrix {
     fn from_python(ob: &PyAny) -> PyResult<Self> {
-        fn extract_csc_indicies(indicies: &PyAny) -> PyResult<Vec<usize>> {
-            let res = match indicies
-                .getattr("dtype")?
-                .getattr("name")?
-                .extract::<&str>()?
-            {
-                "int32" => indicies
-                    .extract::<PyReadonlyArrayDyn<i32>>()?
-                    .as_array()
-                    .iter()
-                    .map(|x| (*x).try_into().unwrap())
-                    .collect(),
-                "int64" => indicies
-                    .extract::<PyReadonlyArrayDyn<i64>>()?
-                    .as_array()
-                    .iter()
-                    .map(|x| (*x).try_into().unwrap())
-                    .collect(),
-                other => panic!("CSC indicies type '{}' is not supported", other),
-            };
-            Ok(res)
-        }
-
         let shape: Vec<usize> = ob.getattr("shape")?.extract()?;
-        let indices = extract_csc_indicies(ob.getattr("indices")?)?;
-        let indptr = extract_csc_indicies(ob.getattr("indptr")?)?;
+        let indices = extract_indices(ob.getattr("indices")?)?;
+        let indptr = extract_indices(ob.getattr("indptr")?)?;
         let ty = ob.getattr("data")?.getattr("dtype")?.getattr("name")?.extract::<&str>()?;
 
         let csc = proc_py_numeric!(
@@ -193,14 +233,66 @@ impl FromPython<'_> for DynCscMatrix {
                 shape[1],
                 indptr,
                 indices,
-                ob.getattr("data")?.extract::<PyReadonlyArrayDyn<_>>()?.to_vec().unwrap()
-            ).unwrap(),
+                ob.getattr("data")?.extract::<PyReadonlyArrayDyn<_>>()?.to_vec()?
+            ).map_err(|e| PyValueError::new_err(e.to_string()))?,
             CscMatrix
         );
          Ok(csc)
     }
 }
 
+/// Return a read-only NumPy view of a cached dense array, or `None` if the
+/// data is not a contiguous dense array of a numeric or boolean type, in which
+/// case it should be copied with `IntoPython` instead.
+///
+/// The view shares the buffer of `data` rather than copying it. A capsule
+/// holding `data` is set as the base of the view, so the buffer lives as long
+/// as the view, or any array derived from it, does, even if the cache is
+/// evicted or the element is written in the meantime. The buffer itself is
+/// never modified, as the cache replaces its data instead of writing to it;
+/// the view is read-only so that Python code cannot modify it either. Note
+/// that the memory held by views is not counted towards the cache budget once
+/// the data is evicted.
+pub(crate) fn cached_array_view(py: Python<'_>, data: Arc<ArrayData>) -> PyResult<Option<PyObject>> {
+    macro_rules! view {
+        ($arr:expr) => {{
+            let arr = $arr;
+            if !arr.is_standard_layout() {
+                return Ok(None);
+            }
+            let capsule = PyCapsule::new(py, data.clone(), None)?;
+            // SAFETY: the buffer is owned by the `Arc` in the capsule, which is
+            // the base of the view, and it is never modified or moved.
+            let view = unsafe { PyArray::borrow_from_array(arr, capsule) };
+            view.call_method("setflags", (), Some([("write", false)].into_py_dict(py)))?;
+            view.to_object(py)
+        }};
+    }
+    let array = match data.as_ref() {
+        ArrayData::Array(x) => x,
+        _ => return Ok(None),
+    };
+    let view = match array {
+        DynArray::I8(arr) => view!(arr),
+        DynArray::I16(arr) => view!(arr),
+        DynArray::I32(arr) => view!(arr),
+        DynArray::I64(arr) => view!(arr),
+        DynArray::U8(arr) => view!(arr),
+        DynArray::U16(arr) => view!(arr),
+        DynArray::U32(arr) => view!(arr),
+        DynArray::U64(arr) => view!(arr),
+        DynArray::Usize(arr) => view!(arr),
+        DynArray::F16(arr) => view!(arr),
+        DynArray::F32(arr) => view!(arr),
+        DynArray::F64(arr) => view!(arr),
+        DynArray::ComplexF32(arr) => view!(arr),
+        DynArray::ComplexF64(arr) => view!(arr),
+        DynArray::Bool(arr) => view!(arr),
+        DynArray::String(_) | DynArray::Categorical(_) => return Ok(None),
+    };
+    Ok(Some(view))
+}
+
 impl IntoPython for DynArray {
     fn into_python(self, py: Python<'_>) -> PyResult<PyObject> {
         let res = match self {
@@ -213,11 +305,25 @@ impl IntoPython for DynArray {
             DynArray::U32(arr) => arr.into_pyarray(py).to_object(py),
             DynArray::U64(arr) => arr.into_pyarray(py).to_object(py),
             DynArray::Usize(arr) => arr.into_pyarray(py).to_object(py),
+            DynArray::F16(arr) => arr.into_pyarray(py).to_object(py),
             DynArray::F32(arr) => arr.into_pyarray(py).to_object(py),
             DynArray::F64(arr) => arr.into_pyarray(py).to_object(py),
+            DynArray::ComplexF32(arr) => arr.into_pyarray(py).to_object(py),
+            DynArray::ComplexF64(arr) => arr.into_pyarray(py).to_object(py),
             DynArray::Bool(arr) => arr.into_pyarray(py).to_object(py),
-            DynArray::String(_) => todo!(),
-            DynArray::Categorical(_) => todo!(),
+            DynArray::String(arr) => {
+                let shape = arr.shape().to_vec();
+                let data: Vec<String> = arr.into_iter().collect();
+                let kwargs = [("dtype", "object")].into_py_dict(py);
+                py.import("numpy")?
+                    .getattr("array")?
+                    .call((data,), Some(kwargs))?
+                    .call_method1("reshape", (shape,))?
+                    .to_object(py)
+            }
+            DynArray::Categorical(_) => {
+                return Err(PyTypeError::new_err("converting categorical arrays to Python is not supported"))
+            }
         };
         Ok(res)
     }
@@ -253,10 +359,11 @@ impl IntoPython for DynCsrMatrix {
             DynCsrMatrix::U32(csr) => helper(csr, py),
             DynCsrMatrix::U64(csr) => helper(csr, py),
             DynCsrMatrix::Usize(csr) => helper(csr, py),
+            DynCsrMatrix::F16(csr) => helper(csr, py),
             DynCsrMatrix::F32(csr) => helper(csr, py),
             DynCsrMatrix::F64(csr) => helper(csr, py),
             DynCsrMatrix::Bool(csr) => helper(csr, py),
-            DynCsrMatrix::String(_) => todo!(),
+            DynCsrMatrix::String(_) => Err(PyTypeError::new_err("converting sparse matrices of strings to Python is not supported")),
         }
     }
 }
@@ -291,10 +398,11 @@ impl IntoPython for DynCsrNonCanonical {
             DynCsrNonCanonical::U32(csr) => helper(csr, py),
             DynCsrNonCanonical::U64(csr) => helper(csr, py),
             DynCsrNonCanonical::Usize(csr) => helper(csr, py),
+            DynCsrNonCanonical::F16(csr) => helper(csr, py),
             DynCsrNonCanonical::F32(csr) => helper(csr, py),
             DynCsrNonCanonical::F64(csr) => helper(csr, py),
             DynCsrNonCanonical::Bool(csr) => helper(csr, py),
-            DynCsrNonCanonical::String(_) => todo!(),
+            DynCsrNonCanonical::String(_) => Err(PyTypeError::new_err("converting sparse matrices of strings to Python is not supported")),
         }
     }
 }
@@ -330,10 +438,123 @@ impl IntoPython for DynCscMatrix {
             DynCscMatrix::U32(csc) => helper(csc, py),
             DynCscMatrix::U64(csc) => helper(csc, py),
             DynCscMatrix::Usize(csc) => helper(csc, py),
+            DynCscMatrix::F16(csc) => helper(csc, py),
             DynCscMatrix::F32(csc) => helper(csc, py),
             DynCscMatrix::F64(csc) => helper(csc, py),
             DynCscMatrix::Bool(csc) => helper(csc, py),
-            DynCscMatrix::String(_) => todo!(),
+            DynCscMatrix::String(_) => Err(PyTypeError::new_err("converting sparse matrices of strings to Python is not supported")),
+        }
+    }
+}
+
+impl FromPython<'_> for RaggedArray {
+    /// Convert an `awkward.Array` or a list of variable-length sequences.
+    fn from_python(ob: &PyAny) -> PyResult<Self> {
+        let py = ob.py();
+        let numpy = py.import("numpy")?;
+        let (values, counts): (&PyAny, Vec<usize>) = if isinstance_of_awkward(ob)? {
+            let ak = py.import("awkward")?;
+            let values = ak.call_method1("to_numpy", (ak.call_method1("flatten", (ob,))?,))?;
+            let counts = ak.call_method1("to_numpy", (ak.call_method1("num", (ob,))?,))?;
+            (values, counts.call_method0("tolist")?.extract()?)
+        } else {
+            let mut rows = Vec::new();
+            let mut counts = Vec::new();
+            for row in ob.iter()? {
+                let row = numpy.call_method1("asarray", (row?,))?;
+                let ndim: usize = row.getattr("ndim")?.extract()?;
+                if ndim != 1 {
+                    return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                        "each row of a ragged array must be a one-dimensional sequence"
+                    ));
+                }
+                let n: usize = row.len()?;
+                if n > 0 {
+                    rows.push(row);
+                }
+                counts.push(n);
+            }
+            // Empty rows are skipped so that they do not change the dtype.
+            let values = if rows.is_empty() {
+                numpy.call_method1("array", (Vec::<f64>::new(),))?
+            } else {
+                numpy.call_method1("concatenate", (rows,))?
+            };
+            (values, counts)
+        };
+        let mut offsets = Vec::with_capacity(counts.len() + 1);
+        offsets.push(0);
+        counts.into_iter().for_each(|n| offsets.push(offsets[offsets.len() - 1] + n));
+        Ok(RaggedArray::new(DynArray::from_python(values)?, offsets)?)
+    }
+}
+
+impl IntoPython for RaggedArray {
+    /// Return an `awkward.Array` if `awkward` is installed, and a list of
+    /// numpy arrays otherwise.
+    fn into_python(self, py: Python<'_>) -> PyResult<PyObject> {
+        let (values, offsets) = self.into_parts();
+        let values = values.into_python(py)?;
+        if let Ok(ak) = py.import("awkward") {
+            let counts: Vec<usize> = offsets.windows(2).map(|w| w[1] - w[0]).collect();
+            Ok(ak.call_method1("unflatten", (values, counts))?.to_object(py))
+        } else {
+            let rows = offsets.windows(2).map(|w| {
+                let slice = PySlice::new(py, w[0] as isize, w[1] as isize, 1);
+                values.as_ref(py).get_item(slice).map(|x| x.to_object(py))
+            }).collect::<PyResult<Vec<_>>>()?;
+            Ok(rows.to_object(py))
         }
     }
-}
\ No newline at end of file
+}
+
+impl FromPython<'_> for RecordArray {
+    /// Convert a one-dimensional NumPy structured array. Bytes fields are
+    /// decoded as ASCII strings.
+    fn from_python(ob: &PyAny) -> PyResult<Self> {
+        let ndim: usize = ob.getattr("ndim")?.extract()?;
+        if ndim != 1 {
+            return Err(PyValueError::new_err(
+                format!("structured arrays must be one-dimensional, found {} dimensions", ndim)
+            ));
+        }
+        let mut fields = Vec::new();
+        for name in ob.getattr("dtype")?.getattr("names")?.iter()? {
+            let name = name?;
+            let mut values = ob.get_item(name)?;
+            if values.getattr("dtype")?.getattr("kind")?.extract::<&str>()? == "S" {
+                values = values.call_method1("astype", ("U",))?;
+            }
+            fields.push((name.extract::<String>()?, DynArray::from_python(values)?));
+        }
+        Ok(RecordArray::new(fields)?)
+    }
+}
+
+impl IntoPython for RecordArray {
+    /// Return a NumPy structured array. String fields have the fixed-length
+    /// unicode type of the longest string.
+    fn into_python(self, py: Python<'_>) -> PyResult<PyObject> {
+        let numpy = py.import("numpy")?;
+        let len = self.len();
+        let fields = self.into_fields().into_iter().map(|(name, values)| {
+            let values = match values {
+                DynArray::String(x) => {
+                    let kwargs = [("dtype", "str")].into_py_dict(py);
+                    let data: Vec<String> = x.into_iter().collect();
+                    numpy.getattr("array")?.call((data,), Some(kwargs))?.to_object(py)
+                }
+                x => x.into_python(py)?,
+            };
+            Ok((name, values))
+        }).collect::<PyResult<Vec<_>>>()?;
+        let dtype = fields.iter()
+            .map(|(name, values)| Ok((name.as_str(), values.getattr(py, "dtype")?)))
+            .collect::<PyResult<Vec<_>>>()?;
+        let records = numpy.call_method1("empty", (len, dtype))?;
+        for (name, values) in fields {
+            records.set_item(name, values)?;
+        }
+        Ok(records.to_object(py))
+    }
+}
@@ -1,6 +1,8 @@
-use super::{isinstance_of_pandas, IntoPython};
+use super::{isinstance_of_pandas, FromPython, IntoPython};
 
 use std::ops::Deref;
+use anndata::data::{DataFrameIndex, DynArray};
+use anndata::data::dataframe::{is_categorical_ordered, set_categorical_ordered};
 use arrow::ffi;
 use polars::prelude::*;
 use polars_arrow::export::arrow;
@@ -8,7 +10,19 @@ use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
 use pyo3::{ffi::Py_uintptr_t, PyAny, PyObject, PyResult};
 
-pub struct PyDataFrame(DataFrame);
+/// A dataframe converted from Python. If it was a pandas dataframe whose index
+/// is not the default range index, the index is kept as well.
+pub struct PyDataFrame(DataFrame, Option<DataFrameIndex>);
+
+impl PyDataFrame {
+    pub fn index(&self) -> Option<&DataFrameIndex> {
+        self.1.as_ref()
+    }
+
+    pub fn into_parts(self) -> (DataFrame, Option<DataFrameIndex>) {
+        (self.0, self.1)
+    }
+}
 
 impl Deref for PyDataFrame {
     type Target = DataFrame;
@@ -20,7 +34,7 @@ impl Deref for PyDataFrame {
 
 impl From<DataFrame> for PyDataFrame {
     fn from(value: DataFrame) -> Self {
-        PyDataFrame(value)
+        PyDataFrame(value, None)
     }
 }
 
@@ -33,15 +47,75 @@ impl From<PyDataFrame> for DataFrame {
 impl<'py> FromPyObject<'py> for PyDataFrame {
     fn extract(ob: &'py PyAny) -> PyResult<Self> {
         let py = ob.py();
-        let df = if isinstance_of_pandas(py, ob)? {
-            py.import("polars")?.call_method1("from_pandas", (ob, ))?
-        } else if ob.is_instance_of::<pyo3::types::PyDict>() {
+        if isinstance_of_pandas(py, ob)? {
+            let pd_index = ob.getattr("index")?;
+            let index = if is_default_index(pd_index)? {
+                None
+            } else {
+                Some(to_index(pd_index)?)
+            };
+            return Ok(PyDataFrame(from_pandas(ob)?, index));
+        }
+        let df = if ob.is_instance_of::<pyo3::types::PyDict>() {
             py.import("polars")?.call_method1("from_dict", (ob, ))?
         } else {
             ob
         };
-        Ok(to_rust_df(ob.py(), df)?.into())
+        Ok(PyDataFrame(to_rust_df(py, df)?, None))
+    }
+}
+
+/// Convert a pandas dataframe, ignoring its index. Ordered categorical columns,
+/// whose flag is dropped by polars, are marked as ordered.
+pub fn from_pandas(df: &PyAny) -> PyResult<DataFrame> {
+    let py = df.py();
+    let mut ordered = Vec::new();
+    for name in df.getattr("columns")?.iter()? {
+        let name = name?;
+        if is_ordered_pandas(df.get_item(name)?) {
+            ordered.push(name.str()?.to_string());
+        }
+    }
+    let mut rust_df = to_rust_df(py, py.import("polars")?.call_method1("from_pandas", (df, ))?)?;
+    for name in ordered {
+        let column = set_categorical_ordered(rust_df.column(&name).map_err(to_py_err)?, true)?;
+        rust_df.replace(&name, column).map_err(to_py_err)?;
     }
+    Ok(rust_df)
+}
+
+/// Whether a pandas series is an ordered categorical.
+fn is_ordered_pandas(series: &PyAny) -> bool {
+    series
+        .getattr("dtype")
+        .and_then(|dtype| dtype.getattr("ordered"))
+        .and_then(|x| x.extract::<bool>())
+        .unwrap_or(false)
+}
+
+fn to_py_err(e: PolarsError) -> PyErr {
+    PyValueError::new_err(format!("{}", e))
+}
+
+/// Whether a pandas index is the default `RangeIndex`, i.e., `0, 1, 2, ...`.
+fn is_default_index(index: &PyAny) -> PyResult<bool> {
+    let range_index = index.py().import("pandas")?.getattr("RangeIndex")?;
+    Ok(index.is_instance(range_index)?
+        && index.getattr("start")?.extract::<i64>()? == 0
+        && index.getattr("step")?.extract::<i64>()? == 1)
+}
+
+/// Convert a sequence of names to an index. Names that are not strings, e.g.,
+/// integer barcodes, are converted through numpy so that their data type is kept.
+pub fn to_index(names: &PyAny) -> anyhow::Result<DataFrameIndex> {
+    let strings = names
+        .iter()
+        .and_then(|iter| iter.map(|x| x?.extract::<String>()).collect::<PyResult<Vec<_>>>());
+    if let Ok(strings) = strings {
+        return Ok(strings.into());
+    }
+    let array = names.py().import("numpy")?.call_method1("asarray", (names,))?;
+    DynArray::from_python(array)?.try_into()
 }
 
 impl IntoPy<PyObject> for PyDataFrame {
@@ -50,6 +124,68 @@ impl IntoPy<PyObject> for PyDataFrame {
     }
 }
 
+/// Convert a dataframe to a pandas dataframe. Integer and boolean columns with
+/// missing values become pandas nullable columns, e.g., `Int64`, instead of
+/// floats and objects. Missing values of string columns become `NaN`, as in
+/// the object columns created by pandas. Ordered categorical columns become
+/// ordered pandas categoricals.
+pub fn to_pandas(py: Python<'_>, df: DataFrame) -> PyResult<PyObject> {
+    let nullable: Vec<(String, &str)> = df
+        .get_columns()
+        .iter()
+        .filter(|x| x.null_count() > 0)
+        .filter_map(|x| pandas_nullable_dtype(x.dtype()).map(|ty| (x.name().to_string(), ty)))
+        .collect();
+    let strings: Vec<(String, Vec<PyObject>)> = df
+        .get_columns()
+        .iter()
+        .filter(|x| x.null_count() > 0 && x.dtype() == &DataType::Utf8)
+        .map(|x| {
+            let values = x.utf8().unwrap().into_iter()
+                .map(|v| v.map_or(f64::NAN.into_py(py), |v| v.into_py(py)))
+                .collect();
+            (x.name().to_string(), values)
+        })
+        .collect();
+    let ordered: Vec<String> = df
+        .get_columns()
+        .iter()
+        .filter(|x| is_categorical_ordered(x))
+        .map(|x| x.name().to_string())
+        .collect();
+    let df = PyDataFrame::from(df).into_py(py);
+    let pandas_df = df.call_method0(py, "to_pandas")?;
+    for (name, dtype) in nullable {
+        let values = df.as_ref(py).get_item(name.as_str())?.call_method0("to_list")?;
+        let column = py.import("pandas")?.call_method1("array", (values, dtype))?;
+        pandas_df.as_ref(py).set_item(name, column)?;
+    }
+    for (name, values) in strings {
+        let column = py.import("pandas")?.call_method1("array", (values, "object"))?;
+        pandas_df.as_ref(py).set_item(name, column)?;
+    }
+    for name in ordered {
+        let column = pandas_df.as_ref(py).get_item(name.as_str())?.getattr("cat")?.call_method0("as_ordered")?;
+        pandas_df.as_ref(py).set_item(name, column)?;
+    }
+    Ok(pandas_df)
+}
+
+fn pandas_nullable_dtype(dtype: &DataType) -> Option<&'static str> {
+    match dtype {
+        DataType::Int8 => Some("Int8"),
+        DataType::Int16 => Some("Int16"),
+        DataType::Int32 => Some("Int32"),
+        DataType::Int64 => Some("Int64"),
+        DataType::UInt8 => Some("UInt8"),
+        DataType::UInt16 => Some("UInt16"),
+        DataType::UInt32 => Some("UInt32"),
+        DataType::UInt64 => Some("UInt64"),
+        DataType::Boolean => Some("boolean"),
+        _ => None,
+    }
+}
+
 pub struct PySeries(Series);
 
 impl From<Series> for PySeries {
@@ -67,7 +203,12 @@ impl From<PySeries> for Series {
 impl<'py> FromPyObject<'py> for PySeries {
     fn extract(ob: &'py PyAny) -> PyResult<Self> {
         let s = ob.py().import("polars")?.call_method1("Series", (ob, ))?;
-        to_rust_series(s).map(Into::into)
+        let series = to_rust_series(s)?;
+        if is_ordered_pandas(ob) {
+            Ok(set_categorical_ordered(&series, true)?.into())
+        } else {
+            Ok(series.into())
+        }
     }
 }
 
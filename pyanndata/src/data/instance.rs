@@ -4,8 +4,7 @@ pub fn isinstance_of_csr<'py>(py: Python<'py>, obj: &'py PyAny) -> PyResult<bool
     obj.is_instance(
         py.import("scipy.sparse.csr")?
             .getattr("csr_matrix")?
-            .downcast::<PyType>()
-            .unwrap(),
+            .downcast::<PyType>()?,
     )
 }
 
@@ -13,8 +12,15 @@ pub fn isinstance_of_csc<'py>(py: Python<'py>, obj: &'py PyAny) -> PyResult<bool
     obj.is_instance(
         py.import("scipy.sparse.csc")?
             .getattr("csc_matrix")?
-            .downcast::<PyType>()
-            .unwrap(),
+            .downcast::<PyType>()?,
+    )
+}
+
+pub fn isinstance_of_coo<'py>(py: Python<'py>, obj: &'py PyAny) -> PyResult<bool> {
+    obj.is_instance(
+        py.import("scipy.sparse")?
+            .getattr("coo_matrix")?
+            .downcast::<PyType>()?,
     )
 }
 
@@ -22,8 +28,7 @@ pub fn isinstance_of_arr<'py>(py: Python<'py>, obj: &'py PyAny) -> PyResult<bool
     obj.is_instance(
         py.import("numpy")?
             .getattr("ndarray")?
-            .downcast::<PyType>()
-            .unwrap(),
+            .downcast::<PyType>()?,
     )
 }
 
@@ -31,8 +36,7 @@ pub fn isinstance_of_pyanndata<'py>(py: Python<'py>, obj: &'py PyAny) -> PyResul
     obj.is_instance(
         py.import("anndata")?
             .getattr("AnnData")?
-            .downcast::<PyType>()
-            .unwrap(),
+            .downcast::<PyType>()?,
     )
 }
 
@@ -40,8 +44,7 @@ pub fn isinstance_of_pandas<'py>(py: Python<'py>, obj: &'py PyAny) -> PyResult<b
     obj.is_instance(
         py.import("pandas")?
             .getattr("DataFrame")?
-            .downcast::<PyType>()
-            .unwrap(),
+            .downcast::<PyType>()?,
     )
 }
 
@@ -49,11 +52,17 @@ pub fn isinstance_of_polars<'py>(py: Python<'py>, obj: &'py PyAny) -> PyResult<b
     obj.is_instance(
         py.import("polars")?
             .getattr("DataFrame")?
-            .downcast::<PyType>()
-            .unwrap(),
+            .downcast::<PyType>()?,
     )
 }
 
+/// Check the module of the type instead of importing `awkward`, which is an
+/// optional dependency.
+pub fn isinstance_of_awkward<'py>(obj: &'py PyAny) -> PyResult<bool> {
+    let module: &str = obj.get_type().getattr("__module__")?.extract()?;
+    Ok(module.split('.').next() == Some("awkward"))
+}
+
 pub fn is_list_of_bools<'py>(py: Python<'py>, obj: &'py PyAny) -> PyResult<bool> {
     if obj.is_instance_of::<pyo3::types::PyList>() {
         Ok(obj.extract::<Vec<PyObject>>()?.into_iter().all(|x| {
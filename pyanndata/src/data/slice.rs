@@ -1,6 +1,6 @@
 use crate::data::instance::*;
 
-use pyo3::prelude::*;
+use pyo3::{exceptions::{PyIndexError, PyTypeError, PyValueError}, prelude::*};
 use anndata::data::{Shape, SelectInfo, SelectInfoElem};
 
 pub fn to_select_info(ob: &PyAny, shape: &Shape) -> PyResult<SelectInfo> {
@@ -13,55 +13,69 @@ pub fn to_select_info(ob: &PyAny, shape: &Shape) -> PyResult<SelectInfo> {
             .map(|(x, len)| to_select_elem(x?, *len))
             .collect()
     } else {
-        todo!()
+        Err(PyTypeError::new_err("the subscript must be a tuple with one element per axis, or `...`"))
     }
 }
 
 pub fn to_select_elem(ob: &PyAny, length: usize) -> PyResult<SelectInfoElem> {
     let py = ob.py();
     let select = if let Ok(slice) = ob.downcast::<pyo3::types::PySlice>() {
+        // Python slices step from `start` towards `stop`, while a negative step
+        // in ndarray traverses `start..end` backwards from `end - 1`.
         let s = slice.indices(length as i64)?;
-        ndarray::Slice { 
-            start: s.start,
-            end: Some(s.stop),
+        let (start, end) = if s.slicelength == 0 {
+            (0, 0)
+        } else if s.step > 0 {
+            (s.start, s.stop)
+        } else {
+            (s.start + s.step * (s.slicelength - 1), s.start + 1)
+        };
+        ndarray::Slice {
+            start,
+            end: Some(end),
             step: s.step,
         }.into()
     } else if is_none_slice(py, ob)? {
         SelectInfoElem::full()
     } else if ob.is_instance_of::<pyo3::types::PyInt>() {
-        ob.extract::<usize>()?.into()
+        to_index(ob.extract()?, length)?.into()
     } else if isinstance_of_arr(py, ob)? && ob.getattr("dtype")?.getattr("name")?.extract::<&str>()? == "bool" {
         let arr = ob
             .extract::<numpy::PyReadonlyArray1<bool>>()?;
-        if arr.len() == length {
-            boolean_mask_to_indices(arr.as_array().into_iter().map(|x| *x)).into()
-        } else {
-            panic!("boolean mask dimension mismatched")
-        }
+        to_mask(arr.as_array().to_vec(), length)?
     } else {
         let boolean_mask: PyResult<Vec<bool>> =
-            ob.iter()?.map(|x| x.unwrap().extract()).collect();
+            ob.iter()?.map(|x| x?.extract()).collect();
         match boolean_mask {
             Ok(mask) => {
-                if mask.len() == length {
-                    boolean_mask_to_indices(mask.into_iter()).into()
-                } else if mask.len() == 0 {
-                    Vec::new().into()
+                if mask.len() == 0 {
+                    Vec::<usize>::new().into()
                 } else {
-                    panic!("boolean mask dimension mismatched")
+                    to_mask(mask, length)?
                 }
             }
-            _ => ob.iter()?.map(|x| x.unwrap().extract()).collect::<PyResult<Vec<usize>>>()?.into(),
+            _ => ob.iter()?.map(|x| to_index(x?.extract()?, length)).collect::<PyResult<Vec<usize>>>()?.into(),
         }
     };
     Ok(select)
 }
 
-fn boolean_mask_to_indices<I>(iter: I) -> Vec<usize>
-where
-    I: Iterator<Item = bool>,
-{
-    iter.enumerate()
-        .filter_map(|(i, x)| if x { Some(i) } else { None })
-        .collect()
+/// Resolve a possibly negative index against an axis of length `length`.
+fn to_index(i: isize, length: usize) -> PyResult<usize> {
+    let resolved = if i < 0 { length.checked_sub(i.unsigned_abs()) } else { Some(i as usize) };
+    resolved.filter(|x| *x < length).ok_or_else(||
+        PyIndexError::new_err(format!("index {} is out of bounds for axis with size {}", i, length))
+    )
+}
+
+fn to_mask(mask: Vec<bool>, length: usize) -> PyResult<SelectInfoElem> {
+    if mask.len() == length {
+        Ok(SelectInfoElem::Mask(mask))
+    } else {
+        Err(PyValueError::new_err(format!(
+            "boolean mask of length {} does not match the axis length {}",
+            mask.len(),
+            length
+        )))
+    }
 }
\ No newline at end of file
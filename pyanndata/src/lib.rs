@@ -2,8 +2,8 @@ pub mod anndata;
 pub mod data;
 pub mod container;
 
-pub use crate::anndata::{AnnData, AnnDataSet, PyAnnData, read, read_mtx, read_dataset};
+pub use crate::anndata::{AnnData, AnnDataSet, PyAnnData, read, read_zarr, read_mtx, read_csv, read_loom, read_10x_h5, read_dataset};
 pub use crate::container::{
     PyAxisArrays, PyDataFrameElem, PyElem, PyElemCollection, PyArrayElem,
-    PyChunkedArray,
+    PyChunkedArray, PyChunkedArrayWithObs,
 };
@@ -8,16 +8,16 @@ use std::fs::File;
 use std::path::Path;
 
 /// Determine if a file is gzipped.
-pub(crate) fn is_gzipped<P: AsRef<Path>>(file: P) -> bool {
-    MultiGzDecoder::new(File::open(file).unwrap())
+pub(crate) fn is_gzipped<P: AsRef<Path>>(file: P) -> std::io::Result<bool> {
+    Ok(MultiGzDecoder::new(File::open(file)?)
         .header()
-        .is_some()
+        .is_some())
 }
 
-pub(crate) fn open_file<P: AsRef<Path>>(file: P) -> Box<dyn std::io::Read> {
-    if is_gzipped(&file) {
-        Box::new(MultiGzDecoder::new(File::open(file).unwrap()))
+pub(crate) fn open_file<P: AsRef<Path>>(file: P) -> std::io::Result<Box<dyn std::io::Read>> {
+    if is_gzipped(&file)? {
+        Ok(Box::new(MultiGzDecoder::new(File::open(file)?)))
     } else {
-        Box::new(File::open(file).unwrap())
+        Ok(Box::new(File::open(file)?))
     }
 }
\ No newline at end of file
@@ -7,12 +7,16 @@ use pyo3::{
 
 #[pymodule]
 fn _anndata_rs(_py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_class::<AnnData>().unwrap();
-    m.add_class::<AnnDataSet>().unwrap();
+    m.add_class::<AnnData>()?;
+    m.add_class::<AnnDataSet>()?;
 
     m.add_function(wrap_pyfunction!(read, m)?)?;
+    m.add_function(wrap_pyfunction!(read_zarr, m)?)?;
     m.add_function(wrap_pyfunction!(read_dataset, m)?)?;
     m.add_function(wrap_pyfunction!(read_mtx, m)?)?;
+    m.add_function(wrap_pyfunction!(read_csv, m)?)?;
+    m.add_function(wrap_pyfunction!(read_loom, m)?)?;
+    m.add_function(wrap_pyfunction!(read_10x_h5, m)?)?;
     /*
     m.add_class::<StackedAnnData>().unwrap();
     m.add_class::<element::PyElemCollection>().unwrap();
@@ -23,8 +27,6 @@ fn _anndata_rs(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<element::PyStackedMatrixElem>().unwrap();
     m.add_class::<element::PyStackedAxisArrays>().unwrap();
     m.add_class::<element::PyStackedDataFrame>().unwrap();
-
-    m.add_function(wrap_pyfunction!(read_csv, m)?)?;
     */
     Ok(())
 }
@@ -1,5 +1,6 @@
 from anndata_rs import AnnData, AnnDataSet, read
 
+import h5py
 import math
 import numpy as np
 import pandas as pd
@@ -71,6 +72,26 @@ def test_assign_arrays(x, tmp_path):
     x_ = adata.uns['x']
     np.testing.assert_array_equal(x_, x)
 
+@given(x=arrays(
+    np.bool_,
+    array_shapes(min_dims=2, max_dims=2, min_side=0, max_side=5),
+))
+@settings(deadline=None, suppress_health_check = [HealthCheck.function_scoped_fixture])
+def test_assign_bool(x, tmp_path):
+    adata = AnnData(filename = h5ad(tmp_path))
+    adata.X = x
+    np.testing.assert_array_equal(x, adata.X[:])
+
+    adata.obsm["mask"] = x
+    np.testing.assert_array_equal(x, adata.obsm["mask"])
+
+    adata.layers["mask"] = x
+    np.testing.assert_array_equal(x, adata.layers["mask"][:])
+
+    mask = x[:, 0] if x.shape[1] > 0 else np.zeros(x.shape[0], dtype=bool)
+    adata.obs = pl.DataFrame({"is_doublet": mask})
+    np.testing.assert_array_equal(mask, adata.obs["is_doublet"])
+
 @given(x=st.floats())
 @settings(deadline=None, suppress_health_check = [HealthCheck.function_scoped_fixture])
 def test_assign_floats(x, tmp_path):
@@ -106,6 +127,748 @@ def test_creation(tmp_path):
     assert adata.var_names == var_names
     assert adata.to_memory().var_names.to_list() == var_names
 
+def test_categorical(tmp_path):
+    cell_type = pd.Categorical(["T", "B", "T"], categories=["T", "NK", "B"])
+    adata = AnnData(filename=h5ad(tmp_path), X=np.zeros((3, 2)))
+    adata.obs = pd.DataFrame({"cell_type": cell_type})
+
+    obs = adata.obs.to_pandas()
+    assert obs["cell_type"].dtype == "category"
+    assert list(obs["cell_type"].cat.categories) == ["T", "NK", "B"]
+    assert list(obs["cell_type"]) == ["T", "B", "T"]
+
+    # The categories are kept when the file is read by anndata.
+    adata_py = adata.to_memory()
+    assert list(adata_py.obs["cell_type"].cat.categories) == ["T", "NK", "B"]
+
+def test_categorical_ordered(tmp_path):
+    level = pd.Categorical(["low", "high", "low"], categories=["low", "mid", "high"], ordered=True)
+    adata = AnnData(filename=h5ad(tmp_path), X=np.zeros((3, 2)), obs=pd.DataFrame({"level": level}))
+    adata.obs["plain"] = pd.Series(pd.Categorical(["a", "b", "a"]))
+    adata.obs["ordered"] = pd.Series(level)
+
+    adata_py = adata.to_memory()
+    for name in ["level", "ordered"]:
+        column = adata_py.obs[name]
+        assert column.cat.ordered
+        assert list(column.cat.categories) == ["low", "mid", "high"]
+        assert list(column) == ["low", "high", "low"]
+    assert not adata_py.obs["plain"].cat.ordered
+
+    # The flag survives a write from memory and a second read.
+    adata2 = AnnData(filename=h5ad(tmp_path), X=adata_py.X, obs=adata_py.obs)
+    assert adata2.to_memory().obs["level"].cat.ordered
+
+def test_to_memory_select(tmp_path):
+    x = np.arange(6, dtype=np.float64).reshape(3, 2)
+    adata = AnnData(filename=h5ad(tmp_path), X=x)
+    adata.obs = pd.DataFrame({"a": [1, 2, 3]})
+    adata.obs_names = ["c1", "c2", "c3"]
+    adata.obsm["pca"] = x
+    adata.obsm["umap"] = x
+    adata.layers["counts"] = csr_matrix(x)
+    adata.uns["note"] = "hello"
+
+    adata_py = adata.to_memory()
+    assert set(adata_py.layers.keys()) == {"counts"}
+
+    adata_py = adata.to_memory(X=False, obsm=["umap"], layers=False, uns=False)
+    assert adata_py.X is None
+    assert list(adata_py.obs_names) == ["c1", "c2", "c3"]
+    assert list(adata_py.obs["a"]) == [1, 2, 3]
+    assert list(adata_py.obsm.keys()) == ["umap"]
+    np.testing.assert_array_equal(adata_py.obsm["umap"], x)
+    assert len(adata_py.layers) == 0
+    assert len(adata_py.uns) == 0
+
+    adata_py = adata.to_memory(obs=False)
+    assert adata_py.obs.shape == (3, 0)
+    np.testing.assert_array_equal(adata_py.X, x)
+
+    with pytest.raises(KeyError, match="not in obsm"):
+        adata.to_memory(obsm=["tsne"])
+
+def test_to_memory_pairwise(tmp_path):
+    adata = AnnData(filename=h5ad(tmp_path), X=np.ones((3, 2)))
+    adata.obsp["distances"] = csr_matrix(np.eye(3))
+    adata.varp["corr"] = np.ones((2, 2))
+
+    adata_py = adata.to_memory()
+    assert adata_py.obsp["distances"].shape == (3, 3)
+    np.testing.assert_array_equal(adata_py.obsp["distances"].toarray(), np.eye(3))
+    np.testing.assert_array_equal(adata_py.varp["corr"], np.ones((2, 2)))
+
+def test_x_sums(tmp_path):
+    x = np.array([[1, 0, 2], [0, 0, 0], [3, 4, 0]], dtype=np.int32)
+    for X in [x, csr_matrix(x)]:
+        adata = AnnData(filename=h5ad(tmp_path), X=X)
+        np.testing.assert_array_equal(adata.x_row_sums(), [3, 0, 7])
+        np.testing.assert_array_equal(adata.x_col_sums(), [4, 4, 2])
+        np.testing.assert_array_equal(adata.x_nnz_per_row(), [2, 0, 2])
+        np.testing.assert_array_equal(adata.x_nnz_per_col(), [2, 1, 1])
+
+def test_to_df(tmp_path):
+    x = np.arange(6, dtype=np.float64).reshape(3, 2)
+    adata = AnnData(filename=h5ad(tmp_path), X=x)
+    adata.obs_names = ["c1", "c2", "c3"]
+    adata.var_names = ["g1", "g2"]
+    adata.layers["counts"] = csr_matrix(x * 2)
+
+    df = adata.to_df()
+    assert list(df.index) == ["c1", "c2", "c3"]
+    assert list(df.columns) == ["g1", "g2"]
+    np.testing.assert_array_equal(df.to_numpy(), x)
+
+    with pytest.warns(UserWarning, match="densifying"):
+        df = adata.to_df(layer="counts")
+    np.testing.assert_array_equal(df.to_numpy(), x * 2)
+
+    with pytest.raises(Exception):
+        adata.to_df(layer="missing")
+
+def test_chunked(tmp_path):
+    x = np.arange(100 * 4).reshape(100, 4)
+    adata = AnnData(filename=h5ad(tmp_path), X=x)
+    adata.obsm["X_pca"] = x * 2
+    adata.layers["counts"] = csr_matrix(x * 3)
+
+    def collect(chunks):
+        result = []
+        for data, start, end in chunks:
+            assert data.shape[0] == end - start
+            result.append(data.todense() if sp.issparse(data) else data)
+        return np.vstack(result)
+
+    np.testing.assert_array_equal(collect(adata.chunked_X(30)), x)
+    np.testing.assert_array_equal(collect(adata.chunked_obsm("X_pca", 30)), x * 2)
+    np.testing.assert_array_equal(collect(adata.chunked_layer("counts", 30)), x * 3)
+    assert adata.chunked_obsm("X_pca", 30).n_chunks() == 4
+
+    with pytest.raises(Exception):
+        adata.chunked_layer("missing")
+
+def test_get_rows(tmp_path):
+    x = np.arange(10 * 4).reshape(10, 4)
+    adata = AnnData(filename=h5ad(tmp_path), X=x)
+    np.testing.assert_array_equal(adata.X.get_rows(3, 7), x[3:7])
+    assert adata.X.get_rows(5, 5).shape == (0, 4)
+
+    adata.X = csr_matrix(x)
+    rows = adata.X.get_rows(3, 7)
+    assert sp.issparse(rows)
+    np.testing.assert_array_equal(rows.todense(), x[3:7])
+
+    with pytest.raises(Exception):
+        adata.X.get_rows(7, 3)
+    with pytest.raises(Exception):
+        adata.X.get_rows(5, 11)
+
+def test_cached_view(tmp_path):
+    x = np.arange(10 * 4, dtype=np.float64).reshape(10, 4)
+    adata = AnnData(filename=h5ad(tmp_path), X=x)
+    uncached = adata.X[:]
+    assert uncached.flags.writeable
+
+    adata.X.enable_cache()
+    adata.X[:]
+    view = adata.X[:]
+    assert not view.flags.writeable
+    assert np.shares_memory(view, adata.X[:])
+    assert not np.shares_memory(view, adata.X[2:5])
+    np.testing.assert_array_equal(view, x)
+
+    adata.X = x + 1
+    np.testing.assert_array_equal(view, x)
+    np.testing.assert_array_equal(adata.X[:], x + 1)
+
+def test_make_unique(tmp_path):
+    adata = AnnData(filename=h5ad(tmp_path), X=np.zeros((4, 2)))
+    adata.obs_names = ["a", "b", "a", "a"]
+    adata.obs_names_make_unique()
+    assert adata.obs_names == ["a", "b", "a-1", "a-2"]
+
+    with pytest.raises(Exception):
+        adata.set_var_names(["g", "g"], check_unique=True)
+    adata.set_var_names(["g", "g"])
+    adata.var_names_make_unique()
+    assert adata.var_names == ["g", "g-1"]
+
+def test_structured_array(tmp_path):
+    adata = AnnData(filename=h5ad(tmp_path))
+    spatial = np.array(
+        [(1.5, 2.0, "a"), (3.0, 4.5, "b"), (5.0, 6.0, "c")],
+        dtype=[("x", np.float64), ("y", np.float32), ("label", "U1")],
+    )
+    adata.obsm["spatial"] = spatial
+    result = adata.obsm["spatial"]
+    assert isinstance(result, np.ndarray)
+    assert result.dtype == spatial.dtype
+    np.testing.assert_array_equal(result, spatial)
+
+    adata.subset([2, 0])
+    np.testing.assert_array_equal(adata.obsm["spatial"], spatial[[2, 0]])
+
+    # Bytes fields are read back as strings.
+    adata.obsm["tags"] = np.array([(1, b"x"), (2, b"yz")], dtype=[("n", np.int32), ("tag", "S2")])
+    tags = adata.obsm["tags"]
+    assert tags.dtype == np.dtype([("n", np.int32), ("tag", "U2")])
+    assert list(tags["tag"]) == ["x", "yz"]
+
+    with pytest.raises(Exception):
+        adata.varm["spatial"] = np.zeros((2, 2), dtype=[("x", np.float64)])
+
+def test_float16(tmp_path):
+    x = np.array([[1.5, 0, -2.25], [0, 4, 0.5]], dtype=np.float16)
+    adata = AnnData(X=x, filename=h5ad(tmp_path))
+    assert adata.X[:].dtype == np.float16
+    np.testing.assert_array_equal(adata.X[:], x)
+    adata.obsm["sparse"] = csr_matrix(x)
+    assert adata.obsm["sparse"].dtype == np.float16
+    np.testing.assert_array_equal(adata.obsm["sparse"].todense(), x)
+
+def test_string_array(tmp_path):
+    labels = np.array([["T", "CD4"], ["B", "naive"], ["NK", ""], ["T", "CD8"]])
+    adata = AnnData(X=np.ones((4, 2)), filename=h5ad(tmp_path))
+    adata.obsm["labels"] = labels
+    adata.uns["notes"] = np.array(["a", "b", "c"], dtype=object)
+    np.testing.assert_array_equal(adata.obsm["labels"], labels)
+    assert adata.obsm["labels"].dtype == object
+    np.testing.assert_array_equal(adata.uns["notes"], ["a", "b", "c"])
+    adata.subset([3, 0])
+    np.testing.assert_array_equal(adata.obsm["labels"], labels[[3, 0]])
+
+def test_nullable(tmp_path):
+    obs = pd.DataFrame({
+        "count": pd.array([1, None, 3], dtype="Int64"),
+        "flag": pd.array([None, True, False], dtype="boolean"),
+    })
+    adata = AnnData(X=np.ones((3, 2)), obs=obs, filename=h5ad(tmp_path))
+    assert adata.obs["count"].to_list() == [1, None, 3]
+    assert adata.obs["flag"].to_list() == [None, True, False]
+    obs_py = adata.to_memory().obs
+    assert obs_py["count"].dtype == "Int64"
+    assert obs_py["flag"].dtype == "boolean"
+    assert obs_py["count"].isna().to_list() == [False, True, False]
+
+def test_nullable_string(tmp_path):
+    obs = pd.DataFrame({"batch": ["a", np.nan, ""]})
+    adata = AnnData(X=np.ones((3, 2)), obs=obs, filename=h5ad(tmp_path))
+    assert adata.obs["batch"].to_list() == ["a", None, ""]
+    adata.subset([2, 1])
+    assert adata.obs["batch"].to_list() == ["", None]
+    obs_py = adata.to_memory().obs
+    assert obs_py["batch"].dtype == object
+    assert obs_py["batch"].isna().to_list() == [False, True]
+    assert obs_py["batch"][0] == ""
+
+def test_datetime(tmp_path):
+    time = pd.to_datetime(["2021-01-01", "2021-06-15 12:30", None])
+    obs = pd.DataFrame({
+        "time": time,
+        "elapsed": pd.to_timedelta([0, 90, 3600], unit="s"),
+    })
+    file = h5ad(tmp_path)
+    adata = AnnData(X=np.ones((3, 2)), obs=obs, filename=file)
+    adata.close()
+    adata = read(file)
+    obs_py = adata.to_memory().obs
+    assert obs_py["time"].dtype == "datetime64[ns]"
+    assert obs_py["time"].isna().to_list() == [False, False, True]
+    assert obs_py["time"][:2].to_list() == time[:2].to_list()
+    assert obs_py["elapsed"].to_list() == obs["elapsed"].to_list()
+
+def test_transform(tmp_path):
+    x = np.arange(21, dtype=np.float64).reshape(7, 3)
+    adata = AnnData(X=x, filename=h5ad(tmp_path))
+    adata.X.transform(lambda c: c * 2, chunk_size=3)
+    np.testing.assert_array_equal(adata.X[:], x * 2)
+    with pytest.raises(Exception):
+        adata.X.transform(lambda c: c[:, :2], chunk_size=3)
+
+    x = csr_matrix(np.array([[1, 0, 2], [0, 0, 3], [4, 5, 0]], dtype=np.float32))
+    adata = AnnData(X=x, filename=h5ad(tmp_path))
+    adata.X.transform(lambda c: c.log1p(), chunk_size=2)
+    np.testing.assert_array_almost_equal(adata.X[:].todense(), x.log1p().todense())
+
+def test_obs_column(tmp_path):
+    obs = pd.DataFrame({"a": [1, 2, 3], "b": ["x", "y", "z"]})
+    var = pd.DataFrame({"c": [0.5, 1.5]})
+    file = h5ad(tmp_path)
+    adata = AnnData(X=np.ones((3, 2)), obs=obs, var=var, filename=file)
+    adata.close()
+    adata = read(file)
+    assert adata.get_obs_column("b").to_list() == ["x", "y", "z"]
+    assert adata.get_var_column("c").to_list() == [0.5, 1.5]
+    with pytest.raises(Exception):
+        adata.get_obs_column("missing")
+
+def test_obs_vector(tmp_path):
+    x = np.arange(6, dtype=np.float64).reshape(3, 2)
+    obs = pd.DataFrame({"n_genes": [1, 2, 3]})
+    var = pd.DataFrame({"mean": [0.5, 1.5]})
+    adata = AnnData(X=csr_matrix(x), obs=obs, var=var, filename=h5ad(tmp_path))
+    adata.obs_names = ["c1", "c2", "c3"]
+    adata.var_names = ["g1", "g2"]
+    adata.layers["counts"] = x * 2
+
+    assert adata.obs_vector("n_genes").to_list() == [1, 2, 3]
+    np.testing.assert_array_equal(adata.obs_vector("g2"), x[:, 1])
+    np.testing.assert_array_equal(adata.obs_vector("g1", layer="counts"), x[:, 0] * 2)
+    assert adata.var_vector("mean").to_list() == [0.5, 1.5]
+    np.testing.assert_array_equal(adata.var_vector("c2"), x[1])
+    assert adata.var_vector("c3", layer="counts").shape == (2,)
+
+    with pytest.raises(KeyError):
+        adata.obs_vector("missing")
+    with pytest.raises(KeyError):
+        adata.var_vector("g1")
+    with pytest.raises(Exception):
+        adata.obs_vector("g1", layer="missing")
+
+def test_access_after_close(tmp_path):
+    adata = AnnData(X=np.ones((3, 2)), obs=pd.DataFrame({"a": [1, 2, 3]}), filename=h5ad(tmp_path))
+    x = adata.X
+    obs = adata.obs
+    adata.close()
+    assert adata.X is None
+    assert adata.obsm is None
+    with pytest.raises(Exception):
+        x[:]
+    with pytest.raises(Exception):
+        obs["a"]
+
+def test_describe(tmp_path):
+    obs = pd.DataFrame({"a": [1, 2, 3], "b": ["x", "y", "z"]})
+    adata = AnnData(X=np.ones((3, 2)), obs=obs, filename=h5ad(tmp_path))
+    adata.obsm = {"e": np.zeros((3, 4), dtype=np.float32)}
+    adata.uns["s"] = 1
+    info = adata.describe()
+    assert info["n_obs"] == 3 and info["n_vars"] == 2
+    assert info["X"] == {"dtype": "Array(f64)", "shape": (3, 2)}
+    assert info["obs"] == {"a": "Array(i64)", "b": "Array(string)"}
+    assert info["obsm"] == {"e": {"dtype": "Array(f32)", "shape": (3, 4)}}
+    assert info["layers"] == {}
+    assert info["uns"] == {"s": "Scalar(i64)"}
+
+def test_add_column(tmp_path):
+    file = h5ad(tmp_path)
+    adata = AnnData(X=np.ones((3, 2)), obs=pd.DataFrame({"a": [1, 2, 3]}), filename=file)
+    adata.obs.add_column("b", ["x", "y", "z"])
+    adata.obs.add_column("a", np.array([4, 5, 6]))
+    with pytest.raises(Exception):
+        adata.obs.add_column("c", [1, 2])
+    adata.close()
+    adata = read(file)
+    assert list(adata.describe()["obs"]) == ["a", "b"]
+    assert adata.obs["a"].to_list() == [4, 5, 6]
+    assert adata.obs["b"].to_list() == ["x", "y", "z"]
+
+def test_join(tmp_path):
+    file = h5ad(tmp_path)
+    adata = AnnData(X=np.ones((2, 3)), filename=file)
+    adata.var_names = ["g1", "g2", "g3"]
+    adata.var.add_column("gene_id", ["e1", "e2", "e3"])
+    meta = pl.DataFrame({"gene_id": ["e3", "e1", "e2"], "symbol": ["C", "A", "B"]})
+    adata.var.join(meta, on="gene_id")
+    adata.var.join(pl.DataFrame({"index": ["g2", "g1", "g3"], "score": [2, 1, 3]}), on="index")
+    with pytest.raises(Exception):
+        adata.var.join(pl.DataFrame({"gene_id": ["e1", "e1"], "x": [1, 2]}), on="gene_id")
+    with pytest.raises(Exception):
+        adata.var.join(meta, on="gene_id", how="cross")
+    adata.close()
+    adata = read(file)
+    assert adata.var["symbol"].to_list() == ["A", "B", "C"]
+    assert adata.var["score"].to_list() == [1, 2, 3]
+
+def test_typed_index(tmp_path):
+    file = h5ad(tmp_path)
+    adata = AnnData(X=np.ones((3, 2)), filename=file)
+    adata.obs_names = np.array([10, 20, 30])
+    adata.var_names = ["g1", "g2"]
+    adata.close()
+    adata = read(file)
+    assert adata.obs_names == ["10", "20", "30"]
+    obs_names = adata.get_obs_names(typed=True)
+    assert obs_names.dtype == np.int64
+    np.testing.assert_array_equal(obs_names, [10, 20, 30])
+    assert adata.get_var_names(typed=True).tolist() == ["g1", "g2"]
+    assert adata.obs_ix(["20"]) == [1]
+
+def test_set_x_dtype(tmp_path):
+    adata = AnnData(filename=h5ad(tmp_path))
+    adata.set_x(np.array([[1, 2], [3, 4]], dtype=np.int64), dtype="float32")
+    assert adata.X[:].dtype == np.float32
+    adata.obsm.add("a", csr_matrix(np.array([[1.5, 0.0], [0.0, 2.0]])), dtype=np.int32)
+    assert adata.obsm["a"].dtype == np.int32
+    np.testing.assert_array_equal(adata.obsm["a"].toarray(), [[1, 0], [0, 2]])
+    with pytest.raises(Exception):
+        adata.set_x(np.array([[1e10, 0.0], [0.0, 0.0]]), dtype=np.int32)
+    with pytest.raises(Exception):
+        adata.obsm.add("b", np.array([[1, 2], [3, 4]]), dtype=str)
+
+def test_to_dense_sparse(tmp_path):
+    x = np.array([[0.0, 0.5, 0.0], [2.0, 0.0, 0.01]])
+    adata = AnnData(X=csr_matrix(x), filename=h5ad(tmp_path))
+    adata.X.to_dense()
+    assert isinstance(adata.X[:], np.ndarray)
+    np.testing.assert_array_equal(adata.X[:], x)
+    adata.X.to_sparse(threshold=0.1)
+    assert sp.issparse(adata.X[:])
+    assert adata.X.shape == [2, 3]
+    np.testing.assert_array_equal(adata.X[:].toarray(), [[0.0, 0.5, 0.0], [2.0, 0.0, 0.0]])
+
+def test_to_dense_fill_value(tmp_path):
+    x = csr_matrix(np.array([[0.0, 0.5, 0.0], [2.0, 0.0, 0.0]], dtype=np.float32))
+    adata = AnnData(X=x, filename=h5ad(tmp_path))
+    adata.X.to_dense(fill_value=-1)
+    np.testing.assert_array_equal(adata.X[:], [[-1.0, 0.5, -1.0], [2.0, -1.0, -1.0]])
+    assert adata.X[:].dtype == np.float32
+    with pytest.raises(Exception):
+        adata.X.to_dense(fill_value="background")
+
+def test_validate(tmp_path):
+    adata = AnnData(X=np.ones((3, 2)), filename=h5ad(tmp_path))
+    adata.obsm["pca"] = np.ones((3, 4))
+    adata.varp["corr"] = np.ones((2, 2))
+    assert adata.validate() == []
+
+def test_verify_integrity(tmp_path):
+    adata = AnnData(X=csr_matrix(np.eye(3)), filename=h5ad(tmp_path))
+    adata.obs = pl.DataFrame({"a": [1, 2, 3]})
+    assert adata.verify_integrity() == []
+    adata.layers["counts"] = np.array([[np.nan, 1.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]])
+    assert adata.verify_integrity() == ["'layers/counts': 1 values are NaN"]
+
+def test_cache_budget(tmp_path):
+    adata = AnnData(X=np.ones((3, 2)), filename=h5ad(tmp_path))
+    adata.obsm["a"] = np.ones((3, 100))
+    adata.obsm["b"] = np.ones((3, 100))
+    assert adata.cache_bytes() == 0
+    for key in ["a", "b"]:
+        adata.obsm.el(key).enable_cache()
+        adata.obsm.el(key)[:]
+    assert adata.cache_bytes() == 2 * 3 * 100 * 8
+    adata.set_cache_budget(3 * 100 * 8)
+    assert adata.cache_bytes() == 3 * 100 * 8
+    np.testing.assert_array_equal(adata.obsm["a"], np.ones((3, 100)))
+    assert adata.cache_bytes() <= 3 * 100 * 8
+    adata.set_cache_budget(None)
+
+def test_evict(tmp_path):
+    adata = AnnData(X=np.ones((3, 100)), filename=h5ad(tmp_path))
+    adata.obsm["a"] = np.ones((3, 100))
+    adata.X.enable_cache()
+    adata.X[:]
+    adata.obsm.el("a").enable_cache()
+    adata.obsm.el("a")[:]
+    assert adata.cache_bytes() == 2 * 3 * 100 * 8
+    adata.X.evict()
+    adata.obsm.evict("a")
+    assert adata.cache_bytes() == 0
+    adata.X[:]
+    assert adata.cache_bytes() == 3 * 100 * 8
+
+def test_x_transposed(tmp_path):
+    x = np.arange(12).reshape(3, 4) % 3
+    file = h5ad(tmp_path)
+    adata = AnnData(X=csr_matrix(x), filename=file)
+    adata.set_x_transposed(True)
+    assert adata.is_x_transposed()
+    assert adata.X.shape == (3, 4)
+    np.testing.assert_array_equal(adata.X[:].todense(), x)
+    np.testing.assert_array_equal(adata.X[1:, [0, 3]].todense(), x[1:, [0, 3]])
+    adata.close()
+
+    # Other readers see a standard CSC matrix of shape (n_obs, n_vars).
+    with h5py.File(file, "r") as f:
+        group = f["X"]
+        assert group.attrs["encoding-type"] == "csc_matrix"
+        assert tuple(group.attrs["shape"]) == (3, 4)
+        stored = csc_matrix(
+            (group["data"][:], group["indices"][:], group["indptr"][:]),
+            shape=tuple(group.attrs["shape"]),
+        )
+        np.testing.assert_array_equal(stored.todense(), x)
+
+    adata = read(file)
+    assert adata.is_x_transposed()
+    adata.subset([2, 0])
+    np.testing.assert_array_equal(adata.X[:].todense(), x[[2, 0]])
+    output = h5ad(tmp_path)
+    adata.write(output)
+    exported = read(output)
+    assert not exported.is_x_transposed()
+    np.testing.assert_array_equal(exported.X[:].todense(), x[[2, 0]])
+
+def test_obsm_dataframe(tmp_path):
+    adata = AnnData(X=np.ones((3, 2)), filename=h5ad(tmp_path))
+    adata.obs_names = ["a", "b", "c"]
+    coords = pd.DataFrame({"x": [1.0, 2.0, 3.0], "y": [4.0, 5.0, 6.0]}, index=["a", "b", "c"])
+    adata.obsm["spatial"] = coords
+    pd.testing.assert_frame_equal(adata.obsm["spatial"], coords)
+    adata.subset([2, 0])
+    pd.testing.assert_frame_equal(adata.obsm["spatial"], coords.iloc[[2, 0]])
+    pd.testing.assert_frame_equal(adata.to_memory().obsm["spatial"], coords.iloc[[2, 0]])
+
+def test_column_stats(tmp_path):
+    x = np.array([[1, 0, -2], [0, 0, 0], [3, 4, 0], [0, 5, 0], [2, 0, 1]], dtype=np.float64)
+    for data in [x, csr_matrix(x)]:
+        adata = AnnData(X=data, filename=h5ad(tmp_path))
+        stats = adata.X.column_stats(chunk_size=2, quantiles=[0.0, 0.5, 1.0])
+        np.testing.assert_allclose(stats["mean"], x.mean(axis=0))
+        np.testing.assert_allclose(stats["variance"], x.var(axis=0, ddof=1))
+        np.testing.assert_array_equal(stats["min"], x.min(axis=0))
+        np.testing.assert_array_equal(stats["max"], x.max(axis=0))
+        np.testing.assert_array_equal(stats["quantiles"], [x.min(axis=0), [1, 0, 0], x.max(axis=0)])
+
+def test_read_many(tmp_path):
+    adata = AnnData(X=np.ones((3, 2)), filename=h5ad(tmp_path))
+    adata.obsm["X_pca"] = np.arange(12, dtype=np.float32).reshape(3, 4)
+    adata.obsm["X_umap"] = csr_matrix(np.eye(3))
+    adata.obsm["X_harmony"] = np.ones((3, 2))
+    arrays = adata.obsm.read_many(["X_pca", "X_umap"])
+    assert set(arrays.keys()) == {"X_pca", "X_umap"}
+    np.testing.assert_array_equal(arrays["X_pca"], adata.obsm["X_pca"])
+    np.testing.assert_array_equal(arrays["X_umap"].toarray(), np.eye(3))
+    with pytest.raises(Exception, match="X_tsne"):
+        adata.obsm.read_many(["X_tsne"])
+
+def test_external_link(tmp_path):
+    x = np.arange(6, dtype=np.int32).reshape(3, 2)
+    shared = h5ad(tmp_path)
+    AnnData(X=x, filename=shared).close()
+
+    adata = AnnData(X=np.zeros((3, 2)), filename=h5ad(tmp_path))
+    adata.layers.add_external_link("shared", shared, "/X")
+    np.testing.assert_array_equal(adata.layers["shared"], x)
+    with pytest.raises(Exception, match="external link"):
+        adata.layers["shared"] = np.ones((3, 2))
+    np.testing.assert_array_equal(adata.layers["shared"], x)
+
+def test_flush(tmp_path):
+    file = h5ad(tmp_path)
+    adata = AnnData(X=np.ones((3, 2)), filename=file)
+    adata.obsm["pca"] = np.ones((3, 4))
+    adata.flush()
+    adata.close()
+    with pytest.raises(Exception):
+        adata.flush()
+    adata = read(file, backed="r")
+    np.testing.assert_array_equal(adata.obsm["pca"], np.ones((3, 4)))
+
+def test_context_manager(tmp_path):
+    file = h5ad(tmp_path)
+    with AnnData(X=np.ones((3, 2)), filename=file) as adata:
+        adata.obsm["pca"] = np.ones((3, 4))
+    assert adata.is_closed()
+
+    with pytest.raises(ValueError, match="oops"):
+        with read(file, backed="r+") as adata:
+            adata.obsm["umap"] = np.zeros((3, 2))
+            raise ValueError("oops")
+    assert adata.is_closed()
+
+    with read(file, backed="r") as adata:
+        np.testing.assert_array_equal(adata.obsm["pca"], np.ones((3, 4)))
+        np.testing.assert_array_equal(adata.obsm["umap"], np.zeros((3, 2)))
+    assert adata.is_closed()
+
+def test_dict_like(tmp_path):
+    adata = AnnData(X=np.ones((3, 2)), filename=h5ad(tmp_path))
+    adata.obsm["pca"] = np.ones((3, 4))
+    adata.uns["n_pcs"] = 4
+    assert "pca" in adata.obsm and "umap" not in adata.obsm
+    with pytest.raises(KeyError):
+        adata.obsm["umap"]
+    with pytest.raises(KeyError):
+        adata.obsm.el("umap")
+    with pytest.raises(KeyError):
+        adata.uns["missing"]
+    assert adata.obsm.get("umap") is None
+    assert adata.obsm.get("umap", 0) == 0
+    np.testing.assert_array_equal(adata.obsm.get("pca"), np.ones((3, 4)))
+    assert adata.uns.get("n_pcs") == 4 and adata.uns.get("missing") is None
+    assert [k for k, _ in adata.obsm.items()] == ["pca"]
+    assert dict(adata.uns.items()) == {"n_pcs": 4}
+
+def test_uns_remove(tmp_path):
+    file = h5ad(tmp_path)
+    adata = AnnData(filename=file)
+    adata.uns["a"] = 1
+    adata.uns["b"] = {"c": np.arange(3)}
+    adata.uns["d"] = "d"
+    del adata.uns["a"]
+    np.testing.assert_array_equal(adata.uns.pop("b")["c"], np.arange(3))
+    assert adata.uns.keys() == ["d"]
+    with pytest.raises(KeyError):
+        del adata.uns["a"]
+    with pytest.raises(KeyError):
+        adata.uns.pop("b")
+    assert adata.uns.pop("b", None) is None
+    assert adata.uns.pop("b", 0) == 0
+    adata.close()
+
+    adata = read(file)
+    assert adata.uns.keys() == ["d"]
+    adata.close()
+
+def test_coo(tmp_path):
+    coo = sp.coo_matrix(
+        (np.array([1, 2, 3, 4], dtype=np.int32), ([2, 0, 2, 1], [1, 2, 1, 0])),
+        shape=(3, 3),
+    )
+    adata = AnnData(X=coo, filename=h5ad(tmp_path))
+    x = adata.X[:]
+    assert isinstance(x, csr_matrix) and x.has_canonical_format
+    assert x.dtype == np.int32
+    np.testing.assert_array_equal(x.todense(), coo.todense())
+    adata.obsm["coo"] = sp.coo_matrix(np.eye(3, dtype=np.float32))
+    np.testing.assert_array_equal(adata.obsm["coo"].todense(), np.eye(3))
+
+def test_ragged(tmp_path):
+    rows = [[1, 2], [], [3, 4, 5], [6]]
+    adata = AnnData(filename=h5ad(tmp_path))
+    adata.obsm["neighbors"] = rows
+    assert adata.n_obs == 4
+    assert [list(np.asarray(r)) for r in adata.obsm["neighbors"]] == rows
+    adata.subset([3, 0, 1])
+    assert [list(np.asarray(r)) for r in adata.obsm["neighbors"]] == [[6], [1, 2], []]
+
+def test_move_uns(tmp_path):
+    adata = AnnData(filename=h5ad(tmp_path))
+    adata.uns["pca"] = {"variance": np.array([3.0, 1.0]), "n": 2}
+    adata.uns.move_item("pca/variance", "pca/var_explained")
+    assert set(adata.uns["pca"].keys()) == {"var_explained", "n"}
+    adata.uns.move_item("pca/n", "n_pcs")
+    assert adata.uns["n_pcs"] == 2
+    with pytest.raises(Exception):
+        adata.uns.move_item("pca/missing", "x")
+    with pytest.raises(Exception):
+        adata.uns.move_item("n_pcs", "pca/var_explained")
+
+def test_set_obs_index(tmp_path):
+    adata = AnnData(X=np.ones((3, 2)), filename=h5ad(tmp_path))
+    adata.obs = pd.DataFrame({"n": [1, 2, 3]})
+    assert adata.obs_names == ["0", "1", "2"]
+    adata.obs = pd.DataFrame({"n": [1, 2, 3]}, index=["a", "b", "c"])
+    assert adata.obs_names == ["a", "b", "c"]
+    assert list(adata.obs["n"]) == [1, 2, 3]
+    with pytest.warns(UserWarning):
+        adata.obs = pd.DataFrame({"n": [4, 5, 6]}, index=["x", "y", "z"])
+    assert adata.obs_names == ["x", "y", "z"]
+    with pytest.raises(Exception):
+        adata.obs = pd.DataFrame({"n": [1, 2]}, index=["a", "b"])
+
+    adata = AnnData(filename=h5ad(tmp_path), var=pd.DataFrame(index=["g1", "g2"]))
+    assert adata.n_vars == 2
+    assert adata.var_names == ["g1", "g2"]
+
+def test_complex(tmp_path):
+    adata = AnnData(X=np.ones((3, 2)), filename=h5ad(tmp_path))
+    for dtype in [np.complex64, np.complex128]:
+        x = (np.arange(6).reshape(3, 2) + 1j * np.arange(6, 12).reshape(3, 2)).astype(dtype)
+        adata.obsm["complex"] = x
+        y = adata.obsm["complex"]
+        assert y.dtype == dtype
+        np.testing.assert_array_equal(y, x)
+        subset = adata.subset([2, 0], out=h5ad(tmp_path))
+        np.testing.assert_array_equal(subset.obsm["complex"], x[[2, 0]])
+
+def test_filter_obs(tmp_path):
+    adata = AnnData(X=np.ones((4, 2)), filename=h5ad(tmp_path))
+    adata.obs = pd.DataFrame({
+        "n_genes": [100, 600, 800, 1000],
+        "pct_mito": [0.05, 0.2, 0.05, 0.01],
+        "cell_type": pd.Categorical(["T", "B", "T", "NK"]),
+    })
+    assert adata.filter_obs("n_genes > 500 & pct_mito < 0.1") == [2, 3]
+    assert adata.filter_obs("cell_type == 'T' | ~(pct_mito < 0.1)") == [0, 1, 2]
+    assert adata.filter_var("true") == [0, 1]
+    with pytest.raises(Exception):
+        adata.filter_obs("n_genes >")
+    adata.subset(adata.filter_obs("n_genes > 500"))
+    assert adata.n_obs == 3
+
+def test_reorder_obs(tmp_path):
+    x = np.arange(6).reshape(3, 2)
+    adata = AnnData(X=x, filename=h5ad(tmp_path))
+    adata.obs_names = ["a", "b", "c"]
+    adata.obsm["pca"] = x * 10
+    adata.reorder_obs([2, 0, 1])
+    assert adata.obs_names == ["c", "a", "b"]
+    np.testing.assert_array_equal(adata.X[:], x[[2, 0, 1]])
+    np.testing.assert_array_equal(adata.obsm["pca"], x[[2, 0, 1]] * 10)
+    with pytest.raises(Exception):
+        adata.reorder_obs([0, 0, 1])
+
+def test_chunked_with_obs(tmp_path):
+    x = np.arange(10 * 2).reshape(10, 2)
+    adata = AnnData(X=x, filename=h5ad(tmp_path))
+    adata.obs = pl.DataFrame({
+        "label": [str(i % 3) for i in range(10)],
+        "score": np.arange(10, dtype=np.float64),
+    })
+    chunks = adata.chunked_with_obs(["label"], chunk_size=4)
+    assert chunks.n_chunks() == 3
+    xs, dfs = zip(*chunks)
+    np.testing.assert_array_equal(np.vstack(xs), x)
+    assert [df.columns for df in dfs] == [["label"]] * 3
+    assert pl.concat(dfs)["label"].to_list() == [str(i % 3) for i in range(10)]
+    with pytest.raises(Exception):
+        adata.chunked_with_obs(["missing"])
+
+def test_hstack_obsm(tmp_path):
+    adata = AnnData(X=np.zeros((3, 2)), filename=h5ad(tmp_path))
+    adata.obsm["pca"] = np.arange(6, dtype=np.float32).reshape(3, 2)
+    adata.obsm["batch"] = csr_matrix(np.array([[1.0], [0.0], [2.0]]))
+    adata.obsm.hstack(["pca", "batch"], "joint")
+    np.testing.assert_array_equal(
+        adata.obsm["joint"],
+        np.hstack([adata.obsm["pca"], [[1.0], [0.0], [2.0]]]),
+    )
+    with pytest.raises(Exception):
+        adata.obsm.hstack(["pca", "missing"], "out")
+
+def test_errors(tmp_path):
+    file = h5ad(tmp_path)
+    adata = AnnData(X=np.ones((3, 2)), filename=file)
+    adata.obs_names = ["a", "b", "c"]
+    with pytest.raises(KeyError, match="Unknown obs name"):
+        adata.subset(["a", "x"])
+    with pytest.raises(KeyError, match="neither a column of obs nor in var_names"):
+        adata.obs_vector("missing")
+    with pytest.raises(TypeError, match="not supported"):
+        adata.X = np.array([[b"a"]])
+    with pytest.raises(ValueError, match="Unsupported backend"):
+        adata.write(tmp_path / "out.h5ad", backend="unknown")
+    adata.close()
+    with pytest.raises(ValueError, match="Unknown mode"):
+        read(file, backed="w+")
+    with pytest.raises(ValueError, match="Unknown backend"):
+        AnnData(filename=h5ad(tmp_path), backend="unknown")
+    assert read(file).n_obs == 3
+
+def test_open_modes(tmp_path):
+    file = h5ad(tmp_path)
+    adata = read(file, backed="a")
+    assert adata.n_obs == 0
+    adata.X = np.ones((3, 2))
+    adata.close()
+
+    adata = read(file, backed="a")
+    assert adata.shape == (3, 2)
+    adata.close()
+
+    adata = read(file, backed="w")
+    assert adata.n_obs == 0
+    adata.X = np.ones((2, 2))
+    adata.close()
+    adata.open(mode="a")
+    assert adata.shape == (2, 2)
+
+    with pytest.raises(ValueError, match="memory mapping"):
+        read(file, backed="a", mmap=True)
+
 def test_type(tmp_path):
     adata = AnnData(filename = h5ad(tmp_path), X = np.array([[1, 2], [3, 4]]))
 
@@ -168,6 +931,20 @@ def test_create_anndataset(x1, x2, x3, tmp_path):
     x = dataset.X[:]
     np.testing.assert_array_equal(x[:, [1,2,3]].todense(), dataset.X[:, [1,2,3]].todense())
 
+def test_anndataset_prefix_obs_names(tmp_path):
+    adata1 = AnnData(X=np.ones((2, 3)), filename=h5ad(tmp_path))
+    adata1.obs_names = ["a", "b"]
+    adata2 = AnnData(X=np.ones((1, 3)), filename=h5ad(tmp_path))
+    adata2.obs_names = ["a"]
+    dataset = AnnDataSet(
+        adatas=[("s1", adata1), ("s2", adata2)],
+        filename=h5ad(tmp_path),
+        prefix_sep="-",
+    )
+    assert dataset.obs_names == ["s1-a", "s1-b", "s2-a"]
+    assert list(dataset.obs["sample"]) == ["s1", "s1", "s2"]
+    assert dataset.obs_ix(["s2-a"]) == [2]
+
 def test_noncanonical_csr(tmp_path):
     def assert_csr_equal(a, b):
         np.testing.assert_array_equal(a.shape, b.shape)
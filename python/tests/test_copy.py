@@ -38,4 +38,33 @@ def test_copy_anndata(x, obsm, obs, tmp_path):
     np.testing.assert_array_equal(adata.X[:], adata_copy.X[:])
     np.testing.assert_array_equal(adata.obsm["X_pca"], adata_copy.obsm["X_pca"])
     np.testing.assert_array_equal(adata.obsm["sparse"].todense(), adata_copy.obsm["sparse"].todense())
-    np.testing.assert_array_equal(adata.obs["txt"], adata_copy.obs["txt"])
\ No newline at end of file
+    np.testing.assert_array_equal(adata.obs["txt"], adata_copy.obs["txt"])
+
+def test_copy_subset(tmp_path):
+    x = np.arange(50 * 7, dtype=np.float32).reshape(50, 7)
+    x[x % 3 == 0] = 0
+    adata = AnnData(X=csr_matrix(x), filename=h5ad(tmp_path))
+    adata.obs_names = [str(i) for i in range(50)]
+    adata.layers["dense"] = x
+    indices = [47, 3, 0, 12, 33]
+    copy = adata.copy(h5ad(tmp_path), obs_indices=indices, var_indices=[6, 0, 2], chunk_size=2)
+    np.testing.assert_array_equal(copy.X[:].todense(), x[np.ix_(indices, [6, 0, 2])])
+    np.testing.assert_array_equal(copy.layers["dense"], x[np.ix_(indices, [6, 0, 2])])
+    assert copy.obs_names == [str(i) for i in indices]
+    assert adata.shape == (50, 7)
+
+    copy = adata.copy(h5ad(tmp_path))
+    np.testing.assert_array_equal(copy.X[:].todense(), x)
+
+def test_subset_progress(tmp_path, capsys):
+    x = np.arange(20 * 3, dtype=np.float32).reshape(20, 3)
+    adata = AnnData(X=csr_matrix(x), filename=h5ad(tmp_path))
+    adata.layers["dense"] = x
+    subset = adata.subset(slice(2, 18), out=h5ad(tmp_path), chunk_size=5, progress=True)
+    np.testing.assert_array_equal(subset.X[:].todense(), x[2:18, :])
+    err = capsys.readouterr().err
+    assert "32/32 rows" in err
+    assert err.endswith("\n")
+
+    adata.subset(slice(2, 18), out=h5ad(tmp_path), chunk_size=5)
+    assert capsys.readouterr().err == ""
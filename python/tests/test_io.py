@@ -0,0 +1,247 @@
+from anndata_rs import AnnData, read, read_zarr, read_loom, read_10x_h5, read_mtx
+
+import h5py
+import pytest
+import numpy as np
+import pandas as pd
+from scipy.sparse import csr_matrix
+from pathlib import Path
+import uuid
+
+def h5ad(dir=Path("./")):
+    dir.mkdir(exist_ok=True)
+    return str(dir / Path(str(uuid.uuid4()) + ".h5ad"))
+
+def test_read_loom(tmp_path):
+    n_genes, n_cells = 4, 3
+    matrix = np.arange(n_genes * n_cells, dtype=np.float32).reshape(n_genes, n_cells)
+    loom = str(tmp_path / "test.loom")
+    with h5py.File(loom, "w") as f:
+        f.create_dataset("matrix", data=matrix)
+        f.create_dataset("layers/spliced", data=matrix * 2)
+        f.create_dataset("col_attrs/CellID", data=np.array([b"c1", b"c2", b"c3"]))
+        f.create_dataset("col_attrs/n_counts", data=np.array([1, 2, 3]))
+        f.create_dataset("col_attrs/umap", data=np.ones((n_cells, 2)))
+        f.create_dataset("row_attrs/Gene", data=np.array([b"g1", b"g2", b"g3", b"g4"]))
+        f.create_dataset("row_attrs/Accession", data=np.array([b"a1", b"a2", b"a3", b"a4"]))
+
+    adata = read_loom(loom, file=h5ad(tmp_path))
+    np.testing.assert_array_equal(adata.X[:], matrix.T)
+    np.testing.assert_array_equal(adata.layers["spliced"][:], matrix.T * 2)
+    assert adata.obs_names == ["c1", "c2", "c3"]
+    assert adata.var_names == ["g1", "g2", "g3", "g4"]
+    assert list(adata.obs["n_counts"]) == [1, 2, 3]
+    assert list(adata.var["Accession"]) == ["a1", "a2", "a3", "a4"]
+    np.testing.assert_array_equal(adata.obsm["umap"], np.ones((n_cells, 2)))
+
+def test_read_10x_h5(tmp_path):
+    x = csr_matrix(np.array([[1, 0, 2], [0, 0, 3], [4, 5, 0], [0, 6, 0]], dtype=np.int32))
+    # Cell Ranger stores a feature-by-barcode CSC matrix, i.e., the CSR layout of x.
+    h5 = str(tmp_path / "filtered_feature_bc_matrix.h5")
+    with h5py.File(h5, "w") as f:
+        f.create_dataset("matrix/data", data=x.data)
+        f.create_dataset("matrix/indices", data=x.indices.astype(np.int64))
+        f.create_dataset("matrix/indptr", data=x.indptr.astype(np.int64))
+        f.create_dataset("matrix/shape", data=np.array([3, 4], dtype=np.int32))
+        f.create_dataset("matrix/barcodes", data=np.array([b"AAAC-1", b"AAAG-1", b"AAAT-1", b"AACC-1"]))
+        f.create_dataset("matrix/features/id", data=np.array([b"ENSG1", b"ENSG2", b"ENSG3"]))
+        f.create_dataset("matrix/features/name", data=np.array([b"A", b"B", b"C"]))
+        f.create_dataset("matrix/features/feature_type", data=np.array([b"Gene Expression"] * 3))
+        f.create_dataset("matrix/features/genome", data=np.array([b"GRCh38"] * 3))
+
+    adata = read_10x_h5(h5, file=h5ad(tmp_path))
+    np.testing.assert_array_equal(adata.X[:].todense(), x.todense())
+    assert adata.obs_names == ["AAAC-1", "AAAG-1", "AAAT-1", "AACC-1"]
+    assert adata.var_names == ["A", "B", "C"]
+    assert list(adata.var["gene_ids"]) == ["ENSG1", "ENSG2", "ENSG3"]
+    assert list(adata.var["feature_types"]) == ["Gene Expression"] * 3
+    assert list(adata.var["genome"]) == ["GRCh38"] * 3
+
+def test_read_mmap(tmp_path):
+    x = np.arange(40, dtype=np.float32).reshape(10, 4)
+    file = h5ad(tmp_path)
+    adata = AnnData(filename=file)
+    adata.obsm["chunked"] = x
+    adata.obsm["contiguous"] = x
+    adata.close()
+    # Rewrite one array without chunking so that it can be memory mapped.
+    with h5py.File(file, "r+") as f:
+        del f["obsm/contiguous"]
+        f.create_dataset("obsm/contiguous", data=x)
+        f["obsm/contiguous"].attrs["encoding-type"] = "array"
+        f["obsm/contiguous"].attrs["encoding-version"] = "0.2.0"
+
+    adata = read(file, backed="r", mmap=True)
+    idx = [7, 2, 2, 0]
+    np.testing.assert_array_equal(adata.obsm.el("contiguous")[idx, :], x[idx, :])
+    np.testing.assert_array_equal(adata.obsm.el("contiguous")[idx, [3, 1]], x[idx][:, [3, 1]])
+    np.testing.assert_array_equal(adata.obsm.el("chunked")[idx, :], x[idx, :])
+    adata.close()
+
+def test_read_lazy(tmp_path):
+    x = np.arange(12, dtype=np.float32).reshape(4, 3)
+    file = h5ad(tmp_path)
+    adata = AnnData(X=x, filename=file)
+    for i in range(20):
+        adata.obsm[f"emb{i}"] = x * i
+    adata.uns["name"] = "test"
+    adata.close()
+
+    adata = read(file, backed="r", lazy=True)
+    assert sorted(adata.obsm.keys()) == sorted(f"emb{i}" for i in range(20))
+    np.testing.assert_array_equal(adata.obsm["emb3"], x * 3)
+    assert adata.uns["name"] == "test"
+    adata.close()
+
+def test_uns_scalar(tmp_path):
+    file = h5ad(tmp_path)
+    adata = AnnData(filename=file)
+    adata.uns["n_pcs"] = 50
+    adata.uns["method"] = "leiden"
+    adata.uns["resolution"] = 0.5
+    adata.uns["log1p"] = True
+    adata.uns["n_neighbors"] = np.int32(15)
+    adata.uns["z"] = 1 + 2j
+    adata.close()
+
+    with h5py.File(file, "r") as f:
+        assert f["uns/n_pcs"].shape == ()
+        assert f["uns/n_pcs"].attrs["encoding-type"] == "numeric-scalar"
+        assert f["uns/method"].attrs["encoding-type"] == "string"
+        assert f["uns/n_neighbors"].dtype == np.int32
+
+    adata = read(file)
+    assert adata.uns["n_pcs"] == 50 and isinstance(adata.uns["n_pcs"], int)
+    assert adata.uns["method"] == "leiden"
+    assert adata.uns["resolution"] == 0.5
+    assert adata.uns["log1p"] is True
+    assert adata.uns["n_neighbors"] == 15
+    assert adata.uns["z"] == 1 + 2j
+
+def test_add_layer(tmp_path):
+    def offsets(dataset):
+        offset = dataset.id.get_offset()
+        if offset is not None:
+            return [offset]
+        return [dataset.id.get_chunk_info(i).byte_offset for i in range(dataset.id.get_num_chunks())]
+
+    x = np.arange(12, dtype=np.float64).reshape(3, 4)
+    file = h5ad(tmp_path)
+    adata = AnnData(X=x, filename=file)
+    adata.add_layer("counts", x)
+    adata.close()
+    with h5py.File(file, "r") as f:
+        before = offsets(f["X"]), offsets(f["layers/counts"])
+
+    adata = read(file, backed="r+")
+    adata.add_layer("log1p", np.log1p(x))
+    with pytest.raises(Exception, match="already exists"):
+        adata.add_layer("counts", x)
+    with pytest.raises(Exception):
+        adata.add_layer("wrong", np.ones((4, 3)))
+    adata.close()
+    with h5py.File(file, "r") as f:
+        assert (offsets(f["X"]), offsets(f["layers/counts"])) == before
+        np.testing.assert_array_equal(f["layers/log1p"][:], np.log1p(x))
+
+def test_write_mtx(tmp_path):
+    x = csr_matrix(np.array([[1, 0, 2], [0, 0, 3], [4, 5, 0]], dtype=np.int32))
+    adata = AnnData(X=x, filename=h5ad(tmp_path))
+    adata.obs_names = ["c1", "c2", "c3"]
+    adata.var_names = ["g1", "g2", "g3"]
+    adata.layers["scaled"] = x.toarray() * 0.5
+
+    mtx = str(tmp_path / "matrix.mtx")
+    adata.write_mtx(mtx)
+    with open(mtx) as f:
+        assert f.readline().strip() == "%%MatrixMarket matrix coordinate integer general"
+        assert f.readline().strip() == "3 3 5"
+
+    mtx = str(tmp_path / "scaled.mtx.gz")
+    barcodes = str(tmp_path / "barcodes.tsv.gz")
+    features = str(tmp_path / "features.tsv.gz")
+    adata.write_mtx(mtx, layer="scaled", obs_names=barcodes, var_names=features)
+    result = read_mtx(mtx, obs_names=barcodes, var_names=features)
+    np.testing.assert_array_equal(result.X.toarray(), x.toarray() * 0.5)
+    assert list(result.obs_names) == ["c1", "c2", "c3"]
+    assert list(result.var_names) == ["g1", "g2", "g3"]
+
+def test_read_errors(tmp_path):
+    with pytest.raises(Exception):
+        read_mtx(str(tmp_path / "missing.mtx"))
+
+    mtx = str(tmp_path / "matrix.mtx")
+    with open(mtx, "w") as f:
+        f.write("%%MatrixMarket matrix coordinate integer general\n2 2 1\n1 2 3\n")
+    with pytest.raises(ValueError, match="Unsupported backend"):
+        read_mtx(mtx, file=h5ad(tmp_path), backend="unknown")
+
+def test_zarr_roundtrip(tmp_path):
+    x = csr_matrix(np.array([[1, 0, 2], [0, 0, 3], [4, 5, 0], [0, 6, 0]], dtype=np.float32))
+    obs = pd.DataFrame({"n": [1, 2, 3, 4], "cell_type": pd.Categorical(["T", "B", "T", "NK"])})
+    h5 = h5ad(tmp_path)
+    adata = AnnData(filename=h5, X=x, obs=obs, obsm={"umap": np.ones((4, 2))}, uns={"method": "leiden"})
+    adata.obs_names = ["c1", "c2", "c3", "c4"]
+    adata.var_names = ["g1", "g2", "g3"]
+    adata.layers["dense"] = x.toarray()
+
+    store = str(tmp_path / "test.zarr")
+    adata.write(store, backend="zarr", chunk_size=3)
+    adata.close()
+
+    adata = read_zarr(store, backed="r")
+    assert adata.backend == "zarr"
+    np.testing.assert_array_equal(adata.X[:].todense(), x.todense())
+    np.testing.assert_array_equal(adata.layers["dense"][:], x.toarray())
+    assert adata.obs_names == ["c1", "c2", "c3", "c4"]
+    assert adata.var_names == ["g1", "g2", "g3"]
+    assert list(adata.obs["cell_type"]) == ["T", "B", "T", "NK"]
+    np.testing.assert_array_equal(adata.obsm["umap"], np.ones((4, 2)))
+    assert adata.uns["method"] == "leiden"
+
+    h5_again = h5ad(tmp_path)
+    adata.write(h5_again, backend="hdf5", chunk_size=3)
+    adata.close()
+    adata = read(h5_again, backed="r")
+    np.testing.assert_array_equal(adata.X[:].todense(), x.todense())
+    assert list(adata.obs["n"]) == [1, 2, 3, 4]
+    adata.close()
+
+def test_zarr_reference(tmp_path):
+    ad = pytest.importorskip("anndata")
+    zarr = pytest.importorskip("zarr")
+
+    x = csr_matrix(np.array([[1, 0, 2], [0, 0, 3], [4, 5, 0], [0, 6, 0]], dtype=np.float32))
+    obs = pd.DataFrame(
+        {"n": [1, 2, 3, 4], "cell_type": pd.Categorical(["T", "B", "T", "NK"])},
+        index=["c1", "c2", "c3", "c4"],
+    )
+    var = pd.DataFrame(index=["g1", "g2", "g3"])
+    reference = ad.AnnData(X=x, obs=obs, var=var, obsm={"umap": np.ones((4, 2))}, uns={"method": "leiden"})
+
+    # Stores written by the anndata package are read here...
+    written_by_ad = str(tmp_path / "reference.zarr")
+    reference.write_zarr(written_by_ad)
+    adata = read_zarr(written_by_ad, backed="r")
+    np.testing.assert_array_equal(adata.X[:].todense(), x.todense())
+    assert adata.obs_names == ["c1", "c2", "c3", "c4"]
+    assert list(adata.obs["cell_type"]) == ["T", "B", "T", "NK"]
+    assert adata.uns["method"] == "leiden"
+
+    # ... and stores converted from an .h5ad file are read by the anndata package.
+    h5 = h5ad(tmp_path)
+    reference.write_h5ad(h5)
+    written_here = str(tmp_path / "converted.zarr")
+    read(h5, backed="r").write(written_here, backend="zarr")
+    result = ad.read_zarr(written_here)
+    np.testing.assert_array_equal(result.X.toarray(), x.toarray())
+    pd.testing.assert_frame_equal(result.obs, reference.obs)
+    np.testing.assert_array_equal(result.obsm["umap"], reference.obsm["umap"])
+    assert result.uns["method"] == "leiden"
+
+    # Both stores use the same encodings.
+    expected, actual = zarr.open_group(written_by_ad, mode="r"), zarr.open_group(written_here, mode="r")
+    for key in ["X", "obs", "obs/cell_type", "obsm/umap", "uns/method"]:
+        assert actual[key].attrs["encoding-type"] == expected[key].attrs["encoding-type"]
+        assert actual[key].attrs["encoding-version"] == expected[key].attrs["encoding-version"]
+    assert actual["X/data"].dtype == expected["X/data"].dtype
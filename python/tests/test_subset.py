@@ -110,6 +110,75 @@ def test_chunk(tmp_path):
         s_ += m.sum(axis = 0)
     np.testing.assert_array_equal(s, s_)
 
+def test_subset_by_chunk(tmp_path):
+    x = np.arange(50 * 7, dtype=np.float32).reshape(50, 7)
+    x[x % 3 == 0] = 0
+    adata = AnnData(X=csr_matrix(x), filename=h5ad(tmp_path))
+    adata.layers["dense"] = x
+    indices = [47, 3, 3, 0, 12, 33, 8, 49, 21, 5, 16]
+    subset = adata.subset(indices, [6, 0, 2], out=h5ad(tmp_path), chunk_size=4)
+    np.testing.assert_array_equal(subset.X[:].todense(), x[np.ix_(indices, [6, 0, 2])])
+    np.testing.assert_array_equal(subset.layers["dense"], x[np.ix_(indices, [6, 0, 2])])
+    subset = adata.subset(slice(5, 45), out=h5ad(tmp_path), chunk_size=3)
+    np.testing.assert_array_equal(subset.X[:].todense(), x[5:45, :])
+    np.testing.assert_array_equal(subset.layers["dense"], x[5:45, :])
+
+def test_subset_obsp(tmp_path):
+    dist = np.abs(np.subtract.outer(np.arange(6.0), np.arange(6.0)))
+    graph = csr_matrix(np.triu(dist) > 3)
+    adata = AnnData(X=np.ones((6, 2)), filename=h5ad(tmp_path))
+    adata.obsp["distances"] = dist
+    adata.obsp["connectivities"] = graph
+    idx = [5, 0, 3, 0]
+    adata.subset(idx)
+    np.testing.assert_array_equal(adata.obsp["distances"], dist[np.ix_(idx, idx)])
+    np.testing.assert_array_equal(adata.obsp["connectivities"].todense(), graph[idx, :][:, idx].todense())
+
+def test_subset_by_names(tmp_path):
+    x = np.arange(6 * 4, dtype=np.float32).reshape(6, 4)
+    adata = AnnData(X=csr_matrix(x), filename=h5ad(tmp_path))
+    adata.obs_names = ["c" + str(i) for i in range(6)]
+    adata.var_names = ["g" + str(i) for i in range(4)]
+
+    subset = adata.subset_by_names(["c4", "c0", "c2"], ["g3", "g1"], out=h5ad(tmp_path))
+    np.testing.assert_array_equal(subset.X[:].todense(), x[np.ix_([4, 0, 2], [3, 1])])
+    assert subset.obs_names == ["c4", "c0", "c2"]
+    assert subset.var_names == ["g3", "g1"]
+
+    with pytest.raises(Exception, match="c9"):
+        adata.subset_by_names(["c1", "c9"], out=h5ad(tmp_path))
+
+    adata.subset_by_names(var_names=["g2", "g0"])
+    np.testing.assert_array_equal(adata.X[:].todense(), x[:, [2, 0]])
+    assert adata.var_names == ["g2", "g0"]
+
+def test_subset_mask(tmp_path):
+    x = np.arange(8 * 5, dtype=np.float32).reshape(8, 5)
+    adata = AnnData(X=csr_matrix(x), filename=h5ad(tmp_path))
+    adata.layers["dense"] = x
+    adata.obs = pd.DataFrame({"n_genes": np.arange(8)})
+    obs_mask = adata.obs["n_genes"].to_numpy() > 2
+    var_mask = np.array([True, False, True, True, False])
+    np.testing.assert_array_equal(adata.X[obs_mask, var_mask].todense(), x[obs_mask][:, var_mask])
+    with pytest.raises(ValueError):
+        adata.X[obs_mask[:5], :]
+    adata.subset(obs_mask, var_mask)
+    np.testing.assert_array_equal(adata.X[:].todense(), x[obs_mask][:, var_mask])
+    np.testing.assert_array_equal(adata.layers["dense"], x[obs_mask][:, var_mask])
+
+def test_negative_indices(tmp_path):
+    x = np.arange(20 * 4, dtype=np.float32).reshape(20, 4)
+    adata = AnnData(X=csr_matrix(x), filename=h5ad(tmp_path))
+    adata.layers["dense"] = x
+    dense = adata.layers.el("dense")
+    for ix in [np.s_[-10:], np.s_[::-1], np.s_[5:-5], np.s_[-5:5], np.s_[15:2:-3], np.s_[-100:100]]:
+        np.testing.assert_array_equal(adata.X[ix, :].todense(), x[ix, :])
+        np.testing.assert_array_equal(dense[ix, :], x[ix, :])
+    np.testing.assert_array_equal(adata.X[[-1, 0, -20], :].todense(), x[[-1, 0, -20], :])
+    np.testing.assert_array_equal(dense[:, [-1, 1]], x[:, [-1, 1]])
+    with pytest.raises(IndexError):
+        adata.X[[-21], :]
+
 @given(
     x1 = arrays(np.int64, (15, 179)),
     x2 = arrays(np.int64, (47, 179)),